@@ -0,0 +1,134 @@
+//! Prints the records or entries of a log file, to debug a broken log.
+//!
+//! ```text
+//! cargo run --example waldump -- [--entries] [--verify] [--block-size N] [--checksums] PATH
+//! ```
+//!
+//! Records are printed by default. `--entries` decodes the entries as
+//! `SingleLogEntry`s with `i32` keys and `String` values, like the logs in
+//! the crate's tests. `--verify` also prints what `wal::verify` finds.
+//! Logs with a header are read with the block format in their header, and
+//! headerless logs with `--block-size` and `--checksums`.
+
+extern crate disk_utils;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use disk_utils::wal::dump::{dump_entries, dump_records};
+use disk_utils::wal::header::FileHeader;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::BlockFormat;
+use disk_utils::wal::verify::{verify, VerifyOptions};
+use disk_utils::wal::LogData;
+
+#[derive(Clone, PartialEq, Debug)]
+struct DumpData;
+
+impl LogData for DumpData {
+    type Key = i32;
+    type Value = String;
+}
+
+struct Args {
+    path: String,
+    entries: bool,
+    verify: bool,
+    format: BlockFormat,
+}
+
+const USAGE: &str = "Usage: waldump [--entries] [--verify] [--block-size N] [--checksums] PATH";
+
+fn parse_args() -> Result<Args, String> {
+    let mut path = None;
+    let mut entries = false;
+    let mut verify = false;
+    let mut format = BlockFormat::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--entries" => entries = true,
+            "--verify" => verify = true,
+            "--checksums" => format.checksums = true,
+            "--block-size" => {
+                let size = args.next().ok_or("--block-size needs a value")?;
+                format.block_size = size
+                    .parse()
+                    .map_err(|_| format!("Invalid block size {}", size))?;
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown flag {}", arg)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(Args {
+        path: path.ok_or(USAGE)?,
+        entries,
+        verify,
+        format,
+    })
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let mut file = File::open(&args.path)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let mut format = args.format;
+    if let Some(header) = FileHeader::read(&mut file)? {
+        writeln!(
+            out,
+            "header version={} block_size={} checksums={} created={}",
+            header.version, header.block_size, header.block_checksums, header.created
+        )?;
+        format = header.format();
+    }
+
+    {
+        let mut iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format)
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        if args.entries {
+            dump_entries::<DumpData, _, _>(&mut iter, &mut out)?;
+        } else {
+            dump_records(&mut iter, &mut out)?;
+        }
+    }
+
+    if args.verify {
+        let options = VerifyOptions {
+            format,
+            entries: args.entries,
+        };
+        let report = verify::<DumpData, _>(&mut file, &options)?;
+        writeln!(
+            out,
+            "verified blocks={} records={} entries={} findings={}",
+            report.blocks,
+            report.records,
+            report.entries,
+            report.findings.len()
+        )?;
+        for finding in &report.findings {
+            writeln!(out, "{}", finding)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(args) {
+        eprintln!("waldump: {}", err);
+        process::exit(1);
+    }
+}
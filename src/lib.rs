@@ -2,6 +2,16 @@
 extern crate enum_primitive;
 extern crate byteorder;
 extern crate crc;
+#[cfg(feature = "compression")]
+extern crate lz4_flex;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "uuid")]
+extern crate uuid;
 
 pub mod testing;
 pub mod wal;
@@ -12,4 +22,37 @@ use std::io::{Read, Write};
 pub trait Serializable: Sized {
     fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()>;
     fn deserialize<R: Read>(bytes: &mut R) -> io::Result<Self>;
+
+    /// Returns the number of bytes `serialize` writes for the value,
+    /// without keeping the bytes.
+    ///
+    /// Logs size every entry they queue, so types that know their size
+    /// without serializing should override this.
+    fn serialized_size(&self) -> io::Result<u64> {
+        let mut counter = ByteCounter(0);
+        self.serialize(&mut counter)?;
+        Ok(counter.0)
+    }
+}
+
+/// Writer that only counts the bytes written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Companion to `Serializable` for types that can borrow from the
+/// serialized bytes instead of copying them.
+///
+/// Returns the deserialized value and the remaining unread bytes.
+pub trait DeserializeRef<'a>: Sized {
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(Self, &'a [u8])>;
 }
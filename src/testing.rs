@@ -1,17 +1,26 @@
 use std::any::Any;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem;
 use std::ops::FnOnce;
 use std::panic;
 use std::panic::{RefUnwindSafe, UnwindSafe};
 use std::path::Path;
 use std::result;
+use std::sync::{Arc, Mutex};
+
+use crate::wal::backend::LogBackend;
+use crate::wal::writer::{SetLen, SyncData};
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum TestFileError {
     IoError(io::Error),
-    ThreadError(Box<Any + Send + 'static>),
+    ThreadError(Box<dyn Any + Send + 'static>),
 }
 
 impl From<io::Error> for TestFileError {
@@ -20,12 +29,40 @@ impl From<io::Error> for TestFileError {
     }
 }
 
-impl From<Box<Any + Send + 'static>> for TestFileError {
-    fn from(err: Box<Any + Send + 'static>) -> TestFileError {
+impl From<Box<dyn Any + Send + 'static>> for TestFileError {
+    fn from(err: Box<dyn Any + Send + 'static>) -> TestFileError {
         TestFileError::ThreadError(err)
     }
 }
 
+impl fmt::Display for TestFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TestFileError::IoError(ref err) => write!(f, "Test file I/O failed: {}", err),
+            TestFileError::ThreadError(ref panic) => {
+                // Panic payloads are usually the panic's message.
+                match panic
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                {
+                    Some(message) => write!(f, "Test panicked: {}", message),
+                    None => write!(f, "Test panicked"),
+                }
+            }
+        }
+    }
+}
+
+impl error::Error for TestFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TestFileError::IoError(ref err) => Some(err),
+            TestFileError::ThreadError(_) => None,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, TestFileError>;
 
 pub fn create_test_file<
@@ -47,6 +84,40 @@ pub fn create_test_file<
     Ok(result?)
 }
 
+/// Runs the function on a directory at the path, which is removed
+/// afterwards along with everything the function put in it.
+pub fn create_test_dir<
+    P: AsRef<Path> + ?Sized + RefUnwindSafe,
+    F: FnOnce(&P) -> R + UnwindSafe,
+    R,
+>(
+    path: &P,
+    fun: F,
+) -> Result<R> {
+    if path.as_ref().exists() {
+        fs::remove_dir_all(path)?;
+    }
+    let result = panic::catch_unwind(move || fun(path));
+    if path.as_ref().exists() {
+        fs::remove_dir_all(path)?;
+    }
+    Ok(result?)
+}
+
+/// Simulates the process crashing while the log has its file at the path
+/// open: the log is forgotten without flushing anything, and its file is
+/// replaced with a copy, since the forgotten log keeps the file locked.
+/// Returns a handle to the copy like the one `create_test_file` passes.
+pub fn crash_log<L, P: AsRef<Path> + ?Sized>(log: L, path: &P) -> io::Result<File> {
+    mem::forget(log);
+    let path = path.as_ref();
+    let mut copy = path.as_os_str().to_owned();
+    copy.push(".crashed");
+    fs::copy(path, &copy)?;
+    fs::rename(&copy, path)?;
+    OpenOptions::new().read(true).append(true).open(path)
+}
+
 pub fn create_two_test_files<
     P1: AsRef<Path> + ?Sized + RefUnwindSafe,
     P2: AsRef<Path> + ?Sized + RefUnwindSafe,
@@ -72,3 +143,359 @@ pub fn create_two_test_files<
     fs::remove_file(path2)?;
     Ok(result?)
 }
+
+/// Fault a `FaultyFile` injects into the writes made to it, triggered by
+/// the file offset the writes reach.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultPolicy {
+    /// Writing past the offset fails. The write that crosses it writes
+    /// the bytes before it first.
+    FailAfter(u64),
+    /// Like `FailAfter`, except only the first write crossing the offset
+    /// fails. Writes and syncs after it succeed, like a transient error.
+    FailOnceAfter(u64),
+    /// The write that crosses the offset only writes the bytes before it
+    /// and returns the short count. Writes after that write nothing.
+    ShortWriteAfter(u64),
+    /// Writes past the offset succeed but their bytes are dropped, like
+    /// writes still in the page cache when the machine crashed.
+    DropAfter(u64),
+    /// Like `DropAfter`, except the block the offset falls in is still
+    /// written, so the write in progress is torn at the block boundary
+    /// after the offset.
+    TornAfter { offset: u64, block_size: u64 },
+}
+
+impl FaultPolicy {
+    /// Returns the offset past which no bytes reach the file.
+    fn limit(&self) -> u64 {
+        match *self {
+            FaultPolicy::FailAfter(offset)
+            | FaultPolicy::FailOnceAfter(offset)
+            | FaultPolicy::ShortWriteAfter(offset)
+            | FaultPolicy::DropAfter(offset) => offset,
+            FaultPolicy::TornAfter { offset, block_size } => {
+                offset.div_ceil(block_size) * block_size
+            }
+        }
+    }
+
+    fn drops_writes(&self) -> bool {
+        matches!(
+            *self,
+            FaultPolicy::DropAfter(_) | FaultPolicy::TornAfter { .. }
+        )
+    }
+}
+
+/// Wraps a file given to a `Writer`, injecting the fault of its policy
+/// into the writes made through it.
+#[derive(Debug)]
+pub struct FaultyFile<F = File> {
+    inner: F,
+    policy: FaultPolicy,
+    pos: u64,
+    faulted: bool,
+}
+
+impl<F: Seek> FaultyFile<F> {
+    pub fn new(mut inner: F, policy: FaultPolicy) -> io::Result<FaultyFile<F>> {
+        let pos = inner.stream_position()?;
+        Ok(FaultyFile {
+            inner,
+            policy,
+            pos,
+            faulted: false,
+        })
+    }
+}
+
+impl<F> FaultyFile<F> {
+    /// Returns whether a write reached the policy's offset.
+    pub fn faulted(&self) -> bool {
+        self.faulted
+    }
+
+    pub fn get_ref(&self) -> &F {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    /// Returns whether a fault that only happens once was injected.
+    fn spent(&self) -> bool {
+        self.faulted && matches!(self.policy, FaultPolicy::FailOnceAfter(_))
+    }
+
+    /// Returns the offset past which writes fault.
+    fn limit(&self) -> u64 {
+        if self.spent() {
+            u64::MAX
+        } else {
+            self.policy.limit()
+        }
+    }
+
+    fn injected_error(&self) -> io::Error {
+        io::Error::other(format!("Injected fault {:?}", self.policy))
+    }
+}
+
+impl<F: Write> Write for FaultyFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let limit = self.limit();
+        let allowed = limit.saturating_sub(self.pos).min(buf.len() as u64) as usize;
+        if allowed == buf.len() {
+            let written = self.inner.write(buf)?;
+            self.pos += written as u64;
+            return Ok(written);
+        }
+
+        self.faulted = true;
+        self.inner.write_all(&buf[..allowed])?;
+        self.pos += allowed as u64;
+        match self.policy {
+            FaultPolicy::FailAfter(_) | FaultPolicy::FailOnceAfter(_) => Err(self.injected_error()),
+            FaultPolicy::ShortWriteAfter(_) => Ok(allowed),
+            FaultPolicy::DropAfter(_) | FaultPolicy::TornAfter { .. } => {
+                self.pos += (buf.len() - allowed) as u64;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<F: Read> Read for FaultyFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<F: Seek> Seek for FaultyFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl<F: SyncData> SyncData for FaultyFile<F> {
+    /// Syncing fails once a failing fault was injected. With a dropping
+    /// fault the sync succeeds, although the dropped bytes are lost.
+    fn sync_data(&mut self) -> io::Result<()> {
+        if self.faulted && !self.policy.drops_writes() && !self.spent() {
+            return Err(self.injected_error());
+        }
+        self.inner.sync_data()
+    }
+}
+
+impl<F: SetLen> SetLen for FaultyFile<F> {
+    /// Growing the file past the policy's offset is a write past it.
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let limit = self.limit();
+        if len > limit {
+            self.faulted = true;
+            if !self.policy.drops_writes() {
+                return Err(self.injected_error());
+            }
+        }
+        self.inner.set_len(len.min(limit))
+    }
+}
+
+/// Runs a log over the wrapped backend, injecting the fault of the
+/// policy into the writes the log makes.
+impl<F: LogBackend> LogBackend for FaultyFile<F> {
+    type Reader = F::Reader;
+
+    fn len(&mut self) -> io::Result<u64> {
+        self.inner.len()
+    }
+
+    fn reader(&self) -> io::Result<F::Reader> {
+        self.inner.reader()
+    }
+
+    fn create_rewrite(&mut self) -> io::Result<FaultyFile<F>> {
+        FaultyFile::new(self.inner.create_rewrite()?, self.policy)
+    }
+
+    fn replace(&mut self, rewritten: FaultyFile<F>) -> io::Result<FaultyFile<F>> {
+        FaultyFile::new(self.inner.replace(rewritten.inner)?, self.policy)
+    }
+}
+
+/// Writes the file at `source` to `target` through a `FaultyFile`,
+/// leaving in `target` what would have been on disk had the fault hit
+/// while `source` was written. Logs only append to their files, so this
+/// stands in for a crash at the fault while the log was running.
+///
+/// Returns whether the fault was hit.
+pub fn replay_with_faults<P1, P2>(source: &P1, target: &P2, policy: FaultPolicy) -> io::Result<bool>
+where
+    P1: AsRef<Path> + ?Sized,
+    P2: AsRef<Path> + ?Sized,
+{
+    let bytes = fs::read(source)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(target)?;
+    let mut file = FaultyFile::new(file, policy)?;
+    match file.write_all(&bytes) {
+        Err(_) if file.faulted() => {}
+        result => result?,
+    }
+    Ok(file.faulted())
+}
+
+/// Runs `run` against a log at `path`, then for each of the fault
+/// policies it returns replays the log into `crash_path` with
+/// `replay_with_faults` and calls `recover` to check recovering from it.
+///
+/// `run` also returns what the log did, like the offsets its entries end
+/// at, for `recover` to know what to expect. Both files are removed
+/// afterwards.
+pub fn create_crash_test<P1, P2, T, F, G>(
+    path: &P1,
+    crash_path: &P2,
+    run: F,
+    mut recover: G,
+) -> Result<()>
+where
+    P1: AsRef<Path> + ?Sized + RefUnwindSafe,
+    P2: AsRef<Path> + ?Sized + RefUnwindSafe,
+    F: FnOnce(&P1) -> (T, Vec<FaultPolicy>) + UnwindSafe,
+    G: FnMut(&P2, &T, FaultPolicy) + UnwindSafe,
+{
+    create_two_test_files(path, crash_path, move |path, crash_path, _, _| {
+        let (ran, policies) = run(path);
+        for policy in policies {
+            replay_with_faults(path, crash_path, policy).unwrap();
+            recover(crash_path, &ran, policy);
+        }
+    })
+}
+
+/// Log backend keeping a log's bytes in memory, so logs can be tested
+/// without files. Clones share the bytes, so a test can keep a clone to
+/// recover the log from after dropping it.
+///
+/// Like a file opened for appending, writes are appended at the end of
+/// the bytes, so logs using it can't preallocate space.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryBackend {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    pos: u64,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> InMemoryBackend {
+        InMemoryBackend::default()
+    }
+
+    /// Creates a backend holding the bytes of a log.
+    pub fn with_contents(bytes: Vec<u8>) -> InMemoryBackend {
+        InMemoryBackend {
+            bytes: Arc::new(Mutex::new(bytes)),
+            pos: 0,
+        }
+    }
+
+    /// Returns a copy of the bytes in the backend.
+    pub fn contents(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+    /// Replaces the bytes shared by the backend and its clones, like
+    /// cutting off the end of a log to simulate a crash.
+    pub fn set_contents(&self, bytes: Vec<u8>) {
+        *self.bytes.lock().unwrap() = bytes;
+    }
+}
+
+impl Read for InMemoryBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.bytes.lock().unwrap();
+        let start = (self.pos as usize).min(bytes.len());
+        let read = (bytes.len() - start).min(buf.len());
+        buf[..read].copy_from_slice(&bytes[start..start + read]);
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for InMemoryBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = self.bytes.lock().unwrap();
+        bytes.extend_from_slice(buf);
+        self.pos = bytes.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for InMemoryBackend {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.bytes.lock().unwrap().len() as u64;
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start"))?;
+        Ok(self.pos)
+    }
+}
+
+impl SetLen for InMemoryBackend {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.bytes.lock().unwrap().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+impl SyncData for InMemoryBackend {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogBackend for InMemoryBackend {
+    type Reader = io::Cursor<Vec<u8>>;
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.bytes.lock().unwrap().len() as u64)
+    }
+
+    /// Reads a copy of the bytes appended so far.
+    fn reader(&self) -> io::Result<io::Cursor<Vec<u8>>> {
+        Ok(io::Cursor::new(self.contents()))
+    }
+
+    fn create_rewrite(&mut self) -> io::Result<InMemoryBackend> {
+        Ok(InMemoryBackend::new())
+    }
+
+    /// Swaps in the rewritten bytes, which clones of the backend share.
+    fn replace(&mut self, rewritten: InMemoryBackend) -> io::Result<InMemoryBackend> {
+        self.set_contents(rewritten.contents());
+        Ok(InMemoryBackend {
+            bytes: self.bytes.clone(),
+            pos: 0,
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use tokio::sync::{mpsc, oneshot};
+use tokio::task;
+
+use crate::wal::iterator::{BlockError, OnCorruption, ReadDirection, WalIterator};
+use crate::wal::record::BlockFormat;
+use crate::wal::writer::Writer;
+use crate::wal::{LogOptions, SerializeResult};
+use crate::Serializable;
+
+/// Number of entries read ahead of an `AsyncEntries` stream.
+const ENTRY_BUFFER: usize = 64;
+
+/// Appends entries to a log without blocking the async runtime.
+///
+/// Each operation runs a `Writer` on tokio's blocking thread pool, so the
+/// log is written with the same framing as a synchronous `Writer`. Entries
+/// are serialized on the calling task before being handed to the pool.
+///
+/// If an operation's future is dropped before it finishes, the operation
+/// still runs to completion but the writer is lost, and later operations
+/// fail.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// extern crate tokio;
+/// use disk_utils::wal::asynchronous::AsyncWriter;
+/// use disk_utils::wal::LogOptions;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let path = "./files/async_writer_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     # let _ = std::fs::remove_file(path);
+///     let mut writer = AsyncWriter::open(path, LogOptions::default()).await.unwrap();
+///     writer.append_serializable(&42u64).await.unwrap();
+///     writer.sync().await.unwrap();
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
+pub struct AsyncWriter {
+    writer: Option<Writer>,
+}
+
+impl AsyncWriter {
+    /// Creates an async writer from a writer of a log file.
+    pub fn new(writer: Writer) -> AsyncWriter {
+        AsyncWriter {
+            writer: Some(writer),
+        }
+    }
+
+    /// Opens a log file for appending like a redo or undo log would,
+    /// creating it with a header if it doesn't exist.
+    pub async fn open<P: Into<PathBuf>>(path: P, options: LogOptions) -> io::Result<AsyncWriter> {
+        let path = path.into();
+        let writer =
+            blocking(move || options.writer_for(options.open_file(&path)?.into_inner())).await?;
+        Ok(AsyncWriter::new(writer))
+    }
+
+    /// Serializes the entry and appends it like `Writer::append_serializable`,
+    /// returning the offset in the file of the entry's first record.
+    pub async fn append_serializable<S: Serializable>(&mut self, entry: &S) -> io::Result<u64> {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes)?;
+        self.append_bytes(bytes).await
+    }
+
+    /// Appends the bytes of a serialized entry like `Writer::append_bytes`.
+    pub async fn append_bytes(&mut self, bytes: Vec<u8>) -> io::Result<u64> {
+        self.run(move |writer| writer.append_bytes(&bytes)).await
+    }
+
+    /// Flushes the appended entries to the file.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.run(|writer| writer.file_mut().flush()).await
+    }
+
+    /// Syncs the appended entries to disk like `Writer::sync`.
+    pub async fn sync(&mut self) -> io::Result<()> {
+        self.run(Writer::sync).await
+    }
+
+    /// Returns the underlying writer, or None if it was lost
+    /// when an operation was cancelled.
+    pub fn writer(&self) -> Option<&Writer> {
+        self.writer.as_ref()
+    }
+
+    pub fn into_inner(self) -> Option<Writer> {
+        self.writer
+    }
+
+    /// Runs the operation on the writer on the blocking thread pool.
+    async fn run<T, F>(&mut self, op: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Writer) -> io::Result<T> + Send + 'static,
+    {
+        let mut writer = self.writer.take().ok_or_else(|| {
+            io::Error::other("Writer was lost when an earlier operation was cancelled")
+        })?;
+        let (writer, result) = blocking(move || {
+            let result = op(&mut writer);
+            Ok((writer, result))
+        })
+        .await?;
+        self.writer = Some(writer);
+        result
+    }
+}
+
+/// Stream of the entries in a log, read on tokio's blocking thread pool.
+///
+/// Entries are read with a `WalIterator` like `WalIterator::entries`, so
+/// corruption is handled and reported the same way. Reading runs ahead of
+/// the stream by a bounded number of entries, and stops when the stream
+/// is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate disk_utils;
+/// extern crate tokio;
+/// use disk_utils::wal::asynchronous::AsyncEntries;
+/// use disk_utils::wal::iterator::{OnCorruption, ReadDirection};
+/// use disk_utils::wal::record::BlockFormat;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut entries = AsyncEntries::<u64>::open(
+///         "./log",
+///         ReadDirection::Forward,
+///         BlockFormat::default(),
+///         OnCorruption::Error,
+///     )
+///     .await
+///     .unwrap();
+///     while let Some(entry) = entries.next().await {
+///         println!("{:?}", entry);
+///     }
+/// }
+/// ```
+pub struct AsyncEntries<S> {
+    receiver: mpsc::Receiver<SerializeResult<S>>,
+}
+
+impl<S: Serializable + Send + 'static> AsyncEntries<S> {
+    /// Opens the log file and starts reading its entries in the direction.
+    /// Reading backwards returns the entries starting from the end.
+    pub async fn open<P: Into<PathBuf>>(
+        path: P,
+        direction: ReadDirection,
+        format: BlockFormat,
+        on_corruption: OnCorruption,
+    ) -> Result<AsyncEntries<S>, BlockError> {
+        let path = path.into();
+        let (opened, open_result) = oneshot::channel();
+        let (sender, receiver) = mpsc::channel(ENTRY_BUFFER);
+        // The iterator isn't `Send`, so it's created on the thread reading it.
+        task::spawn_blocking(move || {
+            let iter = File::open(&path)
+                .map_err(BlockError::from)
+                .and_then(|file| WalIterator::owned(file, direction, format));
+            let iter = match iter {
+                Ok(iter) => {
+                    let _ = opened.send(Ok(()));
+                    iter.on_corruption(on_corruption)
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                    return;
+                }
+            };
+            let mut entries = iter.entries::<S>();
+            let mut next_entry = move || match direction {
+                ReadDirection::Forward => entries.next(),
+                ReadDirection::Backward => entries.next_back(),
+            };
+            while let Some(entry) = next_entry() {
+                // Reading stops once the stream is dropped.
+                if sender.blocking_send(entry).is_err() {
+                    break;
+                }
+            }
+        });
+        open_result
+            .await
+            .map_err(|err| BlockError::IoError(io::Error::other(err)))??;
+        Ok(AsyncEntries { receiver })
+    }
+
+    /// Returns the next entry in the stream, or None after the last entry.
+    pub async fn next(&mut self) -> Option<SerializeResult<S>> {
+        self.receiver.recv().await
+    }
+}
+
+/// Runs the function on the blocking thread pool.
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    task::spawn_blocking(f).await.map_err(io::Error::other)?
+}
@@ -0,0 +1,251 @@
+use std::ffi::OsString;
+use std::fs;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::wal::iterator::{BlockSource, SharedFile};
+use crate::wal::writer::{SetLen, SyncData};
+
+/// Storage a `RedoLog` or `UndoLog` keeps its records in.
+///
+/// The log appends records with a `Writer` over the backend, recovers by
+/// reading the records back with a `WalIterator` over it, and syncs and
+/// truncates it through `SyncData` and `SetLen`. Unless the log
+/// preallocates space, writes must be appended at the end of the backend
+/// wherever its position was left, like writes to a file opened for
+/// appending.
+pub trait LogBackend: Read + Write + Seek + SetLen + SyncData + Sized {
+    /// Handle the log's `reader` reads records through while the log
+    /// keeps appending to the backend.
+    type Reader: BlockSource + 'static;
+
+    /// Returns the length of the backend in bytes.
+    fn len(&mut self) -> io::Result<u64>;
+
+    fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns a reader over the records appended so far.
+    fn reader(&self) -> io::Result<Self::Reader>;
+
+    /// Creates an empty backend that the records kept by truncating the
+    /// log are copied to.
+    fn create_rewrite(&mut self) -> io::Result<Self>;
+
+    /// Replaces the backend's contents with the synced backend returned
+    /// by `create_rewrite`, returning the backend the log continues with.
+    /// A crash partway through must leave either the old contents or the
+    /// new ones.
+    fn replace(&mut self, rewritten: Self) -> io::Result<Self>;
+
+    /// Returns the file the backend keeps its records in, so recovery
+    /// can read it memory-mapped.
+    fn file(&self) -> Option<&File> {
+        None
+    }
+
+    /// Returns whether the backend records where recovery can start
+    /// with `checkpointed`.
+    fn tracks_checkpoints(&self) -> bool {
+        false
+    }
+
+    /// Records that after the last checkpoint, recovery needs no entries
+    /// before the offset. The log has synced the entries before calling it.
+    fn checkpointed(&mut self, offset: u64) -> io::Result<()> {
+        let _ = offset;
+        Ok(())
+    }
+
+    /// Returns the offset recorded by `checkpointed`, before which
+    /// recovery doesn't read, or 0 to read back as far as it needs.
+    fn recovery_start(&mut self) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    /// Returns the offset of the first record the backend keeps, which is
+    /// past 0 once `discard_before` has freed the records before it. The
+    /// log's readers and recovery start reading from it.
+    fn first_offset(&self) -> u64 {
+        0
+    }
+
+    /// Returns whether truncating the log frees the space before the
+    /// entries it keeps with `discard_before` instead of rewriting it.
+    fn can_discard(&self) -> bool {
+        false
+    }
+
+    /// Frees the space of the bytes before the offset, keeping the
+    /// offsets of the bytes after it. Returns whether any was freed.
+    fn discard_before(&mut self, offset: u64) -> io::Result<bool> {
+        let _ = offset;
+        Ok(false)
+    }
+}
+
+/// Backend keeping a log's records in a file. Logs opened by path use it.
+#[derive(Debug)]
+pub struct FileBackend {
+    file: File,
+    path: PathBuf,
+    append: bool,
+}
+
+impl FileBackend {
+    /// Opens the file at the path, creating it if it doesn't exist.
+    ///
+    /// A file opened for appending writes at its end wherever its position
+    /// is, while preallocated logs need to write after their last record.
+    ///
+    /// The file is locked until the backend is dropped, so appends from
+    /// two logs can't interleave. Opening a file another backend has
+    /// locked, in this process or another, fails with `WouldBlock`.
+    pub fn open<P: AsRef<Path> + ?Sized>(path: &P, append: bool) -> io::Result<FileBackend> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .append(append)
+            .create(true)
+            .open(path)?;
+        lock_exclusive(&file)?;
+        Ok(FileBackend {
+            file,
+            path: path.as_ref().to_path_buf(),
+            append,
+        })
+    }
+
+    /// Opens the file at the path for reading without locking it, so tools
+    /// can inspect a log another backend is appending to. Writing to the
+    /// backend fails.
+    pub fn open_read_only<P: AsRef<Path> + ?Sized>(path: &P) -> io::Result<FileBackend> {
+        let file = File::open(path)?;
+        Ok(FileBackend {
+            file,
+            path: path.as_ref().to_path_buf(),
+            append: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+impl Read for FileBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for FileBackend {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileBackend {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl SetLen for FileBackend {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+}
+
+impl SyncData for FileBackend {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+impl LogBackend for FileBackend {
+    type Reader = SharedFile;
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn reader(&self) -> io::Result<SharedFile> {
+        SharedFile::new(&self.file)
+    }
+
+    /// Creates a file next to the log, replacing one left by an
+    /// earlier rewrite that didn't finish.
+    fn create_rewrite(&mut self) -> io::Result<FileBackend> {
+        let temp = rewrite_path(&self.path);
+        if temp.exists() {
+            fs::remove_file(&temp)?;
+        }
+        FileBackend::open(&temp, self.append)
+    }
+
+    /// Renames the rewritten file over the log's file.
+    fn replace(&mut self, rewritten: FileBackend) -> io::Result<FileBackend> {
+        fs::rename(&rewritten.path, &self.path)?;
+        sync_parent(&self.path)?;
+        Ok(FileBackend {
+            file: rewritten.file,
+            path: self.path.clone(),
+            append: rewritten.append,
+        })
+    }
+
+    fn file(&self) -> Option<&File> {
+        Some(&self.file)
+    }
+}
+
+/// Takes the advisory lock of a log's file, which is released when every
+/// handle to the file is closed.
+pub(crate) fn lock_exclusive(file: &File) -> io::Result<()> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "The log file is locked by another log",
+        )),
+        Err(TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// Returns the path of the file a log is rewritten to before it replaces the log.
+pub(crate) fn rewrite_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".rewrite");
+    PathBuf::from(name)
+}
+
+/// Syncs the directory holding the file, so a rename replacing the file is durable.
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+/// Syncs the directory, so the files created, renamed or deleted in it are durable.
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
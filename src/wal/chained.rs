@@ -0,0 +1,149 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::wal::iterator::WalIterator;
+use crate::wal::record::{check_block_size, BLOCK_SIZE};
+
+/// Iterator over the records of a log split into segments.
+pub type ChainedWalIterator<'a, R = File> = WalIterator<'a, ChainedLog<R>>;
+
+/// Reads an ordered list of log segments as if they were a single log.
+///
+/// Every segment but the last is padded out to a whole number of blocks,
+/// so the blocks of each segment line up with the blocks of the chained log.
+/// The padding reads as zeros, which the iterator treats as block padding.
+/// Segments written with block checksums must end on a block boundary.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::io::Cursor;
+/// use disk_utils::wal::chained::ChainedLog;
+/// use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+/// use disk_utils::wal::record::{Record, RecordType};
+///
+/// fn main() {
+///     let mut segments = Vec::new();
+///     for i in 0..3 {
+///         let mut bytes = Vec::new();
+///         Record::new(RecordType::Full, vec![i]).unwrap().write(&mut bytes).unwrap();
+///         segments.push(Cursor::new(bytes));
+///     }
+///
+///     let mut log = ChainedLog::new(segments).unwrap();
+///     let iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+///     let payloads: Vec<_> = iter.map(|record| record.payload[0]).collect();
+///     assert_eq!(payloads, vec![0, 1, 2]);
+/// }
+/// ```
+pub struct ChainedLog<R: Read + Seek = File> {
+    segments: Vec<Segment<R>>,
+    len: u64,
+    pos: u64,
+}
+
+struct Segment<R> {
+    reader: R,
+    /// Position of the segment in the chained log.
+    start: u64,
+    /// Length of the segment's contents.
+    len: u64,
+    /// Length of the segment in the chained log including its padding.
+    padded_len: u64,
+}
+
+impl<R: Read + Seek> ChainedLog<R> {
+    pub fn new(segments: Vec<R>) -> io::Result<ChainedLog<R>> {
+        ChainedLog::with_block_size(segments, BLOCK_SIZE)
+    }
+
+    /// Chains segments of a log written with the given block size.
+    pub fn with_block_size(segments: Vec<R>, block_size: i64) -> io::Result<ChainedLog<R>> {
+        check_block_size(block_size)?;
+        let block_size = block_size as u64;
+        let count = segments.len();
+        let mut chained = Vec::with_capacity(count);
+        let mut start = 0;
+        for (i, mut reader) in segments.into_iter().enumerate() {
+            let len = reader.seek(SeekFrom::End(0))?;
+            let padded_len = if i + 1 < count {
+                len.div_ceil(block_size) * block_size
+            } else {
+                len
+            };
+            chained.push(Segment {
+                reader,
+                start,
+                len,
+                padded_len,
+            });
+            start += padded_len;
+        }
+
+        Ok(ChainedLog {
+            segments: chained,
+            len: start,
+            pos: 0,
+        })
+    }
+
+    /// Returns the segments in order.
+    pub fn into_inner(self) -> Vec<R> {
+        self.segments
+            .into_iter()
+            .map(|segment| segment.reader)
+            .collect()
+    }
+}
+
+impl<R: Read + Seek> Read for ChainedLog<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.pos;
+        let segment = match self
+            .segments
+            .iter_mut()
+            .find(|segment| pos < segment.start + segment.padded_len)
+        {
+            Some(segment) => segment,
+            None => return Ok(0),
+        };
+
+        let offset = pos - segment.start;
+        let read = if offset < segment.len {
+            let max = cmp::min(buf.len() as u64, segment.len - offset) as usize;
+            segment.reader.seek(SeekFrom::Start(offset))?;
+            segment.reader.read(&mut buf[..max])?
+        } else {
+            let max = cmp::min(buf.len() as u64, segment.padded_len - offset) as usize;
+            for byte in buf[..max].iter_mut() {
+                *byte = 0;
+            }
+            max
+        };
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for ChainedLog<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative position",
+            )),
+        }
+    }
+}
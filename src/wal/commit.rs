@@ -0,0 +1,134 @@
+use std::io;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::wal::LogError;
+
+/// Waits for a transaction committed with `RedoLog::commit_async` to
+/// become durable.
+///
+/// The ticket resolves once `RedoLog::flush_commits` has flushed and synced
+/// the transaction's commit, or with the error that kept the commit from
+/// being flushed. Tickets can be sent to other threads to wait there.
+pub struct CommitTicket {
+    tid: u64,
+    status: Arc<CommitStatus>,
+}
+
+impl CommitTicket {
+    /// Returns the id of the committed transaction.
+    pub fn tid(&self) -> u64 {
+        self.tid
+    }
+
+    /// Returns true once the commit has been flushed and synced.
+    pub fn is_durable(&self) -> bool {
+        matches!(*self.status.state.lock().unwrap(), Some(Ok(())))
+    }
+
+    /// Blocks until the commit has been flushed and synced, or returns the
+    /// error that kept it from being flushed. Fails if the log is dropped
+    /// before flushing the commit.
+    pub fn wait(self) -> io::Result<()> {
+        let mut state = self.status.state.lock().unwrap();
+        loop {
+            match *state {
+                Some(Ok(())) => return Ok(()),
+                Some(Err((kind, ref message))) => {
+                    return Err(io::Error::new(kind, message.clone()))
+                }
+                None => state = self.status.changed.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+type CommitResult = Result<(), (io::ErrorKind, String)>;
+
+struct CommitStatus {
+    state: Mutex<Option<CommitResult>>,
+    changed: Condvar,
+}
+
+impl CommitStatus {
+    fn resolve(&self, result: CommitResult) {
+        *self.state.lock().unwrap() = Some(result);
+        self.changed.notify_all();
+    }
+}
+
+/// Transactions committed with `RedoLog::commit_async` whose commits
+/// haven't been flushed yet, in the order they committed.
+///
+/// Dropping the pending commits fails the tickets still waiting on them.
+#[derive(Default)]
+pub(crate) struct PendingCommits {
+    commits: Vec<(u64, Arc<CommitStatus>)>,
+}
+
+impl PendingCommits {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, tid: u64) -> CommitTicket {
+        let status = Arc::new(CommitStatus {
+            state: Mutex::new(None),
+            changed: Condvar::new(),
+        });
+        self.commits.push((tid, status.clone()));
+        CommitTicket { tid, status }
+    }
+
+    /// Removes the pending commits, resolving their tickets once
+    /// the returned commits are dropped or completed.
+    pub(crate) fn take(&mut self) -> Vec<PendingCommit> {
+        mem::take(&mut self.commits)
+            .into_iter()
+            .map(|(tid, status)| PendingCommit { tid, status })
+            .collect()
+    }
+
+    /// Removes the pending commits, failing their tickets with the error.
+    /// Returns the ids of their transactions.
+    pub(crate) fn fail(&mut self, err: &LogError) -> Vec<u64> {
+        self.take()
+            .into_iter()
+            .map(|commit| commit.fail(err))
+            .collect()
+    }
+}
+
+impl Drop for PendingCommits {
+    fn drop(&mut self) {
+        for commit in self.take() {
+            commit.status.resolve(Err((
+                io::ErrorKind::Other,
+                "Log dropped before the commit was flushed".to_string(),
+            )));
+        }
+    }
+}
+
+/// A commit taken from `PendingCommits` to be completed.
+pub(crate) struct PendingCommit {
+    pub(crate) tid: u64,
+    status: Arc<CommitStatus>,
+}
+
+impl PendingCommit {
+    /// Resolves the commit's ticket as durable.
+    pub(crate) fn complete(self) {
+        self.status.resolve(Ok(()));
+    }
+
+    /// Fails the commit's ticket with the error, returning the transaction's id.
+    pub(crate) fn fail(self, err: &LogError) -> u64 {
+        let kind = match *err {
+            LogError::IoError(ref err) => err.kind(),
+            _ => io::ErrorKind::Other,
+        };
+        self.status.resolve(Err((kind, err.to_string())));
+        self.tid
+    }
+}
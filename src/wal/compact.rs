@@ -0,0 +1,168 @@
+use std::cmp;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::wal::backend::LogBackend;
+use crate::wal::entries::{ChangeEntry, DeleteEntry, SingleLogEntry, Transaction};
+use crate::wal::iterator::{ReadDirection, WalIterator};
+use crate::wal::redo_log::{redo_changes, Changes};
+use crate::wal::sink::write_serializable_streaming;
+use crate::wal::writer::Writer;
+use crate::wal::{
+    recover_entry, truncate_torn_entry, Compression, LogData, LogError, LogOptions, LogSource,
+    Result,
+};
+
+/// Compacts the redo log at the path like `RedoLog::compact`, without
+/// opening it as a redo log. The log's file is locked while it's compacted,
+/// so it returns `LogError::AlreadyLocked` if a log has it open.
+///
+/// Transactions the log didn't finish before a crash are left out, like
+/// recovery would abort them, and an entry torn by the crash is removed.
+/// Returns `LogError::ActiveTransactions` if the log has prepared
+/// transactions that haven't been resolved, since their entries are needed
+/// to resolve them.
+pub fn compact<Data, P>(path: &P, options: LogOptions) -> Result<()>
+where
+    Data: LogData,
+    P: AsRef<Path> + ?Sized,
+{
+    options.validate()?;
+    let mut writer = options.writer_for(options.open_log_file(path)?)?;
+    truncate_torn_entry::<SingleLogEntry<Data>, _>(&mut writer, options.on_corruption)?;
+    compact_log::<Data, _>(&mut writer, &options, 0)?;
+    Ok(())
+}
+
+/// A log rewritten by `compact_log`.
+pub(crate) struct Compacted<B> {
+    /// Writer appending to the new log.
+    pub writer: Writer<B>,
+    /// LSN and offset of the first record of the last entry in the new log.
+    pub last_entry: Option<(u64, u64)>,
+}
+
+/// Rewrites the log to hold only the last change committed to each key,
+/// written as the changes of a single committed transaction, and returns a
+/// writer appending to the new log.
+///
+/// The transaction's id is the largest id in the log or `last_tid`, so
+/// recovering the new log finds the same last transaction id. Its records
+/// are numbered after the records of the old log, so a store that applied
+/// the old log's changes up to an LSN still replays them. The new log is
+/// written to a backend from `LogBackend::create_rewrite`, which replaces
+/// the log once it is synced, so a crash leaves either the old log or the
+/// new one.
+pub(crate) fn compact_log<Data: LogData, B: LogBackend>(
+    writer: &mut Writer<B>,
+    options: &LogOptions,
+    last_tid: u64,
+) -> Result<Compacted<B>> {
+    let (changes, max_tid) = committed_changes::<Data, B>(writer, options)?;
+    let tid = cmp::max(last_tid, max_tid);
+    let last_lsn = writer.last_lsn();
+
+    let mut last_entry = None;
+    let rewritten = {
+        let backend = writer.file_mut().create_rewrite()?;
+        let mut new_writer = options.writer_for(backend)?.continue_after(last_lsn);
+        if tid > 0 {
+            let mut entries: Vec<SingleLogEntry<Data>> =
+                vec![SingleLogEntry::Transaction(Transaction::Start(tid))];
+            entries.extend(
+                changes
+                    .flush_changes()
+                    .into_iter()
+                    .map(|(key, val)| match val {
+                        Some(value) => SingleLogEntry::ChangeEntry(ChangeEntry { tid, key, value }),
+                        None => SingleLogEntry::DeleteEntry(DeleteEntry {
+                            tid,
+                            key,
+                            value: None,
+                        }),
+                    }),
+            );
+            entries.push(SingleLogEntry::Transaction(Transaction::Commit(tid)));
+            for entry in &entries {
+                let lsn = new_writer.last_lsn() + 1;
+                let offset = if options.compression == Compression::None {
+                    write_serializable_streaming(&mut new_writer, entry)?
+                } else {
+                    new_writer.append_serializable(entry)?
+                };
+                last_entry = Some((lsn, offset));
+            }
+        }
+        new_writer.sync()?;
+        new_writer.into_inner()
+    };
+    let new_last_lsn = cmp::max(last_lsn, last_entry.map_or(0, |(lsn, _)| lsn));
+    let backend = writer.file_mut().replace(rewritten)?;
+    let writer = options.writer_for(backend)?.continue_after(new_last_lsn);
+    Ok(Compacted { writer, last_entry })
+}
+
+/// Reads the log forwards like recovery, returning the changes of its
+/// committed transactions along with the largest transaction id in it.
+///
+/// Returns `LogError::ActiveTransactions` if a transaction was prepared
+/// but not resolved.
+fn committed_changes<Data: LogData, B: LogBackend>(
+    writer: &mut Writer<B>,
+    options: &LogOptions,
+) -> Result<(Changes<Data>, u64)> {
+    let first_offset = writer.file().first_offset();
+    let format = writer.format();
+    let mut source = LogSource::new(writer.file_mut(), options)?;
+    let mut iter = WalIterator::with_readahead(
+        &mut source,
+        ReadDirection::Forward,
+        format,
+        options.readahead_blocks,
+    )?
+    .on_corruption(options.on_corruption);
+    iter.start_at(first_offset)?;
+    let mut entries = iter.entries::<SingleLogEntry<Data>>();
+
+    let mut changes = Changes::new();
+    let mut prepared = HashSet::new();
+    let mut max_tid = 0;
+    let mut read = 0;
+    while let Some(entry) = recover_entry(
+        &mut entries,
+        ReadDirection::Forward,
+        options.on_corruption,
+        &mut read,
+    )? {
+        let tid = match entry.tid() {
+            Some(tid) => tid,
+            None => continue,
+        };
+        max_tid = cmp::max(max_tid, tid);
+        match entry {
+            SingleLogEntry::Transaction(Transaction::Commit(_)) => {
+                prepared.remove(&tid);
+                changes.commit(tid);
+            }
+            SingleLogEntry::Transaction(Transaction::Abort(_)) => {
+                prepared.remove(&tid);
+                changes.abort(tid);
+            }
+            SingleLogEntry::Transaction(Transaction::Prepare(_)) => {
+                prepared.insert(tid);
+            }
+            entry => {
+                for (key, val) in redo_changes(entry) {
+                    changes.insert(tid, key, val);
+                }
+            }
+        }
+    }
+
+    if !prepared.is_empty() {
+        let mut tids: Vec<_> = prepared.into_iter().collect();
+        tids.sort_unstable();
+        return Err(LogError::ActiveTransactions(tids));
+    }
+    Ok((changes, max_tid))
+}
@@ -0,0 +1,287 @@
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+use crate::wal::entries::{Checkpoint, SingleLogEntry, Transaction};
+use crate::wal::iterator::{BlockError, BlockSource, ReadDirection, WalIterator};
+use crate::wal::record::RecordError;
+use crate::wal::{read_serializable_at, LogData, SerializeError};
+
+/// Writes a line for each record left in the iterator with its offset,
+/// type, payload size, LSN and whether its checksum passed.
+///
+/// A corrupted record is written as an error line, after which the dump
+/// continues with the next block, since the rest of the corrupted block
+/// can't be framed.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::io::Cursor;
+/// use disk_utils::wal::dump::dump_records;
+/// use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+/// use disk_utils::wal::writer::Writer;
+///
+/// fn main() {
+///     let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+///     writer.append_serializable(&"entry".to_string()).unwrap();
+///     let mut log = writer.into_inner();
+///
+///     let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+///     let mut out = Vec::new();
+///     dump_records(&mut iter, &mut out).unwrap();
+///     assert_eq!(
+///         String::from_utf8(out).unwrap(),
+///         "0 Full size=9 lsn=1 crc=ok\n"
+///     );
+/// }
+/// ```
+pub fn dump_records<R: BlockSource, W: Write>(
+    iter: &mut WalIterator<R>,
+    out: &mut W,
+) -> io::Result<()> {
+    loop {
+        match iter.try_next() {
+            Ok(Some(record)) => {
+                let offset = iter.last_record_range().unwrap().start;
+                write!(
+                    out,
+                    "{} {:?} size={} lsn={}",
+                    offset, record.record_type, record.size, record.lsn
+                )?;
+                if record.compressed {
+                    write!(out, " compressed")?;
+                }
+                writeln!(out, " crc=ok")?;
+            }
+            Ok(None) => return Ok(()),
+            Err(err) => corruption(err)?.write(out)?,
+        }
+    }
+}
+
+/// Writes a line for each entry left in the iterator, decoded as a
+/// `SingleLogEntry`, with the offset of its first record, its transaction,
+/// its kind and the debug output of its keys and values.
+///
+/// An entry that can't be read is written as an error line, after which
+/// the dump resyncs to the next entry it can read, like
+/// `read_serializable_resync`.
+pub fn dump_entries<Data, R, W>(iter: &mut WalIterator<R>, out: &mut W) -> io::Result<()>
+where
+    Data: LogData,
+    R: BlockSource,
+    W: Write,
+{
+    walk_entries::<Data, R, _>(iter, |item| match item {
+        Item::Entry { entry, range, .. } => {
+            write!(out, "{} ", range.start)?;
+            write_entry(out, &entry)
+        }
+        Item::Error(failure) => failure.write(out),
+    })
+}
+
+/// Writes a JSON object on its own line for each entry left in the
+/// iterator, decoded as a `SingleLogEntry`.
+///
+/// Each object has the `offset` of the entry's first record, the `end` of
+/// its last record, the number of `records` it spans and the `entry`
+/// itself. An entry that can't be read is written as an object with its
+/// `offset` and an `error` message, after which the export resyncs like
+/// `dump_entries`.
+#[cfg(feature = "serde")]
+pub fn to_json_lines<Data, R, W>(iter: &mut WalIterator<R>, out: &mut W) -> io::Result<()>
+where
+    Data: LogData,
+    Data::Key: serde::Serialize,
+    Data::Value: serde::Serialize,
+    R: BlockSource,
+    W: Write,
+{
+    walk_entries::<Data, R, _>(iter, |item| {
+        match item {
+            Item::Entry {
+                entry,
+                range,
+                records,
+            } => serde_json::to_writer(
+                &mut *out,
+                &JsonEntry {
+                    offset: range.start,
+                    end: range.end,
+                    records,
+                    entry: &entry,
+                },
+            )?,
+            Item::Error(failure) => serde_json::to_writer(
+                &mut *out,
+                &JsonError {
+                    offset: failure.offset,
+                    error: &failure.message,
+                },
+            )?,
+        }
+        writeln!(out)
+    })
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+#[serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")]
+struct JsonEntry<'a, Data: LogData> {
+    offset: u64,
+    end: u64,
+    records: usize,
+    entry: &'a SingleLogEntry<Data>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonError<'a> {
+    offset: u64,
+    error: &'a str,
+}
+
+/// An entry read by `walk_entries`, or an entry that couldn't be read.
+enum Item<Data: LogData> {
+    Entry {
+        entry: SingleLogEntry<Data>,
+        range: Range<u64>,
+        /// Only exported as JSON.
+        #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+        records: usize,
+    },
+    Error(Failure),
+}
+
+/// An entry or record that couldn't be read.
+struct Failure {
+    offset: u64,
+    /// Whether a record failed its checksum.
+    bad_crc: bool,
+    message: String,
+}
+
+impl Failure {
+    fn new(offset: u64, message: String) -> Failure {
+        Failure {
+            offset,
+            bad_crc: false,
+            message,
+        }
+    }
+
+    /// Writes the failure as an error line.
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let crc = if self.bad_crc { " crc=bad" } else { "" };
+        writeln!(out, "{} error{}: {}", self.offset, crc, self.message)
+    }
+}
+
+/// Reads the entries left in the iterator, passing each entry or error to
+/// `f`, and resyncs after errors so the entries after them are read too.
+fn walk_entries<Data, R, F>(iter: &mut WalIterator<R>, mut f: F) -> io::Result<()>
+where
+    Data: LogData,
+    R: BlockSource,
+    F: FnMut(Item<Data>) -> io::Result<()>,
+{
+    loop {
+        let records_read = iter.stats().records_read;
+        match read_serializable_at::<SingleLogEntry<Data>>(iter) {
+            Ok((entry, range)) => f(Item::Entry {
+                entry,
+                range,
+                records: iter.stats().records_read - records_read,
+            })?,
+            Err(SerializeError::OutOfRecords) => return Ok(()),
+            Err(SerializeError::BlockError(err)) | Err(SerializeError::Corrupted(err)) => {
+                f(Item::Error(corruption(err)?))?
+            }
+            Err(err @ SerializeError::InvalidTransfer { offset, .. }) => {
+                f(Item::Error(Failure::new(offset, err.to_string())))?;
+                iter.skip_to_entry_boundary(ReadDirection::Forward)
+                    .map_err(into_io_error)?;
+            }
+            Err(err @ SerializeError::TornEntry { start, .. }) => {
+                return f(Item::Error(Failure::new(start, err.to_string())));
+            }
+            // The entry's records were read, but its bytes aren't an entry.
+            Err(SerializeError::IoError(err)) => {
+                let offset = iter.last_record_range().map_or(0, |range| range.start);
+                let message = format!("Entry can't be deserialized: {}", err);
+                f(Item::Error(Failure::new(offset, message)))?
+            }
+        }
+    }
+}
+
+/// Writes an entry's transaction, kind, keys and values.
+fn write_entry<Data: LogData, W: Write>(
+    out: &mut W,
+    entry: &SingleLogEntry<Data>,
+) -> io::Result<()> {
+    match *entry {
+        SingleLogEntry::InsertEntry(ref entry) => {
+            writeln!(out, "tid={} insert key={:?}", entry.tid, entry.key)
+        }
+        SingleLogEntry::ChangeEntry(ref entry) => writeln!(
+            out,
+            "tid={} change key={:?} value={:?}",
+            entry.tid, entry.key, entry.value
+        ),
+        SingleLogEntry::DeleteEntry(ref entry) => writeln!(
+            out,
+            "tid={} delete key={:?} value={:?}",
+            entry.tid, entry.key, entry.value
+        ),
+        SingleLogEntry::MultiChangeEntry(ref entry) => {
+            write!(out, "tid={} multi-change", entry.tid)?;
+            for (key, value) in &entry.changes {
+                write!(out, " key={:?} value={:?}", key, value)?;
+            }
+            writeln!(out)
+        }
+        SingleLogEntry::Transaction(ref transaction) => {
+            let (kind, tid) = match *transaction {
+                Transaction::Start(tid) => ("start", tid),
+                Transaction::Commit(tid) => ("commit", tid),
+                Transaction::Abort(tid) => ("abort", tid),
+                Transaction::Prepare(tid) => ("prepare", tid),
+            };
+            writeln!(out, "tid={} {}", tid, kind)
+        }
+        SingleLogEntry::Checkpoint(Checkpoint::Begin(ref active)) => {
+            writeln!(out, "checkpoint-begin active={:?}", active)
+        }
+        SingleLogEntry::Checkpoint(Checkpoint::End) => writeln!(out, "checkpoint-end"),
+    }
+}
+
+/// Turns a corrupted record into a failure, returning any other error,
+/// which would be returned again if the dump went on.
+fn corruption(err: BlockError) -> io::Result<Failure> {
+    match err {
+        BlockError::Corrupted { offset, .. } => {
+            let bad_crc = matches!(
+                err.record_error(),
+                Some(RecordError::BadChecksum { .. }) | Some(RecordError::BadBlockChecksum { .. })
+            );
+            Ok(Failure {
+                offset,
+                bad_crc,
+                message: err.to_string(),
+            })
+        }
+        err => Err(into_io_error(err)),
+    }
+}
+
+fn into_io_error(err: BlockError) -> io::Error {
+    match err {
+        BlockError::IoError(err) => err,
+        err => io::Error::other(err.to_string()),
+    }
+}
@@ -6,10 +6,14 @@ use super::super::Serializable;
 use crate::wal::LogData;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Transaction {
     Start(u64),
     Commit(u64),
     Abort(u64),
+    /// The transaction is prepared to commit as part of a two-phase commit,
+    /// and waits to be committed or aborted.
+    Prepare(u64),
 }
 
 impl Serializable for Transaction {
@@ -18,12 +22,14 @@ impl Serializable for Transaction {
             Transaction::Start(_) => bytes.write(&[0])?,
             Transaction::Commit(_) => bytes.write(&[1])?,
             Transaction::Abort(_) => bytes.write(&[2])?,
+            Transaction::Prepare(_) => bytes.write(&[3])?,
         };
 
         let tid = match *self {
             Transaction::Start(tid) => tid,
             Transaction::Commit(tid) => tid,
             Transaction::Abort(tid) => tid,
+            Transaction::Prepare(tid) => tid,
         };
 
         tid.serialize(bytes)?;
@@ -39,32 +45,51 @@ impl Serializable for Transaction {
             0 => Ok(Transaction::Start(tid)),
             1 => Ok(Transaction::Commit(tid)),
             2 => Ok(Transaction::Abort(tid)),
+            3 => Ok(Transaction::Prepare(tid)),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid transaction type",
             )),
         }
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(9)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Checkpoint {
     Begin(Vec<u64>),
     End,
 }
 
+/// Tag for begin checkpoints written with a signed i32 length.
+/// Only read for compatibility with older logs.
+const CHECKPOINT_BEGIN_LEGACY: u8 = 0;
+const CHECKPOINT_END: u8 = 1;
+/// Tag for begin checkpoints written with an unsigned u32 length.
+const CHECKPOINT_BEGIN: u8 = 2;
+
 impl Serializable for Checkpoint {
     fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
         match *self {
             Checkpoint::Begin(ref transactions) => {
-                bytes.write_all(&[0])?;
-                (transactions.len() as i32).serialize(bytes)?;
+                if transactions.len() > u32::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Too many transactions in checkpoint",
+                    ));
+                }
+                bytes.write_all(&[CHECKPOINT_BEGIN])?;
+                (transactions.len() as u32).serialize(bytes)?;
                 for tid in transactions.iter() {
                     tid.serialize(bytes)?;
                 }
             }
             Checkpoint::End => {
-                bytes.write_all(&[1])?;
+                bytes.write_all(&[CHECKPOINT_END])?;
             }
         }
 
@@ -75,26 +100,56 @@ impl Serializable for Checkpoint {
         let mut checkpoint_type = [0; 1];
         bytes.read_exact(&mut checkpoint_type)?;
 
-        match checkpoint_type[0] {
-            0 => {
+        let len = match checkpoint_type[0] {
+            CHECKPOINT_BEGIN_LEGACY => {
                 let len = i32::deserialize(bytes)?;
-                let mut transactions = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    transactions.push(u64::deserialize(bytes)?);
+                if len < 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Negative checkpoint length",
+                    ));
                 }
+                len as u32
+            }
+            CHECKPOINT_BEGIN => u32::deserialize(bytes)?,
+            CHECKPOINT_END => return Ok(Checkpoint::End),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid checkpoint type",
+                ))
+            }
+        };
+
+        let mut transactions = Vec::new();
+        for _ in 0..len {
+            transactions.push(u64::deserialize(bytes)?);
+        }
+        Ok(Checkpoint::Begin(transactions))
+    }
 
-                Ok(Checkpoint::Begin(transactions))
+    fn serialized_size(&self) -> io::Result<u64> {
+        match *self {
+            Checkpoint::Begin(ref transactions) => {
+                if transactions.len() > u32::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Too many transactions in checkpoint",
+                    ));
+                }
+                Ok(5 + 8 * transactions.len() as u64)
             }
-            1 => Ok(Checkpoint::End),
-            _ => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Invalid checkpoint type",
-            )),
+            Checkpoint::End => Ok(1),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")
+)]
 pub struct InsertEntry<Data: LogData> {
     pub tid: u64,
     pub key: Data::Key,
@@ -117,9 +172,18 @@ where
 
         Ok(InsertEntry { tid, key })
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(8 + self.key.serialized_size()?)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")
+)]
 pub struct ChangeEntry<Data: LogData> {
     pub tid: u64,
     pub key: Data::Key,
@@ -147,16 +211,183 @@ where
 
         Ok(ChangeEntry { tid, key, value })
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(8 + self.key.serialized_size()? + self.value.serialized_size()?)
+    }
+}
+
+/// Deletion of a key by a transaction.
+///
+/// Undo logs log the value the key had before it was deleted, so rolling
+/// back the transaction restores it. Redo logs don't log a value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")
+)]
+pub struct DeleteEntry<Data: LogData> {
+    pub tid: u64,
+    pub key: Data::Key,
+    pub value: Option<Data::Value>,
+}
+
+impl<Data> Serializable for DeleteEntry<Data>
+where
+    Data: LogData,
+{
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        self.tid.serialize(bytes)?;
+        self.key.serialize(bytes)?;
+        serialize_optional_value::<Data, W>(&self.value, bytes)
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<DeleteEntry<Data>> {
+        let tid = u64::deserialize(bytes)?;
+        let key = Data::Key::deserialize(bytes)?;
+        let value = deserialize_optional_value::<Data, R>(bytes)?;
+
+        Ok(DeleteEntry { tid, key, value })
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(8 + self.key.serialized_size()? + optional_value_size::<Data>(&self.value)?)
+    }
+}
+
+fn serialize_optional_value<Data: LogData, W: Write>(
+    value: &Option<Data::Value>,
+    bytes: &mut W,
+) -> io::Result<()> {
+    match *value {
+        Some(ref value) => {
+            bytes.write_all(&[1])?;
+            value.serialize(bytes)
+        }
+        None => bytes.write_all(&[0]),
+    }
+}
+
+fn optional_value_size<Data: LogData>(value: &Option<Data::Value>) -> io::Result<u64> {
+    match *value {
+        Some(ref value) => Ok(1 + value.serialized_size()?),
+        None => Ok(1),
+    }
+}
+
+fn deserialize_optional_value<Data: LogData, R: Read>(
+    bytes: &mut R,
+) -> io::Result<Option<Data::Value>> {
+    let mut has_value = [0; 1];
+    bytes.read_exact(&mut has_value)?;
+    match has_value[0] {
+        0 => Ok(None),
+        1 => Ok(Some(Data::Value::deserialize(bytes)?)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid change value tag",
+        )),
+    }
+}
+
+/// Changes of several keys by a transaction, logged as one entry so
+/// the changes share the framing of a single entry.
+///
+/// The changes are in the order they were written. A key without a
+/// value is deleted by a redo log's transaction, or didn't exist before
+/// an undo log's transaction wrote it, so rolling it back removes the key.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")
+)]
+pub struct MultiChangeEntry<Data: LogData> {
+    pub tid: u64,
+    pub changes: Vec<(Data::Key, Option<Data::Value>)>,
+}
+
+impl<Data> Serializable for MultiChangeEntry<Data>
+where
+    Data: LogData,
+{
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        if self.changes.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many changes in entry",
+            ));
+        }
+        self.tid.serialize(bytes)?;
+        (self.changes.len() as u32).serialize(bytes)?;
+        for (key, value) in self.changes.iter() {
+            key.serialize(bytes)?;
+            serialize_optional_value::<Data, W>(value, bytes)?;
+        }
+
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<MultiChangeEntry<Data>> {
+        let tid = u64::deserialize(bytes)?;
+        let len = u32::deserialize(bytes)?;
+        let mut changes = Vec::new();
+        for _ in 0..len {
+            let key = Data::Key::deserialize(bytes)?;
+            let value = deserialize_optional_value::<Data, R>(bytes)?;
+            changes.push((key, value));
+        }
+
+        Ok(MultiChangeEntry { tid, changes })
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        if self.changes.len() > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Too many changes in entry",
+            ));
+        }
+        self.changes.iter().try_fold(12, |size, (key, value)| {
+            Ok(size + key.serialized_size()? + optional_value_size::<Data>(value)?)
+        })
+    }
 }
 
 /// Main log entry for undo logs and redo logs.
 /// This entry type is not used by undo/redo logs.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound = "Data::Key: serde::Serialize, Data::Value: serde::Serialize")
+)]
 pub enum SingleLogEntry<Data: LogData> {
     InsertEntry(InsertEntry<Data>),
     ChangeEntry(ChangeEntry<Data>),
     Transaction(Transaction),
     Checkpoint(Checkpoint),
+    MultiChangeEntry(MultiChangeEntry<Data>),
+    DeleteEntry(DeleteEntry<Data>),
+}
+
+impl<Data: LogData> SingleLogEntry<Data> {
+    /// Returns the id of the transaction the entry belongs to,
+    /// or None for checkpoints.
+    pub fn tid(&self) -> Option<u64> {
+        match *self {
+            SingleLogEntry::InsertEntry(ref entry) => Some(entry.tid),
+            SingleLogEntry::ChangeEntry(ref entry) => Some(entry.tid),
+            SingleLogEntry::MultiChangeEntry(ref entry) => Some(entry.tid),
+            SingleLogEntry::DeleteEntry(ref entry) => Some(entry.tid),
+            SingleLogEntry::Transaction(Transaction::Start(tid))
+            | SingleLogEntry::Transaction(Transaction::Commit(tid))
+            | SingleLogEntry::Transaction(Transaction::Abort(tid))
+            | SingleLogEntry::Transaction(Transaction::Prepare(tid)) => Some(tid),
+            SingleLogEntry::Checkpoint(_) => None,
+        }
+    }
 }
 
 impl<Data> Serializable for SingleLogEntry<Data>
@@ -181,6 +412,14 @@ where
                 bytes.write_all(&[3])?;
                 entry.serialize(bytes)
             }
+            SingleLogEntry::MultiChangeEntry(ref entry) => {
+                bytes.write_all(&[4])?;
+                entry.serialize(bytes)
+            }
+            SingleLogEntry::DeleteEntry(ref entry) => {
+                bytes.write_all(&[5])?;
+                entry.serialize(bytes)
+            }
         }
     }
 
@@ -199,10 +438,28 @@ where
                 bytes,
             )?)),
             3 => Ok(SingleLogEntry::Checkpoint(Checkpoint::deserialize(bytes)?)),
+            4 => Ok(SingleLogEntry::MultiChangeEntry(
+                MultiChangeEntry::deserialize(bytes)?,
+            )),
+            5 => Ok(SingleLogEntry::DeleteEntry(DeleteEntry::deserialize(
+                bytes,
+            )?)),
             _ => Err(io::Error::new(
                 io::ErrorKind::InvalidData,
                 "Invalid entry type",
             )),
         }
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        let size = match *self {
+            SingleLogEntry::InsertEntry(ref entry) => entry.serialized_size()?,
+            SingleLogEntry::ChangeEntry(ref entry) => entry.serialized_size()?,
+            SingleLogEntry::Transaction(ref entry) => entry.serialized_size()?,
+            SingleLogEntry::Checkpoint(ref entry) => entry.serialized_size()?,
+            SingleLogEntry::MultiChangeEntry(ref entry) => entry.serialized_size()?,
+            SingleLogEntry::DeleteEntry(ref entry) => entry.serialized_size()?,
+        };
+        Ok(1 + size)
+    }
 }
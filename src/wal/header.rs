@@ -0,0 +1,234 @@
+use byteorder::{BigEndian, ByteOrder};
+use crc::crc32;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::wal::iterator::BlockSource;
+use crate::wal::record::BlockFormat;
+
+/// Bytes a log file starts with. The first byte isn't a valid record
+/// type byte, so a log with a header can't be mistaken for one without.
+pub const MAGIC: [u8; 8] = *b"\xffDUWAL\r\n";
+
+/// 32B Size of the file header at the start of the first block.
+pub const FILE_HEADER_SIZE: usize = 32;
+
+/// Version of the file header written by `FileHeader::to_bytes`.
+pub const FILE_HEADER_VERSION: u8 = 1;
+
+/// Algorithm of the checksums in the log's records and blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32 with the IEEE polynomial.
+    Crc32 = 1,
+}
+
+/// Header at the start of a log file describing how the log is written.
+///
+/// The header takes up the whole first block of the file so the blocks
+/// after it stay aligned with the file, and records start in the second block.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use disk_utils::wal::header::FileHeader;
+/// use disk_utils::wal::record::BlockFormat;
+///
+/// fn main() {
+///     let header = FileHeader::new(BlockFormat::default());
+///     let parsed = FileHeader::parse(&header.to_bytes()).unwrap();
+///     assert_eq!(parsed, header);
+///     assert_eq!(parsed.format(), BlockFormat::default());
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u8,
+    pub checksum_algorithm: ChecksumAlgorithm,
+    pub block_size: i64,
+    /// Whether each block ends with a CRC over the whole block.
+    pub block_checksums: bool,
+    /// When the log was created, in seconds since the Unix epoch.
+    pub created: u64,
+}
+
+impl FileHeader {
+    /// Creates a header for a log created now with the given block format.
+    pub fn new(format: BlockFormat) -> FileHeader {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        FileHeader {
+            version: FILE_HEADER_VERSION,
+            checksum_algorithm: ChecksumAlgorithm::Crc32,
+            block_size: format.block_size,
+            block_checksums: format.checksums,
+            created,
+        }
+    }
+
+    /// Returns the block format the log is written with.
+    pub fn format(&self) -> BlockFormat {
+        BlockFormat {
+            block_size: self.block_size,
+            checksums: self.block_checksums,
+        }
+    }
+
+    /// Returns an error if the log is opened with a different block
+    /// format than it was written with.
+    pub fn check_format(&self, format: BlockFormat) -> Result<(), HeaderError> {
+        if self.format() != format {
+            return Err(HeaderError::FormatMismatch {
+                expected: format,
+                actual: self.format(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Encodes the header as `[magic][version][checksum algorithm][flags]
+    /// [reserved][block size u32][created u64][reserved u32][crc u32]`.
+    pub fn to_bytes(&self) -> [u8; FILE_HEADER_SIZE] {
+        let mut bytes = [0; FILE_HEADER_SIZE];
+        bytes[..8].copy_from_slice(&MAGIC);
+        bytes[8] = self.version;
+        bytes[9] = self.checksum_algorithm as u8;
+        bytes[10] = self.block_checksums as u8;
+        BigEndian::write_u32(&mut bytes[12..16], self.block_size as u32);
+        BigEndian::write_u64(&mut bytes[16..24], self.created);
+        let crc = crc32::checksum_ieee(&bytes[..28]);
+        BigEndian::write_u32(&mut bytes[28..], crc);
+        bytes
+    }
+
+    /// Parses a header from the start of a log file.
+    pub fn parse(bytes: &[u8]) -> Result<FileHeader, HeaderError> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(HeaderError::Missing);
+        }
+        if bytes.len() < FILE_HEADER_SIZE {
+            return Err(HeaderError::Truncated { len: bytes.len() });
+        }
+        let expected = BigEndian::read_u32(&bytes[28..32]);
+        let actual = crc32::checksum_ieee(&bytes[..28]);
+        if expected != actual {
+            return Err(HeaderError::BadChecksum { expected, actual });
+        }
+        if bytes[8] != FILE_HEADER_VERSION {
+            return Err(HeaderError::UnknownVersion(bytes[8]));
+        }
+        let checksum_algorithm = match bytes[9] {
+            1 => ChecksumAlgorithm::Crc32,
+            algorithm => return Err(HeaderError::UnknownChecksumAlgorithm(algorithm)),
+        };
+        Ok(FileHeader {
+            version: bytes[8],
+            checksum_algorithm,
+            block_size: BigEndian::read_u32(&bytes[12..16]) as i64,
+            block_checksums: bytes[10] & 1 != 0,
+            created: BigEndian::read_u64(&bytes[16..24]),
+        })
+    }
+
+    /// Reads the header at the start of the log, returning None if
+    /// the log doesn't start with one.
+    pub fn read<S: BlockSource>(source: &mut S) -> io::Result<Option<FileHeader>> {
+        let bytes = source.read_at(0, FILE_HEADER_SIZE)?;
+        match FileHeader::parse(&bytes) {
+            Ok(header) => Ok(Some(header)),
+            Err(HeaderError::Missing) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the first block of a log file holding the header.
+    pub(crate) fn to_block(self) -> Vec<u8> {
+        let mut block = vec![0; self.block_size as usize];
+        block[..FILE_HEADER_SIZE].copy_from_slice(&self.to_bytes());
+        block
+    }
+}
+
+/// Error from reading the header of a log file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderError {
+    /// The file doesn't start with the header's magic bytes.
+    Missing,
+    /// The file has data, but doesn't start with a header. The file
+    /// isn't a log, or is a log written before log files had headers.
+    MissingWithData {
+        len: u64,
+    },
+    /// The file ends partway through the header.
+    Truncated {
+        len: usize,
+    },
+    /// The header's checksum doesn't match its contents.
+    BadChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    /// The header was written with a newer version than this crate reads.
+    UnknownVersion(u8),
+    UnknownChecksumAlgorithm(u8),
+    /// The log was written with a different block format than it was opened with.
+    FormatMismatch {
+        expected: BlockFormat,
+        actual: BlockFormat,
+    },
+}
+
+impl HeaderError {
+    /// Returns the header error wrapped in an IO error.
+    pub fn from_io_error(err: &io::Error) -> Option<&HeaderError> {
+        err.get_ref().and_then(|err| err.downcast_ref())
+    }
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeaderError::Missing => write!(f, "File doesn't start with a log header"),
+            HeaderError::MissingWithData { len } => write!(
+                f,
+                "File has {} bytes but no log header, so it isn't a log or was written \
+                 before log headers",
+                len
+            ),
+            HeaderError::Truncated { len } => {
+                write!(f, "Log header cut off after {} bytes", len)
+            }
+            HeaderError::BadChecksum { expected, actual } => write!(
+                f,
+                "Log header checksum failed, expected {:#010x} but found {:#010x}",
+                expected, actual
+            ),
+            HeaderError::UnknownVersion(version) => {
+                write!(f, "Unknown log header version {}", version)
+            }
+            HeaderError::UnknownChecksumAlgorithm(algorithm) => {
+                write!(f, "Unknown checksum algorithm {}", algorithm)
+            }
+            HeaderError::FormatMismatch { expected, actual } => write!(
+                f,
+                "Log was written with block size {} and block checksums {}, but opened \
+                 with block size {} and block checksums {}",
+                actual.block_size, actual.checksums, expected.block_size, expected.checksums
+            ),
+        }
+    }
+}
+
+impl error::Error for HeaderError {}
+
+impl From<HeaderError> for io::Error {
+    fn from(err: HeaderError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
@@ -1,22 +1,272 @@
+use std::cmp;
+use std::error;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
 use std::result;
+use std::sync::Arc;
 
-use crate::wal::record::{Record, BLOCK_SIZE};
+use byteorder::{BigEndian, ReadBytesExt};
+use crc::crc32;
 
-#[derive(PartialEq)]
+use crate::wal::entries::SingleLogEntry;
+use crate::wal::header::{FileHeader, MAGIC};
+use crate::wal::record::{
+    BlockFormat, Payload, Record, RecordError, RecordType, HEADER_SIZE, LEGACY_HEADER_SIZE,
+    PADDING_BYTE,
+};
+use crate::wal::{
+    read_serializable, read_serializable_backwards, LogData, SerializeError, SerializeResult,
+};
+use crate::Serializable;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ReadDirection {
     Forward,
     Backward,
 }
 
+/// Handle to a log file that reads blocks without moving the file's
+/// position, so it can read a log while other handles to the file write
+/// to it or read it.
+///
+/// An iterator over a `SharedFile` reads the records written to the file
+/// before the iterator was created. Records appended afterwards, including
+/// padding written at the end of the last block, are seen by the iterators
+/// created after them.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::fs::OpenOptions;
+/// use disk_utils::wal::iterator::{ReadDirection, SharedFile, WalIterator};
+/// use disk_utils::wal::record::{BlockFormat, Record, RecordType};
+/// use disk_utils::wal::writer::Writer;
+///
+/// fn main() {
+///     let file = OpenOptions::new()
+///         .read(true)
+///         .append(true)
+///         .create(true)
+///         .open("./files/shared_file_doc_example")
+///         .unwrap();
+///     let mut writer = Writer::new(file).unwrap();
+///     let record = Record::new(RecordType::Full, vec![1, 2, 3]).unwrap();
+///     writer.append(&record).unwrap();
+///
+///     let reader = SharedFile::new(writer.file()).unwrap();
+///     let format = BlockFormat::default();
+///     let iter = WalIterator::owned(reader, ReadDirection::Forward, format).unwrap();
+///     writer.append(&record).unwrap();
+///     assert_eq!(iter.count(), 1);
+///     # std::fs::remove_file("./files/shared_file_doc_example").unwrap();
+/// }
+/// ```
+pub struct SharedFile {
+    file: File,
+}
+
+impl SharedFile {
+    /// Creates a handle to the same file as the given handle.
+    pub fn new(file: &File) -> io::Result<SharedFile> {
+        Ok(SharedFile {
+            file: file.try_clone()?,
+        })
+    }
+
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+impl From<File> for SharedFile {
+    fn from(file: File) -> SharedFile {
+        SharedFile { file }
+    }
+}
+
+impl BlockSource for SharedFile {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Payload> {
+        let mut shared: Arc<[u8]> = iter::repeat_n(0, len).collect();
+        let buf = Arc::get_mut(&mut shared).unwrap();
+        let mut bytes_read = 0;
+        while bytes_read < len {
+            let offset = pos + bytes_read as u64;
+            match read_file_at(&self.file, &mut buf[bytes_read..], offset) {
+                Ok(0) => break,
+                Ok(n) => bytes_read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Payload::from(shared).slice(0..bytes_read))
+    }
+}
+
+/// Reads from the file at the offset without using the file's position.
+#[cfg(unix)]
+pub(crate) fn read_file_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+/// Reads from the file at the offset. Windows moves the file's position,
+/// which doesn't affect writers to files opened for appending.
+#[cfg(windows)]
+pub(crate) fn read_file_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+/// Saved position of a `WalIterator`, used to resume reading a log
+/// where an earlier iterator stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    /// Position of the block the iterator was in.
+    pos: u64,
+    /// Index of the record after the position in the block.
+    index: u32,
+    direction: ReadDirection,
+}
+
+impl Cursor {
+    pub fn direction(&self) -> ReadDirection {
+        self.direction
+    }
+}
+
+impl Serializable for Cursor {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        self.pos.serialize(bytes)?;
+        self.index.serialize(bytes)?;
+        let direction = match self.direction {
+            ReadDirection::Forward => 0,
+            ReadDirection::Backward => 1,
+        };
+        bytes.write_all(&[direction])
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<Cursor> {
+        let pos = u64::deserialize(bytes)?;
+        let index = u32::deserialize(bytes)?;
+        let mut direction = [0; 1];
+        bytes.read_exact(&mut direction)?;
+        let direction = match direction[0] {
+            0 => ReadDirection::Forward,
+            1 => ReadDirection::Backward,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid cursor direction",
+                ))
+            }
+        };
+        Ok(Cursor {
+            pos,
+            index,
+            direction,
+        })
+    }
+}
+
+/// What an iterator does when it finds a corrupted record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnCorruption {
+    /// Return the corruption as an error. The records before the corrupted
+    /// record in the block are still returned first.
+    #[default]
+    Error,
+    /// Skip the rest of the corrupted block and continue with the next block.
+    SkipToNextBlock,
+}
+
+/// Counts of the work done by an iterator, returned by `WalIterator::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Records returned by the iterator, not counting peeks.
+    pub records_read: usize,
+    /// Bytes read from the source, including headers read by `count_records`.
+    pub bytes_read: u64,
+    /// Blocks parsed by the iterator.
+    pub blocks_loaded: usize,
+    /// Corruptions skipped with `OnCorruption::SkipToNextBlock`, each of
+    /// which ends the records read from its block.
+    pub corrupt_records_skipped: usize,
+    /// Bytes of padding at the end of the loaded blocks.
+    pub padding_bytes: u64,
+}
+
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum BlockError {
     IoError(io::Error),
     EmptyBlock,
     OutOfBounds,
+    /// A record in the block failed to parse.
+    Corrupted {
+        /// Position in the file where the corrupted record starts.
+        offset: u64,
+        /// Index of the block the record is in.
+        block: u64,
+        /// Index of the record in the block, which is the number of
+        /// records parsed before it.
+        record: usize,
+        /// The failure, usually wrapping a `RecordError`.
+        error: io::Error,
+    },
+}
+
+impl BlockError {
+    /// Returns the record error that caused the block error, if any.
+    ///
+    /// A truncation error means the log ends partway through a record,
+    /// which only happens in the last block of the log.
+    pub fn record_error(&self) -> Option<&RecordError> {
+        match *self {
+            BlockError::IoError(ref error) | BlockError::Corrupted { ref error, .. } => {
+                RecordError::from_io_error(error)
+            }
+            _ => None,
+        }
+    }
+
+    /// Copies a corruption error so it can be both returned and remembered.
+    fn duplicate(&self) -> BlockError {
+        match *self {
+            BlockError::Corrupted {
+                offset,
+                block,
+                record,
+                ref error,
+            } => {
+                let error = match RecordError::from_io_error(error) {
+                    Some(record_error) => record_error.clone().into(),
+                    None => io::Error::new(error.kind(), error.to_string()),
+                };
+                BlockError::Corrupted {
+                    offset,
+                    block,
+                    record,
+                    error,
+                }
+            }
+            BlockError::IoError(ref error) => {
+                BlockError::IoError(io::Error::new(error.kind(), error.to_string()))
+            }
+            BlockError::EmptyBlock => BlockError::EmptyBlock,
+            BlockError::OutOfBounds => BlockError::OutOfBounds,
+        }
+    }
 }
 
 impl From<io::Error> for BlockError {
@@ -25,163 +275,1149 @@ impl From<io::Error> for BlockError {
     }
 }
 
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlockError::IoError(ref err) => write!(f, "Reading a block failed: {}", err),
+            BlockError::EmptyBlock => write!(f, "Block has no records"),
+            BlockError::OutOfBounds => write!(f, "Position is outside of the log"),
+            BlockError::Corrupted {
+                offset,
+                block,
+                record,
+                ref error,
+            } => write!(
+                f,
+                "Record {} of block {} at offset {} is corrupted: {}",
+                record, block, offset, error
+            ),
+        }
+    }
+}
+
+impl error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BlockError::IoError(ref err) | BlockError::Corrupted { error: ref err, .. } => {
+                Some(err)
+            }
+            _ => None,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, BlockError>;
 
+/// Source of the bytes of a log read by a `WalIterator`.
+///
+/// Every `Read + Seek` type is a block source that reads blocks into a new
+/// buffer, which the payloads of the records in the blocks share.
+pub trait BlockSource {
+    /// Returns the length of the log in bytes.
+    fn size(&mut self) -> io::Result<u64>;
+
+    /// Returns up to `len` bytes at the position, stopping early
+    /// at the end of the log.
+    fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Payload>;
+}
+
+impl<R: Read + Seek> BlockSource for R {
+    fn size(&mut self) -> io::Result<u64> {
+        self.seek(SeekFrom::End(0))
+    }
+
+    fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Payload> {
+        self.seek(SeekFrom::Start(pos))?;
+        let mut shared: Arc<[u8]> = iter::repeat_n(0, len).collect();
+        let buf = Arc::get_mut(&mut shared).unwrap();
+        let mut bytes_read = 0;
+        while bytes_read < len {
+            match self.read(&mut buf[bytes_read..]) {
+                Ok(0) => break,
+                Ok(n) => bytes_read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Payload::from(shared).slice(0..bytes_read))
+    }
+}
+
 /// Iterator that reads through the write ahead log.
-pub struct WalIterator<'a> {
-    manager: BlockManager<'a>,
+///
+/// The log can be read from any `BlockSource` and defaults to a `File`.
+/// `try_next` and `try_next_back` return errors from reading the source,
+/// while the `Iterator` implementation stops at the first error and keeps it
+/// to be retrieved with `error`.
+///
+/// Like other double ended iterators, records are taken from the front of the
+/// log by `next` and from the back by `next_back`, and iteration ends when the
+/// two meet so every record is returned once. The direction only decides which
+/// end of the log is loaded when the iterator is created.
+///
+/// Corrupted records are returned as errors by default. Use `on_corruption`
+/// to skip corrupted blocks instead.
+pub struct WalIterator<'a, R: BlockSource + 'a = File> {
+    manager: BlockManager<'a, R>,
+    front: BlockCursor,
+    back: BlockCursor,
     direction: ReadDirection,
-    block: Vec<Record>,
-    index: i32,
+    on_corruption: OnCorruption,
+    error: Option<BlockError>,
+    /// Offsets in the file of the bytes of the last record returned,
+    /// the index of the record in its block and its LSN.
+    last_record: Option<(Range<u64>, usize, u64)>,
+    /// Whether records have been read from the back of the log.
+    back_moved: bool,
 }
 
-impl<'a> WalIterator<'a> {
-    pub fn new<'b>(file: &'b mut File, direction: ReadDirection) -> Result<WalIterator<'b>> {
-        let mut manager = BlockManager::new(file, &direction)?;
-        let block = manager.curr();
-        let index = match direction {
-            ReadDirection::Forward => -1,
-            ReadDirection::Backward => block.len() as i32,
+impl<'a, R: BlockSource + 'a> WalIterator<'a, R> {
+    pub fn new(file: &'a mut R, direction: ReadDirection) -> Result<WalIterator<'a, R>> {
+        WalIterator::with_format(file, direction, BlockFormat::default())
+    }
+
+    /// Creates an iterator over a log written with the given block size.
+    pub fn with_block_size(
+        file: &'a mut R,
+        direction: ReadDirection,
+        block_size: i64,
+    ) -> Result<WalIterator<'a, R>> {
+        let format = BlockFormat {
+            block_size,
+            ..BlockFormat::default()
         };
+        WalIterator::with_format(file, direction, format)
+    }
+
+    /// Creates an iterator over a log written with the given block format.
+    pub fn with_format(
+        file: &'a mut R,
+        direction: ReadDirection,
+        format: BlockFormat,
+    ) -> Result<WalIterator<'a, R>> {
+        WalIterator::with_readahead(file, direction, format, 1)
+    }
+
+    /// Creates an iterator that reads `blocks` blocks from the file at a time
+    /// and parses them as they are reached, reading the blocks before the
+    /// current one when iterating backwards.
+    ///
+    /// Reading ahead issues fewer reads for long scans like recovery at the
+    /// cost of a buffer of `blocks` blocks.
+    pub fn with_readahead(
+        file: &'a mut R,
+        direction: ReadDirection,
+        format: BlockFormat,
+        blocks: usize,
+    ) -> Result<WalIterator<'a, R>> {
+        WalIterator::open(Handle::Borrowed(file), direction, format, blocks)
+    }
+
+    /// Creates an iterator over a log written with the given block format
+    /// that owns the source it reads from.
+    ///
+    /// Together with a `SharedFile`, this lets an iterator read a log while
+    /// the log's file is borrowed by its writer.
+    pub fn owned(
+        file: R,
+        direction: ReadDirection,
+        format: BlockFormat,
+    ) -> Result<WalIterator<'a, R>> {
+        WalIterator::open(Handle::Owned(file), direction, format, 1)
+    }
+
+    /// Creates an iterator and loads the block at the end it starts from.
+    fn open(
+        file: Handle<'a, R>,
+        direction: ReadDirection,
+        format: BlockFormat,
+        blocks: usize,
+    ) -> Result<WalIterator<'a, R>> {
+        let mut iter = WalIterator::unloaded(file, direction, format, blocks)?;
+        match direction {
+            ReadDirection::Forward if iter.manager.len > 0 => iter.enter_front(0)?,
+            ReadDirection::Backward => {
+                // Skip the blocks of padding at the end of the log so the back
+                // starts after the last record, as an empty block before it
+                // would otherwise end the iteration.
+                while iter.back.pos > 0 && iter.back.block.is_empty() {
+                    if iter.back.corruption.is_some() {
+                        break;
+                    }
+                    let pos = iter.back.pos - format.block_size;
+                    iter.enter_back(pos)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(iter)
+    }
+
+    /// Creates an iterator that continues from a cursor saved from an
+    /// iterator over the same log, in the cursor's direction.
+    pub fn resume(file: &'a mut R, cursor: Cursor) -> Result<WalIterator<'a, R>> {
+        WalIterator::resume_with_format(file, cursor, BlockFormat::default())
+    }
+
+    /// Creates an iterator that continues from a saved cursor over a log
+    /// written with the given block format.
+    ///
+    /// Returns `OutOfBounds` if the log has shrunk past the cursor.
+    pub fn resume_with_format(
+        file: &'a mut R,
+        cursor: Cursor,
+        format: BlockFormat,
+    ) -> Result<WalIterator<'a, R>> {
+        let mut iter = WalIterator::unloaded(Handle::Borrowed(file), cursor.direction, format, 1)?;
+        let pos = cursor.pos as i64;
+        let index = cursor.index as usize;
+        if pos % format.block_size != 0 {
+            return Err(BlockError::OutOfBounds);
+        }
 
+        match cursor.direction {
+            ReadDirection::Forward => {
+                if pos > iter.manager.len {
+                    return Err(BlockError::OutOfBounds);
+                }
+                // Start before the block so it is loaded by the first read.
+                iter.front = BlockCursor::new(pos - format.block_size);
+                if index > 0 {
+                    iter.manager.load(&mut iter.front, pos, false)?;
+                    iter.front.index = index;
+                }
+                if iter.front.index > iter.front.block.len() {
+                    return Err(BlockError::OutOfBounds);
+                }
+            }
+            ReadDirection::Backward => {
+                if pos > iter.back.pos {
+                    return Err(BlockError::OutOfBounds);
+                }
+                // The records after the cursor in its block were already read,
+                // along with the corruption at the end of the block.
+                iter.back = BlockCursor::new(pos);
+                if index > 0 {
+                    iter.manager.load(&mut iter.back, pos, true)?;
+                    iter.back.index = index;
+                    if index < iter.back.block.len() {
+                        iter.back.corruption = None;
+                    }
+                }
+                if iter.back.index > iter.back.block.len() {
+                    return Err(BlockError::OutOfBounds);
+                }
+                iter.back_moved = true;
+            }
+        }
+        Ok(iter)
+    }
+
+    /// Creates an iterator without loading a block at either end.
+    fn unloaded(
+        file: Handle<'a, R>,
+        direction: ReadDirection,
+        format: BlockFormat,
+        blocks: usize,
+    ) -> Result<WalIterator<'a, R>> {
+        format.check()?;
+        let manager = BlockManager::with_readahead(file, format, blocks)?;
+        let front = BlockCursor::new(-format.block_size);
+        let back = BlockCursor::new(manager.end_pos());
         Ok(WalIterator {
             manager,
+            front,
+            back,
             direction,
-            block,
-            index,
+            on_corruption: OnCorruption::default(),
+            error: None,
+            last_record: None,
+            back_moved: false,
         })
     }
+
+    /// Moves the front of the iterator to the record starting at the offset,
+    /// like the offset returned by `Writer::append_serializable`, and the
+    /// back to the end of the log.
+    ///
+    /// Returns `OutOfBounds` if no record starts at the offset.
+    pub fn seek(&mut self, offset: u64) -> Result<()> {
+        let block_size = self.manager.format.block_size;
+        let offset = offset as i64;
+        if offset > self.manager.len {
+            return Err(BlockError::OutOfBounds);
+        }
+
+        // Find the index of the record by walking the headers in its block.
+        let pos = offset - offset % block_size;
+        let bytes = self.manager.read_block(pos, false)?;
+        let capacity = self.manager.format.capacity();
+        let (mut block_offset, mut index) = (0, 0);
+        while block_offset < (offset - pos) as usize && bytes[block_offset] != PADDING_BYTE {
+            let limit = capacity.saturating_sub(block_offset);
+            match Record::parse_len(&bytes[block_offset..], limit) {
+                Ok(len) => block_offset += len,
+                Err(_) => break,
+            }
+            index += 1;
+        }
+        if block_offset != (offset - pos) as usize {
+            return Err(BlockError::OutOfBounds);
+        }
+
+        let mut front = BlockCursor::new(pos);
+        self.manager.load(&mut front, pos, false)?;
+        front.index = index;
+        if index > front.block.len() {
+            return Err(BlockError::OutOfBounds);
+        }
+        self.front = front;
+        self.back = BlockCursor::new(self.manager.end_pos());
+        self.direction = ReadDirection::Forward;
+        self.last_record = None;
+        self.back_moved = false;
+        Ok(())
+    }
+
+    /// Moves the front of the iterator to the record starting at the offset
+    /// like `seek`, keeping the back and direction, so neither end reads the
+    /// records before the offset. Does nothing for an offset of 0.
+    pub(crate) fn start_at(&mut self, offset: u64) -> Result<()> {
+        if offset == 0 {
+            return Ok(());
+        }
+        let direction = self.direction;
+        let back = mem::replace(&mut self.back, BlockCursor::new(0));
+        self.seek(offset)?;
+        self.back = back;
+        self.direction = direction;
+        Ok(())
+    }
+
+    /// Returns the position of the iterator in its direction, which can be
+    /// saved to resume reading the log with `resume`.
+    ///
+    /// The position is the front of the iterator when reading forward and
+    /// the back when reading backward.
+    pub fn cursor(&self) -> Cursor {
+        let cursor = match self.direction {
+            ReadDirection::Forward => &self.front,
+            ReadDirection::Backward => &self.back,
+        };
+        if cursor.pos < 0 {
+            return Cursor {
+                pos: 0,
+                index: 0,
+                direction: self.direction,
+            };
+        }
+        Cursor {
+            pos: cursor.pos as u64,
+            index: cursor.index as u32,
+            direction: self.direction,
+        }
+    }
+
+    /// Sets what the iterator does when it finds a corrupted record.
+    pub fn on_corruption(mut self, on_corruption: OnCorruption) -> WalIterator<'a, R> {
+        self.on_corruption = on_corruption;
+        self
+    }
+
+    /// Returns an iterator over the entries in the log deserialized as `S`.
+    pub fn entries<S: Serializable>(self) -> EntryIterator<'a, S, R> {
+        EntryIterator {
+            iter: self,
+            _entry: PhantomData,
+        }
+    }
+
+    /// Returns the first corruption found in a block loaded by the iterator.
+    ///
+    /// Records after a corrupted record in the same block can't be framed,
+    /// so they are never returned.
+    pub fn corruption(&self) -> Option<&BlockError> {
+        self.manager.corruption.as_ref()
+    }
+
+    /// Returns the number of loaded blocks that contained a corruption.
+    pub fn corrupted_blocks(&self) -> usize {
+        self.manager.corrupted_blocks
+    }
+
+    /// Returns the number of reads the iterator has issued to the file.
+    pub fn physical_reads(&self) -> usize {
+        self.manager.physical_reads
+    }
+
+    /// Returns the counts of the records, bytes and blocks read so far.
+    pub fn stats(&self) -> Stats {
+        self.manager.stats
+    }
+
+    /// Calls the callback with the position of each block after it is
+    /// loaded and the stats so far, to report on long scans.
+    ///
+    /// The block loaded when the iterator was created is reported right away.
+    pub fn on_block_loaded<F>(mut self, mut callback: F) -> WalIterator<'a, R>
+    where
+        F: FnMut(u64, &Stats) + Send + 'static,
+    {
+        if self.manager.stats.blocks_loaded > 0 {
+            let pos = match self.direction {
+                ReadDirection::Forward => self.front.pos,
+                ReadDirection::Backward => self.back.pos,
+            };
+            callback(pos as u64, &self.manager.stats);
+        }
+        self.manager.on_block_loaded = Some(Box::new(callback));
+        self
+    }
+
+    /// Counts the records in the log by parsing only their headers.
+    ///
+    /// Payloads aren't checked, so records with corrupted payloads are counted,
+    /// while counting a block stops at a header that can't be parsed. The
+    /// position of the iterator isn't changed, and the counts of each block
+    /// are kept to make `size_hint` tighter.
+    pub fn count_records(&mut self) -> Result<usize> {
+        let counts = self.manager.count_block_records()?;
+        let total = counts.iter().sum();
+        self.manager.block_counts = Some(counts);
+        Ok(total)
+    }
+
+    /// Returns the error that stopped the `Iterator` implementation, if any.
+    pub fn error(&self) -> Option<&BlockError> {
+        self.error.as_ref()
+    }
+
+    /// Moves the front of the iterator to the back and the back to the end of
+    /// the log, so the records already returned by `next_back` are returned
+    /// again in forward order by `next`.
+    ///
+    /// This lets a log be scanned backwards to a starting point and then
+    /// replayed forwards from there.
+    pub fn rewind_back(&mut self) {
+        let end = BlockCursor::new(self.manager.end_pos());
+        self.front = mem::replace(&mut self.back, end);
+        self.direction = ReadDirection::Forward;
+        self.back_moved = false;
+    }
+
+    /// Returns true if the back of the iterator is still at the end of the
+    /// log, so no records have been read from the back.
+    ///
+    /// A chain of records that runs out at the end of the log is the entry
+    /// being appended when the writer stopped, rather than a broken entry.
+    pub fn back_at_end(&self) -> bool {
+        !self.back_moved
+    }
+
+    /// Returns the record at the front of the iterator and moves past it,
+    /// or returns None once the front reaches the back.
+    ///
+    /// If reading the next block fails, the error is returned and the
+    /// iterator stays at its position so the call can be retried.
+    pub fn try_next(&mut self) -> Result<Option<Record>> {
+        if !self.seek_front(false)? {
+            return Ok(None);
+        }
+        let record = self.front.block[self.front.index].0.clone();
+        let index = self.front.index;
+        self.last_record = Some((self.front.record_range(index), index, record.lsn));
+        self.front.index += 1;
+        self.manager.stats.records_read += 1;
+        Ok(Some(record))
+    }
+
+    /// Returns the record at the back of the iterator like `try_next`.
+    pub fn try_next_back(&mut self) -> Result<Option<Record>> {
+        if !self.seek_back(false)? {
+            return Ok(None);
+        }
+        self.back.index -= 1;
+        self.back_moved = true;
+        let index = self.back.index;
+        let record = self.back.block[index].0.clone();
+        self.last_record = Some((self.back.record_range(index), index, record.lsn));
+        self.manager.stats.records_read += 1;
+        Ok(Some(record))
+    }
+
+    /// Returns the offsets in the file of the bytes of the record last
+    /// returned by the iterator, including its header.
+    ///
+    /// Returns None if no record has been returned since the iterator
+    /// was created or moved by `seek`.
+    pub fn last_record_range(&self) -> Option<Range<u64>> {
+        self.last_record.as_ref().map(|(range, ..)| range.clone())
+    }
+
+    /// Returns the index in its block of the record last returned
+    /// by the iterator, like the `record` of `BlockError::Corrupted`.
+    pub fn last_record_index(&self) -> Option<usize> {
+        self.last_record.as_ref().map(|&(_, index, _)| index)
+    }
+
+    /// Returns the LSN of the record last returned by the iterator, or 0
+    /// if the record was written without one.
+    ///
+    /// After an entry is read backwards, this is the LSN of the entry's
+    /// first record, which is the LSN a log reports for the entry when
+    /// flushing it.
+    pub fn last_record_lsn(&self) -> Option<u64> {
+        self.last_record.as_ref().map(|&(.., lsn)| lsn)
+    }
+
+    /// Returns the offset in the file and index in its block of the record
+    /// `peek` or `peek_back` last returned in the direction.
+    pub(crate) fn peeked_record(&self, direction: ReadDirection) -> Option<(u64, usize)> {
+        let (cursor, index) = match direction {
+            ReadDirection::Forward => (&self.front, self.front.index),
+            ReadDirection::Backward => (&self.back, self.back.index.checked_sub(1)?),
+        };
+        if index >= cursor.block.len() {
+            return None;
+        }
+        Some((cursor.record_range(index).start, index))
+    }
+
+    /// Returns the record `try_next` would return without moving past it.
+    ///
+    /// The next block is loaded if the front is at the end of a block, and
+    /// a corruption error is returned again by the next call to `try_next`.
+    pub fn peek(&mut self) -> Result<Option<&Record>> {
+        if !self.seek_front(true)? {
+            return Ok(None);
+        }
+        Ok(self
+            .front
+            .block
+            .get(self.front.index)
+            .map(|(record, _)| record))
+    }
+
+    /// Returns the record `try_next_back` would return without moving past it.
+    pub fn peek_back(&mut self) -> Result<Option<&Record>> {
+        if !self.seek_back(true)? {
+            return Ok(None);
+        }
+        Ok(self
+            .back
+            .block
+            .get(self.back.index - 1)
+            .map(|(record, _)| record))
+    }
+
+    /// Skips records until the iterator is at the boundary of an entry in the
+    /// direction, returning the number of records skipped.
+    ///
+    /// Going forward, the front stops before the next `Full` or `First` record.
+    /// Going backward, the back stops after the previous `Full` or `Last` record.
+    /// This lets entries be read again after a chain of records is broken.
+    pub fn skip_to_entry_boundary(&mut self, direction: ReadDirection) -> Result<usize> {
+        let mut skipped = 0;
+        loop {
+            let record = match direction {
+                ReadDirection::Forward => self.peek()?,
+                ReadDirection::Backward => self.peek_back()?,
+            };
+            let boundary = match record.map(|record| record.record_type) {
+                None => true,
+                Some(RecordType::Zero) | Some(RecordType::Full) => true,
+                Some(RecordType::First) => direction == ReadDirection::Forward,
+                Some(RecordType::Last) => direction == ReadDirection::Backward,
+                Some(RecordType::Middle) => false,
+            };
+            if boundary {
+                return Ok(skipped);
+            }
+            match direction {
+                ReadDirection::Forward => self.try_next()?,
+                ReadDirection::Backward => self.try_next_back()?,
+            };
+            skipped += 1;
+        }
+    }
+
+    /// Moves the front cursor past the end of its block until it is before a
+    /// record, returning false if there are no records left before the back.
+    fn seek_front(&mut self, peek: bool) -> Result<bool> {
+        while self.front.index >= self.front.block.len() {
+            // The rest of the block after its records is corrupted. If the back
+            // is in the same block it has already passed over the corruption.
+            if self.front.pos < self.back.pos {
+                let stats = &mut self.manager.stats;
+                if let Some(err) =
+                    report_corruption(&mut self.front, self.on_corruption, peek, stats)
+                {
+                    return Err(err);
+                }
+            }
+            let pos = self.front.pos + self.manager.format.block_size;
+            if pos >= self.manager.len || pos > self.back.pos {
+                return Ok(false);
+            }
+            self.enter_front(pos)?;
+            let header = pos == 0 && self.manager.header;
+            if self.front.block.is_empty() && self.front.corruption.is_none() && !header {
+                return Ok(false);
+            }
+        }
+        Ok(self.front.pos < self.back.pos || self.front.index < self.back.index)
+    }
+
+    /// Moves the back cursor past the start of its block until it is after a
+    /// record, returning false if there are no records left after the front.
+    fn seek_back(&mut self, peek: bool) -> Result<bool> {
+        loop {
+            // Going backwards, the corrupted end of a block is reached before its records.
+            if self.back.index == self.back.block.len() {
+                let stats = &mut self.manager.stats;
+                if let Some(err) =
+                    report_corruption(&mut self.back, self.on_corruption, peek, stats)
+                {
+                    return Err(err);
+                }
+            }
+            if self.back.index > 0 {
+                break;
+            }
+            let pos = self.back.pos - self.manager.format.block_size;
+            if pos < 0 || pos < self.front.pos {
+                return Ok(false);
+            }
+            if pos == 0 && pos != self.front.pos {
+                // Stop after the header block instead of entering it, so the
+                // back is still on the first block of records.
+                let bytes = self.manager.read_block(pos, true)?;
+                if self.manager.check_header(&bytes)? {
+                    return Ok(false);
+                }
+                self.manager.load_bytes(&mut self.back, pos, bytes)?;
+                self.back.index = self.back.block.len();
+            } else {
+                self.enter_back(pos)?;
+            }
+            if self.back.block.is_empty() && self.back.corruption.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(self.front.pos < self.back.pos || self.front.index < self.back.index)
+    }
+
+    /// Moves the front cursor to the start of the block at the position.
+    fn enter_front(&mut self, pos: i64) -> Result<()> {
+        if pos == self.back.pos {
+            self.front.copy_block(&self.back);
+        } else {
+            self.manager.load(&mut self.front, pos, false)?;
+        }
+        self.front.index = 0;
+        Ok(())
+    }
+
+    /// Moves the back cursor to the end of the block at the position.
+    fn enter_back(&mut self, pos: i64) -> Result<()> {
+        if pos == self.front.pos {
+            self.back.copy_block(&self.front);
+        } else {
+            self.manager.load(&mut self.back, pos, true)?;
+        }
+        self.back.index = self.back.block.len();
+        Ok(())
+    }
 }
 
-impl<'a> Iterator for WalIterator<'a> {
+/// Returns the unhandled corruption in the cursor's block if corruption is
+/// reported as an error. The corruption is handled unless only peeking.
+fn report_corruption(
+    cursor: &mut BlockCursor,
+    on_corruption: OnCorruption,
+    peek: bool,
+    stats: &mut Stats,
+) -> Option<BlockError> {
+    match on_corruption {
+        OnCorruption::Error if peek => cursor.corruption.as_ref().map(BlockError::duplicate),
+        OnCorruption::Error => cursor.corruption.take(),
+        OnCorruption::SkipToNextBlock => {
+            if cursor.corruption.take().is_some() {
+                stats.corrupt_records_skipped += 1;
+            }
+            None
+        }
+    }
+}
+
+impl<'a, R: BlockSource + 'a> Iterator for WalIterator<'a, R> {
     type Item = Record;
 
-    /// Given the current position, return the record at the position and
-    /// increment into the next record.
     fn next(&mut self) -> Option<Record> {
-        if self.direction == ReadDirection::Backward {
-            self.direction = ReadDirection::Forward;
-            return self.block.get(self.index as usize).cloned();
+        if self.error.is_some() {
+            return None;
         }
-
-        if self.index + 1 >= self.block.len() as i32 {
-            match self.manager.next() {
-                Err(BlockError::OutOfBounds) | Err(BlockError::EmptyBlock) => return None,
-                Err(e) => panic!("next() error: {:?}", e),
-                _ => {}
+        match self.try_next() {
+            Ok(record) => record,
+            Err(e) => {
+                self.error = Some(e);
+                None
             }
-            self.block = self.manager.curr();
-            self.index = 0;
-        } else {
-            self.index += 1;
         }
+    }
 
-        self.block.get(self.index as usize).cloned()
+    /// The lower bound is the number of records left in the loaded block at
+    /// the front, since the records at the back aren't returned if the
+    /// iterator stops at a corrupted record before them. The upper bound
+    /// adds the records counted by `count_records` in the blocks between the
+    /// ends, or the most records that fit in them, and the records left in
+    /// the loaded block at the back.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.error.is_some() {
+            return (0, Some(0));
+        }
+        let front = self.front.block.len().saturating_sub(self.front.index);
+        if self.front.pos == self.back.pos {
+            let remaining = self.back.index.saturating_sub(self.front.index);
+            return (remaining, Some(remaining));
+        }
+        let block_size = self.manager.format.block_size;
+        let start = self.front.pos + block_size;
+        let end = cmp::min(self.back.pos, self.manager.len);
+        let between = match self.manager.block_counts {
+            Some(ref counts) => (start..end)
+                .step_by(block_size as usize)
+                .map(|pos| {
+                    counts
+                        .get((pos / block_size) as usize)
+                        .cloned()
+                        .unwrap_or(0)
+                })
+                .sum(),
+            None => cmp::max(end - start, 0) as usize / LEGACY_HEADER_SIZE,
+        };
+        (front, Some(front + self.back.index + between))
     }
 }
 
-impl<'a> DoubleEndedIterator for WalIterator<'a> {
+impl<'a, R: BlockSource + 'a> DoubleEndedIterator for WalIterator<'a, R> {
     fn next_back(&mut self) -> Option<Record> {
-        if self.direction == ReadDirection::Forward {
-            self.direction = ReadDirection::Backward;
-            return self.block.get(self.index as usize).cloned();
+        if self.error.is_some() {
+            return None;
+        }
+        match self.try_next_back() {
+            Ok(record) => record,
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
         }
+    }
+}
 
-        if self.index - 1 < 0 {
-            match self.manager.prev() {
-                Err(BlockError::OutOfBounds) | Err(BlockError::EmptyBlock) => return None,
-                Err(e) => panic!("next_back() error: {:?}", e),
-                _ => {}
+/// Iterator over the entries in a log, reassembled from their records.
+///
+/// Iterating forwards reads entries with `read_serializable` and iterating
+/// backwards with `read_serializable_backwards`. Iteration ends when the
+/// records run out, while corrupted records and entries that can't be
+/// reassembled or deserialized are returned as errors.
+pub struct EntryIterator<'a, S, R: BlockSource + 'a = File> {
+    iter: WalIterator<'a, R>,
+    _entry: PhantomData<S>,
+}
+
+impl<'a, S: Serializable, R: BlockSource + 'a> EntryIterator<'a, S, R> {
+    /// Returns the record iterator the entries are read from.
+    pub fn get_mut(&mut self) -> &mut WalIterator<'a, R> {
+        &mut self.iter
+    }
+
+    pub fn into_inner(self) -> WalIterator<'a, R> {
+        self.iter
+    }
+}
+
+impl<'a, Data: LogData, R: BlockSource + 'a> EntryIterator<'a, SingleLogEntry<Data>, R> {
+    /// Returns an iterator over only the entries of the transaction.
+    pub fn for_transaction(self, tid: u64) -> TransactionEntries<'a, Data, R> {
+        TransactionEntries {
+            entries: self,
+            tid,
+            checkpoints: false,
+        }
+    }
+}
+
+/// Iterator over the entries of a single transaction in a log.
+///
+/// Checkpoints don't belong to any transaction and are skipped
+/// unless `with_checkpoints` is used. Errors are always returned.
+pub struct TransactionEntries<'a, Data: LogData, R: BlockSource + 'a = File> {
+    entries: EntryIterator<'a, SingleLogEntry<Data>, R>,
+    tid: u64,
+    checkpoints: bool,
+}
+
+impl<'a, Data: LogData, R: BlockSource + 'a> TransactionEntries<'a, Data, R> {
+    /// Includes the checkpoint entries in the log.
+    pub fn with_checkpoints(mut self) -> TransactionEntries<'a, Data, R> {
+        self.checkpoints = true;
+        self
+    }
+
+    fn matches(&self, result: &SerializeResult<SingleLogEntry<Data>>) -> bool {
+        match *result {
+            Ok(SingleLogEntry::Checkpoint(_)) => self.checkpoints,
+            Ok(ref entry) => entry.tid() == Some(self.tid),
+            Err(_) => true,
+        }
+    }
+}
+
+impl<'a, Data: LogData, R: BlockSource + 'a> Iterator for TransactionEntries<'a, Data, R> {
+    type Item = SerializeResult<SingleLogEntry<Data>>;
+
+    fn next(&mut self) -> Option<SerializeResult<SingleLogEntry<Data>>> {
+        while let Some(result) = self.entries.next() {
+            if self.matches(&result) {
+                return Some(result);
             }
-            self.block = self.manager.curr();
-            self.index = self.block.len() as i32 - 1;
-        } else {
-            self.index -= 1;
         }
+        None
+    }
+}
 
-        self.block.get(self.index as usize).cloned()
+impl<'a, Data: LogData, R: BlockSource + 'a> DoubleEndedIterator
+    for TransactionEntries<'a, Data, R>
+{
+    fn next_back(&mut self) -> Option<SerializeResult<SingleLogEntry<Data>>> {
+        while let Some(result) = self.entries.next_back() {
+            if self.matches(&result) {
+                return Some(result);
+            }
+        }
+        None
     }
 }
 
-struct BlockManager<'a> {
-    file: &'a mut File,
-    len: i64,
+/// Converts the result of reading an entry into an iterator item.
+fn entry_item<S>(result: SerializeResult<S>) -> Option<SerializeResult<S>> {
+    match result {
+        Err(SerializeError::OutOfRecords) => None,
+        result => Some(result),
+    }
+}
+
+impl<'a, S: Serializable, R: BlockSource + 'a> Iterator for EntryIterator<'a, S, R> {
+    type Item = SerializeResult<S>;
+
+    fn next(&mut self) -> Option<SerializeResult<S>> {
+        entry_item(read_serializable(&mut self.iter))
+    }
+}
+
+impl<'a, S: Serializable, R: BlockSource + 'a> DoubleEndedIterator for EntryIterator<'a, S, R> {
+    fn next_back(&mut self) -> Option<SerializeResult<S>> {
+        entry_item(read_serializable_backwards(&mut self.iter))
+    }
+}
+
+/// One end of the iterator: a position between two records of a block.
+struct BlockCursor {
+    /// Position of the block in the file.
     pos: i64,
-    block: Vec<Record>,
+    /// Index of the record after the cursor in the block.
+    index: usize,
+    /// Records in the block, each with the offset in the block of its end.
+    block: Vec<(Record, usize)>,
+    spare: Vec<(Record, usize)>,
+    /// The corruption in the block that hasn't been handled yet.
+    corruption: Option<BlockError>,
 }
 
-impl<'a> BlockManager<'a> {
-    fn new<'b>(file: &'b mut File, direction: &ReadDirection) -> Result<BlockManager<'b>> {
-        let file_len = file.metadata()?.len() as i64;
-        let pos = match *direction {
-            ReadDirection::Forward => 0,
-            ReadDirection::Backward => {
-                let end_pos = (file_len / BLOCK_SIZE) * BLOCK_SIZE;
-                if end_pos >= file_len {
-                    end_pos - BLOCK_SIZE
-                } else {
-                    end_pos
-                }
-            }
-        };
+impl BlockCursor {
+    /// Creates a cursor at an empty block at the position.
+    fn new(pos: i64) -> BlockCursor {
+        BlockCursor {
+            pos,
+            index: 0,
+            block: Vec::new(),
+            spare: Vec::new(),
+            corruption: None,
+        }
+    }
 
-        let block = match check_out_of_bounds(pos, file_len).and_then(|_| load_block(file, pos)) {
-            Ok(block) => block,
-            Err(BlockError::EmptyBlock) | Err(BlockError::OutOfBounds) => Vec::new(),
-            Err(e) => return Err(e),
+    /// Returns the offsets in the file of the bytes of the record at the index.
+    fn record_range(&self, index: usize) -> Range<u64> {
+        let start = if index == 0 {
+            0
+        } else {
+            self.block[index - 1].1
         };
+        let pos = self.pos as u64;
+        pos + start as u64..pos + self.block[index].1 as u64
+    }
+
+    /// Moves to the block the other cursor is in without reading it again.
+    fn copy_block(&mut self, other: &BlockCursor) {
+        self.pos = other.pos;
+        self.block.clone_from(&other.block);
+        self.corruption = other.corruption.as_ref().map(BlockError::duplicate);
+    }
+}
+
+/// Source that is either borrowed or owned by an iterator.
+enum Handle<'a, R> {
+    Borrowed(&'a mut R),
+    Owned(R),
+}
+
+impl<'a, R> Handle<'a, R> {
+    fn get(&mut self) -> &mut R {
+        match *self {
+            Handle::Borrowed(ref mut file) => file,
+            Handle::Owned(ref mut file) => file,
+        }
+    }
+}
 
+/// Callback set with `WalIterator::on_block_loaded`.
+type BlockCallback = Box<dyn FnMut(u64, &Stats) + Send>;
+
+struct BlockManager<'a, R: BlockSource + 'a> {
+    file: Handle<'a, R>,
+    format: BlockFormat,
+    len: i64,
+    /// Number of blocks read from the file at a time.
+    readahead: usize,
+    /// Blocks read ahead of the iterator, starting at `buffer_pos`.
+    buffer: Payload,
+    buffer_pos: i64,
+    physical_reads: usize,
+    /// Number of records in each block, once counted by `count_records`.
+    block_counts: Option<Vec<usize>>,
+    corruption: Option<BlockError>,
+    corrupted_blocks: usize,
+    stats: Stats,
+    on_block_loaded: Option<BlockCallback>,
+    /// Whether the first block holds the file header instead of records.
+    header: bool,
+}
+
+impl<'a, R: BlockSource + 'a> BlockManager<'a, R> {
+    /// Creates a manager that reads `readahead` blocks from the file at a time.
+    fn with_readahead(
+        mut file: Handle<'a, R>,
+        format: BlockFormat,
+        readahead: usize,
+    ) -> Result<BlockManager<'a, R>> {
+        let len = file.get().size()? as i64;
         Ok(BlockManager {
             file,
-            len: file_len,
-            pos,
-            block,
+            format,
+            len,
+            readahead: cmp::max(readahead, 1),
+            buffer: Payload::from(Vec::new()),
+            buffer_pos: 0,
+            physical_reads: 0,
+            block_counts: None,
+            corruption: None,
+            corrupted_blocks: 0,
+            stats: Stats::default(),
+            on_block_loaded: None,
+            header: false,
         })
     }
 
-    fn curr(&mut self) -> Vec<Record> {
-        mem::replace(&mut self.block, Vec::new())
+    /// Returns the position just past the last block in the file.
+    fn end_pos(&self) -> i64 {
+        let block_size = self.format.block_size;
+        (self.len + block_size - 1) / block_size * block_size
     }
 
-    fn next(&mut self) -> Result<()> {
-        self.pos += BLOCK_SIZE;
-        check_out_of_bounds(self.pos, self.len)?;
+    /// Loads the block at the position into the cursor, remembering
+    /// the corruption found in it.
+    ///
+    /// The block is loaded into the spare buffer so the cursor is left
+    /// unchanged if the block can't be read, and both buffers are reused
+    /// instead of allocating a new one for every block.
+    fn load(&mut self, cursor: &mut BlockCursor, pos: i64, backward: bool) -> Result<()> {
+        let bytes = self.read_block(pos, backward)?;
+        self.load_bytes(cursor, pos, bytes)
+    }
 
-        self.block = load_block(self.file, self.pos)?;
+    /// Loads the block like `load` from bytes already read from the file.
+    fn load_bytes(&mut self, cursor: &mut BlockCursor, pos: i64, bytes: Payload) -> Result<()> {
+        if pos == 0 && self.check_header(&bytes)? {
+            // The header takes up the first block, which has no records.
+            cursor.block.clear();
+            cursor.pos = pos;
+            cursor.corruption = None;
+            return Ok(());
+        }
+        let corruption = load_block(&bytes, pos, self.format, &mut cursor.spare, &mut self.stats)?;
+        self.stats.blocks_loaded += 1;
+        if let Some(ref mut callback) = self.on_block_loaded {
+            callback(pos as u64, &self.stats);
+        }
+        if let Some(ref corruption) = corruption {
+            self.corrupted_blocks += 1;
+            if self.corruption.is_none() {
+                self.corruption = Some(corruption.duplicate());
+            }
+        }
+
+        mem::swap(&mut cursor.block, &mut cursor.spare);
+        cursor.pos = pos;
+        cursor.corruption = corruption;
         Ok(())
     }
 
-    fn prev(&mut self) -> Result<()> {
-        self.pos -= BLOCK_SIZE;
-        check_out_of_bounds(self.pos, self.len)?;
+    /// Returns true if the first block's bytes hold a file header,
+    /// failing if the header is invalid or doesn't match the format.
+    fn check_header(&mut self, bytes: &[u8]) -> Result<bool> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(false);
+        }
+        let header = FileHeader::parse(bytes).map_err(io::Error::from)?;
+        header.check_format(self.format).map_err(io::Error::from)?;
+        self.header = true;
+        Ok(true)
+    }
 
-        self.block = load_block(self.file, self.pos)?;
-        Ok(())
+    /// Counts the records in each block by parsing only their headers,
+    /// reading each block once.
+    fn count_block_records(&mut self) -> Result<Vec<usize>> {
+        let block_size = self.format.block_size;
+        let capacity = self.format.capacity();
+        let mut counts = Vec::new();
+        for pos in (0..self.len).step_by(block_size as usize) {
+            let bytes = self.read_block(pos, false)?;
+            let len = cmp::min(capacity, bytes.len());
+            let mut count = 0;
+            let mut offset = 0;
+            while offset < len {
+                let header = &bytes[offset..cmp::min(offset + HEADER_SIZE, len)];
+                if header[0] == PADDING_BYTE {
+                    break;
+                }
+                match Record::parse_len(header, capacity - offset) {
+                    Ok(record_len) if offset + record_len <= len => {
+                        count += 1;
+                        offset += record_len;
+                    }
+                    _ => break,
+                }
+            }
+            counts.push(count);
+        }
+        Ok(counts)
     }
-}
 
-fn load_block(file: &mut File, pos: i64) -> Result<Vec<Record>> {
-    file.seek(SeekFrom::Start(pos as u64))?;
-    let mut buf = [0; BLOCK_SIZE as usize];
-    // TODO(DarinM223): replacing read with read_exact causes problems
-    file.read(&mut buf)?;
+    /// Returns the bytes of the block at the position, which are shorter
+    /// than the block size if the file ends partway through the block.
+    /// Bytes appended to the file after the manager was created aren't read.
+    ///
+    /// With read-ahead, the blocks after the position are read along with it,
+    /// or the blocks before it when reading backwards.
+    fn read_block(&mut self, pos: i64, backward: bool) -> Result<Payload> {
+        let block_size = self.format.block_size;
+        if self.readahead == 1 {
+            let len = cmp::max(cmp::min(block_size, self.len - pos), 0);
+            self.physical_reads += 1;
+            let bytes = self.file.get().read_at(pos as u64, len as usize)?;
+            self.stats.bytes_read += bytes.len() as u64;
+            return Ok(bytes);
+        }
+
+        let buffer_end = self.buffer_pos + self.buffer.len() as i64;
+        if pos < self.buffer_pos || pos >= buffer_end {
+            let start = if backward {
+                cmp::max(0, pos - (self.readahead as i64 - 1) * block_size)
+            } else {
+                pos
+            };
+            let len = cmp::min(self.readahead as i64 * block_size, self.len - start);
+            let len = cmp::max(len, 0) as usize;
+            self.physical_reads += 1;
+            self.buffer = self.file.get().read_at(start as u64, len)?;
+            self.buffer_pos = start;
+            self.stats.bytes_read += self.buffer.len() as u64;
+        }
 
-    // Read records from the bytes and add them to the block.
-    let mut block = Vec::new();
-    let mut bytes = &buf[..];
-    while let Ok(record) = Record::read(&mut bytes) {
-        block.push(record);
+        let offset = cmp::min((pos - self.buffer_pos) as usize, self.buffer.len());
+        let end = cmp::min(offset + block_size as usize, self.buffer.len());
+        Ok(self.buffer.slice(offset..end))
     }
-    if block.is_empty() {
-        return Err(BlockError::EmptyBlock);
+}
+
+/// Loads the records in the block's bytes into `block`,
+/// returning the first corruption found and adding the
+/// block's padding to the stats.
+///
+/// Parsing stops at padding or at the first record that fails to parse.
+/// Padding starts with `PADDING_BYTE` and must run to the end of the block,
+/// otherwise it is reported as corruption alongside the records before it.
+///
+/// With block checksums, a full block is verified against its trailer before
+/// parsing and no records are returned from a block that fails verification.
+///
+/// The payloads of the loaded records point into the block's shared buffer,
+/// so loading a block doesn't allocate for each record.
+pub(crate) fn load_block(
+    bytes: &Payload,
+    pos: i64,
+    format: BlockFormat,
+    block: &mut Vec<(Record, usize)>,
+    stats: &mut Stats,
+) -> Result<Option<BlockError>> {
+    block.clear();
+    let capacity = format.capacity();
+    let corrupted = |block_offset: usize, record: usize, error: io::Error| {
+        Some(BlockError::Corrupted {
+            offset: (pos + block_offset as i64) as u64,
+            block: (pos / format.block_size) as u64,
+            record,
+            error,
+        })
+    };
+    if format.checksums && bytes.len() == format.block_size as usize {
+        let mut trailer = &bytes[capacity..];
+        let expected = trailer.read_u32::<BigEndian>()?;
+        let actual = crc32::checksum_ieee(&bytes[..capacity]);
+        if actual != expected {
+            let error = RecordError::BadBlockChecksum { expected, actual }.into();
+            return Ok(corrupted(0, 0, error));
+        }
     }
 
-    Ok(block)
-}
+    // Parse records from the bytes and add them to the block. Only the bytes
+    // read from the file are parsed so a record cut off by the end of the
+    // file is reported as truncated.
+    let shared = bytes;
+    let len = cmp::min(capacity, shared.len());
+    let mut block_offset = 0;
+    while block_offset < len {
+        let bytes = &shared[block_offset..len];
+        if bytes[0] == PADDING_BYTE {
+            if bytes.iter().all(|&b| b == PADDING_BYTE) {
+                stats.padding_bytes += bytes.len() as u64;
+                break;
+            }
+            let error = RecordError::InvalidPadding.into();
+            return Ok(corrupted(block_offset, block.len(), error));
+        }
 
-fn check_out_of_bounds(position: i64, file_length: i64) -> Result<()> {
-    if position < 0 || position > file_length {
-        return Err(BlockError::OutOfBounds);
+        match Record::parse_with_limit(bytes, capacity - block_offset) {
+            Ok((view, consumed)) => {
+                let end = block_offset + consumed;
+                let payload = shared.slice(end - view.payload.len()..end);
+                block.push((view.to_record_with(payload), end));
+                block_offset = end;
+            }
+            Err(error) => return Ok(corrupted(block_offset, block.len(), error)),
+        }
     }
-    Ok(())
+
+    Ok(None)
 }
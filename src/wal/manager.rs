@@ -0,0 +1,642 @@
+use byteorder::{BigEndian, ByteOrder};
+use crc::crc32;
+
+use std::cmp;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::wal::backend::{lock_exclusive, sync_dir, LogBackend};
+use crate::wal::header::FileHeader;
+use crate::wal::iterator::read_file_at;
+use crate::wal::record::BlockFormat;
+use crate::wal::writer::{SetLen, SyncData};
+use crate::wal::{invalid_option, LogOptions};
+
+/// Name of the file in a log directory recording its first segment
+/// and last durable checkpoint.
+pub const MANIFEST_NAME: &str = "MANIFEST";
+
+/// Name of the file a new manifest is written to before it replaces the old one.
+const MANIFEST_TEMP_NAME: &str = "MANIFEST.tmp";
+
+/// Name of the file in a log directory that the `Wal` holding the
+/// directory open keeps locked.
+const LOCK_NAME: &str = "LOCK";
+
+/// Extension of the segment files in a log directory.
+const SEGMENT_EXTENSION: &str = "seg";
+
+/// Bytes a manifest starts with.
+const MANIFEST_MAGIC: [u8; 4] = *b"DUWM";
+
+/// Version of the manifest written by `Manifest::to_bytes`.
+const MANIFEST_VERSION: u8 = 1;
+
+/// Size in bytes of an encoded manifest.
+const MANIFEST_SIZE: usize = 60;
+
+/// Position in a log directory: the index of a segment and the
+/// offset in the segment's file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentOffset {
+    pub segment: u64,
+    pub offset: u64,
+}
+
+/// Log kept in a directory of numbered segment files, which logs can be
+/// opened on with `RedoLog::with_wal` and `UndoLog::with_wal`.
+///
+/// The segments read as one log, with entries appended to the last,
+/// active segment. Once the active segment grows to `LogOptions::segment_size`
+/// a new segment is started at the next block boundary. A manifest, replaced
+/// atomically through a temporary file, records the first segment still kept
+/// and where recovery starts after the last checkpoint, so recovery doesn't
+/// read the segments before it. Truncating the log deletes the segments
+/// before the last checkpoint instead of rewriting the log, keeping the
+/// offsets of the entries after them.
+///
+/// The segments can't be rewritten as a whole, so compacting a log kept
+/// in a directory fails with an `Unsupported` error and leaves the log as
+/// it was.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use disk_utils::wal::manager::Wal;
+/// use disk_utils::wal::LogOptions;
+///
+/// fn main() {
+///     let options = LogOptions::default();
+///     let wal = Wal::open("./files/wal_manager_doc_example", &options).unwrap();
+///     assert_eq!(wal.segments().len(), 1);
+///     assert_eq!(wal.checkpoint(), None);
+///     # std::fs::remove_dir_all("./files/wal_manager_doc_example").unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Wal {
+    dir: PathBuf,
+    /// Lock file, whose lock is released when the `Wal` is dropped.
+    _lock: File,
+    segment_size: u64,
+    manifest: Manifest,
+    segments: Segments,
+    pos: u64,
+}
+
+impl Wal {
+    /// Opens the log in the directory, creating the directory, its manifest
+    /// and its first segment if they don't exist.
+    ///
+    /// Fails with an `InvalidData` error if the manifest is corrupted or
+    /// missing from a directory holding segments, or if segments are
+    /// missing between the first one and the active one.
+    ///
+    /// The directory is locked until the `Wal` is dropped, so appends from
+    /// two logs can't interleave. Opening a directory another `Wal` has
+    /// locked, in this process or another, fails with `WouldBlock`.
+    pub fn open<P: AsRef<Path>>(dir: P, options: &LogOptions) -> io::Result<Wal> {
+        options.validate()?;
+        if options.preallocate_blocks > 0 {
+            return Err(invalid_option(
+                "Logs kept in a directory can't be preallocated",
+            ));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_NAME))?;
+        lock_exclusive(&lock)?;
+        // A manifest that wasn't renamed over the old one never took effect.
+        let temp = dir.join(MANIFEST_TEMP_NAME);
+        if temp.exists() {
+            fs::remove_file(&temp)?;
+        }
+
+        let mut indices = segment_indices(&dir)?;
+        let manifest = match fs::read(dir.join(MANIFEST_NAME)) {
+            Ok(bytes) => Manifest::parse(&bytes)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                if !indices.is_empty() {
+                    return Err(invalid_data("Log directory has segments but no manifest"));
+                }
+                let manifest = Manifest::new(options.block_format());
+                manifest.write(&dir)?;
+                manifest
+            }
+            Err(err) => return Err(err),
+        };
+        FileHeader::new(manifest.format).check_format(options.block_format())?;
+
+        // Segments before the first one are left by a crash while
+        // the log was truncated.
+        for &index in indices
+            .iter()
+            .filter(|&&index| index < manifest.first_segment)
+        {
+            fs::remove_file(segment_path(&dir, index))?;
+        }
+        indices.retain(|&index| index >= manifest.first_segment);
+        if indices.is_empty() {
+            indices.push(manifest.first_segment);
+        }
+        let expected = manifest.first_segment..manifest.first_segment + indices.len() as u64;
+        if !indices.iter().cloned().eq(expected) {
+            return Err(invalid_data("Log directory is missing segments"));
+        }
+
+        let mut files = Vec::with_capacity(indices.len());
+        let mut start = manifest.first_start;
+        for index in indices {
+            let file = open_segment(&dir, index)?;
+            let len = file.metadata()?.len();
+            files.push(Segment {
+                index,
+                start,
+                len,
+                file,
+            });
+            start += len;
+        }
+        sync_dir(&dir)?;
+        Ok(Wal {
+            dir,
+            _lock: lock,
+            segment_size: options.segment_size,
+            segments: Segments {
+                header: FileHeader::new(manifest.format).to_block(),
+                first_start: manifest.first_start,
+                files,
+            },
+            manifest,
+            pos: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the paths of the segments in order, the last being the active segment.
+    pub fn segments(&self) -> Vec<PathBuf> {
+        self.segments
+            .files
+            .iter()
+            .map(|segment| segment_path(&self.dir, segment.index))
+            .collect()
+    }
+
+    /// Returns where recovery starts, as recorded in the manifest after
+    /// the last checkpoint, or None if no checkpoint was recorded.
+    pub fn checkpoint(&self) -> Option<SegmentOffset> {
+        self.manifest.checkpoint
+    }
+
+    fn active(&mut self) -> &mut Segment {
+        self.segments.files.last_mut().unwrap()
+    }
+
+    /// Syncs the active segment and starts a new one after it.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.active().file.sync_data()?;
+        let index = self.active().index + 1;
+        let start = self.segments.end();
+        let file = open_segment(&self.dir, index)?;
+        sync_dir(&self.dir)?;
+        self.segments.files.push(Segment {
+            index,
+            start,
+            len: 0,
+            file,
+        });
+        Ok(())
+    }
+
+    /// Returns the offset in the log of the checkpoint recorded in the manifest.
+    fn recovery_start_offset(&self) -> Option<u64> {
+        let checkpoint = self.manifest.checkpoint?;
+        self.segments
+            .files
+            .iter()
+            .find(|segment| segment.index == checkpoint.segment)
+            .map(|segment| segment.start + checkpoint.offset)
+    }
+
+    /// Returns the position of the offset in the segments, treating the
+    /// end of the log as the end of the active segment.
+    fn segment_offset(&self, offset: u64) -> Option<SegmentOffset> {
+        let end = self.segments.end();
+        self.segments
+            .files
+            .iter()
+            .find(|segment| {
+                offset >= segment.start && (offset < segment.start + segment.len || offset == end)
+            })
+            .map(|segment| SegmentOffset {
+                segment: segment.index,
+                offset: offset - segment.start,
+            })
+    }
+}
+
+impl Read for Wal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.segments.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for Wal {
+    /// Appends to the active segment, starting a new segment first if the
+    /// active one is full and the log ends on a block boundary. A write
+    /// to a full segment stops at the next block boundary.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let block_size = self.manifest.format.block_size as u64;
+        let end = self.segments.end();
+        if self.active().len >= self.segment_size && end.is_multiple_of(block_size) {
+            self.rotate()?;
+        }
+        let segment_size = self.segment_size;
+        let active = self.active();
+        let room = if active.len < segment_size {
+            buf.len()
+        } else {
+            cmp::min(buf.len() as u64, block_size - end % block_size) as usize
+        };
+        let n = active.file.write(&buf[..room])?;
+        active.len += n as u64;
+        self.pos = self.segments.end();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.active().file.flush()
+    }
+}
+
+impl Seek for Wal {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.segments.end(), pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl SetLen for Wal {
+    /// Deletes the segments past the length and truncates the segment
+    /// holding it, or grows the active segment to the length. The
+    /// checkpoint in the manifest is forgotten if it's past the length.
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let end = self.segments.end();
+        if len >= end {
+            let active = self.active();
+            active.len += len - end;
+            return active.file.set_len(active.len);
+        }
+        if len < self.manifest.first_record {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Can't truncate the log before its first record",
+            ));
+        }
+
+        let past_checkpoint = self
+            .recovery_start_offset()
+            .is_some_and(|checkpoint| checkpoint > len);
+        if past_checkpoint {
+            self.manifest.checkpoint = None;
+            self.manifest.write(&self.dir)?;
+        }
+        while self.segments.files.len() > 1 && self.active().start >= len {
+            let segment = self.segments.files.pop().unwrap();
+            fs::remove_file(segment_path(&self.dir, segment.index))?;
+        }
+        let active = self.active();
+        active.len = len - active.start;
+        active.file.set_len(active.len)?;
+        sync_dir(&self.dir)
+    }
+}
+
+impl SyncData for Wal {
+    /// Syncs the active segment. The segments before it were synced when
+    /// the segment after them was started.
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.active().file.sync_data()
+    }
+}
+
+impl LogBackend for Wal {
+    type Reader = WalReader;
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.segments.end())
+    }
+
+    /// Returns a reader over the segments written so far.
+    fn reader(&self) -> io::Result<WalReader> {
+        let mut files = Vec::with_capacity(self.segments.files.len());
+        for segment in self.segments.files.iter() {
+            files.push(Segment {
+                index: segment.index,
+                start: segment.start,
+                len: segment.len,
+                file: segment.file.try_clone()?,
+            });
+        }
+        Ok(WalReader {
+            segments: Segments {
+                header: self.segments.header.clone(),
+                first_start: self.segments.first_start,
+                files,
+            },
+            pos: 0,
+        })
+    }
+
+    fn create_rewrite(&mut self) -> io::Result<Wal> {
+        Err(unsupported_rewrite())
+    }
+
+    fn replace(&mut self, _: Wal) -> io::Result<Wal> {
+        Err(unsupported_rewrite())
+    }
+
+    fn first_offset(&self) -> u64 {
+        self.manifest.first_record
+    }
+
+    fn tracks_checkpoints(&self) -> bool {
+        true
+    }
+
+    /// Records the offset in the manifest once the active segment is synced.
+    fn checkpointed(&mut self, offset: u64) -> io::Result<()> {
+        self.active().file.sync_data()?;
+        self.manifest.checkpoint = self.segment_offset(offset);
+        self.manifest.write(&self.dir)
+    }
+
+    /// Returns the offset of the checkpoint recorded in the manifest, or the
+    /// first record kept if there isn't one.
+    fn recovery_start(&mut self) -> io::Result<u64> {
+        let end = self.segments.end();
+        match self.recovery_start_offset() {
+            Some(offset) if offset <= end => Ok(offset),
+            _ => Ok(self.manifest.first_record),
+        }
+    }
+
+    fn can_discard(&self) -> bool {
+        true
+    }
+
+    /// Deletes the segments ending at or before the offset, other than the
+    /// active segment. The manifest is updated before the segments are
+    /// deleted, so a crash leaves segments that are deleted on open.
+    fn discard_before(&mut self, offset: u64) -> io::Result<bool> {
+        let active = self.segments.files.len() - 1;
+        let dead = self.segments.files[..active]
+            .iter()
+            .take_while(|segment| segment.start + segment.len <= offset)
+            .count();
+        if dead == 0 {
+            return Ok(false);
+        }
+        let first = &self.segments.files[dead];
+        self.manifest.first_segment = first.index;
+        self.manifest.first_start = first.start;
+        self.manifest.first_record = offset;
+        self.manifest.write(&self.dir)?;
+        for segment in self.segments.files.drain(..dead) {
+            fs::remove_file(segment_path(&self.dir, segment.index))?;
+        }
+        self.segments.first_start = self.manifest.first_start;
+        sync_dir(&self.dir)?;
+        Ok(true)
+    }
+}
+
+/// Reader over the segments of a `Wal` at the time the reader was created,
+/// returned by the `reader` of a log kept in a directory.
+#[derive(Debug)]
+pub struct WalReader {
+    segments: Segments,
+    pos: u64,
+}
+
+impl Read for WalReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.segments.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for WalReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = seek_position(self.pos, self.segments.end(), pos)?;
+        Ok(self.pos)
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    index: u64,
+    /// Offset of the segment's first byte in the log.
+    start: u64,
+    len: u64,
+    file: File,
+}
+
+/// Segments read as one log.
+///
+/// The bytes before the first segment were in segments that have been
+/// deleted. They read as the log's header block followed by zeros, and
+/// are never read as records since reading starts at the first record kept.
+#[derive(Debug)]
+struct Segments {
+    header: Vec<u8>,
+    first_start: u64,
+    files: Vec<Segment>,
+}
+
+impl Segments {
+    fn end(&self) -> u64 {
+        let last = self.files.last().unwrap();
+        last.start + last.len
+    }
+
+    /// Reads from the segment holding the position, stopping at its end.
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if pos < self.first_start {
+            let len = cmp::min(buf.len() as u64, self.first_start - pos) as usize;
+            for (i, byte) in buf[..len].iter_mut().enumerate() {
+                *byte = *self.header.get(pos as usize + i).unwrap_or(&0);
+            }
+            return Ok(len);
+        }
+        let segment = match self
+            .files
+            .iter()
+            .find(|segment| pos < segment.start + segment.len)
+        {
+            Some(segment) => segment,
+            None => return Ok(0),
+        };
+        let len = cmp::min(buf.len() as u64, segment.start + segment.len - pos) as usize;
+        read_file_at(&segment.file, &mut buf[..len], pos - segment.start)
+    }
+}
+
+/// Manifest of a log directory, encoded as `[magic][version][checksums]
+/// [reserved u16][block size u32][first segment u64][first start u64]
+/// [first record u64][has checkpoint][reserved u8 x 3][checkpoint segment u64]
+/// [checkpoint offset u64][crc u32]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Manifest {
+    format: BlockFormat,
+    first_segment: u64,
+    /// Offset in the log of the first segment's first byte.
+    first_start: u64,
+    /// Offset in the log of the first record kept after the log was truncated.
+    first_record: u64,
+    checkpoint: Option<SegmentOffset>,
+}
+
+impl Manifest {
+    fn new(format: BlockFormat) -> Manifest {
+        Manifest {
+            format,
+            first_segment: 0,
+            first_start: 0,
+            first_record: 0,
+            checkpoint: None,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; MANIFEST_SIZE] {
+        let mut bytes = [0; MANIFEST_SIZE];
+        bytes[..4].copy_from_slice(&MANIFEST_MAGIC);
+        bytes[4] = MANIFEST_VERSION;
+        bytes[5] = self.format.checksums as u8;
+        BigEndian::write_u32(&mut bytes[8..12], self.format.block_size as u32);
+        BigEndian::write_u64(&mut bytes[12..20], self.first_segment);
+        BigEndian::write_u64(&mut bytes[20..28], self.first_start);
+        BigEndian::write_u64(&mut bytes[28..36], self.first_record);
+        if let Some(checkpoint) = self.checkpoint {
+            bytes[36] = 1;
+            BigEndian::write_u64(&mut bytes[40..48], checkpoint.segment);
+            BigEndian::write_u64(&mut bytes[48..56], checkpoint.offset);
+        }
+        let crc = crc32::checksum_ieee(&bytes[..56]);
+        BigEndian::write_u32(&mut bytes[56..], crc);
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> io::Result<Manifest> {
+        if bytes.len() != MANIFEST_SIZE || !bytes.starts_with(&MANIFEST_MAGIC) {
+            return Err(invalid_data("Log directory's manifest is corrupted"));
+        }
+        let expected = BigEndian::read_u32(&bytes[56..]);
+        if expected != crc32::checksum_ieee(&bytes[..56]) {
+            return Err(invalid_data("Log directory's manifest has a bad checksum"));
+        }
+        if bytes[4] != MANIFEST_VERSION {
+            return Err(invalid_data(format!(
+                "Log directory's manifest has unknown version {}",
+                bytes[4]
+            )));
+        }
+        let checkpoint = match bytes[36] {
+            0 => None,
+            _ => Some(SegmentOffset {
+                segment: BigEndian::read_u64(&bytes[40..48]),
+                offset: BigEndian::read_u64(&bytes[48..56]),
+            }),
+        };
+        Ok(Manifest {
+            format: BlockFormat {
+                block_size: BigEndian::read_u32(&bytes[8..12]) as i64,
+                checksums: bytes[5] & 1 != 0,
+            },
+            first_segment: BigEndian::read_u64(&bytes[12..20]),
+            first_start: BigEndian::read_u64(&bytes[20..28]),
+            first_record: BigEndian::read_u64(&bytes[28..36]),
+            checkpoint,
+        })
+    }
+
+    /// Writes the manifest to a temporary file and renames it over the
+    /// directory's manifest.
+    fn write(&self, dir: &Path) -> io::Result<()> {
+        let temp = dir.join(MANIFEST_TEMP_NAME);
+        let mut file = File::create(&temp)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, dir.join(MANIFEST_NAME))?;
+        sync_dir(dir)
+    }
+}
+
+fn segment_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("{:08}.{}", index, SEGMENT_EXTENSION))
+}
+
+fn open_segment(dir: &Path, index: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(segment_path(dir, index))
+}
+
+/// Returns the indices of the segment files in the directory in order.
+fn segment_indices(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut indices = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+            let index = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok());
+            if let Some(index) = index {
+                indices.push(index);
+            }
+        }
+    }
+    indices.sort_unstable();
+    Ok(indices)
+}
+
+fn seek_position(pos: u64, end: u64, seek: SeekFrom) -> io::Result<u64> {
+    let new_pos = match seek {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::End(offset) => end.checked_add_signed(offset),
+        SeekFrom::Current(offset) => pos.checked_add_signed(offset),
+    };
+    new_pos.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't seek before the start of the log",
+        )
+    })
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn unsupported_rewrite() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Logs kept in a directory delete segments instead of being rewritten",
+    )
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of `Metrics::flush_latency`.
+pub const FLUSH_LATENCY_BUCKETS: [Duration; 6] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// Counters a log keeps about what it has done since it was opened,
+/// including recovering it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Entries added to the log, including ones not flushed yet.
+    pub entries_appended: u64,
+    /// Bytes written to the log's file by flushes.
+    pub bytes_flushed: u64,
+    /// Flushes that wrote at least one entry.
+    pub flushes: u64,
+    /// Transactions committed.
+    pub commits: u64,
+    /// Transactions aborted, including the ones recovery aborted.
+    pub aborts: u64,
+    /// Checkpoints begun.
+    pub checkpoints: u64,
+    /// How long recovering the log took when it was opened.
+    pub recovery_duration: Duration,
+    /// Number of flushes that took at most each bound of
+    /// `FLUSH_LATENCY_BUCKETS` and more than the bound before it, with
+    /// the flushes slower than the last bound in the last bucket. Unlike
+    /// Prometheus buckets, the counts aren't cumulative.
+    pub flush_latency: [u64; FLUSH_LATENCY_BUCKETS.len() + 1],
+}
+
+impl Metrics {
+    /// Updates the counters for the event.
+    pub fn record(&mut self, event: MetricEvent) {
+        match event {
+            MetricEvent::EntryAppended => self.entries_appended += 1,
+            MetricEvent::Flushed { bytes, latency } => {
+                self.bytes_flushed += bytes;
+                self.flushes += 1;
+                let bucket = FLUSH_LATENCY_BUCKETS
+                    .iter()
+                    .position(|&bound| latency <= bound)
+                    .unwrap_or(FLUSH_LATENCY_BUCKETS.len());
+                self.flush_latency[bucket] += 1;
+            }
+            MetricEvent::Committed => self.commits += 1,
+            MetricEvent::Aborted => self.aborts += 1,
+            MetricEvent::CheckpointBegun => self.checkpoints += 1,
+            MetricEvent::Recovered { duration } => self.recovery_duration = duration,
+        }
+    }
+}
+
+/// Something a log did that its metrics count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetricEvent {
+    EntryAppended,
+    /// Entries were flushed to the log's file, taking `latency` including
+    /// syncing the file if the flush synced it.
+    Flushed {
+        bytes: u64,
+        latency: Duration,
+    },
+    Committed,
+    Aborted,
+    CheckpointBegun,
+    Recovered {
+        duration: Duration,
+    },
+}
+
+/// Receives the events a log records in its metrics as they happen, for
+/// pushing them to a metrics system. Set with `LogOptions::metrics_sink`.
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, event: MetricEvent);
+}
+
+impl fmt::Debug for dyn MetricsSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MetricsSink")
+    }
+}
+
+/// The metrics of a log along with the sink it forwards events to.
+pub(crate) struct MetricsRecorder {
+    metrics: Metrics,
+    sink: Option<Arc<dyn MetricsSink>>,
+}
+
+impl MetricsRecorder {
+    pub(crate) fn new(sink: Option<Arc<dyn MetricsSink>>) -> MetricsRecorder {
+        MetricsRecorder {
+            metrics: Metrics::default(),
+            sink,
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub(crate) fn record(&mut self, event: MetricEvent) {
+        self.metrics.record(event);
+        if let Some(ref sink) = self.sink {
+            sink.record(event);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::wal::iterator::BlockSource;
+use crate::wal::record::Payload;
+
+/// Reads a log through a read-only memory mapping of its file.
+///
+/// Records are parsed directly out of the mapping and their payloads point
+/// into it, so reading a block doesn't copy it. Payloads keep the mapping
+/// alive, and are only copied when the bytes are copied out of them.
+///
+/// The mapping covers the file as it was when the source was created, so
+/// records appended afterwards aren't read. The file must not be truncated
+/// while the mapping is alive.
+///
+/// If the file can't be mapped, blocks are read from the file instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate disk_utils;
+/// use std::fs::File;
+/// use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+/// use disk_utils::wal::mmap::MmapBlockSource;
+///
+/// fn main() {
+///     let file = File::open("./log").unwrap();
+///     let mut source = MmapBlockSource::new(&file).unwrap();
+///     let iter = WalIterator::new(&mut source, ReadDirection::Forward).unwrap();
+///     for record in iter {
+///         println!("{:?}", record);
+///     }
+/// }
+/// ```
+pub struct MmapBlockSource {
+    source: Source,
+    len: u64,
+}
+
+enum Source {
+    Mapped(Payload),
+    File(File),
+}
+
+impl MmapBlockSource {
+    /// Maps the file, falling back to reading from a handle to
+    /// the file if it can't be mapped.
+    pub fn new(file: &File) -> io::Result<MmapBlockSource> {
+        let len = file.metadata()?.len();
+        let source = match Mapping::new(file, len) {
+            Ok(mapping) => Source::Mapped(Payload::mapped(Arc::new(mapping))),
+            Err(_) => Source::File(file.try_clone()?),
+        };
+        Ok(MmapBlockSource { source, len })
+    }
+
+    /// Returns true if the file was mapped instead of falling back to reads.
+    pub fn is_mapped(&self) -> bool {
+        match self.source {
+            Source::Mapped(_) => true,
+            Source::File(_) => false,
+        }
+    }
+}
+
+impl BlockSource for MmapBlockSource {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Payload> {
+        match self.source {
+            Source::Mapped(ref mapping) => {
+                let start = cmp::min(pos, self.len) as usize;
+                let end = cmp::min(start + len, mapping.len());
+                Ok(mapping.slice(start..end))
+            }
+            Source::File(ref mut file) => file.read_at(pos, len),
+        }
+    }
+}
+
+/// Read-only mapping of a file that is unmapped when dropped.
+pub(crate) struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+// The mapping is read-only and owned by the `Mapping`.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn new(file: &File, len: u64) -> io::Result<Mapping> {
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+
+        if len == 0 {
+            // Empty mappings aren't allowed, but an empty file has no bytes to map.
+            return Ok(Mapping {
+                ptr: ptr::NonNull::dangling().as_ptr(),
+                len: 0,
+            });
+        }
+
+        let len = len as usize;
+        let ptr = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == sys::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn new(_: &File, _: u64) -> io::Result<Mapping> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Memory mapping is not supported on this platform",
+        ))
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        unsafe {
+            if self.len > 0 {
+                sys::munmap(self.ptr as *mut _, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+    use std::os::raw::{c_int, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
@@ -1,22 +1,65 @@
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod backend;
+pub mod chained;
+pub mod commit;
+pub mod compact;
+pub mod dump;
 pub mod entries;
+pub mod header;
 pub mod iterator;
+pub mod manager;
+pub mod metrics;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod options;
 pub mod record;
 pub mod redo_log;
+pub mod replication;
+pub mod segment;
 pub mod serializable;
+pub mod shared_redo_log;
+pub mod sink;
+pub mod snapshot;
+pub mod tail;
+pub mod transaction;
+mod truncate;
 pub mod undo_log;
+pub mod verify;
+pub mod writer;
 
-use self::iterator::{BlockError, WalIterator};
-use self::record::{Record, RecordType, BLOCK_SIZE};
+use self::backend::{FileBackend, LogBackend};
+use self::header::FileHeader;
+use self::iterator::{
+    BlockError, BlockSource, EntryIterator, OnCorruption, ReadDirection, WalIterator,
+};
+use self::metrics::MetricsSink;
+#[cfg(feature = "mmap")]
+use self::mmap::MmapBlockSource;
+use self::record::{
+    check_block_size, check_max_record_size, BlockFormat, Payload, Record, RecordError, RecordType,
+    BLOCK_SIZE, HEADER_SIZE,
+};
+use self::writer::{end_of_log, only_padding, SetLen, Writer};
+use byteorder::{BigEndian, WriteBytesExt};
+use crc::crc32;
 
+use std::cmp;
 use std::collections::HashSet;
+use std::error;
+use std::fmt;
 use std::fmt::Debug;
-use std::fs::File;
 use std::hash::Hash;
 use std::io;
-use std::io::Write;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::ops::Range;
+use std::path::Path;
 use std::result;
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::Serializable;
+use super::{DeserializeRef, Serializable};
 
 pub trait LogData: Clone + PartialEq + Debug {
     type Key: Clone + PartialEq + Eq + Debug + Hash + Serializable;
@@ -29,13 +72,113 @@ pub trait LogStore<Data: LogData> {
     fn update(&mut self, key: Data::Key, val: Data::Value);
     fn flush(&mut self) -> io::Result<()>;
     fn flush_change(&mut self, key: Data::Key, val: Data::Value) -> io::Result<()>;
+
+    /// Makes the removal of the key durable, like `flush_change` does for
+    /// changes. Flushes the whole store unless the store overrides it.
+    fn flush_remove(&mut self, key: &Data::Key) -> io::Result<()> {
+        let _ = key;
+        self.flush()
+    }
+
+    /// Returns the LSN of the commit of the last transaction a redo log
+    /// applied to the store, as recorded by `set_applied_lsn`.
+    ///
+    /// Recovery doesn't replay the transactions committed at or before
+    /// it, so stores whose updates aren't idempotent, like counters, don't
+    /// apply a transaction twice. Returns None unless the store overrides
+    /// it, in which case every committed transaction is replayed.
+    fn applied_lsn(&self) -> Option<u64> {
+        None
+    }
+
+    /// Records the LSN of the commit of the last transaction applied to
+    /// the store, which the store should make durable along with the
+    /// changes when it's flushed. Does nothing unless the store overrides it.
+    fn set_applied_lsn(&mut self, lsn: u64) {
+        let _ = lsn;
+    }
 }
 
+/// Error from opening, writing to or recovering a log.
+///
+/// Implements `std::error::Error`, so it can be returned with `?` from
+/// functions returning boxed errors or `anyhow::Result`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate anyhow;
+/// extern crate disk_utils;
+/// use std::collections::HashMap;
+/// use std::io;
+/// use disk_utils::wal::redo_log::RedoLog;
+/// use disk_utils::wal::{LogData, LogStore};
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Data;
+///
+/// impl LogData for Data {
+///     type Key = i32;
+///     type Value = String;
+/// }
+///
+/// struct Store(HashMap<i32, String>);
+///
+/// impl LogStore<Data> for Store {
+///     fn get(&self, key: &i32) -> Option<String> {
+///         self.0.get(key).cloned()
+///     }
+///     fn remove(&mut self, key: &i32) {
+///         self.0.remove(key);
+///     }
+///     fn update(&mut self, key: i32, val: String) {
+///         self.0.insert(key, val);
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+///     fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// fn write_value(path: &str) -> anyhow::Result<()> {
+///     let mut log = RedoLog::new(path, Store(HashMap::new()))?;
+///     let tid = log.start();
+///     log.write(tid, 1, "value".to_string())?;
+///     log.commit(tid)?;
+///     Ok(())
+/// }
+///
+/// fn main() {
+///     let path = "./files/log_error_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     # let _ = std::fs::remove_file(path);
+///     write_value(path).unwrap();
+///     // A directory can't be opened as a log.
+///     assert!(write_value("./files").is_err());
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum LogError {
     IoError(io::Error),
     BlockError(BlockError),
     SerializeError(SerializeError),
+    /// Recovery stopped at an entry it couldn't read.
+    RecoveryError(RecoveryError),
+    /// The transaction id isn't of an active transaction, either because
+    /// it was never started, because it already committed or aborted, or
+    /// because it was prepared and can only be resolved.
+    UnknownTransaction(u64),
+    /// The transaction id was already used by a transaction in the log.
+    DuplicateTransaction(u64),
+    /// Another log, in this process or another, has the log's file open.
+    AlreadyLocked,
+    /// The log can't be compacted while the transactions are active or
+    /// prepared, since their entries would be lost.
+    ActiveTransactions(Vec<u64>),
 }
 
 impl From<io::Error> for LogError {
@@ -56,8 +199,474 @@ impl From<SerializeError> for LogError {
     }
 }
 
+impl From<RecoveryError> for LogError {
+    fn from(err: RecoveryError) -> LogError {
+        LogError::RecoveryError(err)
+    }
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LogError::IoError(ref err) => write!(f, "Log I/O failed: {}", err),
+            LogError::BlockError(ref err) => write!(f, "Reading the log failed: {}", err),
+            LogError::SerializeError(ref err) => write!(f, "Reading a log entry failed: {}", err),
+            LogError::RecoveryError(ref err) => write!(f, "{}", err),
+            LogError::UnknownTransaction(tid) => write!(f, "Transaction {} isn't active", tid),
+            LogError::DuplicateTransaction(tid) => {
+                write!(f, "Transaction {} was already started", tid)
+            }
+            LogError::AlreadyLocked => write!(f, "The log is already open in another log"),
+            LogError::ActiveTransactions(ref tids) => {
+                write!(f, "Transactions {:?} are still active", tids)
+            }
+        }
+    }
+}
+
+impl error::Error for LogError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            LogError::IoError(ref err) => Some(err),
+            LogError::BlockError(ref err) => Some(err),
+            LogError::SerializeError(ref err) => Some(err),
+            LogError::RecoveryError(ref err) => Some(err),
+            LogError::UnknownTransaction(_)
+            | LogError::DuplicateTransaction(_)
+            | LogError::AlreadyLocked
+            | LogError::ActiveTransactions(_) => None,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, LogError>;
 
+/// Error returned when recovering a log stops at an entry it can't read,
+/// so the store may be missing changes from the rest of the log.
+///
+/// Recovery only returns it with `OnCorruption::Error`. Logs opened with
+/// `OnCorruption::SkipToNextBlock` skip the entries they can't read
+/// instead and recover what is left.
+#[derive(Debug)]
+pub struct RecoveryError {
+    /// Error reading the entry.
+    pub error: SerializeError,
+    /// Entries read before the failing one by the pass of recovery that
+    /// failed.
+    pub entries_recovered: usize,
+    /// Offset in the log of the record where reading failed, if known.
+    pub offset: Option<u64>,
+}
+
+impl fmt::Display for RecoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Recovery failed after {} entries",
+            self.entries_recovered
+        )?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl error::Error for RecoveryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Compression applied to entries before they are split into records.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// LZ4 block compression, available with the `compression` feature.
+    #[cfg(feature = "compression")]
+    Lz4,
+}
+
+/// When a log syncs its file to disk after flushing entries to it.
+///
+/// Entries flushed to the file without syncing can be lost if the
+/// machine crashes, even after the transaction they belong to committed.
+/// Any policy other than `Never` also syncs the aborts recovery logs for
+/// the transactions it rolled back before the log is returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Leave syncing to the operating system.
+    #[default]
+    Never,
+    /// Sync when a transaction commits, before the log flushes the store,
+    /// so a committed transaction is never lost.
+    OnCommit,
+    /// Sync the checkpoint records before the store is flushed for them.
+    OnCheckpoint,
+    /// Sync once this many bytes have been written since the last sync.
+    EveryNBytes(u64),
+}
+
+/// Why a log is flushing its entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SyncPoint {
+    Commit,
+    /// An undo entry is flushed before the change it undoes reaches the store.
+    WriteAhead,
+    Checkpoint,
+    /// Recovery logs the aborts of the transactions it rolled back.
+    Recovery,
+    Flush,
+}
+
+impl SyncPolicy {
+    /// Returns whether the log syncs after flushing at the point with
+    /// the given number of bytes written since the last sync.
+    pub(crate) fn syncs_at(&self, point: SyncPoint, unsynced_bytes: u64) -> bool {
+        match *self {
+            SyncPolicy::Never => false,
+            // A second recovery mustn't find the aborted transactions unfinished.
+            _ if point == SyncPoint::Recovery => true,
+            SyncPolicy::OnCommit => point == SyncPoint::Commit,
+            SyncPolicy::OnCheckpoint => point == SyncPoint::Checkpoint,
+            SyncPolicy::EveryNBytes(bytes) => unsynced_bytes >= bytes,
+        }
+    }
+}
+
+/// When an undo log flushes the old value of a change to the log,
+/// relative to applying the change to the store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteAheadPolicy {
+    /// Flush the entry holding the old value before the change is applied
+    /// to the store, so the change can be rolled back even if the store
+    /// persists it on its own before the transaction commits. The log is
+    /// only synced for it with `SyncPolicy::EveryNBytes`.
+    #[default]
+    BeforeUpdate,
+    /// Keep the entries in memory until the transaction commits or aborts,
+    /// or the log is checkpointed. Only safe with stores that don't persist
+    /// changes until they're flushed.
+    OnCommit,
+}
+
+/// How a prepared transaction is resolved with `resolve_prepared`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Commit,
+    Abort,
+}
+
+/// When a log checkpoints itself after a transaction commits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckpointPolicy {
+    /// Only checkpoint when `checkpoint` is called.
+    #[default]
+    Manual,
+    /// Checkpoint once this many transactions have committed since the last checkpoint.
+    EveryNCommits(u32),
+    /// Checkpoint once this many bytes have been flushed to the log since
+    /// the last checkpoint.
+    EveryNBytes(u64),
+}
+
+impl CheckpointPolicy {
+    /// Returns whether the log checkpoints with the given number of commits
+    /// and bytes flushed since the last checkpoint.
+    pub(crate) fn checkpoints_at(&self, commits: u32, bytes: u64) -> bool {
+        match *self {
+            CheckpointPolicy::Manual => false,
+            CheckpointPolicy::EveryNCommits(n) => commits >= n,
+            CheckpointPolicy::EveryNBytes(n) => bytes >= n,
+        }
+    }
+}
+
+/// Callback that recovery calls with the fraction of recovery that is done,
+/// from 0 to 1.
+#[derive(Clone)]
+pub struct RecoveryProgress(Arc<dyn Fn(f64) + Send + Sync>);
+
+impl RecoveryProgress {
+    pub fn new<F: Fn(f64) + Send + Sync + 'static>(callback: F) -> RecoveryProgress {
+        RecoveryProgress(Arc::new(callback))
+    }
+
+    /// Reports the progress of a pass of recovery over `total` records
+    /// that makes up `share` of recovery after the `start` fraction.
+    pub(crate) fn report_pass(
+        &self,
+        iter: &WalIterator<impl BlockSource>,
+        total: usize,
+        start: f64,
+        share: f64,
+    ) {
+        let remaining = iter.size_hint().1.unwrap_or(total);
+        let done = if total == 0 {
+            1.0
+        } else {
+            total.saturating_sub(remaining) as f64 / total as f64
+        };
+        (self.0)(start + done * share);
+    }
+
+    pub(crate) fn report(&self, fraction: f64) {
+        (self.0)(fraction)
+    }
+}
+
+impl fmt::Debug for RecoveryProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RecoveryProgress")
+    }
+}
+
+/// Options for opening a redo or undo log.
+#[derive(Clone, Debug)]
+pub struct LogOptions {
+    /// Compression applied to entries written to the log.
+    pub compression: Compression,
+    /// When the log's file is synced to disk.
+    pub sync: SyncPolicy,
+    /// When the log checkpoints itself.
+    pub checkpoint: CheckpointPolicy,
+    /// When an undo log flushes old values relative to updating the store.
+    pub write_ahead: WriteAheadPolicy,
+    /// Size of the blocks records are packed into. Must be a power of two
+    /// and match the block size the log was originally written with.
+    pub block_size: i64,
+    /// Whether each block ends with a CRC over the whole block.
+    /// Must match the setting the log was originally written with.
+    pub block_checksums: bool,
+    /// Largest payload of the records entries are split into, or None
+    /// for as much as fits in a block.
+    pub max_record_size: Option<usize>,
+    /// Bytes of entries the log holds in memory before flushing them to
+    /// its file, or None to hold them until a commit, abort or checkpoint
+    /// flushes them. Flushing entries early is safe since recovery ignores
+    /// the entries of transactions that didn't commit.
+    pub max_pending_bytes: Option<u64>,
+    /// Whether a log written before log files had headers can be opened.
+    /// The log keeps being written without a header. Empty files are
+    /// always given a header.
+    pub allow_headerless: bool,
+    /// Number of blocks the log's file is grown by at a time, or 0 to
+    /// grow it as records are appended. See `Writer::preallocate`.
+    pub preallocate_blocks: u64,
+    /// Size a segment of a log kept in a directory grows to before the
+    /// next segment is started. See `manager::Wal`.
+    pub segment_size: u64,
+    /// Whether a redo log pads the rest of the block after the end of each
+    /// checkpoint, so the next checkpoint starts at a block boundary.
+    pub seal_checkpoints: bool,
+    /// Whether closing a log checkpoints it before syncing its file.
+    pub checkpoint_on_close: bool,
+    /// How long an undo log's checkpoint waits for the transactions active
+    /// when it began before `checkpoint` aborts them to end it, or None
+    /// to wait for them forever.
+    pub checkpoint_timeout: Option<Duration>,
+    /// Whether a redo log applies the changes of a transaction to the
+    /// store only once the transaction commits, instead of as they are
+    /// written, so the store never sees uncommitted changes.
+    pub defer_store_updates: bool,
+    /// What recovery does when it finds a corrupted record in the log.
+    /// By default recovery fails with a `RecoveryError`, while
+    /// `OnCorruption::SkipToNextBlock` recovers whatever can still be read.
+    pub on_corruption: OnCorruption,
+    /// Number of blocks recovery reads from the log at a time.
+    pub readahead_blocks: usize,
+    /// Whether recovery reads the log through a memory mapping. Logs
+    /// kept in backends other than files are read without one.
+    #[cfg(feature = "mmap")]
+    pub mmap: bool,
+    /// Called as recovery reads the log. Counting the records in the log
+    /// for the progress takes an extra pass over the record headers.
+    pub recovery_progress: Option<RecoveryProgress>,
+    /// Receives the events the log counts in its `Metrics`.
+    pub metrics_sink: Option<Arc<dyn MetricsSink>>,
+}
+
+/// 64MiB Default size of the segments of a log kept in a directory.
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+impl Default for LogOptions {
+    fn default() -> LogOptions {
+        LogOptions {
+            compression: Compression::default(),
+            sync: SyncPolicy::default(),
+            checkpoint: CheckpointPolicy::default(),
+            write_ahead: WriteAheadPolicy::default(),
+            block_size: BLOCK_SIZE,
+            block_checksums: false,
+            max_record_size: None,
+            max_pending_bytes: None,
+            allow_headerless: false,
+            preallocate_blocks: 0,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            seal_checkpoints: false,
+            checkpoint_on_close: false,
+            checkpoint_timeout: None,
+            defer_store_updates: false,
+            on_corruption: OnCorruption::default(),
+            readahead_blocks: 1,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+            recovery_progress: None,
+            metrics_sink: None,
+        }
+    }
+}
+
+impl LogOptions {
+    /// Checks that the options can be used to open a log, returning an
+    /// `InvalidInput` error describing the first option that can't.
+    pub fn validate(&self) -> io::Result<()> {
+        check_block_size(self.block_size)?;
+        if let Some(size) = self.max_record_size {
+            let max_payload_size = self.block_format().max_payload_size();
+            if size == 0 || size > max_payload_size {
+                return Err(invalid_option(format!(
+                    "Max record size {} must be between 1 and {} for blocks of {} bytes",
+                    size, max_payload_size, self.block_size
+                )));
+            }
+        }
+        match self.checkpoint {
+            CheckpointPolicy::EveryNCommits(0) => {
+                return Err(invalid_option(
+                    "Checkpoint interval must be at least 1 commit",
+                ));
+            }
+            CheckpointPolicy::EveryNBytes(0) => {
+                return Err(invalid_option(
+                    "Checkpoint interval must be at least 1 byte",
+                ));
+            }
+            _ => {}
+        }
+        if self.max_pending_bytes == Some(0) {
+            return Err(invalid_option("Pending byte limit must be at least 1 byte"));
+        }
+        if self.sync == SyncPolicy::EveryNBytes(0) {
+            return Err(invalid_option("Sync interval must be at least 1 byte"));
+        }
+        if self.readahead_blocks == 0 {
+            return Err(invalid_option("Readahead must be at least 1 block"));
+        }
+        if self.segment_size < self.block_size as u64 {
+            return Err(invalid_option(format!(
+                "Segment size {} must be at least the block size of {} bytes",
+                self.segment_size, self.block_size
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn block_format(&self) -> BlockFormat {
+        BlockFormat {
+            block_size: self.block_size,
+            checksums: self.block_checksums,
+        }
+    }
+
+    /// Opens the log's file, creating it if it doesn't exist. Preallocated
+    /// logs write after their last record, so their files aren't opened
+    /// for appending.
+    pub(crate) fn open_file<P: AsRef<Path> + ?Sized>(&self, path: &P) -> io::Result<FileBackend> {
+        FileBackend::open(path, self.preallocate_blocks == 0)
+    }
+
+    /// Opens the log's file like `open_file`, returning
+    /// `LogError::AlreadyLocked` if another log has it open.
+    pub(crate) fn open_log_file<P: AsRef<Path> + ?Sized>(&self, path: &P) -> Result<FileBackend> {
+        self.open_file(path).map_err(|err| match err.kind() {
+            io::ErrorKind::WouldBlock => LogError::AlreadyLocked,
+            _ => LogError::IoError(err),
+        })
+    }
+
+    /// Creates a writer appending to the log's file or backend, writing
+    /// a header to it first if it's empty.
+    ///
+    /// Opening a preallocated log without preallocation trims the zeroed
+    /// space after its records, since records appended after the zeroed
+    /// blocks wouldn't be read. Other bytes after the last record are left
+    /// for recovery to remove with `truncate_torn_entry`.
+    pub(crate) fn writer_for<W>(&self, mut file: W) -> io::Result<Writer<W>>
+    where
+        W: Read + Write + Seek + SetLen,
+    {
+        let format = self.block_format();
+        let len = file.seek(SeekFrom::End(0))?;
+        if self.preallocate_blocks == 0 {
+            let end = end_of_log(&mut file, format, len)?;
+            if end < len && only_padding(&mut file, format, end, len)? {
+                file.set_len(end)?;
+            }
+        }
+        let headerless = self.allow_headerless
+            && file.seek(SeekFrom::End(0))? > 0
+            && FileHeader::read(&mut file)?.is_none();
+        let writer = if headerless {
+            Writer::with_format(file, format)?
+        } else {
+            Writer::with_header(file, format)?
+        };
+        let mut writer = writer.compression(self.compression);
+        if let Some(size) = self.max_record_size {
+            writer = writer.max_record_size(size);
+        }
+        match self.preallocate_blocks {
+            0 => Ok(writer),
+            blocks => writer.preallocate(blocks),
+        }
+    }
+}
+
+fn invalid_option<E: Into<Box<dyn error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+/// Source recovery reads a log's backend through. Only backends
+/// keeping their records in a file can be memory-mapped.
+pub(crate) enum LogSource<'a, B> {
+    Backend(&'a mut B),
+    #[cfg(feature = "mmap")]
+    Mapped(MmapBlockSource),
+}
+
+impl<'a, B: LogBackend> LogSource<'a, B> {
+    #[cfg_attr(not(feature = "mmap"), allow(unused_variables))]
+    pub(crate) fn new(file: &'a mut B, options: &LogOptions) -> io::Result<LogSource<'a, B>> {
+        #[cfg(feature = "mmap")]
+        {
+            if let (true, Some(mapped)) = (options.mmap, file.file()) {
+                return Ok(LogSource::Mapped(MmapBlockSource::new(mapped)?));
+            }
+        }
+        Ok(LogSource::Backend(file))
+    }
+}
+
+impl<'a, B: LogBackend> BlockSource for LogSource<'a, B> {
+    fn size(&mut self) -> io::Result<u64> {
+        match *self {
+            LogSource::Backend(ref mut file) => file.size(),
+            #[cfg(feature = "mmap")]
+            LogSource::Mapped(ref mut source) => source.size(),
+        }
+    }
+
+    fn read_at(&mut self, pos: u64, len: usize) -> io::Result<Payload> {
+        match *self {
+            LogSource::Backend(ref mut file) => file.read_at(pos, len),
+            #[cfg(feature = "mmap")]
+            LogSource::Mapped(ref mut source) => source.read_at(pos, len),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum RecoverState {
     /// No checkpoint entry found, read until end of log.
@@ -71,10 +680,34 @@ enum RecoverState {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SerializeError {
     IoError(io::Error),
-    InvalidTransfer(RecordType),
+    /// Reading records from the log failed.
+    BlockError(BlockError),
+    /// A record doesn't continue the chain of records read before it.
+    InvalidTransfer {
+        /// Type of the record that broke the chain.
+        found: RecordType,
+        /// Type of the record read before it for the entry, or None if
+        /// it was the first record read for the entry.
+        previous: Option<RecordType>,
+        /// Offset in the file of the record.
+        offset: u64,
+        /// Index of the record in its block.
+        record: usize,
+    },
+    /// A record of the entry is corrupted. The error is always
+    /// a `BlockError::Corrupted` saying where.
+    Corrupted(BlockError),
     OutOfRecords,
+    /// The log ends partway through a chain of records, because the
+    /// writer stopped while appending the entry. `start` and `end` are the
+    /// offsets in the file of the bytes of the records that were written.
+    TornEntry {
+        start: u64,
+        end: u64,
+    },
 }
 
 impl From<io::Error> for SerializeError {
@@ -83,6 +716,55 @@ impl From<io::Error> for SerializeError {
     }
 }
 
+impl From<BlockError> for SerializeError {
+    fn from(err: BlockError) -> SerializeError {
+        match err {
+            BlockError::Corrupted { .. } => SerializeError::Corrupted(err),
+            err => SerializeError::BlockError(err),
+        }
+    }
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerializeError::IoError(ref err) => write!(f, "Entry couldn't be decoded: {}", err),
+            SerializeError::BlockError(ref err) | SerializeError::Corrupted(ref err) => {
+                write!(f, "{}", err)
+            }
+            SerializeError::InvalidTransfer {
+                found,
+                previous,
+                offset,
+                ..
+            } => {
+                write!(f, "Invalid record transition {:?} after ", found)?;
+                match previous {
+                    Some(previous) => write!(f, "{:?}", previous)?,
+                    None => write!(f, "None")?,
+                }
+                write!(f, " at offset {}", offset)
+            }
+            SerializeError::OutOfRecords => write!(f, "No records left to read an entry from"),
+            SerializeError::TornEntry { start, end } => write!(
+                f,
+                "Log ends partway through the entry at offsets {}..{}",
+                start, end
+            ),
+        }
+    }
+}
+
+impl error::Error for SerializeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SerializeError::IoError(ref err) => Some(err),
+            SerializeError::BlockError(ref err) | SerializeError::Corrupted(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum SerializeState {
     None,
@@ -90,75 +772,381 @@ enum SerializeState {
     Middle,
 }
 
+impl SerializeError {
+    /// Returns the offset in the log of the record the error was found at,
+    /// if the error knows it.
+    pub fn offset(&self) -> Option<u64> {
+        match *self {
+            SerializeError::InvalidTransfer { offset, .. } => Some(offset),
+            SerializeError::TornEntry { start, .. } => Some(start),
+            SerializeError::Corrupted(BlockError::Corrupted { offset, .. }) => Some(offset),
+            _ => None,
+        }
+    }
+}
+
 pub type SerializeResult<T> = result::Result<T, SerializeError>;
 
-pub fn read_serializable<S: Serializable>(iter: &mut WalIterator) -> SerializeResult<S> {
+/// Returns the next entry read during recovery in the direction, or None
+/// if recovery should stop because no more entries can be read.
+///
+/// `recovered` counts the entries the pass has returned so far. Errors
+/// reading the log itself are returned as a `RecoveryError` so recovery
+/// fails instead of stopping early. So are entries that can't be
+/// reassembled or deserialized, unless corrupted blocks are skipped, in
+/// which case the broken entries are skipped as well.
+fn recover_entry<S: Serializable, R: BlockSource>(
+    entries: &mut EntryIterator<S, R>,
+    direction: ReadDirection,
+    on_corruption: OnCorruption,
+    recovered: &mut usize,
+) -> Result<Option<S>> {
+    loop {
+        let result = match direction {
+            ReadDirection::Forward => entries.next(),
+            ReadDirection::Backward => entries.next_back(),
+        };
+        let error = match result {
+            Some(Ok(entry)) => {
+                *recovered += 1;
+                return Ok(Some(entry));
+            }
+            Some(Err(SerializeError::TornEntry { .. })) => continue,
+            Some(Err(err @ SerializeError::BlockError(_)))
+            | Some(Err(err @ SerializeError::Corrupted(_))) => err,
+            Some(Err(_)) if on_corruption == OnCorruption::SkipToNextBlock => continue,
+            Some(Err(err)) => err,
+            None => return Ok(None),
+        };
+        // An entry that failed to deserialize was read up to its last record.
+        let offset = match error {
+            SerializeError::IoError(_) => entries.get_mut().last_record_range().map(|r| r.start),
+            _ => error.offset(),
+        };
+        return Err(RecoveryError {
+            error,
+            entries_recovered: *recovered,
+            offset,
+        }
+        .into());
+    }
+}
+
+/// Returns whether recovery reading backwards has read the entry at the
+/// offset the backend says recovery starts from, so the entries before
+/// it aren't needed. An offset of 0 doesn't stop recovery.
+fn reached_recovery_start<S: Serializable, R: BlockSource>(
+    entries: &mut EntryIterator<S, R>,
+    recovery_start: u64,
+) -> bool {
+    recovery_start > 0
+        && entries
+            .get_mut()
+            .last_record_range()
+            .is_some_and(|range| range.start <= recovery_start)
+}
+
+/// Removes the entry at the end of the log if the writer stopped partway
+/// through appending it, so recovery reads only complete entries and the
+/// next entry isn't appended after the torn one's records.
+///
+/// The writer may have stopped partway through one of the entry's records
+/// as well, leaving the file cut off inside the record. The cut off record
+/// is removed first, then the records of the entry written before it.
+///
+/// Bytes after the last record that the log can't read, like the rest of
+/// a block whose record was torn before its header was written, are
+/// removed as well unless corruption fails recovery.
+///
+/// Returns the range of the bytes of the torn entry that were removed.
+pub(crate) fn truncate_torn_entry<S: Serializable, B: LogBackend>(
+    writer: &mut Writer<B>,
+    on_corruption: OnCorruption,
+) -> Result<Option<Range<u64>>> {
+    let format = writer.format();
+    let end = writer.position();
+    let first_offset = writer.file().first_offset();
+    let last_record_end = end_of_log(writer.file_mut(), format, end)?;
+    if last_record_end < end {
+        if on_corruption == OnCorruption::Error {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unreadable bytes after the last record at offset {}",
+                    last_record_end
+                ),
+            )
+            .into());
+        }
+        writer.truncate(last_record_end)?;
+    }
+    let cut = {
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), ReadDirection::Backward, format)?;
+        iter.start_at(first_offset)?;
+        match iter.try_next_back() {
+            Err(ref err @ BlockError::Corrupted { offset, .. })
+                if err.record_error().is_some_and(RecordError::is_truncation) =>
+            {
+                Some(offset)
+            }
+            _ => None,
+        }
+    };
+    if let Some(cut) = cut {
+        writer.truncate(cut)?;
+    }
+    let torn = {
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), ReadDirection::Backward, format)?;
+        iter.start_at(first_offset)?;
+        match read_serializable_backwards_at::<S>(&mut iter) {
+            Err(SerializeError::TornEntry { start, .. }) => Some(start),
+            _ => None,
+        }
+    };
+    if let Some(start) = torn {
+        writer.truncate(start)?;
+    }
+    Ok(torn.or(cut).map(|start| start..end))
+}
+
+pub fn read_serializable<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<S> {
+    let mut buf = Vec::new();
+    read_entry_bytes(iter, &mut buf)?;
+    Ok(S::deserialize(&mut &buf[..])?)
+}
+
+/// Reads the next entry like `read_serializable`, also returning the offsets
+/// in the file of the bytes of the entry's records, including their headers.
+///
+/// The range starts at the entry's first record and ends after its last
+/// record, so it also covers any block trailers between its records.
+pub fn read_serializable_at<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<(S, Range<u64>)> {
     let mut buf = Vec::new();
+    let range = read_entry_bytes(iter, &mut buf)?;
+    Ok((S::deserialize(&mut &buf[..])?, range))
+}
+
+/// Reads every entry left in the iterator with `read_serializable`.
+///
+/// Reading stops at the end of the log or at the first entry that can't be
+/// read, which is returned along with the entries read before it, so a
+/// log that ends in a corrupted or torn entry can be told apart from one
+/// that ends cleanly.
+pub fn read_all_serializable<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> (Vec<S>, Option<SerializeError>) {
+    let mut entries = Vec::new();
+    loop {
+        match read_serializable(iter) {
+            Ok(entry) => entries.push(entry),
+            Err(SerializeError::OutOfRecords) => return (entries, None),
+            Err(err) => return (entries, Some(err)),
+        }
+    }
+}
+
+/// Reads the next entry like `read_serializable`, but deserializes a value
+/// that borrows from `buf` instead of copying out of it.
+///
+/// `buf` is cleared and used as the record reassembly buffer, so it can be
+/// reused across calls to avoid reallocating.
+pub fn read_serializable_ref<'a, S: DeserializeRef<'a>>(
+    iter: &mut WalIterator<impl BlockSource>,
+    buf: &'a mut Vec<u8>,
+) -> SerializeResult<S> {
+    buf.clear();
+    read_entry_bytes(iter, buf)?;
+    let buf: &'a Vec<u8> = buf;
+    let (entry, _) = S::deserialize_ref(&buf[..])?;
+    Ok(entry)
+}
+
+/// Reads the next entry like `read_serializable`, skipping the records of
+/// broken chains instead of returning `InvalidTransfer` errors, so the
+/// entries after a damaged entry can still be read.
+pub fn read_serializable_resync<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<S> {
+    loop {
+        iter.skip_to_entry_boundary(ReadDirection::Forward)?;
+        match read_serializable(iter) {
+            Err(SerializeError::InvalidTransfer { .. }) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Reads the previous entry like `read_serializable_resync`.
+pub fn read_serializable_backwards_resync<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<S> {
+    loop {
+        iter.skip_to_entry_boundary(ReadDirection::Backward)?;
+        match read_serializable_backwards(iter) {
+            Err(SerializeError::InvalidTransfer { .. }) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Reads the next chain of records from the iterator and appends
+/// their combined payloads into `buf`, decompressing them if needed.
+/// Returns the offsets in the file of the bytes of the records.
+fn read_entry_bytes<R: BlockSource>(
+    iter: &mut WalIterator<R>,
+    buf: &mut Vec<u8>,
+) -> SerializeResult<Range<u64>> {
     let mut state = SerializeState::None;
-    for mut record in iter {
+    let mut previous = None;
+    let mut start = 0;
+    let mut end = 0;
+    loop {
+        // A record starting another entry means the chain lost its last
+        // record. The record is left to be read as the next entry.
+        if state != SerializeState::None {
+            if let Ok(Some(record)) = iter.peek() {
+                if let RecordType::Zero | RecordType::Full | RecordType::First = record.record_type
+                {
+                    let found = record.record_type;
+                    let position = iter.peeked_record(ReadDirection::Forward).unwrap();
+                    return Err(invalid_transfer(found, previous, position));
+                }
+            }
+        }
+        let record = match iter.try_next()? {
+            Some(record) => record,
+            None => break,
+        };
+        let range = iter.last_record_range().unwrap();
+        if state == SerializeState::None {
+            start = range.start;
+        }
+        end = range.end;
+        let prior = previous.replace(record.record_type);
+        let invalid = || invalid_transfer(record.record_type, prior, last_position(iter));
         match record.record_type {
             RecordType::Zero | RecordType::Full => {
-                return Ok(S::deserialize(&mut &record.payload[..])?);
+                buf.extend_from_slice(&record.payload);
+                finish_entry(buf, record.compressed)?;
+                return Ok(start..range.end);
             }
             RecordType::First => {
                 if state != SerializeState::None {
-                    return Err(SerializeError::InvalidTransfer(RecordType::First));
+                    return Err(invalid());
                 }
                 state = SerializeState::First;
-                buf.append(&mut record.payload);
+                buf.extend_from_slice(&record.payload);
             }
             RecordType::Middle => {
                 if state != SerializeState::First && state != SerializeState::Middle {
-                    return Err(SerializeError::InvalidTransfer(RecordType::Middle));
+                    return Err(invalid());
                 }
                 state = SerializeState::Middle;
-                buf.append(&mut record.payload);
+                buf.extend_from_slice(&record.payload);
             }
             RecordType::Last => {
-                if state != SerializeState::Middle {
-                    return Err(SerializeError::InvalidTransfer(RecordType::Last));
+                if state != SerializeState::First && state != SerializeState::Middle {
+                    return Err(invalid());
                 }
-                buf.append(&mut record.payload);
-                return Ok(S::deserialize(&mut &buf[..])?);
+                buf.extend_from_slice(&record.payload);
+                finish_entry(buf, record.compressed)?;
+                return Ok(start..range.end);
             }
         }
     }
 
+    if state != SerializeState::None && iter.back_at_end() {
+        return Err(SerializeError::TornEntry { start, end });
+    }
     Err(SerializeError::OutOfRecords)
 }
 
-pub fn read_serializable_backwards<S: Serializable>(iter: &mut WalIterator) -> SerializeResult<S> {
-    let mut buf = Vec::new();
+pub fn read_serializable_backwards<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<S> {
+    read_serializable_backwards_at(iter).map(|(entry, _)| entry)
+}
+
+/// Reads the previous entry like `read_serializable_backwards`, also
+/// returning the offsets in the file of the bytes of the entry's records
+/// like `read_serializable_at`.
+pub fn read_serializable_backwards_at<S: Serializable>(
+    iter: &mut WalIterator<impl BlockSource>,
+) -> SerializeResult<(S, Range<u64>)> {
+    // Payloads of the records read so far, from the last record backwards.
+    // They are only joined once the first record is found, so the bytes of
+    // the entry are copied once.
+    let mut fragments: Vec<Payload> = Vec::new();
     let mut state = SerializeState::None;
-    while let Some(mut record) = iter.next_back() {
+    let mut previous = None;
+    let mut end = 0;
+    let at_end = iter.back_at_end();
+    loop {
+        // Going backwards, a record ending another entry means the chain
+        // lost its first record.
+        if state != SerializeState::None {
+            if let Ok(Some(record)) = iter.peek_back() {
+                if let RecordType::Zero | RecordType::Full | RecordType::Last = record.record_type {
+                    let found = record.record_type;
+                    let position = iter.peeked_record(ReadDirection::Backward).unwrap();
+                    return Err(invalid_transfer(found, previous, position));
+                }
+            }
+        }
+        let record = match iter.try_next_back()? {
+            Some(record) => record,
+            None => break,
+        };
+        let range = iter.last_record_range().unwrap();
+        if state == SerializeState::None {
+            end = range.end;
+        }
+        let prior = previous.replace(record.record_type);
+        let invalid = || invalid_transfer(record.record_type, prior, last_position(iter));
         match record.record_type {
+            RecordType::Zero | RecordType::Full if !record.compressed => {
+                return Ok((S::deserialize(&mut &record.payload[..])?, range));
+            }
             RecordType::Zero | RecordType::Full => {
-                return Ok(S::deserialize(&mut &record.payload[..])?);
+                let mut buf = record.payload.to_vec();
+                finish_entry(&mut buf, record.compressed)?;
+                return Ok((S::deserialize(&mut &buf[..])?, range));
+            }
+            RecordType::First | RecordType::Middle if state == SerializeState::None && at_end => {
+                return Err(torn_entry_backwards(iter, record.record_type, range));
             }
             RecordType::First => {
-                if state != SerializeState::Middle {
-                    return Err(SerializeError::InvalidTransfer(RecordType::First));
+                if state != SerializeState::First && state != SerializeState::Middle {
+                    return Err(invalid());
+                }
+                let len: usize = fragments.iter().map(|fragment| fragment.len()).sum();
+                let mut buf = Vec::with_capacity(record.payload.len() + len);
+                buf.extend_from_slice(&record.payload);
+                for fragment in fragments.iter().rev() {
+                    buf.extend_from_slice(fragment);
                 }
-                record.payload.reverse();
-                buf.append(&mut record.payload);
-                buf.reverse();
-                return Ok(S::deserialize(&mut &buf[..])?);
+                finish_entry(&mut buf, record.compressed)?;
+                return Ok((S::deserialize(&mut &buf[..])?, range.start..end));
             }
             RecordType::Middle => {
                 if state != SerializeState::First && state != SerializeState::Middle {
-                    return Err(SerializeError::InvalidTransfer(RecordType::Middle));
+                    return Err(invalid());
                 }
                 state = SerializeState::Middle;
-                record.payload.reverse();
-                buf.append(&mut record.payload);
+                fragments.push(record.payload);
             }
             RecordType::Last => {
                 if state != SerializeState::None {
-                    return Err(SerializeError::InvalidTransfer(RecordType::Last));
+                    return Err(invalid());
                 }
                 state = SerializeState::First;
-                record.payload.reverse();
-                buf.append(&mut record.payload);
+                fragments.push(record.payload);
             }
         }
     }
@@ -166,32 +1154,288 @@ pub fn read_serializable_backwards<S: Serializable>(iter: &mut WalIterator) -> S
     Err(SerializeError::OutOfRecords)
 }
 
+/// Returns the error for a record of the type that doesn't continue the chain
+/// of records, given the offset and index in its block of the record.
+fn invalid_transfer(
+    found: RecordType,
+    previous: Option<RecordType>,
+    (offset, record): (u64, usize),
+) -> SerializeError {
+    SerializeError::InvalidTransfer {
+        found,
+        previous,
+        offset,
+        record,
+    }
+}
+
+/// Returns the offset and index in its block of the record last
+/// returned by the iterator.
+fn last_position<R: BlockSource>(iter: &WalIterator<R>) -> (u64, usize) {
+    let range = iter.last_record_range().unwrap();
+    (range.start, iter.last_record_index().unwrap())
+}
+
+/// Reads the rest of a chain of records at the end of the log backwards,
+/// starting from its last record at `range`, and returns the torn entry
+/// the records are from.
+fn torn_entry_backwards<R: BlockSource>(
+    iter: &mut WalIterator<R>,
+    mut record_type: RecordType,
+    range: Range<u64>,
+) -> SerializeError {
+    let mut start = range.start;
+    while record_type == RecordType::Middle {
+        match iter.peek_back() {
+            Ok(Some(record))
+                if matches!(record.record_type, RecordType::First | RecordType::Middle) => {}
+            _ => break,
+        }
+        if let Ok(Some(record)) = iter.try_next_back() {
+            record_type = record.record_type;
+            start = iter.last_record_range().unwrap().start;
+        }
+    }
+    SerializeError::TornEntry {
+        start,
+        end: range.end,
+    }
+}
+
+/// Decompresses the assembled entry bytes in place if the entry was compressed.
+pub(crate) fn finish_entry(buf: &mut Vec<u8>, compressed: bool) -> SerializeResult<()> {
+    if compressed {
+        *buf = decompress(&buf[..])?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compression")]
+fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    lz4_flex::decompress_size_prepended(bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_: &[u8]) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reading compressed entries requires the compression feature",
+    ))
+}
+
+/// Splits the bytes into records holding at most `max_record_size` bytes each.
+///
+/// Returns an error if `max_record_size` is 0, or if it plus the record
+/// header doesn't fit in the u16 record size field.
 pub fn split_bytes_into_records(bytes: &[u8], max_record_size: usize) -> io::Result<Vec<Record>> {
-    let mut records: Vec<_> = bytes
-        .chunks(max_record_size)
-        .map(|bytes| Record::new(RecordType::Middle, bytes.to_vec()))
+    split_bytes_after(bytes, max_record_size, max_record_size)
+}
+
+/// Splits the bytes into records sized to fill blocks of the format,
+/// given the space left in the block the records are appended to.
+///
+/// The first record fills the rest of the current block and the records
+/// after it fill whole blocks, so appending them only pads a block when
+/// the space left in it can't hold a record header. `space_left_in_block`
+/// is the value of `BlockFormat::space_remaining` for the end of the log.
+pub fn split_bytes_for_append(
+    bytes: &[u8],
+    space_left_in_block: u64,
+    format: BlockFormat,
+) -> io::Result<Vec<Record>> {
+    let first_record_size = first_record_size(space_left_in_block, format);
+    split_bytes_after(bytes, first_record_size, format.max_payload_size())
+}
+
+/// Returns the size of the first record of an entry appended to a block
+/// with the space left, so the record fills the rest of the block.
+pub(crate) fn first_record_size(space_left_in_block: u64, format: BlockFormat) -> usize {
+    let max_record_size = format.max_payload_size();
+    match (space_left_in_block as usize).saturating_sub(HEADER_SIZE) {
+        0 => max_record_size,
+        size => cmp::min(size, max_record_size),
+    }
+}
+
+/// Splits the bytes into records like `split_bytes_into_records`, with
+/// at most `first_record_size` bytes in the first record so it can fill
+/// the rest of a partially written block.
+pub(crate) fn split_bytes_after(
+    bytes: &[u8],
+    first_record_size: usize,
+    max_record_size: usize,
+) -> io::Result<Vec<Record>> {
+    check_max_record_size(max_record_size)?;
+
+    // The bytes are copied once and the records' payloads share the copy.
+    let first = cmp::min(first_record_size, bytes.len());
+    let ranges: Vec<_> = iter::once(0..first)
+        .filter(|range| !range.is_empty())
+        .chain(
+            (first..bytes.len())
+                .step_by(max_record_size)
+                .map(|start| start..cmp::min(start + max_record_size, bytes.len())),
+        )
         .collect();
-    if records.len() == 1 {
-        records.first_mut().unwrap().record_type = RecordType::Full;
-    } else if records.len() > 1 {
-        records.first_mut().unwrap().record_type = RecordType::First;
-        records.last_mut().unwrap().record_type = RecordType::Last;
-    } else {
-        records.push(Record::new(RecordType::Zero, vec![]));
+    let payload = Payload::from(bytes);
+    let num_chunks = ranges.len();
+    let mut records = ranges
+        .into_iter()
+        .enumerate()
+        .map(|(i, range)| {
+            let record_type = match i {
+                _ if num_chunks == 1 => RecordType::Full,
+                0 => RecordType::First,
+                i if i == num_chunks - 1 => RecordType::Last,
+                _ => RecordType::Middle,
+            };
+            Record::with_payload(record_type, payload.slice(range))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if records.is_empty() {
+        records.push(Record::new(RecordType::Zero, vec![])?);
     }
 
     Ok(records)
 }
 
-pub fn append_to_file(file: &mut File, record: &Record) -> io::Result<()> {
-    let file_len = file.metadata()?.len();
-    let curr_block_len = file_len - (file_len / BLOCK_SIZE as u64) * BLOCK_SIZE as u64;
-    if curr_block_len + record.payload.len() as u64 > BLOCK_SIZE as u64 {
-        let padding_len = BLOCK_SIZE as u64 - curr_block_len;
-        let padding = vec![0; padding_len as usize];
-        file.write_all(&padding[..])?;
+/// Splits the bytes into records like `split_bytes_into_records`,
+/// compressing them first with the given compression.
+///
+/// Entries that don't get smaller when compressed are stored uncompressed.
+pub fn split_bytes_into_records_with(
+    bytes: &[u8],
+    max_record_size: usize,
+    compression: Compression,
+) -> io::Result<Vec<Record>> {
+    match compress(bytes, compression) {
+        Some(compressed) => {
+            let records = split_bytes_into_records(&compressed, max_record_size)?;
+            Ok(records.into_iter().map(Record::into_compressed).collect())
+        }
+        None => split_bytes_into_records(bytes, max_record_size),
+    }
+}
+
+/// Compresses the bytes of an entry, returning None if the
+/// entry isn't compressed or compressing doesn't shrink it.
+#[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+pub(crate) fn compress(bytes: &[u8], compression: Compression) -> Option<Vec<u8>> {
+    match compression {
+        Compression::None => None,
+        #[cfg(feature = "compression")]
+        Compression::Lz4 => {
+            let compressed = lz4_flex::compress_prepend_size(bytes);
+            if compressed.len() >= bytes.len() {
+                return None;
+            }
+            Some(compressed)
+        }
+    }
+}
+
+/// Appends the record to the end of the file, padding the rest of the
+/// current block first if the record doesn't fit in it, and returns the
+/// offset in the file the record was written at.
+///
+/// The record is written with its own LSN; use a `Writer` to have
+/// LSNs assigned automatically. Each append seeks to the end of the file
+/// to find the block offset, while a `Writer` tracks it itself.
+pub fn append_to_file<W: Read + Write + Seek>(file: &mut W, record: &Record) -> io::Result<u64> {
+    append_to_file_with_block_size(file, record, BLOCK_SIZE)
+}
+
+/// Serializes the entry and appends it to the end of the file split into
+/// records holding at most `max_record_size` bytes each, returning the
+/// offset in the file of the entry's first record.
+///
+/// The records are written with `append_to_file`, so they don't get LSNs.
+/// Entries written this way are read back with `read_serializable`.
+pub fn write_serializable<W, S>(file: &mut W, entry: &S, max_record_size: usize) -> io::Result<u64>
+where
+    W: Read + Write + Seek,
+    S: Serializable,
+{
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes)?;
+    let mut records = split_bytes_into_records(&bytes, max_record_size)?.into_iter();
+    let offset = append_to_file(file, &records.next().unwrap())?;
+    for record in records {
+        append_to_file(file, &record)?;
     }
+    Ok(offset)
+}
 
+/// Serializes the entry and appends it with the writer like
+/// `write_serializable`, with records sized to fill the writer's blocks.
+pub fn write_serializable_to<W, S>(writer: &mut Writer<W>, entry: &S) -> io::Result<u64>
+where
+    W: Read + Write + Seek,
+    S: Serializable,
+{
+    writer.append_serializable(entry)
+}
+
+/// Appends the record like `append_to_file` to a log
+/// written with the given block size.
+pub fn append_to_file_with_block_size<W: Read + Write + Seek>(
+    file: &mut W,
+    record: &Record,
+    block_size: i64,
+) -> io::Result<u64> {
+    let format = BlockFormat {
+        block_size,
+        ..BlockFormat::default()
+    };
+    format.check()?;
+    let file_len = file.seek(SeekFrom::End(0))?;
+    let offset = file_len + pad_for_record(file, record, format, file_len)?;
     record.write(file)?;
-    Ok(())
+    Ok(offset)
+}
+
+/// Pads the rest of the current block if the record doesn't fit in it,
+/// returning the number of padding bytes written after `file_len`.
+fn pad_for_record<W: Read + Write + Seek>(
+    file: &mut W,
+    record: &Record,
+    format: BlockFormat,
+    file_len: u64,
+) -> io::Result<u64> {
+    if (HEADER_SIZE + record.payload.len()) as u64 > format.space_remaining(file_len) {
+        return pad_block(file, format, file_len);
+    }
+    Ok(0)
+}
+
+/// Pads the rest of the block at the end of the file, returning
+/// the number of padding bytes written after `file_len`.
+///
+/// With block checksums, padding seals the block by writing the CRC
+/// of the block's contents into its trailer.
+fn pad_block<W: Read + Write + Seek>(
+    file: &mut W,
+    format: BlockFormat,
+    file_len: u64,
+) -> io::Result<u64> {
+    let block_size = format.block_size as u64;
+    let capacity = format.capacity() as u64;
+    let curr_block_len = file_len % block_size;
+    if curr_block_len > capacity {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Last block overlaps the block trailer",
+        ));
+    }
+    let mut block = vec![0; block_size as usize];
+    if format.checksums && curr_block_len > 0 {
+        file.seek(SeekFrom::Start(file_len - curr_block_len))?;
+        file.read_exact(&mut block[..curr_block_len as usize])?;
+        file.seek(SeekFrom::Start(file_len))?;
+        let crc = crc32::checksum_ieee(&block[..capacity as usize]);
+        (&mut block[capacity as usize..]).write_u32::<BigEndian>(crc)?;
+    }
+    file.write_all(&block[curr_block_len as usize..])?;
+    Ok(block_size - curr_block_len)
 }
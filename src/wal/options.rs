@@ -0,0 +1,215 @@
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::wal::iterator::OnCorruption;
+use crate::wal::metrics::MetricsSink;
+use crate::wal::{CheckpointPolicy, Compression, LogOptions, RecoveryProgress, SyncPolicy};
+
+/// Builder for the options a log is opened with, returned by
+/// `RedoLog::options` and `UndoLog::options`.
+///
+/// Options left unset keep their values from `LogOptions::default`. The
+/// options are validated when the log is opened, failing with an
+/// `InvalidInput` error for the first option that isn't valid.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::collections::HashMap;
+/// use std::io;
+/// use disk_utils::wal::redo_log::RedoLog;
+/// use disk_utils::wal::{LogData, LogStore, SyncPolicy};
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Data;
+///
+/// impl LogData for Data {
+///     type Key = i32;
+///     type Value = String;
+/// }
+///
+/// struct Store(HashMap<i32, String>);
+///
+/// impl LogStore<Data> for Store {
+///     fn get(&self, key: &i32) -> Option<String> {
+///         self.0.get(key).cloned()
+///     }
+///     fn remove(&mut self, key: &i32) {
+///         self.0.remove(key);
+///     }
+///     fn update(&mut self, key: i32, val: String) {
+///         self.0.insert(key, val);
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+///     fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// fn main() {
+///     let path = "./files/log_builder_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     # let _ = std::fs::remove_file(path);
+///     let mut log = RedoLog::options()
+///         .sync(SyncPolicy::OnCommit)
+///         .max_record_size(8192)
+///         .checkpoint_every_commits(100)
+///         .open(path, Store(HashMap::new()))
+///         .unwrap();
+///     let tid = log.start();
+///     log.write(tid, 1, "value".to_string()).unwrap();
+///     log.commit(tid).unwrap();
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
+pub struct LogBuilder<Log> {
+    pub(crate) options: LogOptions,
+    _log: PhantomData<fn() -> Log>,
+}
+
+impl<Log> LogBuilder<Log> {
+    pub(crate) fn new() -> LogBuilder<Log> {
+        LogBuilder {
+            options: LogOptions::default(),
+            _log: PhantomData,
+        }
+    }
+
+    /// Sets when the log's file is synced to disk.
+    pub fn sync(mut self, sync: SyncPolicy) -> LogBuilder<Log> {
+        self.options.sync = sync;
+        self
+    }
+
+    /// Sets when the log checkpoints itself.
+    pub fn checkpoint(mut self, checkpoint: CheckpointPolicy) -> LogBuilder<Log> {
+        self.options.checkpoint = checkpoint;
+        self
+    }
+
+    /// Checkpoints the log once this many transactions have committed
+    /// since the last checkpoint.
+    pub fn checkpoint_every_commits(self, commits: u32) -> LogBuilder<Log> {
+        self.checkpoint(CheckpointPolicy::EveryNCommits(commits))
+    }
+
+    /// Checkpoints the log once this many bytes have been flushed
+    /// to it since the last checkpoint.
+    pub fn checkpoint_every_bytes(self, bytes: u64) -> LogBuilder<Log> {
+        self.checkpoint(CheckpointPolicy::EveryNBytes(bytes))
+    }
+
+    /// Sets the largest payload of the records entries are split into.
+    /// It must be at least 1 and fit in a block.
+    pub fn max_record_size(mut self, max_record_size: usize) -> LogBuilder<Log> {
+        self.options.max_record_size = Some(max_record_size);
+        self
+    }
+
+    /// Flushes the entries held in memory to the log once they take up
+    /// this many bytes, instead of holding them until a transaction finishes.
+    pub fn max_pending_bytes(mut self, bytes: u64) -> LogBuilder<Log> {
+        self.options.max_pending_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets the size of the blocks records are packed into. It must be a
+    /// power of two and match the block size the log was written with.
+    pub fn block_size(mut self, block_size: i64) -> LogBuilder<Log> {
+        self.options.block_size = block_size;
+        self
+    }
+
+    /// Sets whether each block ends with a CRC over the whole block.
+    pub fn block_checksums(mut self, block_checksums: bool) -> LogBuilder<Log> {
+        self.options.block_checksums = block_checksums;
+        self
+    }
+
+    /// Sets whether closing the log checkpoints it first.
+    pub fn checkpoint_on_close(mut self, checkpoint: bool) -> LogBuilder<Log> {
+        self.options.checkpoint_on_close = checkpoint;
+        self
+    }
+
+    /// Sets the compression applied to entries written to the log.
+    pub fn compression(mut self, compression: Compression) -> LogBuilder<Log> {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets what recovery does when it finds a corrupted record.
+    pub fn on_corruption(mut self, on_corruption: OnCorruption) -> LogBuilder<Log> {
+        self.options.on_corruption = on_corruption;
+        self
+    }
+
+    /// Sets whether a log written without a file header can be opened.
+    pub fn allow_headerless(mut self, allow_headerless: bool) -> LogBuilder<Log> {
+        self.options.allow_headerless = allow_headerless;
+        self
+    }
+
+    /// Sets the number of blocks the log's file is grown by at a time.
+    pub fn preallocate_blocks(mut self, blocks: u64) -> LogBuilder<Log> {
+        self.options.preallocate_blocks = blocks;
+        self
+    }
+
+    /// Sets the size a segment of a log kept in a directory grows to
+    /// before the next one is started. It must be at least the block size.
+    pub fn segment_size(mut self, size: u64) -> LogBuilder<Log> {
+        self.options.segment_size = size;
+        self
+    }
+
+    /// Sets the number of blocks recovery reads at a time. It must be at least 1.
+    pub fn readahead_blocks(mut self, blocks: usize) -> LogBuilder<Log> {
+        self.options.readahead_blocks = blocks;
+        self
+    }
+
+    /// Sets whether recovery reads the log through a memory mapping.
+    #[cfg(feature = "mmap")]
+    pub fn mmap(mut self, mmap: bool) -> LogBuilder<Log> {
+        self.options.mmap = mmap;
+        self
+    }
+
+    /// Calls the callback with the fraction of recovery that is done.
+    pub fn recovery_progress<F>(mut self, callback: F) -> LogBuilder<Log>
+    where
+        F: Fn(f64) + Send + Sync + 'static,
+    {
+        self.options.recovery_progress = Some(RecoveryProgress::new(callback));
+        self
+    }
+
+    /// Sends the events the log counts in its metrics to the sink.
+    pub fn metrics_sink(mut self, sink: Arc<dyn MetricsSink>) -> LogBuilder<Log> {
+        self.options.metrics_sink = Some(sink);
+        self
+    }
+
+    /// Returns the options set so far.
+    pub fn log_options(&self) -> &LogOptions {
+        &self.options
+    }
+
+    /// Validates the options and returns them.
+    pub fn build(self) -> io::Result<LogOptions> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+impl<Log> fmt::Debug for LogBuilder<Log> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("LogBuilder").field(&self.options).finish()
+    }
+}
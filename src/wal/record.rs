@@ -1,13 +1,25 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crc::crc32;
 
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
-use std::io::{Cursor, Read, Write};
+use std::io::{IoSlice, Read, Write};
+use std::iter;
+use std::ops::{Deref, Range};
+use std::result;
+use std::sync::Arc;
 
 use enum_primitive::FromPrimitive;
 
+#[cfg(feature = "mmap")]
+use crate::wal::mmap::Mapping;
+
 enum_from_primitive! {
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum RecordType {
     Zero = 1,
     Full = 2,
@@ -20,11 +32,246 @@ pub enum RecordType {
 
 /// 32KB Block size.
 pub const BLOCK_SIZE: i64 = 32768;
-/// 7B Header size for record.
-pub const HEADER_SIZE: usize = 7;
+/// Checks that the block size is a power of two large enough to
+/// hold a record header and at least one byte of payload.
+pub fn check_block_size(block_size: i64) -> io::Result<()> {
+    let min_size = (HEADER_SIZE + BLOCK_TRAILER_SIZE + 1) as i64;
+    if block_size <= min_size || block_size.count_ones() != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Block size {} must be a power of two larger than {}",
+                block_size, min_size
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Largest payload of a record in a block of any size, limited so the
+/// size of the record along with its header fits in a u16.
+pub const MAX_RECORD_SIZE: usize = u16::MAX as usize - HEADER_SIZE;
+
+/// Returns an error if records can't be split to hold at most
+/// `max_record_size` bytes each.
+pub fn check_max_record_size(max_record_size: usize) -> io::Result<()> {
+    if max_record_size == 0 || max_record_size > MAX_RECORD_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Max record size {} must be between 1 and {}",
+                max_record_size, MAX_RECORD_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// 4B CRC trailer at the end of blocks written with block checksums.
+pub const BLOCK_TRAILER_SIZE: usize = 4;
+
+/// Layout of the blocks records are packed into.
+///
+/// Reading a log must use the same format the log was written with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockFormat {
+    pub block_size: i64,
+    /// Whether the last `BLOCK_TRAILER_SIZE` bytes of each block are
+    /// reserved for a CRC over the rest of the block. The CRC is written
+    /// when the block is sealed by padding it, so the partially filled
+    /// block at the end of the log has no checksum yet.
+    pub checksums: bool,
+}
+
+impl Default for BlockFormat {
+    fn default() -> BlockFormat {
+        BlockFormat {
+            block_size: BLOCK_SIZE,
+            checksums: false,
+        }
+    }
+}
+
+impl BlockFormat {
+    pub fn check(&self) -> io::Result<()> {
+        check_block_size(self.block_size)
+    }
+
+    /// Returns the number of bytes in a block available for records.
+    pub fn capacity(&self) -> usize {
+        if self.checksums {
+            self.block_size as usize - BLOCK_TRAILER_SIZE
+        } else {
+            self.block_size as usize
+        }
+    }
+
+    /// Returns the largest payload of a record that fits in an empty block,
+    /// limited to the largest record size `split_bytes_into_records` accepts.
+    pub fn max_payload_size(&self) -> usize {
+        cmp::min(self.capacity() - HEADER_SIZE, MAX_RECORD_SIZE)
+    }
+
+    /// Returns the number of bytes left for records in the
+    /// block that a file of the given length ends in.
+    ///
+    /// Returns 0 if the file ends inside the block trailer.
+    pub fn space_remaining(&self, file_len: u64) -> u64 {
+        let block_len = file_len % self.block_size as u64;
+        (self.capacity() as u64).saturating_sub(block_len)
+    }
+}
+
+/// Largest payload of a record that fits in an empty block of `BLOCK_SIZE`.
+pub const MAX_PAYLOAD_SIZE: usize = BLOCK_SIZE as usize - HEADER_SIZE;
+
+/// Returns the number of records `split_bytes_into_records` splits
+/// `len` bytes into, given the maximum payload size of a record.
+///
+/// Empty entries still take up a single record.
+pub fn records_needed(len: usize, max_record_size: usize) -> usize {
+    cmp::max(len.div_ceil(max_record_size), 1)
+}
+
+/// Returns the number of bytes left in the block of `BLOCK_SIZE`
+/// that a file of the given length ends in.
+pub fn space_remaining_in_block(file_len: u64) -> u64 {
+    BlockFormat::default().space_remaining(file_len)
+}
+
+/// 15B Header size for records written with the current format version.
+pub const HEADER_SIZE: usize = 15;
+/// 7B Header size for records written before LSNs were added.
+pub const LEGACY_HEADER_SIZE: usize = 7;
+
+/// Version of the record format written by `Record::write`.
+///
+/// The version is stored in the high nibble of the type byte. Version 0
+/// records predate versioning and only checksum their payload. Version 1
+/// records checksum the record type and size as well. Version 2 records
+/// add a u64 log sequence number to the header.
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Version of records written before the format was versioned.
+const LEGACY_VERSION: u8 = 0;
+/// Version of records with a header checksum but no LSN.
+const HEADER_CRC_VERSION: u8 = 1;
+const VERSION_SHIFT: u8 = 4;
+const RECORD_TYPE_MASK: u8 = 0x07;
+
+/// Set in the type byte of records belonging to a compressed entry.
+pub const COMPRESSED_FLAG: u8 = 0x08;
+
+/// Reserved type byte used to pad the rest of a block.
+/// Record types start at 1 so a record can never begin with this byte.
+pub const PADDING_BYTE: u8 = 0;
+
+/// Errors specific to decoding records, wrapped in the `io::Error`
+/// returned by `Record::read`.
+///
+/// Truncation errors distinguish a log that ends partway through a record,
+/// such as after a torn write, from records that were damaged in place.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordError {
+    /// The reader had no bytes left, so no record was started.
+    Eof,
+    /// The reader ended partway through the record header.
+    IncompleteHeader { consumed: usize },
+    /// The reader ended partway through the record payload.
+    IncompletePayload { size: u16, consumed: usize },
+    /// The type byte doesn't hold a valid record type.
+    BadType(u8),
+    /// The checksum doesn't match the record's contents. `expected` is the
+    /// checksum stored in the header and `actual` is computed from the contents.
+    BadChecksum {
+        consumed: usize,
+        expected: u32,
+        actual: u32,
+    },
+    /// The block's checksum trailer doesn't match the block's contents.
+    BadBlockChecksum { expected: u32, actual: u32 },
+    /// Padding at the end of a block is followed by non-padding bytes.
+    InvalidPadding,
+    /// The record was written with a newer format than this crate reads.
+    UnknownVersion(u8),
+    /// The size in the header claims more bytes than are left to read.
+    SizeTooLarge { size: u16, remaining: usize },
+}
+
+impl RecordError {
+    /// Returns the record error wrapped in an error returned by `Record::read`.
+    pub fn from_io_error(err: &io::Error) -> Option<&RecordError> {
+        err.get_ref().and_then(|err| err.downcast_ref())
+    }
+
+    /// Returns true if the error was caused by running out of bytes
+    /// rather than by invalid bytes.
+    pub fn is_truncation(&self) -> bool {
+        matches!(
+            *self,
+            RecordError::Eof
+                | RecordError::IncompleteHeader { .. }
+                | RecordError::IncompletePayload { .. }
+        )
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordError::Eof => write!(f, "No record left to read"),
+            RecordError::IncompleteHeader { consumed } => {
+                write!(f, "Record header cut off after {} bytes", consumed)
+            }
+            RecordError::IncompletePayload { size, consumed } => write!(
+                f,
+                "Record with payload size {} cut off after {} bytes",
+                size, consumed
+            ),
+            RecordError::BadType(byte) => write!(f, "Invalid record type byte {}", byte),
+            RecordError::BadChecksum {
+                expected, actual, ..
+            } => write!(
+                f,
+                "CRC checksum failed, expected {:#010x} but found {:#010x}, possibly corrupted record data",
+                expected, actual
+            ),
+            RecordError::BadBlockChecksum { expected, actual } => write!(
+                f,
+                "Block checksum failed, expected {:#010x} but found {:#010x}",
+                expected, actual
+            ),
+            RecordError::InvalidPadding => write!(f, "Invalid block padding"),
+            RecordError::UnknownVersion(version) => {
+                write!(f, "Unknown record format version {}", version)
+            }
+            RecordError::SizeTooLarge { size, remaining } => write!(
+                f,
+                "Record size {} is larger than the {} bytes remaining",
+                size, remaining
+            ),
+        }
+    }
+}
+
+impl error::Error for RecordError {}
+
+impl From<RecordError> for io::Error {
+    fn from(err: RecordError) -> io::Error {
+        let kind = if err.is_truncation() {
+            io::ErrorKind::UnexpectedEof
+        } else {
+            io::ErrorKind::InvalidData
+        };
+        io::Error::new(kind, err)
+    }
+}
 
 /// A single entry of the write ahead log stored in blocks.
 ///
+/// With the `serde` feature, a record serializes as its metadata, without
+/// its payload.
+///
 /// # Examples
 ///
 /// ```
@@ -32,7 +279,7 @@ pub const HEADER_SIZE: usize = 7;
 /// use disk_utils::wal::record::{Record, RecordType};
 ///
 /// fn main() {
-///     let record = Record::new(RecordType::Full, vec![123; 12345]);
+///     let record = Record::new(RecordType::Full, vec![123; 12345]).unwrap();
 ///
 ///     // Write record into a byte buffer.
 ///     let mut bytes = Vec::new();
@@ -44,78 +291,482 @@ pub const HEADER_SIZE: usize = 7;
 /// }
 /// ```
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Record {
     pub crc: u32,
     pub size: u16,
     pub record_type: RecordType,
-    pub payload: Vec<u8>,
+    /// Whether the record is a fragment of a compressed entry.
+    pub compressed: bool,
+    /// Log sequence number assigned when the record was appended by a
+    /// `Writer`, or 0 for records that were never assigned one.
+    pub lsn: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub payload: Payload,
 }
 
 impl Record {
-    pub fn new(record_type: RecordType, payload: Vec<u8>) -> Record {
-        let crc = crc32::checksum_ieee(&payload[..]);
-        Record {
+    /// Creates a record holding the payload.
+    ///
+    /// Returns an error if the payload is too large for the u16 size field.
+    pub fn new(record_type: RecordType, payload: Vec<u8>) -> io::Result<Record> {
+        Record::with_payload(record_type, Payload::from(payload))
+    }
+
+    /// Creates a record like `new` from a payload that can share
+    /// its bytes with other payloads instead of owning a copy.
+    pub fn with_payload(record_type: RecordType, payload: Payload) -> io::Result<Record> {
+        if payload.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Record payload is too large",
+            ));
+        }
+
+        let size = payload.len() as u16;
+        let crc = header_crc(type_byte(record_type, false), size, Some(0), &payload[..]);
+        Ok(Record {
             crc,
-            size: payload.len() as u16,
+            size,
             record_type,
+            compressed: false,
+            lsn: 0,
             payload,
-        }
+        })
     }
 
+    /// Reads a record from the reader.
+    ///
+    /// Errors caused by invalid or missing bytes wrap a `RecordError`,
+    /// which can be retrieved with `RecordError::from_io_error`.
     pub fn read<R: Read>(reader: &mut R) -> io::Result<Record> {
+        Record::read_with_limit(reader, usize::MAX)
+    }
+
+    /// Reads a record that must fit within `limit` bytes, header included.
+    ///
+    /// The size field is checked against the limit before the payload is
+    /// allocated, so a corrupted size can't make the read allocate more
+    /// than the bytes actually remaining.
+    pub fn read_with_limit<R: Read>(reader: &mut R, limit: usize) -> io::Result<Record> {
         let mut buf = [0; HEADER_SIZE];
-        reader.read_exact(&mut buf)?;
+        if read_fully(reader, &mut buf[..1])? == 0 {
+            return Err(RecordError::Eof.into());
+        }
+        let header_size = header_size(buf[0])?;
+        let consumed = 1 + read_fully(reader, &mut buf[1..header_size])?;
+        if consumed < header_size {
+            return Err(RecordError::IncompleteHeader { consumed }.into());
+        }
+        let header = Header::decode(&buf[..header_size], limit)?;
 
-        let record_type = match RecordType::from_u8(buf[0]) {
-            Some(rt) => rt,
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid record type",
-                ))
-            }
-        };
+        // Read straight into the shared allocation to avoid copying the payload.
+        let size = header.size;
+        let mut payload: Arc<[u8]> = iter::repeat_n(0, size as usize).collect();
+        let consumed = header_size + read_fully(reader, Arc::get_mut(&mut payload).unwrap())?;
+        if consumed < header_size + size as usize {
+            return Err(RecordError::IncompletePayload { size, consumed }.into());
+        }
+        header.check_crc(&payload, consumed)?;
 
-        let mut rdr = Cursor::new(buf[1..5].to_vec());
-        let crc = rdr.read_u32::<BigEndian>()?;
+        Ok(header.into_record(Payload::from(payload)))
+    }
 
-        rdr = Cursor::new(buf[5..7].to_vec());
-        let size = rdr.read_u16::<BigEndian>()?;
+    /// Parses a record from the start of the bytes without copying its payload,
+    /// returning a view of the record and the number of bytes it took up.
+    pub fn parse(bytes: &[u8]) -> io::Result<(RecordView<'_>, usize)> {
+        Record::parse_with_limit(bytes, usize::MAX)
+    }
 
-        let mut payload = vec![0; size as usize];
-        reader.read_exact(&mut payload)?;
+    /// Parses a record like `parse` that must fit within `limit` bytes,
+    /// header included.
+    pub fn parse_with_limit(bytes: &[u8], limit: usize) -> io::Result<(RecordView<'_>, usize)> {
+        if bytes.is_empty() {
+            return Err(RecordError::Eof.into());
+        }
+        let header_size = header_size(bytes[0])?;
+        if bytes.len() < header_size {
+            let consumed = bytes.len();
+            return Err(RecordError::IncompleteHeader { consumed }.into());
+        }
+        let header = Header::decode(&bytes[..header_size], limit)?;
 
-        let payload_crc = crc32::checksum_ieee(&payload[..]);
-        if payload_crc != crc {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "CRC checksum failed, possibly corrupted record data",
-            ));
+        let size = header.size;
+        let len = header_size + size as usize;
+        if bytes.len() < len {
+            let consumed = bytes.len();
+            return Err(RecordError::IncompletePayload { size, consumed }.into());
         }
+        let payload = &bytes[header_size..len];
+        header.check_crc(payload, len)?;
 
-        Ok(Record {
-            crc,
+        let view = RecordView {
+            crc: header.crc,
             size,
-            record_type,
+            record_type: header.record_type,
+            compressed: header.compressed,
+            lsn: header.lsn.unwrap_or(0),
             payload,
-        })
+        };
+        Ok((view, len))
     }
 
+    /// Returns the length of the record starting with the header bytes,
+    /// header included, without reading or checking its payload.
+    pub fn parse_len(header: &[u8], limit: usize) -> io::Result<usize> {
+        if header.is_empty() {
+            return Err(RecordError::Eof.into());
+        }
+        let header_size = header_size(header[0])?;
+        if header.len() < header_size {
+            let consumed = header.len();
+            return Err(RecordError::IncompleteHeader { consumed }.into());
+        }
+        let header = Header::decode(&header[..header_size], limit)?;
+        Ok(header_size + header.size as usize)
+    }
+
+    /// Returns a copy of the record marked as part of a compressed entry.
+    pub fn into_compressed(mut self) -> Record {
+        self.compressed = true;
+        self.crc = self.current_crc(self.lsn);
+        self
+    }
+
+    /// Writes the record with a CRC covering the header and payload.
+    ///
+    /// The CRC is recomputed from the current fields so records read
+    /// from older logs are upgraded to the current format version.
+    /// The header and payload are written together with `write_vectored`
+    /// without copying the payload, and the writer is not flushed, so
+    /// callers decide when to flush.
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        let record_type = self.record_type as u8;
+        self.write_with_lsn(writer, self.lsn)
+    }
+
+    /// Writes the record like `write`, but with the given LSN in the header.
+    pub(crate) fn write_with_lsn<W: Write>(&self, writer: &mut W, lsn: u64) -> io::Result<()> {
+        let mut header = [0; HEADER_SIZE];
+        header[0] = type_byte(self.record_type, self.compressed);
+        {
+            let mut rest = &mut header[1..];
+            rest.write_u32::<BigEndian>(self.current_crc(lsn))?;
+            rest.write_u16::<BigEndian>(self.size)?;
+            rest.write_u64::<BigEndian>(lsn)?;
+        }
+        write_all_vectored(writer, &header, &self.payload)
+    }
+
+    /// Computes the CRC of the record in the current format version.
+    fn current_crc(&self, lsn: u64) -> u32 {
+        let type_byte = type_byte(self.record_type, self.compressed);
+        header_crc(type_byte, self.size, Some(lsn), &self.payload[..])
+    }
+}
+
+/// A record parsed by `Record::parse` that borrows its payload.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordView<'a> {
+    pub crc: u32,
+    pub size: u16,
+    pub record_type: RecordType,
+    pub compressed: bool,
+    pub lsn: u64,
+    pub payload: &'a [u8],
+}
+
+impl<'a> RecordView<'a> {
+    /// Returns an owned record with a copy of the payload.
+    pub fn to_record(self) -> Record {
+        self.to_record_with(Payload::from(self.payload))
+    }
+
+    /// Returns an owned record with the given payload, which
+    /// must hold the same bytes as the view's payload.
+    pub(crate) fn to_record_with(self, payload: Payload) -> Record {
+        Record {
+            crc: self.crc,
+            size: self.size,
+            record_type: self.record_type,
+            compressed: self.compressed,
+            lsn: self.lsn,
+            payload,
+        }
+    }
+}
 
-        let mut wtr = Vec::new();
-        wtr.write_u32::<BigEndian>(self.crc)?;
-        let (crc1, crc2, crc3, crc4) = (wtr[0], wtr[1], wtr[2], wtr[3]);
+/// Returns the size of the header of a record starting with the type byte,
+/// checking that the type byte is valid.
+fn header_size(type_byte: u8) -> result::Result<usize, RecordError> {
+    let version = type_byte >> VERSION_SHIFT;
+    let header_size = match version {
+        LEGACY_VERSION | HEADER_CRC_VERSION => LEGACY_HEADER_SIZE,
+        FORMAT_VERSION => HEADER_SIZE,
+        _ => return Err(RecordError::UnknownVersion(version)),
+    };
+    let compressed = type_byte & COMPRESSED_FLAG != 0;
+    if compressed && version == LEGACY_VERSION {
+        return Err(RecordError::BadType(type_byte));
+    }
+    if RecordType::from_u8(type_byte & RECORD_TYPE_MASK).is_none() {
+        return Err(RecordError::BadType(type_byte));
+    }
+    Ok(header_size)
+}
 
-        wtr = Vec::new();
-        wtr.write_u16::<BigEndian>(self.size)?;
-        let (size1, size2) = (wtr[0], wtr[1]);
+/// Decoded record header.
+struct Header {
+    type_byte: u8,
+    crc: u32,
+    size: u16,
+    record_type: RecordType,
+    compressed: bool,
+    lsn: Option<u64>,
+}
 
-        writer.write_all(&[record_type, crc1, crc2, crc3, crc4, size1, size2])?;
-        writer.write_all(&self.payload)?;
-        writer.flush()?;
+impl Header {
+    /// Decodes a complete header whose type byte was checked by `header_size`,
+    /// checking that the record fits within `limit` bytes.
+    fn decode(mut buf: &[u8], limit: usize) -> io::Result<Header> {
+        let header_size = buf.len();
+        let type_byte = buf.read_u8()?;
+        let crc = buf.read_u32::<BigEndian>()?;
+        let size = buf.read_u16::<BigEndian>()?;
+        let lsn = if type_byte >> VERSION_SHIFT == FORMAT_VERSION {
+            Some(buf.read_u64::<BigEndian>()?)
+        } else {
+            None
+        };
 
+        if header_size + size as usize > limit {
+            return Err(RecordError::SizeTooLarge {
+                size,
+                remaining: limit.saturating_sub(header_size),
+            }
+            .into());
+        }
+
+        Ok(Header {
+            type_byte,
+            crc,
+            size,
+            record_type: RecordType::from_u8(type_byte & RECORD_TYPE_MASK).unwrap(),
+            compressed: type_byte & COMPRESSED_FLAG != 0,
+            lsn,
+        })
+    }
+
+    fn check_crc(&self, payload: &[u8], consumed: usize) -> io::Result<()> {
+        let actual = if self.type_byte >> VERSION_SHIFT == LEGACY_VERSION {
+            crc32::checksum_ieee(payload)
+        } else {
+            header_crc(self.type_byte, self.size, self.lsn, payload)
+        };
+        if actual != self.crc {
+            return Err(RecordError::BadChecksum {
+                consumed,
+                expected: self.crc,
+                actual,
+            }
+            .into());
+        }
         Ok(())
     }
+
+    fn into_record(self, payload: Payload) -> Record {
+        Record {
+            crc: self.crc,
+            size: self.size,
+            record_type: self.record_type,
+            compressed: self.compressed,
+            lsn: self.lsn.unwrap_or(0),
+            payload,
+        }
+    }
+}
+
+/// Reads until the buffer is full or the reader has no bytes left,
+/// returning the number of bytes read.
+fn read_fully<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut bytes_read = 0;
+    while bytes_read < buf.len() {
+        match reader.read(&mut buf[bytes_read..]) {
+            Ok(0) => break,
+            Ok(n) => bytes_read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(bytes_read)
+}
+
+/// Payload bytes of a record.
+///
+/// The bytes are reference counted, so cloning a record shares its payload
+/// instead of copying it. Records read by a `WalIterator` share the buffer
+/// of the block they were read from, or the mapping of the log when it is
+/// read through an `MmapBlockSource`.
+#[derive(Clone)]
+pub struct Payload {
+    bytes: Bytes,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone)]
+enum Bytes {
+    Shared(Arc<[u8]>),
+    #[cfg(feature = "mmap")]
+    Mapped(Arc<Mapping>),
+}
+
+impl Payload {
+    /// Returns a payload of the whole mapping.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn mapped(mapping: Arc<Mapping>) -> Payload {
+        let end = mapping.len();
+        Payload {
+            bytes: Bytes::Mapped(mapping),
+            start: 0,
+            end,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match self.bytes {
+            Bytes::Shared(ref bytes) => &bytes[self.start..self.end],
+            #[cfg(feature = "mmap")]
+            Bytes::Mapped(ref mapping) => &mapping[self.start..self.end],
+        }
+    }
+
+    /// Returns a payload sharing a subrange of these bytes.
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> Payload {
+        assert!(range.start <= range.end && range.end <= self.len());
+        Payload {
+            bytes: self.bytes.clone(),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Payload {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl From<Arc<[u8]>> for Payload {
+    fn from(bytes: Arc<[u8]>) -> Payload {
+        let end = bytes.len();
+        Payload {
+            bytes: Bytes::Shared(bytes),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Payload {
+        Payload::from(Arc::<[u8]>::from(bytes))
+    }
+}
+
+impl<'a> From<&'a [u8]> for Payload {
+    fn from(bytes: &'a [u8]) -> Payload {
+        Payload::from(Arc::<[u8]>::from(bytes))
+    }
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Payload) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for Payload {}
+
+impl Hash for Payload {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl PartialEq<Vec<u8>> for Payload {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        self.as_slice() == &other[..]
+    }
+}
+
+impl PartialEq<[u8]> for Payload {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_slice().fmt(f)
+    }
+}
+
+/// Writes both buffers like `write_all`, submitting them together with
+/// `write_vectored` until everything is written.
+///
+/// Writers that don't support vectored writes write only the first
+/// non-empty buffer in each call, which writes the buffers one after
+/// the other like calling `write_all` on each.
+fn write_all_vectored<W: Write>(
+    writer: &mut W,
+    mut first: &[u8],
+    mut second: &[u8],
+) -> io::Result<()> {
+    while !first.is_empty() || !second.is_empty() {
+        let bufs = [IoSlice::new(first), IoSlice::new(second)];
+        match writer.write_vectored(&bufs) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ))
+            }
+            Ok(n) => {
+                let from_first = cmp::min(n, first.len());
+                first = &first[from_first..];
+                second = &second[n - from_first..];
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the type byte for a record written with the current format version.
+fn type_byte(record_type: RecordType, compressed: bool) -> u8 {
+    let flags = if compressed { COMPRESSED_FLAG } else { 0 };
+    FORMAT_VERSION << VERSION_SHIFT | flags | record_type as u8
+}
+
+/// Computes the CRC over the type byte, the size, the LSN if
+/// the format version has one, and the payload.
+fn header_crc(type_byte: u8, size: u16, lsn: Option<u64>, payload: &[u8]) -> u32 {
+    let header = [type_byte, (size >> 8) as u8, size as u8];
+    let mut crc = crc32::update(0, &crc32::IEEE_TABLE, &header);
+    if let Some(lsn) = lsn {
+        crc = crc32::update(crc, &crc32::IEEE_TABLE, &lsn.to_be_bytes());
+    }
+    crc32::update(crc, &crc32::IEEE_TABLE, payload)
 }
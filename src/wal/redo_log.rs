@@ -1,25 +1,65 @@
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::fs::{File, OpenOptions};
+use std::cmp;
+use std::collections::{vec_deque, HashMap, HashSet, VecDeque};
+use std::io;
+use std::mem;
 use std::path::Path;
+use std::time::Instant;
 
-use super::super::Serializable;
-
-use crate::wal::entries::{ChangeEntry, Checkpoint, SingleLogEntry, Transaction};
-use crate::wal::iterator::{ReadDirection, WalIterator};
+use crate::wal::backend::{FileBackend, LogBackend};
+use crate::wal::commit::{CommitTicket, PendingCommits};
+use crate::wal::compact::compact_log;
+use crate::wal::entries::{
+    ChangeEntry, Checkpoint, DeleteEntry, MultiChangeEntry, SingleLogEntry, Transaction,
+};
+use crate::wal::iterator::{ReadDirection, Stats, WalIterator};
+use crate::wal::manager::Wal;
+use crate::wal::metrics::{MetricEvent, Metrics, MetricsRecorder};
+use crate::wal::options::LogBuilder;
+use crate::wal::sink::write_serializable_streaming;
+use crate::wal::snapshot::{Snapshot, SnapshotSource};
+use crate::wal::tail::TailIterator;
+use crate::wal::transaction::{FinishedTids, TransactionGuard, TransactionLog};
+use crate::wal::truncate::{checkpoint_start, rewrite_log};
+use crate::wal::writer::Writer;
 use crate::wal::{
-    append_to_file, read_serializable, read_serializable_backwards, split_bytes_into_records,
-    LogData, LogStore, RecoverState, Result,
+    reached_recovery_start, recover_entry, truncate_torn_entry, Compression, LogData, LogError,
+    LogOptions, LogSource, LogStore, Outcome, RecoverState, Result, SyncPoint,
 };
+use crate::Serializable;
 
-const MAX_RECORD_SIZE: usize = 1024;
+/// Builder for the options a `RedoLog` is opened with.
+pub type RedoLogOptions<Data, Store> = LogBuilder<RedoLog<Data, Store>>;
 
-pub struct RedoLog<Data: LogData, Store: LogStore<Data>> {
-    file: File,
+pub struct RedoLog<Data: LogData, Store: LogStore<Data>, Backend: LogBackend = FileBackend> {
+    writer: Writer<Backend>,
     mem_log: VecDeque<SingleLogEntry<Data>>,
+    /// Serialized size of the entries in `mem_log`.
+    pending_bytes: u64,
     last_tid: u64,
     changes: Changes<Data>,
     active_tids: HashSet<u64>,
+    /// Transactions prepared with `prepare` that haven't been resolved.
+    prepared_tids: HashSet<u64>,
+    /// Ids of the finished transactions, so `start_with_tid` doesn't reuse them.
+    finished_tids: FinishedTids,
+    /// Prepared transactions found by recovery, whose changes haven't
+    /// been applied to the store.
+    in_doubt_tids: HashSet<u64>,
     store: Store,
+    options: LogOptions,
+    last_flushed_lsn: Option<u64>,
+    last_flushed_offset: Option<u64>,
+    recovery_stats: Stats,
+    /// Transactions committed since the last checkpoint began.
+    commits_since_checkpoint: u32,
+    /// Bytes flushed to the log since the last checkpoint began.
+    bytes_since_checkpoint: u64,
+    /// Transactions committed with `commit_async` that haven't been flushed.
+    pending_commits: PendingCommits,
+    metrics: MetricsRecorder,
+    /// Whether the log recovered and hasn't been closed, so dropping it
+    /// flushes the entries held in memory.
+    open: bool,
 }
 
 impl<Data, Store> RedoLog<Data, Store>
@@ -27,118 +67,1008 @@ where
     Data: LogData,
     Store: LogStore<Data>,
 {
+    /// Returns a builder for opening a log with options other than the defaults.
+    pub fn options() -> RedoLogOptions<Data, Store> {
+        LogBuilder::new()
+    }
+
     pub fn new<P: AsRef<Path> + ?Sized>(path: &P, store: Store) -> Result<RedoLog<Data, Store>> {
-        let file = OpenOptions::new()
-            .read(true)
-            .append(true)
-            .create(true)
-            .open(path)?;
+        RedoLog::new_with_options(path, store, LogOptions::default())
+    }
+
+    /// Opens the log at the path with the options, recovering it into
+    /// the store. The log's file stays locked until the log is closed or
+    /// dropped, and returns `LogError::AlreadyLocked` if another log
+    /// already has it open.
+    pub fn new_with_options<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<RedoLog<Data, Store>> {
+        options.validate()?;
+        let writer = options.writer_for(options.open_log_file(path)?)?;
+        RedoLog::from_writer(writer, store, options, None)
+    }
+
+    /// Opens the log at `log_path` like `new`, loading the snapshot at
+    /// `snapshot_path` into the store first, so recovery only replays the
+    /// transactions committed after the snapshot was taken. The log is
+    /// recovered without a snapshot if there is no file at `snapshot_path`.
+    ///
+    /// The store should hold nothing that isn't in the snapshot, since the
+    /// snapshot's keys are added to it without removing any other keys.
+    pub fn open_with_snapshot<P1, P2>(
+        snapshot_path: &P1,
+        log_path: &P2,
+        store: Store,
+    ) -> Result<RedoLog<Data, Store>>
+    where
+        P1: AsRef<Path> + ?Sized,
+        P2: AsRef<Path> + ?Sized,
+    {
+        RedoLog::options().open_with_snapshot(snapshot_path, log_path, store)
+    }
+}
+
+impl<Data, Store> RedoLog<Data, Store, Wal>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    /// Opens the log kept in the directory managed by the `Wal`, recovering
+    /// it into the store from the last checkpoint recorded in its manifest.
+    pub fn with_wal(
+        wal: Wal,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<RedoLog<Data, Store, Wal>> {
+        RedoLog::with_backend(wal, store, options)
+    }
+}
+
+impl<Data, Store, Backend> RedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    /// Opens the log kept in the backend with the options, recovering
+    /// it into the store. An empty backend starts a new log.
+    pub fn with_backend(
+        backend: Backend,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<RedoLog<Data, Store, Backend>> {
+        options.validate()?;
+        let writer = options.writer_for(backend)?;
+        RedoLog::from_writer(writer, store, options, None)
+    }
+
+    fn from_writer(
+        writer: Writer<Backend>,
+        store: Store,
+        options: LogOptions,
+        snapshot_path: Option<&Path>,
+    ) -> Result<RedoLog<Data, Store, Backend>> {
+        let metrics = MetricsRecorder::new(options.metrics_sink.clone());
         let mut log = RedoLog {
-            file,
+            writer,
             mem_log: VecDeque::new(),
+            pending_bytes: 0,
             last_tid: 0,
             changes: Changes::new(),
             active_tids: HashSet::new(),
+            prepared_tids: HashSet::new(),
+            finished_tids: FinishedTids::default(),
+            in_doubt_tids: HashSet::new(),
             store,
+            options,
+            last_flushed_lsn: None,
+            last_flushed_offset: None,
+            recovery_stats: Stats::default(),
+            commits_since_checkpoint: 0,
+            bytes_since_checkpoint: 0,
+            pending_commits: PendingCommits::default(),
+            metrics,
+            open: false,
         };
-        log.recover()?;
+        let began = Instant::now();
+        let snapshot = match snapshot_path {
+            Some(path) => log.load_snapshot(path)?,
+            None => None,
+        };
+        log.recover(snapshot)?;
+        log.metrics.record(MetricEvent::Recovered {
+            duration: began.elapsed(),
+        });
+        log.open = true;
         Ok(log)
     }
 
-    pub fn entries(&self) -> Vec<SingleLogEntry<Data>> {
-        self.mem_log.clone().into_iter().collect()
+    /// Returns the entries that haven't been flushed to the log yet.
+    pub fn entries(&self) -> vec_deque::Iter<'_, SingleLogEntry<Data>> {
+        self.mem_log.iter()
+    }
+
+    /// Returns the ids of the transactions that haven't finished, in order.
+    pub fn active_transactions(&self) -> Vec<u64> {
+        let mut tids: Vec<_> = self.active_tids.iter().cloned().collect();
+        tids.sort_unstable();
+        tids
+    }
+
+    /// Returns the ids of the prepared transactions recovery found that
+    /// haven't been resolved with `resolve_prepared`, in order.
+    pub fn in_doubt_transactions(&self) -> Vec<u64> {
+        let mut tids: Vec<_> = self.in_doubt_tids.iter().cloned().collect();
+        tids.sort_unstable();
+        tids
+    }
+
+    /// Returns the id of the last transaction started.
+    pub fn last_tid(&self) -> u64 {
+        self.last_tid
+    }
+
+    /// Returns whether the transaction finished in the part of the log
+    /// recovery would read.
+    pub(crate) fn has_finished(&self, tid: u64) -> bool {
+        self.finished_tids.contains(tid)
+    }
+
+    /// Returns the number of entries that haven't been flushed to the log yet.
+    pub fn pending_entries(&self) -> usize {
+        self.mem_log.len()
+    }
+
+    /// Returns the serialized size of the entries that haven't been
+    /// flushed to the log yet.
+    pub fn pending_bytes(&self) -> u64 {
+        self.pending_bytes
+    }
+
+    /// Closes the log, flushing the entries held in memory, checkpointing
+    /// the log if `LogOptions::checkpoint_on_close` is set and syncing
+    /// its file.
+    ///
+    /// Dropping the log also flushes the entries held in memory, but
+    /// ignores any error doing so. Closing the log is the only way to
+    /// know its entries reached the disk.
+    ///
+    /// The lock on the log's file is released once the readers returned
+    /// by `reader` and `subscribe` are dropped too.
+    pub fn close(mut self) -> Result<()> {
+        self.open = false;
+        if self.options.checkpoint_on_close {
+            self.checkpoint()?;
+        } else {
+            self.flush(SyncPoint::Flush)?;
+        }
+        self.writer.sync()?;
+        Ok(())
     }
 
     pub fn checkpoint(&mut self) -> Result<()> {
-        let transactions: Vec<_> = self.active_tids.clone().into_iter().collect();
+        self.commits_since_checkpoint = 0;
+        self.bytes_since_checkpoint = 0;
+        // Prepared transactions are still unfinished, so their entries
+        // are needed by recovery.
+        let transactions: Vec<_> = self
+            .active_tids
+            .union(&self.prepared_tids)
+            .cloned()
+            .collect();
         let entry = SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions.clone()));
 
         // Add begin checkpoint into the log.
-        self.mem_log.push_back(entry);
-        self.flush()?;
+        self.push_entry(entry)?;
+        self.flush(SyncPoint::Checkpoint)?;
+        self.finished_tids.checkpoint_began(&transactions);
+        self.metrics.record(MetricEvent::CheckpointBegun);
 
         // Ensure that all changes committed before the begin checkpoint are flushed to disk.
+        // They're only forgotten once all of them are flushed, so a failed
+        // checkpoint flushes them again next time.
         for (key, val) in self.changes.flush_changes() {
-            self.store.flush_change(key, val)?;
+            match val {
+                Some(val) => self.store.flush_change(key, val)?,
+                None => self.store.flush_remove(&key)?,
+            }
         }
+        self.changes.remove_committed();
 
         // Add end checkpoint to log and flush the log.
-        self.mem_log
-            .push_back(SingleLogEntry::Checkpoint(Checkpoint::End));
-        self.flush()?;
+        self.push_entry(SingleLogEntry::Checkpoint(Checkpoint::End))?;
+        self.flush(SyncPoint::Checkpoint)?;
+        self.finished_tids.checkpoint_ended(&transactions);
+        if self.options.seal_checkpoints {
+            self.writer.pad_to_block_boundary()?;
+        }
+        self.record_checkpoint()?;
 
         Ok(())
     }
 
+    /// Records where recovery starts after the checkpoint that just ended
+    /// in backends that track it, once the log is synced.
+    fn record_checkpoint(&mut self) -> Result<()> {
+        if !self.writer.file().tracks_checkpoints() {
+            return Ok(());
+        }
+        self.writer.sync()?;
+        if let Some(start) = checkpoint_start::<Data, _>(&mut self.writer, &self.options, true)? {
+            self.writer.file_mut().checkpointed(start)?;
+        }
+        Ok(())
+    }
+
     pub fn start(&mut self) -> u64 {
         self.last_tid += 1;
         let entry = SingleLogEntry::Transaction(Transaction::Start(self.last_tid));
-        self.mem_log.push_back(entry);
+        // The size of a transaction entry is always known.
+        let _ = self.push_entry(entry);
         self.active_tids.insert(self.last_tid);
 
         self.last_tid
     }
 
-    pub fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) {
-        if self.active_tids.contains(&tid) {
-            let entry = SingleLogEntry::ChangeEntry(ChangeEntry {
-                tid,
-                key: key.clone(),
-                value: val.clone(),
-            });
+    /// Starts a transaction with an id chosen by the caller, like one
+    /// handed out by an external sequencer, instead of the next id after
+    /// `last_tid`. Transactions started with `start` afterwards get ids
+    /// after the largest id used.
+    ///
+    /// Returns `LogError::DuplicateTransaction` if the id is of a
+    /// transaction that is active, prepared, or finished in the part of the
+    /// log recovery would read.
+    pub fn start_with_tid(&mut self, tid: u64) -> Result<()> {
+        if self.active_tids.contains(&tid)
+            || self.prepared_tids.contains(&tid)
+            || self.finished_tids.contains(tid)
+        {
+            return Err(LogError::DuplicateTransaction(tid));
+        }
+        self.last_tid = cmp::max(self.last_tid, tid);
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Start(tid)))?;
+        self.active_tids.insert(tid);
+        Ok(())
+    }
 
-            self.changes.write(tid, key.clone(), val.clone());
+    /// Starts a transaction that is aborted if the returned guard is
+    /// dropped before it is committed.
+    pub fn transaction(&mut self) -> TransactionGuard<'_, Data, Self> {
+        let tid = self.start();
+        TransactionGuard::new(self, tid)
+    }
+
+    /// Logs the change of the key to the value by the transaction and
+    /// applies it to the store, unless store updates are deferred until
+    /// the transaction commits.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let entry = SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid,
+            key: key.clone(),
+            value: val.clone(),
+        });
+
+        self.push_entry(entry)?;
+        self.changes.write(tid, key.clone(), val.clone());
+        if !self.options.defer_store_updates {
             self.store.update(key, val);
-            self.mem_log.push_back(entry);
         }
+        self.spill()
+    }
+
+    /// Logs the changes of the keys to the values by the transaction as a
+    /// single entry and applies them like `write`, in order.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write_batch<I>(&mut self, tid: u64, changes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Data::Key, Data::Value)>,
+    {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let changes: Vec<_> = changes.into_iter().collect();
+        if !changes.is_empty() {
+            self.push_entry(SingleLogEntry::MultiChangeEntry(MultiChangeEntry {
+                tid,
+                changes: changes
+                    .iter()
+                    .map(|(key, val)| (key.clone(), Some(val.clone())))
+                    .collect(),
+            }))?;
+        }
+        for (key, val) in changes {
+            self.changes.write(tid, key.clone(), val.clone());
+            if !self.options.defer_store_updates {
+                self.store.update(key, val);
+            }
+        }
+        self.spill()
     }
 
+    /// Logs the deletion of the key by the transaction and removes it from
+    /// the store, unless store updates are deferred until the transaction
+    /// commits. Recovery deletes the key again if the transaction committed.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn delete(&mut self, tid: u64, key: Data::Key) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.push_entry(SingleLogEntry::DeleteEntry(DeleteEntry {
+            tid,
+            key: key.clone(),
+            value: None,
+        }))?;
+        self.changes.delete(tid, key.clone());
+        if !self.options.defer_store_updates {
+            self.store.remove(&key);
+        }
+        self.spill()
+    }
+
+    /// Returns the value of the key as seen by the transaction: the last
+    /// value the transaction wrote to it, or else the value in the store.
+    pub fn get(&self, tid: u64, key: &Data::Key) -> Option<Data::Value> {
+        match self.changes.get(tid, key) {
+            Some(val) => val.cloned(),
+            None => self.store.get(key),
+        }
+    }
+
+    /// Commits the transaction and flushes the log. The LSN of the commit
+    /// is then recorded in the store with `LogStore::set_applied_lsn`.
+    ///
+    /// If the log can't be flushed, the error is returned and the commit
+    /// is taken back: a commit that wasn't written leaves the transaction
+    /// active, and one that was written is followed by an abort, so
+    /// recovery doesn't replay it. Returns `LogError::UnknownTransaction`
+    /// if the transaction isn't active.
     pub fn commit(&mut self, tid: u64) -> Result<()> {
-        if self.active_tids.contains(&tid) {
-            let entry = SingleLogEntry::Transaction(Transaction::Commit(tid));
-            self.mem_log.push_back(entry);
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let entry = SingleLogEntry::Transaction(Transaction::Commit(tid));
+        self.push_entry(entry)?;
+
+        if let Err(err) = self.flush(SyncPoint::Commit) {
+            if !self.unqueue(|entry| is_commit(entry, tid)) {
+                self.active_tids.remove(&tid);
+                self.finished_tids.insert(tid);
+                self.abort_failed_commit(tid);
+            }
+            return Err(err);
+        }
 
-            self.flush()?;
+        self.active_tids.remove(&tid);
+        self.finished_tids.insert(tid);
+        self.changes.commit(tid);
+        if self.options.defer_store_updates {
+            for (key, val) in self.changes.transaction_changes(tid) {
+                apply_change(&mut self.store, key.clone(), val.cloned());
+            }
+        }
+        self.set_applied_lsn();
+        self.finish_commit()
+    }
 
-            self.active_tids.remove(&tid);
-            self.changes.commit(tid);
+    /// Commits the transaction without flushing the log, returning a ticket
+    /// that resolves once `flush_commits` makes the commit durable.
+    ///
+    /// The transaction can't be written to afterwards, and its changes are
+    /// applied like a commit's once the commit is flushed. Committing
+    /// several transactions this way lets them share one flush and sync of
+    /// the log. Returns `LogError::UnknownTransaction` if the transaction
+    /// isn't active.
+    pub fn commit_async(&mut self, tid: u64) -> Result<CommitTicket> {
+        if !self.active_tids.remove(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
         }
+        self.finished_tids.insert(tid);
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Commit(tid)))?;
+        Ok(self.pending_commits.push(tid))
+    }
 
+    /// Flushes the commits made with `commit_async` with a single flush
+    /// and sync of the log, then applies their changes to the store and
+    /// resolves their tickets.
+    ///
+    /// The entries of each transaction are queued before its commit, so
+    /// they're flushed together. Any other flush of the log, like a
+    /// `commit`, flushes the pending commits along with it. If the log
+    /// can't be flushed, the tickets fail with the error and the
+    /// transactions are aborted.
+    pub fn flush_commits(&mut self) -> Result<()> {
+        if self.pending_commits.is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = self.flush(SyncPoint::Commit) {
+            return Err(self.fail_pending_commits(err));
+        }
+        self.set_applied_lsn();
+        self.checkpoint_if_due()
+    }
+
+    /// Commits the transactions together with `commit_async` and
+    /// `flush_commits`, returning once their commits are durable.
+    ///
+    /// Returns `LogError::UnknownTransaction` without committing any of
+    /// them if one of the transactions isn't active.
+    pub fn commit_group(&mut self, tids: &[u64]) -> Result<()> {
+        if let Some(&tid) = tids.iter().find(|tid| !self.active_tids.contains(tid)) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        for &tid in tids {
+            self.commit_async(tid)?;
+        }
+        self.flush_commits()
+    }
+
+    /// Aborts the transaction, so its changes are never flushed to the
+    /// store by a checkpoint or replayed by recovery, and flushes the log.
+    ///
+    /// The changes the transaction already applied to the store with
+    /// `write` are left for the store to discard. Returns
+    /// `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn abort(&mut self, tid: u64) -> Result<()> {
+        if !self.active_tids.remove(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.finished_tids.insert(tid);
+        self.changes.abort(tid);
+        let entry = SingleLogEntry::Transaction(Transaction::Abort(tid));
+        self.push_entry(entry)?;
+
+        self.flush(SyncPoint::Flush)?;
+        self.metrics.record(MetricEvent::Aborted);
         Ok(())
     }
 
-    fn flush(&mut self) -> Result<()> {
-        for entry in self.mem_log.iter_mut() {
-            let mut bytes = Vec::new();
-            entry.serialize(&mut bytes)?;
+    /// Prepares the transaction for a two-phase commit, flushing its
+    /// entries and a prepare entry to the log and syncing it.
+    ///
+    /// The prepared transaction can't be written to, and is committed or
+    /// aborted with `resolve_prepared`. If the log crashes first, recovery
+    /// neither replays nor aborts the transaction but reports it in
+    /// `in_doubt_transactions`. Returns `LogError::UnknownTransaction` if
+    /// the transaction isn't active.
+    pub fn prepare(&mut self, tid: u64) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Prepare(tid)))?;
+        self.flush(SyncPoint::Flush)?;
+        // The prepared transaction has to survive a crash whatever the sync policy.
+        if self.writer.synced_lsn() < self.writer.last_lsn() {
+            self.writer.sync()?;
+        }
+
+        self.active_tids.remove(&tid);
+        self.prepared_tids.insert(tid);
+        Ok(())
+    }
+
+    /// Commits or aborts the prepared transaction like `commit` or `abort`.
+    ///
+    /// Committing a transaction recovery found in doubt applies its changes
+    /// to the store. Returns `LogError::UnknownTransaction` if the
+    /// transaction isn't prepared.
+    pub fn resolve_prepared(&mut self, tid: u64, outcome: Outcome) -> Result<()> {
+        if !self.prepared_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let entry = match outcome {
+            Outcome::Commit => Transaction::Commit(tid),
+            Outcome::Abort => Transaction::Abort(tid),
+        };
+        self.push_entry(SingleLogEntry::Transaction(entry))?;
+        match outcome {
+            Outcome::Commit => self.flush(SyncPoint::Commit)?,
+            Outcome::Abort => self.flush(SyncPoint::Flush)?,
+        };
+
+        self.prepared_tids.remove(&tid);
+        self.finished_tids.insert(tid);
+        let in_doubt = self.in_doubt_tids.remove(&tid);
+        if outcome == Outcome::Abort {
+            self.changes.abort(tid);
+            self.metrics.record(MetricEvent::Aborted);
+            return Ok(());
+        }
+        self.changes.commit(tid);
+        if self.options.defer_store_updates || in_doubt {
+            for (key, val) in self.changes.transaction_changes(tid) {
+                apply_change(&mut self.store, key.clone(), val.cloned());
+            }
+        }
+        self.set_applied_lsn();
+        self.finish_commit()
+    }
+
+    /// Logs a whole transaction whose changes were buffered outside the
+    /// log: its start, a batch of its changes and its commit are flushed together,
+    /// and the changes are then applied to the store, with None for deleted keys.
+    ///
+    /// If the log can't be flushed, the error is returned and the entries
+    /// of the transaction are taken back. When none of them were written,
+    /// the transaction can be committed again. Otherwise it's aborted and
+    /// `has_finished` returns true for it.
+    pub(crate) fn commit_buffered(
+        &mut self,
+        tid: u64,
+        changes: Vec<(Data::Key, Option<Data::Value>)>,
+    ) -> Result<()> {
+        let batch = SingleLogEntry::MultiChangeEntry(MultiChangeEntry {
+            tid,
+            changes: changes.clone(),
+        });
+        // Check the batch's size before its start is queued.
+        batch.serialized_size()?;
 
-            let records = split_bytes_into_records(&bytes, MAX_RECORD_SIZE)?;
-            for record in records.iter() {
-                append_to_file(&mut self.file, record)?;
+        self.last_tid = cmp::max(self.last_tid, tid);
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Start(tid)))?;
+        if !changes.is_empty() {
+            self.push_entry(batch)?;
+        }
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Commit(tid)))?;
+        if let Err(err) = self.flush(SyncPoint::Commit) {
+            // The entries are still queued if none of them were written.
+            if !self.unqueue(|entry| entry.tid() == Some(tid)) {
+                self.finished_tids.insert(tid);
+                self.abort_failed_commit(tid);
             }
+            return Err(err);
+        }
+        self.finished_tids.insert(tid);
+
+        for (key, val) in changes {
+            self.changes.insert(tid, key.clone(), val.clone());
+            apply_change(&mut self.store, key, val);
+        }
+        self.changes.commit(tid);
+        self.set_applied_lsn();
+        self.finish_commit()
+    }
+
+    /// Records the commit just flushed as the last transaction applied
+    /// to the store.
+    fn set_applied_lsn(&mut self) {
+        if let Some(lsn) = self.last_flushed_lsn.filter(|&lsn| lsn > 0) {
+            self.store.set_applied_lsn(lsn);
+        }
+    }
+
+    /// Counts a commit towards the checkpoint policy, checkpointing
+    /// if the policy says to.
+    fn finish_commit(&mut self) -> Result<()> {
+        self.commits_since_checkpoint += 1;
+        self.metrics.record(MetricEvent::Committed);
+        self.checkpoint_if_due()
+    }
+
+    fn checkpoint_if_due(&mut self) -> Result<()> {
+        if self
+            .options
+            .checkpoint
+            .checkpoints_at(self.commits_since_checkpoint, self.bytes_since_checkpoint)
+        {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the entries before the last checkpoint that has ended from
+    /// the log, so recovery doesn't have to scan them and the file doesn't
+    /// keep growing. Returns whether the log was truncated.
+    ///
+    /// Entries of the transactions that were still active when the checkpoint
+    /// began are kept along with the entries after them. The log is synced
+    /// first, and the kept entries are copied to a new file that atomically
+    /// replaces the log.
+    pub fn truncate_before_checkpoint(&mut self) -> Result<bool> {
+        self.flush(SyncPoint::Flush)?;
+        self.writer.sync()?;
+        let start = match checkpoint_start::<Data, _>(&mut self.writer, &self.options, true)? {
+            Some(start) => start,
+            None => return Ok(false),
+        };
+        if self.writer.file().can_discard() {
+            return Ok(self.writer.file_mut().discard_before(start)?);
+        }
+        self.writer = rewrite_log(
+            &mut self.writer,
+            &self.options,
+            start,
+            &mut self.last_flushed_offset,
+        )?;
+        Ok(true)
+    }
+
+    /// Rewrites the log to hold only the last value committed to each key,
+    /// so a log whose keys are rewritten over and over doesn't keep every
+    /// old value around until the next checkpoint is truncated.
+    ///
+    /// The values are written as the changes of one committed transaction
+    /// with the id of `last_tid`, so reopening the log recovers the same
+    /// store and last transaction id. The log is synced first, and the new
+    /// log is written to a new file that atomically replaces the log.
+    ///
+    /// Returns `LogError::ActiveTransactions` if a transaction is active or
+    /// prepared, since its entries would be lost. A log kept in a directory
+    /// with `Wal` can't be rewritten, so compacting it returns an
+    /// `Unsupported` error without changing the log.
+    pub fn compact(&mut self) -> Result<()> {
+        let mut active: Vec<_> = self
+            .active_tids
+            .union(&self.prepared_tids)
+            .cloned()
+            .collect();
+        if !active.is_empty() {
+            active.sort_unstable();
+            return Err(LogError::ActiveTransactions(active));
+        }
+        self.flush(SyncPoint::Flush)?;
+        self.writer.sync()?;
+        let compacted = compact_log::<Data, _>(&mut self.writer, &self.options, self.last_tid)?;
+        self.writer = compacted.writer;
+        self.last_flushed_lsn = compacted.last_entry.map(|(lsn, _)| lsn);
+        self.last_flushed_offset = compacted.last_entry.map(|(_, offset)| offset);
+        Ok(())
+    }
+
+    /// Returns an iterator over the records flushed to the log so far.
+    ///
+    /// The iterator reads through its own handle to the log's backend,
+    /// so it can be used while the log keeps writing.
+    pub fn reader(
+        &self,
+        direction: ReadDirection,
+    ) -> Result<WalIterator<'static, Backend::Reader>> {
+        let file = self.writer.file().reader()?;
+        let mut iter = WalIterator::owned(file, direction, self.writer.format())?;
+        iter.start_at(self.writer.file().first_offset())?;
+        Ok(iter.on_corruption(self.options.on_corruption))
+    }
+
+    /// Returns an iterator following the log's entries as they're flushed,
+    /// starting from the first entry in the log.
+    ///
+    /// Like `reader`, the iterator reads through its own handle to the log's
+    /// backend, so it only sees the entries flushed later if the backend's
+    /// reader does, like the reader of a log kept in a file. Its `wait_next`
+    /// waits for the log to flush more entries until the log is dropped.
+    /// Truncating the log ends the subscription, since the entries after
+    /// the truncated ones move.
+    pub fn subscribe(&mut self) -> Result<TailIterator<Backend::Reader, SingleLogEntry<Data>>> {
+        let format = self.writer.format();
+        let mut file = self.writer.file().reader()?;
+        let first_offset = self.writer.file().first_offset();
+        let tail = if first_offset > 0 {
+            let mut iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format)?;
+            iter.start_at(first_offset)?;
+            let cursor = iter.cursor();
+            TailIterator::from_cursor(file, format, cursor)
+        } else {
+            TailIterator::new(file, format)
+        };
+        Ok(tail.notifier(self.writer.notifier()))
+    }
+
+    /// Returns the LSN of the first record of the last entry flushed to the log.
+    pub fn last_flushed_lsn(&self) -> Option<u64> {
+        self.last_flushed_lsn
+    }
+
+    /// Returns the offset in the log's file of the first record
+    /// of the last entry flushed to the log.
+    pub fn last_flushed_offset(&self) -> Option<u64> {
+        self.last_flushed_offset
+    }
+
+    /// Returns the LSN of the last record synced to disk.
+    pub fn synced_lsn(&self) -> u64 {
+        self.writer.synced_lsn()
+    }
+
+    /// Returns the counters of what the log has done since it was opened.
+    pub fn metrics(&self) -> &Metrics {
+        self.metrics.metrics()
+    }
+
+    /// Returns the stats of the iterator that recovered the log when it was opened.
+    pub fn recovery_stats(&self) -> Stats {
+        self.recovery_stats
+    }
+
+    /// Applies the changes of the transaction in the log to the store
+    /// again, returning whether the transaction was committed.
+    ///
+    /// Only entries already flushed to the log are read, and the changes
+    /// of transactions that haven't committed are not applied.
+    pub fn replay_transaction(&mut self, tid: u64) -> Result<bool> {
+        let first_offset = self.writer.file().first_offset();
+        let format = self.writer.format();
+        let mut source = LogSource::new(self.writer.file_mut(), &self.options)?;
+        let mut iter = WalIterator::with_readahead(
+            &mut source,
+            ReadDirection::Forward,
+            format,
+            self.options.readahead_blocks,
+        )?
+        .on_corruption(self.options.on_corruption);
+        iter.start_at(first_offset)?;
+        let entries = iter.entries::<SingleLogEntry<Data>>().for_transaction(tid);
+
+        let mut changes = Vec::new();
+        let mut committed = false;
+        for entry in entries {
+            match entry? {
+                SingleLogEntry::Transaction(Transaction::Commit(_)) => committed = true,
+                // A commit followed by an abort failed to be synced.
+                SingleLogEntry::Transaction(Transaction::Abort(_)) => committed = false,
+                entry => changes.extend(redo_changes(entry)),
+            }
+        }
+
+        if committed {
+            for (key, val) in changes {
+                apply_change(&mut self.store, key, val);
+            }
+        }
+        Ok(committed)
+    }
+
+    /// Adds the entry to the entries waiting to be flushed to the log.
+    ///
+    /// Returns an error without adding the entry if its serialized size
+    /// can't be computed.
+    fn push_entry(&mut self, entry: SingleLogEntry<Data>) -> Result<()> {
+        self.pending_bytes += entry.serialized_size()?;
+        self.mem_log.push_back(entry);
+        self.metrics.record(MetricEvent::EntryAppended);
+        Ok(())
+    }
+
+    /// Flushes the in-memory entries to the log early if they take up
+    /// more memory than the options allow.
+    fn spill(&mut self) -> Result<()> {
+        if let Some(max) = self.options.max_pending_bytes {
+            if self.pending_bytes >= max {
+                self.flush(SyncPoint::Flush)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the in-memory entries to the log, recording the LSN and
+    /// offset of the first record of the last flushed entry.
+    ///
+    /// The log is synced afterwards if the sync policy syncs at the point.
+    fn flush(&mut self, point: SyncPoint) -> Result<()> {
+        let began = Instant::now();
+        let mut last_flushed = None;
+        let start = self.writer.position();
+        // Without compression, large entries are streamed into records instead
+        // of being serialized in full first. Entries that fit in one record
+        // are still written in one go.
+        let streaming = self.options.compression == Compression::None;
+        for entry in self.mem_log.iter() {
+            let lsn = self.writer.last_lsn() + 1;
+            let written = if streaming {
+                write_serializable_streaming(&mut self.writer, entry)
+            } else {
+                self.writer.append_serializable(entry)
+            };
+            let offset = match written {
+                Ok(offset) => offset,
+                Err(err) => {
+                    // The entries written before the one that failed are
+                    // cut off, so they're only logged by the next flush.
+                    self.writer.truncate(start)?;
+                    return Err(err.into());
+                }
+            };
+            last_flushed = Some((lsn, offset));
         }
         self.mem_log.clear();
+        self.pending_bytes = 0;
+        self.bytes_since_checkpoint += self.writer.position() - start;
+        if let Some((lsn, offset)) = last_flushed {
+            self.last_flushed_lsn = Some(lsn);
+            self.last_flushed_offset = Some(offset);
+        }
+        if self.writer.synced_lsn() < self.writer.last_lsn()
+            && self
+                .options
+                .sync
+                .syncs_at(point, self.writer.unsynced_bytes())
+        {
+            self.writer.sync()?;
+        }
+        if last_flushed.is_some() {
+            self.metrics.record(MetricEvent::Flushed {
+                bytes: self.writer.position() - start,
+                latency: began.elapsed(),
+            });
+        }
+        // The commits queued by `commit_async` were flushed along with the
+        // other entries.
+        if !self.pending_commits.is_empty() {
+            self.finish_pending_commits()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the commits queued by `commit_async` once they're flushed,
+    /// then applies their changes like `commit` and resolves their tickets.
+    fn finish_pending_commits(&mut self) -> Result<()> {
+        if self.writer.synced_lsn() < self.writer.last_lsn() {
+            if let Err(err) = self.writer.sync() {
+                return Err(self.fail_pending_commits(err.into()));
+            }
+        }
+        for commit in self.pending_commits.take() {
+            self.changes.commit(commit.tid);
+            if self.options.defer_store_updates {
+                for (key, val) in self.changes.transaction_changes(commit.tid) {
+                    apply_change(&mut self.store, key.clone(), val.cloned());
+                }
+            }
+            self.commits_since_checkpoint += 1;
+            self.metrics.record(MetricEvent::Committed);
+            commit.complete();
+        }
         Ok(())
     }
 
-    fn recover(&mut self) -> Result<()> {
-        let mut committed = HashSet::new();
+    /// Fails the tickets of the commits queued by `commit_async` with the
+    /// error and aborts their transactions, returning the error.
+    ///
+    /// Commits that weren't written are removed from the entries waiting
+    /// to be flushed. Commits that were written but maybe not synced are
+    /// followed by aborts, so recovery doesn't replay them.
+    fn fail_pending_commits(&mut self, err: LogError) -> LogError {
+        let failed = self.pending_commits.fail(&err);
+        self.unqueue(|entry| failed.iter().any(|&tid| is_commit(entry, tid)));
+        for tid in failed {
+            self.abort_failed_commit(tid);
+        }
+        err
+    }
+
+    /// Aborts the transaction whose commit failed to flush, logging an
+    /// abort after the commit in case it was written.
+    fn abort_failed_commit(&mut self, tid: u64) {
+        self.changes.abort(tid);
+        // The size of a transaction entry is always known.
+        let _ = self.push_entry(SingleLogEntry::Transaction(Transaction::Abort(tid)));
+        self.metrics.record(MetricEvent::Aborted);
+    }
+
+    /// Removes the entries waiting to be flushed that match, returning
+    /// whether any of them matched.
+    fn unqueue<F>(&mut self, mut matches: F) -> bool
+    where
+        F: FnMut(&SingleLogEntry<Data>) -> bool,
+    {
+        let mut removed = false;
+        for entry in mem::take(&mut self.mem_log) {
+            if matches(&entry) {
+                // The entry's size was known when it was queued.
+                self.pending_bytes -= entry.serialized_size().unwrap_or(0);
+                removed = true;
+            } else {
+                self.mem_log.push_back(entry);
+            }
+        }
+        removed
+    }
+
+    /// Loads the snapshot at the path into the store, returning where in
+    /// the log it was taken, or None if there is no snapshot at the path.
+    fn load_snapshot(&mut self, path: &Path) -> Result<Option<Snapshot>> {
+        let (snapshot, entries) = match Snapshot::load::<Data, _>(path) {
+            Ok(loaded) => loaded,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let (key, val) = entry?;
+            self.store.update(key, val);
+        }
+        Ok(Some(snapshot))
+    }
+
+    fn recover(&mut self, snapshot: Option<Snapshot>) -> Result<()> {
+        // LSNs of the commits of the committed transactions.
+        let mut committed = HashMap::new();
         let mut uncommitted = HashSet::new();
         let mut aborted = HashSet::new();
+        let mut prepared = HashSet::new();
+        let mut recovered_tids = HashSet::new();
+        let mut max_started = 0;
         let mut state = RecoverState::None;
+        let mut passed_snapshot = false;
+
+        // An entry torn by a crash while it was being appended is removed,
+        // and recovery continues with the entries before it.
+        truncate_torn_entry::<SingleLogEntry<Data>, _>(
+            &mut self.writer,
+            self.options.on_corruption,
+        )?;
+        let recovery_start = self.writer.file_mut().recovery_start()?;
+        let first_offset = self.writer.file().first_offset();
 
-        let mut iter = WalIterator::new(&mut self.file, ReadDirection::Backward)?;
+        let format = self.writer.format();
+        let mut source = LogSource::new(self.writer.file_mut(), &self.options)?;
+        let on_corruption = self.options.on_corruption;
+        let mut iter = WalIterator::with_readahead(
+            &mut source,
+            ReadDirection::Backward,
+            format,
+            self.options.readahead_blocks,
+        )?
+        .on_corruption(on_corruption);
+        iter.start_at(first_offset)?;
+        let progress = self.options.recovery_progress.clone();
+        let total = match progress {
+            Some(_) => iter.count_records()?,
+            None => 0,
+        };
+        let mut entries = iter.entries::<SingleLogEntry<Data>>();
+        let mut read_back = 0;
 
-        // First pass:
-        while let Ok(data) = read_serializable_backwards::<SingleLogEntry<Data>>(&mut iter) {
+        // First pass: read backwards to the oldest entry recovery needs, which
+        // is the begin entry of the last ended checkpoint, the start entry of
+        // the oldest transaction still active when it began, or the first
+        // entry of the log.
+        while let Some(data) = recover_entry(
+            &mut entries,
+            ReadDirection::Backward,
+            on_corruption,
+            &mut read_back,
+        )? {
+            if let Some(ref progress) = progress {
+                progress.report_pass(entries.get_mut(), total, 0.0, 0.5);
+            }
+            // The entries up to the snapshot are only needed for the
+            // transactions that were still active when it was taken.
+            if let Some(ref snapshot) = snapshot {
+                let lsn = entries.get_mut().last_record_lsn().unwrap_or(0);
+                if !passed_snapshot && lsn <= snapshot.lsn {
+                    passed_snapshot = true;
+                    if snapshot.active.is_empty() {
+                        break;
+                    }
+                    state = RecoverState::Begin(snapshot.active.iter().cloned().collect());
+                }
+            }
+            if let Some(tid) = data.tid() {
+                recovered_tids.insert(tid);
+            }
             match data {
-                SingleLogEntry::Transaction(Transaction::Commit(id)) => {
-                    committed.insert(id);
+                // A commit followed by an abort failed to be synced.
+                SingleLogEntry::Transaction(Transaction::Commit(id)) if !aborted.contains(&id) => {
+                    let lsn = entries.get_mut().last_record_lsn().unwrap_or(0);
+                    committed.insert(id, lsn);
                 }
                 SingleLogEntry::Transaction(Transaction::Abort(id)) => {
                     aborted.insert(id);
                 }
+                SingleLogEntry::Transaction(Transaction::Prepare(id))
+                    if !committed.contains_key(&id) && !aborted.contains(&id) =>
+                {
+                    prepared.insert(id);
+                }
                 SingleLogEntry::Transaction(Transaction::Start(id)) => {
+                    max_started = cmp::max(max_started, id);
                     if let RecoverState::Begin(ref mut transactions) = state {
                         transactions.remove(&id);
                         if transactions.is_empty() {
@@ -146,87 +1076,403 @@ where
                         }
                     }
                 }
-                SingleLogEntry::ChangeEntry(ref entry) => {
-                    if !committed.contains(&entry.tid) && !aborted.contains(&entry.tid) {
-                        uncommitted.insert(entry.tid);
-                    }
+                SingleLogEntry::ChangeEntry(ChangeEntry { tid, .. })
+                | SingleLogEntry::MultiChangeEntry(MultiChangeEntry { tid, .. })
+                | SingleLogEntry::DeleteEntry(DeleteEntry { tid, .. })
+                    if !committed.contains_key(&tid)
+                        && !aborted.contains(&tid)
+                        && !prepared.contains(&tid) =>
+                {
+                    uncommitted.insert(tid);
                 }
-                SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions)) => {
-                    if state == RecoverState::End {
-                        if transactions.is_empty() {
-                            break;
-                        }
-                        state = RecoverState::Begin(transactions.into_iter().collect());
+                SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions))
+                    if state == RecoverState::End =>
+                {
+                    if transactions.is_empty() {
+                        break;
                     }
+                    state = RecoverState::Begin(transactions.into_iter().collect());
                 }
                 SingleLogEntry::Checkpoint(Checkpoint::End) if state == RecoverState::None => {
                     state = RecoverState::End;
                 }
                 _ => {}
             }
+            if reached_recovery_start(&mut entries, recovery_start) {
+                break;
+            }
         }
 
-        // Second pass:
-        while let Ok(data) = read_serializable::<SingleLogEntry<Data>>(&mut iter) {
-            if let SingleLogEntry::ChangeEntry(entry) = data {
-                if committed.contains(&entry.tid) {
-                    self.store.update(entry.key, entry.value);
+        // Second pass: replay forwards from the start of the last entry the
+        // first pass read, which is where the back of the iterator stopped.
+        // Rewinding turns that position into the front, so the entry is read
+        // again and no entry after it is skipped or read twice.
+        //
+        // Transactions the store already applied, or that are in the
+        // snapshot, aren't replayed. Commits written without an LSN are
+        // always replayed, unless there is a snapshot, which they're before.
+        // The changes of the prepared transactions are kept until they're
+        // resolved.
+        let snapshot_lsn = snapshot.as_ref().map(|snapshot| snapshot.lsn);
+        let applied = cmp::max(self.store.applied_lsn(), snapshot_lsn);
+        let replays = |lsn: u64| {
+            (lsn == 0 && snapshot_lsn.is_none()) || applied.is_none_or(|applied| lsn > applied)
+        };
+        entries.get_mut().rewind_back();
+        let replayed = entries.get_mut().size_hint().1.unwrap_or(0);
+        // Each pass counts the entries it reads, since the replay reads
+        // the entries the first pass read again.
+        let mut replayed_entries = 0;
+        while let Some(data) = recover_entry(
+            &mut entries,
+            ReadDirection::Forward,
+            on_corruption,
+            &mut replayed_entries,
+        )? {
+            if let Some(ref progress) = progress {
+                progress.report_pass(entries.get_mut(), replayed, 0.5, 0.5);
+            }
+            let tid = match data.tid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            if committed.get(&tid).is_some_and(|&lsn| replays(lsn)) {
+                for (key, val) in redo_changes(data) {
+                    apply_change(&mut self.store, key, val);
+                }
+            } else if prepared.contains(&tid) {
+                for (key, val) in redo_changes(data) {
+                    self.changes.insert(tid, key, val);
                 }
             }
         }
+        if let Some(ref progress) = progress {
+            progress.report(1.0);
+        }
+        self.recovery_stats = entries.get_mut().stats();
+
+        let last_replayed = committed
+            .values()
+            .cloned()
+            .filter(|&lsn| replays(lsn))
+            .max();
+        if let Some(lsn) = last_replayed.filter(|&lsn| lsn > 0) {
+            self.store.set_applied_lsn(lsn);
+        }
 
         // Flush redo store changes first before writing aborts to the log.
         self.store.flush()?;
         for tid in uncommitted.iter() {
-            self.mem_log
-                .push_back(SingleLogEntry::Transaction(Transaction::Abort(*tid)));
+            self.push_entry(SingleLogEntry::Transaction(Transaction::Abort(*tid)))?;
+            self.metrics.record(MetricEvent::Aborted);
         }
 
-        // Set the last tid to the largest tid.
-        let max_committed = committed.into_iter().max().unwrap_or(0);
+        // Set the last tid to the largest tid, including transactions that
+        // only logged their start.
+        let max_committed = committed.into_keys().max().unwrap_or(0);
         let max_uncommitted = uncommitted.into_iter().max().unwrap_or(0);
         let max_aborted = aborted.into_iter().max().unwrap_or(0);
-        let max_tids = vec![max_committed, max_uncommitted, max_aborted];
+        let max_prepared = prepared.iter().cloned().max().unwrap_or(0);
+        let max_snapshot = snapshot.map_or(0, |snapshot| snapshot.last_tid);
+        let max_tids = vec![
+            max_committed,
+            max_uncommitted,
+            max_aborted,
+            max_prepared,
+            max_started,
+            max_snapshot,
+        ];
         self.last_tid = max_tids.into_iter().max().unwrap();
+        self.finished_tids =
+            FinishedTids::new(recovered_tids.difference(&prepared).cloned().collect());
+        self.in_doubt_tids = prepared.clone();
+        self.prepared_tids = prepared;
 
-        self.flush()?;
+        // The aborts are synced so recovering the log again doesn't find
+        // the transactions unfinished and abort them a second time.
+        self.flush(SyncPoint::Recovery)?;
         Ok(())
     }
 }
 
-struct Changes<Data: LogData> {
+impl<Data, Store, Backend> RedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data> + SnapshotSource<Data>,
+    Backend: LogBackend,
+{
+    /// Writes a snapshot of the store to the path, which
+    /// `RedoLog::open_with_snapshot` loads so recovery doesn't replay the
+    /// transactions committed before it. Returns where in the log the
+    /// snapshot was taken.
+    ///
+    /// The log is flushed and synced first, so the snapshot is only used
+    /// with entries that reached the log. Recovery replays the transactions
+    /// still active or prepared from the log if they commit.
+    ///
+    /// Unless store updates are deferred, returns
+    /// `LogError::ActiveTransactions` if a transaction is active or
+    /// prepared, since the changes it already applied to the store would be
+    /// in the snapshot even if it never commits.
+    ///
+    /// Recovery still needs the entries after the snapshot, so take a new
+    /// snapshot before truncating the log at a later checkpoint.
+    pub fn snapshot<P: AsRef<Path> + ?Sized>(&mut self, path: &P) -> Result<Snapshot> {
+        let mut active: Vec<_> = self
+            .active_tids
+            .union(&self.prepared_tids)
+            .cloned()
+            .collect();
+        active.sort_unstable();
+        if !self.options.defer_store_updates && !active.is_empty() {
+            return Err(LogError::ActiveTransactions(active));
+        }
+        self.flush(SyncPoint::Flush)?;
+        self.writer.sync()?;
+        let snapshot = Snapshot {
+            lsn: self.writer.last_lsn(),
+            last_tid: self.last_tid,
+            active,
+        };
+        snapshot.write::<Data, _, _>(path, self.store.iter_all())?;
+        Ok(snapshot)
+    }
+}
+
+impl<Data, Store> RedoLogOptions<Data, Store>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    /// Opens the log at the path with the options, recovering it into the store.
+    pub fn open<P: AsRef<Path> + ?Sized>(
+        self,
+        path: &P,
+        store: Store,
+    ) -> Result<RedoLog<Data, Store>> {
+        RedoLog::new_with_options(path, store, self.options)
+    }
+
+    /// Opens the log at `log_path` with the options like
+    /// `RedoLog::open_with_snapshot`, loading the snapshot at
+    /// `snapshot_path` into the store first.
+    pub fn open_with_snapshot<P1, P2>(
+        self,
+        snapshot_path: &P1,
+        log_path: &P2,
+        store: Store,
+    ) -> Result<RedoLog<Data, Store>>
+    where
+        P1: AsRef<Path> + ?Sized,
+        P2: AsRef<Path> + ?Sized,
+    {
+        self.options.validate()?;
+        let writer = self
+            .options
+            .writer_for(self.options.open_log_file(log_path)?)?;
+        RedoLog::from_writer(writer, store, self.options, Some(snapshot_path.as_ref()))
+    }
+
+    /// Opens the log kept in the backend with the options, recovering it into the store.
+    pub fn open_backend<Backend: LogBackend>(
+        self,
+        backend: Backend,
+        store: Store,
+    ) -> Result<RedoLog<Data, Store, Backend>> {
+        RedoLog::with_backend(backend, store, self.options)
+    }
+
+    /// Sets whether the changes of a transaction are applied to the
+    /// store only once the transaction commits.
+    pub fn defer_store_updates(mut self, defer: bool) -> RedoLogOptions<Data, Store> {
+        self.options.defer_store_updates = defer;
+        self
+    }
+
+    /// Sets whether the rest of the block after the end of each
+    /// checkpoint is padded.
+    pub fn seal_checkpoints(mut self, seal: bool) -> RedoLogOptions<Data, Store> {
+        self.options.seal_checkpoints = seal;
+        self
+    }
+}
+
+impl<Data, Store, Backend> Drop for RedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    fn drop(&mut self) {
+        // Errors can't be returned from drop, so `close` has to be
+        // used to find out whether the entries were flushed.
+        if self.open {
+            let _ = self.flush(SyncPoint::Flush);
+        }
+    }
+}
+
+impl<Data, Store, Backend> TransactionLog<Data> for RedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()> {
+        RedoLog::write(self, tid, key, val)
+    }
+
+    fn write_batch(&mut self, tid: u64, changes: Vec<(Data::Key, Data::Value)>) -> Result<()> {
+        RedoLog::write_batch(self, tid, changes)
+    }
+
+    fn delete(&mut self, tid: u64, key: Data::Key) -> Result<()> {
+        RedoLog::delete(self, tid, key)
+    }
+
+    fn commit(&mut self, tid: u64) -> Result<()> {
+        RedoLog::commit(self, tid)
+    }
+
+    fn abort(&mut self, tid: u64) -> Result<()> {
+        RedoLog::abort(self, tid)
+    }
+}
+
+/// Returns the changes an entry redoes in order, with None for deleted keys.
+pub(crate) fn redo_changes<Data: LogData>(
+    entry: SingleLogEntry<Data>,
+) -> Vec<(Data::Key, Option<Data::Value>)> {
+    match entry {
+        SingleLogEntry::ChangeEntry(entry) => vec![(entry.key, Some(entry.value))],
+        SingleLogEntry::MultiChangeEntry(entry) => entry.changes,
+        SingleLogEntry::DeleteEntry(entry) => vec![(entry.key, None)],
+        _ => Vec::new(),
+    }
+}
+
+/// Updates the key in the store to the value, or removes it if there is no value.
+pub(crate) fn apply_change<Data, Store>(store: &mut Store, key: Data::Key, val: Option<Data::Value>)
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    match val {
+        Some(val) => store.update(key, val),
+        None => store.remove(&key),
+    }
+}
+
+/// Returns whether the entry is the commit of the transaction.
+fn is_commit<Data: LogData>(entry: &SingleLogEntry<Data>, tid: u64) -> bool {
+    matches!(entry, SingleLogEntry::Transaction(Transaction::Commit(commit)) if *commit == tid)
+}
+
+/// The last change written to each key, with None for deleted keys,
+/// tagged with when it was written.
+type KeyChanges<Data> = HashMap<<Data as LogData>::Key, (u64, Option<<Data as LogData>::Value>)>;
+
+pub(crate) struct Changes<Data: LogData> {
     committed_tids: HashSet<u64>,
-    transaction_changes: Vec<(u64, Data::Key, Data::Value)>,
+    /// The last change each transaction wrote to each key.
+    transaction_changes: HashMap<u64, KeyChanges<Data>>,
+    /// Sequence number of the next change written.
+    next_seq: u64,
 }
 
 impl<Data> Changes<Data>
 where
     Data: LogData,
 {
-    fn new() -> Changes<Data> {
+    pub(crate) fn new() -> Changes<Data> {
         Changes {
             committed_tids: HashSet::new(),
-            transaction_changes: Vec::new(),
+            transaction_changes: HashMap::new(),
+            next_seq: 0,
         }
     }
 
     fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) {
-        self.transaction_changes.push((tid, key, val));
+        self.insert(tid, key, Some(val));
+    }
+
+    fn delete(&mut self, tid: u64, key: Data::Key) {
+        self.insert(tid, key, None);
     }
 
-    fn commit(&mut self, tid: u64) {
+    pub(crate) fn insert(&mut self, tid: u64, key: Data::Key, val: Option<Data::Value>) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.transaction_changes
+            .entry(tid)
+            .or_default()
+            .insert(key, (seq, val));
+    }
+
+    pub(crate) fn commit(&mut self, tid: u64) {
         self.committed_tids.insert(tid);
     }
 
-    fn flush_changes(&self) -> HashMap<Data::Key, Data::Value> {
-        let mut map = HashMap::new();
-        for &(tid, ref key, ref value) in self.transaction_changes.iter() {
-            if self.committed_tids.contains(&tid) {
-                map.insert(key.clone(), value.clone());
+    pub(crate) fn abort(&mut self, tid: u64) {
+        self.transaction_changes.remove(&tid);
+    }
+
+    /// Returns the number of changes kept, one per key written by each transaction.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.transaction_changes.values().map(HashMap::len).sum()
+    }
+
+    /// Returns the last value the transaction wrote to the key, which
+    /// is None if the transaction deleted the key.
+    fn get(&self, tid: u64, key: &Data::Key) -> Option<Option<&Data::Value>> {
+        self.transaction_changes
+            .get(&tid)?
+            .get(key)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// Returns the last change the transaction wrote to each key, in the
+    /// order they were written.
+    fn transaction_changes(&self, tid: u64) -> Vec<(&Data::Key, Option<&Data::Value>)> {
+        let mut changes: Vec<_> = self
+            .transaction_changes
+            .get(&tid)
+            .into_iter()
+            .flatten()
+            .collect();
+        changes.sort_by_key(|&(_, &(seq, _))| seq);
+        changes
+            .into_iter()
+            .map(|(key, (_, value))| (key, value.as_ref()))
+            .collect()
+    }
+
+    /// Returns the last change committed to each key since the committed
+    /// changes were last removed.
+    pub(crate) fn flush_changes(&self) -> HashMap<Data::Key, Option<Data::Value>> {
+        let mut latest: HashMap<&Data::Key, &(u64, Option<Data::Value>)> = HashMap::new();
+        for tid in self.committed_tids.iter() {
+            for (key, change) in self.transaction_changes.get(tid).into_iter().flatten() {
+                let newer = latest.get(key).is_none_or(|&&(seq, _)| seq < change.0);
+                if newer {
+                    latest.insert(key, change);
+                }
             }
         }
 
-        map
+        latest
+            .into_iter()
+            .map(|(key, (_, value))| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Forgets the changes of the committed transactions once they've been
+    /// flushed, keeping the changes of the transactions still active.
+    fn remove_committed(&mut self) {
+        for tid in self.committed_tids.drain() {
+            self.transaction_changes.remove(&tid);
+        }
     }
 }
 
@@ -246,7 +1492,7 @@ fn test_changes() {
 
     let flush_changes = changes.flush_changes();
     assert_eq!(flush_changes.len(), 1);
-    assert_eq!(flush_changes.get(&2), Some(&"Hello".to_string()));
+    assert_eq!(flush_changes.get(&2), Some(&Some("Hello".to_string())));
 
     let mut changes: Changes<MyLogData> = Changes::new();
     changes.write(1, 2, "Hello".to_string());
@@ -259,6 +1505,102 @@ fn test_changes() {
 
     let flush_changes = changes.flush_changes();
     assert_eq!(flush_changes.len(), 2);
-    assert_eq!(flush_changes.get(&2), Some(&"Hello".to_string()));
-    assert_eq!(flush_changes.get(&3), Some(&"Foo".to_string()));
+    assert_eq!(flush_changes.get(&2), Some(&Some("Hello".to_string())));
+    assert_eq!(flush_changes.get(&3), Some(&Some("Foo".to_string())));
+
+    let mut changes: Changes<MyLogData> = Changes::new();
+    changes.write(1, 2, "Hello".to_string());
+    changes.write(2, 2, "World".to_string());
+    changes.abort(2);
+    changes.commit(1);
+    changes.commit(2);
+
+    let flush_changes = changes.flush_changes();
+    assert_eq!(flush_changes.len(), 1);
+    assert_eq!(flush_changes.get(&2), Some(&Some("Hello".to_string())));
+
+    let mut changes: Changes<MyLogData> = Changes::new();
+    changes.write(1, 2, "Hello".to_string());
+    changes.delete(1, 2);
+    changes.delete(2, 3);
+    changes.commit(1);
+
+    assert_eq!(changes.get(1, &2), Some(None));
+    assert_eq!(changes.get(2, &2), None);
+    let flush_changes = changes.flush_changes();
+    assert_eq!(flush_changes.len(), 1);
+    assert_eq!(flush_changes.get(&2), Some(&None));
+}
+
+#[test]
+fn test_changes_removed_after_flush() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct MyLogData;
+    impl LogData for MyLogData {
+        type Key = i32;
+        type Value = String;
+    }
+
+    let mut changes: Changes<MyLogData> = Changes::new();
+    changes.write(1, 2, "Hello".to_string());
+    changes.write(2, 3, "World".to_string());
+    changes.write(3, 4, "Aborted".to_string());
+    changes.commit(1);
+    changes.abort(3);
+
+    // Transaction 2 is still active at the first checkpoint.
+    assert_eq!(changes.flush_changes().len(), 1);
+    changes.remove_committed();
+    assert!(changes.flush_changes().is_empty());
+    assert_eq!(changes.get(2, &3), Some(Some(&"World".to_string())));
+    assert_eq!(changes.len(), 1);
+
+    // It commits after the checkpoint, so the next one flushes it.
+    changes.write(2, 2, "Later".to_string());
+    changes.commit(2);
+    let flush_changes = changes.flush_changes();
+    assert_eq!(flush_changes.len(), 2);
+    assert_eq!(flush_changes.get(&2), Some(&Some("Later".to_string())));
+    changes.remove_committed();
+    assert_eq!(changes.len(), 0);
+    assert!(changes.committed_tids.is_empty());
+}
+
+#[test]
+fn test_changes_keep_last_write() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct MyLogData;
+    impl LogData for MyLogData {
+        type Key = i32;
+        type Value = String;
+    }
+
+    let mut changes: Changes<MyLogData> = Changes::new();
+    for i in 0..100_000 {
+        changes.write(1, 2, i.to_string());
+    }
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes.get(1, &2), Some(Some(&"99999".to_string())));
+
+    // Later writes win across transactions regardless of commit order.
+    changes.write(2, 3, "First".to_string());
+    changes.write(1, 3, "Second".to_string());
+    changes.delete(1, 4);
+    changes.commit(1);
+    changes.commit(2);
+    assert_eq!(
+        changes.transaction_changes(1),
+        vec![
+            (&2, Some(&"99999".to_string())),
+            (&3, Some(&"Second".to_string())),
+            (&4, None)
+        ]
+    );
+    let flush_changes = changes.flush_changes();
+    assert_eq!(flush_changes.len(), 3);
+    assert_eq!(flush_changes.get(&2), Some(&Some("99999".to_string())));
+    assert_eq!(flush_changes.get(&3), Some(&Some("Second".to_string())));
+    assert_eq!(flush_changes.get(&4), Some(&None));
+    changes.remove_committed();
+    assert_eq!(changes.len(), 0);
 }
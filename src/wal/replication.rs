@@ -0,0 +1,529 @@
+use byteorder::{BigEndian, ByteOrder};
+use crc::crc32;
+use enum_primitive::FromPrimitive;
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+
+use crate::wal::entries::{MultiChangeEntry, SingleLogEntry, Transaction};
+use crate::wal::header::{FileHeader, FILE_HEADER_SIZE, MAGIC};
+use crate::wal::iterator::BlockError;
+use crate::wal::record::{BlockFormat, Record, RecordError, RecordType, PADDING_BYTE};
+use crate::wal::redo_log::{apply_change, redo_changes};
+use crate::wal::{
+    finish_entry, invalid_transfer, LogData, LogError, LogStore, Result, SerializeError,
+};
+use crate::Serializable;
+
+/// Bytes read from the stream at a time.
+const READ_SIZE: usize = 8192;
+
+/// Applies the transactions committed in a redo log read from the stream
+/// to the store, like recovering the log would, and returns what was
+/// applied.
+///
+/// The stream is read forwards from the start of the log file until it
+/// ends, so it can be a socket or pipe receiving a copy of the log's
+/// bytes. Transactions that committed at or before `from_lsn`, like the
+/// last LSN a previous call applied, aren't applied again. Transactions
+/// the stream ends partway through are left out; to keep applying a log
+/// as more of it arrives, use an `ApplyState` instead.
+pub fn apply_stream<Data, Store, R>(
+    reader: R,
+    store: &mut Store,
+    from_lsn: Option<u64>,
+) -> Result<AppliedReport>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    R: Read,
+{
+    ApplyState::new(from_lsn).apply(reader, store)
+}
+
+/// What a call to `ApplyState::apply` or `apply_stream` read from its
+/// stream and applied to the store.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AppliedReport {
+    /// Bytes read from the stream.
+    pub bytes_read: u64,
+    /// Entries completed by the bytes read.
+    pub entries: usize,
+    /// Transactions whose changes were applied to the store.
+    pub committed: usize,
+    /// Committed transactions that weren't applied because they committed
+    /// at or before the LSN the store had already applied.
+    pub skipped: usize,
+    /// Transactions aborted, whose changes were dropped.
+    pub aborted: usize,
+    /// Changes applied to the store, counting each deleted key as a change.
+    pub changes: usize,
+    /// LSN of the last record read from the log so far, or 0 if none was.
+    pub last_lsn: u64,
+    /// LSN of the commit of the last transaction applied to the store so
+    /// far, if any.
+    pub applied_lsn: Option<u64>,
+    /// Transactions started but not yet committed or aborted, in order.
+    pub in_flight: Vec<u64>,
+}
+
+/// Progress of applying a redo log streamed in chunks to a store, so a
+/// follower can apply the log as it arrives.
+///
+/// Each call to `apply` continues from the byte after the last one the
+/// state was given. A record cut off by the end of a chunk waits for the
+/// rest of its bytes, and the changes of a transaction are kept until its
+/// commit arrives. The state implements `Serializable`, so it can be
+/// saved along with the store and loaded to resume applying the log from
+/// `position` after a restart.
+///
+/// Each record is checked against its checksum as it arrives. With block
+/// checksums, a block is also checked once all of its bytes arrived. A
+/// corrupted record or entry returns an error, since skipping it would
+/// leave the store missing changes.
+pub struct ApplyState<Data: LogData> {
+    /// Commits at or before this LSN aren't applied to the store.
+    applied_lsn: Option<u64>,
+    /// Format of the log's blocks, found once enough of the log arrived
+    /// to tell whether it starts with a file header.
+    format: Option<BlockFormat>,
+    /// Whether the log starts with a file header taking up its first block.
+    header: bool,
+    /// Offset in the log of the block being received.
+    pos: u64,
+    /// Bytes of the block received so far.
+    block: Vec<u8>,
+    /// Offset in the block of the first record not read yet.
+    block_offset: usize,
+    /// Records read from the block so far.
+    block_records: usize,
+    /// Payloads of the records read so far for the entry being received.
+    entry: Vec<u8>,
+    /// Type of the last record of the entry being received, or None
+    /// between entries.
+    previous: Option<RecordType>,
+    /// LSN of the last record read.
+    last_lsn: u64,
+    /// Changes of the transactions that haven't committed or aborted.
+    transactions: BTreeMap<u64, MultiChangeEntry<Data>>,
+}
+
+impl<Data: LogData> ApplyState<Data> {
+    /// Creates a state for applying a log from its start, skipping the
+    /// transactions committed at or before `from_lsn`.
+    pub fn new(from_lsn: Option<u64>) -> ApplyState<Data> {
+        ApplyState {
+            applied_lsn: from_lsn,
+            format: None,
+            header: false,
+            pos: 0,
+            block: Vec::new(),
+            block_offset: 0,
+            block_records: 0,
+            entry: Vec::new(),
+            previous: None,
+            last_lsn: 0,
+            transactions: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of bytes of the log applied so far, which is
+    /// the offset in the log the next call to `apply` continues from.
+    pub fn position(&self) -> u64 {
+        self.pos + self.block.len() as u64
+    }
+
+    /// Returns the LSN of the last record read, or 0 if none was.
+    pub fn last_lsn(&self) -> u64 {
+        self.last_lsn
+    }
+
+    /// Returns the LSN of the commit of the last transaction applied to
+    /// the store, or the LSN the state was created with if none was.
+    pub fn applied_lsn(&self) -> Option<u64> {
+        self.applied_lsn
+    }
+
+    /// Returns the transactions started but not yet committed or aborted,
+    /// in order.
+    pub fn in_flight(&self) -> Vec<u64> {
+        self.transactions.keys().cloned().collect()
+    }
+
+    /// Reads the next bytes of the log from the stream until it ends,
+    /// applying the transactions that commit in them to the store.
+    ///
+    /// The store's `set_applied_lsn` is called after each transaction is
+    /// applied. If an error is returned, the bytes read before it that
+    /// completed a transaction have been applied, but the state shouldn't
+    /// be used again.
+    pub fn apply<R, Store>(&mut self, mut reader: R, store: &mut Store) -> Result<AppliedReport>
+    where
+        R: Read,
+        Store: LogStore<Data>,
+    {
+        let mut report = AppliedReport::default();
+        let mut buf = vec![0; READ_SIZE];
+        loop {
+            let len = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            report.bytes_read += len as u64;
+            self.feed(&buf[..len], store, &mut report)?;
+        }
+        report.last_lsn = self.last_lsn;
+        report.applied_lsn = self.applied_lsn;
+        report.in_flight = self.in_flight();
+        Ok(report)
+    }
+
+    /// Adds the bytes to the block being received, reading the records
+    /// completed by them and moving to the next block once it's full.
+    fn feed<Store: LogStore<Data>>(
+        &mut self,
+        mut bytes: &[u8],
+        store: &mut Store,
+        report: &mut AppliedReport,
+    ) -> Result<()> {
+        while !bytes.is_empty() {
+            let wanted = self
+                .format
+                .map_or(FILE_HEADER_SIZE, |format| format.block_size as usize);
+            let len = cmp::min(bytes.len(), wanted - self.block.len());
+            self.block.extend_from_slice(&bytes[..len]);
+            bytes = &bytes[len..];
+
+            let format = match self.format {
+                Some(format) => format,
+                None => match self.read_format()? {
+                    Some(format) => format,
+                    None => continue,
+                },
+            };
+            // The file header takes up the whole first block.
+            if !(self.header && self.pos == 0) {
+                self.read_records(format, store, report)?;
+            }
+            if self.block.len() == format.block_size as usize {
+                self.pos += format.block_size as u64;
+                self.block.clear();
+                self.block_offset = 0;
+                self.block_records = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the block format of the log once enough of the first block
+    /// arrived to read it from the file header, or the default format if
+    /// the log was written without one.
+    fn read_format(&mut self) -> Result<Option<BlockFormat>> {
+        let format = if self.block[0] != MAGIC[0] {
+            BlockFormat::default()
+        } else if self.block.len() < FILE_HEADER_SIZE {
+            return Ok(None);
+        } else {
+            let header = FileHeader::parse(&self.block).map_err(io::Error::from)?;
+            let format = header.format();
+            format.check()?;
+            self.header = true;
+            format
+        };
+        self.format = Some(format);
+        Ok(Some(format))
+    }
+
+    /// Reads the records of the block that arrived since the last call,
+    /// stopping at a record that hasn't fully arrived yet.
+    fn read_records<Store: LogStore<Data>>(
+        &mut self,
+        format: BlockFormat,
+        store: &mut Store,
+        report: &mut AppliedReport,
+    ) -> Result<()> {
+        let capacity = format.capacity();
+        let full = self.block.len() == format.block_size as usize;
+        if full && format.checksums {
+            let expected = BigEndian::read_u32(&self.block[capacity..]);
+            let actual = crc32::checksum_ieee(&self.block[..capacity]);
+            if expected != actual {
+                let error = RecordError::BadBlockChecksum { expected, actual }.into();
+                return Err(self.corrupted(format, 0, error));
+            }
+        }
+
+        let len = cmp::min(capacity, self.block.len());
+        while self.block_offset < len {
+            let bytes = &self.block[self.block_offset..len];
+            if bytes[0] == PADDING_BYTE {
+                // Padding runs to the end of the block, so the next record
+                // is in the next block.
+                if full && !bytes.iter().all(|&b| b == PADDING_BYTE) {
+                    let error = RecordError::InvalidPadding.into();
+                    return Err(self.corrupted(format, self.block_offset, error));
+                }
+                break;
+            }
+
+            let (record, len) = match Record::parse_with_limit(bytes, capacity - self.block_offset)
+            {
+                Ok((view, len)) => (view.to_record(), len),
+                Err(ref err)
+                    if !full
+                        && RecordError::from_io_error(err)
+                            .is_some_and(RecordError::is_truncation) =>
+                {
+                    break;
+                }
+                Err(err) => return Err(self.corrupted(format, self.block_offset, err)),
+            };
+            let offset = self.pos + self.block_offset as u64;
+            let index = self.block_records;
+            self.block_offset += len;
+            self.block_records += 1;
+            self.read_record(record, offset, index, store, report)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the record to the entry being received, applying the entry
+    /// once its last record arrived.
+    fn read_record<Store: LogStore<Data>>(
+        &mut self,
+        record: Record,
+        offset: u64,
+        index: usize,
+        store: &mut Store,
+        report: &mut AppliedReport,
+    ) -> Result<()> {
+        let in_entry = self.previous.is_some();
+        let continues = matches!(record.record_type, RecordType::Middle | RecordType::Last);
+        if in_entry != continues {
+            let err = invalid_transfer(record.record_type, self.previous, (offset, index));
+            return Err(err.into());
+        }
+        if record.lsn > 0 {
+            self.last_lsn = record.lsn;
+        }
+        self.entry.extend_from_slice(&record.payload);
+        if let RecordType::First | RecordType::Middle = record.record_type {
+            self.previous = Some(record.record_type);
+            return Ok(());
+        }
+
+        self.previous = None;
+        let mut bytes = mem::take(&mut self.entry);
+        finish_entry(&mut bytes, record.compressed)?;
+        let entry = SingleLogEntry::<Data>::deserialize(&mut &bytes[..])
+            .map_err(SerializeError::IoError)?;
+        report.entries += 1;
+        self.apply_entry(entry, record.lsn, store, report);
+        Ok(())
+    }
+
+    /// Applies the entry whose last record has the LSN to the store if it
+    /// commits a transaction, or keeps its changes until the transaction
+    /// commits.
+    fn apply_entry<Store: LogStore<Data>>(
+        &mut self,
+        entry: SingleLogEntry<Data>,
+        lsn: u64,
+        store: &mut Store,
+        report: &mut AppliedReport,
+    ) {
+        let tid = match entry.tid() {
+            Some(tid) => tid,
+            None => return,
+        };
+        match entry {
+            SingleLogEntry::Transaction(Transaction::Commit(_)) => {
+                let changes = self
+                    .transactions
+                    .remove(&tid)
+                    .map_or_else(Vec::new, |entry| entry.changes);
+                // Commits written without an LSN are always applied.
+                if lsn > 0 && self.applied_lsn.is_some_and(|applied| lsn <= applied) {
+                    report.skipped += 1;
+                    return;
+                }
+                report.committed += 1;
+                report.changes += changes.len();
+                for (key, val) in changes {
+                    apply_change(store, key, val);
+                }
+                if lsn > 0 {
+                    store.set_applied_lsn(lsn);
+                    self.applied_lsn = Some(lsn);
+                }
+            }
+            SingleLogEntry::Transaction(Transaction::Abort(_)) => {
+                self.transactions.remove(&tid);
+                report.aborted += 1;
+            }
+            SingleLogEntry::Transaction(Transaction::Prepare(_)) => {}
+            entry => {
+                let changes = redo_changes(entry);
+                self.transactions
+                    .entry(tid)
+                    .or_insert_with(|| MultiChangeEntry {
+                        tid,
+                        changes: Vec::new(),
+                    })
+                    .changes
+                    .extend(changes);
+            }
+        }
+    }
+
+    /// Returns the error for a corrupted record at the offset in the block.
+    fn corrupted(&self, format: BlockFormat, block_offset: usize, error: io::Error) -> LogError {
+        let err = BlockError::Corrupted {
+            offset: self.pos + block_offset as u64,
+            block: self.pos / format.block_size as u64,
+            record: self.block_records,
+            error,
+        };
+        SerializeError::from(err).into()
+    }
+}
+
+impl<Data: LogData> Serializable for ApplyState<Data> {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        serialize_optional_lsn(self.applied_lsn, bytes)?;
+        match self.format {
+            Some(format) => {
+                bytes.write_all(&[1, format.checksums as u8, self.header as u8])?;
+                (format.block_size as u64).serialize(bytes)?;
+            }
+            None => bytes.write_all(&[0])?,
+        }
+        self.pos.serialize(bytes)?;
+        serialize_bytes(&self.block, bytes)?;
+        (self.block_offset as u32).serialize(bytes)?;
+        (self.block_records as u32).serialize(bytes)?;
+        serialize_bytes(&self.entry, bytes)?;
+        bytes.write_all(&[self.previous.map_or(0, |record_type| record_type as u8)])?;
+        self.last_lsn.serialize(bytes)?;
+        (self.transactions.len() as u32).serialize(bytes)?;
+        for entry in self.transactions.values() {
+            entry.serialize(bytes)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<ApplyState<Data>> {
+        let applied_lsn = deserialize_optional_lsn(bytes)?;
+        let mut tag = [0; 1];
+        bytes.read_exact(&mut tag)?;
+        let (format, header) = match tag[0] {
+            0 => (None, false),
+            1 => {
+                let mut flags = [0; 2];
+                bytes.read_exact(&mut flags)?;
+                let format = BlockFormat {
+                    block_size: u64::deserialize(bytes)? as i64,
+                    checksums: flags[0] != 0,
+                };
+                format.check()?;
+                (Some(format), flags[1] != 0)
+            }
+            _ => return Err(invalid_state("Invalid block format in apply state")),
+        };
+        let pos = u64::deserialize(bytes)?;
+        let block = deserialize_bytes(bytes)?;
+        let block_offset = u32::deserialize(bytes)? as usize;
+        let block_records = u32::deserialize(bytes)? as usize;
+        let entry = deserialize_bytes(bytes)?;
+        let mut previous = [0; 1];
+        bytes.read_exact(&mut previous)?;
+        let previous = match previous[0] {
+            0 => None,
+            byte => match RecordType::from_u8(byte) {
+                Some(record_type @ RecordType::First) | Some(record_type @ RecordType::Middle) => {
+                    Some(record_type)
+                }
+                _ => return Err(invalid_state("Invalid record type in apply state")),
+            },
+        };
+        let last_lsn = u64::deserialize(bytes)?;
+        let len = u32::deserialize(bytes)?;
+        let mut transactions = BTreeMap::new();
+        for _ in 0..len {
+            let entry = MultiChangeEntry::<Data>::deserialize(bytes)?;
+            transactions.insert(entry.tid, entry);
+        }
+
+        let max_block = format.map_or(FILE_HEADER_SIZE, |format| format.block_size as usize);
+        if block.len() > max_block || block_offset > block.len() {
+            return Err(invalid_state("Invalid block position in apply state"));
+        }
+        Ok(ApplyState {
+            applied_lsn,
+            format,
+            header,
+            pos,
+            block,
+            block_offset,
+            block_records,
+            entry,
+            previous,
+            last_lsn,
+            transactions,
+        })
+    }
+}
+
+fn serialize_optional_lsn<W: Write>(lsn: Option<u64>, bytes: &mut W) -> io::Result<()> {
+    match lsn {
+        Some(lsn) => {
+            bytes.write_all(&[1])?;
+            lsn.serialize(bytes)
+        }
+        None => bytes.write_all(&[0]),
+    }
+}
+
+fn deserialize_optional_lsn<R: Read>(bytes: &mut R) -> io::Result<Option<u64>> {
+    let mut flag = [0; 1];
+    bytes.read_exact(&mut flag)?;
+    match flag[0] {
+        0 => Ok(None),
+        1 => Ok(Some(u64::deserialize(bytes)?)),
+        _ => Err(invalid_state("Invalid LSN in apply state")),
+    }
+}
+
+/// Writes the bytes prefixed with their u32 length.
+fn serialize_bytes<W: Write>(buf: &[u8], bytes: &mut W) -> io::Result<()> {
+    if buf.len() > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Entry is too large to serialize",
+        ));
+    }
+    (buf.len() as u32).serialize(bytes)?;
+    bytes.write_all(buf)
+}
+
+fn deserialize_bytes<R: Read>(bytes: &mut R) -> io::Result<Vec<u8>> {
+    let len = u32::deserialize(bytes)? as u64;
+    let mut buf = Vec::new();
+    bytes.by_ref().take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Apply state ends partway through its bytes",
+        ));
+    }
+    Ok(buf)
+}
+
+fn invalid_state(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
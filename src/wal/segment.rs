@@ -0,0 +1,240 @@
+use std::ffi::OsString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::wal::chained::ChainedLog;
+use crate::wal::record::BlockFormat;
+use crate::wal::writer::{last_lsn_in_file, Writer};
+use crate::wal::Compression;
+use crate::Serializable;
+
+/// Writes a log split into segment files named `<base>.NNNNNN`,
+/// starting a new segment when the current one would grow past
+/// a maximum size.
+///
+/// Entries are never split across segments, so an entry larger than the
+/// maximum segment size gets a segment to itself. Before a new segment is
+/// started, the current segment is padded to a whole number of blocks and
+/// synced, so the segments can be read in order as a `ChainedLog`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+/// use disk_utils::wal::read_serializable;
+/// use disk_utils::wal::segment::SegmentedWriter;
+///
+/// fn main() {
+///     let mut writer = SegmentedWriter::new("./files/segment_doc_example", 1024).unwrap();
+///     for i in 0..10 {
+///         writer.append_serializable(&"a".repeat(i * 100)).unwrap();
+///     }
+///     assert!(writer.sealed_segments().len() > 1);
+///
+///     let mut log = writer.reader().unwrap();
+///     let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+///     for i in 0..10 {
+///         assert_eq!(read_serializable::<String>(&mut iter).unwrap(), "a".repeat(i * 100));
+///     }
+///     # for path in writer.segments() {
+///     #     std::fs::remove_file(path).unwrap();
+///     # }
+/// }
+/// ```
+pub struct SegmentedWriter {
+    base: PathBuf,
+    format: BlockFormat,
+    max_segment_size: u64,
+    compression: Compression,
+    /// Paths of the segments before the current segment, in order.
+    sealed: Vec<PathBuf>,
+    /// Number of the current segment.
+    index: u64,
+    writer: Writer,
+}
+
+impl SegmentedWriter {
+    /// Opens the segments of the log at the base path, creating the
+    /// first segment if there are none, and appends to the last segment.
+    pub fn new<P: AsRef<Path>>(base: P, max_segment_size: u64) -> io::Result<SegmentedWriter> {
+        SegmentedWriter::with_format(base, BlockFormat::default(), max_segment_size)
+    }
+
+    /// Opens the segments like `new` for a log written with the given block format.
+    pub fn with_format<P: AsRef<Path>>(
+        base: P,
+        format: BlockFormat,
+        max_segment_size: u64,
+    ) -> io::Result<SegmentedWriter> {
+        format.check()?;
+        let base = base.as_ref().to_path_buf();
+        let mut segments = segment_numbers(&base)?;
+        let index = segments.pop().unwrap_or(0);
+        let sealed: Vec<_> = segments
+            .into_iter()
+            .map(|index| segment_path(&base, index))
+            .collect();
+
+        // An empty current segment continues the LSNs of the segment before it.
+        let last_lsn = match sealed.last() {
+            Some(path) => last_lsn_in_file(&mut File::open(path)?, format)?,
+            None => 0,
+        };
+        let writer = open_segment(&base, index, format)?.continue_after(last_lsn);
+        Ok(SegmentedWriter {
+            base,
+            format,
+            max_segment_size,
+            compression: Compression::default(),
+            sealed,
+            index,
+            writer,
+        })
+    }
+
+    /// Sets the compression applied to entries appended with `append_serializable`.
+    pub fn compression(mut self, compression: Compression) -> SegmentedWriter {
+        self.compression = compression;
+        self.writer = self.writer.compression(compression);
+        self
+    }
+
+    /// Serializes the entry and appends it split into records, returning the
+    /// number of the segment it was written to and its offset in the segment.
+    pub fn append_serializable<S: Serializable>(&mut self, entry: &S) -> io::Result<(u64, u64)> {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes)?;
+        self.append_bytes(&bytes)
+    }
+
+    /// Appends the bytes of a serialized entry like `append_serializable`.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> io::Result<(u64, u64)> {
+        let mut records = self.writer.entry_records(bytes)?;
+        if self.writer.position() > 0
+            && self.writer.position_after(&records) > self.max_segment_size
+        {
+            self.roll()?;
+            records = self.writer.entry_records(bytes)?;
+        }
+        let offset = self.writer.append_records(&records)?;
+        Ok((self.index, offset))
+    }
+
+    /// Seals the current segment and starts a new one.
+    pub fn roll(&mut self) -> io::Result<()> {
+        self.writer.pad_to_block_boundary()?;
+        self.writer.sync()?;
+        let last_lsn = self.writer.last_lsn();
+        let writer = open_segment(&self.base, self.index + 1, self.format)?
+            .continue_after(last_lsn)
+            .compression(self.compression);
+        self.writer = writer;
+        self.sealed.push(segment_path(&self.base, self.index));
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Syncs the current segment to disk. Sealed segments are
+    /// synced when they are sealed.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.sync()
+    }
+
+    /// Returns the paths of the segments that are no longer written to, in order.
+    pub fn sealed_segments(&self) -> &[PathBuf] {
+        &self.sealed
+    }
+
+    /// Returns the path of the segment being written to.
+    pub fn current_segment(&self) -> PathBuf {
+        segment_path(&self.base, self.index)
+    }
+
+    /// Returns the paths of every segment in order, ending with the current segment.
+    pub fn segments(&self) -> Vec<PathBuf> {
+        let mut segments = self.sealed.clone();
+        segments.push(self.current_segment());
+        segments
+    }
+
+    /// Opens the segments for reading as a single log.
+    pub fn reader(&self) -> io::Result<ChainedLog> {
+        open_segments(&self.base, self.format)
+    }
+
+    /// Returns the LSN of the last appended record, or 0 if the
+    /// log has no records with LSNs.
+    pub fn last_lsn(&self) -> u64 {
+        self.writer.last_lsn()
+    }
+
+    pub fn format(&self) -> BlockFormat {
+        self.format
+    }
+
+    pub fn writer(&self) -> &Writer {
+        &self.writer
+    }
+}
+
+/// Opens the segments of the log at the base path in order as a single log.
+pub fn open_segments<P: AsRef<Path>>(base: P, format: BlockFormat) -> io::Result<ChainedLog> {
+    let base = base.as_ref();
+    let files = segment_numbers(base)?
+        .into_iter()
+        .map(|index| File::open(segment_path(base, index)))
+        .collect::<io::Result<Vec<_>>>()?;
+    ChainedLog::with_block_size(files, format.block_size)
+}
+
+/// Returns the path of the segment with the number.
+pub fn segment_path<P: AsRef<Path>>(base: P, index: u64) -> PathBuf {
+    let mut path = OsString::from(base.as_ref().as_os_str());
+    path.push(format!(".{:06}", index));
+    PathBuf::from(path)
+}
+
+/// Returns the numbers of the segments of the log at the base path in order.
+fn segment_numbers(base: &Path) -> io::Result<Vec<u64>> {
+    let name = match base.file_name().and_then(|name| name.to_str()) {
+        Some(name) => format!("{}.", name),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Segment base path must end in a file name",
+            ))
+        }
+    };
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name();
+        let suffix = match file_name.to_str().and_then(|s| s.strip_prefix(&name)) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+        if suffix.len() >= 6 && suffix.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(index) = suffix.parse() {
+                numbers.push(index);
+            }
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+fn open_segment(base: &Path, index: u64, format: BlockFormat) -> io::Result<Writer> {
+    let file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(segment_path(base, index))?;
+    Writer::with_format(file, format)
+}
@@ -1,9 +1,11 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
+use std::collections::{BTreeSet, HashSet};
+use std::hash::Hash;
 use std::io;
 use std::io::{Cursor, Read, Write};
 
-use super::super::Serializable;
+use super::super::{DeserializeRef, Serializable};
 
 impl Serializable for String {
     fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
@@ -29,6 +31,10 @@ impl Serializable for String {
             io::Error::new(io::ErrorKind::InvalidData, "Error converting bytes to UTF8")
         })
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(4 + self.len() as u64)
+    }
 }
 
 impl Serializable for i32 {
@@ -44,7 +50,32 @@ impl Serializable for i32 {
         bytes.read_exact(&mut buf)?;
 
         let mut rdr = Cursor::new(buf[..].to_vec());
-        Ok(rdr.read_i32::<BigEndian>()?)
+        rdr.read_i32::<BigEndian>()
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(4)
+    }
+}
+
+impl Serializable for u32 {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        let mut num_bytes = Vec::new();
+        num_bytes.write_u32::<BigEndian>(*self)?;
+        bytes.write_all(&num_bytes)?;
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        bytes.read_exact(&mut buf)?;
+
+        let mut num_reader = Cursor::new(buf[..].to_vec());
+        num_reader.read_u32::<BigEndian>()
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(4)
     }
 }
 
@@ -63,4 +94,159 @@ impl Serializable for u64 {
         let mut num_reader = Cursor::new(buf[..].to_vec());
         num_reader.read_u64::<BigEndian>()
     }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(8)
+    }
+}
+
+/// Serialized as the 16 raw bytes of the UUID.
+#[cfg(feature = "uuid")]
+impl Serializable for uuid::Uuid {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        bytes.write_all(self.as_bytes())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<uuid::Uuid> {
+        let mut buf = [0; 16];
+        bytes.read_exact(&mut buf)?;
+        Ok(uuid::Uuid::from_bytes(buf))
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        Ok(16)
+    }
+}
+
+/// Writes the u32 length prefix used by collection encodings.
+fn serialize_len<W: Write>(len: usize, bytes: &mut W) -> io::Result<()> {
+    if len > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Collection is too large to serialize",
+        ));
+    }
+    (len as u32).serialize(bytes)
+}
+
+/// Sums the sizes of a collection's u32 length prefix and its elements.
+fn collection_size<'a, T, I>(len: usize, mut items: I) -> io::Result<u64>
+where
+    T: Serializable + 'a,
+    I: Iterator<Item = &'a T>,
+{
+    if len > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Collection is too large to serialize",
+        ));
+    }
+    items.try_fold(4, |size, item| Ok(size + item.serialized_size()?))
+}
+
+/// Serialized as a u32 length followed by each element.
+/// Element order is the set's iteration order.
+impl<T> Serializable for HashSet<T>
+where
+    T: Serializable + Eq + Hash,
+{
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        serialize_len(self.len(), bytes)?;
+        for item in self.iter() {
+            item.serialize(bytes)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<HashSet<T>> {
+        let len = u32::deserialize(bytes)?;
+        let mut set = HashSet::new();
+        for _ in 0..len {
+            set.insert(T::deserialize(bytes)?);
+        }
+        Ok(set)
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        collection_size(self.len(), self.iter())
+    }
+}
+
+/// Serialized as a u32 length followed by each element in sorted order,
+/// so equal sets always produce identical bytes.
+impl<T> Serializable for BTreeSet<T>
+where
+    T: Serializable + Ord,
+{
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        serialize_len(self.len(), bytes)?;
+        for item in self.iter() {
+            item.serialize(bytes)?;
+        }
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<BTreeSet<T>> {
+        let len = u32::deserialize(bytes)?;
+        let mut set = BTreeSet::new();
+        for _ in 0..len {
+            set.insert(T::deserialize(bytes)?);
+        }
+        Ok(set)
+    }
+
+    fn serialized_size(&self) -> io::Result<u64> {
+        collection_size(self.len(), self.iter())
+    }
+}
+
+/// Splits `len` bytes off the front of `bytes`, failing if there aren't enough.
+fn split_ref(bytes: &[u8], len: usize) -> io::Result<(&[u8], &[u8])> {
+    if bytes.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Not enough bytes to deserialize",
+        ));
+    }
+    Ok(bytes.split_at(len))
+}
+
+impl<'a> DeserializeRef<'a> for &'a [u8] {
+    /// Reads a u32 length prefix followed by that many bytes,
+    /// the same layout `String` serializes to.
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(&'a [u8], &'a [u8])> {
+        let (len, rest) = u32::deserialize_ref(bytes)?;
+        split_ref(rest, len as usize)
+    }
+}
+
+impl<'a> DeserializeRef<'a> for &'a str {
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(&'a str, &'a [u8])> {
+        let (str_bytes, rest) = <&[u8]>::deserialize_ref(bytes)?;
+        let s = std::str::from_utf8(str_bytes).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "Error converting bytes to UTF8")
+        })?;
+        Ok((s, rest))
+    }
+}
+
+impl<'a> DeserializeRef<'a> for i32 {
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(i32, &'a [u8])> {
+        let (mut num_bytes, rest) = split_ref(bytes, 4)?;
+        Ok((num_bytes.read_i32::<BigEndian>()?, rest))
+    }
+}
+
+impl<'a> DeserializeRef<'a> for u32 {
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(u32, &'a [u8])> {
+        let (mut num_bytes, rest) = split_ref(bytes, 4)?;
+        Ok((num_bytes.read_u32::<BigEndian>()?, rest))
+    }
+}
+
+impl<'a> DeserializeRef<'a> for u64 {
+    fn deserialize_ref(bytes: &'a [u8]) -> io::Result<(u64, &'a [u8])> {
+        let (mut num_bytes, rest) = split_ref(bytes, 8)?;
+        Ok((num_bytes.read_u64::<BigEndian>()?, rest))
+    }
 }
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::wal::backend::{FileBackend, LogBackend};
+use crate::wal::redo_log::RedoLog;
+use crate::wal::{LogData, LogError, LogOptions, LogStore, Result};
+
+/// Handle to a redo log shared between threads.
+///
+/// Transactions started through the handle buffer their changes in memory
+/// under a lock of their own, so threads writing to different transactions
+/// don't wait on each other. Committing a transaction takes the log's lock
+/// and flushes the transaction's start, changes and commit to the log
+/// together before applying the changes to the store, so the entries of
+/// each transaction are contiguous in the log.
+///
+/// Aborting a transaction only drops its buffered changes, since nothing
+/// was written to the log for it. Cloning the handle shares the same log.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::collections::HashMap;
+/// use std::io;
+/// use std::thread;
+/// use disk_utils::wal::shared_redo_log::SharedRedoLog;
+/// use disk_utils::wal::{LogData, LogStore};
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Data;
+///
+/// impl LogData for Data {
+///     type Key = i32;
+///     type Value = String;
+/// }
+///
+/// struct Store(HashMap<i32, String>);
+///
+/// impl LogStore<Data> for Store {
+///     fn get(&self, key: &i32) -> Option<String> {
+///         self.0.get(key).cloned()
+///     }
+///     fn remove(&mut self, key: &i32) {
+///         self.0.remove(key);
+///     }
+///     fn update(&mut self, key: i32, val: String) {
+///         self.0.insert(key, val);
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+///     fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// fn main() {
+///     let path = "./files/shared_redo_log_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     # let _ = std::fs::remove_file(path);
+///     let log = SharedRedoLog::new(path, Store(HashMap::new())).unwrap();
+///     let threads: Vec<_> = (0..4)
+///         .map(|i| {
+///             let log = log.clone();
+///             thread::spawn(move || {
+///                 let tid = log.start();
+///                 log.write(tid, i, "value".to_string()).unwrap();
+///                 log.commit(tid).unwrap();
+///             })
+///         })
+///         .collect();
+///     for thread in threads {
+///         thread.join().unwrap();
+///     }
+///     assert_eq!(log.get(0, &3), Some("value".to_string()));
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
+pub struct SharedRedoLog<Data: LogData, Store: LogStore<Data>, Backend: LogBackend = FileBackend> {
+    inner: Arc<SharedInner<Data, Store, Backend>>,
+}
+
+/// Changes buffered by a transaction, in the order they were written,
+/// with None for deleted keys.
+type Buffer<Data> = Arc<Mutex<Vec<(<Data as LogData>::Key, Option<<Data as LogData>::Value>)>>>;
+
+struct SharedInner<Data: LogData, Store: LogStore<Data>, Backend: LogBackend> {
+    /// The log, locked to flush a transaction to it.
+    log: Mutex<RedoLog<Data, Store, Backend>>,
+    last_tid: AtomicU64,
+    /// Changes of the active transactions, each behind its own lock.
+    transactions: RwLock<HashMap<u64, Buffer<Data>>>,
+}
+
+impl<Data, Store> SharedRedoLog<Data, Store>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    pub fn new<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        store: Store,
+    ) -> Result<SharedRedoLog<Data, Store>> {
+        SharedRedoLog::new_with_options(path, store, LogOptions::default())
+    }
+
+    /// Opens and recovers the log like `RedoLog::new_with_options`.
+    pub fn new_with_options<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<SharedRedoLog<Data, Store>> {
+        Ok(SharedRedoLog::from_log(RedoLog::new_with_options(
+            path, store, options,
+        )?))
+    }
+}
+
+impl<Data, Store, Backend> SharedRedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    /// Shares the log between threads. Transactions already active in
+    /// the log can't be written to through the handle.
+    pub fn from_log(log: RedoLog<Data, Store, Backend>) -> SharedRedoLog<Data, Store, Backend> {
+        SharedRedoLog {
+            inner: Arc::new(SharedInner {
+                last_tid: AtomicU64::new(log.last_tid()),
+                log: Mutex::new(log),
+                transactions: RwLock::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Starts a transaction, returning its id.
+    pub fn start(&self) -> u64 {
+        let tid = self.inner.last_tid.fetch_add(1, Ordering::SeqCst) + 1;
+        self.inner
+            .transactions
+            .write()
+            .unwrap()
+            .insert(tid, Arc::new(Mutex::new(Vec::new())));
+        tid
+    }
+
+    /// Buffers the change of the key to the value by the transaction
+    /// until it commits.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write(&self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()> {
+        let changes = self.transaction(tid)?;
+        changes.lock().unwrap().push((key, Some(val)));
+        Ok(())
+    }
+
+    /// Buffers the changes of the keys to the values by the transaction
+    /// until it commits, like `write`.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write_batch<I>(&self, tid: u64, changes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Data::Key, Data::Value)>,
+    {
+        let buffer = self.transaction(tid)?;
+        buffer
+            .lock()
+            .unwrap()
+            .extend(changes.into_iter().map(|(key, val)| (key, Some(val))));
+        Ok(())
+    }
+
+    /// Buffers the deletion of the key by the transaction until it
+    /// commits, like `write`.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn delete(&self, tid: u64, key: Data::Key) -> Result<()> {
+        let changes = self.transaction(tid)?;
+        changes.lock().unwrap().push((key, None));
+        Ok(())
+    }
+
+    /// Returns the value of the key as seen by the transaction: the last
+    /// value the transaction wrote to it, or else the value in the store.
+    pub fn get(&self, tid: u64, key: &Data::Key) -> Option<Data::Value> {
+        if let Ok(changes) = self.transaction(tid) {
+            let changes = changes.lock().unwrap();
+            let written = changes
+                .iter()
+                .rev()
+                .find(|(change_key, _)| change_key == key);
+            if let Some((_, val)) = written {
+                return val.clone();
+            }
+        }
+        self.inner.log.lock().unwrap().get(tid, key)
+    }
+
+    /// Writes the transaction to the log and applies its changes to the
+    /// store, waiting for the transactions committing before it.
+    ///
+    /// If the log can't be flushed before any of the transaction was
+    /// written, the transaction stays active so it can be committed again
+    /// or aborted. Returns `LogError::UnknownTransaction` if the
+    /// transaction isn't active.
+    pub fn commit(&self, tid: u64) -> Result<()> {
+        let buffer = self
+            .inner
+            .transactions
+            .write()
+            .unwrap()
+            .remove(&tid)
+            .ok_or(LogError::UnknownTransaction(tid))?;
+        let changes = buffer.lock().unwrap().clone();
+        let mut log = self.inner.log.lock().unwrap();
+        let result = log.commit_buffered(tid, changes);
+        if result.is_err() && !log.has_finished(tid) {
+            self.inner.transactions.write().unwrap().insert(tid, buffer);
+        }
+        result
+    }
+
+    /// Aborts the transaction, dropping its buffered changes.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn abort(&self, tid: u64) -> Result<()> {
+        self.inner
+            .transactions
+            .write()
+            .unwrap()
+            .remove(&tid)
+            .map(|_| ())
+            .ok_or(LogError::UnknownTransaction(tid))
+    }
+
+    /// Checkpoints the log, waiting for the transactions committing before it.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.inner.log.lock().unwrap().checkpoint()
+    }
+
+    /// Returns the ids of the transactions that haven't finished, in order.
+    pub fn active_transactions(&self) -> Vec<u64> {
+        let mut tids: Vec<_> = self
+            .inner
+            .transactions
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        tids.sort_unstable();
+        tids
+    }
+
+    /// Returns the id of the last transaction started.
+    pub fn last_tid(&self) -> u64 {
+        self.inner.last_tid.load(Ordering::SeqCst)
+    }
+
+    /// Truncates the log like `RedoLog::truncate_before_checkpoint`.
+    pub fn truncate_before_checkpoint(&self) -> Result<bool> {
+        self.inner.log.lock().unwrap().truncate_before_checkpoint()
+    }
+
+    fn transaction(&self, tid: u64) -> Result<Buffer<Data>> {
+        self.inner
+            .transactions
+            .read()
+            .unwrap()
+            .get(&tid)
+            .cloned()
+            .ok_or(LogError::UnknownTransaction(tid))
+    }
+}
+
+impl<Data, Store, Backend> Clone for SharedRedoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    fn clone(&self) -> SharedRedoLog<Data, Store, Backend> {
+        SharedRedoLog {
+            inner: self.inner.clone(),
+        }
+    }
+}
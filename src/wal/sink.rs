@@ -0,0 +1,225 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::mem;
+
+use crate::wal::record::{Payload, Record, RecordType};
+use crate::wal::writer::{SetLen, Writer};
+use crate::wal::Compression;
+use crate::Serializable;
+
+/// Splits an entry into records as it is serialized, appending each
+/// record to the writer once it is full.
+///
+/// The sink buffers at most one record's payload, so the whole entry never
+/// has to be in memory at once. The records are the same as the ones
+/// `Writer::append_bytes` appends for the serialized entry, so both write
+/// the same bytes to the log. An entry that fits in its first record is
+/// only written when the sink is finished.
+///
+/// The entry isn't complete until `finish` is called. If the sink is
+/// dropped or fails partway through a large entry, the records already
+/// appended are left in the log without the entry's last record.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::io::{Cursor, Write};
+/// use disk_utils::wal::sink::RecordSink;
+/// use disk_utils::wal::writer::Writer;
+///
+/// fn main() {
+///     let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+///     let mut sink = RecordSink::new(&mut writer).unwrap();
+///     for _ in 0..100 {
+///         sink.write_all(&[1; 1000]).unwrap();
+///     }
+///     let offset = sink.finish().unwrap();
+///     assert_eq!(offset, 0);
+///     assert_eq!(writer.last_lsn(), 4);
+/// }
+/// ```
+pub struct RecordSink<'a, W: 'a = File> {
+    writer: &'a mut Writer<W>,
+    buffer: Vec<u8>,
+    /// Size of the payload of the record being filled.
+    record_size: usize,
+    max_record_size: usize,
+    records: usize,
+    first_offset: Option<u64>,
+    max_buffered: usize,
+}
+
+impl<'a, W: Read + Write + Seek> RecordSink<'a, W> {
+    /// Creates a sink appending an entry to the writer.
+    ///
+    /// Fails if the writer compresses entries, since an entry has to be
+    /// serialized in full before it can be compressed.
+    pub fn new(writer: &'a mut Writer<W>) -> io::Result<RecordSink<'a, W>> {
+        check_uncompressed(writer)?;
+        Ok(RecordSink::uncompressed(writer))
+    }
+
+    /// Creates a sink appending to a writer known not to compress entries.
+    fn uncompressed(writer: &'a mut Writer<W>) -> RecordSink<'a, W> {
+        let record_size = writer.first_record_size();
+        let max_record_size = writer.record_size_limit();
+        RecordSink {
+            writer,
+            buffer: Vec::new(),
+            record_size,
+            max_record_size,
+            records: 0,
+            first_offset: None,
+            max_buffered: 0,
+        }
+    }
+
+    /// Appends the entry's last record, returning the offset
+    /// in the file of the entry's first record.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.append_last_record()
+    }
+
+    /// Returns the most bytes the sink has buffered at once.
+    pub fn max_buffered(&self) -> usize {
+        self.max_buffered
+    }
+
+    fn append_last_record(&mut self) -> io::Result<u64> {
+        self.append_record(true)?;
+        Ok(self.first_offset.unwrap())
+    }
+
+    /// Appends the buffered bytes as the entry's next record.
+    fn append_record(&mut self, last: bool) -> io::Result<()> {
+        let record_type = match (self.records, last) {
+            (0, true) if self.buffer.is_empty() => RecordType::Zero,
+            (0, true) => RecordType::Full,
+            (0, false) => RecordType::First,
+            (_, true) => RecordType::Last,
+            (_, false) => RecordType::Middle,
+        };
+        let record = Record::with_payload(record_type, Payload::from(&self.buffer[..]))?;
+        let offset = self.writer.append(&record)?;
+        self.first_offset.get_or_insert(offset);
+        self.records += 1;
+        self.buffer.clear();
+        self.record_size = self.max_record_size;
+        Ok(())
+    }
+}
+
+impl<'a, W: Read + Write + Seek> Write for RecordSink<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            // A full record is only appended once more bytes arrive,
+            // since the entry's last record has a different type.
+            if self.buffer.len() == self.record_size {
+                self.append_record(false)?;
+            }
+            let len = cmp::min(self.record_size - self.buffer.len(), buf.len() - written);
+            self.buffer.extend_from_slice(&buf[written..written + len]);
+            self.max_buffered = cmp::max(self.max_buffered, self.buffer.len());
+            written += len;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Entries smaller than this are serialized into a buffer and appended in
+/// one go by `write_serializable_streaming`, which only streams larger
+/// entries into records.
+pub const STREAMING_THRESHOLD: usize = 64 * 1024;
+
+/// Serializes the entry directly into records appended to the writer,
+/// returning the offset in the file of the entry's first record.
+///
+/// Writes the same records as `Writer::append_serializable` without
+/// serializing the entry into a buffer first, once it's larger than
+/// `STREAMING_THRESHOLD`. See `RecordSink`. If serializing or appending
+/// fails partway, the records already appended are removed from the log.
+pub fn write_serializable_streaming<W, S>(writer: &mut Writer<W>, entry: &S) -> io::Result<u64>
+where
+    W: Read + Write + Seek + SetLen,
+    S: Serializable,
+{
+    check_uncompressed(writer)?;
+    let start = writer.position();
+    let mut sink = ThresholdSink {
+        writer: Some(writer),
+        buffer: Vec::new(),
+        sink: None,
+    };
+    let result = entry.serialize(&mut sink).and_then(|_| sink.finish());
+    let writer = sink.into_writer();
+    if result.is_err() && writer.position() != start {
+        // The entry's error is returned even if the log can't be cut back,
+        // since recovery removes the torn entry anyway.
+        let _ = writer.truncate(start);
+    }
+    result
+}
+
+fn check_uncompressed<W: Read + Write + Seek>(writer: &Writer<W>) -> io::Result<()> {
+    if writer.entry_compression() != Compression::None {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Compressed entries can't be streamed into records",
+        ));
+    }
+    Ok(())
+}
+
+/// Buffers an entry until it grows past `STREAMING_THRESHOLD`, then
+/// streams the rest of it into a `RecordSink`.
+struct ThresholdSink<'a, W: 'a> {
+    writer: Option<&'a mut Writer<W>>,
+    buffer: Vec<u8>,
+    sink: Option<RecordSink<'a, W>>,
+}
+
+impl<'a, W: Read + Write + Seek> ThresholdSink<'a, W> {
+    /// Appends the entry, returning the offset of its first record.
+    fn finish(&mut self) -> io::Result<u64> {
+        match self.sink {
+            Some(ref mut sink) => sink.append_last_record(),
+            None => self.writer.as_mut().unwrap().append_bytes(&self.buffer),
+        }
+    }
+
+    fn into_writer(self) -> &'a mut Writer<W> {
+        match self.sink {
+            Some(sink) => sink.writer,
+            None => self.writer.unwrap(),
+        }
+    }
+}
+
+impl<'a, W: Read + Write + Seek> Write for ThresholdSink<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.sink.is_none() && self.buffer.len() + buf.len() > STREAMING_THRESHOLD {
+            let buffer = mem::take(&mut self.buffer);
+            let sink = RecordSink::uncompressed(self.writer.take().unwrap());
+            self.sink.get_or_insert(sink).write_all(&buffer)?;
+        }
+        match self.sink {
+            Some(ref mut sink) => sink.write(buf),
+            None => {
+                self.buffer.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::wal::backend::{rewrite_path, sync_parent};
+use crate::wal::header::FileHeader;
+use crate::wal::iterator::{BlockError, ReadDirection, WalIterator};
+use crate::wal::record::BlockFormat;
+use crate::wal::writer::Writer;
+use crate::wal::{read_serializable, LogData, SerializeError};
+use crate::Serializable;
+
+/// Bytes the first entry of a snapshot starts with, so a log can't be
+/// loaded as a snapshot.
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"DUSNAPSH";
+
+/// Version of the snapshot format written by `Snapshot::write`.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Store that can list everything in it, so a redo log can write a
+/// snapshot of it with `RedoLog::snapshot`.
+pub trait SnapshotSource<Data: LogData> {
+    /// Returns every key in the store with its value, in any order.
+    fn iter_all(&self) -> Box<dyn Iterator<Item = (Data::Key, Data::Value)> + '_>;
+}
+
+/// Where in a redo log a snapshot of its store was taken.
+///
+/// A snapshot file holds the position followed by every key and value in
+/// the store, written as entries of a log file so each of them is checked
+/// against its record checksums, and ends with the number of keys, so a
+/// snapshot missing its last keys isn't loaded.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use disk_utils::wal::snapshot::Snapshot;
+/// use disk_utils::wal::LogData;
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Data;
+///
+/// impl LogData for Data {
+///     type Key = i32;
+///     type Value = String;
+/// }
+///
+/// fn main() {
+///     let path = "./files/snapshot_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     let snapshot = Snapshot {
+///         lsn: 10,
+///         last_tid: 3,
+///         active: vec![],
+///     };
+///     let values = vec![(1, "a".to_string()), (2, "b".to_string())];
+///     snapshot.write::<Data, _, _>(path, values.clone()).unwrap();
+///
+///     let (loaded, entries) = Snapshot::load::<Data, _>(path).unwrap();
+///     assert_eq!(loaded, snapshot);
+///     let loaded: Vec<_> = entries.map(Result::unwrap).collect();
+///     assert_eq!(loaded, values);
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// LSN of the last record in the log when the snapshot was taken.
+    /// The store has the changes of the transactions committed at or
+    /// before it.
+    pub lsn: u64,
+    /// Id of the last transaction started when the snapshot was taken.
+    pub last_tid: u64,
+    /// Transactions active or prepared when the snapshot was taken, in
+    /// order. Recovery replays them from the log if they commit.
+    pub active: Vec<u64>,
+}
+
+impl Snapshot {
+    /// Writes the snapshot with the keys and values to the path.
+    ///
+    /// The snapshot is written to a new file that atomically replaces the
+    /// file at the path once it's synced, so a crash leaves either the old
+    /// snapshot or the new one.
+    pub fn write<Data, P, I>(&self, path: &P, entries: I) -> io::Result<()>
+    where
+        Data: LogData,
+        P: AsRef<Path> + ?Sized,
+        I: IntoIterator<Item = (Data::Key, Data::Value)>,
+    {
+        let path = path.as_ref();
+        let temp = rewrite_path(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        let mut writer = Writer::with_header(file, BlockFormat::default())?;
+        writer.append_serializable(&SnapshotEntry::<Data>::Begin(self.clone()))?;
+        let mut count = 0;
+        for (key, value) in entries {
+            writer.append_serializable(&SnapshotEntry::<Data>::Pair(key, value))?;
+            count += 1;
+        }
+        writer.append_serializable(&SnapshotEntry::<Data>::End(count))?;
+        writer.sync()?;
+        drop(writer);
+        fs::rename(&temp, path)?;
+        sync_parent(path)
+    }
+
+    /// Reads the snapshot at the path, returning it along with an iterator
+    /// over its keys and values.
+    ///
+    /// The iterator returns an error if a key or value can't be read, or
+    /// if the snapshot ends before all of its keys.
+    pub fn load<Data, P>(path: &P) -> io::Result<(Snapshot, SnapshotEntries<Data>)>
+    where
+        Data: LogData,
+        P: AsRef<Path> + ?Sized,
+    {
+        let mut file = File::open(path)?;
+        let format = match FileHeader::read(&mut file)? {
+            Some(header) => header.format(),
+            None => return Err(invalid_snapshot("Snapshot has no header")),
+        };
+        let mut iter = WalIterator::owned(file, ReadDirection::Forward, format)
+            .map_err(|err| into_io_error(err.into()))?;
+        let snapshot = match read_serializable(&mut iter).map_err(into_io_error)? {
+            SnapshotEntry::<Data>::Begin(snapshot) => snapshot,
+            _ => return Err(invalid_snapshot("Snapshot doesn't start with its position")),
+        };
+        let entries = SnapshotEntries {
+            iter,
+            count: 0,
+            done: false,
+            _data: PhantomData,
+        };
+        Ok((snapshot, entries))
+    }
+}
+
+/// Iterator over the keys and values of a snapshot, returned by
+/// `Snapshot::load`.
+pub struct SnapshotEntries<Data: LogData> {
+    iter: WalIterator<'static, File>,
+    /// Keys read so far.
+    count: u64,
+    /// Whether the end of the snapshot or an error was returned.
+    done: bool,
+    _data: PhantomData<Data>,
+}
+
+impl<Data: LogData> SnapshotEntries<Data> {
+    fn read_next(&mut self) -> io::Result<Option<(Data::Key, Data::Value)>> {
+        let entry = match read_serializable(&mut self.iter) {
+            Ok(entry) => entry,
+            Err(SerializeError::OutOfRecords) | Err(SerializeError::TornEntry { .. }) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Snapshot ends before all of its keys",
+                ))
+            }
+            Err(err) => return Err(into_io_error(err)),
+        };
+        match entry {
+            SnapshotEntry::<Data>::Pair(key, value) => {
+                self.count += 1;
+                Ok(Some((key, value)))
+            }
+            SnapshotEntry::End(count) if count == self.count => Ok(None),
+            SnapshotEntry::End(_) => Err(invalid_snapshot("Snapshot is missing keys")),
+            SnapshotEntry::Begin(_) => Err(invalid_snapshot("Snapshot has two positions")),
+        }
+    }
+}
+
+impl<Data: LogData> Iterator for SnapshotEntries<Data> {
+    type Item = io::Result<(Data::Key, Data::Value)>;
+
+    fn next(&mut self) -> Option<io::Result<(Data::Key, Data::Value)>> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_next();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+/// Entry of a snapshot file.
+enum SnapshotEntry<Data: LogData> {
+    Begin(Snapshot),
+    Pair(Data::Key, Data::Value),
+    /// Number of keys in the snapshot.
+    End(u64),
+}
+
+const SNAPSHOT_BEGIN: u8 = 0;
+const SNAPSHOT_PAIR: u8 = 1;
+const SNAPSHOT_END: u8 = 2;
+
+impl<Data: LogData> Serializable for SnapshotEntry<Data> {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        match *self {
+            SnapshotEntry::Begin(ref snapshot) => {
+                if snapshot.active.len() > u32::MAX as usize {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Too many active transactions in snapshot",
+                    ));
+                }
+                bytes.write_all(&[SNAPSHOT_BEGIN])?;
+                bytes.write_all(&SNAPSHOT_MAGIC)?;
+                bytes.write_all(&[SNAPSHOT_VERSION])?;
+                snapshot.lsn.serialize(bytes)?;
+                snapshot.last_tid.serialize(bytes)?;
+                (snapshot.active.len() as u32).serialize(bytes)?;
+                for tid in snapshot.active.iter() {
+                    tid.serialize(bytes)?;
+                }
+            }
+            SnapshotEntry::Pair(ref key, ref value) => {
+                bytes.write_all(&[SNAPSHOT_PAIR])?;
+                key.serialize(bytes)?;
+                value.serialize(bytes)?;
+            }
+            SnapshotEntry::End(count) => {
+                bytes.write_all(&[SNAPSHOT_END])?;
+                count.serialize(bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<SnapshotEntry<Data>> {
+        let mut tag = [0; 1];
+        bytes.read_exact(&mut tag)?;
+        match tag[0] {
+            SNAPSHOT_BEGIN => {
+                let mut magic = [0; 8];
+                bytes.read_exact(&mut magic)?;
+                if magic != SNAPSHOT_MAGIC {
+                    return Err(invalid_snapshot("Invalid snapshot magic"));
+                }
+                let mut version = [0; 1];
+                bytes.read_exact(&mut version)?;
+                if version[0] != SNAPSHOT_VERSION {
+                    return Err(invalid_snapshot("Unsupported snapshot version"));
+                }
+                let lsn = u64::deserialize(bytes)?;
+                let last_tid = u64::deserialize(bytes)?;
+                let len = u32::deserialize(bytes)?;
+                let mut active = Vec::new();
+                for _ in 0..len {
+                    active.push(u64::deserialize(bytes)?);
+                }
+                Ok(SnapshotEntry::Begin(Snapshot {
+                    lsn,
+                    last_tid,
+                    active,
+                }))
+            }
+            SNAPSHOT_PAIR => {
+                let key = Data::Key::deserialize(bytes)?;
+                let value = Data::Value::deserialize(bytes)?;
+                Ok(SnapshotEntry::Pair(key, value))
+            }
+            SNAPSHOT_END => Ok(SnapshotEntry::End(u64::deserialize(bytes)?)),
+            _ => Err(invalid_snapshot("Invalid snapshot entry type")),
+        }
+    }
+}
+
+fn invalid_snapshot(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Converts the error into an I/O error, keeping it as the source unless
+/// it already wraps one.
+fn into_io_error(err: SerializeError) -> io::Error {
+    match err {
+        SerializeError::IoError(err) | SerializeError::BlockError(BlockError::IoError(err)) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::wal::iterator::{BlockSource, Cursor, ReadDirection, WalIterator};
+use crate::wal::record::{BlockFormat, RecordError};
+use crate::wal::writer::AppendNotifier;
+use crate::wal::{read_serializable, SerializeError, SerializeResult};
+use crate::Serializable;
+
+/// Reads the entries of a log as another handle appends them, like a
+/// change data capture consumer following a log written in the same process.
+///
+/// `try_next` returns None once every complete entry appended so far has
+/// been read, and the next call continues from `position`. An entry the
+/// writer is partway through appending isn't returned until all of its
+/// records are written. With the writer's `AppendNotifier`, `wait_next`
+/// blocks until the writer appends instead of returning None.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::fs::OpenOptions;
+/// use disk_utils::wal::iterator::SharedFile;
+/// use disk_utils::wal::record::BlockFormat;
+/// use disk_utils::wal::tail::TailIterator;
+/// use disk_utils::wal::writer::Writer;
+///
+/// fn main() {
+///     let file = OpenOptions::new()
+///         .read(true)
+///         .append(true)
+///         .create(true)
+///         .open("./files/tail_doc_example")
+///         .unwrap();
+///     let mut writer = Writer::new(file).unwrap();
+///     let reader = SharedFile::new(writer.file()).unwrap();
+///     let mut tail = TailIterator::<_, String>::new(reader, BlockFormat::default());
+///
+///     writer.append_serializable(&"first".to_string()).unwrap();
+///     assert_eq!(tail.try_next().unwrap(), Some("first".to_string()));
+///     assert_eq!(tail.try_next().unwrap(), None);
+///
+///     writer.append_serializable(&"second".to_string()).unwrap();
+///     assert_eq!(tail.try_next().unwrap(), Some("second".to_string()));
+///     # std::fs::remove_file("./files/tail_doc_example").unwrap();
+/// }
+/// ```
+pub struct TailIterator<R, S> {
+    source: R,
+    format: BlockFormat,
+    /// Position after the last entry returned, or None for the start of the log.
+    position: Option<Cursor>,
+    /// Position after the last entry read from the log.
+    read_to: Option<Cursor>,
+    /// Entries read from the log but not returned yet, with the
+    /// position after each of them.
+    ready: VecDeque<(S, Cursor)>,
+    notifier: Option<AppendNotifier>,
+}
+
+impl<R: BlockSource, S: Serializable> TailIterator<R, S> {
+    /// Creates an iterator reading the log's entries from its start.
+    pub fn new(source: R, format: BlockFormat) -> TailIterator<R, S> {
+        TailIterator {
+            source,
+            format,
+            position: None,
+            read_to: None,
+            ready: VecDeque::new(),
+            notifier: None,
+        }
+    }
+
+    /// Creates an iterator continuing from a position returned by
+    /// `position`, or the cursor of an iterator reading forward.
+    pub fn from_cursor(source: R, format: BlockFormat, cursor: Cursor) -> TailIterator<R, S> {
+        TailIterator {
+            position: Some(cursor),
+            read_to: Some(cursor),
+            ..TailIterator::new(source, format)
+        }
+    }
+
+    /// Sets the notifier of the writer appending to the log, which
+    /// `wait_next` waits on.
+    pub fn notifier(mut self, notifier: AppendNotifier) -> TailIterator<R, S> {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Returns the position after the last entry returned, which
+    /// `from_cursor` continues reading from.
+    pub fn position(&self) -> Option<Cursor> {
+        self.position
+    }
+
+    /// Returns the next entry, or None if the entries appended so far have
+    /// all been returned.
+    pub fn try_next(&mut self) -> SerializeResult<Option<S>> {
+        if self.ready.is_empty() {
+            self.read_ready()?;
+        }
+        Ok(self.ready.pop_front().map(|(entry, cursor)| {
+            self.position = Some(cursor);
+            entry
+        }))
+    }
+
+    /// Returns the next entry, waiting up to the timeout for the writer to
+    /// append it, or forever without a timeout. Returns None if the timeout
+    /// passes or the writer is dropped first, or right away if the iterator
+    /// has no notifier to wait on.
+    pub fn wait_next(&mut self, timeout: Option<Duration>) -> SerializeResult<Option<S>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            // Appends after the count is read are waited for even if
+            // they happen before the wait starts.
+            let seen = self.notifier.as_ref().map(AppendNotifier::appends);
+            if let Some(entry) = self.try_next()? {
+                return Ok(Some(entry));
+            }
+            let (notifier, seen) = match (self.notifier.as_ref(), seen) {
+                (Some(notifier), Some(seen)) => (notifier, seen),
+                _ => return Ok(None),
+            };
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) => Some(timeout),
+                    None => return Ok(None),
+                },
+                None => None,
+            };
+            if !notifier.wait(seen, timeout) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Reads the complete entries appended after the last entry read.
+    fn read_ready(&mut self) -> SerializeResult<()> {
+        let mut iter = match self.read_to {
+            Some(cursor) => WalIterator::resume_with_format(&mut self.source, cursor, self.format)?,
+            None => {
+                WalIterator::with_format(&mut self.source, ReadDirection::Forward, self.format)?
+            }
+        };
+        loop {
+            match read_serializable::<S>(&mut iter) {
+                Ok(entry) => {
+                    let cursor = iter.cursor();
+                    self.ready.push_back((entry, cursor));
+                    self.read_to = Some(cursor);
+                }
+                Err(ref err) if is_incomplete(err) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// Returns whether reading an entry failed only because the writer hasn't
+/// finished appending it: the log ends before the entry, partway through
+/// its chain of records, or partway through one of its records.
+fn is_incomplete(err: &SerializeError) -> bool {
+    match *err {
+        SerializeError::OutOfRecords | SerializeError::TornEntry { .. } => true,
+        SerializeError::BlockError(ref err) | SerializeError::Corrupted(ref err) => {
+            err.record_error().is_some_and(RecordError::is_truncation)
+        }
+        _ => false,
+    }
+}
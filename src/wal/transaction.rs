@@ -0,0 +1,233 @@
+use std::collections::{HashSet, VecDeque};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::wal::{LogData, Result};
+
+/// A log transactions are written to, implemented by `RedoLog` and `UndoLog`.
+pub trait TransactionLog<Data: LogData> {
+    fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()>;
+    fn write_batch(&mut self, tid: u64, changes: Vec<(Data::Key, Data::Value)>) -> Result<()>;
+    fn delete(&mut self, tid: u64, key: Data::Key) -> Result<()>;
+    fn commit(&mut self, tid: u64) -> Result<()>;
+    fn abort(&mut self, tid: u64) -> Result<()>;
+}
+
+/// A transaction that is aborted when dropped without being committed,
+/// returned by `RedoLog::transaction` and `UndoLog::transaction`.
+///
+/// The guard mutably borrows the log for as long as the transaction is
+/// open, so other transactions can't be written to the log at the same
+/// time through the log itself. Interleaved transactions still have to
+/// use the log's `start`, `write` and `commit` methods.
+///
+/// Aborting on drop ignores errors writing the abort to the log; call
+/// `abort` to handle them. Transactions left unfinished in the log are
+/// aborted by recovery anyway.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::collections::HashMap;
+/// use std::io;
+/// use disk_utils::wal::undo_log::UndoLog;
+/// use disk_utils::wal::{LogData, LogStore};
+///
+/// #[derive(Clone, PartialEq, Debug)]
+/// struct Data;
+///
+/// impl LogData for Data {
+///     type Key = i32;
+///     type Value = String;
+/// }
+///
+/// struct Store(HashMap<i32, String>);
+///
+/// impl LogStore<Data> for Store {
+///     fn get(&self, key: &i32) -> Option<String> {
+///         self.0.get(key).cloned()
+///     }
+///     fn remove(&mut self, key: &i32) {
+///         self.0.remove(key);
+///     }
+///     fn update(&mut self, key: i32, val: String) {
+///         self.0.insert(key, val);
+///     }
+///     fn flush(&mut self) -> io::Result<()> {
+///         Ok(())
+///     }
+///     fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// fn main() {
+///     let path = "./files/transaction_guard_doc";
+///     # std::fs::create_dir_all("./files").unwrap();
+///     # let _ = std::fs::remove_file(path);
+///     let mut log = UndoLog::new(path, Store(HashMap::new())).unwrap();
+///     {
+///         let mut txn = log.transaction();
+///         txn.write(1, "value".to_string()).unwrap();
+///         // Dropped without committing, so the write is rolled back.
+///     }
+///     let mut txn = log.transaction();
+///     txn.write(2, "value".to_string()).unwrap();
+///     txn.commit().unwrap();
+///     # std::fs::remove_file(path).unwrap();
+/// }
+/// ```
+pub struct TransactionGuard<'a, Data: LogData, Log: TransactionLog<Data> + 'a> {
+    log: &'a mut Log,
+    tid: u64,
+    finished: bool,
+    _data: PhantomData<Data>,
+}
+
+impl<'a, Data: LogData, Log: TransactionLog<Data> + 'a> TransactionGuard<'a, Data, Log> {
+    /// Creates a guard for the transaction that was started in the log.
+    pub(crate) fn new(log: &'a mut Log, tid: u64) -> TransactionGuard<'a, Data, Log> {
+        TransactionGuard {
+            log,
+            tid,
+            finished: false,
+            _data: PhantomData,
+        }
+    }
+
+    /// Returns the id of the transaction.
+    pub fn tid(&self) -> u64 {
+        self.tid
+    }
+
+    pub fn write(&mut self, key: Data::Key, val: Data::Value) -> Result<()> {
+        self.log.write(self.tid, key, val)
+    }
+
+    pub fn write_batch<I>(&mut self, changes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Data::Key, Data::Value)>,
+    {
+        self.log
+            .write_batch(self.tid, changes.into_iter().collect())
+    }
+
+    pub fn delete(&mut self, key: Data::Key) -> Result<()> {
+        self.log.delete(self.tid, key)
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        self.log.commit(self.tid)
+    }
+
+    pub fn abort(mut self) -> Result<()> {
+        self.finished = true;
+        self.log.abort(self.tid)
+    }
+}
+
+impl<'a, Data: LogData, Log: TransactionLog<Data> + 'a> Drop for TransactionGuard<'a, Data, Log> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.log.abort(self.tid);
+        }
+    }
+}
+
+/// Ids of finished transactions, which `start_with_tid` can't reuse while
+/// recovery could read their entries along with the new transaction's.
+///
+/// Recovery reads back to the begin entry of the last ended checkpoint,
+/// or to the start of the oldest transaction active when it began. Once a
+/// checkpoint ends that none of the transactions active when an earlier
+/// checkpoint began were still active at, recovery never reads the
+/// entries before that earlier checkpoint, so the ids finished before it
+/// began are forgotten.
+#[derive(Debug, Default)]
+pub(crate) struct FinishedTids {
+    /// Ids finished since the last checkpoint began.
+    current: HashSet<u64>,
+    /// For each checkpoint that began, oldest first, the ids finished
+    /// before it and after the checkpoint before it, along with the
+    /// transactions active when it began.
+    checkpoints: VecDeque<(HashSet<u64>, HashSet<u64>)>,
+}
+
+impl FinishedTids {
+    pub(crate) fn new(tids: HashSet<u64>) -> FinishedTids {
+        FinishedTids {
+            current: tids,
+            checkpoints: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, tid: u64) {
+        self.current.insert(tid);
+    }
+
+    pub(crate) fn contains(&self, tid: u64) -> bool {
+        self.current.contains(&tid)
+            || self
+                .checkpoints
+                .iter()
+                .any(|(finished, _)| finished.contains(&tid))
+    }
+
+    /// Records that a checkpoint began while the transactions were active.
+    pub(crate) fn checkpoint_began(&mut self, active: &[u64]) {
+        let finished = mem::take(&mut self.current);
+        self.checkpoints
+            .push_back((finished, active.iter().cloned().collect()));
+    }
+
+    /// Forgets the ids recovery no longer reads after the checkpoint that
+    /// began while the transactions were active ended.
+    pub(crate) fn checkpoint_ended(&mut self, active: &[u64]) {
+        let last = self
+            .checkpoints
+            .iter()
+            .rposition(|(_, was_active)| active.iter().all(|tid| !was_active.contains(tid)));
+        if let Some(last) = last {
+            self.checkpoints.drain(..=last);
+        }
+    }
+
+    /// Returns the number of ids kept.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.current.len()
+            + self
+                .checkpoints
+                .iter()
+                .map(|(finished, _)| finished.len())
+                .sum::<usize>()
+    }
+}
+
+#[test]
+fn test_finished_tids() {
+    let mut tids = FinishedTids::new(vec![1, 2].into_iter().collect());
+    tids.checkpoint_began(&[3]);
+    tids.insert(4);
+    tids.checkpoint_ended(&[3]);
+    assert!(tids.contains(1) && tids.contains(4));
+
+    // Transaction 3 was still active when the second checkpoint began.
+    tids.checkpoint_began(&[3, 5]);
+    tids.insert(3);
+    tids.checkpoint_ended(&[3, 5]);
+    assert_eq!(tids.len(), 4);
+
+    // Nothing active when the first checkpoint began is still active.
+    tids.insert(5);
+    tids.checkpoint_began(&[6]);
+    tids.checkpoint_ended(&[6]);
+    assert!(!tids.contains(1) && !tids.contains(4));
+    assert!(tids.contains(3) && tids.contains(5));
+
+    tids.checkpoint_began(&[]);
+    tids.checkpoint_ended(&[]);
+    assert_eq!(tids.len(), 0);
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use crate::wal::backend::LogBackend;
+use crate::wal::entries::{Checkpoint, SingleLogEntry, Transaction};
+use crate::wal::iterator::{ReadDirection, WalIterator};
+use crate::wal::writer::Writer;
+use crate::wal::{read_serializable_backwards_at, LogData, LogOptions, Result, SerializeError};
+
+/// Returns the offset of the first entry recovery still needs once the
+/// last checkpoint in the log has ended: the offset of the checkpoint's
+/// begin entry. Returns None if no checkpoint in the log has ended, or
+/// if there are no entries before the offset.
+///
+/// With `keep_active`, the entries of the transactions that were active
+/// when the checkpoint began are needed as well, so the offset is of the
+/// earliest of their start entries instead if it's before the checkpoint.
+pub(crate) fn checkpoint_start<Data: LogData, B: LogBackend>(
+    writer: &mut Writer<B>,
+    options: &LogOptions,
+    keep_active: bool,
+) -> Result<Option<u64>> {
+    let format = writer.format();
+    let first_offset = writer.file().first_offset();
+    let mut iter = WalIterator::with_format(writer.file_mut(), ReadDirection::Backward, format)?
+        .on_corruption(options.on_corruption);
+    iter.start_at(first_offset)?;
+    let mut ended = false;
+    let mut active: Option<HashSet<u64>> = None;
+    loop {
+        let (entry, range) = match read_serializable_backwards_at(&mut iter) {
+            Ok(entry) => entry,
+            // The log starts before everything recovery needs was found.
+            Err(SerializeError::OutOfRecords) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match (entry, active.as_mut()) {
+            (SingleLogEntry::<Data>::Checkpoint(Checkpoint::End), None) => ended = true,
+            (SingleLogEntry::Checkpoint(Checkpoint::Begin(tids)), None) if ended => {
+                if !keep_active || tids.is_empty() {
+                    return first_needed(&mut iter, range.start);
+                }
+                active = Some(tids.into_iter().collect());
+            }
+            (SingleLogEntry::Transaction(Transaction::Start(tid)), Some(active)) => {
+                active.remove(&tid);
+                if active.is_empty() {
+                    return first_needed(&mut iter, range.start);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the offset of the entry the iterator last read backwards, unless
+/// it's the first entry in the log.
+fn first_needed<B: LogBackend>(iter: &mut WalIterator<B>, offset: u64) -> Result<Option<u64>> {
+    Ok(iter.peek_back()?.map(|_| offset))
+}
+
+/// Rewrites the log to hold only its records from `start` on, keeping
+/// their LSNs, and returns a writer appending to the new log.
+///
+/// The records are copied to a backend from `LogBackend::create_rewrite`,
+/// which replaces the log once it is synced, so a crash leaves either the
+/// old log or the new one. `offset` is translated to the offset in the new log of the
+/// record that was at it in the old log, or None if it was removed.
+pub(crate) fn rewrite_log<B: LogBackend>(
+    writer: &mut Writer<B>,
+    options: &LogOptions,
+    start: u64,
+    offset: &mut Option<u64>,
+) -> Result<Writer<B>> {
+    let last_lsn = writer.last_lsn();
+    let mut translated = None;
+    let rewritten = {
+        let mut new_writer = options.writer_for(writer.file_mut().create_rewrite()?)?;
+        let format = writer.format();
+        let mut iter = WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, format)?;
+        iter.seek(start)?;
+        while let Some(record) = iter.try_next()? {
+            let new_offset = new_writer.append_copy(&record)?;
+            if Some(iter.last_record_range().unwrap().start) == *offset {
+                translated = Some(new_offset);
+            }
+        }
+        new_writer.sync()?;
+        new_writer.into_inner()
+    };
+    let backend = writer.file_mut().replace(rewritten)?;
+    *offset = translated;
+    Ok(options.writer_for(backend)?.continue_after(last_lsn))
+}
@@ -1,26 +1,69 @@
 use std::cmp;
-use std::collections::{HashSet, VecDeque};
-use std::fs::{File, OpenOptions};
+use std::collections::{vec_deque, HashSet, VecDeque};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use super::super::Serializable;
-
-use crate::wal::entries::{ChangeEntry, Checkpoint, InsertEntry, SingleLogEntry, Transaction};
-use crate::wal::iterator::{ReadDirection, WalIterator};
+use crate::wal::backend::{FileBackend, LogBackend};
+use crate::wal::entries::{
+    ChangeEntry, Checkpoint, DeleteEntry, InsertEntry, MultiChangeEntry, SingleLogEntry,
+    Transaction,
+};
+use crate::wal::iterator::{ReadDirection, Stats, WalIterator};
+use crate::wal::manager::Wal;
+use crate::wal::metrics::{MetricEvent, Metrics, MetricsRecorder};
+use crate::wal::options::LogBuilder;
+use crate::wal::tail::TailIterator;
+use crate::wal::transaction::{FinishedTids, TransactionGuard, TransactionLog};
+use crate::wal::truncate::{checkpoint_start, rewrite_log};
+use crate::wal::writer::Writer;
 use crate::wal::{
-    append_to_file, read_serializable_backwards, split_bytes_into_records, LogData, LogStore,
-    RecoverState, Result,
+    reached_recovery_start, recover_entry, truncate_torn_entry, LogData, LogError, LogOptions,
+    LogSource, LogStore, Outcome, RecoverState, Result, SyncPoint, WriteAheadPolicy,
 };
+use crate::Serializable;
+
+/// What `UndoLog::checkpoint` did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointStatus {
+    /// A new checkpoint began.
+    Started,
+    /// A checkpoint is still waiting for the transactions active when it
+    /// began to finish, so no new checkpoint began.
+    AlreadyPending,
+}
 
-const MAX_RECORD_SIZE: usize = 1024;
+/// Builder for the options a `UndoLog` is opened with.
+pub type UndoLogOptions<Data, Store> = LogBuilder<UndoLog<Data, Store>>;
 
-pub struct UndoLog<Data: LogData, Store: LogStore<Data>> {
-    file: File,
+pub struct UndoLog<Data: LogData, Store: LogStore<Data>, Backend: LogBackend = FileBackend> {
+    writer: Writer<Backend>,
     mem_log: VecDeque<SingleLogEntry<Data>>,
+    /// Serialized size of the entries in `mem_log`.
+    pending_bytes: u64,
     last_tid: u64,
     checkpoint_tids: Option<Vec<u64>>,
+    /// When the pending checkpoint began.
+    checkpoint_started: Option<Instant>,
     active_tids: HashSet<u64>,
+    /// Transactions prepared with `prepare` that haven't been resolved.
+    prepared_tids: HashSet<u64>,
+    /// Ids of the finished transactions, so `start_with_tid` doesn't reuse them.
+    finished_tids: FinishedTids,
+    /// Prepared transactions found by recovery that haven't been resolved.
+    in_doubt_tids: HashSet<u64>,
     store: Store,
+    options: LogOptions,
+    last_flushed_lsn: Option<u64>,
+    last_flushed_offset: Option<u64>,
+    recovery_stats: Stats,
+    /// Transactions committed since the last checkpoint began.
+    commits_since_checkpoint: u32,
+    /// Bytes flushed to the log since the last checkpoint began.
+    bytes_since_checkpoint: u64,
+    metrics: MetricsRecorder,
+    /// Whether the log recovered and hasn't been closed, so dropping it
+    /// flushes the entries held in memory.
+    open: bool,
 }
 
 impl<Data, Store> UndoLog<Data, Store>
@@ -28,122 +71,747 @@ where
     Data: LogData,
     Store: LogStore<Data>,
 {
+    /// Returns a builder for opening a log with options other than the defaults.
+    pub fn options() -> UndoLogOptions<Data, Store> {
+        LogBuilder::new()
+    }
+
     pub fn new<P: AsRef<Path> + ?Sized>(path: &P, store: Store) -> Result<UndoLog<Data, Store>> {
-        let file = OpenOptions::new()
-            .read(true)
-            .append(true)
-            .create(true)
-            .open(path)?;
+        UndoLog::new_with_options(path, store, LogOptions::default())
+    }
+
+    /// Opens the log at the path with the options, recovering it into
+    /// the store. The log's file stays locked until the log is closed or
+    /// dropped, and returns `LogError::AlreadyLocked` if another log
+    /// already has it open.
+    pub fn new_with_options<P: AsRef<Path> + ?Sized>(
+        path: &P,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<UndoLog<Data, Store>> {
+        options.validate()?;
+        let writer = options.writer_for(options.open_log_file(path)?)?;
+        UndoLog::from_writer(writer, store, options)
+    }
+}
+
+impl<Data, Store> UndoLog<Data, Store, Wal>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    /// Opens the log kept in the directory managed by the `Wal`, recovering
+    /// it into the store from the last checkpoint recorded in its manifest.
+    pub fn with_wal(
+        wal: Wal,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<UndoLog<Data, Store, Wal>> {
+        UndoLog::with_backend(wal, store, options)
+    }
+}
+
+impl<Data, Store, Backend> UndoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    /// Opens the log kept in the backend with the options, recovering
+    /// it into the store. An empty backend starts a new log.
+    pub fn with_backend(
+        backend: Backend,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<UndoLog<Data, Store, Backend>> {
+        options.validate()?;
+        let writer = options.writer_for(backend)?;
+        UndoLog::from_writer(writer, store, options)
+    }
+
+    fn from_writer(
+        writer: Writer<Backend>,
+        store: Store,
+        options: LogOptions,
+    ) -> Result<UndoLog<Data, Store, Backend>> {
+        let metrics = MetricsRecorder::new(options.metrics_sink.clone());
         let mut log = UndoLog {
-            file,
+            writer,
             mem_log: VecDeque::new(),
+            pending_bytes: 0,
             last_tid: 0,
             checkpoint_tids: None,
+            checkpoint_started: None,
             active_tids: HashSet::new(),
+            prepared_tids: HashSet::new(),
+            finished_tids: FinishedTids::default(),
+            in_doubt_tids: HashSet::new(),
             store,
+            options,
+            last_flushed_lsn: None,
+            last_flushed_offset: None,
+            recovery_stats: Stats::default(),
+            commits_since_checkpoint: 0,
+            bytes_since_checkpoint: 0,
+            metrics,
+            open: false,
         };
+        let began = Instant::now();
         log.recover()?;
+        log.metrics.record(MetricEvent::Recovered {
+            duration: began.elapsed(),
+        });
+        log.open = true;
         Ok(log)
     }
 
-    pub fn entries(&self) -> Vec<SingleLogEntry<Data>> {
-        self.mem_log.clone().into_iter().collect()
+    /// Returns the entries that haven't been flushed to the log yet.
+    pub fn entries(&self) -> vec_deque::Iter<'_, SingleLogEntry<Data>> {
+        self.mem_log.iter()
     }
 
-    pub fn checkpoint(&mut self) -> Result<()> {
-        if self.checkpoint_tids.is_none() {
-            let transactions: Vec<_> = self.active_tids.clone().into_iter().collect();
-            let entry = SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions.clone()));
-            self.mem_log.push_back(entry);
-            self.flush()?;
-            self.checkpoint_tids = Some(transactions);
-        }
+    /// Returns the ids of the transactions that haven't finished, in order.
+    pub fn active_transactions(&self) -> Vec<u64> {
+        let mut tids: Vec<_> = self.active_tids.iter().cloned().collect();
+        tids.sort_unstable();
+        tids
+    }
 
+    /// Returns the ids of the prepared transactions recovery found that
+    /// haven't been resolved with `resolve_prepared`, in order.
+    pub fn in_doubt_transactions(&self) -> Vec<u64> {
+        let mut tids: Vec<_> = self.in_doubt_tids.iter().cloned().collect();
+        tids.sort_unstable();
+        tids
+    }
+
+    /// Returns the id of the last transaction started.
+    pub fn last_tid(&self) -> u64 {
+        self.last_tid
+    }
+
+    /// Returns the number of entries that haven't been flushed to the log yet.
+    pub fn pending_entries(&self) -> usize {
+        self.mem_log.len()
+    }
+
+    /// Returns the serialized size of the entries that haven't been
+    /// flushed to the log yet.
+    pub fn pending_bytes(&self) -> u64 {
+        self.pending_bytes
+    }
+
+    /// Closes the log, flushing the entries held in memory, checkpointing
+    /// the log if `LogOptions::checkpoint_on_close` is set and syncing
+    /// its file.
+    ///
+    /// Dropping the log also flushes the entries held in memory, but
+    /// ignores any error doing so. Closing the log is the only way to
+    /// know its entries reached the disk.
+    ///
+    /// The lock on the log's file is released once the readers returned
+    /// by `reader` and `subscribe` are dropped too.
+    pub fn close(mut self) -> Result<()> {
+        self.open = false;
+        if self.options.checkpoint_on_close {
+            self.checkpoint()?;
+        } else {
+            self.flush(SyncPoint::Flush)?;
+        }
+        self.writer.sync()?;
         Ok(())
     }
 
+    /// Begins a checkpoint, which ends once the transactions active when
+    /// it began have finished.
+    ///
+    /// Returns `CheckpointStatus::AlreadyPending` without beginning a new
+    /// checkpoint if the last one hasn't ended. If the pending checkpoint
+    /// began longer than `LogOptions::checkpoint_timeout` ago, it's ended
+    /// with `checkpoint_force` first and a new checkpoint begins.
+    pub fn checkpoint(&mut self) -> Result<CheckpointStatus> {
+        if self.checkpoint_tids.is_some() {
+            let timed_out = match (self.checkpoint_started, self.options.checkpoint_timeout) {
+                (Some(started), Some(timeout)) => started.elapsed() >= timeout,
+                _ => false,
+            };
+            if !timed_out {
+                return Ok(CheckpointStatus::AlreadyPending);
+            }
+            // A checkpoint waiting for prepared transactions stays pending.
+            self.checkpoint_force()?;
+            if self.checkpoint_tids.is_some() {
+                return Ok(CheckpointStatus::AlreadyPending);
+            }
+        }
+
+        self.commits_since_checkpoint = 0;
+        self.bytes_since_checkpoint = 0;
+        let transactions: Vec<_> = self
+            .active_tids
+            .union(&self.prepared_tids)
+            .cloned()
+            .collect();
+        let entry = SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions.clone()));
+        self.push_entry(entry)?;
+        self.flush(SyncPoint::Checkpoint)?;
+        self.finished_tids.checkpoint_began(&transactions);
+        self.checkpoint_tids = Some(transactions);
+        self.checkpoint_started = Some(Instant::now());
+        self.metrics.record(MetricEvent::CheckpointBegun);
+        Ok(CheckpointStatus::Started)
+    }
+
+    /// Ends the pending checkpoint by aborting the transactions it's
+    /// waiting for, beginning a checkpoint first if none is pending.
+    ///
+    /// Prepared transactions aren't aborted, so the checkpoint stays
+    /// pending until they're resolved.
+    pub fn checkpoint_force(&mut self) -> Result<()> {
+        let tids = match self.checkpoint_tids {
+            Some(ref tids) => tids.clone(),
+            None => {
+                self.checkpoint()?;
+                self.checkpoint_tids.clone().unwrap_or_default()
+            }
+        };
+        for tid in tids {
+            if self.active_tids.contains(&tid) {
+                self.abort_transaction(tid)?;
+            }
+        }
+        self.end_checkpoint()
+    }
+
+    /// Returns the transactions the pending checkpoint is waiting for,
+    /// or None if no checkpoint is pending.
+    pub fn checkpoint_pending(&self) -> Option<&[u64]> {
+        self.checkpoint_tids.as_deref()
+    }
+
     pub fn start(&mut self) -> u64 {
         self.last_tid += 1;
         let entry = SingleLogEntry::Transaction(Transaction::Start(self.last_tid));
-        self.mem_log.push_back(entry);
+        // The size of a transaction entry is always known.
+        let _ = self.push_entry(entry);
         self.active_tids.insert(self.last_tid);
 
         self.last_tid
     }
 
-    pub fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) {
-        if self.active_tids.contains(&tid) {
-            let entry = if let Some(old_value) = self.store.get(&key) {
-                SingleLogEntry::ChangeEntry(ChangeEntry {
-                    tid,
-                    key: key.clone(),
-                    value: old_value,
-                })
-            } else {
-                SingleLogEntry::InsertEntry(InsertEntry {
-                    tid,
-                    key: key.clone(),
-                })
-            };
+    /// Starts a transaction with an id chosen by the caller, like one
+    /// handed out by an external sequencer, instead of the next id after
+    /// `last_tid`. Transactions started with `start` afterwards get ids
+    /// after the largest id used.
+    ///
+    /// Returns `LogError::DuplicateTransaction` if the id is of a
+    /// transaction that is active, prepared, or finished in the part of the
+    /// log recovery would read.
+    pub fn start_with_tid(&mut self, tid: u64) -> Result<()> {
+        if self.active_tids.contains(&tid)
+            || self.prepared_tids.contains(&tid)
+            || self.finished_tids.contains(tid)
+        {
+            return Err(LogError::DuplicateTransaction(tid));
+        }
+        self.last_tid = cmp::max(self.last_tid, tid);
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Start(tid)))?;
+        self.active_tids.insert(tid);
+        Ok(())
+    }
+
+    /// Starts a transaction that is aborted if the returned guard is
+    /// dropped before it is committed.
+    pub fn transaction(&mut self) -> TransactionGuard<'_, Data, Self> {
+        let tid = self.start();
+        TransactionGuard::new(self, tid)
+    }
+
+    /// Logs the value the key had before the transaction changes it to
+    /// the value, then applies the change to the store.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let entry = if let Some(old_value) = self.store.get(&key) {
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid,
+                key: key.clone(),
+                value: old_value,
+            })
+        } else {
+            SingleLogEntry::InsertEntry(InsertEntry {
+                tid,
+                key: key.clone(),
+            })
+        };
+        self.push_entry(entry)?;
+        self.write_ahead()?;
+        self.store.update(key, val);
+        Ok(())
+    }
+
+    /// Logs the values the keys had before the transaction changes them
+    /// as a single entry, then applies the changes to the store in order.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn write_batch<I>(&mut self, tid: u64, changes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Data::Key, Data::Value)>,
+    {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        // A key changed twice in the batch logs the value it had before the batch.
+        let changes: Vec<_> = changes.into_iter().collect();
+        let mut seen = HashSet::new();
+        let mut logged: Vec<(Data::Key, Option<Data::Value>)> = Vec::new();
+        for (key, _) in changes.iter() {
+            if seen.insert(key) {
+                logged.push((key.clone(), self.store.get(key)));
+            }
+        }
+        if logged.is_empty() {
+            return Ok(());
+        }
+        self.push_entry(SingleLogEntry::MultiChangeEntry(MultiChangeEntry {
+            tid,
+            changes: logged,
+        }))?;
+        self.write_ahead()?;
+        for (key, val) in changes {
             self.store.update(key, val);
-            self.mem_log.push_back(entry);
         }
+        Ok(())
     }
 
+    /// Logs the value the key had before the transaction deletes it, then
+    /// removes the key from the store.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn delete(&mut self, tid: u64, key: Data::Key) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        let value = self.store.get(&key);
+        self.push_entry(SingleLogEntry::DeleteEntry(DeleteEntry {
+            tid,
+            key: key.clone(),
+            value,
+        }))?;
+        self.write_ahead()?;
+        self.store.remove(&key);
+        Ok(())
+    }
+
+    /// Flushes the store and commits the transaction.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't active.
     pub fn commit(&mut self, tid: u64) -> Result<()> {
-        if self.active_tids.contains(&tid) {
-            self.flush()?;
-            self.store.flush()?;
-
-            let entry = SingleLogEntry::Transaction(Transaction::Commit(tid));
-            self.mem_log.push_back(entry);
-            self.active_tids.remove(&tid);
-
-            // Add end checkpoint to log if all checkpoint transactions have finished.
-            if let Some(tids) = self.checkpoint_tids.take() {
-                let mut transactions_completed = true;
-                for tid in tids.iter() {
-                    if self.active_tids.contains(tid) {
-                        transactions_completed = false;
-                        break;
-                    }
-                }
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.commit_transaction(tid)
+    }
+
+    fn commit_transaction(&mut self, tid: u64) -> Result<()> {
+        self.flush(SyncPoint::Commit)?;
+        self.store.flush()?;
+
+        let entry = SingleLogEntry::Transaction(Transaction::Commit(tid));
+        self.push_entry(entry)?;
+        self.active_tids.remove(&tid);
+        self.prepared_tids.remove(&tid);
+        self.finished_tids.insert(tid);
+        self.flush(SyncPoint::Commit)?;
+        self.metrics.record(MetricEvent::Committed);
+        self.end_checkpoint()?;
+
+        // Automatic checkpoints wait for a pending checkpoint to end.
+        self.commits_since_checkpoint += 1;
+        if self.checkpoint_tids.is_none()
+            && self
+                .options
+                .checkpoint
+                .checkpoints_at(self.commits_since_checkpoint, self.bytes_since_checkpoint)
+        {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Aborts the transaction, rolling back its changes to the store by
+    /// applying the old values logged for them in reverse order.
+    ///
+    /// The entries of the transaction that were already flushed are read
+    /// back from the log. The store is flushed before the abort is logged,
+    /// so recovery never has to roll the transaction back again. Returns
+    /// `LogError::UnknownTransaction` if the transaction isn't active.
+    pub fn abort(&mut self, tid: u64) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.abort_transaction(tid)
+    }
+
+    fn abort_transaction(&mut self, tid: u64) -> Result<()> {
+        let undo = self.undo_entries(tid)?;
+        self.flush(SyncPoint::Flush)?;
+        for entry in undo {
+            undo_entry(&mut self.store, entry);
+        }
+        self.store.flush()?;
+
+        let entry = SingleLogEntry::Transaction(Transaction::Abort(tid));
+        self.push_entry(entry)?;
+        self.active_tids.remove(&tid);
+        self.prepared_tids.remove(&tid);
+        self.finished_tids.insert(tid);
+        self.flush(SyncPoint::Flush)?;
+        self.metrics.record(MetricEvent::Aborted);
+        self.end_checkpoint()
+    }
+
+    /// Prepares the transaction for a two-phase commit, flushing its
+    /// entries and a prepare entry to the log and syncing it.
+    ///
+    /// The prepared transaction can't be written to, and is committed or
+    /// aborted with `resolve_prepared`. If the log crashes first, recovery
+    /// doesn't roll the transaction back but reports it in
+    /// `in_doubt_transactions`. Returns `LogError::UnknownTransaction` if
+    /// the transaction isn't active.
+    pub fn prepare(&mut self, tid: u64) -> Result<()> {
+        if !self.active_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.push_entry(SingleLogEntry::Transaction(Transaction::Prepare(tid)))?;
+        self.flush(SyncPoint::Flush)?;
+        // The prepared transaction has to survive a crash whatever the sync policy.
+        if self.writer.synced_lsn() < self.writer.last_lsn() {
+            self.writer.sync()?;
+        }
+
+        self.active_tids.remove(&tid);
+        self.prepared_tids.insert(tid);
+        Ok(())
+    }
+
+    /// Commits or aborts the prepared transaction like `commit` or `abort`.
+    ///
+    /// Returns `LogError::UnknownTransaction` if the transaction isn't prepared.
+    pub fn resolve_prepared(&mut self, tid: u64, outcome: Outcome) -> Result<()> {
+        if !self.prepared_tids.contains(&tid) {
+            return Err(LogError::UnknownTransaction(tid));
+        }
+        self.in_doubt_tids.remove(&tid);
+        match outcome {
+            Outcome::Commit => self.commit_transaction(tid),
+            Outcome::Abort => self.abort_transaction(tid),
+        }
+    }
+
+    /// Removes the entries before the last checkpoint that has ended from
+    /// the log, so recovery doesn't have to scan them and the file doesn't
+    /// keep growing. Returns whether the log was truncated.
+    ///
+    /// The transactions active when the checkpoint began all finished before
+    /// it ended, so only the entries from the checkpoint's begin entry on are
+    /// kept. The log is synced first, and the kept entries are copied to a
+    /// new file that atomically replaces the log.
+    pub fn truncate_before_checkpoint(&mut self) -> Result<bool> {
+        self.flush(SyncPoint::Flush)?;
+        self.writer.sync()?;
+        let start = match checkpoint_start::<Data, _>(&mut self.writer, &self.options, false)? {
+            Some(start) => start,
+            None => return Ok(false),
+        };
+        if self.writer.file().can_discard() {
+            return Ok(self.writer.file_mut().discard_before(start)?);
+        }
+        self.writer = rewrite_log(
+            &mut self.writer,
+            &self.options,
+            start,
+            &mut self.last_flushed_offset,
+        )?;
+        Ok(true)
+    }
+
+    /// Returns an iterator over the records flushed to the log so far.
+    ///
+    /// The iterator reads through its own handle to the log's backend,
+    /// so it can be used while the log keeps writing.
+    pub fn reader(
+        &self,
+        direction: ReadDirection,
+    ) -> Result<WalIterator<'static, Backend::Reader>> {
+        let file = self.writer.file().reader()?;
+        let mut iter = WalIterator::owned(file, direction, self.writer.format())?;
+        iter.start_at(self.writer.file().first_offset())?;
+        Ok(iter.on_corruption(self.options.on_corruption))
+    }
+
+    /// Returns an iterator following the log's entries as they're flushed,
+    /// starting from the first entry in the log.
+    ///
+    /// Like `reader`, the iterator reads through its own handle to the log's
+    /// backend, so it only sees the entries flushed later if the backend's
+    /// reader does, like the reader of a log kept in a file. Its `wait_next`
+    /// waits for the log to flush more entries until the log is dropped.
+    /// Truncating the log ends the subscription, since the entries after
+    /// the truncated ones move.
+    pub fn subscribe(&mut self) -> Result<TailIterator<Backend::Reader, SingleLogEntry<Data>>> {
+        let format = self.writer.format();
+        let mut file = self.writer.file().reader()?;
+        let first_offset = self.writer.file().first_offset();
+        let tail = if first_offset > 0 {
+            let mut iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format)?;
+            iter.start_at(first_offset)?;
+            let cursor = iter.cursor();
+            TailIterator::from_cursor(file, format, cursor)
+        } else {
+            TailIterator::new(file, format)
+        };
+        Ok(tail.notifier(self.writer.notifier()))
+    }
 
-                if transactions_completed {
-                    let entry = SingleLogEntry::Checkpoint(Checkpoint::End);
-                    self.mem_log.push_back(entry);
-                    self.checkpoint_tids = None;
-                } else {
-                    self.checkpoint_tids = Some(tids);
+    /// Returns the LSN of the first record of the last entry flushed to the log.
+    pub fn last_flushed_lsn(&self) -> Option<u64> {
+        self.last_flushed_lsn
+    }
+
+    /// Returns the offset in the log's file of the first record
+    /// of the last entry flushed to the log.
+    pub fn last_flushed_offset(&self) -> Option<u64> {
+        self.last_flushed_offset
+    }
+
+    /// Returns the LSN of the last record synced to disk.
+    pub fn synced_lsn(&self) -> u64 {
+        self.writer.synced_lsn()
+    }
+
+    /// Returns the counters of what the log has done since it was opened.
+    pub fn metrics(&self) -> &Metrics {
+        self.metrics.metrics()
+    }
+
+    /// Returns the stats of the iterator that recovered the log when it was opened.
+    pub fn recovery_stats(&self) -> Stats {
+        self.recovery_stats
+    }
+
+    /// Returns the entries of the transaction, newest first, going back to
+    /// its start entry. The log is only read if the start entry has
+    /// already been flushed.
+    fn undo_entries(&mut self, tid: u64) -> Result<Vec<SingleLogEntry<Data>>> {
+        let start = SingleLogEntry::Transaction(Transaction::Start(tid));
+        let mut undo = Vec::new();
+        for entry in self.mem_log.iter().rev() {
+            if *entry == start {
+                return Ok(undo);
+            }
+            if entry.tid() == Some(tid) {
+                undo.push(entry.clone());
+            }
+        }
+
+        let first_offset = self.writer.file().first_offset();
+        let format = self.writer.format();
+        let mut source = LogSource::new(self.writer.file_mut(), &self.options)?;
+        let mut iter = WalIterator::with_readahead(
+            &mut source,
+            ReadDirection::Backward,
+            format,
+            self.options.readahead_blocks,
+        )?
+        .on_corruption(self.options.on_corruption);
+        iter.start_at(first_offset)?;
+        let entries = iter.entries::<SingleLogEntry<Data>>().for_transaction(tid);
+        for entry in entries.rev() {
+            let entry = entry?;
+            if entry == start {
+                break;
+            }
+            undo.push(entry);
+        }
+        Ok(undo)
+    }
+
+    /// Adds the end checkpoint to the log if all of the checkpoint's
+    /// transactions have finished.
+    fn end_checkpoint(&mut self) -> Result<()> {
+        if let Some(tids) = self.checkpoint_tids.take() {
+            let mut transactions_completed = true;
+            for tid in tids.iter() {
+                if self.active_tids.contains(tid) || self.prepared_tids.contains(tid) {
+                    transactions_completed = false;
+                    break;
                 }
             }
-            self.flush()?;
+
+            if transactions_completed {
+                let entry = SingleLogEntry::Checkpoint(Checkpoint::End);
+                self.push_entry(entry)?;
+                self.checkpoint_tids = None;
+                self.checkpoint_started = None;
+                self.flush(SyncPoint::Checkpoint)?;
+                self.finished_tids.checkpoint_ended(&tids);
+                self.record_checkpoint()?;
+            } else {
+                self.checkpoint_tids = Some(tids);
+            }
         }
+        Ok(())
+    }
 
+    /// Records where recovery starts after the checkpoint that just ended
+    /// in backends that track it, once the log is synced.
+    fn record_checkpoint(&mut self) -> Result<()> {
+        if !self.writer.file().tracks_checkpoints() {
+            return Ok(());
+        }
+        self.writer.sync()?;
+        if let Some(start) = checkpoint_start::<Data, _>(&mut self.writer, &self.options, false)? {
+            self.writer.file_mut().checkpointed(start)?;
+        }
         Ok(())
     }
 
-    fn flush(&mut self) -> Result<()> {
-        for entry in self.mem_log.iter_mut() {
-            let mut bytes = Vec::new();
-            entry.serialize(&mut bytes)?;
+    /// Adds the entry to the entries waiting to be flushed to the log.
+    ///
+    /// Returns an error without adding the entry if its serialized size
+    /// can't be computed.
+    fn push_entry(&mut self, entry: SingleLogEntry<Data>) -> Result<()> {
+        self.pending_bytes += entry.serialized_size()?;
+        self.mem_log.push_back(entry);
+        self.metrics.record(MetricEvent::EntryAppended);
+        Ok(())
+    }
 
-            let records = split_bytes_into_records(&bytes, MAX_RECORD_SIZE)?;
-            for record in records.iter() {
-                append_to_file(&mut self.file, record)?;
+    /// Flushes the in-memory entries to the log early if they take up
+    /// more memory than the options allow.
+    fn spill(&mut self) -> Result<()> {
+        if let Some(max) = self.options.max_pending_bytes {
+            if self.pending_bytes >= max {
+                self.flush(SyncPoint::Flush)?;
             }
         }
+        Ok(())
+    }
+
+    /// Flushes the entries logging old values before the changes are
+    /// applied to the store, if the write ahead policy says to, or if
+    /// they take up more memory than the options allow.
+    fn write_ahead(&mut self) -> Result<()> {
+        if self.options.write_ahead == WriteAheadPolicy::BeforeUpdate {
+            self.flush(SyncPoint::WriteAhead)?;
+        }
+        self.spill()
+    }
+
+    /// Flushes the in-memory entries to the log, recording the LSN and
+    /// offset of the first record of the last flushed entry.
+    ///
+    /// The log is synced afterwards if the sync policy syncs at the point.
+    fn flush(&mut self, point: SyncPoint) -> Result<()> {
+        let began = Instant::now();
+        let mut last_flushed = None;
+        let start = self.writer.position();
+        for entry in self.mem_log.iter() {
+            let lsn = self.writer.last_lsn() + 1;
+            let offset = match self.writer.append_serializable(entry) {
+                Ok(offset) => offset,
+                Err(err) => {
+                    // The entries written before the one that failed are
+                    // cut off, so they're only logged by the next flush.
+                    self.writer.truncate(start)?;
+                    return Err(err.into());
+                }
+            };
+            last_flushed = Some((lsn, offset));
+        }
         self.mem_log.clear();
+        self.pending_bytes = 0;
+        self.bytes_since_checkpoint += self.writer.position() - start;
+        if let Some((lsn, offset)) = last_flushed {
+            self.last_flushed_lsn = Some(lsn);
+            self.last_flushed_offset = Some(offset);
+        }
+        if self.writer.synced_lsn() < self.writer.last_lsn()
+            && self
+                .options
+                .sync
+                .syncs_at(point, self.writer.unsynced_bytes())
+        {
+            self.writer.sync()?;
+        }
+        if last_flushed.is_some() {
+            self.metrics.record(MetricEvent::Flushed {
+                bytes: self.writer.position() - start,
+                latency: began.elapsed(),
+            });
+        }
         Ok(())
     }
 
     fn recover(&mut self) -> Result<()> {
         let mut finished = HashSet::new();
         let mut unfinished = HashSet::new();
+        let mut prepared = HashSet::new();
+        let mut recovered_tids = HashSet::new();
+        // Unfinished transactions whose start hasn't been read yet. Their
+        // older entries have to be undone too, even if they're before the
+        // checkpoint recovery would otherwise stop at.
+        let mut awaiting_start = HashSet::new();
+        // Whether recovery read back to the begin entry of the last ended
+        // checkpoint, or the start entries of the transactions active when
+        // the last checkpoint began.
+        let mut reached_checkpoint = false;
+        let mut max_started = 0;
         let mut state = RecoverState::None;
 
-        let mut iter = WalIterator::new(&mut self.file, ReadDirection::Backward)?;
-        while let Ok(data) = read_serializable_backwards::<SingleLogEntry<Data>>(&mut iter) {
+        // An entry torn by a crash while it was being appended is removed,
+        // and recovery continues with the entries before it.
+        truncate_torn_entry::<SingleLogEntry<Data>, _>(
+            &mut self.writer,
+            self.options.on_corruption,
+        )?;
+        let recovery_start = self.writer.file_mut().recovery_start()?;
+        let first_offset = self.writer.file().first_offset();
+
+        let format = self.writer.format();
+        let mut source = LogSource::new(self.writer.file_mut(), &self.options)?;
+        let on_corruption = self.options.on_corruption;
+        let mut iter = WalIterator::with_readahead(
+            &mut source,
+            ReadDirection::Backward,
+            format,
+            self.options.readahead_blocks,
+        )?
+        .on_corruption(on_corruption);
+        iter.start_at(first_offset)?;
+        let progress = self.options.recovery_progress.clone();
+        let total = match progress {
+            Some(_) => iter.count_records()?,
+            None => 0,
+        };
+        let mut entries = iter.entries::<SingleLogEntry<Data>>();
+        let mut recovered = 0;
+        while let Some(data) = recover_entry(
+            &mut entries,
+            ReadDirection::Backward,
+            on_corruption,
+            &mut recovered,
+        )? {
+            if let Some(ref progress) = progress {
+                progress.report_pass(entries.get_mut(), total, 0.0, 1.0);
+            }
+            if let Some(tid) = data.tid() {
+                recovered_tids.insert(tid);
+            }
             match data {
                 SingleLogEntry::Transaction(Transaction::Commit(id)) => {
                     finished.insert(id);
@@ -151,34 +819,39 @@ where
                 SingleLogEntry::Transaction(Transaction::Abort(id)) => {
                     finished.insert(id);
                 }
+                SingleLogEntry::Transaction(Transaction::Prepare(id)) => {
+                    if !finished.contains(&id) {
+                        prepared.insert(id);
+                    }
+                }
                 SingleLogEntry::Transaction(Transaction::Start(id)) => {
+                    max_started = cmp::max(max_started, id);
+                    awaiting_start.remove(&id);
                     if let RecoverState::Begin(ref mut transactions) = state {
                         transactions.remove(&id);
                         if transactions.is_empty() {
-                            break;
+                            reached_checkpoint = true;
                         }
                     }
                 }
-                SingleLogEntry::InsertEntry(entry) => {
-                    if !finished.contains(&entry.tid) {
-                        self.store.remove(&entry.key);
-                        unfinished.insert(entry.tid);
-                    }
-                }
-                SingleLogEntry::ChangeEntry(entry) => {
-                    if !finished.contains(&entry.tid) {
-                        self.store.update(entry.key, entry.value);
-                        unfinished.insert(entry.tid);
+                SingleLogEntry::InsertEntry(InsertEntry { tid, .. })
+                | SingleLogEntry::ChangeEntry(ChangeEntry { tid, .. })
+                | SingleLogEntry::MultiChangeEntry(MultiChangeEntry { tid, .. })
+                | SingleLogEntry::DeleteEntry(DeleteEntry { tid, .. }) => {
+                    if !finished.contains(&tid) && !prepared.contains(&tid) {
+                        undo_entry(&mut self.store, data);
+                        unfinished.insert(tid);
+                        awaiting_start.insert(tid);
                     }
                 }
                 SingleLogEntry::Checkpoint(Checkpoint::Begin(transactions)) => match state {
                     RecoverState::None => {
                         if transactions.is_empty() {
-                            break;
+                            reached_checkpoint = true;
                         }
                         state = RecoverState::Begin(transactions.into_iter().collect());
                     }
-                    RecoverState::End => break,
+                    RecoverState::End => reached_checkpoint = true,
                     _ => {}
                 },
                 SingleLogEntry::Checkpoint(Checkpoint::End) => {
@@ -187,21 +860,147 @@ where
                     }
                 }
             }
+            if reached_recovery_start(&mut entries, recovery_start) {
+                reached_checkpoint = true;
+            }
+            if reached_checkpoint && awaiting_start.is_empty() {
+                break;
+            }
         }
 
+        if let Some(ref progress) = progress {
+            progress.report(1.0);
+        }
+        self.recovery_stats = entries.get_mut().stats();
+
         // Flush undo store changes first before writing aborts to the log.
         self.store.flush()?;
         for tid in unfinished.iter() {
-            self.mem_log
-                .push_back(SingleLogEntry::Transaction(Transaction::Abort(*tid)));
+            self.push_entry(SingleLogEntry::Transaction(Transaction::Abort(*tid)))?;
+            self.metrics.record(MetricEvent::Aborted);
         }
 
-        // Set the last tid to the largest tid.
+        // Set the last tid to the largest tid, including transactions that
+        // only logged their start.
         let max_unfinished = unfinished.into_iter().max().unwrap_or(0);
         let max_finished = finished.into_iter().max().unwrap_or(0);
-        self.last_tid = cmp::max(max_unfinished, max_finished);
+        let max_prepared = prepared.iter().cloned().max().unwrap_or(0);
+        let max_tids = vec![max_unfinished, max_finished, max_prepared, max_started];
+        self.last_tid = max_tids.into_iter().max().unwrap();
+        self.finished_tids =
+            FinishedTids::new(recovered_tids.difference(&prepared).cloned().collect());
+        self.in_doubt_tids = prepared.clone();
+        self.prepared_tids = prepared;
 
-        self.flush()?;
+        // The aborts are synced so recovering the log again doesn't find
+        // the transactions unfinished and abort them a second time.
+        self.flush(SyncPoint::Recovery)?;
         Ok(())
     }
 }
+
+impl<Data, Store> UndoLogOptions<Data, Store>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    /// Opens the log at the path with the options, recovering it into the store.
+    pub fn open<P: AsRef<Path> + ?Sized>(
+        self,
+        path: &P,
+        store: Store,
+    ) -> Result<UndoLog<Data, Store>> {
+        UndoLog::new_with_options(path, store, self.options)
+    }
+
+    /// Opens the log kept in the backend with the options, recovering it into the store.
+    pub fn open_backend<Backend: LogBackend>(
+        self,
+        backend: Backend,
+        store: Store,
+    ) -> Result<UndoLog<Data, Store, Backend>> {
+        UndoLog::with_backend(backend, store, self.options)
+    }
+
+    /// Sets when old values are flushed to the log relative to
+    /// applying changes to the store.
+    pub fn write_ahead(mut self, write_ahead: WriteAheadPolicy) -> UndoLogOptions<Data, Store> {
+        self.options.write_ahead = write_ahead;
+        self
+    }
+
+    /// Sets how long a checkpoint waits for its transactions to finish
+    /// before `checkpoint` aborts them.
+    pub fn checkpoint_timeout(mut self, timeout: Duration) -> UndoLogOptions<Data, Store> {
+        self.options.checkpoint_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<Data, Store, Backend> Drop for UndoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    fn drop(&mut self) {
+        // Errors can't be returned from drop, so `close` has to be
+        // used to find out whether the entries were flushed.
+        if self.open {
+            let _ = self.flush(SyncPoint::Flush);
+        }
+    }
+}
+
+impl<Data, Store, Backend> TransactionLog<Data> for UndoLog<Data, Store, Backend>
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+    Backend: LogBackend,
+{
+    fn write(&mut self, tid: u64, key: Data::Key, val: Data::Value) -> Result<()> {
+        UndoLog::write(self, tid, key, val)
+    }
+
+    fn write_batch(&mut self, tid: u64, changes: Vec<(Data::Key, Data::Value)>) -> Result<()> {
+        UndoLog::write_batch(self, tid, changes)
+    }
+
+    fn delete(&mut self, tid: u64, key: Data::Key) -> Result<()> {
+        UndoLog::delete(self, tid, key)
+    }
+
+    fn commit(&mut self, tid: u64) -> Result<()> {
+        UndoLog::commit(self, tid)
+    }
+
+    fn abort(&mut self, tid: u64) -> Result<()> {
+        UndoLog::abort(self, tid)
+    }
+}
+
+/// Rolls back the changes the entry logged the old values of.
+fn undo_entry<Data, Store>(store: &mut Store, entry: SingleLogEntry<Data>)
+where
+    Data: LogData,
+    Store: LogStore<Data>,
+{
+    match entry {
+        SingleLogEntry::ChangeEntry(entry) => store.update(entry.key, entry.value),
+        SingleLogEntry::InsertEntry(entry) => store.remove(&entry.key),
+        SingleLogEntry::DeleteEntry(entry) => match entry.value {
+            Some(value) => store.update(entry.key, value),
+            None => store.remove(&entry.key),
+        },
+        // A key changed twice in the batch is left with its first old value.
+        SingleLogEntry::MultiChangeEntry(entry) => {
+            for (key, value) in entry.changes.into_iter().rev() {
+                match value {
+                    Some(value) => store.update(key, value),
+                    None => store.remove(&key),
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,423 @@
+use std::cmp;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::wal::entries::{SingleLogEntry, Transaction};
+use crate::wal::header::{FileHeader, HeaderError, MAGIC};
+use crate::wal::iterator::{load_block, BlockError, BlockSource, Stats};
+use crate::wal::record::{BlockFormat, RecordError, RecordType};
+use crate::wal::{finish_entry, LogData};
+use crate::Serializable;
+
+/// Options for `verify`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VerifyOptions {
+    /// Block format of logs without a header. Logs with a header are read
+    /// with the format in their header.
+    pub format: BlockFormat,
+    /// Whether the entries are deserialized as `SingleLogEntry`s and
+    /// checked against the transactions they belong to, instead of only
+    /// checking that the records frame whole entries.
+    pub entries: bool,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> VerifyOptions {
+        VerifyOptions {
+            format: BlockFormat::default(),
+            entries: true,
+        }
+    }
+}
+
+/// Problem found in a log by `verify`.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Finding {
+    /// The file starts like a log header, but the header can't be read.
+    BadHeader(HeaderError),
+    /// A record failed to parse or failed its checksum, or its block failed
+    /// the block checksum. The rest of its block can't be read.
+    CorruptRecord {
+        /// Offset in the file of the record, or of the block if the
+        /// block checksum failed.
+        offset: u64,
+        error: RecordError,
+    },
+    /// A record doesn't continue the chain of records before it, like
+    /// `SerializeError::InvalidTransfer`.
+    InvalidChain {
+        offset: u64,
+        found: RecordType,
+        previous: Option<RecordType>,
+    },
+    /// A block holds only padding while records follow it. Readers stop
+    /// at an empty block, so they never reach the records after it.
+    EmptyBlock { offset: u64 },
+    /// The log ends partway through an entry or one of its records, like
+    /// a log whose writer stopped while appending. Recovery truncates it.
+    TornTail { start: u64, end: u64 },
+    /// The records of an entry frame it, but it can't be deserialized.
+    BadEntry { start: u64, end: u64, error: String },
+    /// A change belongs to a transaction that wasn't started before it.
+    ChangeWithoutStart { offset: u64, tid: u64 },
+    /// A transaction commits without having been started before.
+    CommitWithoutStart { offset: u64, tid: u64 },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Finding::BadHeader(ref err) => write!(f, "Bad log header: {}", err),
+            Finding::CorruptRecord { offset, ref error } => {
+                write!(f, "Corrupted record at offset {}: {}", offset, error)
+            }
+            Finding::InvalidChain {
+                offset,
+                found,
+                previous,
+            } => write!(
+                f,
+                "Invalid record transition {:?} after {:?} at offset {}",
+                found, previous, offset
+            ),
+            Finding::EmptyBlock { offset } => write!(
+                f,
+                "Block at offset {} is empty, hiding the records after it",
+                offset
+            ),
+            Finding::TornTail { start, end } => {
+                write!(
+                    f,
+                    "Log ends partway through the entry at offsets {}..{}",
+                    start, end
+                )
+            }
+            Finding::BadEntry {
+                start,
+                end,
+                ref error,
+            } => write!(
+                f,
+                "Entry at offsets {}..{} can't be deserialized: {}",
+                start, end, error
+            ),
+            Finding::ChangeWithoutStart { offset, tid } => write!(
+                f,
+                "Change at offset {} belongs to transaction {}, which wasn't started",
+                offset, tid
+            ),
+            Finding::CommitWithoutStart { offset, tid } => write!(
+                f,
+                "Commit at offset {} of transaction {}, which wasn't started",
+                offset, tid
+            ),
+        }
+    }
+}
+
+/// What `verify` found in a log.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Header the log starts with, if any.
+    pub header: Option<FileHeader>,
+    /// Block format the log was read with.
+    pub format: BlockFormat,
+    /// Blocks of records scanned, not counting the header block.
+    pub blocks: u64,
+    /// Records that parsed and passed their checksums.
+    pub records: u64,
+    /// Entries framed by complete chains of records.
+    pub entries: u64,
+    /// Offsets of the padding at the ends of blocks, with the padding of
+    /// consecutive blocks joined into one range.
+    pub padding: Vec<Range<u64>>,
+    /// Problems found, in the order of their offsets.
+    pub findings: Vec<Finding>,
+}
+
+impl VerifyReport {
+    /// Returns true if nothing is wrong with the log.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Adds the padding at the offsets, joining it with padding right before it.
+    fn add_padding(&mut self, range: Range<u64>) {
+        match self.padding.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.padding.push(range),
+        }
+    }
+}
+
+/// Verifies the log at the path like `verify`, opening it read-only
+/// without taking the lock a log holds on its file.
+pub fn verify_path<Data, P>(path: &P, options: &VerifyOptions) -> io::Result<VerifyReport>
+where
+    Data: LogData,
+    P: AsRef<Path> + ?Sized,
+{
+    verify::<Data, _>(&mut File::open(path)?, options)
+}
+
+/// Checks every block, record and entry of a log without changing it or
+/// opening it as a redo or undo log, returning the problems found.
+///
+/// Records are checked against their checksums and the block checksums,
+/// and their chains are checked to frame whole entries. With
+/// `VerifyOptions::entries`, the entries are deserialized as
+/// `SingleLogEntry<Data>` and their transactions are checked to be started
+/// before their changes and commits. Otherwise `Data` isn't used.
+///
+/// Returns an error only if reading the log fails.
+pub fn verify<Data, R>(source: &mut R, options: &VerifyOptions) -> io::Result<VerifyReport>
+where
+    Data: LogData,
+    R: BlockSource,
+{
+    let len = source.size()?;
+    let mut report = VerifyReport {
+        format: options.format,
+        ..VerifyReport::default()
+    };
+    let mut pos = 0;
+    let start = source.read_at(0, cmp::min(len, MAGIC.len() as u64) as usize)?;
+    if start.as_slice() == MAGIC {
+        match FileHeader::read(source) {
+            Ok(header) => {
+                report.header = header;
+                if let Some(header) = header {
+                    report.format = header.format();
+                }
+            }
+            Err(err) => match HeaderError::from_io_error(&err) {
+                Some(err) => report.findings.push(Finding::BadHeader(err.clone())),
+                None => return Err(err),
+            },
+        }
+        pos = report.format.block_size as u64;
+    }
+    report.format.check()?;
+
+    let mut scan = Scan::<Data>::new(*options);
+    let format = report.format;
+    let block_size = format.block_size as u64;
+    let mut records = Vec::new();
+    let mut stats = Stats::default();
+    let mut empty_block = None;
+    while pos < len {
+        let bytes = source.read_at(pos, cmp::min(block_size, len - pos) as usize)?;
+        let corruption =
+            load_block(&bytes, pos as i64, format, &mut records, &mut stats).map_err(|err| {
+                match err {
+                    BlockError::IoError(err) => err,
+                    err => io::Error::other(err.to_string()),
+                }
+            })?;
+        report.blocks += 1;
+
+        if !records.is_empty() {
+            if let Some(offset) = empty_block.take() {
+                report.findings.push(Finding::EmptyBlock { offset });
+            }
+        }
+        let mut record_start = 0;
+        for (record, end) in records.iter() {
+            let range = pos + record_start as u64..pos + *end as u64;
+            record_start = *end;
+            report.records += 1;
+            scan.record(
+                &mut report,
+                record.record_type,
+                record.compressed,
+                &record.payload,
+                range,
+            );
+        }
+
+        let parsed_end = pos + record_start as u64;
+        match corruption {
+            Some(BlockError::Corrupted { offset, error, .. }) => {
+                let error = match RecordError::from_io_error(&error) {
+                    Some(error) => error.clone(),
+                    None => return Err(error),
+                };
+                if error.is_truncation() && pos + block_size >= len {
+                    let start = scan.pending_start().unwrap_or(offset);
+                    scan.reset();
+                    report.findings.push(Finding::TornTail { start, end: len });
+                } else {
+                    // The chain in progress can't continue past the corruption.
+                    scan.reset();
+                    report
+                        .findings
+                        .push(Finding::CorruptRecord { offset, error });
+                }
+            }
+            Some(err) => return Err(io::Error::other(err.to_string())),
+            None => {
+                let end = pos + cmp::min(format.capacity() as u64, bytes.len() as u64);
+                if parsed_end < end {
+                    report.add_padding(parsed_end..end);
+                }
+                if records.is_empty() && empty_block.is_none() {
+                    empty_block = Some(pos);
+                }
+            }
+        }
+        pos += block_size;
+    }
+    if let Some(start) = scan.pending_start() {
+        report.findings.push(Finding::TornTail { start, end: len });
+    }
+    Ok(report)
+}
+
+/// Chain of records of the entry being read.
+struct Pending {
+    start: u64,
+    previous: RecordType,
+    bytes: Vec<u8>,
+}
+
+/// State of the scan of the entries in a log.
+struct Scan<Data> {
+    options: VerifyOptions,
+    pending: Option<Pending>,
+    started: HashSet<u64>,
+    _data: PhantomData<Data>,
+}
+
+impl<Data: LogData> Scan<Data> {
+    fn new(options: VerifyOptions) -> Scan<Data> {
+        Scan {
+            options,
+            pending: None,
+            started: HashSet::new(),
+            _data: PhantomData,
+        }
+    }
+
+    fn pending_start(&self) -> Option<u64> {
+        self.pending.as_ref().map(|pending| pending.start)
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    /// Adds the record at the offsets to the chain of the entry being read,
+    /// checking the entry once its chain is complete.
+    fn record(
+        &mut self,
+        report: &mut VerifyReport,
+        record_type: RecordType,
+        compressed: bool,
+        payload: &[u8],
+        range: Range<u64>,
+    ) {
+        let starts_entry = matches!(
+            record_type,
+            RecordType::Zero | RecordType::Full | RecordType::First
+        );
+        match self.pending.take() {
+            // A record starting another entry means the chain lost its last record.
+            Some(pending) if starts_entry => report.findings.push(Finding::InvalidChain {
+                offset: range.start,
+                found: record_type,
+                previous: Some(pending.previous),
+            }),
+            Some(mut pending) => {
+                pending.bytes.extend_from_slice(payload);
+                pending.previous = record_type;
+                if record_type == RecordType::Last {
+                    self.entry(report, pending.bytes, compressed, pending.start..range.end);
+                } else {
+                    self.pending = Some(pending);
+                }
+                return;
+            }
+            None if !starts_entry => {
+                report.findings.push(Finding::InvalidChain {
+                    offset: range.start,
+                    found: record_type,
+                    previous: None,
+                });
+                return;
+            }
+            None => {}
+        }
+
+        if record_type == RecordType::First {
+            self.pending = Some(Pending {
+                start: range.start,
+                previous: record_type,
+                bytes: payload.to_vec(),
+            });
+        } else {
+            self.entry(report, payload.to_vec(), compressed, range);
+        }
+    }
+
+    /// Checks the entry framed by a complete chain of records.
+    fn entry(
+        &mut self,
+        report: &mut VerifyReport,
+        mut bytes: Vec<u8>,
+        compressed: bool,
+        range: Range<u64>,
+    ) {
+        report.entries += 1;
+        if !self.options.entries {
+            return;
+        }
+        let entry = finish_entry(&mut bytes, compressed)
+            .map_err(|err| err.to_string())
+            .and_then(|()| {
+                SingleLogEntry::<Data>::deserialize(&mut &bytes[..]).map_err(|err| err.to_string())
+            });
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                report.findings.push(Finding::BadEntry {
+                    start: range.start,
+                    end: range.end,
+                    error,
+                });
+                return;
+            }
+        };
+
+        let offset = range.start;
+        match entry {
+            SingleLogEntry::Transaction(Transaction::Start(tid)) => {
+                self.started.insert(tid);
+            }
+            SingleLogEntry::Transaction(Transaction::Commit(tid)) => {
+                if !self.started.contains(&tid) {
+                    report
+                        .findings
+                        .push(Finding::CommitWithoutStart { offset, tid });
+                }
+            }
+            SingleLogEntry::Transaction(_) | SingleLogEntry::Checkpoint(_) => {}
+            SingleLogEntry::InsertEntry(_)
+            | SingleLogEntry::ChangeEntry(_)
+            | SingleLogEntry::MultiChangeEntry(_)
+            | SingleLogEntry::DeleteEntry(_) => {
+                let tid = entry.tid().unwrap();
+                if !self.started.contains(&tid) {
+                    report
+                        .findings
+                        .push(Finding::ChangeWithoutStart { offset, tid });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1075 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::wal::header::{FileHeader, HeaderError};
+use crate::wal::iterator::{BlockError, BlockSource, OnCorruption, ReadDirection, WalIterator};
+use crate::wal::record::{BlockFormat, Record, HEADER_SIZE, PADDING_BYTE};
+use crate::wal::{
+    compress, first_record_size, pad_block, pad_for_record, split_bytes_after, Compression,
+};
+use crate::Serializable;
+
+/// Appends records to the end of a log file, assigning each record
+/// a log sequence number (LSN) one greater than the previous record.
+///
+/// The log can be written to any `Read + Write + Seek` target and defaults
+/// to a `File`. The target is read when the writer is created to find the
+/// last LSN, and when sealing a block with block checksums.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::fs::OpenOptions;
+/// use disk_utils::wal::record::{Record, RecordType};
+/// use disk_utils::wal::writer::Writer;
+///
+/// fn main() {
+///     let file = OpenOptions::new()
+///         .read(true)
+///         .append(true)
+///         .create(true)
+///         .open("./files/writer_doc_example")
+///         .unwrap();
+///     let mut writer = Writer::new(file).unwrap();
+///
+///     let record = Record::new(RecordType::Full, vec![1, 2, 3]).unwrap();
+///     writer.append(&record).unwrap();
+///     writer.append(&record).unwrap();
+///     assert_eq!(writer.last_lsn(), 2);
+///     # std::fs::remove_file("./files/writer_doc_example").unwrap();
+/// }
+/// ```
+pub struct Writer<W = File> {
+    file: W,
+    format: BlockFormat,
+    last_lsn: u64,
+    /// Offset in the file where the next record or padding is written.
+    pos: u64,
+    compression: Compression,
+    /// Largest payload of the records entries are split into, if
+    /// smaller than what fits in a block.
+    max_record_size: Option<usize>,
+    /// LSN of the last record synced to disk.
+    synced_lsn: u64,
+    /// Bytes written since the file was last synced.
+    unsynced_bytes: u64,
+    preallocation: Option<Preallocation<W>>,
+    /// Signals the readers waiting for records, once one asked for a notifier.
+    appended: Option<AppendSignal>,
+}
+
+/// Space allocated in the file ahead of the records appended to it.
+struct Preallocation<W> {
+    /// Number of bytes the file is grown by at a time.
+    chunk: u64,
+    /// Length of the file, including the zeroed space past the records.
+    len: u64,
+    set_len: fn(&mut W, u64) -> io::Result<()>,
+}
+
+impl<W> Preallocation<W> {
+    /// Grows the file in chunks until it is at least `end` bytes long.
+    fn reserve(&mut self, file: &mut W, end: u64) -> io::Result<()> {
+        if end > self.len {
+            let len = end.div_ceil(self.chunk) * self.chunk;
+            (self.set_len)(file, len)?;
+            self.len = len;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Read + Write + Seek> Writer<W> {
+    /// Creates a writer appending to the file, continuing from
+    /// the LSN of the last record already in the file.
+    pub fn new(file: W) -> io::Result<Writer<W>> {
+        Writer::with_format(file, BlockFormat::default())
+    }
+
+    /// Creates a writer like `new` for a log written with the given block size.
+    pub fn with_block_size(file: W, block_size: i64) -> io::Result<Writer<W>> {
+        let format = BlockFormat {
+            block_size,
+            ..BlockFormat::default()
+        };
+        Writer::with_format(file, format)
+    }
+
+    /// Creates a writer like `new` for a log written with the given block format.
+    pub fn with_format(mut file: W, format: BlockFormat) -> io::Result<Writer<W>> {
+        let pos = file.seek(SeekFrom::End(0))?;
+        Writer::with_position(file, format, pos)
+    }
+
+    /// Creates a writer like `with_format` for a log file that starts with
+    /// a `FileHeader`, writing the header if the file is empty.
+    ///
+    /// Fails if the file has data but doesn't start with a header, or if
+    /// the header's block format doesn't match the given format.
+    pub fn with_header(mut file: W, format: BlockFormat) -> io::Result<Writer<W>> {
+        format.check()?;
+        let len = file.seek(SeekFrom::End(0))?;
+        if len == 0 {
+            file.write_all(&FileHeader::new(format).to_block())?;
+            return Writer::with_position(file, format, format.block_size as u64);
+        }
+        match FileHeader::read(&mut file)? {
+            Some(_) => Writer::with_position(file, format, len),
+            None => Err(HeaderError::MissingWithData { len }.into()),
+        }
+    }
+
+    /// Creates a writer like `with_format` that appends to a file whose
+    /// end is already known, instead of asking the file for its length.
+    ///
+    /// If the file starts with a `FileHeader`, its block format must
+    /// match the given format.
+    pub fn with_position(mut file: W, format: BlockFormat, pos: u64) -> io::Result<Writer<W>> {
+        format.check()?;
+        if let Some(header) = FileHeader::read(&mut file)? {
+            header.check_format(format)?;
+        }
+        let last_lsn = last_lsn_in_file(&mut file, format)?;
+        file.seek(SeekFrom::Start(pos))?;
+        Ok(Writer {
+            file,
+            format,
+            last_lsn,
+            pos,
+            compression: Compression::default(),
+            max_record_size: None,
+            synced_lsn: last_lsn,
+            unsynced_bytes: 0,
+            preallocation: None,
+            appended: None,
+        })
+    }
+
+    /// Sets the compression applied to entries appended with `append_serializable`.
+    pub fn compression(mut self, compression: Compression) -> Writer<W> {
+        self.compression = compression;
+        self
+    }
+
+    /// Limits the payload of the records entries appended with
+    /// `append_serializable` are split into to at most `max_record_size`
+    /// bytes. Records are never larger than what fits in a block.
+    pub fn max_record_size(mut self, max_record_size: usize) -> Writer<W> {
+        self.max_record_size = Some(max_record_size);
+        self
+    }
+
+    /// Returns the largest payload of the records entries are split into.
+    pub(crate) fn record_size_limit(&self) -> usize {
+        let max_payload_size = self.format.max_payload_size();
+        self.max_record_size
+            .map_or(max_payload_size, |size| cmp::min(size, max_payload_size))
+    }
+
+    /// Returns the largest payload of the first record of an
+    /// entry appended at the current position.
+    pub(crate) fn first_record_size(&self) -> usize {
+        let space = self.format.space_remaining(self.pos);
+        cmp::min(
+            first_record_size(space, self.format),
+            self.record_size_limit(),
+        )
+    }
+
+    /// Appends the record with the next LSN, padding the rest of the
+    /// current block first if the record doesn't fit in it, and returns
+    /// the offset in the file the record was written at.
+    ///
+    /// The writer keeps track of the end of the file itself, so appending
+    /// only writes to the file.
+    pub fn append(&mut self, record: &Record) -> io::Result<u64> {
+        self.append_at(record)
+    }
+
+    /// Serializes the entry and appends it split into records, returning
+    /// the offset in the file of the entry's first record.
+    ///
+    /// The first record fills the rest of the current block, so the
+    /// entry only starts a new block if its header doesn't fit.
+    pub fn append_serializable<S: Serializable>(&mut self, entry: &S) -> io::Result<u64> {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes)?;
+        self.append_bytes(&bytes)
+    }
+
+    /// Appends the bytes of a serialized entry like `append_serializable`.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> io::Result<u64> {
+        let records = self.entry_records(bytes)?;
+        self.append_records(&records)
+    }
+
+    /// Splits the bytes of a serialized entry into the records
+    /// `append_bytes` would append at the current position.
+    pub(crate) fn entry_records(&self, bytes: &[u8]) -> io::Result<Vec<Record>> {
+        let (first, max) = (self.first_record_size(), self.record_size_limit());
+        Ok(match compress(bytes, self.compression) {
+            Some(compressed) => split_bytes_after(&compressed, first, max)?
+                .into_iter()
+                .map(Record::into_compressed)
+                .collect(),
+            None => split_bytes_after(bytes, first, max)?,
+        })
+    }
+
+    pub(crate) fn entry_compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Returns the position the writer would be at after appending the records.
+    pub(crate) fn position_after(&self, records: &[Record]) -> u64 {
+        records
+            .iter()
+            .fold(self.pos, |pos, record| record_end(self.format, pos, record))
+    }
+
+    /// Appends the records, returning the offset of the first record.
+    pub(crate) fn append_records(&mut self, records: &[Record]) -> io::Result<u64> {
+        let mut records = records.iter();
+        let offset = self.append_at(records.next().unwrap())?;
+        for record in records {
+            self.append_at(record)?;
+        }
+        Ok(offset)
+    }
+
+    /// Appends a record copied from another log, keeping its LSN unless
+    /// it isn't after the last LSN of the writer.
+    pub(crate) fn append_copy(&mut self, record: &Record) -> io::Result<u64> {
+        let lsn = cmp::max(record.lsn, self.last_lsn + 1);
+        self.append_with_lsn(record, lsn)
+    }
+
+    /// Appends the record, returning the offset it was written at.
+    fn append_at(&mut self, record: &Record) -> io::Result<u64> {
+        let lsn = self.last_lsn + 1;
+        self.append_with_lsn(record, lsn)
+    }
+
+    fn append_with_lsn(&mut self, record: &Record, lsn: u64) -> io::Result<u64> {
+        let start = self.pos;
+        if let Some(ref mut preallocation) = self.preallocation {
+            let end = record_end(self.format, self.pos, record);
+            preallocation.reserve(&mut self.file, end)?;
+            self.file.seek(SeekFrom::Start(self.pos))?;
+        }
+        self.pos += pad_for_record(&mut self.file, record, self.format, self.pos)?;
+        let offset = self.pos;
+        record.write_with_lsn(&mut self.file, lsn)?;
+        self.pos += (HEADER_SIZE + record.payload.len()) as u64;
+        self.unsynced_bytes += self.pos - start;
+        self.last_lsn = lsn;
+        self.notify_appended();
+        Ok(offset)
+    }
+
+    /// Returns the number of bytes left for records in the current block.
+    ///
+    /// A record that doesn't fit in the space left, along with its header,
+    /// is either split across blocks or written after padding to the next one.
+    pub fn remaining_in_block(&self) -> u64 {
+        self.format.space_remaining(self.pos)
+    }
+
+    /// Pads the rest of the current block, so the next record is appended
+    /// at the start of the next block. Does nothing if the writer is already
+    /// on a block boundary. With block checksums, the padding seals the block.
+    ///
+    /// The padding is skipped when the log is read.
+    pub fn pad_to_block_boundary(&mut self) -> io::Result<()> {
+        let block_size = self.format.block_size as u64;
+        if !self.pos.is_multiple_of(block_size) {
+            let start = self.pos;
+            if let Some(ref mut preallocation) = self.preallocation {
+                preallocation.reserve(&mut self.file, start - start % block_size + block_size)?;
+                self.file.seek(SeekFrom::Start(start))?;
+            }
+            self.pos += pad_block(&mut self.file, self.format, self.pos)?;
+            self.unsynced_bytes += self.pos - start;
+            self.notify_appended();
+        }
+        Ok(())
+    }
+
+    /// Returns a handle that readers following the log, like a
+    /// `TailIterator`, can wait on for more records to be appended.
+    ///
+    /// The handle is notified after every record or padding the writer
+    /// appends, and closed once the writer is dropped.
+    pub fn notifier(&mut self) -> AppendNotifier {
+        self.appended
+            .get_or_insert_with(|| AppendSignal(AppendNotifier::default()))
+            .0
+            .clone()
+    }
+
+    fn notify_appended(&self) {
+        if let Some(ref appended) = self.appended {
+            appended.0.notify();
+        }
+    }
+
+    /// Continues numbering records after the LSN if the file
+    /// has no records with later LSNs.
+    pub(crate) fn continue_after(mut self, last_lsn: u64) -> Writer<W> {
+        if self.last_lsn < last_lsn {
+            self.last_lsn = last_lsn;
+            self.synced_lsn = last_lsn;
+        }
+        self
+    }
+
+    /// Returns the LSN of the last record synced to disk. Records already
+    /// in the file when the writer was created count as synced.
+    pub fn synced_lsn(&self) -> u64 {
+        self.synced_lsn
+    }
+
+    /// Returns the number of bytes appended since the last sync.
+    pub fn unsynced_bytes(&self) -> u64 {
+        self.unsynced_bytes
+    }
+
+    /// Returns the offset in the file where the next record is written,
+    /// unless it has to be padded to the next block first.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Returns the LSN of the last appended record, or 0 if the
+    /// log has no records with LSNs.
+    pub fn last_lsn(&self) -> u64 {
+        self.last_lsn
+    }
+
+    pub fn format(&self) -> BlockFormat {
+        self.format
+    }
+
+    pub fn file(&self) -> &W {
+        &self.file
+    }
+
+    /// Returns the file being written to. Writing to the file directly
+    /// leaves the writer's position behind the end of the file.
+    /// Unless the file is preallocated, reading from it must not move
+    /// the file's cursor away from the end of the file either.
+    pub fn file_mut(&mut self) -> &mut W {
+        &mut self.file
+    }
+
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl<W: Read + Write + Seek + SetLen> Writer<W> {
+    /// Removes the records from the position to the end of the log, so the
+    /// next record is appended at the position. The position must be the
+    /// offset of a record, like the offset of an entry that was torn.
+    ///
+    /// A preallocated file keeps its length, with the removed bytes zeroed.
+    pub fn truncate(&mut self, pos: u64) -> io::Result<()> {
+        if pos > self.pos {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Can't truncate the log past its end",
+            ));
+        }
+        match self.preallocation {
+            Some(_) => {
+                self.file.seek(SeekFrom::Start(pos))?;
+                let zeroes = vec![PADDING_BYTE; (self.pos - pos) as usize];
+                self.file.write_all(&zeroes)?;
+            }
+            None => self.file.set_len(pos)?,
+        }
+        self.last_lsn = last_lsn_in_file(&mut self.file, self.format)?;
+        self.synced_lsn = cmp::min(self.synced_lsn, self.last_lsn);
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Grows the file `blocks` blocks at a time instead of a record at a
+    /// time, so that appending rarely changes the file's length.
+    ///
+    /// The space past the last record is zeroed, which reading the log
+    /// skips over like padding. Records are written after the last record
+    /// instead of at the end of the file, so the file must not be opened
+    /// in append mode.
+    pub fn preallocate(mut self, blocks: u64) -> io::Result<Writer<W>> {
+        if blocks == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Must preallocate at least one block",
+            ));
+        }
+        let len = self.file.seek(SeekFrom::End(0))?;
+        self.pos = end_of_log(&mut self.file, self.format, len)?;
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        self.preallocation = Some(Preallocation {
+            chunk: blocks * self.format.block_size as u64,
+            len,
+            set_len: <W as SetLen>::set_len,
+        });
+        Ok(self)
+    }
+}
+
+impl<W: Read + Write + Seek + SyncData> Writer<W> {
+    /// Syncs the appended records to disk with `SyncData::sync_data`.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.synced_lsn = self.last_lsn;
+        self.unsynced_bytes = 0;
+        Ok(())
+    }
+}
+
+/// Handle to wait on for a `Writer` to append more records, returned by
+/// `Writer::notifier`.
+///
+/// The handle counts the writer's appends, so a reader that found no new
+/// records after reading the count can wait for the count to change
+/// without missing the appends made in between.
+#[derive(Clone, Debug, Default)]
+pub struct AppendNotifier {
+    shared: Arc<(Mutex<AppendState>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct AppendState {
+    appends: u64,
+    closed: bool,
+}
+
+impl AppendNotifier {
+    /// Returns the number of records and paddings the writer has appended
+    /// since the notifier was created.
+    pub fn appends(&self) -> u64 {
+        self.shared.0.lock().unwrap().appends
+    }
+
+    /// Returns whether the writer was dropped, so it won't append again.
+    pub fn is_closed(&self) -> bool {
+        self.shared.0.lock().unwrap().closed
+    }
+
+    /// Blocks until the writer has appended more than `seen` times, the
+    /// writer is dropped or the timeout passes, waiting forever without
+    /// a timeout. Returns whether the writer appended.
+    pub fn wait(&self, seen: u64, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let (ref state, ref appended) = *self.shared;
+        let mut state = state.lock().unwrap();
+        while state.appends <= seen && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    appended.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => appended.wait(state).unwrap(),
+            };
+        }
+        state.appends > seen
+    }
+
+    fn notify(&self) {
+        let (ref state, ref appended) = *self.shared;
+        state.lock().unwrap().appends += 1;
+        appended.notify_all();
+    }
+}
+
+/// The writer's end of its `AppendNotifier`, which closes the
+/// notifier when the writer is dropped.
+struct AppendSignal(AppendNotifier);
+
+impl Drop for AppendSignal {
+    fn drop(&mut self) {
+        let (ref state, ref appended) = *self.0.shared;
+        state.lock().unwrap().closed = true;
+        appended.notify_all();
+    }
+}
+
+/// Target whose writes can be made durable, like `File::sync_data`.
+pub trait SyncData {
+    fn sync_data(&mut self) -> io::Result<()>;
+}
+
+impl SyncData for File {
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
+
+/// In-memory logs have nothing to sync.
+impl<T> SyncData for io::Cursor<T> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Target whose length can be changed, like `File::set_len`.
+pub trait SetLen {
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl SetLen for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+impl SetLen for io::Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+}
+
+/// Options for batching commits in a `GroupWriter`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupCommitOptions {
+    /// How long the committer that writes a batch waits for other
+    /// commits to join it before writing.
+    pub max_delay: Duration,
+    /// Largest number of entry bytes written in a single batch. A batch
+    /// always holds at least one entry, and stops waiting once it is full.
+    pub max_batch_bytes: usize,
+}
+
+impl Default for GroupCommitOptions {
+    fn default() -> GroupCommitOptions {
+        GroupCommitOptions {
+            max_delay: Duration::from_millis(0),
+            max_batch_bytes: 1 << 20,
+        }
+    }
+}
+
+/// Writer shared between threads that batches concurrent commits
+/// into a single write and sync.
+///
+/// Each committer queues its serialized entry and waits. The first
+/// committer to find no batch being written writes every queued entry
+/// and syncs the log once for all of them, then wakes the others.
+/// Entries queued while a batch is written go in the next batch.
+///
+/// If writing a batch fails the state of the file is unknown,
+/// so every later commit returns an error.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::fs::OpenOptions;
+/// use std::sync::Arc;
+/// use std::thread;
+/// use disk_utils::wal::writer::{GroupCommitOptions, GroupWriter, Writer};
+///
+/// fn main() {
+///     let file = OpenOptions::new()
+///         .read(true)
+///         .append(true)
+///         .create(true)
+///         .open("./files/group_writer_doc_example")
+///         .unwrap();
+///     let writer = Writer::new(file).unwrap();
+///     let group = Arc::new(GroupWriter::new(writer, GroupCommitOptions::default()));
+///
+///     let threads: Vec<_> = (0..4u64)
+///         .map(|i| {
+///             let group = group.clone();
+///             thread::spawn(move || group.commit(&i).unwrap())
+///         })
+///         .collect();
+///     for thread in threads {
+///         thread.join().unwrap();
+///     }
+///     assert!(group.syncs() <= 4);
+///     # std::fs::remove_file("./files/group_writer_doc_example").unwrap();
+/// }
+/// ```
+pub struct GroupWriter<W = File> {
+    writer: Mutex<Writer<W>>,
+    state: Mutex<GroupState>,
+    changed: Condvar,
+    options: GroupCommitOptions,
+}
+
+struct GroupState {
+    /// Serialized entries waiting for a batch, with their sequence numbers.
+    queue: VecDeque<(u64, Vec<u8>)>,
+    queued_bytes: usize,
+    next_seq: u64,
+    /// Every entry with a sequence number below this has been synced.
+    synced_seq: u64,
+    /// Whether a committer is writing a batch.
+    writing: bool,
+    /// The failure that poisoned the writer.
+    error: Option<(io::ErrorKind, String)>,
+    syncs: usize,
+}
+
+impl<W: Read + Write + Seek + SyncData> GroupWriter<W> {
+    pub fn new(writer: Writer<W>, options: GroupCommitOptions) -> GroupWriter<W> {
+        GroupWriter {
+            writer: Mutex::new(writer),
+            state: Mutex::new(GroupState {
+                queue: VecDeque::new(),
+                queued_bytes: 0,
+                next_seq: 0,
+                synced_seq: 0,
+                writing: false,
+                error: None,
+                syncs: 0,
+            }),
+            changed: Condvar::new(),
+            options,
+        }
+    }
+
+    /// Appends the entry and returns once it is synced to disk
+    /// along with the other entries in its batch.
+    pub fn commit<S: Serializable>(&self, entry: &S) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes)?;
+
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.queued_bytes += bytes.len();
+        state.queue.push_back((seq, bytes));
+        self.changed.notify_all();
+
+        loop {
+            if let Some((kind, ref message)) = state.error {
+                return Err(io::Error::new(kind, message.clone()));
+            }
+            if state.synced_seq > seq {
+                return Ok(());
+            }
+            if state.writing {
+                state = self.changed.wait(state).unwrap();
+                continue;
+            }
+
+            state.writing = true;
+            state = self.wait_for_batch(state);
+            let batch = take_batch(&mut state, self.options.max_batch_bytes);
+            drop(state);
+
+            let result = self.write_batch(&batch);
+            state = self.state.lock().unwrap();
+            state.writing = false;
+            match result {
+                Ok(()) => {
+                    state.synced_seq = batch.last().map(|&(seq, _)| seq + 1).unwrap();
+                    state.syncs += 1;
+                }
+                Err(e) => state.error = Some((e.kind(), e.to_string())),
+            }
+            self.changed.notify_all();
+        }
+    }
+
+    /// Returns the number of times the log has been synced.
+    pub fn syncs(&self) -> usize {
+        self.state.lock().unwrap().syncs
+    }
+
+    pub fn into_inner(self) -> Writer<W> {
+        self.writer.into_inner().unwrap()
+    }
+
+    /// Waits up to the max delay for the queued entries to fill a batch.
+    fn wait_for_batch<'a>(
+        &self,
+        mut state: MutexGuard<'a, GroupState>,
+    ) -> MutexGuard<'a, GroupState> {
+        let deadline = Instant::now() + self.options.max_delay;
+        while state.queued_bytes < self.options.max_batch_bytes {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        state
+    }
+
+    fn write_batch(&self, batch: &[(u64, Vec<u8>)]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for (_, bytes) in batch.iter() {
+            writer.append_bytes(bytes)?;
+        }
+        writer.sync()
+    }
+}
+
+/// Takes the queued entries that fit in a batch, and at least one entry.
+fn take_batch(state: &mut GroupState, max_batch_bytes: usize) -> Vec<(u64, Vec<u8>)> {
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    while let Some((_, bytes)) = state.queue.front() {
+        if !batch.is_empty() && batch_bytes + bytes.len() > max_batch_bytes {
+            break;
+        }
+        batch_bytes += bytes.len();
+        batch.push(state.queue.pop_front().unwrap());
+    }
+    state.queued_bytes -= batch_bytes;
+    batch
+}
+
+/// Writer shared between threads that appends entries on a background thread.
+///
+/// Entries are serialized by the threads appending them and sent to the
+/// background thread through a bounded channel, so appending blocks when
+/// the channel is full. The background thread appends every entry it has
+/// received and syncs the log once for all of them. The handle returned
+/// for each entry waits until the entry is synced.
+///
+/// If appending an entry or syncing fails, the entry and every entry after
+/// it return the error, since the state of the file is unknown.
+///
+/// Dropping the last clone of the writer waits for the background
+/// thread to append and sync the entries already sent.
+///
+/// # Examples
+///
+/// ```
+/// extern crate disk_utils;
+/// use std::io::Cursor;
+/// use std::thread;
+/// use disk_utils::wal::writer::{SharedWriter, Writer};
+///
+/// fn main() {
+///     let writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+///     let shared = SharedWriter::new(writer, 16);
+///     let threads: Vec<_> = (0..4)
+///         .map(|i| {
+///             let shared = shared.clone();
+///             thread::spawn(move || shared.append_serializable(&i.to_string()).unwrap().wait())
+///         })
+///         .collect();
+///     for thread in threads {
+///         thread.join().unwrap().unwrap();
+///     }
+///     assert_eq!(shared.close().unwrap().last_lsn(), 4);
+/// }
+/// ```
+pub struct SharedWriter<W: Send + 'static = File> {
+    inner: Arc<SharedInner<W>>,
+}
+
+impl<W: Send + 'static> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct SharedInner<W: Send + 'static> {
+    sender: Mutex<SharedSender>,
+    thread: Option<JoinHandle<Writer<W>>>,
+    status: Arc<SharedStatus>,
+}
+
+struct SharedSender {
+    sender: Option<SyncSender<(u64, Vec<u8>)>>,
+    next_seq: u64,
+}
+
+/// Progress of the background thread, shared with the flush handles.
+struct SharedStatus {
+    state: Mutex<SharedState>,
+    changed: Condvar,
+}
+
+struct SharedState {
+    /// Every entry with a sequence number below this has been synced.
+    synced_seq: u64,
+    /// The failure that poisoned the writer, and the sequence
+    /// number of the first entry it failed.
+    error: Option<(u64, io::ErrorKind, String)>,
+}
+
+impl<W: Read + Write + Seek + SyncData + Send + 'static> SharedWriter<W> {
+    /// Starts the background thread appending to the writer. At most
+    /// `capacity` entries wait in the channel for the thread.
+    pub fn new(writer: Writer<W>, capacity: usize) -> SharedWriter<W> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let status = Arc::new(SharedStatus {
+            state: Mutex::new(SharedState {
+                synced_seq: 0,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        });
+        let thread_status = status.clone();
+        let thread = thread::spawn(move || append_received(writer, receiver, &thread_status));
+        SharedWriter {
+            inner: Arc::new(SharedInner {
+                sender: Mutex::new(SharedSender {
+                    sender: Some(sender),
+                    next_seq: 0,
+                }),
+                thread: Some(thread),
+                status,
+            }),
+        }
+    }
+
+    /// Serializes the entry and sends it to be appended, returning
+    /// a handle that waits until the entry is synced.
+    pub fn append_serializable<S: Serializable>(&self, entry: &S) -> io::Result<FlushHandle> {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes)?;
+        self.append_bytes(bytes)
+    }
+
+    /// Sends the bytes of a serialized entry to be appended
+    /// like `append_serializable`.
+    pub fn append_bytes(&self, bytes: Vec<u8>) -> io::Result<FlushHandle> {
+        let mut sender = self.inner.sender.lock().unwrap();
+        let seq = sender.next_seq;
+        let sent = match sender.sender {
+            Some(ref sender) => sender.send((seq, bytes)).is_ok(),
+            None => false,
+        };
+        if !sent {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Background writer thread has stopped",
+            ));
+        }
+        sender.next_seq += 1;
+        Ok(FlushHandle {
+            seq,
+            status: self.inner.status.clone(),
+        })
+    }
+
+    /// Waits until every entry sent so far is synced.
+    pub fn flush(&self) -> io::Result<()> {
+        let seq = self.inner.sender.lock().unwrap().next_seq;
+        match seq {
+            0 => Ok(()),
+            seq => self.inner.status.wait(seq - 1),
+        }
+    }
+
+    /// Stops the background thread once it has appended and synced the
+    /// entries already sent, returning the writer. Fails if the writer
+    /// is still shared with other clones.
+    pub fn close(self) -> io::Result<Writer<W>> {
+        let mut inner = Arc::try_unwrap(self.inner)
+            .map_err(|_| io::Error::new(io::ErrorKind::WouldBlock, "Writer is still shared"))?;
+        inner.sender.get_mut().unwrap().sender = None;
+        let thread = inner.thread.take().unwrap();
+        thread
+            .join()
+            .map_err(|_| io::Error::other("Background writer thread panicked"))
+    }
+}
+
+impl<W: Send + 'static> Drop for SharedInner<W> {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread after the entries already sent.
+        self.sender.get_mut().unwrap().sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Waits for an entry sent to a `SharedWriter` to be synced.
+pub struct FlushHandle {
+    seq: u64,
+    status: Arc<SharedStatus>,
+}
+
+impl FlushHandle {
+    /// Blocks until the entry is synced to disk, or returns the error
+    /// that kept it from being appended or synced.
+    pub fn wait(self) -> io::Result<()> {
+        self.status.wait(self.seq)
+    }
+}
+
+impl SharedStatus {
+    fn wait(&self, seq: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.synced_seq > seq {
+                return Ok(());
+            }
+            if let Some((failed_seq, kind, ref message)) = state.error {
+                if seq >= failed_seq {
+                    return Err(io::Error::new(kind, message.clone()));
+                }
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+/// Appends the entries received until the channel is closed, syncing
+/// after appending every entry already waiting in the channel.
+fn append_received<W: Read + Write + Seek + SyncData>(
+    mut writer: Writer<W>,
+    receiver: Receiver<(u64, Vec<u8>)>,
+    status: &SharedStatus,
+) -> Writer<W> {
+    let mut poisoned = false;
+    while let Ok(first) = receiver.recv() {
+        if poisoned {
+            continue;
+        }
+        let batch: Vec<_> = iter::once(first).chain(receiver.try_iter()).collect();
+        let mut appended = 0;
+        let mut error = None;
+        for (seq, bytes) in batch.iter() {
+            match writer.append_bytes(bytes) {
+                Ok(_) => appended += 1,
+                Err(e) => {
+                    error = Some((*seq, e));
+                    break;
+                }
+            }
+        }
+        if appended > 0 {
+            if let Err(e) = writer.sync() {
+                // None of the batch is known to be on disk.
+                appended = 0;
+                error = Some((batch[0].0, e));
+            }
+        }
+
+        let mut state = status.state.lock().unwrap();
+        if appended > 0 {
+            state.synced_seq = batch[appended - 1].0 + 1;
+        }
+        if let Some((seq, e)) = error {
+            state.error = Some((seq, e.kind(), e.to_string()));
+            poisoned = true;
+        }
+        status.changed.notify_all();
+    }
+    writer
+}
+
+/// Returns the LSN of the last intact record in the file, skipping
+/// corrupted blocks so a damaged tail doesn't prevent opening the log.
+pub(crate) fn last_lsn_in_file<R: Read + Seek>(
+    file: &mut R,
+    format: BlockFormat,
+) -> io::Result<u64> {
+    let mut iter = WalIterator::with_format(file, ReadDirection::Backward, format)
+        .map_err(block_error_to_io)?
+        .on_corruption(OnCorruption::SkipToNextBlock);
+    let record = iter.try_next_back().map_err(block_error_to_io)?;
+    Ok(record.map(|record| record.lsn).unwrap_or(0))
+}
+
+/// Returns the position after appending the record at the
+/// position, including the padding written before it.
+fn record_end(format: BlockFormat, pos: u64, record: &Record) -> u64 {
+    let len = (HEADER_SIZE + record.payload.len()) as u64;
+    let block_size = format.block_size as u64;
+    if len > format.space_remaining(pos) {
+        pos - pos % block_size + block_size + len
+    } else {
+        pos + len
+    }
+}
+
+/// Returns the offset just past the last record in the first `len` bytes
+/// of the file, skipping the zeroed blocks at the end of a preallocated file.
+pub(crate) fn end_of_log<R: Read + Seek>(
+    file: &mut R,
+    format: BlockFormat,
+    len: u64,
+) -> io::Result<u64> {
+    let block_size = format.block_size as u64;
+    let capacity = format.capacity();
+    let mut pos = len - len % block_size;
+    if pos == len && pos > 0 {
+        pos -= block_size;
+    }
+    loop {
+        let bytes = file.read_at(pos, cmp::min(block_size, len - pos) as usize)?;
+        if bytes.is_empty() || bytes[0] == PADDING_BYTE {
+            if pos == 0 {
+                return Ok(0);
+            }
+            pos -= block_size;
+            continue;
+        }
+
+        // Every block with records starts with one, so walk the headers.
+        let limit = cmp::min(capacity, bytes.len());
+        let mut offset = 0;
+        while offset < limit && bytes[offset] != PADDING_BYTE {
+            match Record::parse_len(&bytes[offset..], capacity - offset) {
+                Ok(len) => offset += len,
+                // Don't write over a damaged record.
+                Err(_) => offset = bytes.len(),
+            }
+        }
+        return Ok(cmp::min(pos + offset as u64, len));
+    }
+}
+
+/// Returns whether the bytes of the file from `start` to `len` are only
+/// padding, apart from block trailers, like the zeroed space at the end of
+/// a preallocated file.
+pub(crate) fn only_padding<R: Read + Seek>(
+    file: &mut R,
+    format: BlockFormat,
+    start: u64,
+    len: u64,
+) -> io::Result<bool> {
+    let block_size = format.block_size as u64;
+    let capacity = format.capacity() as u64;
+    let mut pos = start;
+    while pos < len {
+        let block_end = cmp::min(pos - pos % block_size + capacity, len);
+        if pos < block_end {
+            let bytes = file.read_at(pos, (block_end - pos) as usize)?;
+            if bytes.iter().any(|&byte| byte != PADDING_BYTE) {
+                return Ok(false);
+            }
+        }
+        pos = pos - pos % block_size + block_size;
+    }
+    Ok(true)
+}
+
+fn block_error_to_io(err: BlockError) -> io::Error {
+    match err {
+        BlockError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", err)),
+    }
+}
@@ -0,0 +1,126 @@
+//! Store shared by the integration tests.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use disk_utils::wal::snapshot::SnapshotSource;
+use disk_utils::wal::{LogData, LogStore};
+
+/// Log data of the `TestStore`, mapping integer keys to strings.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TestData;
+
+impl LogData for TestData {
+    type Key = i32;
+    type Value = String;
+}
+
+/// Store for testing logs, keeping its changes in memory along with the
+/// changes flushed so far, which are the ones that survive a crash.
+/// Clones share the data, so a test can keep a clone of a log's store.
+#[derive(Clone, Debug, Default)]
+pub struct TestStore {
+    data: Arc<Mutex<HashMap<i32, String>>>,
+    flushed_data: Arc<Mutex<HashMap<i32, String>>>,
+    flush_err: Arc<Mutex<bool>>,
+    recorded_lsn: Arc<Mutex<Option<u64>>>,
+}
+
+impl TestStore {
+    pub fn new() -> TestStore {
+        TestStore::default()
+    }
+
+    /// Creates a store holding the flushed changes in the map.
+    pub fn with_contents(map: HashMap<i32, String>) -> TestStore {
+        TestStore {
+            data: Arc::new(Mutex::new(map.clone())),
+            flushed_data: Arc::new(Mutex::new(map)),
+            ..TestStore::default()
+        }
+    }
+
+    /// Returns a copy of the changes in the store.
+    pub fn map(&self) -> HashMap<i32, String> {
+        self.data.lock().unwrap().clone()
+    }
+
+    pub fn get_flushed(&self, key: &i32) -> Option<String> {
+        self.flushed_data.lock().unwrap().get(key).cloned()
+    }
+
+    /// Makes flushing the store fail, after the flushed changes were
+    /// recorded, until it's turned off again.
+    pub fn set_flush_err(&self, flush_err: bool) {
+        *self.flush_err.lock().unwrap() = flush_err;
+    }
+
+    /// Loses the changes that weren't flushed, like a crash would.
+    pub fn discard_changes(&self) {
+        *self.data.lock().unwrap() = self.flushed_data.lock().unwrap().clone();
+    }
+
+    /// Returns a new store with only the changes that survived a crash.
+    pub fn after_crash(&self) -> TestStore {
+        TestStore::with_contents(self.flushed_data.lock().unwrap().clone())
+    }
+
+    /// Returns the LSN last given to `set_applied_lsn`. The store doesn't
+    /// report it back from `applied_lsn`, so recovery replays every
+    /// committed transaction into it.
+    pub fn recorded_lsn(&self) -> Option<u64> {
+        *self.recorded_lsn.lock().unwrap()
+    }
+
+    fn flush_result(&self) -> io::Result<()> {
+        if *self.flush_err.lock().unwrap() {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Flush error occurred",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl LogStore<TestData> for TestStore {
+    fn get(&self, key: &i32) -> Option<String> {
+        self.data.lock().unwrap().get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &i32) {
+        self.data.lock().unwrap().remove(key);
+    }
+
+    fn update(&mut self, key: i32, val: String) {
+        self.data.lock().unwrap().insert(key, val);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        *self.flushed_data.lock().unwrap() = self.map();
+        self.flush_result()
+    }
+
+    fn flush_change(&mut self, key: i32, val: String) -> io::Result<()> {
+        self.flushed_data.lock().unwrap().insert(key, val);
+        self.flush_result()
+    }
+
+    fn flush_remove(&mut self, key: &i32) -> io::Result<()> {
+        self.flushed_data.lock().unwrap().remove(key);
+        self.flush_result()
+    }
+
+    fn set_applied_lsn(&mut self, lsn: u64) {
+        *self.recorded_lsn.lock().unwrap() = Some(lsn);
+    }
+}
+
+impl SnapshotSource<TestData> for TestStore {
+    fn iter_all(&self) -> Box<dyn Iterator<Item = (i32, String)> + '_> {
+        Box::new(self.map().into_iter())
+    }
+}
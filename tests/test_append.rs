@@ -1,11 +1,15 @@
 extern crate disk_utils;
 
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
 
 use disk_utils::testing::{create_test_file, create_two_test_files};
-use disk_utils::wal::append_to_file;
 use disk_utils::wal::iterator::{ReadDirection, WalIterator};
-use disk_utils::wal::record::{Record, RecordType, BLOCK_SIZE, HEADER_SIZE};
+use disk_utils::wal::record::{BlockFormat, Record, RecordType, BLOCK_SIZE, HEADER_SIZE};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{
+    append_to_file, append_to_file_with_block_size, split_bytes_for_append,
+    split_bytes_into_records,
+};
 
 #[test]
 fn test_no_padding_on_same_block() {
@@ -19,7 +23,7 @@ fn test_no_padding_on_same_block() {
             _ => RecordType::Middle,
         };
 
-        records.push(Record::new(record_type, vec![123; payload_size as usize]));
+        records.push(Record::new(record_type, vec![123; payload_size as usize]).unwrap());
     }
 
     create_two_test_files(
@@ -38,7 +42,9 @@ fn test_no_padding_on_same_block() {
 
             let mut num_comparisons = 0;
             let file_len = direct_write_file.metadata().unwrap().len();
-            for (b1, b2) in direct_write_file.bytes().zip(writer_file.bytes()) {
+            let direct_bytes = BufReader::new(&mut direct_write_file).bytes();
+            let writer_bytes = BufReader::new(&mut writer_file).bytes();
+            for (b1, b2) in direct_bytes.zip(writer_bytes) {
                 assert_eq!(b1.unwrap(), b2.unwrap());
                 num_comparisons += 1;
             }
@@ -60,7 +66,7 @@ fn test_padding_before_new_block() {
             _ => RecordType::Middle,
         };
 
-        records.push(Record::new(record_type, vec![123; payload_size as usize]));
+        records.push(Record::new(record_type, vec![123; payload_size as usize]).unwrap());
     }
 
     create_two_test_files(
@@ -102,7 +108,7 @@ fn test_single_bytes() {
             _ => RecordType::Middle,
         };
 
-        records.push(Record::new(record_type, vec![0]));
+        records.push(Record::new(record_type, vec![0]).unwrap());
     }
 
     create_test_file("./files/single_byte_test", move |_, mut file| {
@@ -120,3 +126,185 @@ fn test_single_bytes() {
     })
     .unwrap();
 }
+
+#[test]
+fn test_padding_includes_header() {
+    // The second record's payload fits in the rest of the first block,
+    // but its header doesn't.
+    let remaining = 20;
+    let payload_size = remaining - HEADER_SIZE / 2;
+    let records = [
+        Record::new(
+            RecordType::Full,
+            vec![1; BLOCK_SIZE as usize - HEADER_SIZE - remaining],
+        )
+        .unwrap(),
+        Record::new(RecordType::Full, vec![2; payload_size]).unwrap(),
+        Record::new(RecordType::Full, vec![3; 5]).unwrap(),
+    ];
+
+    create_two_test_files(
+        "./files/padding_includes_header",
+        "./files/padding_includes_header_writer",
+        move |_, _, mut file, writer_file| {
+            for record in records.iter() {
+                append_to_file(&mut file, record).unwrap();
+            }
+            let mut writer = Writer::new(writer_file).unwrap();
+            for record in records.iter() {
+                writer.append(record).unwrap();
+            }
+            let mut writer_file = writer.into_inner();
+
+            for file in [&mut file, &mut writer_file] {
+                let len = file.metadata().unwrap().len();
+                assert_eq!(
+                    len,
+                    BLOCK_SIZE as u64 + (2 * HEADER_SIZE + payload_size + 5) as u64
+                );
+
+                let forward: Vec<_> = WalIterator::new(file, ReadDirection::Forward)
+                    .unwrap()
+                    .map(|record| record.payload.to_vec())
+                    .collect();
+                let mut iter = WalIterator::new(file, ReadDirection::Backward).unwrap();
+                let mut backward: Vec<_> = iter
+                    .by_ref()
+                    .rev()
+                    .map(|record| record.payload.to_vec())
+                    .collect();
+                assert!(iter.error().is_none());
+                backward.reverse();
+
+                let payloads: Vec<_> = records.iter().map(|r| r.payload.to_vec()).collect();
+                assert_eq!(forward, payloads);
+                assert_eq!(backward, payloads);
+            }
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_append_returns_offsets() {
+    // The second record pads the rest of the first block.
+    let first_size = BLOCK_SIZE as usize - HEADER_SIZE - 10;
+    let records = [
+        Record::new(RecordType::Full, vec![1; first_size]).unwrap(),
+        Record::new(RecordType::Full, vec![2; 20]).unwrap(),
+        Record::new(RecordType::Full, vec![3; 5]).unwrap(),
+    ];
+    let expected = [
+        0,
+        BLOCK_SIZE as u64,
+        BLOCK_SIZE as u64 + (HEADER_SIZE + 20) as u64,
+    ];
+
+    create_two_test_files(
+        "./files/append_returns_offsets",
+        "./files/append_returns_offsets_writer",
+        move |_, _, mut file, writer_file| {
+            let offsets: Vec<_> = records
+                .iter()
+                .map(|record| append_to_file(&mut file, record).unwrap())
+                .collect();
+            assert_eq!(offsets, expected);
+            let mut writer = Writer::new(writer_file).unwrap();
+            let writer_offsets: Vec<_> = records
+                .iter()
+                .map(|record| writer.append(record).unwrap())
+                .collect();
+            assert_eq!(writer_offsets, expected);
+            let mut writer_file = writer.into_inner();
+
+            for file in [&mut file, &mut writer_file] {
+                let mut iter = WalIterator::new(file, ReadDirection::Forward).unwrap();
+                for (record, &offset) in records.iter().zip(expected.iter()) {
+                    iter.seek(offset).unwrap();
+                    assert_eq!(iter.next().unwrap().payload, record.payload);
+                }
+            }
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_split_bytes_for_append() {
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let bytes = vec![1; 1000];
+    let lens = |records: &[Record]| -> Vec<usize> {
+        records.iter().map(|record| record.payload.len()).collect()
+    };
+
+    // The first record fills the rest of the block and the rest fill whole blocks.
+    let records = split_bytes_for_append(&bytes, 100, format).unwrap();
+    assert_eq!(lens(&records), vec![85, 241, 241, 241, 192]);
+    assert_eq!(records[0].record_type, RecordType::First);
+    assert_eq!(records[4].record_type, RecordType::Last);
+
+    let records = split_bytes_for_append(&bytes, 256, format).unwrap();
+    assert_eq!(lens(&records), vec![241, 241, 241, 241, 36]);
+
+    // A block without room for a record header is padded, so the first
+    // record fills the next block.
+    let records = split_bytes_for_append(&bytes, HEADER_SIZE as u64, format).unwrap();
+    assert_eq!(lens(&records), vec![241, 241, 241, 241, 36]);
+
+    let records = split_bytes_for_append(&bytes[..50], 100, format).unwrap();
+    assert_eq!(lens(&records), vec![50]);
+    assert_eq!(records[0].record_type, RecordType::Full);
+}
+
+#[test]
+fn test_large_entries_fill_blocks() {
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    // Each entry takes exactly four blocks of records.
+    let entry = vec![7; 4 * (256 - HEADER_SIZE)];
+    for _ in 0..10 {
+        writer.append_bytes(&entry).unwrap();
+    }
+    assert_eq!(writer.position(), 10 * 4 * 256);
+
+    let mut iter =
+        WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, format).unwrap();
+    assert_eq!(iter.by_ref().count(), 40);
+    assert_eq!(iter.stats().padding_bytes, 0);
+}
+
+#[test]
+fn test_large_entries_pad_less_than_fixed_records() {
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    let mut fixed = Cursor::new(Vec::new());
+    for i in 0..50 {
+        let entry = vec![i as u8; 500 + i * 37];
+        writer.append_bytes(&entry).unwrap();
+        for record in split_bytes_into_records(&entry, 100).unwrap() {
+            append_to_file_with_block_size(&mut fixed, &record, 256).unwrap();
+        }
+    }
+
+    let mut iter =
+        WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, format).unwrap();
+    iter.by_ref().count();
+    let padding = iter.stats().padding_bytes;
+    // Blocks are only padded when the space left can't hold a record header.
+    let blocks = writer.position() / 256;
+    assert!(padding < blocks * HEADER_SIZE as u64);
+
+    let mut iter = WalIterator::with_format(&mut fixed, ReadDirection::Forward, format).unwrap();
+    iter.by_ref().count();
+    assert!(iter.stats().padding_bytes > padding);
+    assert!(writer.position() < fixed.get_ref().len() as u64);
+}
@@ -0,0 +1,298 @@
+#![cfg(feature = "async")]
+
+extern crate disk_utils;
+extern crate tokio;
+
+mod common;
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+
+use common::{TestData, TestStore};
+use disk_utils::wal::asynchronous::{AsyncEntries, AsyncWriter};
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::header::FileHeader;
+use disk_utils::wal::iterator::{BlockError, OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::record::BlockFormat;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{LogOptions, SerializeError};
+
+const BLOCK_SIZE: i64 = 256;
+
+type Entry = SingleLogEntry<TestData>;
+
+/// Path of a test file that is removed when dropped, since
+/// `create_test_file` can't run async code.
+struct TestPath(&'static str);
+
+impl TestPath {
+    fn new(path: &'static str) -> TestPath {
+        let _ = fs::remove_file(path);
+        TestPath(path)
+    }
+
+    fn create(&self) -> File {
+        OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(self.0)
+            .unwrap()
+    }
+}
+
+impl Drop for TestPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(self.0);
+    }
+}
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        ..LogOptions::default()
+    }
+}
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    }
+}
+
+/// Entries of committed transactions, with values long enough that
+/// some entries are split across blocks.
+fn entries() -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for tid in 1..=20 {
+        entries.push(SingleLogEntry::Transaction(Transaction::Start(tid)));
+        entries.push(SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid,
+            key: tid as i32,
+            value: "v".repeat(tid as usize * 17),
+        }));
+        entries.push(SingleLogEntry::Transaction(Transaction::Commit(tid)));
+    }
+    entries
+}
+
+fn read_entries(path: &str, direction: ReadDirection) -> Vec<Entry> {
+    let mut file = File::open(path).unwrap();
+    let entries = WalIterator::with_format(&mut file, direction, format())
+        .unwrap()
+        .entries();
+    match direction {
+        ReadDirection::Forward => entries.map(Result::unwrap).collect(),
+        ReadDirection::Backward => entries.rev().map(Result::unwrap).collect(),
+    }
+}
+
+async fn read_async_entries(
+    path: &str,
+    direction: ReadDirection,
+    on_corruption: OnCorruption,
+) -> Vec<Result<Entry, SerializeError>> {
+    let mut stream = AsyncEntries::open(path, direction, format(), on_corruption)
+        .await
+        .unwrap();
+    let mut entries = Vec::new();
+    while let Some(entry) = stream.next().await {
+        entries.push(entry);
+    }
+    entries
+}
+
+#[tokio::test]
+async fn test_async_append_matches_sync() {
+    let async_path = TestPath::new("./files/async_append");
+    let sync_path = TestPath::new("./files/async_append_sync");
+
+    let mut writer = AsyncWriter::new(Writer::with_format(async_path.create(), format()).unwrap());
+    let mut sync_writer = Writer::with_format(sync_path.create(), format()).unwrap();
+    for entry in entries() {
+        let offset = writer.append_serializable(&entry).await.unwrap();
+        assert_eq!(sync_writer.append_serializable(&entry).unwrap(), offset);
+    }
+    writer.flush().await.unwrap();
+    writer.sync().await.unwrap();
+    let writer = writer.into_inner().unwrap();
+    assert_eq!(writer.synced_lsn(), writer.last_lsn());
+    assert_eq!(writer.position(), sync_writer.position());
+
+    assert_eq!(
+        fs::read(async_path.0).unwrap(),
+        fs::read(sync_path.0).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_async_open_writes_header() {
+    let async_path = TestPath::new("./files/async_open_header");
+    let sync_path = TestPath::new("./files/async_open_header_sync");
+
+    let mut writer = AsyncWriter::open(async_path.0, options()).await.unwrap();
+    let mut sync_writer = Writer::with_header(sync_path.create(), format()).unwrap();
+    for entry in entries() {
+        writer.append_serializable(&entry).await.unwrap();
+        sync_writer.append_serializable(&entry).unwrap();
+    }
+    drop(writer);
+
+    // Only the creation time in the header can differ.
+    let bytes = fs::read(async_path.0).unwrap();
+    let sync_bytes = fs::read(sync_path.0).unwrap();
+    let header = FileHeader::parse(&bytes).unwrap();
+    assert_eq!(header.format(), format());
+    assert_eq!(
+        header.format(),
+        FileHeader::parse(&sync_bytes).unwrap().format()
+    );
+    let block_size = BLOCK_SIZE as usize;
+    assert_eq!(bytes[block_size..], sync_bytes[block_size..]);
+
+    // Reopening continues after the appended entries.
+    let mut writer = AsyncWriter::open(async_path.0, options()).await.unwrap();
+    let entry: Entry = SingleLogEntry::Transaction(Transaction::Start(21));
+    let offset = writer.append_serializable(&entry).await.unwrap();
+    assert!(offset >= bytes.len() as u64);
+    assert_eq!(read_entries(async_path.0, ReadDirection::Forward).len(), 61);
+}
+
+#[tokio::test]
+async fn test_async_entries() {
+    let path = TestPath::new("./files/async_entries");
+    let mut writer = Writer::with_header(path.create(), format()).unwrap();
+    for entry in entries() {
+        writer.append_serializable(&entry).unwrap();
+    }
+
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let entries: Vec<_> = read_async_entries(path.0, direction, OnCorruption::Error)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries, read_entries(path.0, direction));
+    }
+    let mut reversed = entries();
+    reversed.reverse();
+    let backward: Vec<_> = read_async_entries(path.0, ReadDirection::Backward, OnCorruption::Error)
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(backward, reversed);
+}
+
+#[tokio::test]
+async fn test_async_entries_open_errors() {
+    let result = AsyncEntries::<Entry>::open(
+        "./files/async_entries_missing",
+        ReadDirection::Forward,
+        format(),
+        OnCorruption::Error,
+    )
+    .await;
+    match result {
+        Err(BlockError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+        _ => panic!("Expected opening a missing log to fail"),
+    }
+
+    // The header is checked against the format like `WalIterator`.
+    let path = TestPath::new("./files/async_entries_mismatch");
+    Writer::with_header(path.create(), format()).unwrap();
+    let result = AsyncEntries::<Entry>::open(
+        path.0,
+        ReadDirection::Forward,
+        BlockFormat::default(),
+        OnCorruption::Error,
+    )
+    .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_async_entries_corruption() {
+    let path = TestPath::new("./files/async_entries_corruption");
+    let mut writer = Writer::with_header(path.create(), format()).unwrap();
+    for entry in entries() {
+        writer.append_serializable(&entry).unwrap();
+    }
+    let mut file = OpenOptions::new().write(true).open(path.0).unwrap();
+    file.seek(SeekFrom::Start(3 * BLOCK_SIZE as u64 + 100))
+        .unwrap();
+    file.write_all(&[0xFF]).unwrap();
+
+    // The corruption is an item of the stream like with `entries`.
+    let entries = read_async_entries(path.0, ReadDirection::Forward, OnCorruption::Error).await;
+    let corruption = entries
+        .iter()
+        .position(|entry| {
+            matches!(
+                entry,
+                Err(SerializeError::Corrupted(BlockError::Corrupted { .. }))
+            )
+        })
+        .unwrap();
+    assert!(entries[..corruption].iter().all(Result::is_ok));
+
+    let mut file = File::open(path.0).unwrap();
+    let expected: Vec<_> = WalIterator::with_format(&mut file, ReadDirection::Forward, format())
+        .unwrap()
+        .on_corruption(OnCorruption::SkipToNextBlock)
+        .entries::<Entry>()
+        .filter_map(Result::ok)
+        .collect();
+    let skipped: Vec<_> = read_async_entries(
+        path.0,
+        ReadDirection::Forward,
+        OnCorruption::SkipToNextBlock,
+    )
+    .await
+    .into_iter()
+    .filter_map(Result::ok)
+    .collect();
+    assert_eq!(skipped, expected);
+}
+
+#[tokio::test]
+async fn test_async_recover() {
+    let path = TestPath::new("./files/async_recover");
+    let mut writer = AsyncWriter::open(path.0, options()).await.unwrap();
+    for entry in entries() {
+        writer.append_serializable(&entry).await.unwrap();
+    }
+    // Leave a transaction uncommitted at the end of the log.
+    let uncommitted: Vec<Entry> = vec![
+        SingleLogEntry::Transaction(Transaction::Start(21)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 21,
+            key: 1,
+            value: "uncommitted".to_string(),
+        }),
+    ];
+    for entry in &uncommitted {
+        writer.append_serializable(entry).await.unwrap();
+    }
+    writer.sync().await.unwrap();
+    drop(writer);
+
+    // Recovery replays the committed transactions written asynchronously.
+    let store = TestStore::new();
+    let recovered = store.clone();
+    let path_str = path.0;
+    let next_tid = tokio::task::spawn_blocking(move || {
+        let mut redo_log = RedoLog::new_with_options(path_str, recovered, options()).unwrap();
+        redo_log.start()
+    })
+    .await
+    .unwrap();
+    assert_eq!(next_tid, 22);
+    let map = store.map();
+    assert_eq!(map.len(), 20);
+    assert_eq!(map.get(&1), Some(&"v".repeat(17)));
+}
@@ -0,0 +1,153 @@
+extern crate disk_utils;
+
+mod common;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::append_to_file_with_block_size;
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{check_block_size, Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{LogOptions, LogStore};
+
+fn options(block_size: i64) -> LogOptions {
+    LogOptions {
+        block_size,
+        ..LogOptions::default()
+    }
+}
+
+#[test]
+fn test_invalid_block_sizes() {
+    for &block_size in &[0, -4096, HEADER_SIZE as i64 + 1, 1000, 4097] {
+        assert!(check_block_size(block_size).is_err());
+    }
+    for &block_size in &[32, 4096, 32768, 131072] {
+        assert!(check_block_size(block_size).is_ok());
+    }
+
+    create_test_file("./files/invalid_block_size", |path, mut file| {
+        let record = Record::new(RecordType::Full, vec![1, 2, 3]).unwrap();
+        assert!(append_to_file_with_block_size(&mut file, &record, 1000).is_err());
+        assert!(WalIterator::with_block_size(&mut file, ReadDirection::Forward, 1000).is_err());
+        assert!(Writer::with_block_size(file, 1000).is_err());
+        assert!(RedoLog::new_with_options(path, TestStore::new(), options(1000)).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_small_block_size_records() {
+    create_test_file("./files/small_block_size_records", |_, mut file| {
+        let block_size = 64;
+        let record = Record::new(RecordType::Full, vec![1; 64 / 2 - HEADER_SIZE]).unwrap();
+        for _ in 0..10 {
+            append_to_file_with_block_size(&mut file, &record, block_size).unwrap();
+        }
+        // Two records fill each block exactly.
+        assert_eq!(file.metadata().unwrap().len(), 10 * 32);
+
+        let iter =
+            WalIterator::with_block_size(&mut file, ReadDirection::Forward, block_size).unwrap();
+        assert_eq!(iter.count(), 10);
+        let iter =
+            WalIterator::with_block_size(&mut file, ReadDirection::Backward, block_size).unwrap();
+        assert_eq!(iter.rev().count(), 10);
+    })
+    .unwrap();
+}
+
+fn test_redo_log_block_size(path: &str, block_size: i64) {
+    create_test_file(path, |path, mut file| {
+        let value = "a".repeat(3 * block_size as usize);
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(block_size)).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, value.clone()).unwrap();
+        redo_log.write(tid, 2, "Hello".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 3, "World".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        crash_log(redo_log, path).unwrap();
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(block_size)).unwrap();
+        assert_eq!(redo_log.start(), 3);
+
+        let expected_entries = vec![
+            SingleLogEntry::Transaction(Transaction::Start(1)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 1,
+                key: 1,
+                value,
+            }),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 1,
+                key: 2,
+                value: "Hello".to_string(),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(1)),
+            SingleLogEntry::Transaction(Transaction::Start(2)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 2,
+                key: 3,
+                value: "World".to_string(),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(2)),
+        ];
+        let mut entries =
+            WalIterator::with_block_size(&mut file, ReadDirection::Forward, block_size)
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>();
+        let read_entries: Vec<_> = entries.by_ref().map(Result::unwrap).collect();
+        assert_eq!(read_entries, expected_entries);
+        assert!(entries.get_mut().corruption().is_none());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_4k_blocks() {
+    test_redo_log_block_size("./files/redo_log_4k_blocks", 4096);
+}
+
+#[test]
+fn test_redo_log_64k_blocks() {
+    test_redo_log_block_size("./files/redo_log_64k_blocks", 65536);
+}
+
+fn test_undo_log_block_size(path: &str, block_size: i64) {
+    create_test_file(path, |path, _| {
+        let value = "a".repeat(3 * block_size as usize);
+        let mut store = TestStore::new();
+        store.update(1, value.clone());
+        let mut undo_log = UndoLog::new_with_options(path, store, options(block_size)).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "Hello".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "World".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+
+        crash_log(undo_log, path).unwrap();
+        let mut undo_log =
+            UndoLog::new_with_options(path, TestStore::new(), options(block_size)).unwrap();
+        assert_eq!(undo_log.start(), 3);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_4k_blocks() {
+    test_undo_log_block_size("./files/undo_log_4k_blocks", 4096);
+}
+
+#[test]
+fn test_undo_log_64k_blocks() {
+    test_undo_log_block_size("./files/undo_log_64k_blocks", 65536);
+}
@@ -0,0 +1,85 @@
+extern crate disk_utils;
+
+use std::fs::File;
+
+use disk_utils::testing::{create_test_file, create_two_test_files};
+use disk_utils::wal::chained::{ChainedLog, ChainedWalIterator};
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::MAX_PAYLOAD_SIZE;
+use disk_utils::wal::{append_to_file, split_bytes_into_records, LogData};
+use disk_utils::Serializable;
+
+#[derive(Clone, PartialEq, Debug)]
+struct MyLogData;
+
+impl LogData for MyLogData {
+    type Key = i32;
+    type Value = String;
+}
+
+/// Returns the entries of a committed transaction whose change
+/// is large enough to span several records.
+fn transaction_entries(tid: u64) -> Vec<SingleLogEntry<MyLogData>> {
+    vec![
+        SingleLogEntry::Transaction(Transaction::Start(tid)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid,
+            key: tid as i32,
+            value: "a".repeat(tid as usize * 5000),
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(tid)),
+    ]
+}
+
+fn append_entries(file: &mut File, entries: &[SingleLogEntry<MyLogData>]) {
+    for entry in entries.iter() {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes).unwrap();
+        for record in split_bytes_into_records(&bytes, MAX_PAYLOAD_SIZE).unwrap() {
+            append_to_file(file, &record).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_chained_segments() {
+    create_test_file("./files/chained_segment_1", |_, mut file1| {
+        create_two_test_files(
+            "./files/chained_segment_2",
+            "./files/chained_segment_3",
+            |_, _, mut file2, mut file3| {
+                let mut entries = Vec::new();
+                for (i, file) in [&mut file1, &mut file2, &mut file3].iter_mut().enumerate() {
+                    for tid in (3 * i as u64 + 1)..(3 * i as u64 + 4) {
+                        let transaction = transaction_entries(tid);
+                        append_entries(file, &transaction);
+                        entries.extend(transaction);
+                    }
+                }
+                // The segments end partway through their last blocks.
+                assert!(file1.metadata().unwrap().len() % 32768 != 0);
+
+                let mut log = ChainedLog::new(vec![file1, file2, file3]).unwrap();
+
+                // Redo-style replay reads every entry in order.
+                let iter: ChainedWalIterator =
+                    WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+                let replayed: Vec<SingleLogEntry<MyLogData>> =
+                    iter.entries().map(Result::unwrap).collect();
+                assert_eq!(replayed, entries);
+
+                // Undo-style replay reads every entry in reverse order.
+                let iter = WalIterator::new(&mut log, ReadDirection::Backward).unwrap();
+                let mut replayed: Vec<SingleLogEntry<MyLogData>> =
+                    iter.entries().rev().map(Result::unwrap).collect();
+                replayed.reverse();
+                assert_eq!(replayed, entries);
+
+                assert_eq!(log.into_inner().len(), 3);
+            },
+        )
+        .unwrap();
+    })
+    .unwrap();
+}
@@ -0,0 +1,154 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::compact::compact;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::{LogError, LogOptions, Outcome};
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: 256,
+        ..LogOptions::default()
+    }
+}
+
+/// Rewrites a few keys over and over, deleting one of them, and aborts
+/// some of the transactions.
+fn overwrite(redo_log: &mut RedoLog<TestData, TestStore>) {
+    for i in 0..300 {
+        let tid = redo_log.start();
+        redo_log
+            .write(tid, i % 5, format!("value {} {}", i, "x".repeat(50)))
+            .unwrap();
+        if i % 7 == 0 {
+            redo_log.abort(tid).unwrap();
+        } else {
+            redo_log.commit(tid).unwrap();
+        }
+        if i == 150 {
+            redo_log.checkpoint().unwrap();
+        }
+    }
+    let tid = redo_log.start();
+    redo_log.delete(tid, 3).unwrap();
+    redo_log.commit(tid).unwrap();
+}
+
+#[test]
+fn test_compact_redo_log() {
+    create_test_file("./files/compact_redo_log", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        overwrite(&mut redo_log);
+        let committed = store.map();
+        assert_eq!(committed.len(), 4);
+        let last_tid = redo_log.last_tid();
+        let before = fs::metadata(path).unwrap().len();
+
+        redo_log.compact().unwrap();
+        let after = fs::metadata(path).unwrap().len();
+        assert!(
+            after * 20 < before,
+            "{} bytes compacted to {}",
+            before,
+            after
+        );
+        assert_eq!(redo_log.last_tid(), last_tid);
+        assert_eq!(store.map(), committed);
+
+        // Only the changes flushed at the checkpoint reached the store, so
+        // the rest are recovered from the compacted log.
+        let crashed = store.after_crash();
+        assert!(crashed.map() != committed);
+        crash_log(redo_log, path).unwrap();
+        let mut redo_log = RedoLog::new_with_options(path, crashed.clone(), options()).unwrap();
+        assert_eq!(crashed.map(), committed);
+        assert_eq!(redo_log.last_tid(), last_tid);
+
+        // The log keeps working after it's compacted.
+        let tid = redo_log.start();
+        assert_eq!(tid, last_tid + 1);
+        redo_log.write(tid, 10, "after".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        redo_log.compact().unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 11, "uncommitted".to_string()).unwrap();
+        redo_log.close().unwrap();
+
+        let store = TestStore::new();
+        let redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        let mut expected = committed.clone();
+        expected.insert(10, "after".to_string());
+        assert_eq!(store.map(), expected);
+        assert_eq!(redo_log.last_tid(), last_tid + 2);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_compact_with_active_transactions() {
+    create_test_file("./files/compact_active", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        let first = redo_log.start();
+        redo_log.write(first, 1, "a".to_string()).unwrap();
+        let second = redo_log.start();
+        redo_log.write(second, 2, "b".to_string()).unwrap();
+        redo_log.prepare(second).unwrap();
+
+        match redo_log.compact() {
+            Err(LogError::ActiveTransactions(ref tids)) if *tids == vec![first, second] => {}
+            result => panic!("Expected compaction to fail, got {:?}", result),
+        }
+
+        redo_log.commit(first).unwrap();
+        redo_log.resolve_prepared(second, Outcome::Commit).unwrap();
+        redo_log.compact().unwrap();
+        redo_log.close().unwrap();
+
+        let store = TestStore::new();
+        RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        let expected: HashMap<_, _> = vec![(1, "a".to_string()), (2, "b".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(store.map(), expected);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_compact_path() {
+    create_test_file("./files/compact_path", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        overwrite(&mut redo_log);
+        let committed = store.map();
+
+        // The log's file is locked while the log has it open.
+        match compact::<TestData, _>(path, options()) {
+            Err(LogError::AlreadyLocked) => {}
+            result => panic!("Expected the file to be locked, got {:?}", result),
+        }
+
+        // The log crashes partway through a transaction.
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "uncommitted".to_string()).unwrap();
+        let crashed = store.after_crash();
+        drop(redo_log);
+        let before = fs::metadata(path).unwrap().len();
+
+        compact::<TestData, _>(path, options()).unwrap();
+        assert!(fs::metadata(path).unwrap().len() * 20 < before);
+
+        let redo_log = RedoLog::new_with_options(path, crashed.clone(), options()).unwrap();
+        assert_eq!(crashed.map(), committed);
+        assert_eq!(redo_log.last_tid(), tid);
+    })
+    .unwrap();
+}
@@ -0,0 +1,120 @@
+#![cfg(feature = "compression")]
+
+extern crate disk_utils;
+
+mod common;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::entries::ChangeEntry;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::{
+    append_to_file, read_serializable, read_serializable_backwards, split_bytes_into_records_with,
+    Compression, LogOptions,
+};
+use disk_utils::Serializable;
+
+fn change_entry(tid: u64, key: i32, value: String) -> ChangeEntry<TestData> {
+    ChangeEntry { tid, key, value }
+}
+
+#[test]
+fn test_compressed_records() {
+    let entry = change_entry(1, 2, "{\"key\": \"value\"}".repeat(500));
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+
+    let records = split_bytes_into_records_with(&bytes, 100, Compression::Lz4).unwrap();
+    assert!(records.iter().all(|record| record.compressed));
+    let compressed_len: usize = records.iter().map(|record| record.payload.len()).sum();
+    assert!(compressed_len * 5 < bytes.len());
+}
+
+#[test]
+fn test_incompressible_stored_raw() {
+    let entry = change_entry(1, 2, "abc".to_string());
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+
+    let records = split_bytes_into_records_with(&bytes, 100, Compression::Lz4).unwrap();
+    assert_eq!(records.len(), 1);
+    assert!(!records[0].compressed);
+    assert_eq!(records[0].payload, bytes);
+}
+
+#[test]
+fn test_mixed_compressed_entries() {
+    create_test_file("./files/mixed_compressed_entries", |_, mut file| {
+        let entries = [
+            change_entry(1, 1, "Hello".to_string()),
+            change_entry(1, 2, "Hello world! ".repeat(1000)),
+            change_entry(2, 3, "World".to_string()),
+            change_entry(2, 4, "a".repeat(5000)),
+            change_entry(3, 5, "Uncompressed ".repeat(200)),
+        ];
+
+        for (i, entry) in entries.iter().enumerate() {
+            let compression = if i == 4 {
+                Compression::None
+            } else {
+                Compression::Lz4
+            };
+            let mut bytes = Vec::new();
+            entry.serialize(&mut bytes).unwrap();
+            let records = split_bytes_into_records_with(&bytes, 1024, compression).unwrap();
+            for record in records.iter() {
+                append_to_file(&mut file, record).unwrap();
+            }
+        }
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for entry in entries.iter() {
+            let test_entry = read_serializable::<ChangeEntry<TestData>>(&mut iter).unwrap();
+            assert_eq!(&test_entry, entry);
+        }
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        for entry in entries.iter().rev() {
+            let test_entry =
+                read_serializable_backwards::<ChangeEntry<TestData>>(&mut iter).unwrap();
+            assert_eq!(&test_entry, entry);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_compressed_redo_log_recover() {
+    create_test_file("./files/compressed_redo_log", |path, _| {
+        let store: TestStore = TestStore::new();
+        let options = LogOptions {
+            compression: Compression::Lz4,
+            ..LogOptions::default()
+        };
+
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 20, "{\"a\": 1}".repeat(2000)).unwrap();
+        redo_log.write(tid2, 30, "Hello".to_string()).unwrap();
+        redo_log.write(tid1, 40, "World".to_string()).unwrap();
+        redo_log.commit(tid1).unwrap();
+
+        let mut file = crash_log(redo_log, path).unwrap();
+        store.discard_changes();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        assert_eq!(redo_log.start(), 3);
+        assert_eq!(store.get_flushed(&20), Some("{\"a\": 1}".repeat(2000)));
+        assert_eq!(store.get_flushed(&30), None);
+        assert_eq!(store.get_flushed(&40), Some("World".to_string()));
+
+        // The log mixes compressed and uncompressed records.
+        let records: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .collect();
+        assert!(records.iter().any(|record| record.compressed));
+        assert!(records.iter().any(|record| !record.compressed));
+    })
+    .unwrap();
+}
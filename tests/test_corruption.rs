@@ -0,0 +1,271 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::{SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{BlockError, OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::SerializeError;
+use disk_utils::wal::{LogError, LogOptions, LogStore, RecoveryError};
+
+const BLOCK_SIZE: i64 = 256;
+
+fn options(on_corruption: OnCorruption) -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        on_corruption,
+        ..LogOptions::default()
+    }
+}
+
+/// Flips a byte in the middle of the third block of the log.
+fn corrupt_middle_block(path: &str) {
+    let mut file = OpenOptions::new().write(true).open(path).unwrap();
+    assert!(file.metadata().unwrap().len() > 6 * BLOCK_SIZE as u64);
+    file.seek(SeekFrom::Start(
+        2 * BLOCK_SIZE as u64 + BLOCK_SIZE as u64 / 2,
+    ))
+    .unwrap();
+    file.write_all(&[0xFF]).unwrap();
+}
+
+fn assert_intact(file: &mut File) {
+    let mut iter = WalIterator::with_block_size(file, ReadDirection::Forward, BLOCK_SIZE).unwrap();
+    assert!(iter.by_ref().count() > 0);
+    assert!(iter.corruption().is_none());
+}
+
+/// Reads every entry that is intact in the log, skipping corrupted blocks
+/// and entries that can't be reassembled.
+fn intact_entries(file: &mut File) -> Vec<SingleLogEntry<TestData>> {
+    WalIterator::with_block_size(file, ReadDirection::Forward, BLOCK_SIZE)
+        .unwrap()
+        .on_corruption(OnCorruption::SkipToNextBlock)
+        .entries()
+        .filter_map(Result::ok)
+        .collect()
+}
+
+fn assert_corruption_error<T>(result: Result<T, LogError>) {
+    match result {
+        Err(LogError::RecoveryError(RecoveryError {
+            error: SerializeError::Corrupted(BlockError::Corrupted { .. }),
+            ..
+        })) => {}
+        Err(e) => panic!("Expected corruption error, got {:?}", e),
+        Ok(_) => panic!("Expected corruption error"),
+    }
+}
+
+#[test]
+fn test_redo_log_recovery_with_corruption() {
+    create_test_file("./files/redo_log_corruption", |path, mut file| {
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(OnCorruption::Error))
+                .unwrap();
+        for i in 0..20 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_intact(&mut file);
+        corrupt_middle_block(path);
+
+        // The changes of every transaction with an intact commit should be recovered.
+        let entries = intact_entries(&mut file);
+        let committed: HashSet<_> = entries
+            .iter()
+            .filter_map(|entry| match *entry {
+                SingleLogEntry::Transaction(Transaction::Commit(tid)) => Some(tid),
+                _ => None,
+            })
+            .collect();
+        let mut expected = HashMap::new();
+        for entry in entries {
+            if let SingleLogEntry::ChangeEntry(entry) = entry {
+                if committed.contains(&entry.tid) {
+                    expected.insert(entry.key, entry.value);
+                }
+            }
+        }
+        assert!(expected.len() < 20);
+        assert!(expected.len() >= 15);
+
+        let store = TestStore::new();
+        assert_corruption_error(RedoLog::new_with_options(
+            path,
+            store.clone(),
+            options(OnCorruption::Error),
+        ));
+        assert!(store.map().is_empty());
+
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, store.clone(), options(OnCorruption::SkipToNextBlock))
+                .unwrap();
+        assert_eq!(store.map(), expected);
+        assert_eq!(redo_log.start(), 21);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_recovery_with_corruption() {
+    create_test_file("./files/undo_log_corruption", |path, mut file| {
+        // Existing values make every write log the value it replaces.
+        let mut store = TestStore::new();
+        for i in 0..20 {
+            store.update(i, "o".repeat(30));
+        }
+        let mut undo_log =
+            UndoLog::new_with_options(path, store.clone(), options(OnCorruption::Error)).unwrap();
+        for i in 0..20 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+
+        // Leave a transaction unfinished at the end of the log.
+        store.set_flush_err(true);
+        let tid = undo_log.start();
+        undo_log.write(tid, 0, "changed".to_string()).unwrap();
+        undo_log.write(tid, 100, "inserted".to_string()).unwrap();
+        assert!(undo_log.commit(tid).is_err());
+        store.set_flush_err(false);
+        drop(undo_log);
+        assert_intact(&mut file);
+        corrupt_middle_block(path);
+
+        assert_corruption_error(UndoLog::new_with_options(
+            path,
+            store.clone(),
+            options(OnCorruption::Error),
+        ));
+
+        let mut undo_log =
+            UndoLog::new_with_options(path, store.clone(), options(OnCorruption::SkipToNextBlock))
+                .unwrap();
+        assert_eq!(store.get(&0), Some(format!("value {:026}", 0)));
+        assert_eq!(store.get(&100), None);
+        assert_eq!(undo_log.start(), 22);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_entry_iterator_corruption() {
+    create_test_file("./files/entry_iterator_corruption", |path, mut file| {
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(OnCorruption::Error))
+                .unwrap();
+        for i in 0..20 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_intact(&mut file);
+        corrupt_middle_block(path);
+
+        // The corruption is an error item instead of the end of the entries.
+        for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+            let entries: Vec<_> = WalIterator::with_block_size(&mut file, direction, BLOCK_SIZE)
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>()
+                .collect();
+            let corruption = entries
+                .iter()
+                .position(|entry| {
+                    matches!(
+                        entry,
+                        Err(SerializeError::Corrupted(BlockError::Corrupted { .. }))
+                    )
+                })
+                .unwrap();
+            assert!(corruption > 0);
+            assert!(corruption < entries.len() - 1);
+            assert!(entries[corruption + 1..].iter().any(Result::is_ok));
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_corrupted_commit_record() {
+    create_test_file("./files/redo_log_corrupted_commit", |path, mut file| {
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(OnCorruption::Error))
+                .unwrap();
+        for i in 0..20 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+
+        // Find the commit record of a transaction in the middle of the log.
+        let mut ranges = Vec::new();
+        let mut commits = HashMap::new();
+        let mut entries =
+            WalIterator::with_block_size(&mut file, ReadDirection::Forward, BLOCK_SIZE)
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>();
+        while let Some(entry) = entries.next() {
+            let range = entries.get_mut().last_record_range().unwrap();
+            if let SingleLogEntry::Transaction(Transaction::Commit(tid)) = entry.unwrap() {
+                commits.insert(tid, range.clone());
+            }
+            ranges.push(range);
+        }
+        drop(entries);
+        let commit = commits[&10].clone();
+        let mut log = OpenOptions::new().write(true).open(path).unwrap();
+        log.seek(SeekFrom::Start(commit.end - 1)).unwrap();
+        log.write_all(&[0xFF]).unwrap();
+
+        // Recovery reads backwards, so it only gets through the blocks after the corruption.
+        let block_end = (commit.start / BLOCK_SIZE as u64 + 1) * BLOCK_SIZE as u64;
+        let after = ranges
+            .iter()
+            .filter(|range| range.start >= block_end)
+            .count();
+        let store = TestStore::new();
+        match RedoLog::new_with_options(path, store.clone(), options(OnCorruption::Error)) {
+            Err(LogError::RecoveryError(RecoveryError {
+                error: SerializeError::Corrupted(BlockError::Corrupted { offset, .. }),
+                entries_recovered,
+                offset: Some(err_offset),
+            })) => {
+                assert_eq!(offset, commit.start);
+                assert_eq!(err_offset, commit.start);
+                assert_eq!(entries_recovered, after);
+            }
+            Err(e) => panic!("Expected recovery error, got {:?}", e),
+            Ok(_) => panic!("Expected recovery to fail"),
+        }
+        assert!(store.map().is_empty());
+
+        let store = TestStore::new();
+        RedoLog::new_with_options(path, store.clone(), options(OnCorruption::SkipToNextBlock))
+            .unwrap();
+        // Records before the corruption in its block can still be read, so
+        // only the transactions committed after it in the block are lost.
+        let expected: HashMap<_, _> = commits
+            .iter()
+            .filter(|(_, range)| range.start < commit.start || range.start >= block_end)
+            .map(|(&tid, _)| (tid as i32 - 1, format!("value {}", tid - 1)))
+            .collect();
+        assert!(expected.len() >= 15);
+        assert!(!expected.contains_key(&9));
+        assert_eq!(store.map(), expected);
+    })
+    .unwrap();
+}
@@ -0,0 +1,447 @@
+extern crate disk_utils;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, RwLock};
+
+use disk_utils::testing::{create_crash_test, FaultPolicy, FaultyFile};
+use disk_utils::wal::entries::{SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::BlockFormat;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{LogData, LogOptions, LogStore};
+
+const BLOCK_SIZE: i64 = 256;
+
+#[derive(Clone, PartialEq, Debug)]
+struct MyLogData;
+
+impl LogData for MyLogData {
+    type Key = i32;
+    type Value = String;
+}
+
+/// Store that keeps the changes flushed to it apart, along with the size
+/// of the log when it was last flushed.
+#[derive(Clone)]
+struct MyStore {
+    map: Arc<RwLock<HashMap<i32, String>>>,
+    flushed: Arc<RwLock<HashMap<i32, String>>>,
+    log_path: String,
+    flushed_at: Arc<Mutex<Option<u64>>>,
+}
+
+impl MyStore {
+    fn new(log_path: &str) -> MyStore {
+        MyStore::with_map(log_path, HashMap::new())
+    }
+
+    fn with_map(log_path: &str, map: HashMap<i32, String>) -> MyStore {
+        MyStore {
+            map: Arc::new(RwLock::new(map)),
+            flushed: Arc::new(RwLock::new(HashMap::new())),
+            log_path: log_path.to_string(),
+            flushed_at: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn map(&self) -> HashMap<i32, String> {
+        self.map.read().unwrap().clone()
+    }
+}
+
+impl LogStore<MyLogData> for MyStore {
+    fn get(&self, key: &i32) -> Option<String> {
+        self.map.read().unwrap().get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &i32) {
+        self.map.write().unwrap().remove(key);
+    }
+
+    fn update(&mut self, key: i32, val: String) {
+        self.map.write().unwrap().insert(key, val);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let len = fs::metadata(&self.log_path)?.len();
+        *self.flushed_at.lock().unwrap() = Some(len);
+        Ok(())
+    }
+
+    fn flush_change(&mut self, key: i32, val: String) -> io::Result<()> {
+        self.flushed.write().unwrap().insert(key, val);
+        self.flush()
+    }
+}
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    }
+}
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        ..LogOptions::default()
+    }
+}
+
+fn log_len(path: &str) -> u64 {
+    fs::metadata(path).unwrap().len()
+}
+
+/// Returns policies crashing the log at offsets spread over `start..=end`,
+/// taking turns between the kinds of faults.
+fn crash_points(start: u64, end: u64) -> Vec<FaultPolicy> {
+    (start..=end)
+        .step_by(3)
+        .enumerate()
+        .map(|(i, offset)| match i % 4 {
+            0 => FaultPolicy::FailAfter(offset),
+            1 => FaultPolicy::ShortWriteAfter(offset),
+            2 => FaultPolicy::DropAfter(offset),
+            _ => FaultPolicy::TornAfter {
+                offset,
+                block_size: BLOCK_SIZE as u64,
+            },
+        })
+        .collect()
+}
+
+fn value(key: i32) -> String {
+    format!("{}", key).repeat(40)
+}
+
+#[test]
+fn test_faulty_file_policies() {
+    let entry = "a".repeat(100);
+    let policies = vec![
+        (FaultPolicy::FailAfter(300), 300),
+        (FaultPolicy::ShortWriteAfter(300), 300),
+        (FaultPolicy::DropAfter(300), 300),
+        (
+            FaultPolicy::TornAfter {
+                offset: 300,
+                block_size: BLOCK_SIZE as u64,
+            },
+            512,
+        ),
+    ];
+    for (policy, len) in policies {
+        let file = FaultyFile::new(Cursor::new(Vec::new()), policy).unwrap();
+        let mut writer = Writer::with_format(file, format()).unwrap();
+        let results: Vec<_> = (0..8)
+            .map(|_| writer.append_serializable(&entry).map(|_| ()))
+            .collect();
+        let failed = results.iter().position(Result::is_err);
+        let synced = writer.sync();
+
+        let file = writer.into_inner();
+        assert!(file.faulted(), "{:?}", policy);
+        match policy {
+            FaultPolicy::FailAfter(_) => {
+                assert_eq!(failed, Some(2));
+                assert_eq!(synced.unwrap_err().kind(), io::ErrorKind::Other);
+            }
+            FaultPolicy::ShortWriteAfter(_) => {
+                assert_eq!(failed, Some(2));
+                assert_eq!(
+                    results[2].as_ref().unwrap_err().kind(),
+                    io::ErrorKind::WriteZero
+                );
+                assert!(synced.is_err());
+            }
+            _ => {
+                // The dropped writes look like they succeeded.
+                assert_eq!(failed, None, "{:?}", policy);
+                synced.unwrap();
+            }
+        }
+        assert_eq!(file.into_inner().into_inner().len(), len, "{:?}", policy);
+    }
+}
+
+#[test]
+fn test_faulty_file_passes_writes_before_offset() {
+    let file = FaultyFile::new(Cursor::new(Vec::new()), FaultPolicy::FailAfter(10_000)).unwrap();
+    let mut writer = Writer::with_format(file, format()).unwrap();
+    for _ in 0..4 {
+        writer.append_serializable(&"b".repeat(100)).unwrap();
+    }
+    writer.sync().unwrap();
+    let len = writer.position();
+    let file = writer.into_inner();
+    assert!(!file.faulted());
+    assert_eq!(file.into_inner().into_inner().len() as u64, len);
+}
+
+#[test]
+fn test_redo_log_crash_during_flush() {
+    let path = "./files/crash_flush_redo_log";
+    let crash_path = "./files/crash_flush_redo_log_crashed";
+    create_crash_test(
+        path,
+        crash_path,
+        |path| {
+            let mut redo_log =
+                RedoLog::new_with_options(path, MyStore::new(path), options()).unwrap();
+            let start = log_len(path);
+            let mut commits = Vec::new();
+            for i in 1..4 {
+                let tid = redo_log.start();
+                redo_log.write(tid, i, value(i)).unwrap();
+                redo_log.write(tid, i + 10, value(i + 10)).unwrap();
+                redo_log.commit(tid).unwrap();
+                commits.push((i, log_len(path)));
+            }
+            let end = log_len(path);
+            (commits, crash_points(start, end))
+        },
+        |crash_path, commits, policy| {
+            let len = log_len(crash_path);
+            let expected: HashMap<_, _> = commits
+                .iter()
+                .filter(|&&(_, end)| end <= len)
+                .flat_map(|&(i, _)| vec![(i, value(i)), (i + 10, value(i + 10))])
+                .collect();
+
+            let store = MyStore::new(crash_path);
+            let mut redo_log =
+                RedoLog::new_with_options(crash_path, store.clone(), options()).unwrap();
+            assert_eq!(store.map(), expected, "{:?}", policy);
+
+            // The recovered log keeps working.
+            let tid = redo_log.start();
+            redo_log.write(tid, 100, value(100)).unwrap();
+            redo_log.commit(tid).unwrap();
+            drop(redo_log);
+            let store = MyStore::new(crash_path);
+            let _redo_log =
+                RedoLog::new_with_options(crash_path, store.clone(), options()).unwrap();
+            assert_eq!(store.get(&100), Some(value(100)), "{:?}", policy);
+        },
+    )
+    .unwrap();
+}
+
+/// What a redo log wrote around a checkpoint.
+struct RedoCheckpointRun {
+    commits: Vec<(i32, u64)>,
+    flushed: HashMap<i32, String>,
+    store_flushed_at: u64,
+    checkpoint_end: u64,
+}
+
+#[test]
+fn test_redo_log_crash_during_checkpoint() {
+    let path = "./files/crash_checkpoint_redo_log";
+    let crash_path = "./files/crash_checkpoint_redo_log_crashed";
+    create_crash_test(
+        path,
+        crash_path,
+        |path| {
+            let store = MyStore::new(path);
+            let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+            let start = log_len(path);
+            let mut commits = Vec::new();
+
+            let tid = redo_log.start();
+            redo_log.write(tid, 1, value(1)).unwrap();
+            redo_log.commit(tid).unwrap();
+            commits.push((1, log_len(path)));
+
+            // Still active when the checkpoint begins.
+            let active = redo_log.start();
+            redo_log.write(active, 2, value(2)).unwrap();
+            redo_log.checkpoint().unwrap();
+            let checkpoint_end = log_len(path);
+            redo_log.commit(active).unwrap();
+            commits.push((2, log_len(path)));
+
+            let tid = redo_log.start();
+            redo_log.write(tid, 3, value(3)).unwrap();
+            redo_log.commit(tid).unwrap();
+            commits.push((3, log_len(path)));
+
+            let store_flushed_at = store.flushed_at.lock().unwrap().unwrap();
+            assert!(store_flushed_at < checkpoint_end);
+            let run = RedoCheckpointRun {
+                commits,
+                flushed: store.flushed.read().unwrap().clone(),
+                store_flushed_at,
+                checkpoint_end,
+            };
+            (run, crash_points(start, log_len(path)))
+        },
+        |crash_path, run, policy| {
+            let len = log_len(crash_path);
+            let expected: HashMap<_, _> = run
+                .commits
+                .iter()
+                .filter(|&&(_, end)| end <= len)
+                .map(|&(i, _)| (i, value(i)))
+                .collect();
+
+            // Between the begin checkpoint and the end checkpoint the store
+            // may or may not have been flushed.
+            let mut stores = Vec::new();
+            if len < run.checkpoint_end {
+                stores.push(HashMap::new());
+            }
+            if len >= run.store_flushed_at {
+                stores.push(run.flushed.clone());
+            }
+            for map in stores {
+                let store = MyStore::with_map(crash_path, map);
+                let _redo_log =
+                    RedoLog::new_with_options(crash_path, store.clone(), options()).unwrap();
+                assert_eq!(store.map(), expected, "{:?}", policy);
+            }
+        },
+    )
+    .unwrap();
+}
+
+/// What an undo log wrote: the offset each write's entry ends at and the
+/// offset each transaction's commit ends at.
+#[derive(Default)]
+struct UndoRun {
+    writes: Vec<(u64, u64, i32, String)>,
+    commits: HashMap<u64, u64>,
+}
+
+impl UndoRun {
+    fn write(&mut self, log: &mut UndoLog<MyLogData, MyStore>, path: &str, tid: u64, key: i32) {
+        log.write(tid, key, value(key)).unwrap();
+        self.writes.push((log_len(path), tid, key, value(key)));
+    }
+
+    fn commit(&mut self, log: &mut UndoLog<MyLogData, MyStore>, path: &str, tid: u64) {
+        log.commit(tid).unwrap();
+        self.commits.insert(tid, commit_end(path, tid));
+    }
+
+    /// Checks recovering from a crash that left the first `len` bytes of
+    /// the log. The store has every change whose undo entry was written,
+    /// the most an undo log has to roll back.
+    fn check_recovery(&self, crash_path: &str, policy: FaultPolicy) {
+        let len = log_len(crash_path);
+        let mut crashed = initial_map();
+        let mut expected = initial_map();
+        for &(end, tid, key, ref val) in &self.writes {
+            if end <= len {
+                crashed.insert(key, val.clone());
+            }
+            if self.commits.get(&tid).is_some_and(|&end| end <= len) {
+                expected.insert(key, val.clone());
+            }
+        }
+
+        let store = MyStore::with_map(crash_path, crashed);
+        let _undo_log = UndoLog::new_with_options(crash_path, store.clone(), options()).unwrap();
+        assert_eq!(store.map(), expected, "{:?}", policy);
+    }
+}
+
+/// Returns the offset the commit entry of the transaction ends at. A
+/// commit that finishes a checkpoint appends the end checkpoint after it.
+fn commit_end(path: &str, tid: u64) -> u64 {
+    let mut file = File::open(path).unwrap();
+    let mut entries = WalIterator::with_format(&mut file, ReadDirection::Forward, format())
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>();
+    while let Some(entry) = entries.next() {
+        if entry.unwrap() == SingleLogEntry::Transaction(Transaction::Commit(tid)) {
+            return entries.get_mut().last_record_range().unwrap().end;
+        }
+    }
+    panic!("Transaction {} wasn't committed", tid);
+}
+
+fn initial_map() -> HashMap<i32, String> {
+    vec![(1, "initial 1".to_string()), (2, "initial 2".to_string())]
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn test_undo_log_crash_during_flush() {
+    let path = "./files/crash_flush_undo_log";
+    let crash_path = "./files/crash_flush_undo_log_crashed";
+    create_crash_test(
+        path,
+        crash_path,
+        |path| {
+            let store = MyStore::with_map(path, initial_map());
+            let mut undo_log = UndoLog::new_with_options(path, store, options()).unwrap();
+            let start = log_len(path);
+            let mut run = UndoRun::default();
+
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 1);
+            run.write(&mut undo_log, path, tid, 3);
+            run.commit(&mut undo_log, path, tid);
+
+            // Overwrites the first transaction's change.
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 3);
+            run.write(&mut undo_log, path, tid, 2);
+            run.commit(&mut undo_log, path, tid);
+
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 1);
+            let end = log_len(path);
+            std::mem::forget(undo_log);
+            (run, crash_points(start, end))
+        },
+        |crash_path, run, policy| run.check_recovery(crash_path, policy),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_crash_during_checkpoint() {
+    let path = "./files/crash_checkpoint_undo_log";
+    let crash_path = "./files/crash_checkpoint_undo_log_crashed";
+    create_crash_test(
+        path,
+        crash_path,
+        |path| {
+            let store = MyStore::with_map(path, initial_map());
+            let mut undo_log = UndoLog::new_with_options(path, store, options()).unwrap();
+            let start = log_len(path);
+            let mut run = UndoRun::default();
+
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 1);
+            run.commit(&mut undo_log, path, tid);
+
+            // The checkpoint ends once this transaction commits.
+            let active = undo_log.start();
+            run.write(&mut undo_log, path, active, 2);
+            undo_log.checkpoint().unwrap();
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 3);
+            run.commit(&mut undo_log, path, tid);
+            run.write(&mut undo_log, path, active, 1);
+            run.commit(&mut undo_log, path, active);
+            assert!(undo_log.checkpoint_pending().is_none());
+
+            let tid = undo_log.start();
+            run.write(&mut undo_log, path, tid, 2);
+            let end = log_len(path);
+            std::mem::forget(undo_log);
+            (run, crash_points(start, end))
+        },
+        |crash_path, run, policy| run.check_recovery(crash_path, policy),
+    )
+    .unwrap();
+}
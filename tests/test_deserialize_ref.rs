@@ -0,0 +1,121 @@
+extern crate disk_utils;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::{
+    append_to_file, read_serializable, read_serializable_ref, split_bytes_into_records,
+};
+use disk_utils::{DeserializeRef, Serializable};
+
+/// Allocator that counts the bytes allocated by the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocated_during<F: FnOnce() -> R, R>(fun: F) -> (R, usize) {
+    let before = ALLOCATED.with(|allocated| allocated.get());
+    let result = fun();
+    let after = ALLOCATED.with(|allocated| allocated.get());
+    (result, after - before)
+}
+
+const ONE_MIB: usize = 1024 * 1024;
+
+#[test]
+fn test_deserialize_ref_str() {
+    let value = "Hello world".to_string();
+    let mut bytes = Vec::new();
+    value.serialize(&mut bytes).unwrap();
+    42u64.serialize(&mut bytes).unwrap();
+
+    let (s, rest) = <&str>::deserialize_ref(&bytes).unwrap();
+    assert_eq!(s, "Hello world");
+    let (num, rest) = u64::deserialize_ref(rest).unwrap();
+    assert_eq!(num, 42);
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_deserialize_ref_errors() {
+    let value = "Hello world".to_string();
+    let mut bytes = Vec::new();
+    value.serialize(&mut bytes).unwrap();
+
+    assert!(<&str>::deserialize_ref(&bytes[..bytes.len() - 1]).is_err());
+    assert!(u64::deserialize_ref(&bytes[..3]).is_err());
+
+    // Invalid UTF8 is rejected for strings but not for byte slices.
+    let bytes = [0, 0, 0, 2, 0xFF, 0xFE];
+    assert!(<&str>::deserialize_ref(&bytes).is_err());
+    let (slice, _) = <&[u8]>::deserialize_ref(&bytes).unwrap();
+    assert_eq!(slice, &[0xFF, 0xFE]);
+}
+
+#[test]
+fn test_deserialize_ref_no_allocation() {
+    let value = "a".repeat(ONE_MIB);
+    let mut bytes = Vec::new();
+    value.serialize(&mut bytes).unwrap();
+
+    let (result, allocated) = allocated_during(|| {
+        let (s, _) = <&str>::deserialize_ref(&bytes).unwrap();
+        s.len()
+    });
+    assert_eq!(result, ONE_MIB);
+    assert_eq!(allocated, 0);
+
+    let (result, allocated) =
+        allocated_during(|| String::deserialize(&mut &bytes[..]).unwrap().len());
+    assert_eq!(result, ONE_MIB);
+    assert!(allocated >= ONE_MIB);
+}
+
+#[test]
+fn test_read_serializable_ref() {
+    create_test_file("./files/read_serializable_ref", |_, mut file| {
+        let value = "a".repeat(ONE_MIB);
+        let mut bytes = Vec::new();
+        value.serialize(&mut bytes).unwrap();
+        let records = split_bytes_into_records(&bytes, 1024).unwrap();
+        for record in records.iter() {
+            append_to_file(&mut file, record).unwrap();
+        }
+
+        let (_, owned_allocated) = allocated_during(|| {
+            let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+            let s = read_serializable::<String>(&mut iter).unwrap();
+            assert_eq!(s, value);
+        });
+
+        // Reserve the reassembly buffer up front so only record reading allocates.
+        let mut buf = Vec::with_capacity(bytes.len());
+        let (_, ref_allocated) = allocated_during(|| {
+            let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+            let s = read_serializable_ref::<&str>(&mut iter, &mut buf).unwrap();
+            assert_eq!(s, value);
+        });
+
+        // The borrowed read skips both the reassembly buffer and the string copy.
+        assert!(owned_allocated - ref_allocated >= 2 * ONE_MIB);
+    })
+    .unwrap();
+}
@@ -0,0 +1,143 @@
+extern crate disk_utils;
+
+use std::fs;
+use std::fs::File;
+
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::dump::{dump_entries, dump_records};
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, HEADER_SIZE};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::LogData;
+
+#[derive(Clone, PartialEq, Debug)]
+struct MyLogData;
+
+impl LogData for MyLogData {
+    type Key = i32;
+    type Value = String;
+}
+
+type Entry = SingleLogEntry<MyLogData>;
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    }
+}
+
+fn start(tid: u64) -> Entry {
+    SingleLogEntry::Transaction(Transaction::Start(tid))
+}
+
+fn commit(tid: u64) -> Entry {
+    SingleLogEntry::Transaction(Transaction::Commit(tid))
+}
+
+fn change(tid: u64, key: i32, value: String) -> Entry {
+    SingleLogEntry::ChangeEntry(ChangeEntry { tid, key, value })
+}
+
+fn records(file: &mut File, format: BlockFormat) -> String {
+    let mut iter = WalIterator::with_format(file, ReadDirection::Forward, format).unwrap();
+    let mut out = Vec::new();
+    dump_records(&mut iter, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn entries(file: &mut File, format: BlockFormat) -> String {
+    let mut iter = WalIterator::with_format(file, ReadDirection::Forward, format).unwrap();
+    let mut out = Vec::new();
+    dump_entries::<MyLogData, _, _>(&mut iter, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_dump_fixture() {
+    let mut file = File::open("./tests/fixtures/legacy_redo_log.bin").unwrap();
+    assert_eq!(
+        records(&mut file, BlockFormat::default()),
+        "0 Full size=10 lsn=0 crc=ok
+17 Full size=10 lsn=0 crc=ok
+34 Full size=22 lsn=0 crc=ok
+63 First size=1024 lsn=0 crc=ok
+1094 Middle size=1024 lsn=0 crc=ok
+2125 Last size=969 lsn=0 crc=ok
+3101 Full size=10 lsn=0 crc=ok
+3118 Full size=22 lsn=0 crc=ok
+3147 Full size=10 lsn=0 crc=ok
+3164 Full size=10 lsn=0 crc=ok
+3181 Full size=28 lsn=0 crc=ok
+3216 Full size=14 lsn=0 crc=ok
+3237 Full size=2 lsn=0 crc=ok
+"
+    );
+
+    let expected = format!(
+        "0 tid=1 start
+17 tid=2 start
+34 tid=1 change key=1 value=\"Hello\"
+63 tid=2 change key=2 value=\"{}\"
+3101 tid=1 commit
+3118 tid=2 change key=1 value=\"World\"
+3147 tid=2 commit
+3164 tid=3 start
+3181 tid=3 change key=3 value=\"Uncommitted\"
+3216 checkpoint-begin active=[3]
+3237 checkpoint-end
+",
+        "x".repeat(3000)
+    );
+    assert!(entries(&mut file, BlockFormat::default()) == expected);
+}
+
+#[test]
+fn test_dump_corrupted_record() {
+    create_test_file("./files/dump_corrupted", |path, mut file| {
+        let mut writer = Writer::with_format(file.try_clone().unwrap(), format()).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        let corrupted = writer
+            .append_serializable(&change(1, 1, "a".to_string()))
+            .unwrap();
+        writer.append_serializable(&commit(1)).unwrap();
+        writer.pad_to_block_boundary().unwrap();
+        writer.append_serializable(&start(2)).unwrap();
+        writer
+            .append_serializable(&change(2, 2, "b".repeat(300)))
+            .unwrap();
+        writer.append_serializable(&commit(2)).unwrap();
+        drop(writer);
+
+        let mut bytes = fs::read(path).unwrap();
+        bytes[corrupted as usize + HEADER_SIZE + 2] ^= 0xff;
+        fs::write(path, bytes).unwrap();
+
+        // The rest of the corrupted block is skipped, but the next block
+        // is still dumped.
+        assert_eq!(
+            records(&mut file, format()),
+            "0 Full size=10 lsn=1 crc=ok
+25 error crc=bad: Record 1 of block 0 at offset 25 is corrupted: CRC checksum failed, \
+expected 0x04019ef6 but found 0x0970a721, possibly corrupted record data
+256 Full size=10 lsn=4 crc=ok
+281 First size=216 lsn=5 crc=ok
+512 Last size=101 lsn=6 crc=ok
+628 Full size=10 lsn=7 crc=ok
+"
+        );
+        let expected = format!(
+            "0 tid=1 start
+25 error crc=bad: Record 1 of block 0 at offset 25 is corrupted: CRC checksum failed, \
+expected 0x04019ef6 but found 0x0970a721, possibly corrupted record data
+256 tid=2 start
+281 tid=2 change key=2 value=\"{}\"
+628 tid=2 commit
+",
+            "b".repeat(300)
+        );
+        assert!(entries(&mut file, format()) == expected);
+    })
+    .unwrap();
+}
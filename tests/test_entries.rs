@@ -1,6 +1,9 @@
 extern crate disk_utils;
 
-use disk_utils::wal::entries::{ChangeEntry, Checkpoint, InsertEntry};
+use disk_utils::wal::entries::{
+    ChangeEntry, Checkpoint, DeleteEntry, InsertEntry, MultiChangeEntry, SingleLogEntry,
+    Transaction,
+};
 use disk_utils::wal::LogData;
 use disk_utils::Serializable;
 
@@ -50,3 +53,124 @@ fn test_checkpoint() {
         assert_eq!(checkpoint, test_checkpoint);
     }
 }
+
+#[test]
+fn test_checkpoint_unsigned_length() {
+    let checkpoint = Checkpoint::Begin(vec![20, 30]);
+    let mut bytes = Vec::new();
+    checkpoint.serialize(&mut bytes).unwrap();
+
+    let expected = vec![
+        2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 30,
+    ];
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_checkpoint_legacy_encoding() {
+    // Begin checkpoint encoded with the old signed length.
+    let bytes = vec![
+        0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 30,
+    ];
+    let checkpoint = Checkpoint::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(checkpoint, Checkpoint::Begin(vec![20, 30]));
+
+    // Negative lengths can only come from corrupted data.
+    let bytes = [0, 0xFF, 0xFF, 0xFF, 0xFF];
+    assert!(Checkpoint::deserialize(&mut &bytes[..]).is_err());
+}
+
+#[test]
+fn test_multi_change_entry() {
+    let entry: MultiChangeEntry<MyLogData> = MultiChangeEntry {
+        tid: 123,
+        changes: vec![
+            (20, Some("Hello".to_string())),
+            (30, None),
+            (20, Some("world!".to_string())),
+        ],
+    };
+
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+    let test_entry = MultiChangeEntry::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(entry, test_entry);
+
+    let entry = SingleLogEntry::MultiChangeEntry(entry);
+    assert_eq!(entry.tid(), Some(123));
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+    assert_eq!(bytes[0], 4);
+    let test_entry = SingleLogEntry::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(entry, test_entry);
+
+    // Value tags other than 0 and 1 can only come from corrupted data.
+    let bytes = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 5, 2];
+    assert!(MultiChangeEntry::<MyLogData>::deserialize(&mut &bytes[..]).is_err());
+}
+
+#[test]
+fn test_delete_entry() {
+    let entries: Vec<DeleteEntry<MyLogData>> = vec![
+        DeleteEntry {
+            tid: 123,
+            key: 20,
+            value: Some("Hello world!".to_string()),
+        },
+        DeleteEntry {
+            tid: 123,
+            key: 20,
+            value: None,
+        },
+    ];
+
+    for entry in entries {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes).unwrap();
+        let test_entry = DeleteEntry::deserialize(&mut &bytes[..]).unwrap();
+        assert_eq!(entry, test_entry);
+
+        let entry = SingleLogEntry::DeleteEntry(entry);
+        assert_eq!(entry.tid(), Some(123));
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes).unwrap();
+        assert_eq!(bytes[0], 5);
+        let test_entry = SingleLogEntry::deserialize(&mut &bytes[..]).unwrap();
+        assert_eq!(entry, test_entry);
+    }
+}
+
+#[test]
+fn test_serialized_size() {
+    let entries: Vec<SingleLogEntry<MyLogData>> = vec![
+        SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 20 }),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 1,
+            key: 20,
+            value: "Hello".to_string(),
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(1)),
+        SingleLogEntry::Checkpoint(Checkpoint::Begin(vec![1, 2, 3])),
+        SingleLogEntry::Checkpoint(Checkpoint::End),
+        SingleLogEntry::MultiChangeEntry(MultiChangeEntry {
+            tid: 1,
+            changes: vec![(1, Some("a".to_string())), (2, None)],
+        }),
+        SingleLogEntry::DeleteEntry(DeleteEntry {
+            tid: 1,
+            key: 20,
+            value: Some("world".to_string()),
+        }),
+        SingleLogEntry::DeleteEntry(DeleteEntry {
+            tid: 1,
+            key: 20,
+            value: None,
+        }),
+    ];
+
+    for entry in entries {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes).unwrap();
+        assert_eq!(entry.serialized_size().unwrap(), bytes.len() as u64);
+    }
+}
@@ -0,0 +1,135 @@
+extern crate disk_utils;
+
+use std::error::Error;
+use std::io;
+use std::io::Cursor;
+
+use disk_utils::testing::{create_test_file, TestFileError};
+use disk_utils::wal::iterator::{BlockError, ReadDirection, WalIterator};
+use disk_utils::wal::record::RecordType;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{read_serializable, LogError, RecoveryError, SerializeError};
+
+/// Returns the last error in the chain of sources starting at the error.
+fn root_cause<'a>(err: &'a (dyn Error + 'static)) -> &'a (dyn Error + 'static) {
+    let mut err = err;
+    while let Some(source) = err.source() {
+        err = source;
+    }
+    err
+}
+
+#[test]
+fn test_display() {
+    let err = LogError::IoError(io::Error::new(io::ErrorKind::NotFound, "no log"));
+    assert_eq!(err.to_string(), "Log I/O failed: no log");
+
+    let err = SerializeError::InvalidTransfer {
+        found: RecordType::Middle,
+        previous: None,
+        offset: 163840,
+        record: 3,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Invalid record transition Middle after None at offset 163840"
+    );
+    let err = SerializeError::InvalidTransfer {
+        found: RecordType::Full,
+        previous: Some(RecordType::First),
+        offset: 512,
+        record: 0,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Invalid record transition Full after First at offset 512"
+    );
+    let err = SerializeError::TornEntry { start: 10, end: 20 };
+    assert_eq!(
+        err.to_string(),
+        "Log ends partway through the entry at offsets 10..20"
+    );
+    assert_eq!(
+        SerializeError::OutOfRecords.to_string(),
+        "No records left to read an entry from"
+    );
+
+    assert_eq!(
+        BlockError::OutOfBounds.to_string(),
+        "Position is outside of the log"
+    );
+    let err = LogError::RecoveryError(RecoveryError {
+        error: SerializeError::OutOfRecords,
+        entries_recovered: 12,
+        offset: Some(4096),
+    });
+    assert_eq!(
+        err.to_string(),
+        "Recovery failed after 12 entries at offset 4096: No records left to read an entry from"
+    );
+    let err = LogError::BlockError(BlockError::EmptyBlock);
+    assert_eq!(
+        err.to_string(),
+        "Reading the log failed: Block has no records"
+    );
+}
+
+#[test]
+fn test_source_reaches_io_error() {
+    let err = LogError::SerializeError(SerializeError::BlockError(BlockError::IoError(
+        io::Error::new(io::ErrorKind::UnexpectedEof, "short read"),
+    )));
+    let cause = root_cause(&err);
+    let io_err = cause.downcast_ref::<io::Error>().unwrap();
+    assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
+
+    assert!(SerializeError::OutOfRecords.source().is_none());
+    assert!(BlockError::EmptyBlock.source().is_none());
+}
+
+#[test]
+fn test_corruption_display_and_source() {
+    let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    writer.append_serializable(&"entry".to_string()).unwrap();
+    let mut cursor = writer.into_inner();
+    let len = cursor.get_ref().len();
+    cursor.get_mut()[len - 1] ^= 0xFF;
+
+    let mut iter = WalIterator::new(&mut cursor, ReadDirection::Forward).unwrap();
+    let err = read_serializable::<String>(&mut iter).unwrap_err();
+    let message = err.to_string();
+    assert!(
+        message.starts_with("Record 0 of block 0 at offset 0 is corrupted: "),
+        "{}",
+        message
+    );
+    // The source of the corruption is the io::Error wrapping the record error.
+    let source = err.source().unwrap().source().unwrap();
+    assert!(source.downcast_ref::<io::Error>().is_some());
+}
+
+#[test]
+fn test_boxed_errors() {
+    fn read_entry(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
+        let mut cursor = Cursor::new(bytes);
+        let mut iter = WalIterator::new(&mut cursor, ReadDirection::Forward)?;
+        Ok(read_serializable(&mut iter)?)
+    }
+    let err = read_entry(Vec::new()).unwrap_err();
+    assert_eq!(err.to_string(), "No records left to read an entry from");
+}
+
+#[test]
+fn test_test_file_error() {
+    let err = create_test_file("./files/test_file_error", |_, _| {
+        panic!("expected failure");
+    })
+    .unwrap_err();
+    assert!(matches!(err, TestFileError::ThreadError(_)));
+    assert_eq!(err.to_string(), "Test panicked: expected failure");
+    assert!(err.source().is_none());
+
+    let err = TestFileError::from(io::Error::other("disk full"));
+    assert_eq!(err.to_string(), "Test file I/O failed: disk full");
+    assert!(err.source().unwrap().downcast_ref::<io::Error>().is_some());
+}
@@ -0,0 +1,241 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::{Cursor, Seek, SeekFrom, Write};
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::{SingleLogEntry, Transaction};
+use disk_utils::wal::header::{FileHeader, HeaderError, FILE_HEADER_SIZE, MAGIC};
+use disk_utils::wal::iterator::{BlockError, ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, Record, RecordType, MAX_PAYLOAD_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{append_to_file, write_serializable, LogError, LogOptions};
+
+const BLOCK_SIZE: i64 = 256;
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        ..LogOptions::default()
+    }
+}
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    }
+}
+
+/// Returns the header error from opening a log.
+fn header_error<T>(result: Result<T, LogError>) -> HeaderError {
+    match result {
+        Err(LogError::IoError(err)) => HeaderError::from_io_error(&err).unwrap().clone(),
+        Err(err) => panic!("Expected a header error, got {:?}", err),
+        Ok(_) => panic!("Expected a header error"),
+    }
+}
+
+#[test]
+fn test_parse_header() {
+    let header = FileHeader::new(format());
+    let bytes = header.to_bytes();
+    assert!(bytes.starts_with(&MAGIC));
+    assert_eq!(FileHeader::parse(&bytes), Ok(header));
+    assert_eq!(header.format(), format());
+
+    assert_eq!(FileHeader::parse(&[0; 32]), Err(HeaderError::Missing));
+    assert_eq!(
+        FileHeader::parse(&bytes[..20]),
+        Err(HeaderError::Truncated { len: 20 })
+    );
+
+    let mut corrupted = bytes;
+    corrupted[13] ^= 1;
+    assert!(matches!(
+        FileHeader::parse(&corrupted),
+        Err(HeaderError::BadChecksum { .. })
+    ));
+}
+
+#[test]
+fn test_empty_file_gets_header() {
+    create_test_file("./files/header_empty_file", |path, mut file| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), BLOCK_SIZE as u64);
+        let header = FileHeader::read(&mut file).unwrap().unwrap();
+        assert_eq!(header.format(), format());
+
+        // Records start in the block after the header.
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        assert!(redo_log.last_flushed_offset().unwrap() >= BLOCK_SIZE as u64);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_reopen_validates_header() {
+    create_test_file("./files/header_reopen", |path, mut file| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        for i in 0..20 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+
+        let header = FileHeader::read(&mut file).unwrap().unwrap();
+        let mut store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store, options()).unwrap();
+        assert_eq!(redo_log.start(), 21);
+        drop(redo_log);
+        // Reopening keeps the original header.
+        assert_eq!(FileHeader::read(&mut file).unwrap(), Some(header));
+
+        store = TestStore::new();
+        let mut undo_log = UndoLog::new_with_options(path, store, options()).unwrap();
+        assert!(undo_log.start() > 20);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_data_without_header_fails() {
+    create_test_file("./files/header_missing", |path, mut file| {
+        let record = Record::new(RecordType::Full, vec![1; 10]).unwrap();
+        append_to_file(&mut file, &record).unwrap();
+        let len = file.metadata().unwrap().len();
+
+        let err = header_error(RedoLog::new(path, TestStore::new()));
+        assert_eq!(err, HeaderError::MissingWithData { len });
+        let err = header_error(UndoLog::new(path, TestStore::new()));
+        assert_eq!(err, HeaderError::MissingWithData { len });
+        // The file isn't changed by the failed opens.
+        assert_eq!(file.metadata().unwrap().len(), len);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_format_mismatch_fails() {
+    create_test_file("./files/header_format_mismatch", |path, mut file| {
+        RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+
+        let err = header_error(RedoLog::new(path, TestStore::new()));
+        assert_eq!(
+            err,
+            HeaderError::FormatMismatch {
+                expected: BlockFormat::default(),
+                actual: format(),
+            }
+        );
+        let err = header_error(UndoLog::new(path, TestStore::new()));
+        assert!(matches!(err, HeaderError::FormatMismatch { .. }));
+
+        // The iterator checks the header against its format too.
+        let result = WalIterator::new(&mut file, ReadDirection::Forward);
+        match result {
+            Err(BlockError::IoError(err)) => assert!(matches!(
+                HeaderError::from_io_error(&err),
+                Some(HeaderError::FormatMismatch { .. })
+            )),
+            _ => panic!("Expected the header format to be checked"),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_corrupted_header_fails() {
+    create_test_file("./files/header_corrupted", |path, _| {
+        RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(20)).unwrap();
+        file.write_all(&[0xFF]).unwrap();
+
+        let err = header_error(RedoLog::new_with_options(path, TestStore::new(), options()));
+        assert!(matches!(err, HeaderError::BadChecksum { .. }));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_allow_headerless() {
+    create_test_file("./files/header_allow_headerless", |path, mut file| {
+        for tid in 1..4 {
+            let entry: SingleLogEntry<TestData> =
+                SingleLogEntry::Transaction(Transaction::Commit(tid));
+            write_serializable(&mut file, &entry, MAX_PAYLOAD_SIZE).unwrap();
+        }
+        let len = file.metadata().unwrap().len();
+
+        let options = LogOptions {
+            allow_headerless: true,
+            ..LogOptions::default()
+        };
+        let mut undo_log = UndoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+
+        // The log is extended without adding a header.
+        assert!(file.metadata().unwrap().len() > len);
+        assert_eq!(FileHeader::read(&mut file).unwrap(), None);
+        let count = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .count();
+        assert!(count > 3);
+    })
+    .unwrap();
+
+    // An empty file still gets a header when headerless logs are allowed.
+    create_test_file("./files/header_allow_headerless_empty", |path, mut file| {
+        let options = LogOptions {
+            allow_headerless: true,
+            ..LogOptions::default()
+        };
+        RedoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        assert!(FileHeader::read(&mut file).unwrap().is_some());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_iterator_skips_header() {
+    let mut cursor = Cursor::new(Vec::new());
+    {
+        let mut writer = Writer::with_header(&mut cursor, format()).unwrap();
+        for i in 0..20 {
+            let record = Record::new(RecordType::Full, vec![i; 30]).unwrap();
+            writer.append(&record).unwrap();
+        }
+    }
+    assert!(cursor.get_ref().starts_with(&MAGIC));
+
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let iter = WalIterator::with_format(&mut cursor, direction, format()).unwrap();
+        let records: Vec<_> = match direction {
+            ReadDirection::Forward => iter.collect(),
+            ReadDirection::Backward => iter.rev().collect(),
+        };
+        assert_eq!(records.len(), 20);
+    }
+
+    // Records are only found after the header block.
+    let mut iter = WalIterator::with_format(&mut cursor, ReadDirection::Forward, format()).unwrap();
+    assert!(matches!(
+        iter.seek(FILE_HEADER_SIZE as u64),
+        Err(BlockError::OutOfBounds)
+    ));
+    iter.seek(BLOCK_SIZE as u64).unwrap();
+    assert_eq!(iter.next().unwrap().payload.to_vec(), vec![0; 30]);
+}
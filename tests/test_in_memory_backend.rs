@@ -0,0 +1,252 @@
+extern crate disk_utils;
+
+mod common;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::InMemoryBackend;
+use disk_utils::wal::entries::{ChangeEntry, InsertEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::ReadDirection;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{LogOptions, LogStore};
+
+fn logged_entries<I, E>(entries: I) -> Vec<SingleLogEntry<TestData>>
+where
+    I: Iterator<Item = Result<SingleLogEntry<TestData>, E>>,
+    E: std::fmt::Debug,
+{
+    entries.map(Result::unwrap).collect()
+}
+
+#[test]
+fn test_redo_log_recover() {
+    let backend = InMemoryBackend::new();
+    let store = TestStore::new();
+
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 20, "Hello".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+
+    let tid = redo_log.start();
+    redo_log.write(tid, 20, "World".to_string()).unwrap();
+    redo_log.write(tid, 30, "Hello".to_string()).unwrap();
+
+    let tid = redo_log.start();
+    redo_log.commit(tid).unwrap();
+    drop(redo_log);
+
+    store.discard_changes();
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(redo_log.start(), 4);
+    assert_eq!(store.get_flushed(&20), Some("Hello".to_string()));
+    assert_eq!(store.get_flushed(&30), None);
+
+    let entries = redo_log
+        .reader(ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<TestData>>();
+    assert_eq!(
+        logged_entries(entries),
+        vec![
+            SingleLogEntry::Transaction(Transaction::Start(1)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 1,
+                key: 20,
+                value: "Hello".to_string(),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(1)),
+            SingleLogEntry::Transaction(Transaction::Start(2)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 2,
+                key: 20,
+                value: "World".to_string(),
+            }),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 2,
+                key: 30,
+                value: "Hello".to_string(),
+            }),
+            SingleLogEntry::Transaction(Transaction::Start(3)),
+            SingleLogEntry::Transaction(Transaction::Commit(3)),
+            SingleLogEntry::Transaction(Transaction::Abort(2)),
+        ]
+    );
+}
+
+#[test]
+fn test_redo_log_checkpoint_recover_after_end() {
+    let backend = InMemoryBackend::new();
+    let store = TestStore::new();
+
+    let mut redo_log = RedoLog::options()
+        .open_backend(backend.clone(), store.clone())
+        .unwrap();
+    let tid1 = redo_log.start();
+    let tid2 = redo_log.start();
+    redo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+    redo_log.write(tid2, 20, "World".to_string()).unwrap();
+    redo_log.write(tid2, 30, "Blah".to_string()).unwrap();
+    redo_log.write(tid1, 30, "Foo".to_string()).unwrap();
+    redo_log.commit(tid1).unwrap();
+    redo_log.commit(tid2).unwrap();
+
+    let tid3 = redo_log.start();
+    let tid4 = redo_log.start();
+    redo_log.write(tid3, 20, "A".to_string()).unwrap();
+    redo_log.write(tid4, 30, "C".to_string()).unwrap();
+    redo_log.checkpoint().unwrap();
+    redo_log.commit(tid4).unwrap();
+    redo_log.commit(tid3).unwrap();
+    drop(redo_log);
+
+    store.discard_changes();
+    let mut redo_log = RedoLog::options()
+        .open_backend(backend, store.clone())
+        .unwrap();
+    assert_eq!(redo_log.start(), 5);
+    assert_eq!(store.get_flushed(&20), Some("A".to_string()));
+    assert_eq!(store.get_flushed(&30), Some("C".to_string()));
+}
+
+#[test]
+fn test_redo_log_recover_torn_tail() {
+    let backend = InMemoryBackend::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), TestStore::new(), LogOptions::default()).unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 1, "committed".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    let len = backend.contents().len();
+    let tid = redo_log.start();
+    redo_log.write(tid, 2, "torn".repeat(100)).unwrap();
+    redo_log.commit(tid).unwrap();
+    drop(redo_log);
+
+    // Cut the second commit off partway through its entries.
+    let mut contents = backend.contents();
+    contents.truncate(len + (contents.len() - len) / 2);
+    backend.set_contents(contents);
+
+    let store = TestStore::new();
+    let _redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(store.get(&1), Some("committed".to_string()));
+    assert_eq!(store.get(&2), None);
+}
+
+#[test]
+fn test_redo_log_truncate_before_checkpoint() {
+    let backend = InMemoryBackend::new();
+    let store = TestStore::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    for i in 0..10 {
+        let tid = redo_log.start();
+        redo_log.write(tid, i, format!("value {}", i)).unwrap();
+        redo_log.commit(tid).unwrap();
+    }
+    redo_log.checkpoint().unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 10, "after".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    let len = backend.contents().len();
+
+    assert!(redo_log.truncate_before_checkpoint().unwrap());
+    assert!(backend.contents().len() < len);
+    let tid = redo_log.start();
+    redo_log.write(tid, 11, "truncated".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    drop(redo_log);
+
+    store.discard_changes();
+    let redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(redo_log.last_tid(), 12);
+    assert_eq!(store.get_flushed(&0), Some("value 0".to_string()));
+    assert_eq!(store.get_flushed(&10), Some("after".to_string()));
+    assert_eq!(store.get_flushed(&11), Some("truncated".to_string()));
+}
+
+#[test]
+fn test_undo_log_recover() {
+    let backend = InMemoryBackend::new();
+    let store = TestStore::new();
+
+    let mut undo_log =
+        UndoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    let tid = undo_log.start();
+    undo_log.write(tid, 20, "Hello".to_string()).unwrap();
+    undo_log.commit(tid).unwrap();
+
+    store.set_flush_err(true);
+    let tid = undo_log.start();
+    undo_log.write(tid, 20, "World".to_string()).unwrap();
+    undo_log.write(tid, 30, "Hello".to_string()).unwrap();
+    assert!(undo_log.commit(tid).is_err());
+    store.set_flush_err(false);
+    drop(undo_log);
+
+    let mut undo_log =
+        UndoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(undo_log.start(), 3);
+    assert_eq!(store.get(&20), Some("Hello".to_string()));
+    assert_eq!(store.get(&30), None);
+
+    let entries = undo_log
+        .reader(ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<TestData>>();
+    assert_eq!(
+        logged_entries(entries),
+        vec![
+            SingleLogEntry::Transaction(Transaction::Start(1)),
+            SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 20 }),
+            SingleLogEntry::Transaction(Transaction::Commit(1)),
+            SingleLogEntry::Transaction(Transaction::Start(2)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 2,
+                key: 20,
+                value: "Hello".to_string(),
+            }),
+            SingleLogEntry::InsertEntry(InsertEntry { tid: 2, key: 30 }),
+            SingleLogEntry::Transaction(Transaction::Abort(2)),
+        ]
+    );
+}
+
+#[test]
+fn test_undo_log_checkpoint_recover_before_end() {
+    let backend = InMemoryBackend::new();
+    let store = TestStore::new();
+
+    let mut undo_log = UndoLog::options()
+        .open_backend(backend.clone(), store.clone())
+        .unwrap();
+    let tid1 = undo_log.start();
+    let tid2 = undo_log.start();
+    undo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+    undo_log.write(tid2, 30, "Blah".to_string()).unwrap();
+    undo_log.commit(tid1).unwrap();
+
+    // The checkpoint doesn't end because the second transaction's
+    // commit fails to flush the store.
+    undo_log.checkpoint().unwrap();
+    let tid3 = undo_log.start();
+    undo_log.write(tid3, 20, "World".to_string()).unwrap();
+    undo_log.commit(tid3).unwrap();
+    undo_log.write(tid2, 20, "Foo".to_string()).unwrap();
+    store.set_flush_err(true);
+    assert!(undo_log.commit(tid2).is_err());
+    store.set_flush_err(false);
+    drop(undo_log);
+
+    let mut undo_log = UndoLog::options()
+        .open_backend(backend, store.clone())
+        .unwrap();
+    assert_eq!(undo_log.start(), 4);
+    assert_eq!(store.get(&20), Some("World".to_string()));
+    assert_eq!(store.get(&30), None);
+}
@@ -1,13 +1,28 @@
 extern crate disk_utils;
 
-use std::fs::File;
-use std::io::{Seek, SeekFrom};
+use std::cell::Cell;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 
 use disk_utils::testing::create_test_file;
-use disk_utils::wal::iterator::{ReadDirection, WalIterator};
-use disk_utils::wal::record::{Record, RecordType, BLOCK_SIZE, HEADER_SIZE};
+use disk_utils::wal::entries::ChangeEntry;
+use disk_utils::wal::iterator;
+use disk_utils::wal::iterator::{BlockError, OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::record::{
+    BlockFormat, Record, RecordError, RecordType, BLOCK_SIZE, FORMAT_VERSION, HEADER_SIZE,
+    PADDING_BYTE,
+};
+use disk_utils::wal::{append_to_file, read_serializable, split_bytes_into_records};
+use disk_utils::wal::{LogData, SerializeError};
+use disk_utils::Serializable;
 
-fn test_file(file: &mut File, records: Vec<Record>) {
+fn test_file<R: Read + Seek>(file: &mut R, records: Vec<Record>) {
     // Test going from beginning to end.
     let mut count = 0;
     let iter = WalIterator::new(file, ReadDirection::Forward).unwrap();
@@ -36,7 +51,7 @@ fn test_file(file: &mut File, records: Vec<Record>) {
 #[test]
 fn test_small_file() {
     create_test_file("./files/small_file", |_, mut file| {
-        let record = Record::new(RecordType::Full, vec![0]);
+        let record = Record::new(RecordType::Full, vec![0]).unwrap();
         record.write(&mut file).unwrap();
 
         test_file(&mut file, vec![record]);
@@ -44,8 +59,8 @@ fn test_small_file() {
     .unwrap();
 }
 
-#[test]
-fn test_perfect_file() {
+/// Records that exactly fill two blocks.
+fn perfect_file_records() -> Vec<Record> {
     let record_size = (BLOCK_SIZE / 4) as u16;
     let payload_size = record_size - HEADER_SIZE as u16;
     let mut records = Vec::with_capacity(8);
@@ -56,9 +71,14 @@ fn test_perfect_file() {
             _ => RecordType::Middle,
         };
 
-        records.push(Record::new(record_type, vec![123; payload_size as usize]));
+        records.push(Record::new(record_type, vec![123; payload_size as usize]).unwrap());
     }
+    records
+}
 
+#[test]
+fn test_perfect_file() {
+    let records = perfect_file_records();
     create_test_file("./files/perfect_file", move |_, mut file| {
         for record in records.iter() {
             record.write(&mut file).unwrap();
@@ -71,20 +91,905 @@ fn test_perfect_file() {
 
 #[test]
 fn test_back_and_forth() {
-    let record1 = Record::new(RecordType::First, vec![0; 1]);
-    let record2 = Record::new(RecordType::Middle, vec![1; 1]);
-    let record3 = Record::new(RecordType::Last, vec![2; 1]);
+    let record1 = Record::new(RecordType::First, vec![0; 1]).unwrap();
+    let record2 = Record::new(RecordType::Middle, vec![1; 1]).unwrap();
+    let record3 = Record::new(RecordType::Last, vec![2; 1]).unwrap();
 
     create_test_file("./files/back_and_forth", move |_, mut file| {
         record1.write(&mut file).unwrap();
         record2.write(&mut file).unwrap();
         record3.write(&mut file).unwrap();
 
+        // The front and back of the iterator meet instead of passing each other.
         let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
         assert_eq!(iter.next(), Some(record1.clone()));
+        assert_eq!(iter.next_back(), Some(record3.clone()));
         assert_eq!(iter.next(), Some(record2.clone()));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+
+        // Rewinding the back replays the records read backwards.
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        assert_eq!(iter.next_back(), Some(record3.clone()));
         assert_eq!(iter.next_back(), Some(record2.clone()));
-        assert_eq!(iter.next_back(), Some(record1.clone()));
+        iter.rewind_back();
+        assert_eq!(iter.next(), Some(record2.clone()));
+        assert_eq!(iter.next(), Some(record3.clone()));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    })
+    .unwrap();
+}
+
+/// Checks that every sequence of next and next_back calls returns each
+/// record once, with the front in order and the back in reverse order.
+fn test_interleaved<R: Read + Seek>(file: &mut R, records: &[Record]) {
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        for calls in 0..(1 << records.len()) {
+            let mut iter = WalIterator::new(file, direction).unwrap();
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+            for i in 0..records.len() {
+                if calls & (1 << i) == 0 {
+                    front.push(iter.next().unwrap());
+                } else {
+                    back.push(iter.next_back().unwrap());
+                }
+            }
+            assert_eq!(iter.next(), None);
+            assert_eq!(iter.next_back(), None);
+
+            back.reverse();
+            front.extend(back);
+            assert_eq!(front, records);
+        }
+    }
+}
+
+#[test]
+fn test_interleaved_next_and_next_back() {
+    create_test_file("./files/interleaved_next_and_next_back", |_, mut file| {
+        let records = write_padded_records(&mut file);
+        test_interleaved(&mut file, &records);
+    })
+    .unwrap();
+}
+
+/// Records that fill the first block and spill into the second.
+fn padded_records() -> Vec<Record> {
+    let payload_size = (BLOCK_SIZE / 3) as usize - HEADER_SIZE;
+    (0..5)
+        .map(|i| Record::new(RecordType::Full, vec![i; payload_size]).unwrap())
+        .collect()
+}
+
+/// Appends enough records to fill the first block and spill into the second.
+fn write_padded_records(file: &mut File) -> Vec<Record> {
+    let records = padded_records();
+    for record in records.iter() {
+        append_to_file(file, record).unwrap();
+    }
+    records
+}
+
+/// Serializes the records into an in-memory log, padding the rest
+/// of a block when the next record doesn't fit in it.
+fn in_memory_log(records: &[Record]) -> Cursor<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for record in records.iter() {
+        let remaining = BLOCK_SIZE as usize - bytes.len() % BLOCK_SIZE as usize;
+        if HEADER_SIZE + record.payload.len() > remaining {
+            bytes.resize(bytes.len() + remaining, 0);
+        }
+        record.write(&mut bytes).unwrap();
+    }
+    Cursor::new(bytes)
+}
+
+#[test]
+fn test_in_memory_log() {
+    let records = vec![Record::new(RecordType::Full, vec![0]).unwrap()];
+    test_file(&mut in_memory_log(&records), records);
+
+    let records = perfect_file_records();
+    test_file(&mut in_memory_log(&records), records);
+
+    let records = padded_records();
+    let mut log = in_memory_log(&records);
+    assert_eq!(log.get_ref().clone(), padded_records_bytes().1);
+    test_file(&mut log, records.clone());
+    test_interleaved(&mut log, &records);
+}
+
+#[test]
+fn test_peek() {
+    create_test_file("./files/peek", |_, mut file| {
+        let records = write_padded_records(&mut file);
+
+        // Peeking across the block boundary doesn't consume any records.
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for record in records[..3].iter() {
+            assert_eq!(iter.peek().unwrap(), Some(record));
+            assert_eq!(iter.peek().unwrap(), Some(record));
+            assert_eq!(iter.next().as_ref(), Some(record));
+        }
+        assert_eq!(iter.peek().unwrap(), Some(&records[3]));
+        assert_eq!(iter.peek_back().unwrap(), Some(&records[4]));
+        assert_eq!(iter.next_back().as_ref(), Some(&records[4]));
+        assert_eq!(iter.peek_back().unwrap(), Some(&records[3]));
+        assert_eq!(iter.peek().unwrap(), Some(&records[3]));
+        assert_eq!(iter.next().as_ref(), Some(&records[3]));
+        assert_eq!(iter.peek().unwrap(), None);
+        assert_eq!(iter.peek_back().unwrap(), None);
+        assert_eq!(iter.next(), None);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        for record in records[2..].iter().rev() {
+            assert_eq!(iter.peek_back().unwrap(), Some(record));
+            assert_eq!(iter.next_back().as_ref(), Some(record));
+        }
+        assert_eq!(iter.peek().unwrap(), Some(&records[0]));
+        assert_eq!(iter.peek_back().unwrap(), Some(&records[1]));
+        iter.rewind_back();
+        assert_eq!(iter.peek().unwrap(), Some(&records[2]));
+        assert_eq!(iter.peek_back().unwrap(), Some(&records[4]));
+        assert_eq!(iter.next().as_ref(), Some(&records[2]));
+        assert_eq!(iter.peek().unwrap(), Some(&records[3]));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_peek_corruption() {
+    create_test_file("./files/peek_corruption", |path, mut file| {
+        let records = write_padded_records(&mut file);
+
+        // Corrupt the payload of the second record in the second block.
+        let corrupt_offset = BLOCK_SIZE as u64 + (BLOCK_SIZE / 3) as u64;
+        let mut writer = OpenOptions::new().write(true).open(path).unwrap();
+        writer
+            .seek(SeekFrom::Start(corrupt_offset + HEADER_SIZE as u64 + 10))
+            .unwrap();
+        writer.write_all(&[0xFF]).unwrap();
+
+        // Peeking reports the corruption without handling it.
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        assert!(iter.peek_back().is_err());
+        assert!(iter.peek_back().is_err());
+        assert!(iter.try_next_back().is_err());
+        assert_eq!(iter.peek_back().unwrap(), Some(&records[3]));
+        assert_eq!(iter.next_back().as_ref(), Some(&records[3]));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_padding_is_not_corruption() {
+    create_test_file("./files/padding_not_corruption", |_, mut file| {
+        let records = write_padded_records(&mut file);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, records);
+        assert!(iter.corruption().is_none());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_corruption_after_padded_block() {
+    create_test_file("./files/corruption_after_padding", |path, mut file| {
+        let records = write_padded_records(&mut file);
+
+        // Corrupt the payload of the second record in the second block.
+        let record_size = (BLOCK_SIZE / 3) as u64;
+        let corrupt_offset = BLOCK_SIZE as u64 + record_size;
+        let mut writer = OpenOptions::new().write(true).open(path).unwrap();
+        writer
+            .seek(SeekFrom::Start(corrupt_offset + HEADER_SIZE as u64 + 10))
+            .unwrap();
+        writer.write_all(&[0xFF]).unwrap();
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, records[..4].to_vec());
+        match iter.corruption() {
+            Some(BlockError::Corrupted { offset, .. }) => assert_eq!(*offset, corrupt_offset),
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_skip_corrupted_block() {
+    create_test_file("./files/skip_corrupted_block", |path, mut file| {
+        let payload_size = (BLOCK_SIZE / 3) as usize - HEADER_SIZE;
+        let records: Vec<_> = (0..9)
+            .map(|i| Record::new(RecordType::Full, vec![i; payload_size]).unwrap())
+            .collect();
+        for record in records.iter() {
+            append_to_file(&mut file, record).unwrap();
+        }
+
+        // Corrupt the payload of the second record in the middle block.
+        let corrupt_offset = BLOCK_SIZE as u64 + (BLOCK_SIZE / 3) as u64;
+        let mut writer = OpenOptions::new().write(true).open(path).unwrap();
+        writer
+            .seek(SeekFrom::Start(corrupt_offset + HEADER_SIZE as u64 + 10))
+            .unwrap();
+        writer.write_all(&[0xFF]).unwrap();
+
+        // Strict mode returns the records up to the corruption, then the error.
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for record in records[..4].iter() {
+            assert_eq!(iter.try_next().unwrap().as_ref(), Some(record));
+        }
+        match iter.try_next() {
+            Err(BlockError::Corrupted { offset, .. }) => assert_eq!(offset, corrupt_offset),
+            r => panic!("Expected corruption error, got {:?}", r),
+        }
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        let read_records: Vec<_> = iter.by_ref().rev().collect();
+        assert_eq!(
+            read_records,
+            vec![records[8].clone(), records[7].clone(), records[6].clone()]
+        );
+        assert!(matches!(iter.error(), Some(BlockError::Corrupted { .. })));
+
+        // Tolerant mode skips the rest of the corrupted block.
+        let mut intact = records[..4].to_vec();
+        intact.extend_from_slice(&records[6..]);
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .on_corruption(OnCorruption::SkipToNextBlock);
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, intact);
+        assert!(iter.error().is_none());
+        assert_eq!(iter.corrupted_blocks(), 1);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward)
+            .unwrap()
+            .on_corruption(OnCorruption::SkipToNextBlock);
+        let mut read_records: Vec<_> = iter.by_ref().rev().collect();
+        read_records.reverse();
+        assert_eq!(read_records, intact);
+        assert_eq!(iter.corrupted_blocks(), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_invalid_padding() {
+    create_test_file("./files/invalid_padding", |path, mut file| {
+        let records = write_padded_records(&mut file);
+
+        // Non-zero bytes inside the padding of the first block.
+        let mut writer = OpenOptions::new().write(true).open(path).unwrap();
+        writer.seek(SeekFrom::Start(BLOCK_SIZE as u64 - 2)).unwrap();
+        writer.write_all(&[1]).unwrap();
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, records[..3].to_vec());
+        match iter.error() {
+            Some(BlockError::Corrupted { offset, .. }) => {
+                assert_eq!(*offset, 3 * (BLOCK_SIZE / 3) as u64)
+            }
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .on_corruption(OnCorruption::SkipToNextBlock);
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, records);
+        assert!(iter.error().is_none());
+        match iter.corruption() {
+            Some(BlockError::Corrupted { offset, .. }) => {
+                assert_eq!(*offset, 3 * (BLOCK_SIZE / 3) as u64)
+            }
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
     })
     .unwrap();
 }
+
+#[test]
+fn test_block_of_garbage() {
+    create_test_file("./files/block_of_garbage", |_, mut file| {
+        // A valid type byte followed by 0xFF bytes claims a 0xFFFF size
+        // which can never fit in what is left of the block.
+        let mut garbage = vec![0xFF; BLOCK_SIZE as usize];
+        file.write_all(&garbage).unwrap();
+        garbage[0] = FORMAT_VERSION << 4 | RecordType::Full as u8;
+        file.write_all(&garbage).unwrap();
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        assert_eq!(iter.by_ref().count(), 0);
+        match iter.corruption() {
+            Some(BlockError::Corrupted { offset, .. }) => assert_eq!(*offset, 0),
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        assert_eq!(iter.by_ref().rev().count(), 0);
+        match iter.corruption() {
+            Some(BlockError::Corrupted { offset, error, .. }) => {
+                assert_eq!(*offset, BLOCK_SIZE as u64);
+                let record_err = error.get_ref().unwrap().downcast_ref::<RecordError>();
+                assert_eq!(
+                    record_err,
+                    Some(&RecordError::SizeTooLarge {
+                        size: u16::MAX,
+                        remaining: BLOCK_SIZE as usize - HEADER_SIZE
+                    })
+                );
+            }
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_truncated_tail_record() {
+    create_test_file("./files/truncated_tail_record", |_, mut file| {
+        let records: Vec<_> = (0..5)
+            .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+            .collect();
+        for record in records.iter() {
+            append_to_file(&mut file, record).unwrap();
+        }
+
+        // Cut the last record off partway through its payload.
+        let record_len = (HEADER_SIZE + 1000) as u64;
+        file.set_len(4 * record_len + HEADER_SIZE as u64 + 10)
+            .unwrap();
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let read_records: Vec<_> = iter.by_ref().collect();
+        assert_eq!(read_records, records[..4].to_vec());
+        let corruption = iter.corruption().unwrap();
+        match *corruption {
+            BlockError::Corrupted { offset, .. } => assert_eq!(offset, 4 * record_len),
+            ref e => panic!("Expected corruption error, got {:?}", e),
+        }
+        let record_err = corruption.record_error().unwrap();
+        assert!(record_err.is_truncation());
+        assert_eq!(
+            *record_err,
+            RecordError::IncompletePayload {
+                size: 1000,
+                consumed: HEADER_SIZE + 10
+            }
+        );
+
+        // Cut the last record off partway through its header.
+        file.set_len(4 * record_len + 3).unwrap();
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        assert_eq!(iter.by_ref().count(), 4);
+        assert_eq!(
+            iter.corruption().and_then(|e| e.record_error()),
+            Some(&RecordError::IncompleteHeader { consumed: 3 })
+        );
+    })
+    .unwrap();
+}
+
+/// Reader over an in-memory log that fails reads after a number of bytes.
+struct FailingReader {
+    inner: Cursor<Vec<u8>>,
+    remaining: Rc<Cell<usize>>,
+}
+
+impl FailingReader {
+    fn new(bytes: Vec<u8>, remaining: usize) -> FailingReader {
+        FailingReader {
+            inner: Cursor::new(bytes),
+            remaining: Rc::new(Cell::new(remaining)),
+        }
+    }
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            return Err(io::Error::other("Read failed"));
+        }
+        let len = buf.len().min(remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining.set(remaining - n);
+        Ok(n)
+    }
+}
+
+impl Seek for FailingReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn padded_records_bytes() -> (Vec<Record>, Vec<u8>) {
+    create_test_file("./files/padded_records_bytes", |_, mut file| {
+        let records = write_padded_records(&mut file);
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut bytes).unwrap();
+        (records, bytes)
+    })
+    .unwrap()
+}
+
+/// Reader returning at most a few bytes from each read, and interrupting
+/// every other read, like a pipe or a slow device.
+struct ShortReader {
+    inner: Cursor<Vec<u8>>,
+    interrupt: bool,
+}
+
+impl Read for ShortReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupt = !self.interrupt;
+        if self.interrupt {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted"));
+        }
+        let len = buf.len().min(7);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+impl Seek for ShortReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn test_short_reads() {
+    let (records, bytes) = padded_records_bytes();
+    let mut reader = ShortReader {
+        inner: Cursor::new(bytes),
+        interrupt: false,
+    };
+    test_file(&mut reader, records);
+}
+
+#[test]
+fn test_read_error_is_returned() {
+    let (records, bytes) = padded_records_bytes();
+    let mut reader = FailingReader::new(bytes, BLOCK_SIZE as usize);
+
+    let mut iter = WalIterator::new(&mut reader, ReadDirection::Forward).unwrap();
+    for record in records[..3].iter() {
+        assert_eq!(iter.try_next().unwrap().as_ref(), Some(record));
+    }
+    match iter.try_next() {
+        Err(BlockError::IoError(err)) => assert_eq!(err.to_string(), "Read failed"),
+        result => panic!("Expected read error, got {:?}", result),
+    }
+
+    // The `Iterator` implementation stops at the error instead of panicking.
+    assert_eq!(iter.next(), None);
+    assert!(matches!(iter.error(), Some(BlockError::IoError(_))));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn test_read_error_can_be_retried() {
+    let (records, bytes) = padded_records_bytes();
+    let mut reader = FailingReader::new(bytes, BLOCK_SIZE as usize);
+    let remaining = reader.remaining.clone();
+
+    let mut iter = WalIterator::new(&mut reader, ReadDirection::Forward).unwrap();
+    for _ in 0..3 {
+        iter.try_next().unwrap();
+    }
+    assert!(iter.try_next().is_err());
+    assert!(iter.try_next().is_err());
+
+    // Once reads succeed again, the iterator continues where it failed.
+    remaining.set(usize::MAX);
+    for record in records[3..].iter() {
+        assert_eq!(iter.try_next().unwrap().as_ref(), Some(record));
+    }
+    assert_eq!(iter.try_next().unwrap(), None);
+}
+
+#[test]
+fn test_read_error_in_read_serializable() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct MyLogData;
+
+    impl LogData for MyLogData {
+        type Key = i32;
+        type Value = String;
+    }
+
+    let entry = ChangeEntry::<MyLogData> {
+        tid: 1,
+        key: 1,
+        value: "a".repeat(3 * BLOCK_SIZE as usize),
+    };
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+    let records = split_bytes_into_records(&bytes, 1000).unwrap();
+
+    let mut log = Vec::new();
+    for record in records.iter() {
+        let remaining = BLOCK_SIZE as usize - log.len() % BLOCK_SIZE as usize;
+        if record.payload.len() + HEADER_SIZE > remaining {
+            log.resize(log.len() + remaining, 0);
+        }
+        record.write(&mut log).unwrap();
+    }
+
+    let mut reader = FailingReader::new(log.clone(), 2 * BLOCK_SIZE as usize);
+    let mut iter = WalIterator::new(&mut reader, ReadDirection::Forward).unwrap();
+    match read_serializable::<u64>(&mut iter) {
+        Err(SerializeError::BlockError(BlockError::IoError(_))) => {}
+        result => panic!("Expected read error, got {:?}", result),
+    }
+
+    let mut reader = Cursor::new(log);
+    let mut iter = WalIterator::new(&mut reader, ReadDirection::Forward).unwrap();
+    assert_eq!(
+        read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+        entry
+    );
+}
+
+#[test]
+fn test_resume() {
+    let records: Vec<_> = (0..100)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let mut log = in_memory_log(&records);
+    assert!(log.get_ref().len() > 3 * BLOCK_SIZE as usize);
+
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let mut expected = records.clone();
+        if direction == ReadDirection::Backward {
+            expected.reverse();
+        }
+        for split in (0..=records.len()).step_by(7) {
+            let mut iter = WalIterator::new(&mut log, direction).unwrap();
+            let mut read: Vec<_> = match direction {
+                ReadDirection::Forward => iter.by_ref().take(split).collect(),
+                ReadDirection::Backward => iter.by_ref().rev().take(split).collect(),
+            };
+
+            // Save the cursor and drop the iterator.
+            let mut bytes = Vec::new();
+            iter.cursor().serialize(&mut bytes).unwrap();
+            let cursor = iterator::Cursor::deserialize(&mut &bytes[..]).unwrap();
+            assert_eq!(cursor, iter.cursor());
+            assert_eq!(cursor.direction(), direction);
+
+            let iter = WalIterator::resume(&mut log, cursor).unwrap();
+            match direction {
+                ReadDirection::Forward => read.extend(iter),
+                ReadDirection::Backward => read.extend(iter.rev()),
+            }
+            assert_eq!(read, expected);
+        }
+    }
+}
+
+#[test]
+fn test_resume_shrunk_log() {
+    let records: Vec<_> = (0..100)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let mut log = in_memory_log(&records);
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.by_ref().take(50).count(), 50);
+    let cursor = iter.cursor();
+
+    // Cut the log off before the cursor.
+    let len = log.get_ref().len();
+    log.get_mut().truncate(len / 4);
+    match WalIterator::resume(&mut log, cursor) {
+        Err(BlockError::OutOfBounds) => {}
+        Err(e) => panic!("Expected out of bounds error, got {:?}", e),
+        Ok(_) => panic!("Expected out of bounds error"),
+    }
+}
+
+/// Returns the block index, record index, and record error of a corruption.
+fn corruption_location(err: &BlockError) -> (u64, u64, usize, RecordError) {
+    match *err {
+        BlockError::Corrupted {
+            offset,
+            block,
+            record,
+            ..
+        } => (offset, block, record, err.record_error().unwrap().clone()),
+        ref e => panic!("Expected corruption error, got {:?}", e),
+    }
+}
+
+#[test]
+fn test_corruption_diagnostics() {
+    // Each block holds 32 records followed by padding.
+    let record_len = (HEADER_SIZE + 1000) as u64;
+    let records: Vec<_> = (0..100)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let log = in_memory_log(&records);
+    let record_offset = |block: u64, record: u64| block * BLOCK_SIZE as u64 + record * record_len;
+
+    // A flipped payload byte in the sixth record of the second block.
+    let mut corrupted = log.clone();
+    let offset = record_offset(1, 5);
+    corrupted.get_mut()[offset as usize + HEADER_SIZE + 10] ^= 1;
+    let mut iter = WalIterator::new(&mut corrupted, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.by_ref().count(), 32 + 5);
+    let (err_offset, block, record, record_err) = corruption_location(iter.error().unwrap());
+    assert_eq!((err_offset, block, record), (offset, 1, 5));
+    match record_err {
+        RecordError::BadChecksum {
+            consumed,
+            expected,
+            actual,
+        } => {
+            assert_eq!(consumed as u64, record_len);
+            assert_eq!(expected, records[37].crc);
+            assert_ne!(expected, actual);
+        }
+        e => panic!("Expected checksum error, got {:?}", e),
+    }
+
+    // An invalid type byte in the fourth record of the third block is found
+    // after the last block when reading backwards.
+    let mut corrupted = log.clone();
+    let offset = record_offset(2, 3);
+    corrupted.get_mut()[offset as usize] = FORMAT_VERSION << 4 | 7;
+    let mut iter = WalIterator::new(&mut corrupted, ReadDirection::Backward).unwrap();
+    assert_eq!(iter.by_ref().rev().count(), 100 - 96);
+    let (err_offset, block, record, record_err) = corruption_location(iter.error().unwrap());
+    assert_eq!((err_offset, block, record), (offset, 2, 3));
+    assert_eq!(record_err, RecordError::BadType(FORMAT_VERSION << 4 | 7));
+
+    // A payload cut off partway through the last record.
+    let mut truncated = log.clone();
+    let offset = record_offset(3, 3);
+    truncated
+        .get_mut()
+        .truncate(offset as usize + HEADER_SIZE + 10);
+    let mut iter = WalIterator::new(&mut truncated, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.by_ref().count(), 99);
+    let (err_offset, block, record, record_err) = corruption_location(iter.error().unwrap());
+    assert_eq!((err_offset, block, record), (offset, 3, 3));
+    assert_eq!(
+        record_err,
+        RecordError::IncompletePayload {
+            size: 1000,
+            consumed: HEADER_SIZE + 10
+        }
+    );
+
+    // read_serializable returns the same diagnostics.
+    let mut iter = WalIterator::new(&mut truncated, ReadDirection::Forward).unwrap();
+    for _ in 0..99 {
+        iter.try_next().unwrap().unwrap();
+    }
+    match read_serializable::<u64>(&mut iter) {
+        Err(SerializeError::Corrupted(err)) => {
+            assert_eq!(corruption_location(&err).0, offset);
+        }
+        Err(e) => panic!("Expected corruption error, got {:?}", e),
+        Ok(_) => panic!("Expected corruption error"),
+    }
+}
+
+/// Checks the size hint of an iterator over the log after each record,
+/// which is exact once the records have been counted.
+fn assert_size_hints<R: Read + Seek>(log: &mut R, direction: ReadDirection, count: bool) {
+    let total = WalIterator::new(log, ReadDirection::Forward)
+        .unwrap()
+        .count();
+    let mut iter = WalIterator::new(log, direction)
+        .unwrap()
+        .on_corruption(OnCorruption::SkipToNextBlock);
+    if count {
+        assert_eq!(iter.count_records().unwrap(), total);
+    }
+    for read in 0..=total {
+        let remaining = total - read;
+        let (lower, upper) = iter.size_hint();
+        assert!(lower <= remaining);
+        assert!(upper.unwrap() >= remaining);
+        if count {
+            assert_eq!(upper, Some(remaining));
+        }
+        if read < total {
+            match direction {
+                ReadDirection::Forward => iter.next().unwrap(),
+                ReadDirection::Backward => iter.next_back().unwrap(),
+            };
+        }
+    }
+    assert!(iter.next().is_none());
+    assert_eq!(iter.size_hint(), (0, Some(0)));
+}
+
+#[test]
+fn test_count_records() {
+    // A log with padding at the end of its blocks.
+    let records: Vec<_> = (0..100)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let mut log = in_memory_log(&records);
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.by_ref().take(40).count(), 40);
+
+    // Counting doesn't move the iterator.
+    assert_eq!(iter.count_records().unwrap(), 100);
+    assert_eq!(iter.collect::<Vec<_>>(), records[40..].to_vec());
+
+    // A log whose last block is partial.
+    let mut log = in_memory_log(&padded_records());
+    assert!(!log.get_ref().len().is_multiple_of(BLOCK_SIZE as usize));
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Backward).unwrap();
+    assert_eq!(iter.count_records().unwrap(), 5);
+
+    // A log with a record cut off at its end.
+    let mut truncated = in_memory_log(&records);
+    let len = truncated.get_ref().len();
+    truncated.get_mut().truncate(len - 10);
+    let mut iter = WalIterator::new(&mut truncated, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.count_records().unwrap(), 99);
+
+    let mut empty = Cursor::new(Vec::new());
+    let mut iter = WalIterator::new(&mut empty, ReadDirection::Forward).unwrap();
+    assert_eq!(iter.count_records().unwrap(), 0);
+
+    for log in [&mut log, &mut in_memory_log(&records), &mut truncated] {
+        for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+            assert_size_hints(log, direction, false);
+            assert_size_hints(log, direction, true);
+        }
+    }
+}
+
+#[test]
+fn test_size_hint_stops_at_corruption() {
+    // Three full blocks of 32 records, with a corrupted record in the first.
+    let records: Vec<_> = (0..96)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let mut log = in_memory_log(&records);
+    log.get_mut()[5 * (HEADER_SIZE + 1000) + HEADER_SIZE + 10] ^= 1;
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+
+    // The records loaded at the back aren't returned, since reading from
+    // the front stops at the corrupted record.
+    iter.next_back().unwrap();
+    let (lower, _) = iter.size_hint();
+    assert_eq!(iter.by_ref().count(), 5);
+    assert!(lower <= 5);
+    assert!(iter.error().is_some());
+}
+
+#[test]
+fn test_stats() {
+    // Three full blocks of 32 records and 288 bytes of padding,
+    // followed by a block with the last 4 records.
+    let record_len = (HEADER_SIZE + 1000) as u64;
+    let records: Vec<_> = (0..100)
+        .map(|i| Record::new(RecordType::Full, vec![i; 1000]).unwrap())
+        .collect();
+    let mut log = in_memory_log(&records);
+    let len = log.get_ref().len() as u64;
+    let padding = BLOCK_SIZE as u64 - 32 * record_len;
+    assert_eq!(len, 3 * BLOCK_SIZE as u64 + 4 * record_len);
+
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let mut iter = WalIterator::new(&mut log, direction).unwrap();
+        assert_eq!(iter.stats().records_read, 0);
+        match direction {
+            ReadDirection::Forward => assert_eq!(iter.by_ref().count(), 100),
+            ReadDirection::Backward => assert_eq!(iter.by_ref().rev().count(), 100),
+        }
+        let expected = iterator::Stats {
+            records_read: 100,
+            bytes_read: len,
+            blocks_loaded: 4,
+            corrupt_records_skipped: 0,
+            padding_bytes: 3 * padding,
+        };
+        assert_eq!(iter.stats(), expected);
+    }
+
+    // Peeking doesn't count as reading a record, and counting the records
+    // reads each block once.
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+    iter.peek().unwrap().unwrap();
+    assert_eq!(iter.stats().records_read, 0);
+    iter.count_records().unwrap();
+    assert_eq!(iter.stats().bytes_read, BLOCK_SIZE as u64 + len);
+
+    // The records after the corruption in the second block are skipped.
+    let mut corrupted = log.clone();
+    let offset = BLOCK_SIZE as usize + 5 * record_len as usize;
+    corrupted.get_mut()[offset + HEADER_SIZE + 10] ^= 1;
+    let mut iter = WalIterator::new(&mut corrupted, ReadDirection::Forward)
+        .unwrap()
+        .on_corruption(OnCorruption::SkipToNextBlock);
+    assert_eq!(iter.by_ref().count(), 100 - 27);
+    let stats = iter.stats();
+    assert_eq!(stats.records_read, 100 - 27);
+    assert_eq!(stats.corrupt_records_skipped, 1);
+    assert_eq!(stats.blocks_loaded, 4);
+    assert_eq!(stats.padding_bytes, 2 * padding);
+
+    // The callback is called once for each block with the stats so far,
+    // and the iterator can still be sent to another thread.
+    let loaded = Arc::new(AtomicUsize::new(0));
+    let counter = loaded.clone();
+    let iter =
+        WalIterator::with_readahead(&mut log, ReadDirection::Forward, BlockFormat::default(), 2)
+            .unwrap()
+            .on_block_loaded(move |pos, stats| {
+                let blocks = counter.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(pos, blocks as u64 * BLOCK_SIZE as u64);
+                assert_eq!(stats.blocks_loaded, blocks + 1);
+            });
+    let count = thread::scope(|scope| scope.spawn(move || iter.count()).join().unwrap());
+    assert_eq!(count, 100);
+    assert_eq!(loaded.load(Ordering::SeqCst), 4);
+}
+
+/// Checks that reading the log backwards starts at its last record.
+fn assert_reads_backward<R: Read + Seek>(log: &mut R, records: &[Record]) {
+    let mut iter = WalIterator::new(log, ReadDirection::Backward).unwrap();
+    assert_eq!(iter.next_back().as_ref(), records.last());
+    let mut read: Vec<_> = iter.rev().collect();
+    read.reverse();
+    assert_eq!(read, records[..records.len() - 1].to_vec());
+}
+
+#[test]
+fn test_backward_start_position() {
+    // A single record smaller than a block.
+    let records = vec![Record::new(RecordType::Full, vec![1]).unwrap()];
+    assert_reads_backward(&mut in_memory_log(&records), &records);
+
+    // A log ending exactly at a block boundary.
+    let mut records = perfect_file_records();
+    let mut log = in_memory_log(&records);
+    assert_eq!(log.get_ref().len(), 2 * BLOCK_SIZE as usize);
+    assert_reads_backward(&mut log, &records);
+
+    // A log ending just past a block boundary.
+    records.push(Record::new(RecordType::Full, vec![2]).unwrap());
+    let mut log = in_memory_log(&records);
+    assert_eq!(
+        log.get_ref().len(),
+        2 * BLOCK_SIZE as usize + HEADER_SIZE + 1
+    );
+    assert_reads_backward(&mut log, &records);
+
+    // A log whose last block ends with padding, which may end with the file
+    // partway through the block or be followed by blocks of padding.
+    let records = padded_records();
+    let log = in_memory_log(&records);
+    let len = log.get_ref().len();
+    let padded_lens = (2..5).map(|blocks| blocks * BLOCK_SIZE as usize);
+    for padded_len in iter::once(len + 1).chain(padded_lens) {
+        let mut padded = log.clone();
+        padded.get_mut().resize(padded_len, PADDING_BYTE);
+        assert_reads_backward(&mut padded, &records);
+    }
+
+    // A log whose first block ends with padding and whose last block is empty.
+    let records = &records[..3];
+    let mut padded = in_memory_log(records);
+    padded
+        .get_mut()
+        .resize(2 * BLOCK_SIZE as usize, PADDING_BYTE);
+    assert_reads_backward(&mut padded, records);
+}
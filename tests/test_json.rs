@@ -0,0 +1,155 @@
+#![cfg(feature = "serde")]
+
+extern crate disk_utils;
+extern crate serde_json;
+
+use std::io::Cursor;
+
+use serde_json::{json, Value};
+
+use disk_utils::wal::dump::to_json_lines;
+use disk_utils::wal::entries::{
+    ChangeEntry, Checkpoint, DeleteEntry, InsertEntry, MultiChangeEntry, SingleLogEntry,
+    Transaction,
+};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::LogData;
+
+#[derive(Clone, PartialEq, Debug)]
+struct MyLogData;
+
+impl LogData for MyLogData {
+    type Key = i32;
+    type Value = String;
+}
+
+type Entry = SingleLogEntry<MyLogData>;
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    }
+}
+
+fn entries() -> Vec<Entry> {
+    vec![
+        SingleLogEntry::Transaction(Transaction::Start(1)),
+        SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 1 }),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 1,
+            key: 2,
+            value: "a".repeat(600),
+        }),
+        SingleLogEntry::DeleteEntry(DeleteEntry {
+            tid: 1,
+            key: 3,
+            value: Some("b".to_string()),
+        }),
+        SingleLogEntry::MultiChangeEntry(MultiChangeEntry {
+            tid: 1,
+            changes: vec![(4, Some("c".to_string())), (5, None)],
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(1)),
+        SingleLogEntry::Checkpoint(Checkpoint::Begin(vec![2, 3])),
+        SingleLogEntry::Checkpoint(Checkpoint::End),
+    ]
+}
+
+fn json_lines(bytes: Vec<u8>) -> Vec<Value> {
+    let mut log = Cursor::new(bytes);
+    let mut iter = WalIterator::with_format(&mut log, ReadDirection::Forward, format()).unwrap();
+    let mut out = Vec::new();
+    to_json_lines::<MyLogData, _, _>(&mut iter, &mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_json_lines() {
+    let entries = entries();
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    let mut ranges = Vec::new();
+    for entry in &entries {
+        let offset = writer.append_serializable(entry).unwrap();
+        ranges.push(offset..writer.position());
+    }
+
+    let lines = json_lines(writer.into_inner().into_inner());
+    assert_eq!(lines.len(), entries.len());
+    for ((line, entry), range) in lines.iter().zip(&entries).zip(&ranges) {
+        assert_eq!(line["offset"], json!(range.start));
+        assert_eq!(line["end"], json!(range.end));
+        assert_eq!(line["entry"], serde_json::to_value(entry).unwrap());
+    }
+
+    // The large change spans several records, and the multi-change is
+    // split at the end of its block.
+    let records: Vec<_> = lines
+        .iter()
+        .map(|line| line["records"].as_u64().unwrap())
+        .collect();
+    assert_eq!(records, vec![1, 1, 3, 1, 2, 1, 1, 1]);
+
+    assert_eq!(
+        lines[2]["entry"],
+        json!({"ChangeEntry": {"tid": 1, "key": 2, "value": "a".repeat(600)}})
+    );
+    assert_eq!(
+        lines[4]["entry"],
+        json!({"MultiChangeEntry": {"tid": 1, "changes": [[4, "c"], [5, null]]}})
+    );
+    assert_eq!(lines[6]["entry"], json!({"Checkpoint": {"Begin": [2, 3]}}));
+}
+
+#[test]
+fn test_json_lines_corrupted_record() {
+    let entries = entries();
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    writer.append_serializable(&entries[0]).unwrap();
+    let corrupted = writer.append_serializable(&entries[1]).unwrap();
+    writer.pad_to_block_boundary().unwrap();
+    let next = writer.append_serializable(&entries[5]).unwrap();
+    let mut bytes = writer.into_inner().into_inner();
+    bytes[corrupted as usize + HEADER_SIZE + 2] ^= 0xff;
+
+    // The corrupted entry is an error object, and the export goes on with
+    // the next block.
+    let lines = json_lines(bytes);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0]["entry"],
+        serde_json::to_value(&entries[0]).unwrap()
+    );
+    assert_eq!(lines[1]["offset"], json!(corrupted));
+    assert!(lines[1]["error"]
+        .as_str()
+        .unwrap()
+        .contains("CRC checksum failed"));
+    assert_eq!(lines[1].get("entry"), None);
+    assert_eq!(lines[2]["offset"], json!(next));
+    assert_eq!(
+        lines[2]["entry"],
+        serde_json::to_value(&entries[5]).unwrap()
+    );
+}
+
+#[test]
+fn test_record_metadata_json() {
+    let record = Record::new(RecordType::First, vec![1, 2, 3]).unwrap();
+    assert_eq!(
+        serde_json::to_value(&record).unwrap(),
+        json!({
+            "crc": record.crc,
+            "size": 3,
+            "record_type": "First",
+            "compressed": false,
+            "lsn": 0,
+        })
+    );
+}
@@ -0,0 +1,105 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::fs::File;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::{ChangeEntry, Checkpoint, SingleLogEntry, Transaction};
+use disk_utils::wal::header::HeaderError;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::{LogError, LogOptions};
+
+/// Redo log written before records were versioned, with payload-only
+/// checksums and a begin checkpoint using the signed length encoding.
+const LEGACY_REDO_LOG: &str = "./tests/fixtures/legacy_redo_log.bin";
+
+fn legacy_entries() -> Vec<SingleLogEntry<TestData>> {
+    vec![
+        SingleLogEntry::Transaction(Transaction::Start(1)),
+        SingleLogEntry::Transaction(Transaction::Start(2)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 1,
+            key: 1,
+            value: "Hello".to_string(),
+        }),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 2,
+            key: 2,
+            value: "x".repeat(3000),
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(1)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 2,
+            key: 1,
+            value: "World".to_string(),
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(2)),
+        SingleLogEntry::Transaction(Transaction::Start(3)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 3,
+            key: 3,
+            value: "Uncommitted".to_string(),
+        }),
+        SingleLogEntry::Checkpoint(Checkpoint::Begin(vec![3])),
+        SingleLogEntry::Checkpoint(Checkpoint::End),
+    ]
+}
+
+fn read_entries(file: &mut File) -> Vec<SingleLogEntry<TestData>> {
+    WalIterator::new(file, ReadDirection::Forward)
+        .unwrap()
+        .entries()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn test_read_legacy_log() {
+    let mut file = File::open(LEGACY_REDO_LOG).unwrap();
+    assert_eq!(read_entries(&mut file), legacy_entries());
+}
+
+#[test]
+fn test_recover_legacy_log() {
+    create_test_file("./files/legacy_redo_log", |path, mut file| {
+        fs::copy(LEGACY_REDO_LOG, path).unwrap();
+
+        // Logs written before file headers are only opened when allowed.
+        match RedoLog::new(path, TestStore::new()) {
+            Err(LogError::IoError(err)) => assert!(matches!(
+                HeaderError::from_io_error(&err),
+                Some(HeaderError::MissingWithData { .. })
+            )),
+            _ => panic!("Opening a log without a header should fail"),
+        }
+
+        let options = LogOptions {
+            allow_headerless: true,
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        let tid = redo_log.start();
+        assert_eq!(tid, 4);
+        redo_log.write(tid, 4, "New".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        // New records are appended in the current format after the legacy ones.
+        let mut expected_entries = legacy_entries();
+        expected_entries.extend(vec![
+            SingleLogEntry::Transaction(Transaction::Abort(3)),
+            SingleLogEntry::Transaction(Transaction::Start(4)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 4,
+                key: 4,
+                value: "New".to_string(),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(4)),
+        ]);
+        assert_eq!(read_entries(&mut file), expected_entries);
+    })
+    .unwrap();
+}
@@ -0,0 +1,143 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::io;
+use std::io::Write;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{create_test_dir, create_test_file};
+use disk_utils::wal::backend::FileBackend;
+use disk_utils::wal::entries::{SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::manager::Wal;
+use disk_utils::wal::read_serializable;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{LogError, LogOptions, LogStore};
+
+#[test]
+fn test_redo_log_locks_file() {
+    create_test_file("./files/lock_redo_log", |path, _| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        match RedoLog::<TestData, _>::new(path, TestStore::new()) {
+            Err(LogError::AlreadyLocked) => {}
+            _ => panic!("Expected the second log to find the file locked"),
+        }
+        match UndoLog::<TestData, _>::new(path, TestStore::new()) {
+            Err(LogError::AlreadyLocked) => {}
+            _ => panic!("Expected the second log to find the file locked"),
+        }
+
+        redo_log.close().unwrap();
+        let store = TestStore::new();
+        let redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&1), Some("a".to_string()));
+        drop(redo_log);
+        RedoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_locks_file() {
+    create_test_file("./files/lock_undo_log", |path, _| {
+        let undo_log = UndoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+        match UndoLog::<TestData, _>::new(path, TestStore::new()) {
+            Err(LogError::AlreadyLocked) => {}
+            _ => panic!("Expected the second log to find the file locked"),
+        }
+
+        undo_log.close().unwrap();
+        let undo_log = UndoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+        drop(undo_log);
+        UndoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_lock_survives_truncation() {
+    create_test_file("./files/lock_truncate", |path, _| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        for key in 0..3 {
+            let tid = redo_log.start();
+            redo_log.write(tid, key, "a".repeat(100)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        redo_log.checkpoint().unwrap();
+        assert!(redo_log.truncate_before_checkpoint().unwrap());
+
+        // The rewritten file replacing the log's file is locked too.
+        match RedoLog::<TestData, _>::new(path, TestStore::new()) {
+            Err(LogError::AlreadyLocked) => {}
+            _ => panic!("Expected the second log to find the file locked"),
+        }
+        redo_log.close().unwrap();
+        RedoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_read_only_skips_lock() {
+    create_test_file("./files/lock_read_only", |path, _| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        let err = FileBackend::open(path, true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        let mut backend = FileBackend::open_read_only(path).unwrap();
+        assert!(backend.write_all(b"oops").is_err());
+        let mut entries = Vec::new();
+        {
+            let mut iter = WalIterator::new(&mut backend, ReadDirection::Forward).unwrap();
+            while let Ok(entry) = read_serializable::<SingleLogEntry<TestData>>(&mut iter) {
+                entries.push(entry);
+            }
+        }
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[2],
+            SingleLogEntry::Transaction(Transaction::Commit(tid))
+        );
+
+        // Inspecting the log doesn't keep another log from opening it.
+        redo_log.close().unwrap();
+        RedoLog::<TestData, _>::new(path, TestStore::new()).unwrap();
+        drop(backend);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_wal_locks_dir() {
+    create_test_dir("./files/lock_wal", |path| {
+        let options = LogOptions::default();
+        let mut redo_log = RedoLog::with_wal(
+            Wal::open(path, &options).unwrap(),
+            TestStore::new(),
+            options.clone(),
+        )
+        .unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        let err = Wal::open(path, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        redo_log.close().unwrap();
+        let store = TestStore::new();
+        RedoLog::with_wal(Wal::open(path, &options).unwrap(), store.clone(), options).unwrap();
+        assert_eq!(store.get(&1), Some("a".to_string()));
+    })
+    .unwrap();
+}
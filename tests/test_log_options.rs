@@ -0,0 +1,296 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::iterator::{OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::record::BlockFormat;
+use disk_utils::wal::redo_log::{RedoLog, RedoLogOptions};
+use disk_utils::wal::undo_log::{UndoLog, UndoLogOptions};
+use disk_utils::wal::{CheckpointPolicy, LogError, LogStore, SyncPolicy, WriteAheadPolicy};
+
+/// Returns the largest payload of the records in the log.
+fn largest_record(path: &str, format: BlockFormat) -> usize {
+    let mut file = File::open(path).unwrap();
+    let mut iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format).unwrap();
+    let mut largest = 0;
+    while let Some(record) = iter.try_next().unwrap() {
+        largest = largest.max(record.payload.len());
+    }
+    largest
+}
+
+fn assert_invalid(result: disk_utils::wal::Result<RedoLog<TestData, TestStore>>, message: &str) {
+    match result {
+        Err(LogError::IoError(err)) => {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), message);
+        }
+        Err(err) => panic!("Expected invalid options, got {:?}", err),
+        Ok(_) => panic!("Expected invalid options"),
+    }
+}
+
+#[test]
+fn test_redo_log_small_records() {
+    create_test_file("./files/options_small_records", |path, _| {
+        let options = || -> RedoLogOptions<TestData, TestStore> {
+            RedoLog::options()
+                .sync(SyncPolicy::OnCommit)
+                .max_record_size(64)
+                .checkpoint_every_commits(3)
+        };
+        let mut store = TestStore::new();
+        let mut redo_log = options().open(path, store.clone()).unwrap();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log
+                .write(tid, i, format!("{}", i).repeat(100))
+                .unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_eq!(largest_record(path, BlockFormat::default()), 64);
+
+        // The last commit came after the last checkpoint, so its change
+        // may not have reached the store and is redone by recovery.
+        store.remove(&9);
+        options().open(path, store.clone()).unwrap();
+        let expected: HashMap<_, _> = (0..10).map(|i| (i, format!("{}", i).repeat(100))).collect();
+        assert_eq!(store.map(), expected);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_small_blocks() {
+    create_test_file("./files/options_small_blocks", |path, _| {
+        let options = || -> UndoLogOptions<TestData, TestStore> {
+            UndoLog::options()
+                .block_size(256)
+                .block_checksums(true)
+                .sync(SyncPolicy::OnCheckpoint)
+                .checkpoint_every_bytes(512)
+        };
+        let store = TestStore::new();
+        let mut undo_log = options().open(path, store.clone()).unwrap();
+        for i in 0..10 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, "old".repeat(200)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+        // The uncommitted write is flushed to the log by the commit after it.
+        let uncommitted = undo_log.start();
+        undo_log.write(uncommitted, 0, "new".to_string()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 20, "committed".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+
+        let format = BlockFormat {
+            block_size: 256,
+            checksums: true,
+        };
+        assert_eq!(largest_record(path, format), format.max_payload_size());
+        assert_eq!(store.get(&0), Some("new".to_string()));
+
+        // Recovery rolls back the uncommitted write.
+        options().open(path, store.clone()).unwrap();
+        let mut expected: HashMap<_, _> = (0..10).map(|i| (i, "old".repeat(200))).collect();
+        expected.insert(20, "committed".to_string());
+        assert_eq!(store.map(), expected);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_deferred_updates() {
+    create_test_file("./files/options_deferred_updates", |path, _| {
+        let recovered = Arc::new(AtomicBool::new(false));
+        let reported = recovered.clone();
+        let options = RedoLog::options()
+            .defer_store_updates(true)
+            .seal_checkpoints(true)
+            .readahead_blocks(4)
+            .on_corruption(OnCorruption::SkipToNextBlock)
+            .recovery_progress(move |fraction| {
+                if fraction >= 1.0 {
+                    reported.store(true, Ordering::SeqCst);
+                }
+            });
+        let built = options.log_options().clone();
+        assert!(built.defer_store_updates);
+        assert_eq!(built.readahead_blocks, 4);
+
+        let store = TestStore::new();
+        let mut redo_log = options.open(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 1, "one".to_string()).unwrap();
+        redo_log.write(tid2, 2, "two".to_string()).unwrap();
+        assert_eq!(store.get(&1), None);
+        redo_log.commit(tid1).unwrap();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.map().len(), 1);
+        drop(redo_log);
+
+        let store = TestStore::new();
+        let redo_log = RedoLog::new_with_options(path, store.clone(), built).unwrap();
+        assert!(recovered.load(Ordering::SeqCst));
+        assert_eq!(store.get(&1), Some("one".to_string()));
+        assert_eq!(store.get(&2), None);
+        assert!(redo_log.active_transactions().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_invalid_options() {
+    create_test_file("./files/options_invalid", |path, _| {
+        assert_invalid(
+            RedoLog::options()
+                .max_record_size(0)
+                .open(path, TestStore::new()),
+            "Max record size 0 must be between 1 and 32753 for blocks of 32768 bytes",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .block_size(256)
+                .max_record_size(1024)
+                .open(path, TestStore::new()),
+            "Max record size 1024 must be between 1 and 241 for blocks of 256 bytes",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .block_size(1000)
+                .open(path, TestStore::new()),
+            "Block size 1000 must be a power of two larger than 20",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .checkpoint_every_commits(0)
+                .open(path, TestStore::new()),
+            "Checkpoint interval must be at least 1 commit",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .sync(SyncPolicy::EveryNBytes(0))
+                .open(path, TestStore::new()),
+            "Sync interval must be at least 1 byte",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .readahead_blocks(0)
+                .open(path, TestStore::new()),
+            "Readahead must be at least 1 block",
+        );
+        assert_invalid(
+            RedoLog::options()
+                .max_pending_bytes(0)
+                .open(path, TestStore::new()),
+            "Pending byte limit must be at least 1 byte",
+        );
+        // Nothing is written to the log for invalid options.
+        assert_eq!(std::fs::metadata(path).unwrap().len(), 0);
+
+        let err = UndoLog::<TestData, TestStore>::options()
+            .checkpoint(CheckpointPolicy::EveryNBytes(0))
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Checkpoint interval must be at least 1 byte"
+        );
+        let options = UndoLog::<TestData, TestStore>::options()
+            .max_record_size(100)
+            .build()
+            .unwrap();
+        assert_eq!(options.max_record_size, Some(100));
+    })
+    .unwrap();
+}
+
+const MAX_PENDING_BYTES: u64 = 1024;
+
+#[test]
+fn test_redo_log_max_pending_bytes() {
+    create_test_file("./files/options_max_pending_bytes_redo", |path, _| {
+        let options = || -> RedoLogOptions<TestData, TestStore> {
+            RedoLog::options().max_pending_bytes(MAX_PENDING_BYTES)
+        };
+        let mut redo_log = options().open(path, TestStore::new()).unwrap();
+        let committed = redo_log.start();
+        let uncommitted = redo_log.start();
+        let mut peak = 0;
+        for i in 0..200 {
+            redo_log
+                .write(committed, i, format!("committed {}", i))
+                .unwrap();
+            redo_log
+                .write(uncommitted, i + 200, "uncommitted".to_string())
+                .unwrap();
+            peak = peak.max(redo_log.pending_bytes());
+        }
+        assert!(peak < MAX_PENDING_BYTES);
+        // The entries were spilled to the log many times over.
+        assert!(std::fs::metadata(path).unwrap().len() > 10 * MAX_PENDING_BYTES);
+        redo_log.commit(committed).unwrap();
+        drop(redo_log);
+
+        let store = TestStore::new();
+        let redo_log = options().open(path, store.clone()).unwrap();
+        let expected: HashMap<_, _> = (0..200).map(|i| (i, format!("committed {}", i))).collect();
+        assert_eq!(store.map(), expected);
+        assert!(redo_log.active_transactions().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_max_pending_bytes() {
+    create_test_file("./files/options_max_pending_bytes_undo", |path, _| {
+        let options = || -> UndoLogOptions<TestData, TestStore> {
+            UndoLog::options()
+                .write_ahead(WriteAheadPolicy::OnCommit)
+                .max_pending_bytes(MAX_PENDING_BYTES)
+        };
+        let store = TestStore::new();
+        let mut undo_log = options().open(path, store.clone()).unwrap();
+        let committed = undo_log.start();
+        for i in 0..200 {
+            undo_log
+                .write(committed, i, format!("committed {}", i))
+                .unwrap();
+        }
+        undo_log.commit(committed).unwrap();
+
+        let uncommitted = undo_log.start();
+        let mut peak = 0;
+        for i in 0..200 {
+            undo_log
+                .write(uncommitted, i, "uncommitted".to_string())
+                .unwrap();
+            peak = peak.max(undo_log.pending_bytes());
+        }
+        assert!(peak < MAX_PENDING_BYTES);
+        assert!(std::fs::metadata(path).unwrap().len() > 10 * MAX_PENDING_BYTES);
+        // The writes that weren't spilled yet are flushed by a checkpoint,
+        // since they would be lost from the log otherwise.
+        assert!(undo_log.pending_entries() > 0);
+        undo_log.checkpoint().unwrap();
+        drop(undo_log);
+
+        // Recovery rolls back the uncommitted writes from the spilled entries.
+        options().open(path, store.clone()).unwrap();
+        let expected: HashMap<_, _> = (0..200).map(|i| (i, format!("committed {}", i))).collect();
+        assert_eq!(store.map(), expected);
+    })
+    .unwrap();
+}
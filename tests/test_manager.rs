@@ -0,0 +1,346 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{create_test_dir, create_test_file};
+use disk_utils::wal::entries::SingleLogEntry;
+use disk_utils::wal::iterator::ReadDirection;
+use disk_utils::wal::manager::{Wal, MANIFEST_NAME};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{LogError, LogOptions, LogStore};
+
+/// Options with small segments, so a few transactions span several of them.
+fn segment_options() -> LogOptions {
+    LogOptions {
+        block_size: 256,
+        segment_size: 1024,
+        // Entries are flushed as they're written, so the entries of
+        // unfinished transactions reach the log.
+        max_pending_bytes: Some(1),
+        ..LogOptions::default()
+    }
+}
+
+/// Returns the segment files in the directory in order.
+fn segment_files<P: AsRef<Path> + ?Sized>(dir: &P) -> Vec<PathBuf> {
+    let mut segments: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "seg"))
+        .collect();
+    segments.sort();
+    segments
+}
+
+fn open_wal<P: AsRef<Path> + ?Sized>(dir: &P) -> Wal {
+    Wal::open(dir.as_ref(), &segment_options()).unwrap()
+}
+
+/// Commits a transaction writing a value to each of the keys.
+fn commit_redo<B: disk_utils::wal::backend::LogBackend>(
+    log: &mut RedoLog<TestData, TestStore, B>,
+    keys: std::ops::Range<i32>,
+) {
+    let tid = log.start();
+    for key in keys {
+        log.write(tid, key, format!("value {}", key).repeat(10))
+            .unwrap();
+    }
+    log.commit(tid).unwrap();
+}
+
+#[test]
+fn test_open_empty_dir() {
+    create_test_dir("./files/manager_empty_dir", |path| {
+        let wal = open_wal(path);
+        assert!(Path::new(path).join(MANIFEST_NAME).exists());
+        assert_eq!(wal.segments().len(), 1);
+        assert!(wal.segments()[0].exists());
+        assert_eq!(wal.checkpoint(), None);
+
+        let redo_log = RedoLog::with_wal(wal, TestStore::new(), segment_options()).unwrap();
+        assert_eq!(redo_log.last_tid(), 0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_reopen_after_rotation() {
+    create_test_dir("./files/manager_reopen_after_rotation", |path| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::with_wal(open_wal(path), store.clone(), segment_options()).unwrap();
+        for i in 0..10 {
+            commit_redo(&mut redo_log, i * 2..i * 2 + 2);
+        }
+        redo_log.checkpoint().unwrap();
+        commit_redo(&mut redo_log, 20..22);
+        let tid = redo_log.start();
+        redo_log.write(tid, 0, "uncommitted".to_string()).unwrap();
+        drop(redo_log);
+
+        let wal = open_wal(path);
+        assert!(wal.segments().len() > 2);
+        assert!(wal.checkpoint().is_some());
+
+        store.discard_changes();
+        let redo_log = RedoLog::with_wal(wal, store.clone(), segment_options()).unwrap();
+        assert_eq!(redo_log.last_tid(), 12);
+        for key in 0..22 {
+            assert_eq!(store.get(&key), Some(format!("value {}", key).repeat(10)));
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_manifest_corruption() {
+    create_test_dir("./files/manager_manifest_corruption", |path| {
+        let dir = Path::new(path);
+        let mut redo_log =
+            RedoLog::with_wal(open_wal(path), TestStore::new(), segment_options()).unwrap();
+        commit_redo(&mut redo_log, 0..2);
+        redo_log.checkpoint().unwrap();
+        drop(redo_log);
+
+        // A manifest that was never renamed into place is ignored.
+        fs::write(dir.join("MANIFEST.tmp"), b"garbage").unwrap();
+        assert!(open_wal(path).checkpoint().is_some());
+        assert!(!dir.join("MANIFEST.tmp").exists());
+
+        let manifest = fs::read(dir.join(MANIFEST_NAME)).unwrap();
+        let mut corrupted = manifest.clone();
+        corrupted[14] ^= 0xff;
+        fs::write(dir.join(MANIFEST_NAME), &corrupted).unwrap();
+        let err = Wal::open(path, &segment_options()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(dir.join(MANIFEST_NAME), &manifest[..10]).unwrap();
+        let err = Wal::open(path, &segment_options()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Segments without a manifest aren't mistaken for a new log.
+        fs::remove_file(dir.join(MANIFEST_NAME)).unwrap();
+        let err = Wal::open(path, &segment_options()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::write(dir.join(MANIFEST_NAME), &manifest).unwrap();
+        let options = LogOptions {
+            block_size: 512,
+            ..segment_options()
+        };
+        assert!(Wal::open(path, &options).is_err());
+        assert!(Wal::open(path, &segment_options()).is_ok());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_missing_segment() {
+    create_test_dir("./files/manager_missing_segment", |path| {
+        let mut redo_log =
+            RedoLog::with_wal(open_wal(path), TestStore::new(), segment_options()).unwrap();
+        for i in 0..10 {
+            commit_redo(&mut redo_log, i * 2..i * 2 + 2);
+        }
+        drop(redo_log);
+
+        let segments = open_wal(path).segments();
+        assert!(segments.len() > 2);
+        fs::remove_file(&segments[1]).unwrap();
+        let err = Wal::open(path, &segment_options()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_recovery_matches_single_file() {
+    fn run<B: disk_utils::wal::backend::LogBackend>(log: &mut RedoLog<TestData, TestStore, B>) {
+        for i in 0..6 {
+            commit_redo(log, i * 3..i * 3 + 3);
+        }
+        let active = log.start();
+        log.write(active, 1, "active".to_string()).unwrap();
+        log.checkpoint().unwrap();
+        for i in 6..9 {
+            commit_redo(log, i * 3..i * 3 + 3);
+        }
+        log.commit(active).unwrap();
+        let aborted = log.start();
+        log.write(aborted, 2, "aborted".to_string()).unwrap();
+        log.abort(aborted).unwrap();
+        let unfinished = log.start();
+        log.write(unfinished, 3, "unfinished".to_string()).unwrap();
+    }
+
+    create_test_file("./files/manager_redo_single_file", |file_path, _| {
+        create_test_dir("./files/manager_redo_equivalence", |dir| {
+            let mut log =
+                RedoLog::new_with_options(file_path, TestStore::new(), segment_options()).unwrap();
+            run(&mut log);
+            drop(log);
+            let mut log =
+                RedoLog::with_wal(open_wal(dir), TestStore::new(), segment_options()).unwrap();
+            run(&mut log);
+            drop(log);
+
+            let file_store = TestStore::new();
+            let file_log =
+                RedoLog::new_with_options(file_path, file_store.clone(), segment_options())
+                    .unwrap();
+            let wal_store = TestStore::new();
+            let wal_log =
+                RedoLog::with_wal(open_wal(dir), wal_store.clone(), segment_options()).unwrap();
+            assert!(segment_files(dir).len() > 2);
+
+            assert_eq!(wal_store.map(), file_store.map());
+            assert_eq!(wal_log.last_tid(), file_log.last_tid());
+            let file_entries = file_log
+                .reader(ReadDirection::Forward)
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>()
+                .map(Result::unwrap);
+            let wal_entries = wal_log
+                .reader(ReadDirection::Forward)
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>()
+                .map(Result::unwrap);
+            assert!(wal_entries.eq(file_entries));
+        })
+        .unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_recovery_matches_single_file() {
+    fn run<B: disk_utils::wal::backend::LogBackend>(log: &mut UndoLog<TestData, TestStore, B>) {
+        for i in 0..6 {
+            let tid = log.start();
+            for key in i * 3..i * 3 + 3 {
+                log.write(tid, key, format!("value {}", key).repeat(10))
+                    .unwrap();
+            }
+            log.commit(tid).unwrap();
+        }
+        let active = log.start();
+        log.write(active, 1, "active".to_string()).unwrap();
+        log.checkpoint().unwrap();
+        log.commit(active).unwrap();
+        let unfinished = log.start();
+        log.write(unfinished, 3, "unfinished".to_string()).unwrap();
+        log.write(unfinished, 30, "inserted".to_string()).unwrap();
+    }
+
+    create_test_file("./files/manager_undo_single_file", |file_path, _| {
+        create_test_dir("./files/manager_undo_equivalence", |dir| {
+            let file_store = TestStore::new();
+            let mut log =
+                UndoLog::new_with_options(file_path, file_store.clone(), segment_options())
+                    .unwrap();
+            run(&mut log);
+            drop(log);
+            let wal_store = TestStore::new();
+            let mut log =
+                UndoLog::with_wal(open_wal(dir), wal_store.clone(), segment_options()).unwrap();
+            run(&mut log);
+            drop(log);
+
+            assert!(open_wal(dir).checkpoint().is_some());
+            let file_log =
+                UndoLog::new_with_options(file_path, file_store.clone(), segment_options())
+                    .unwrap();
+            let wal_log =
+                UndoLog::with_wal(open_wal(dir), wal_store.clone(), segment_options()).unwrap();
+
+            assert_eq!(wal_store.map(), file_store.map());
+            assert_eq!(wal_store.get(&3), Some("value 3".repeat(10)));
+            assert_eq!(wal_store.get(&30), None);
+            assert_eq!(wal_log.last_tid(), file_log.last_tid());
+        })
+        .unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_truncate_deletes_dead_segments() {
+    create_test_dir("./files/manager_truncate", |path| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::with_wal(open_wal(path), store.clone(), segment_options()).unwrap();
+        for i in 0..10 {
+            commit_redo(&mut redo_log, i * 2..i * 2 + 2);
+        }
+        redo_log.checkpoint().unwrap();
+        commit_redo(&mut redo_log, 20..22);
+
+        let segments = segment_files(path);
+        assert!(redo_log.truncate_before_checkpoint().unwrap());
+        let kept = segment_files(path);
+        assert!(kept.len() < segments.len());
+        assert!(!segments[0].exists());
+        assert_eq!(kept.last(), segments.last());
+
+        commit_redo(&mut redo_log, 22..24);
+        drop(redo_log);
+
+        store.discard_changes();
+        let recovered = TestStore::new();
+        let redo_log =
+            RedoLog::with_wal(open_wal(path), recovered.clone(), segment_options()).unwrap();
+        assert_eq!(redo_log.last_tid(), 12);
+        for key in 20..24 {
+            assert_eq!(
+                recovered.get(&key),
+                Some(format!("value {}", key).repeat(10))
+            );
+        }
+
+        // The log can still be read from its start.
+        let entries = redo_log
+            .reader(ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<TestData>>()
+            .map(Result::unwrap)
+            .count();
+        assert!(entries > 0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_compact_unsupported() {
+    create_test_dir("./files/manager_compact", |path| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::with_wal(open_wal(path), store.clone(), segment_options()).unwrap();
+        for i in 0..6 {
+            commit_redo(&mut redo_log, i * 3..i * 3 + 3);
+        }
+        let segments = segment_files(path);
+
+        match redo_log.compact() {
+            Err(LogError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::Unsupported),
+            result => panic!("Expected an unsupported error, got {:?}", result),
+        }
+        assert_eq!(segment_files(path), segments);
+
+        // The log keeps working and recovers as if compact wasn't called.
+        commit_redo(&mut redo_log, 18..21);
+        drop(redo_log);
+        let recovered = TestStore::new();
+        let redo_log =
+            RedoLog::with_wal(open_wal(path), recovered.clone(), segment_options()).unwrap();
+        assert_eq!(recovered.map(), store.map());
+        assert_eq!(redo_log.last_tid(), 7);
+    })
+    .unwrap();
+}
@@ -0,0 +1,165 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::TestStore;
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::metrics::{MetricEvent, Metrics, MetricsSink};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::{CheckpointStatus, UndoLog};
+
+#[derive(Default)]
+struct EventSink(Mutex<Vec<MetricEvent>>);
+
+impl MetricsSink for EventSink {
+    fn record(&self, event: MetricEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+/// Asserts the counters of the metrics other than the timings.
+fn assert_counts(metrics: &Metrics, expected: Metrics) {
+    assert_eq!(
+        metrics.flush_latency.iter().sum::<u64>(),
+        metrics.flushes,
+        "Every flush is in a latency bucket"
+    );
+    let counts = Metrics {
+        recovery_duration: expected.recovery_duration,
+        flush_latency: expected.flush_latency,
+        ..metrics.clone()
+    };
+    assert_eq!(counts, expected);
+}
+
+#[test]
+fn test_redo_log_metrics() {
+    create_test_file("./files/metrics_redo_log", |path, file| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        let header = file.metadata().unwrap().len();
+        assert_counts(redo_log.metrics(), Metrics::default());
+
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "one".to_string()).unwrap();
+        redo_log.write(tid, 2, "two".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 3, "three".to_string()).unwrap();
+        redo_log.abort(tid).unwrap();
+        redo_log.checkpoint().unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 4, "four".to_string()).unwrap();
+
+        // The last transaction's entries haven't been flushed.
+        let bytes_flushed = file.metadata().unwrap().len() - header;
+        assert_counts(
+            redo_log.metrics(),
+            Metrics {
+                entries_appended: 11,
+                bytes_flushed,
+                flushes: 4,
+                commits: 1,
+                aborts: 1,
+                checkpoints: 1,
+                ..Metrics::default()
+            },
+        );
+        drop(redo_log);
+        let size = file.metadata().unwrap().len();
+
+        // Recovery aborts the unfinished transaction.
+        let redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        assert_counts(
+            redo_log.metrics(),
+            Metrics {
+                entries_appended: 1,
+                bytes_flushed: file.metadata().unwrap().len() - size,
+                flushes: 1,
+                aborts: 1,
+                ..Metrics::default()
+            },
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_metrics() {
+    create_test_file("./files/metrics_undo_log", |path, file| {
+        let mut undo_log = UndoLog::new(path, TestStore::new()).unwrap();
+        let header = file.metadata().unwrap().len();
+
+        // Each write flushes its entry before the change reaches the store.
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "one".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 2, "two".to_string()).unwrap();
+        undo_log.abort(tid).unwrap();
+        assert_eq!(undo_log.checkpoint().unwrap(), CheckpointStatus::Started);
+        assert_eq!(
+            undo_log.checkpoint().unwrap(),
+            CheckpointStatus::AlreadyPending
+        );
+        let tid = undo_log.start();
+        undo_log.write(tid, 3, "three".to_string()).unwrap();
+
+        assert_counts(
+            undo_log.metrics(),
+            Metrics {
+                entries_appended: 9,
+                bytes_flushed: file.metadata().unwrap().len() - header,
+                flushes: 6,
+                commits: 1,
+                aborts: 1,
+                checkpoints: 1,
+                ..Metrics::default()
+            },
+        );
+        drop(undo_log);
+        let size = file.metadata().unwrap().len();
+
+        // Recovery rolls back and aborts the unfinished transaction.
+        let undo_log = UndoLog::new(path, TestStore::new()).unwrap();
+        assert_counts(
+            undo_log.metrics(),
+            Metrics {
+                entries_appended: 1,
+                bytes_flushed: file.metadata().unwrap().len() - size,
+                flushes: 1,
+                aborts: 1,
+                ..Metrics::default()
+            },
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_metrics_sink() {
+    create_test_file("./files/metrics_sink_redo_log", |path, _| {
+        let sink = Arc::new(EventSink::default());
+        let mut redo_log = RedoLog::options()
+            .metrics_sink(sink.clone())
+            .open(path, TestStore::new())
+            .unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "one".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        let events = sink.0.lock().unwrap().clone();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(events[0], MetricEvent::Recovered { .. }));
+        assert_eq!(events[1..4], [MetricEvent::EntryAppended; 3]);
+        match events[4] {
+            MetricEvent::Flushed { bytes, .. } => {
+                assert_eq!(bytes, redo_log.metrics().bytes_flushed)
+            }
+            event => panic!("Expected a flush, got {:?}", event),
+        }
+        assert_eq!(events[5], MetricEvent::Committed);
+    })
+    .unwrap();
+}
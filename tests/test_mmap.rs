@@ -0,0 +1,199 @@
+#![cfg(feature = "mmap")]
+
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::fs::File;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::SingleLogEntry;
+use disk_utils::wal::iterator::{BlockSource, OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::mmap::MmapBlockSource;
+use disk_utils::wal::record::{BlockFormat, Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{append_to_file, LogOptions, LogStore};
+
+const BLOCK_SIZE: i64 = 256;
+
+fn options(mmap: bool) -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        mmap,
+        ..LogOptions::default()
+    }
+}
+
+/// Reads every record in the direction from the source.
+fn read_records<S: BlockSource>(source: &mut S, direction: ReadDirection) -> Vec<Record> {
+    let format = BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    };
+    let iter = WalIterator::with_format(source, direction, format)
+        .unwrap()
+        .on_corruption(OnCorruption::SkipToNextBlock);
+    match direction {
+        ReadDirection::Forward => iter.collect(),
+        ReadDirection::Backward => iter.rev().collect(),
+    }
+}
+
+/// Checks that reading the log through a mapping returns the same records
+/// as reading it from the file.
+fn assert_same_records(file: &mut File) {
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let mut source = MmapBlockSource::new(file).unwrap();
+        assert!(source.is_mapped());
+        let mapped = read_records(&mut source, direction);
+        assert_eq!(mapped, read_records(file, direction));
+    }
+}
+
+#[test]
+fn test_mmap_empty_file() {
+    create_test_file("./files/mmap_empty_file", |_, mut file| {
+        let mut source = MmapBlockSource::new(&file).unwrap();
+        assert!(source.is_mapped());
+        assert!(read_records(&mut source, ReadDirection::Forward).is_empty());
+        assert!(read_records(&mut source, ReadDirection::Backward).is_empty());
+        assert_same_records(&mut file);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_mmap_unaligned_file() {
+    create_test_file("./files/mmap_unaligned_file", |_, mut file| {
+        for i in 0..20 {
+            let record = Record::new(RecordType::Full, vec![i; 30]).unwrap();
+            append_to_file(&mut file, &record).unwrap();
+        }
+        assert!(file.metadata().unwrap().len() % BLOCK_SIZE as u64 != 0);
+        assert_same_records(&mut file);
+
+        // Cut the last record off partway through its payload.
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - 10).unwrap();
+        assert_same_records(&mut file);
+        file.set_len(len - 30 - HEADER_SIZE as u64 + 3).unwrap();
+        assert_same_records(&mut file);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_mmap_entries() {
+    create_test_file("./files/mmap_entries", |path, mut file| {
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(false)).unwrap();
+        for i in 0..50 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_same_records(&mut file);
+
+        let format = BlockFormat {
+            block_size: BLOCK_SIZE,
+            ..BlockFormat::default()
+        };
+        let mut source = MmapBlockSource::new(&file).unwrap();
+        let mapped: Vec<_> = WalIterator::with_format(&mut source, ReadDirection::Forward, format)
+            .unwrap()
+            .entries::<SingleLogEntry<TestData>>()
+            .map(Result::unwrap)
+            .collect();
+        let read: Vec<_> = WalIterator::with_format(&mut file, ReadDirection::Forward, format)
+            .unwrap()
+            .entries::<SingleLogEntry<TestData>>()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(mapped.len(), 150);
+        assert_eq!(mapped, read);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_mmap_redo_log_recovery() {
+    create_test_file("./files/mmap_redo_log_recovery", |path, mut file| {
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options(false)).unwrap();
+        for i in 0..50 {
+            let tid = redo_log.start();
+            redo_log
+                .write(tid, i % 20, format!("value {:026}", i))
+                .unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        let tid = redo_log.start();
+        redo_log.write(tid, 0, "uncommitted".to_string()).unwrap();
+        drop(redo_log);
+        assert_same_records(&mut file);
+
+        // Recover a copy of the log, since recovery appends aborts to the log.
+        create_test_file("./files/mmap_redo_log_recovery_copy", |copy, _| {
+            fs::copy(path, copy).unwrap();
+
+            let store = TestStore::new();
+            let mut redo_log =
+                RedoLog::new_with_options(path, store.clone(), options(false)).unwrap();
+            let mapped_store = TestStore::new();
+            let mut mapped_redo_log =
+                RedoLog::new_with_options(copy, mapped_store.clone(), options(true)).unwrap();
+            assert_eq!(redo_log.start(), mapped_redo_log.start());
+            assert_eq!(store.map().len(), 20);
+            assert_eq!(store.map(), mapped_store.map());
+        })
+        .unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_mmap_undo_log_recovery() {
+    create_test_file("./files/mmap_undo_log_recovery", |path, mut file| {
+        let mut store = TestStore::new();
+        for i in 0..20 {
+            store.update(i, "o".repeat(30));
+        }
+        let mut undo_log = UndoLog::new_with_options(path, store.clone(), options(false)).unwrap();
+        for i in 0..20 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+
+        // Leave a transaction unfinished at the end of the log.
+        store.set_flush_err(true);
+        let tid = undo_log.start();
+        undo_log.write(tid, 0, "changed".to_string()).unwrap();
+        undo_log.write(tid, 100, "inserted".to_string()).unwrap();
+        assert!(undo_log.commit(tid).is_err());
+        store.set_flush_err(false);
+        drop(undo_log);
+        assert_same_records(&mut file);
+
+        // Recover a copy of the log, since recovery appends aborts to the log.
+        create_test_file("./files/mmap_undo_log_recovery_copy", |copy, _| {
+            fs::copy(path, copy).unwrap();
+
+            let mapped_store = TestStore::with_contents(store.map());
+            let mut undo_log =
+                UndoLog::new_with_options(path, store.clone(), options(false)).unwrap();
+            let mut mapped_undo_log =
+                UndoLog::new_with_options(copy, mapped_store.clone(), options(true)).unwrap();
+            assert_eq!(undo_log.start(), mapped_undo_log.start());
+            assert_eq!(store.get(&0), Some(format!("value {:026}", 0)));
+            assert_eq!(store.get(&100), None);
+            assert_eq!(store.map(), mapped_store.map());
+        })
+        .unwrap();
+    })
+    .unwrap();
+}
@@ -1,12 +1,21 @@
 extern crate disk_utils;
 
+use std::io;
+use std::io::Cursor;
+
 use disk_utils::testing::create_test_file;
 use disk_utils::wal::entries::ChangeEntry;
-use disk_utils::wal::iterator::{ReadDirection, WalIterator};
-use disk_utils::wal::record::RecordType;
+use disk_utils::wal::iterator::{BlockError, ReadDirection, WalIterator};
+use disk_utils::wal::record::{
+    check_max_record_size, records_needed, space_remaining_in_block, BlockFormat, Record,
+    RecordType, BLOCK_SIZE, HEADER_SIZE, MAX_PAYLOAD_SIZE, MAX_RECORD_SIZE,
+};
+use disk_utils::wal::writer::Writer;
 use disk_utils::wal::{
-    append_to_file, read_serializable, read_serializable_backwards, split_bytes_into_records,
-    LogData,
+    append_to_file, read_all_serializable, read_serializable, read_serializable_at,
+    read_serializable_backwards, read_serializable_backwards_at,
+    read_serializable_backwards_resync, read_serializable_resync, split_bytes_into_records,
+    write_serializable, write_serializable_to, LogData, SerializeError,
 };
 use disk_utils::Serializable;
 
@@ -28,17 +37,17 @@ fn test_split_bytes() {
 
     let mut bytes = Vec::new();
     entry.serialize(&mut bytes).unwrap();
-    let mut records = split_bytes_into_records(&bytes, 2).unwrap();
+    let records = split_bytes_into_records(&bytes, 2).unwrap();
 
     assert_eq!(records[0].record_type, RecordType::First);
-    for i in 1..(records.len() - 1) {
-        assert_eq!(records[i].record_type, RecordType::Middle);
+    for record in &records[1..(records.len() - 1)] {
+        assert_eq!(record.record_type, RecordType::Middle);
     }
     assert_eq!(records[records.len() - 1].record_type, RecordType::Last);
 
     let mut buf = Vec::new();
-    for record in records.iter_mut() {
-        buf.append(&mut record.payload);
+    for record in records.iter() {
+        buf.extend_from_slice(&record.payload);
     }
 
     for (b1, b2) in bytes.iter().zip(buf.iter()) {
@@ -46,6 +55,57 @@ fn test_split_bytes() {
     }
 }
 
+#[test]
+fn test_split_bytes_matches_owned_records() {
+    let bytes: Vec<u8> = (0..100).collect();
+    for &(max_record_size, lens) in &[
+        (10, &[10; 10][..]),
+        (30, &[30, 30, 30, 10][..]),
+        (100, &[100][..]),
+        (1000, &[100][..]),
+    ] {
+        let records = split_bytes_into_records(&bytes, max_record_size).unwrap();
+        let mut start = 0;
+        for (i, (record, &len)) in records.iter().zip(lens).enumerate() {
+            let record_type = match i {
+                _ if lens.len() == 1 => RecordType::Full,
+                0 => RecordType::First,
+                i if i == lens.len() - 1 => RecordType::Last,
+                _ => RecordType::Middle,
+            };
+            let chunk = bytes[start..start + len].to_vec();
+            assert_eq!(*record, Record::new(record_type, chunk).unwrap());
+            start += len;
+        }
+        assert_eq!(records.len(), lens.len());
+    }
+
+    let records = split_bytes_into_records(&[], 10).unwrap();
+    assert_eq!(
+        records,
+        vec![Record::new(RecordType::Zero, vec![]).unwrap()]
+    );
+}
+
+#[test]
+fn test_split_bytes_leaves_buffer_usable() {
+    let mut bytes = vec![1; 50];
+    let records = split_bytes_into_records(&bytes, 20).unwrap();
+
+    // Reusing the buffer doesn't change the records split from it.
+    bytes.clear();
+    bytes.extend_from_slice(&[2; 30]);
+    let reused = split_bytes_into_records(&bytes, 20).unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(records
+        .iter()
+        .all(|record| record.payload.iter().all(|&b| b == 1)));
+    assert_eq!(reused.len(), 2);
+    assert!(reused
+        .iter()
+        .all(|record| record.payload.iter().all(|&b| b == 2)));
+}
+
 #[test]
 fn test_read_serializable() {
     create_test_file("./files/read_serializable_test", |_, mut file| {
@@ -55,12 +115,7 @@ fn test_read_serializable() {
             value: "Hello world".to_string(),
         };
 
-        let mut bytes = Vec::new();
-        entry.serialize(&mut bytes).unwrap();
-        let records = split_bytes_into_records(&bytes, 1).unwrap();
-        for record in records.iter() {
-            append_to_file(&mut file, record).unwrap();
-        }
+        write_serializable(&mut file, &entry, 1).unwrap();
 
         let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
         let result_entry = read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap();
@@ -96,12 +151,7 @@ fn test_read_serializable_back_and_forth() {
         ];
 
         for entry in entries.iter() {
-            let mut bytes = Vec::new();
-            entry.serialize(&mut bytes).unwrap();
-            let records = split_bytes_into_records(&bytes, 1).unwrap();
-            for record in records.iter() {
-                append_to_file(&mut file, record).unwrap();
-            }
+            write_serializable(&mut file, entry, 1).unwrap();
         }
 
         let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
@@ -110,17 +160,514 @@ fn test_read_serializable_back_and_forth() {
             entries[0]
         );
         assert_eq!(
-            read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
-            entries[1]
+            read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+            entries[2]
         );
         assert_eq!(
-            read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+            read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
             entries[1]
         );
+        assert!(matches!(
+            read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter),
+            Err(SerializeError::OutOfRecords)
+        ));
+        assert!(matches!(
+            read_serializable::<ChangeEntry<MyLogData>>(&mut iter),
+            Err(SerializeError::OutOfRecords)
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_first_and_last_records() {
+    create_test_file("./files/read_first_and_last_records", |_, mut file| {
+        let entry = ChangeEntry {
+            tid: 123,
+            key: 20,
+            value: "Hello world".to_string(),
+        };
+        // The entry is split into a First record followed directly by a
+        // Last record, without any Middle records.
+        let size = entry.serialized_size().unwrap() as usize;
+        write_serializable(&mut file, &entry, size / 2 + 1).unwrap();
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let record_types: Vec<_> = iter.by_ref().map(|record| record.record_type).collect();
+        assert_eq!(record_types, vec![RecordType::First, RecordType::Last]);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let result_entry = read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap();
+        assert_eq!(entry, result_entry);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        let result_entry =
+            read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter).unwrap();
+        assert_eq!(entry, result_entry);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_split_degenerate_record_sizes() {
+    let bytes = vec![7; 10];
+
+    // Records can't be empty, so a record size of 0 is rejected.
+    let err = split_bytes_into_records(&bytes, 0).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(split_bytes_into_records(&[], 0).is_err());
+    assert!(check_max_record_size(0).is_err());
+
+    let records = split_bytes_into_records(&bytes, 1).unwrap();
+    assert_eq!(records.len(), 10);
+    assert!(records.iter().all(|record| record.payload.len() == 1));
+
+    let err = split_bytes_into_records(&bytes, u16::MAX as usize).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(check_max_record_size(u16::MAX as usize + 1).is_err());
+    assert!(check_max_record_size(MAX_RECORD_SIZE).is_ok());
+}
+
+#[test]
+fn test_split_large_payload() {
+    let bytes = vec![7; 70_000];
+
+    // A record size that overflows the u16 size field is rejected.
+    assert!(split_bytes_into_records(&bytes, 70_000).is_err());
+    assert!(split_bytes_into_records(&bytes, u16::MAX as usize).is_err());
+    assert!(split_bytes_into_records(&bytes, u16::MAX as usize + 1).is_err());
+    assert!(split_bytes_into_records(&bytes, MAX_RECORD_SIZE + 1).is_err());
+
+    let records = split_bytes_into_records(&bytes, u16::MAX as usize - HEADER_SIZE).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].record_type, RecordType::First);
+    assert_eq!(records[1].record_type, RecordType::Last);
+    let total: usize = records.iter().map(|record| record.size as usize).sum();
+    assert_eq!(total, bytes.len());
+    for record in records.iter() {
+        assert_eq!(record.size as usize, record.payload.len());
+    }
+}
+
+#[test]
+fn test_record_geometry() {
+    assert_eq!(MAX_PAYLOAD_SIZE, BLOCK_SIZE as usize - HEADER_SIZE);
+    assert_eq!(BlockFormat::default().max_payload_size(), MAX_PAYLOAD_SIZE);
+    let checksummed = BlockFormat {
+        checksums: true,
+        ..BlockFormat::default()
+    };
+    assert_eq!(checksummed.max_payload_size(), MAX_PAYLOAD_SIZE - 4);
+    let large = BlockFormat {
+        block_size: 131072,
+        checksums: false,
+    };
+    assert_eq!(large.max_payload_size(), u16::MAX as usize - HEADER_SIZE);
+
+    assert_eq!(records_needed(0, 10), 1);
+    assert_eq!(records_needed(1, 10), 1);
+    assert_eq!(records_needed(10, 10), 1);
+    assert_eq!(records_needed(11, 10), 2);
+    assert_eq!(records_needed(100, 10), 10);
+    for &len in &[0, 1, 9, 10, 11, 100, 101] {
+        let bytes = vec![0; len];
+        let records = split_bytes_into_records(&bytes, 10).unwrap();
+        assert_eq!(records.len(), records_needed(len, 10));
+    }
+
+    assert_eq!(space_remaining_in_block(0), BLOCK_SIZE as u64);
+    assert_eq!(space_remaining_in_block(100), BLOCK_SIZE as u64 - 100);
+    assert_eq!(
+        space_remaining_in_block(BLOCK_SIZE as u64),
+        BLOCK_SIZE as u64
+    );
+    assert_eq!(
+        space_remaining_in_block(BLOCK_SIZE as u64 + 1),
+        BLOCK_SIZE as u64 - 1
+    );
+    assert_eq!(checksummed.space_remaining(BLOCK_SIZE as u64 - 2), 0);
+}
+
+/// Returns a string that serializes to exactly `len` bytes.
+fn string_serialized_to(len: usize) -> String {
+    let mut bytes = Vec::new();
+    String::new().serialize(&mut bytes).unwrap();
+    "a".repeat(len - bytes.len())
+}
+
+#[test]
+fn test_split_at_max_payload_boundary() {
+    create_test_file("./files/max_payload_boundary", |_, mut file| {
+        let entries = [
+            string_serialized_to(MAX_PAYLOAD_SIZE),
+            string_serialized_to(MAX_PAYLOAD_SIZE + 1),
+        ];
+
+        let mut bytes = Vec::new();
+        entries[0].serialize(&mut bytes).unwrap();
+        let records = split_bytes_into_records(&bytes, MAX_PAYLOAD_SIZE).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].record_type, RecordType::Full);
+        for record in records.iter() {
+            append_to_file(&mut file, record).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        entries[1].serialize(&mut bytes).unwrap();
+        let records = split_bytes_into_records(&bytes, MAX_PAYLOAD_SIZE).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].record_type, RecordType::First);
+        assert_eq!(records[1].record_type, RecordType::Last);
+        assert_eq!(records[1].payload.len(), 1);
+        for record in records.iter() {
+            append_to_file(&mut file, record).unwrap();
+        }
+
+        // A full size record fills its block exactly.
+        assert_eq!(file.metadata().unwrap().len(), 2 * BLOCK_SIZE as u64 + 16);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for entry in entries.iter() {
+            assert_eq!(read_serializable::<String>(&mut iter).unwrap(), *entry);
+        }
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        for entry in entries.iter().rev() {
+            assert_eq!(
+                read_serializable_backwards::<String>(&mut iter).unwrap(),
+                *entry
+            );
+        }
+    })
+    .unwrap();
+}
+
+fn fragmented_entries() -> Vec<ChangeEntry<MyLogData>> {
+    (0..5)
+        .map(|i| ChangeEntry {
+            tid: i as u64,
+            key: i,
+            value: format!("Value number {}", i),
+        })
+        .collect()
+}
+
+/// Writes the entries split into records of 8 bytes to an in-memory log,
+/// leaving out the record of the given entry with the given type.
+fn log_without_record(
+    entries: &[ChangeEntry<MyLogData>],
+    entry: usize,
+    record_type: RecordType,
+) -> Cursor<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for (i, change) in entries.iter().enumerate() {
+        let mut serialized = Vec::new();
+        change.serialize(&mut serialized).unwrap();
+        let records = split_bytes_into_records(&serialized, 8).unwrap();
+        assert!(records.len() > 3);
+        let skipped = if i != entry {
+            None
+        } else if record_type == RecordType::Middle {
+            Some(2)
+        } else {
+            records.iter().position(|r| r.record_type == record_type)
+        };
+        for (j, record) in records.iter().enumerate() {
+            if Some(j) != skipped {
+                record.write(&mut bytes).unwrap();
+            }
+        }
+    }
+    Cursor::new(bytes)
+}
+
+#[test]
+fn test_skip_to_entry_boundary() {
+    let entries = fragmented_entries();
+    let mut log = log_without_record(&entries, 1, RecordType::First);
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+    assert_eq!(
+        iter.skip_to_entry_boundary(ReadDirection::Forward).unwrap(),
+        0
+    );
+    read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap();
+
+    // The rest of the second entry is skipped.
+    let skipped = iter.skip_to_entry_boundary(ReadDirection::Forward).unwrap();
+    assert!(skipped > 1);
+    assert_eq!(iter.peek().unwrap().unwrap().record_type, RecordType::First);
+    assert_eq!(
+        read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+        entries[2]
+    );
+
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Backward).unwrap();
+    iter.try_next_back().unwrap();
+    let skipped = iter
+        .skip_to_entry_boundary(ReadDirection::Backward)
+        .unwrap();
+    assert!(skipped > 1);
+    assert_eq!(
+        iter.peek_back().unwrap().unwrap().record_type,
+        RecordType::Last
+    );
+    assert_eq!(
+        read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+        entries[3]
+    );
+}
+
+#[test]
+fn test_read_serializable_resync() {
+    let entries = fragmented_entries();
+    for &record_type in &[RecordType::First, RecordType::Middle, RecordType::Last] {
+        let mut log = log_without_record(&entries, 2, record_type);
+
+        let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+        let mut read = Vec::new();
+        loop {
+            match read_serializable_resync::<ChangeEntry<MyLogData>>(&mut iter) {
+                Ok(entry) => read.push(entry),
+                Err(SerializeError::OutOfRecords) => break,
+                // The entry missing a middle record fails to deserialize.
+                Err(SerializeError::IoError(_)) => assert_eq!(record_type, RecordType::Middle),
+                Err(e) => panic!("Unexpected error {:?}", e),
+            }
+        }
+        let mut expected = entries.clone();
+        expected.remove(2);
+        assert_eq!(read, expected);
+
+        let mut iter = WalIterator::new(&mut log, ReadDirection::Backward).unwrap();
+        let mut read = Vec::new();
+        loop {
+            match read_serializable_backwards_resync::<ChangeEntry<MyLogData>>(&mut iter) {
+                Ok(entry) => read.push(entry),
+                Err(SerializeError::OutOfRecords) => break,
+                Err(SerializeError::IoError(_)) => assert_eq!(record_type, RecordType::Middle),
+                Err(e) => panic!("Unexpected error {:?}", e),
+            }
+        }
+        read.reverse();
+        assert_eq!(read, expected);
+    }
+}
+
+#[test]
+fn test_lost_last_record_leaves_next_entry() {
+    let entries = fragmented_entries();
+    let mut log = log_without_record(&entries, 2, RecordType::Last);
+    let mut iter = WalIterator::new(&mut log, ReadDirection::Forward).unwrap();
+    for entry in entries[..2].iter() {
         assert_eq!(
-            read_serializable_backwards::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
-            entries[0]
+            read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+            *entry
         );
+    }
+    match read_serializable::<ChangeEntry<MyLogData>>(&mut iter) {
+        Err(SerializeError::InvalidTransfer {
+            found: RecordType::First,
+            previous: Some(RecordType::Middle),
+            ..
+        }) => {}
+        result => panic!("Expected invalid transfer, got {:?}", result),
+    }
+    assert_eq!(
+        read_serializable::<ChangeEntry<MyLogData>>(&mut iter).unwrap(),
+        entries[3]
+    );
+}
+
+#[test]
+fn test_read_serializable_at() {
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    let mut expected = Vec::new();
+    for &len in &[10, 600, 100, 241, 1000, 5] {
+        let entry = "x".repeat(len);
+        let start = writer.append_serializable(&entry).unwrap();
+        expected.push((entry, start..writer.position()));
+    }
+    // Most of the entries span a block boundary.
+    let spanning = expected
+        .iter()
+        .filter(|(_, range)| range.start / 256 != (range.end - 1) / 256)
+        .count();
+    assert!(spanning >= 3);
+
+    let mut cursor = writer.into_inner();
+    let len = cursor.get_ref().len() as u64;
+    {
+        let mut iter =
+            WalIterator::with_format(&mut cursor, ReadDirection::Forward, format).unwrap();
+        for (entry, range) in &expected {
+            let (read, read_range) = read_serializable_at::<String>(&mut iter).unwrap();
+            assert_eq!(&read, entry);
+            assert_eq!(&read_range, range);
+        }
+        assert!(read_serializable_at::<String>(&mut iter).is_err());
+    }
+
+    let mut iter = WalIterator::with_format(&mut cursor, ReadDirection::Backward, format).unwrap();
+    for (entry, range) in expected.iter().rev() {
+        let (read, read_range) = read_serializable_backwards_at::<String>(&mut iter).unwrap();
+        assert_eq!(&read, entry);
+        assert_eq!(&read_range, range);
+    }
+    assert!(read_serializable_backwards_at::<String>(&mut iter).is_err());
+    assert_eq!(expected.last().unwrap().1.end, len);
+}
+
+#[test]
+fn test_write_serializable_round_trip() {
+    create_test_file("./files/write_serializable_round_trip", |_, mut file| {
+        // Entries both smaller than a record and spanning several blocks.
+        let entries = vec!["small".to_string(), "x".repeat(3 * BLOCK_SIZE as usize)];
+        let mut offsets = Vec::new();
+        for entry in &entries {
+            offsets.push(write_serializable(&mut file, entry, MAX_PAYLOAD_SIZE).unwrap());
+        }
+        assert_eq!(offsets[0], 0);
+
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for (entry, &offset) in entries.iter().zip(&offsets) {
+            let (read, range) = read_serializable_at::<String>(&mut iter).unwrap();
+            assert_eq!(&read, entry);
+            assert_eq!(range.start, offset);
+        }
     })
     .unwrap();
+
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    let entries = vec!["small".to_string(), "x".repeat(1000)];
+    for entry in &entries {
+        write_serializable_to(&mut writer, entry).unwrap();
+    }
+    assert_eq!(writer.last_lsn(), 6);
+    let mut cursor = writer.into_inner();
+    let mut iter = WalIterator::with_format(&mut cursor, ReadDirection::Backward, format).unwrap();
+    for entry in entries.iter().rev() {
+        assert_eq!(
+            &read_serializable_backwards::<String>(&mut iter).unwrap(),
+            entry
+        );
+    }
+}
+
+#[test]
+fn test_read_all_serializable() {
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let entries: Vec<String> = (0..10).map(|i| i.to_string().repeat(i * 20)).collect();
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    for entry in &entries {
+        writer.append_serializable(entry).unwrap();
+    }
+    let mut cursor = writer.into_inner();
+    {
+        let mut iter =
+            WalIterator::with_format(&mut cursor, ReadDirection::Forward, format).unwrap();
+        let (read, err) = read_all_serializable::<String>(&mut iter);
+        assert_eq!(read, entries);
+        assert!(err.is_none());
+    }
+
+    // Corrupt the last byte of the final entry.
+    let len = cursor.get_ref().len();
+    cursor.get_mut()[len - 1] ^= 0xFF;
+    let mut iter = WalIterator::with_format(&mut cursor, ReadDirection::Forward, format).unwrap();
+    let (read, err) = read_all_serializable::<String>(&mut iter);
+    assert_eq!(read, entries[..9].to_vec());
+    match err {
+        Some(SerializeError::Corrupted(BlockError::Corrupted { .. })) => {}
+        err => panic!("Expected the corruption to be returned, got {:?}", err),
+    }
+}
+
+#[test]
+fn test_invalid_transfer_positions() {
+    let mut full = Vec::new();
+    "entry".to_string().serialize(&mut full).unwrap();
+    let format = BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    };
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    // A chain missing its last record followed by one missing its first,
+    // all in the same block.
+    let record_types = [
+        RecordType::Full,
+        RecordType::First,
+        RecordType::Middle,
+        RecordType::Full,
+        RecordType::Middle,
+        RecordType::Last,
+    ];
+    let offsets: Vec<u64> = record_types
+        .iter()
+        .map(|&record_type| {
+            let payload = if record_type == RecordType::Full {
+                full.clone()
+            } else {
+                vec![1; 10]
+            };
+            writer
+                .append(&Record::new(record_type, payload).unwrap())
+                .unwrap()
+        })
+        .collect();
+    assert!(offsets
+        .iter()
+        .all(|offset| offset / 256 == offsets[0] / 256));
+    let mut log = writer.into_inner();
+
+    let expect_invalid = |result: Result<String, SerializeError>,
+                          found: RecordType,
+                          previous: Option<RecordType>,
+                          record: usize| match result {
+        Err(SerializeError::InvalidTransfer {
+            found: err_found,
+            previous: err_previous,
+            offset,
+            record: err_record,
+        }) => assert_eq!(
+            (err_found, err_previous, offset, err_record),
+            (found, previous, offsets[record], record)
+        ),
+        result => panic!("Expected invalid transfer, got {:?}", result),
+    };
+
+    let mut iter = WalIterator::with_format(&mut log, ReadDirection::Forward, format).unwrap();
+    assert_eq!(read_serializable::<String>(&mut iter).unwrap(), "entry");
+    let result = read_serializable::<String>(&mut iter);
+    expect_invalid(result, RecordType::Full, Some(RecordType::Middle), 3);
+    assert_eq!(read_serializable::<String>(&mut iter).unwrap(), "entry");
+    let result = read_serializable::<String>(&mut iter);
+    expect_invalid(result, RecordType::Middle, None, 4);
+    let result = read_serializable::<String>(&mut iter);
+    expect_invalid(result, RecordType::Last, None, 5);
+
+    let mut iter = WalIterator::with_format(&mut log, ReadDirection::Backward, format).unwrap();
+    let result = read_serializable_backwards::<String>(&mut iter);
+    expect_invalid(result, RecordType::Full, Some(RecordType::Middle), 3);
+    assert_eq!(
+        read_serializable_backwards::<String>(&mut iter).unwrap(),
+        "entry"
+    );
+    let result = read_serializable_backwards::<String>(&mut iter);
+    expect_invalid(result, RecordType::Middle, None, 2);
+    let result = read_serializable_backwards::<String>(&mut iter);
+    expect_invalid(result, RecordType::First, None, 1);
+    assert_eq!(
+        read_serializable_backwards::<String>(&mut iter).unwrap(),
+        "entry"
+    );
 }
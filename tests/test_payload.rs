@@ -0,0 +1,158 @@
+extern crate disk_utils;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{Payload, Record, RecordType, BLOCK_SIZE, HEADER_SIZE};
+use disk_utils::wal::{append_to_file, read_serializable_backwards, split_bytes_into_records};
+use disk_utils::Serializable;
+
+/// Allocator that counts the bytes and allocations made by the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + layout.size()));
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocated_during<F: FnOnce() -> R, R>(fun: F) -> (R, usize) {
+    let before = ALLOCATED.with(|allocated| allocated.get());
+    let result = fun();
+    let after = ALLOCATED.with(|allocated| allocated.get());
+    (result, after - before)
+}
+
+fn allocations_during<F: FnOnce() -> R, R>(fun: F) -> (R, usize) {
+    let before = ALLOCATIONS.with(|allocations| allocations.get());
+    let result = fun();
+    let after = ALLOCATIONS.with(|allocations| allocations.get());
+    (result, after - before)
+}
+
+const ONE_MIB: usize = 1024 * 1024;
+
+#[test]
+fn test_payload_equality() {
+    let payload = Payload::from(vec![1, 2, 3]);
+    assert_eq!(payload, vec![1, 2, 3]);
+    assert_eq!(payload, Payload::from(&[1, 2, 3][..]));
+    assert_eq!(&payload[1..], &[2, 3]);
+    assert_eq!(format!("{:?}", payload), "[1, 2, 3]");
+}
+
+#[test]
+fn test_cloned_records_share_payload() {
+    let record = Record::new(RecordType::Full, vec![1; 1000]).unwrap();
+    let cloned = record.clone();
+    assert_eq!(record.payload.as_ptr(), cloned.payload.as_ptr());
+}
+
+#[test]
+fn test_iteration_does_not_copy_payloads() {
+    create_test_file("./files/iteration_payload_copies", |_, mut file| {
+        let payload_size = 8 * 1024 - HEADER_SIZE;
+        let record = Record::new(RecordType::Full, vec![1; payload_size]).unwrap();
+        for _ in 0..(16 * ONE_MIB / (8 * 1024)) {
+            append_to_file(&mut file, &record).unwrap();
+        }
+        let file_len = file.metadata().unwrap().len() as usize;
+
+        // Reading the file takes one block buffer and one payload allocation
+        // per block worth of records. Copying each yielded payload would
+        // allocate the size of the file a third time.
+        let (count, allocated) = allocated_during(|| {
+            let iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+            iter.map(|record| record.payload.len())
+                .filter(|&len| len == payload_size)
+                .count()
+        });
+        assert_eq!(count, file_len / (8 * 1024));
+        assert!(allocated < file_len * 21 / 10, "allocated {}", allocated);
+
+        // Replaying records read backwards yields them again without a copy.
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Backward).unwrap();
+        let backward = iter.next_back().unwrap();
+        iter.rewind_back();
+        let forward = iter.next().unwrap();
+        assert_eq!(forward.payload.as_ptr(), backward.payload.as_ptr());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_payload_slice() {
+    let payload = Payload::from(vec![1, 2, 3, 4, 5]);
+    let slice = payload.slice(1..4);
+    assert_eq!(slice, vec![2, 3, 4]);
+    assert_eq!(slice.slice(1..3), vec![3, 4]);
+    assert_eq!(slice.as_ptr(), payload[1..].as_ptr());
+}
+
+fn allocations_per_block(path: &str, records_per_block: usize) {
+    create_test_file(path, |_, mut file| {
+        let blocks = 64;
+        let payload_size = BLOCK_SIZE as usize / records_per_block - HEADER_SIZE;
+        let record = Record::new(RecordType::Full, vec![1; payload_size]).unwrap();
+        for _ in 0..blocks * records_per_block {
+            append_to_file(&mut file, &record).unwrap();
+        }
+
+        let (count, allocations) = allocations_during(|| {
+            let iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+            iter.count()
+        });
+        assert_eq!(count, blocks * records_per_block);
+        // One buffer per block, plus the record lists reused across blocks.
+        assert!(allocations <= blocks + 8, "{} allocations", allocations);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_max_size_records_allocate_once_per_block() {
+    allocations_per_block("./files/max_size_record_allocations", 1);
+}
+
+#[test]
+fn test_small_records_allocate_once_per_block() {
+    allocations_per_block("./files/small_record_allocations", 16);
+}
+
+#[test]
+fn test_backward_entry_of_single_byte_fragments() {
+    let entry: String = (0..10_000)
+        .map(|i| (b'a' + (i % 26) as u8) as char)
+        .collect();
+    let mut bytes = Vec::new();
+    entry.serialize(&mut bytes).unwrap();
+    let mut cursor = Cursor::new(Vec::new());
+    for record in split_bytes_into_records(&bytes, 1).unwrap() {
+        append_to_file(&mut cursor, &record).unwrap();
+    }
+
+    let mut iter = WalIterator::new(&mut cursor, ReadDirection::Backward).unwrap();
+    let (read, allocations) =
+        allocations_during(|| read_serializable_backwards::<String>(&mut iter).unwrap());
+    assert_eq!(read, entry);
+    // The fragments are joined once instead of being copied for each record.
+    let blocks = bytes.len() * (HEADER_SIZE + 1) / BLOCK_SIZE as usize + 1;
+    assert!(allocations <= blocks + 64, "{} allocations", allocations);
+}
@@ -0,0 +1,221 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use common::TestStore;
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::iterator::{OnCorruption, ReadDirection, WalIterator};
+use disk_utils::wal::record::{Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{LogOptions, LogStore};
+
+const BLOCK_SIZE: i64 = 256;
+
+fn options(preallocate_blocks: u64) -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        preallocate_blocks,
+        ..LogOptions::default()
+    }
+}
+
+fn read_lsns<S: Read + Seek>(source: &mut S, direction: ReadDirection) -> Vec<u64> {
+    let iter = WalIterator::with_block_size(source, direction, BLOCK_SIZE).unwrap();
+    match direction {
+        ReadDirection::Forward => iter.map(|record| record.lsn).collect(),
+        ReadDirection::Backward => {
+            let mut lsns: Vec<_> = iter.rev().map(|record| record.lsn).collect();
+            lsns.reverse();
+            lsns
+        }
+    }
+}
+
+/// Asserts that the file is preallocated past the end of its records.
+fn assert_zeroed_tail(file: &mut File) {
+    let len = file.metadata().unwrap().len();
+    assert_eq!(len % (4 * BLOCK_SIZE as u64), 0);
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_to_end(&mut bytes).unwrap();
+    assert!(bytes.ends_with(&[0; BLOCK_SIZE as usize]));
+}
+
+#[test]
+fn test_writer_preallocate() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let record_len = (HEADER_SIZE + 100) as u64;
+    let mut writer = Writer::with_block_size(Cursor::new(Vec::new()), BLOCK_SIZE)
+        .unwrap()
+        .preallocate(4)
+        .unwrap();
+    for _ in 0..5 {
+        writer.append(&record).unwrap();
+    }
+    // Two records fit in each block.
+    assert_eq!(writer.position(), 2 * BLOCK_SIZE as u64 + record_len);
+    assert_eq!(writer.file().get_ref().len(), 4 * BLOCK_SIZE as usize);
+
+    let mut cursor = writer.into_inner();
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        assert_eq!(
+            read_lsns(&mut cursor, direction),
+            (1..=5).collect::<Vec<_>>()
+        );
+    }
+
+    // Reopening continues after the last record instead of the zeroed tail.
+    let mut writer = Writer::with_block_size(cursor, BLOCK_SIZE)
+        .unwrap()
+        .preallocate(4)
+        .unwrap();
+    assert_eq!(writer.position(), 2 * BLOCK_SIZE as u64 + record_len);
+    assert_eq!(writer.last_lsn(), 5);
+    for _ in 0..4 {
+        writer.append(&record).unwrap();
+    }
+    assert_eq!(writer.position(), 4 * BLOCK_SIZE as u64 + record_len);
+    assert_eq!(writer.file().get_ref().len(), 8 * BLOCK_SIZE as usize);
+
+    let mut cursor = writer.into_inner();
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        assert_eq!(
+            read_lsns(&mut cursor, direction),
+            (1..=9).collect::<Vec<_>>()
+        );
+    }
+
+    assert!(Writer::new(cursor).unwrap().preallocate(0).is_err());
+}
+
+#[test]
+fn test_redo_log_preallocate() {
+    create_test_file("./files/redo_log_preallocate", |path, mut file| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options(4)).unwrap();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_zeroed_tail(&mut file);
+
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options(4)).unwrap();
+        assert_eq!(store.map().len(), 10);
+        assert_eq!(redo_log.start(), 11);
+        for i in 10..20 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        drop(redo_log);
+        assert_zeroed_tail(&mut file);
+
+        // The records appended after reopening follow the earlier records.
+        let lsns = read_lsns(&mut file, ReadDirection::Forward);
+        assert_eq!(lsns, (1..=lsns.len() as u64).collect::<Vec<_>>());
+        assert_eq!(read_lsns(&mut file, ReadDirection::Backward), lsns);
+
+        // Opening the log without preallocation trims the zeroed tail.
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options(0)).unwrap();
+        assert_eq!(store.map().len(), 20);
+        assert!(file.metadata().unwrap().len() % (4 * BLOCK_SIZE as u64) != 0);
+        let tid = redo_log.start();
+        redo_log.write(tid, 20, "appended".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        crash_log(redo_log, path).unwrap();
+        let store = TestStore::new();
+        RedoLog::new_with_options(path, store.clone(), options(4)).unwrap();
+        assert_eq!(store.get(&20), Some("appended".to_string()));
+        assert_eq!(store.map().len(), 21);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_preallocate() {
+    create_test_file("./files/undo_log_preallocate", |path, mut file| {
+        let mut store = TestStore::new();
+        for i in 0..10 {
+            store.update(i, "o".repeat(30));
+        }
+        let mut undo_log = UndoLog::new_with_options(path, store.clone(), options(4)).unwrap();
+        for i in 0..10 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+
+        // Leave a transaction unfinished at the end of the log.
+        store.set_flush_err(true);
+        let tid = undo_log.start();
+        undo_log.write(tid, 0, "changed".to_string()).unwrap();
+        undo_log.write(tid, 100, "inserted".to_string()).unwrap();
+        assert!(undo_log.commit(tid).is_err());
+        store.set_flush_err(false);
+        drop(undo_log);
+        assert_zeroed_tail(&mut file);
+
+        let mut undo_log = UndoLog::new_with_options(path, store.clone(), options(4)).unwrap();
+        assert_eq!(store.get(&0), Some(format!("value {:026}", 0)));
+        assert_eq!(store.get(&100), None);
+        assert_eq!(undo_log.start(), 12);
+        drop(undo_log);
+
+        let lsns = read_lsns(&mut file, ReadDirection::Forward);
+        assert_eq!(lsns, (1..=lsns.len() as u64).collect::<Vec<_>>());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_unreadable_tail_is_left_for_recovery() {
+    create_test_file("./files/unreadable_tail_redo_log", |path, mut file| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options(0)).unwrap();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        redo_log.close().unwrap();
+        let len = file.metadata().unwrap().len();
+        assert_ne!(len % BLOCK_SIZE as u64, 0);
+
+        // Bytes after padding in the last block can't be read as records.
+        file.write_all(&[0, 0, 0xAB, 0xCD]).unwrap();
+        let strict = LogOptions {
+            on_corruption: OnCorruption::Error,
+            ..options(0)
+        };
+        assert!(RedoLog::new_with_options(path, TestStore::new(), strict).is_err());
+        assert_eq!(file.metadata().unwrap().len(), len + 4);
+
+        let skipping = LogOptions {
+            on_corruption: OnCorruption::SkipToNextBlock,
+            ..options(0)
+        };
+        let recovered = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, recovered.clone(), skipping.clone()).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), len);
+        assert!(recovered.map() == store.map());
+        let tid = redo_log.start();
+        redo_log.write(tid, 10, "appended".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        redo_log.close().unwrap();
+
+        let recovered = TestStore::new();
+        RedoLog::new_with_options(path, recovered.clone(), skipping).unwrap();
+        assert_eq!(recovered.get(&10), Some("appended".to_string()));
+    })
+    .unwrap();
+}
@@ -0,0 +1,86 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs::File;
+
+use common::TestStore;
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, Record};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::LogOptions;
+
+const BLOCK_SIZE: i64 = 256;
+const READAHEAD_BLOCKS: usize = 8;
+
+fn options(readahead_blocks: usize) -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        readahead_blocks,
+        ..LogOptions::default()
+    }
+}
+
+/// Reads every record in the direction, returning the records in the order
+/// they were read and the number of reads issued to the file.
+fn read_records(
+    file: &mut File,
+    direction: ReadDirection,
+    readahead: usize,
+) -> (Vec<Record>, usize) {
+    let format = BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    };
+    let mut iter = WalIterator::with_readahead(file, direction, format, readahead).unwrap();
+    let records: Vec<_> = match direction {
+        ReadDirection::Forward => iter.by_ref().collect(),
+        ReadDirection::Backward => iter.by_ref().rev().collect(),
+    };
+    assert!(iter.corruption().is_none());
+    (records, iter.physical_reads())
+}
+
+#[test]
+fn test_readahead_recovery() {
+    create_test_file("./files/readahead_recovery", |path, mut file| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options(1)).unwrap();
+        for i in 0..200 {
+            let tid = redo_log.start();
+            redo_log
+                .write(tid, i % 50, format!("value {:026}", i))
+                .unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        // Leave a transaction uncommitted at the end of the log.
+        let tid = redo_log.start();
+        redo_log.write(tid, 0, "uncommitted".to_string()).unwrap();
+        drop(redo_log);
+        assert!(file.metadata().unwrap().len() > 50 * BLOCK_SIZE as u64);
+
+        for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+            let (records, reads) = read_records(&mut file, direction, 1);
+            let (readahead_records, readahead_reads) =
+                read_records(&mut file, direction, READAHEAD_BLOCKS);
+            assert!(!records.is_empty());
+            assert_eq!(records, readahead_records);
+            assert!(readahead_reads * READAHEAD_BLOCKS <= reads + READAHEAD_BLOCKS);
+        }
+
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options(1)).unwrap();
+        let tid = redo_log.start();
+        drop(redo_log);
+
+        let readahead_store = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, readahead_store.clone(), options(READAHEAD_BLOCKS))
+                .unwrap();
+        // Dropping the previous log flushed the start of `tid`.
+        assert_eq!(redo_log.start(), tid + 1);
+        assert_eq!(store.map().len(), 50);
+        assert_eq!(store.map(), readahead_store.map());
+    })
+    .unwrap();
+}
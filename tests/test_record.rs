@@ -1,14 +1,16 @@
 extern crate disk_utils;
 
-use std::io::{Seek, SeekFrom};
+use std::cmp;
+use std::io;
+use std::io::{IoSlice, Seek, SeekFrom, Write};
 
 use disk_utils::testing::create_test_file;
-use disk_utils::wal::record::{Record, RecordType};
+use disk_utils::wal::record::{Record, RecordError, RecordType, FORMAT_VERSION, HEADER_SIZE};
 
 #[test]
 fn test_file_read_write() {
     create_test_file("./files/record_test", |_, mut file| {
-        let record = Record::new(RecordType::Full, vec![123; 12345]);
+        let record = Record::new(RecordType::Full, vec![123; 12345]).unwrap();
         record.write(&mut file).unwrap();
         file.seek(SeekFrom::Start(0)).unwrap();
 
@@ -20,7 +22,7 @@ fn test_file_read_write() {
 
 #[test]
 fn test_single_byte_read_write() {
-    let record = Record::new(RecordType::Full, vec![0]);
+    let record = Record::new(RecordType::Full, vec![0]).unwrap();
     let mut bytes = Vec::new();
     record.write(&mut bytes).unwrap();
 
@@ -30,7 +32,7 @@ fn test_single_byte_read_write() {
 
 #[test]
 fn test_corrupted_record() {
-    let record = Record::new(RecordType::Full, vec![123; 12345]);
+    let record = Record::new(RecordType::Full, vec![123; 12345]).unwrap();
     let mut bytes = Vec::new();
     record.write(&mut bytes).unwrap();
 
@@ -44,22 +46,432 @@ fn test_corrupted_record() {
 #[test]
 fn test_read_write_invalid_record() {
     let mut bytes = vec![0; 100];
-    if let Ok(_) = Record::read(&mut &bytes[..]) {
+    if Record::read(&mut &bytes[..]).is_ok() {
         panic!("Reading invalid record padded by zeros should return error");
     }
 
     bytes = vec![0; 1];
-    if let Ok(_) = Record::read(&mut &bytes[..]) {
+    if Record::read(&mut &bytes[..]).is_ok() {
         panic!("Reading invalid record with a single zero should return error");
     }
 
     bytes = vec![1, 2, 3, 4, 5, 6];
-    if let Ok(_) = Record::read(&mut &bytes[..]) {
+    if Record::read(&mut &bytes[..]).is_ok() {
         panic!("Reading invalid record with a smaller header size should return error");
     }
 
     bytes = vec![1, 2, 3, 4, 5, 6, 7, 0];
-    if let Ok(_) = Record::read(&mut &bytes[..]) {
+    if Record::read(&mut &bytes[..]).is_ok() {
         panic!("Reading invalid record with a smaller data size should return error");
     }
 }
+
+#[test]
+fn test_corrupted_header() {
+    let record = Record::new(RecordType::Middle, vec![123; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    for i in 0..HEADER_SIZE {
+        for bit in 0..8 {
+            let mut corrupted = bytes.clone();
+            corrupted[i] ^= 1 << bit;
+            assert!(
+                Record::read(&mut &corrupted[..]).is_err(),
+                "Flipping bit {} of header byte {} was not detected",
+                bit,
+                i
+            );
+        }
+
+        let mut corrupted = bytes.clone();
+        corrupted[i] = !corrupted[i];
+        assert!(Record::read(&mut &corrupted[..]).is_err());
+    }
+}
+
+#[test]
+fn test_format_version() {
+    let record = Record::new(RecordType::Full, vec![1, 2, 3]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+    assert_eq!(bytes[0], FORMAT_VERSION << 4 | RecordType::Full as u8);
+}
+
+#[test]
+fn test_read_older_versions() {
+    // Record written before header checksums: Full, crc32([1, 2, 3]), size 3.
+    let bytes = vec![2, 0x55, 0xBC, 0x80, 0x1D, 0, 3, 1, 2, 3];
+    let record = Record::read(&mut &bytes[..]).unwrap();
+    assert_eq!(record.lsn, 0);
+    assert_eq!(record.record_type, RecordType::Full);
+    assert_eq!(record.payload, vec![1, 2, 3]);
+
+    // Rewriting the record upgrades it to the current format version.
+    let mut new_bytes = Vec::new();
+    record.write(&mut new_bytes).unwrap();
+    assert_eq!(new_bytes[0], FORMAT_VERSION << 4 | RecordType::Full as u8);
+    let test_record = Record::read(&mut &new_bytes[..]).unwrap();
+    assert_eq!(test_record.payload, record.payload);
+
+    // Records with a header checksum but no LSN.
+    let bytes = vec![0x12, 0x47, 0x94, 0xF4, 0xD2, 0, 3, 1, 2, 3];
+    let record = Record::read(&mut &bytes[..]).unwrap();
+    assert_eq!(record.record_type, RecordType::Full);
+    assert_eq!(record.lsn, 0);
+    assert_eq!(record.payload, vec![1, 2, 3]);
+    let mut corrupted = bytes.clone();
+    corrupted[0] = 0x13;
+    assert!(Record::read(&mut &corrupted[..]).is_err());
+
+    // Legacy records still detect payload corruption.
+    let mut corrupted = bytes.clone();
+    corrupted[8] = 5;
+    assert!(Record::read(&mut &corrupted[..]).is_err());
+}
+
+/// Writer that accepts at most a few bytes per call.
+struct ShortWriter {
+    bytes: Vec<u8>,
+    max_write: usize,
+    writes: usize,
+}
+
+impl Write for ShortWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.max_write);
+        self.bytes.extend_from_slice(&buf[..len]);
+        self.writes += 1;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_short_writes() {
+    let record = Record::new(RecordType::Full, vec![123; 100]).unwrap();
+    let mut writer = ShortWriter {
+        bytes: Vec::new(),
+        max_write: 3,
+        writes: 0,
+    };
+    record.write(&mut writer).unwrap();
+
+    assert_eq!(writer.bytes.len(), HEADER_SIZE + 100);
+    assert_eq!(writer.writes, (HEADER_SIZE + 100).div_ceil(3));
+    let test_record = Record::read(&mut &writer.bytes[..]).unwrap();
+    assert_eq!(record, test_record);
+}
+
+/// Writer that records the lengths of the buffers passed to each
+/// vectored write, accepting at most `max_write` bytes per call.
+struct VectoredWriter {
+    bytes: Vec<u8>,
+    max_write: usize,
+    calls: Vec<Vec<usize>>,
+}
+
+impl Write for VectoredWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.calls.push(bufs.iter().map(|buf| buf.len()).collect());
+        let mut written = 0;
+        for buf in bufs {
+            let len = cmp::min(buf.len(), self.max_write - written);
+            self.bytes.extend_from_slice(&buf[..len]);
+            written += len;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_vectored() {
+    let record = Record::new(RecordType::Full, vec![123; 100]).unwrap();
+    let mut writer = VectoredWriter {
+        bytes: Vec::new(),
+        max_write: usize::MAX,
+        calls: Vec::new(),
+    };
+    record.write(&mut writer).unwrap();
+
+    // The header and payload are submitted in a single call.
+    assert_eq!(writer.calls, vec![vec![HEADER_SIZE, 100]]);
+    assert_eq!(Record::read(&mut &writer.bytes[..]).unwrap(), record);
+}
+
+#[test]
+fn test_write_vectored_partial_writes() {
+    let record = Record::new(RecordType::Full, vec![123; 100]).unwrap();
+    let mut writer = VectoredWriter {
+        bytes: Vec::new(),
+        max_write: 40,
+        calls: Vec::new(),
+    };
+    record.write(&mut writer).unwrap();
+
+    assert_eq!(
+        writer.calls,
+        vec![
+            vec![HEADER_SIZE, 100],
+            vec![0, 100 - (40 - HEADER_SIZE)],
+            vec![0, 100 - (80 - HEADER_SIZE)],
+        ]
+    );
+    assert_eq!(Record::read(&mut &writer.bytes[..]).unwrap(), record);
+
+    // A write that only finishes part of the header continues with the rest of it.
+    let mut writer = VectoredWriter {
+        bytes: Vec::new(),
+        max_write: 10,
+        calls: Vec::new(),
+    };
+    record.write(&mut writer).unwrap();
+    assert_eq!(writer.calls[1], vec![HEADER_SIZE - 10, 100]);
+    assert_eq!(Record::read(&mut &writer.bytes[..]).unwrap(), record);
+}
+
+#[test]
+fn test_write_vectored_matches_sequential_writes() {
+    for &len in &[0, 1, 100, 5000] {
+        let record = Record::new(RecordType::Middle, vec![7; len]).unwrap();
+        let mut vectored = Vec::new();
+        record.write(&mut vectored).unwrap();
+
+        // `ShortWriter` only implements `write`, so the buffers are
+        // written one after the other.
+        let mut sequential = ShortWriter {
+            bytes: Vec::new(),
+            max_write: usize::MAX,
+            writes: 0,
+        };
+        record.write(&mut sequential).unwrap();
+        assert_eq!(sequential.writes, if len == 0 { 1 } else { 2 });
+        assert_eq!(vectored, sequential.bytes);
+    }
+}
+
+#[test]
+fn test_write_bytes() {
+    let record = Record::new(RecordType::Last, vec![1, 2, 3]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    let crc = record.crc.to_be_bytes();
+    let expected = vec![
+        FORMAT_VERSION << 4 | RecordType::Last as u8,
+        crc[0],
+        crc[1],
+        crc[2],
+        crc[3],
+        0,
+        3,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        1,
+        2,
+        3,
+    ];
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_oversized_payload() {
+    assert!(Record::new(RecordType::Full, vec![0; 70_000]).is_err());
+    assert!(Record::new(RecordType::Full, vec![0; u16::MAX as usize + 1]).is_err());
+
+    let record = Record::new(RecordType::Full, vec![0; u16::MAX as usize]).unwrap();
+    assert_eq!(record.size, u16::MAX);
+}
+
+#[test]
+fn test_unknown_version() {
+    let record = Record::new(RecordType::Full, vec![1, 2, 3]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+    bytes[0] = (FORMAT_VERSION + 1) << 4 | RecordType::Full as u8;
+
+    let err = Record::read(&mut &bytes[..]).unwrap_err();
+    let record_err = err.get_ref().unwrap().downcast_ref::<RecordError>();
+    assert_eq!(
+        record_err,
+        Some(&RecordError::UnknownVersion(FORMAT_VERSION + 1))
+    );
+}
+
+#[test]
+fn test_size_larger_than_limit() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    let err = Record::read_with_limit(&mut &bytes[..], HEADER_SIZE + 99).unwrap_err();
+    let record_err = err.get_ref().unwrap().downcast_ref::<RecordError>();
+    assert_eq!(
+        record_err,
+        Some(&RecordError::SizeTooLarge {
+            size: 100,
+            remaining: 99
+        })
+    );
+
+    let test_record = Record::read_with_limit(&mut &bytes[..], HEADER_SIZE + 100).unwrap();
+    assert_eq!(test_record, record);
+}
+
+fn read_record_error(bytes: &[u8]) -> RecordError {
+    let err = Record::read(&mut &bytes[..]).unwrap_err();
+    let record_err = RecordError::from_io_error(&err).unwrap();
+    if record_err.is_truncation() {
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    } else {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    record_err.clone()
+}
+
+#[test]
+fn test_header_cut_off() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    assert_eq!(read_record_error(&[]), RecordError::Eof);
+    for len in 1..HEADER_SIZE {
+        assert_eq!(
+            read_record_error(&bytes[..len]),
+            RecordError::IncompleteHeader { consumed: len }
+        );
+    }
+}
+
+#[test]
+fn test_payload_cut_off() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    for &payload_len in &[0, 1, 50, 99] {
+        assert_eq!(
+            read_record_error(&bytes[..HEADER_SIZE + payload_len]),
+            RecordError::IncompletePayload {
+                size: 100,
+                consumed: HEADER_SIZE + payload_len
+            }
+        );
+    }
+}
+
+#[test]
+fn test_bad_type_and_checksum() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    let mut corrupted = bytes.clone();
+    corrupted[0] = FORMAT_VERSION << 4 | 7;
+    assert_eq!(
+        read_record_error(&corrupted),
+        RecordError::BadType(corrupted[0])
+    );
+
+    // The actual checksum is the checksum of a record with the corrupted payload.
+    let mut corrupted = bytes.clone();
+    corrupted[HEADER_SIZE + 10] ^= 1;
+    let mut payload = vec![1; 100];
+    payload[10] ^= 1;
+    let actual = Record::new(RecordType::Full, payload).unwrap().crc;
+    assert_eq!(
+        read_record_error(&corrupted),
+        RecordError::BadChecksum {
+            consumed: bytes.len(),
+            expected: record.crc,
+            actual,
+        }
+    );
+}
+
+#[test]
+fn test_parse_consumed() {
+    let records = [
+        Record::new(RecordType::Full, vec![1; 100]).unwrap(),
+        Record::new(RecordType::First, vec![]).unwrap(),
+        Record::new(RecordType::Last, vec![2; 12345]).unwrap(),
+    ];
+    let mut bytes = Vec::new();
+    for record in records.iter() {
+        record.write(&mut bytes).unwrap();
+    }
+    // A version 1 record with a 7 byte header.
+    bytes.extend_from_slice(&[0x12, 0x47, 0x94, 0xF4, 0xD2, 0, 3, 1, 2, 3]);
+
+    let mut rest = &bytes[..];
+    for record in records.iter() {
+        let (view, consumed) = Record::parse(rest).unwrap();
+        assert_eq!(consumed, HEADER_SIZE + record.payload.len());
+        assert_eq!(view.to_record(), *record);
+        assert_eq!(view.payload.as_ptr(), rest[HEADER_SIZE..].as_ptr());
+        rest = &rest[consumed..];
+    }
+    let (view, consumed) = Record::parse(rest).unwrap();
+    assert_eq!(consumed, 10);
+    assert_eq!(view.payload, &[1, 2, 3]);
+    assert_eq!(view.to_record(), Record::read(&mut &rest[..]).unwrap());
+    rest = &rest[consumed..];
+
+    let err = Record::parse(rest).unwrap_err();
+    assert_eq!(RecordError::from_io_error(&err), Some(&RecordError::Eof));
+}
+
+#[test]
+fn test_parse_errors_match_read() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut bytes = Vec::new();
+    record.write(&mut bytes).unwrap();
+
+    for len in 0..bytes.len() {
+        let err = Record::parse(&bytes[..len]).unwrap_err();
+        assert_eq!(
+            RecordError::from_io_error(&err).cloned(),
+            Some(read_record_error(&bytes[..len]))
+        );
+    }
+
+    let mut corrupted = bytes.clone();
+    corrupted[HEADER_SIZE] ^= 1;
+    let err = Record::parse(&corrupted).unwrap_err();
+    assert_eq!(
+        RecordError::from_io_error(&err).cloned(),
+        Some(read_record_error(&corrupted))
+    );
+    assert!(matches!(
+        RecordError::from_io_error(&err),
+        Some(&RecordError::BadChecksum { consumed, expected, .. })
+            if consumed == bytes.len() && expected == record.crc
+    ));
+
+    let err = Record::parse_with_limit(&bytes, HEADER_SIZE + 10).unwrap_err();
+    assert_eq!(
+        RecordError::from_io_error(&err),
+        Some(&RecordError::SizeTooLarge {
+            size: 100,
+            remaining: 10
+        })
+    );
+}
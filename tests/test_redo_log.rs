@@ -1,14 +1,25 @@
 extern crate disk_utils;
 
 use std::collections::HashMap;
+use std::fs;
 use std::io;
+use std::io::{Read, Write};
+use std::mem;
 use std::sync::{Arc, RwLock};
 
-use disk_utils::testing::create_test_file;
+use disk_utils::testing::{
+    crash_log, create_test_file, create_two_test_files, FaultPolicy, FaultyFile, InMemoryBackend,
+};
 use disk_utils::wal::entries::{ChangeEntry, Checkpoint, SingleLogEntry, Transaction};
 use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::BlockFormat;
 use disk_utils::wal::redo_log::RedoLog;
-use disk_utils::wal::{read_serializable, LogData, LogStore};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{
+    CheckpointPolicy, LogData, LogError, LogOptions, LogStore, Outcome, RecoveryProgress,
+    WriteAheadPolicy,
+};
+use disk_utils::Serializable;
 
 #[derive(Clone, PartialEq, Debug)]
 struct MyLogData;
@@ -23,6 +34,7 @@ struct MyStore<Data: LogData> {
     data: Arc<RwLock<HashMap<Data::Key, Data::Value>>>,
     flushed_data: Arc<RwLock<HashMap<Data::Key, Data::Value>>>,
     flush_err: Arc<RwLock<bool>>,
+    flush_change_calls: Arc<RwLock<usize>>,
 }
 
 impl<Data> MyStore<Data>
@@ -34,6 +46,7 @@ where
             data: Arc::new(RwLock::new(HashMap::new())),
             flushed_data: Arc::new(RwLock::new(HashMap::new())),
             flush_err: Arc::new(RwLock::new(false)),
+            flush_change_calls: Arc::new(RwLock::new(0)),
         }
     }
 
@@ -45,6 +58,10 @@ where
         self.flushed_data.read().unwrap().get(key).cloned()
     }
 
+    pub fn flush_change_calls(&self) -> usize {
+        *self.flush_change_calls.read().unwrap()
+    }
+
     pub fn discard_changes(&mut self) {
         *self.data.write().unwrap() = self.flushed_data.read().unwrap().clone();
     }
@@ -79,6 +96,7 @@ where
     }
 
     fn flush_change(&mut self, key: Data::Key, val: Data::Value) -> io::Result<()> {
+        *self.flush_change_calls.write().unwrap() += 1;
         self.flushed_data.write().unwrap().insert(key, val);
         if *self.flush_err.read().unwrap() {
             Err(io::Error::new(
@@ -101,7 +119,7 @@ fn test_start() {
         assert_eq!(tid, 1);
         assert_eq!(redo_log.entries().len(), 1);
         assert_eq!(
-            redo_log.entries()[0],
+            *redo_log.entries().next().unwrap(),
             SingleLogEntry::Transaction(Transaction::Start(1))
         );
     })
@@ -117,11 +135,11 @@ fn test_write() {
         let tid = redo_log.start();
         assert_eq!(tid, 1);
 
-        redo_log.write(tid, 20, "Hello".to_string());
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
 
         assert_eq!(redo_log.entries().len(), 2);
         assert_eq!(
-            redo_log.entries()[1],
+            *redo_log.entries().nth(1).unwrap(),
             SingleLogEntry::ChangeEntry(ChangeEntry {
                 tid: 1,
                 key: 20,
@@ -129,11 +147,11 @@ fn test_write() {
             })
         );
 
-        redo_log.write(tid, 20, "World".to_string());
+        redo_log.write(tid, 20, "World".to_string()).unwrap();
 
         assert_eq!(redo_log.entries().len(), 3);
         assert_eq!(
-            redo_log.entries()[2],
+            *redo_log.entries().nth(2).unwrap(),
             SingleLogEntry::ChangeEntry(ChangeEntry {
                 tid: 1,
                 key: 20,
@@ -151,8 +169,8 @@ fn test_commit() {
         let mut redo_log = RedoLog::new(path, store).unwrap();
         let tid = redo_log.start();
         assert_eq!(tid, 1);
-        redo_log.write(tid, 20, "Hello".to_string());
-        redo_log.write(tid, 20, "World".to_string());
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid, 20, "World".to_string()).unwrap();
         redo_log.commit(tid).unwrap();
 
         let mut expected_entries = vec![
@@ -170,9 +188,11 @@ fn test_commit() {
             SingleLogEntry::Transaction(Transaction::Commit(1)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
     })
     .unwrap();
@@ -185,16 +205,17 @@ fn test_recover() {
 
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
         let tid = redo_log.start();
-        redo_log.write(tid, 20, "Hello".to_string());
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
         redo_log.commit(tid).unwrap();
 
         let tid = redo_log.start();
-        redo_log.write(tid, 20, "World".to_string());
-        redo_log.write(tid, 30, "Hello".to_string());
+        redo_log.write(tid, 20, "World".to_string()).unwrap();
+        redo_log.write(tid, 30, "Hello".to_string()).unwrap();
 
         let tid = redo_log.start();
         redo_log.commit(tid).unwrap();
 
+        crash_log(redo_log, path).unwrap();
         store.discard_changes();
         // Create a new redo log which should automatically recover data.
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
@@ -224,9 +245,11 @@ fn test_recover() {
             SingleLogEntry::Transaction(Transaction::Abort(2)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
 
         assert_eq!(store.get_flushed(&20), Some("Hello".to_string()));
@@ -237,30 +260,31 @@ fn test_recover() {
 
 #[test]
 fn test_multiple_recover() {
-    create_test_file("./files/multiple_recover_redo_log", |path, mut file| {
+    create_test_file("./files/multiple_recover_redo_log", |path, _| {
         let mut store: MyStore<MyLogData> = MyStore::new();
 
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
         let tid1 = redo_log.start();
         let tid2 = redo_log.start();
-        redo_log.write(tid1, 20, "Hello".to_string());
-        redo_log.write(tid2, 30, "World".to_string());
-        redo_log.write(tid1, 30, "Blah".to_string());
+        redo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid2, 30, "World".to_string()).unwrap();
+        redo_log.write(tid1, 30, "Blah".to_string()).unwrap();
         redo_log.commit(tid1).unwrap();
-        redo_log.write(tid2, 20, "World".to_string());
+        redo_log.write(tid2, 20, "World".to_string()).unwrap();
         redo_log.commit(tid2).unwrap();
 
         let tid3 = redo_log.start();
         let tid4 = redo_log.start();
 
-        redo_log.write(tid3, 40, "Foo".to_string());
-        redo_log.write(tid4, 30, "Bar".to_string());
+        redo_log.write(tid3, 40, "Foo".to_string()).unwrap();
+        redo_log.write(tid4, 30, "Bar".to_string()).unwrap();
         redo_log.commit(tid3).unwrap();
 
-        redo_log.write(tid4, 50, "Hello".to_string());
+        redo_log.write(tid4, 50, "Hello".to_string()).unwrap();
 
         store.discard_changes();
 
+        let mut file = crash_log(redo_log, path).unwrap();
         // Create a new redo log which should automatically recover data.
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
         assert_eq!(redo_log.start(), 5);
@@ -306,9 +330,11 @@ fn test_multiple_recover() {
             SingleLogEntry::Transaction(Transaction::Abort(4)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
 
         // Test expected state after recovery:
@@ -336,6 +362,7 @@ fn test_add_end_checkpoint() {
         redo_log.commit(tid4).unwrap();
         redo_log.commit(tid2).unwrap();
 
+        crash_log(redo_log, path).unwrap();
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
         assert_eq!(redo_log.start(), 5);
 
@@ -352,8 +379,11 @@ fn test_add_end_checkpoint() {
             SingleLogEntry::Transaction(Transaction::Commit(2)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            let data = data.unwrap();
             if let SingleLogEntry::Checkpoint(Checkpoint::Begin(mut data)) = data {
                 data.sort();
                 assert_eq!(
@@ -377,10 +407,10 @@ fn test_checkpoint_recover_after_end() {
         let tid1 = redo_log.start();
         let tid2 = redo_log.start();
 
-        redo_log.write(tid1, 20, "Hello".to_string());
-        redo_log.write(tid2, 20, "World".to_string());
-        redo_log.write(tid2, 30, "Blah".to_string());
-        redo_log.write(tid1, 30, "Foo".to_string());
+        redo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid2, 20, "World".to_string()).unwrap();
+        redo_log.write(tid2, 30, "Blah".to_string()).unwrap();
+        redo_log.write(tid1, 30, "Foo".to_string()).unwrap();
 
         redo_log.commit(tid1).unwrap();
         redo_log.commit(tid2).unwrap();
@@ -389,10 +419,10 @@ fn test_checkpoint_recover_after_end() {
         let tid4 = redo_log.start();
         let tid5 = redo_log.start();
 
-        redo_log.write(tid3, 20, "A".to_string());
-        redo_log.write(tid5, 30, "B".to_string());
-        redo_log.write(tid4, 30, "C".to_string());
-        redo_log.write(tid4, 50, "D".to_string());
+        redo_log.write(tid3, 20, "A".to_string()).unwrap();
+        redo_log.write(tid5, 30, "B".to_string()).unwrap();
+        redo_log.write(tid4, 30, "C".to_string()).unwrap();
+        redo_log.write(tid4, 50, "D".to_string()).unwrap();
 
         redo_log.checkpoint().unwrap();
         redo_log.commit(tid4).unwrap();
@@ -401,6 +431,7 @@ fn test_checkpoint_recover_after_end() {
 
         store.discard_changes();
 
+        crash_log(redo_log, path).unwrap();
         // Create a new redo log which should automatically recover data.
         let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
         assert_eq!(redo_log.start(), 6);
@@ -422,10 +453,10 @@ fn test_checkpoint_flushed_changes() {
         let tid1 = redo_log.start();
         let tid2 = redo_log.start();
 
-        redo_log.write(tid1, 20, "Hello".to_string());
-        redo_log.write(tid2, 30, "World".to_string());
-        redo_log.write(tid2, 20, "World".to_string());
-        redo_log.write(tid1, 30, "Hello".to_string());
+        redo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid2, 30, "World".to_string()).unwrap();
+        redo_log.write(tid2, 20, "World".to_string()).unwrap();
+        redo_log.write(tid1, 30, "Hello".to_string()).unwrap();
 
         redo_log.commit(tid2).unwrap();
         // Should  flush (20 -> "World") and (30 -> "World") to disk.
@@ -433,14 +464,15 @@ fn test_checkpoint_flushed_changes() {
         assert_eq!(store.get_flushed(&20), Some("World".to_string()));
         assert_eq!(store.get_flushed(&30), Some("World".to_string()));
 
-        redo_log.write(tid1, 40, "New key".to_string());
+        redo_log.write(tid1, 40, "New key".to_string()).unwrap();
 
         let tid3 = redo_log.start();
         let tid4 = redo_log.start();
-        redo_log.write(tid3, 50, "New key".to_string());
-        redo_log.write(tid4, 50, "New new key".to_string());
+        redo_log.write(tid3, 50, "New key".to_string()).unwrap();
+        redo_log.write(tid4, 50, "New new key".to_string()).unwrap();
         redo_log.commit(tid3).unwrap();
 
+        crash_log(redo_log, path).unwrap();
         store.discard_changes();
         // Create a new redo log which should automatically recover data.
         let _ = RedoLog::new(path, store.clone()).unwrap();
@@ -451,3 +483,1469 @@ fn test_checkpoint_flushed_changes() {
     })
     .unwrap();
 }
+
+#[test]
+fn test_transaction_entries() {
+    create_test_file("./files/transaction_entries_redo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tids: Vec<_> = (0..3).map(|_| redo_log.start()).collect();
+        for i in 0..3 {
+            for &tid in tids.iter() {
+                redo_log
+                    .write(tid, tid as i32 * 10 + i, format!("{} {}", tid, i))
+                    .unwrap();
+            }
+            if i == 0 {
+                redo_log.checkpoint().unwrap();
+            }
+        }
+        redo_log.commit(tids[0]).unwrap();
+        redo_log.commit(tids[2]).unwrap();
+
+        for &tid in tids.iter() {
+            let mut expected = vec![SingleLogEntry::Transaction(Transaction::Start(tid))];
+            for i in 0..3 {
+                expected.push(SingleLogEntry::ChangeEntry(ChangeEntry {
+                    tid,
+                    key: tid as i32 * 10 + i,
+                    value: format!("{} {}", tid, i),
+                }));
+            }
+            if tid != tids[1] {
+                expected.push(SingleLogEntry::Transaction(Transaction::Commit(tid)));
+            }
+
+            let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+                .unwrap()
+                .entries::<SingleLogEntry<MyLogData>>()
+                .for_transaction(tid)
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(entries, expected);
+
+            let mut entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Backward)
+                .unwrap()
+                .entries::<SingleLogEntry<MyLogData>>()
+                .for_transaction(tid)
+                .rev()
+                .map(Result::unwrap)
+                .collect();
+            entries.reverse();
+            assert_eq!(entries, expected);
+
+            // The begin and end checkpoints come after the first change.
+            let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+                .unwrap()
+                .entries::<SingleLogEntry<MyLogData>>()
+                .for_transaction(tid)
+                .with_checkpoints()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(entries.len(), expected.len() + 2);
+            assert!(matches!(
+                entries[2],
+                SingleLogEntry::Checkpoint(Checkpoint::Begin(_))
+            ));
+            assert_eq!(entries[3], SingleLogEntry::Checkpoint(Checkpoint::End));
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_replay_transaction() {
+    create_test_file("./files/replay_transaction_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 10, "Hello".to_string()).unwrap();
+        redo_log.write(tid2, 20, "World".to_string()).unwrap();
+        redo_log.write(tid1, 11, "Foo".to_string()).unwrap();
+        redo_log.commit(tid1).unwrap();
+
+        crash_log(redo_log, path).unwrap();
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        store.data.write().unwrap().clear();
+
+        assert!(!redo_log.replay_transaction(tid2).unwrap());
+        assert_eq!(store.get(&20), None);
+
+        assert!(redo_log.replay_transaction(tid1).unwrap());
+        assert_eq!(store.get(&10), Some("Hello".to_string()));
+        assert_eq!(store.get(&11), Some("Foo".to_string()));
+        assert_eq!(store.get(&20), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_progress() {
+    create_test_file("./files/recover_progress_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        for i in 0..30 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "Hello".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+
+        let fractions = Arc::new(RwLock::new(Vec::new()));
+        let reported = fractions.clone();
+        let options = LogOptions {
+            recovery_progress: Some(RecoveryProgress::new(move |fraction| {
+                reported.write().unwrap().push(fraction);
+            })),
+            ..LogOptions::default()
+        };
+        crash_log(redo_log, path).unwrap();
+        let store: MyStore<MyLogData> = MyStore::new();
+        let redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        assert_eq!(store.data.read().unwrap().len(), 30);
+
+        // The second pass reads the records of the first pass again
+        // without loading the block again.
+        let stats = redo_log.recovery_stats();
+        assert_eq!(stats.records_read, 2 * 90);
+        assert_eq!(stats.blocks_loaded, 1);
+
+        // Each pass reads the 90 entries in the log.
+        let fractions = fractions.read().unwrap();
+        assert_eq!(fractions.len(), 2 * 90 + 1);
+        assert!(fractions.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(fractions[0] > 0.0);
+        assert_eq!(fractions[89], 0.5);
+        assert_eq!(*fractions.last().unwrap(), 1.0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_concurrent_readers() {
+    create_test_file("./files/concurrent_readers_redo_log", |path, _| {
+        let options = LogOptions {
+            block_size: 256,
+            ..LogOptions::default()
+        };
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store, options).unwrap();
+        let write_transactions = |redo_log: &mut RedoLog<MyLogData, MyStore<MyLogData>>, range| {
+            for i in range {
+                let tid = redo_log.start();
+                redo_log.write(tid, i, format!("value {:026}", i)).unwrap();
+                redo_log.commit(tid).unwrap();
+            }
+        };
+        write_transactions(&mut redo_log, 0..3);
+
+        let first_reader = redo_log.reader(ReadDirection::Forward).unwrap();
+        let second_reader = redo_log.reader(ReadDirection::Backward).unwrap();
+
+        // Flush enough entries to pad the end of the first blocks.
+        write_transactions(&mut redo_log, 3..10);
+        let third_reader = redo_log.reader(ReadDirection::Forward).unwrap();
+
+        // Readers only see the records flushed before they were created.
+        let first: Vec<_> = first_reader
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(Result::unwrap)
+            .collect();
+        let mut second: Vec<_> = second_reader
+            .entries::<SingleLogEntry<MyLogData>>()
+            .rev()
+            .map(Result::unwrap)
+            .collect();
+        second.reverse();
+        assert_eq!(first.len(), 9);
+        assert_eq!(first, second);
+
+        let mut third = third_reader.entries::<SingleLogEntry<MyLogData>>();
+        let all: Vec<_> = third.by_ref().map(Result::unwrap).collect();
+        assert_eq!(all.len(), 30);
+        assert_eq!(all[..9], first[..]);
+        assert!(third.get_mut().corruption().is_none());
+        assert!(third.get_mut().physical_reads() > 2);
+
+        // The log keeps appending after the readers read the file.
+        write_transactions(&mut redo_log, 10..11);
+        let reader = redo_log.reader(ReadDirection::Forward).unwrap();
+        assert_eq!(reader.entries::<SingleLogEntry<MyLogData>>().count(), 33);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_last_flushed_offset() {
+    create_test_file("./files/redo_log_last_flushed_offset", |path, mut file| {
+        let options = LogOptions {
+            block_size: 256,
+            ..LogOptions::default()
+        };
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store, options).unwrap();
+        assert_eq!(redo_log.last_flushed_offset(), None);
+
+        let mut offsets = Vec::new();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "a".repeat(i as usize * 30)).unwrap();
+            redo_log.commit(tid).unwrap();
+            offsets.push((tid, redo_log.last_flushed_offset().unwrap()));
+        }
+        assert!(offsets.last().unwrap().1 > 256);
+
+        // The commit is the last entry flushed by each commit.
+        for (tid, offset) in offsets {
+            let mut iter =
+                WalIterator::with_block_size(&mut file, ReadDirection::Forward, 256).unwrap();
+            iter.seek(offset).unwrap();
+            let entry = iter.entries::<SingleLogEntry<MyLogData>>().next();
+            assert_eq!(
+                entry.unwrap().unwrap(),
+                SingleLogEntry::Transaction(Transaction::Commit(tid))
+            );
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_seal_checkpoints() {
+    create_test_file("./files/seal_checkpoints_redo_log", |path, mut file| {
+        let options = LogOptions {
+            block_size: 256,
+            seal_checkpoints: true,
+            ..LogOptions::default()
+        };
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store, options.clone()).unwrap();
+        for i in 0..3 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "Hello".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+            redo_log.checkpoint().unwrap();
+            assert_eq!(file.metadata().unwrap().len() % 256, 0);
+        }
+        let tid = redo_log.start();
+        redo_log.write(tid, 3, "Hello".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        drop(redo_log);
+
+        // The padding after each checkpoint is skipped when reading the log.
+        let entries: Vec<SingleLogEntry<MyLogData>> =
+            WalIterator::with_block_size(&mut file, ReadDirection::Forward, 256)
+                .unwrap()
+                .entries()
+                .map(Result::unwrap)
+                .collect();
+        let ends = entries
+            .iter()
+            .filter(|entry| **entry == SingleLogEntry::Checkpoint(Checkpoint::End))
+            .count();
+        assert_eq!(ends, 3);
+        assert_eq!(entries.len(), 4 * 3 + 3 * 2);
+
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        // Only the transaction after the last checkpoint is replayed.
+        assert_eq!(store.data.read().unwrap().len(), 1);
+        assert_eq!(store.get(&3), Some("Hello".to_string()));
+        assert_eq!(redo_log.start(), 5);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_abort() {
+    create_test_file("./files/abort_redo_log", |path, mut file| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid2, 30, "World".to_string()).unwrap();
+        redo_log.write(tid2, 40, "World".to_string()).unwrap();
+        redo_log.write(tid1, 40, "Hello".to_string()).unwrap();
+
+        redo_log.abort(tid2).unwrap();
+        // Writes to the aborted transaction are rejected.
+        assert!(redo_log.write(tid2, 50, "Ignored".to_string()).is_err());
+        redo_log.commit(tid1).unwrap();
+
+        // The checkpoint only flushes the committed transaction's changes.
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.get_flushed(&20), Some("Hello".to_string()));
+        assert_eq!(store.get_flushed(&30), None);
+        assert_eq!(store.get_flushed(&40), Some("Hello".to_string()));
+        assert_eq!(store.get_flushed(&50), None);
+
+        let aborted: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(Result::unwrap)
+            .filter(|entry| entry.tid() == Some(tid2))
+            .collect();
+        assert_eq!(
+            aborted.last(),
+            Some(&SingleLogEntry::Transaction(Transaction::Abort(tid2)))
+        );
+        assert_eq!(aborted.len(), 4);
+
+        crash_log(redo_log, path).unwrap();
+        // Simulate a crash that loses the store's unflushed changes.
+        store.discard_changes();
+        RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&20), Some("Hello".to_string()));
+        assert_eq!(store.get(&30), None);
+        assert_eq!(store.get(&40), Some("Hello".to_string()));
+        assert_eq!(store.get(&50), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_inactive_transaction() {
+    create_test_file("./files/inactive_transaction_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 10, "A".to_string()).unwrap();
+        redo_log.commit(tid1).unwrap();
+        redo_log.abort(tid2).unwrap();
+
+        // Write after commit.
+        match redo_log.write(tid1, 20, "B".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) => assert_eq!(tid, tid1),
+            result => panic!("Expected unknown transaction, got {:?}", result),
+        }
+        // Write after abort.
+        match redo_log.write(tid2, 20, "B".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) => assert_eq!(tid, tid2),
+            result => panic!("Expected unknown transaction, got {:?}", result),
+        }
+        // Commit twice, and a tid that was never started.
+        assert!(matches!(
+            redo_log.commit(tid1),
+            Err(LogError::UnknownTransaction(_))
+        ));
+        assert!(matches!(
+            redo_log.commit(100),
+            Err(LogError::UnknownTransaction(100))
+        ));
+        assert!(matches!(
+            redo_log.abort(tid2),
+            Err(LogError::UnknownTransaction(_))
+        ));
+        assert_eq!(store.get(&20), None);
+        assert_eq!(redo_log.pending_entries(), 0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_read_your_writes() {
+    create_test_file("./files/read_your_writes_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let options = LogOptions {
+            defer_store_updates: true,
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 10, "A".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 10, "B".to_string()).unwrap();
+        redo_log.write(tid1, 20, "C".to_string()).unwrap();
+        redo_log.write(tid1, 10, "D".to_string()).unwrap();
+
+        // A reader of the store only sees committed values.
+        let reader = store.clone();
+        assert_eq!(reader.get(&10), Some("A".to_string()));
+        assert_eq!(reader.get(&20), None);
+
+        // The transaction sees its own writes, other transactions don't.
+        assert_eq!(redo_log.get(tid1, &10), Some("D".to_string()));
+        assert_eq!(redo_log.get(tid1, &20), Some("C".to_string()));
+        assert_eq!(redo_log.get(tid2, &10), Some("A".to_string()));
+        assert_eq!(redo_log.get(tid2, &20), None);
+
+        redo_log.commit(tid1).unwrap();
+        assert_eq!(reader.get(&10), Some("D".to_string()));
+        assert_eq!(reader.get(&20), Some("C".to_string()));
+
+        // Aborted changes never reach the store.
+        redo_log.write(tid2, 30, "E".to_string()).unwrap();
+        assert_eq!(redo_log.get(tid2, &30), Some("E".to_string()));
+        redo_log.abort(tid2).unwrap();
+        assert_eq!(reader.get(&30), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_policy() {
+    create_test_file("./files/checkpoint_policy_redo_log", |path, mut file| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let options = LogOptions {
+            checkpoint: CheckpointPolicy::EveryNCommits(3),
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options.clone()).unwrap();
+        for i in 1..=7 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+
+        let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(Result::unwrap)
+            .filter(|entry| {
+                matches!(
+                    entry,
+                    SingleLogEntry::Transaction(Transaction::Commit(_))
+                        | SingleLogEntry::Checkpoint(_)
+                )
+            })
+            .collect();
+        let commit = |tid| SingleLogEntry::Transaction(Transaction::Commit(tid));
+        let begin = SingleLogEntry::Checkpoint(Checkpoint::Begin(vec![]));
+        let end = SingleLogEntry::Checkpoint(Checkpoint::End);
+        assert_eq!(
+            entries,
+            vec![
+                commit(1),
+                commit(2),
+                commit(3),
+                begin.clone(),
+                end.clone(),
+                commit(4),
+                commit(5),
+                commit(6),
+                begin,
+                end,
+                commit(7),
+            ]
+        );
+        for i in 1..=6 {
+            assert_eq!(store.get_flushed(&i), Some(format!("value {}", i)));
+        }
+        assert_eq!(store.get_flushed(&7), None);
+
+        crash_log(redo_log, path).unwrap();
+        // Recovery only replays the transaction after the last checkpoint.
+        store.discard_changes();
+        let redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        for i in 1..=7 {
+            assert_eq!(store.get(&i), Some(format!("value {}", i)));
+        }
+        // Both passes read the last transaction and the checkpoint.
+        assert_eq!(redo_log.recovery_stats().records_read, 10);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_accessors() {
+    create_test_file("./files/accessors_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store).unwrap();
+        assert_eq!(redo_log.last_tid(), 0);
+        assert!(redo_log.active_transactions().is_empty());
+
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        assert_eq!(redo_log.last_tid(), tid2);
+        assert_eq!(redo_log.active_transactions(), vec![tid1, tid2]);
+        assert_eq!(redo_log.pending_entries(), 2);
+        let start_bytes = redo_log.pending_bytes();
+
+        redo_log.write(tid2, 10, "value".to_string()).unwrap();
+        let entries: Vec<_> = redo_log.entries().collect();
+        assert_eq!(redo_log.pending_entries(), 3);
+        assert_eq!(
+            redo_log.pending_bytes(),
+            entries
+                .iter()
+                .map(|entry| entry.serialized_size().unwrap())
+                .sum::<u64>()
+        );
+        assert!(redo_log.pending_bytes() > start_bytes);
+
+        redo_log.commit(tid2).unwrap();
+        assert_eq!(redo_log.active_transactions(), vec![tid1]);
+        assert_eq!(redo_log.pending_entries(), 0);
+        assert_eq!(redo_log.pending_bytes(), 0);
+
+        redo_log.write(tid1, 20, "value".to_string()).unwrap();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(redo_log.active_transactions(), vec![tid1]);
+        assert_eq!(redo_log.pending_entries(), 0);
+        assert_eq!(redo_log.last_tid(), tid2);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_write_batch() {
+    create_two_test_files(
+        "./files/write_batch_single_redo_log",
+        "./files/write_batch_redo_log",
+        |single_path, batch_path, _, _| {
+            let changes: Vec<_> = (0..1000).map(|i| (i, format!("value {}", i))).collect();
+            let mut recovered = Vec::new();
+            for (path, batched) in [(single_path, false), (batch_path, true)] {
+                let mut store: MyStore<MyLogData> = MyStore::new();
+                let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+                let tid = redo_log.start();
+                let uncommitted = redo_log.start();
+                if batched {
+                    redo_log.write_batch(tid, changes.clone()).unwrap();
+                    redo_log
+                        .write_batch(uncommitted, vec![(0, "uncommitted".to_string())])
+                        .unwrap();
+                } else {
+                    for (key, val) in changes.clone() {
+                        redo_log.write(tid, key, val).unwrap();
+                    }
+                    redo_log
+                        .write(uncommitted, 0, "uncommitted".to_string())
+                        .unwrap();
+                }
+                assert_eq!(redo_log.get(tid, &999), Some("value 999".to_string()));
+                redo_log.commit(tid).unwrap();
+                drop(redo_log);
+
+                // Crash before the store flushed the changes.
+                store.discard_changes();
+                assert_eq!(store.get(&0), None);
+                RedoLog::new(path, store.clone()).unwrap();
+                recovered.push(store.data.read().unwrap().clone());
+            }
+            let expected: HashMap<_, _> = changes.into_iter().collect();
+            assert_eq!(recovered[0], expected);
+            assert_eq!(recovered[1], expected);
+
+            let single_size = fs::metadata(single_path).unwrap().len();
+            let batch_size = fs::metadata(batch_path).unwrap().len();
+            assert!(
+                batch_size < single_size,
+                "{} >= {}",
+                batch_size,
+                single_size
+            );
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_write_batch_inactive_transaction() {
+    create_test_file("./files/write_batch_inactive_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        match redo_log.write_batch(5, vec![(1, "value".to_string())]) {
+            Err(LogError::UnknownTransaction(5)) => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        assert_eq!(store.get(&1), None);
+
+        // An empty batch logs nothing.
+        let tid = redo_log.start();
+        redo_log.write_batch(tid, Vec::new()).unwrap();
+        assert_eq!(redo_log.pending_entries(), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_delete() {
+    create_test_file("./files/delete_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "one".to_string()).unwrap();
+        redo_log.write(tid, 2, "two".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        // Deleting a key that doesn't exist is logged as well.
+        let tid = redo_log.start();
+        redo_log.delete(tid, 1).unwrap();
+        redo_log.delete(tid, 3).unwrap();
+        assert_eq!(redo_log.get(tid, &1), None);
+        assert_eq!(store.get(&1), None);
+        redo_log.commit(tid).unwrap();
+
+        // Crash before the deleting transaction commits.
+        let uncommitted = redo_log.start();
+        redo_log.delete(uncommitted, 2).unwrap();
+        drop(redo_log);
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&1), None);
+        assert_eq!(store.get(&2), Some("two".to_string()));
+        assert_eq!(store.get(&3), None);
+
+        // A checkpoint makes the deletion durable in the store.
+        let tid = redo_log.start();
+        redo_log.delete(tid, 2).unwrap();
+        redo_log.commit(tid).unwrap();
+        assert_eq!(store.get_flushed(&2), Some("two".to_string()));
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.get_flushed(&2), None);
+
+        match redo_log.delete(tid, 2) {
+            Err(LogError::UnknownTransaction(id)) => assert_eq!(id, tid),
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+    })
+    .unwrap();
+}
+
+/// Store recording every update applied to it, in order.
+#[derive(Clone, Default)]
+struct RecordingStore(Arc<RwLock<Vec<(i32, String)>>>);
+
+impl LogStore<MyLogData> for RecordingStore {
+    fn get(&self, key: &i32) -> Option<String> {
+        let updates = self.0.read().unwrap();
+        updates
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn remove(&mut self, _: &i32) {}
+
+    fn update(&mut self, key: i32, val: String) {
+        self.0.write().unwrap().push((key, val));
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_recover_replays_from_checkpoint_boundary() {
+    create_test_file("./files/checkpoint_boundary_redo_log", |path, _| {
+        for &block_size in &[256, 512, 32768] {
+            for &readahead_blocks in &[1, 3] {
+                for pad in (0..120).step_by(17) {
+                    for &active_at_checkpoint in &[false, true] {
+                        fs::write(path, b"").unwrap();
+                        let options = || LogOptions {
+                            block_size,
+                            readahead_blocks,
+                            ..LogOptions::default()
+                        };
+                        let value = |name: &str| format!("{}{}", name, "x".repeat(pad + 100));
+                        let mut redo_log =
+                            RedoLog::new_with_options(path, RecordingStore::default(), options())
+                                .unwrap();
+                        let before = redo_log.start();
+                        redo_log.write(before, 1, value("before")).unwrap();
+                        redo_log.commit(before).unwrap();
+
+                        let mut expected = Vec::new();
+                        let active = redo_log.start();
+                        let committed = redo_log.start();
+                        redo_log.write(active, 2, value("active")).unwrap();
+                        redo_log.write(committed, 3, value("committed")).unwrap();
+                        redo_log.commit(committed).unwrap();
+                        if active_at_checkpoint {
+                            expected.push((2, value("active")));
+                            expected.push((3, value("committed")));
+                        } else {
+                            redo_log.commit(active).unwrap();
+                        }
+                        redo_log.checkpoint().unwrap();
+
+                        // The first change after the checkpoint comes right after its end.
+                        let after = redo_log.start();
+                        redo_log.write(after, 4, value("after")).unwrap();
+                        redo_log.write(after, 5, value("after")).unwrap();
+                        redo_log.commit(after).unwrap();
+                        if active_at_checkpoint {
+                            redo_log.commit(active).unwrap();
+                        }
+                        expected.push((4, value("after")));
+                        expected.push((5, value("after")));
+                        drop(redo_log);
+
+                        // Every change after the boundary is replayed once, in order.
+                        let store = RecordingStore::default();
+                        RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+                        assert_eq!(
+                            *store.0.read().unwrap(),
+                            expected,
+                            "block size {}, readahead {}, pad {}, active {}",
+                            block_size,
+                            readahead_blocks,
+                            pad,
+                            active_at_checkpoint
+                        );
+                    }
+                }
+            }
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoints_flush_changes_once() {
+    create_test_file("./files/checkpoints_flush_once_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "one".to_string()).unwrap();
+        redo_log.write(tid, 2, "two".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        let active = redo_log.start();
+        redo_log.write(active, 3, "three".to_string()).unwrap();
+        let aborted = redo_log.start();
+        redo_log.write(aborted, 4, "four".to_string()).unwrap();
+        redo_log.abort(aborted).unwrap();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 2);
+        assert_eq!(store.get_flushed(&3), None);
+
+        // Nothing committed since the last checkpoint, so nothing is flushed.
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 2);
+
+        // The transaction active at the first checkpoint is flushed once it commits.
+        redo_log.commit(active).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "uno".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 4);
+        assert_eq!(store.get_flushed(&1), Some("uno".to_string()));
+        assert_eq!(store.get_flushed(&3), Some("three".to_string()));
+        assert_eq!(store.get_flushed(&4), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_store_flush_error() {
+    create_test_file("./files/checkpoint_flush_error_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "one".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        store.set_flush_err(true);
+        assert!(redo_log.checkpoint().is_err());
+
+        // The changes that failed to flush are flushed by the next checkpoint.
+        store.set_flush_err(false);
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 2);
+        assert_eq!(store.get_flushed(&1), Some("one".to_string()));
+    })
+    .unwrap();
+}
+
+/// Returns the number of abort entries in the log.
+fn logged_aborts(path: &str) -> usize {
+    let mut file = fs::File::open(path).unwrap();
+    WalIterator::new(&mut file, ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .filter(|entry| {
+            matches!(
+                entry,
+                Ok(SingleLogEntry::Transaction(Transaction::Abort(_)))
+            )
+        })
+        .count()
+}
+
+#[test]
+fn test_recover_twice() {
+    create_test_file("./files/recover_twice_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let committed = redo_log.start();
+        let uncommitted = redo_log.start();
+        redo_log
+            .write(committed, 1, "committed".to_string())
+            .unwrap();
+        redo_log
+            .write(uncommitted, 2, "uncommitted".to_string())
+            .unwrap();
+        redo_log.commit(committed).unwrap();
+        drop(redo_log);
+
+        store.discard_changes();
+        let redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(redo_log.last_tid(), uncommitted);
+        drop(redo_log);
+        let len = fs::metadata(path).unwrap().len();
+        let recovered = store.data.read().unwrap().clone();
+        assert_eq!(logged_aborts(path), 1);
+
+        // Recovering again finds the transaction already aborted.
+        store.discard_changes();
+        let redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(redo_log.last_tid(), uncommitted);
+        drop(redo_log);
+        assert_eq!(fs::metadata(path).unwrap().len(), len);
+        assert_eq!(*store.data.read().unwrap(), recovered);
+        assert_eq!(logged_aborts(path), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_start_only_transaction() {
+    create_test_file("./files/recover_start_only_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let committed = redo_log.start();
+        redo_log
+            .write(committed, 1, "committed".to_string())
+            .unwrap();
+        redo_log.commit(committed).unwrap();
+        // The checkpoint flushes the start of the transaction that writes nothing.
+        let started = redo_log.start();
+        redo_log.checkpoint().unwrap();
+        drop(redo_log);
+
+        let mut redo_log = RedoLog::new(path, store).unwrap();
+        assert_eq!(redo_log.last_tid(), started);
+        assert!(redo_log.start() > started);
+    })
+    .unwrap();
+}
+
+/// Store counting the updates of each key, which replaying a transaction
+/// twice would count twice.
+#[derive(Clone, Default)]
+struct CounterStore {
+    counts: Arc<RwLock<HashMap<i32, u32>>>,
+    applied_lsn: Arc<RwLock<Option<u64>>>,
+    /// The counts and applied LSN as of the last flush.
+    flushed_counts: Arc<RwLock<HashMap<i32, u32>>>,
+    flushed_lsn: Arc<RwLock<Option<u64>>>,
+}
+
+impl CounterStore {
+    fn count(&self, key: i32) -> u32 {
+        self.counts.read().unwrap().get(&key).cloned().unwrap_or(0)
+    }
+
+    /// Loses the updates that weren't flushed.
+    fn crash(&self) {
+        *self.counts.write().unwrap() = self.flushed_counts.read().unwrap().clone();
+        *self.applied_lsn.write().unwrap() = *self.flushed_lsn.read().unwrap();
+    }
+}
+
+impl LogStore<MyLogData> for CounterStore {
+    fn get(&self, key: &i32) -> Option<String> {
+        self.counts
+            .read()
+            .unwrap()
+            .get(key)
+            .map(|count| count.to_string())
+    }
+
+    fn remove(&mut self, key: &i32) {
+        self.counts.write().unwrap().remove(key);
+    }
+
+    fn update(&mut self, key: i32, _: String) {
+        *self.counts.write().unwrap().entry(key).or_insert(0) += 1;
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        *self.flushed_counts.write().unwrap() = self.counts.read().unwrap().clone();
+        *self.flushed_lsn.write().unwrap() = *self.applied_lsn.read().unwrap();
+        Ok(())
+    }
+
+    fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+        self.flush()
+    }
+
+    fn applied_lsn(&self) -> Option<u64> {
+        *self.applied_lsn.read().unwrap()
+    }
+
+    fn set_applied_lsn(&mut self, lsn: u64) {
+        *self.applied_lsn.write().unwrap() = Some(lsn);
+    }
+}
+
+#[test]
+fn test_recover_skips_applied_transactions() {
+    create_test_file("./files/applied_lsn_redo_log", |path, _| {
+        let options = || LogOptions {
+            defer_store_updates: true,
+            ..LogOptions::default()
+        };
+        let store = CounterStore::default();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        for i in 0..3 {
+            let tid = redo_log.start();
+            redo_log.write(tid, 1, "increment".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+            // The store flushes itself after the second transaction.
+            if i == 1 {
+                store.clone().flush().unwrap();
+            }
+        }
+        assert_eq!(store.count(1), 3);
+        assert_eq!(store.applied_lsn(), redo_log.last_flushed_lsn());
+        drop(redo_log);
+
+        // Only the transaction committed after the flush is replayed.
+        store.crash();
+        assert_eq!(store.count(1), 2);
+        RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        assert_eq!(store.count(1), 3);
+
+        // Recovering again replays nothing.
+        store.crash();
+        RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        assert_eq!(store.count(1), 3);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_group_commit() {
+    create_test_file("./files/group_commit_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let write_batch = |redo_log: &mut RedoLog<MyLogData, MyStore<MyLogData>>, batch: i32| {
+            (0..5)
+                .map(|i| {
+                    let tid = redo_log.start();
+                    redo_log
+                        .write(tid, batch * 10 + i, format!("batch {}", batch))
+                        .unwrap();
+                    tid
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let tids = write_batch(&mut redo_log, 1);
+        let tickets: Vec<_> = tids
+            .iter()
+            .map(|&tid| redo_log.commit_async(tid).unwrap())
+            .collect();
+        assert!(redo_log.active_transactions().is_empty());
+        assert!(tickets.iter().all(|ticket| !ticket.is_durable()));
+        // Crash before the commits are flushed. Dropping the log would
+        // flush them.
+        crash_log(redo_log, path).unwrap();
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert!(store.data.read().unwrap().is_empty());
+
+        let tids = write_batch(&mut redo_log, 2);
+        let tickets: Vec<_> = tids
+            .iter()
+            .map(|&tid| redo_log.commit_async(tid).unwrap())
+            .collect();
+        let size = fs::metadata(path).unwrap().len();
+        redo_log.flush_commits().unwrap();
+        assert!(fs::metadata(path).unwrap().len() > size);
+        assert_eq!(redo_log.synced_lsn(), redo_log.last_flushed_lsn().unwrap());
+        for ticket in tickets {
+            assert!(ticket.is_durable());
+            ticket.wait().unwrap();
+        }
+        // The group shares the log flush and sync, with nothing left to flush.
+        redo_log.flush_commits().unwrap();
+        drop(redo_log);
+
+        store.discard_changes();
+        RedoLog::new(path, store.clone()).unwrap();
+        for i in 0..5 {
+            assert_eq!(store.get(&(20 + i)), Some("batch 2".to_string()));
+            assert_eq!(store.get(&(10 + i)), None);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_commit_group() {
+    create_test_file("./files/commit_group_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        let tid2 = redo_log.start();
+        redo_log.write(tid1, 1, "one".to_string()).unwrap();
+        redo_log.write(tid2, 2, "two".to_string()).unwrap();
+        match redo_log.commit_group(&[tid1, tid2, 10]) {
+            Err(LogError::UnknownTransaction(10)) => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        assert_eq!(redo_log.active_transactions(), vec![tid1, tid2]);
+
+        redo_log.commit_group(&[tid1, tid2]).unwrap();
+        assert!(redo_log.active_transactions().is_empty());
+        drop(redo_log);
+
+        store.discard_changes();
+        RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&1), Some("one".to_string()));
+        assert_eq!(store.get(&2), Some("two".to_string()));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_overwrites_flushed_once() {
+    create_test_file("./files/overwrites_redo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let options = LogOptions {
+            write_ahead: WriteAheadPolicy::OnCommit,
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        let tid = redo_log.start();
+        for i in 0..100_000 {
+            redo_log.write(tid, 1, i.to_string()).unwrap();
+        }
+        redo_log.commit(tid).unwrap();
+
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 1);
+        assert_eq!(store.get_flushed(&1), Some("99999".to_string()));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_close() {
+    create_test_file("./files/close_redo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        redo_log.write(tid, 30, "World".to_string()).unwrap();
+        assert_eq!(redo_log.pending_entries(), 3);
+        redo_log.close().unwrap();
+
+        let expected_entries = vec![
+            SingleLogEntry::Transaction(Transaction::Start(1)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 1,
+                key: 20,
+                value: "Hello".to_string(),
+            }),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid: 1,
+                key: 30,
+                value: "World".to_string(),
+            }),
+        ];
+        let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert_eq!(entries, expected_entries);
+
+        // Recovery finds the unfinished transaction and aborts it.
+        let mut redo_log = RedoLog::new(path, store).unwrap();
+        assert_eq!(redo_log.start(), 2);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_close_checkpoints() {
+    create_test_file("./files/close_checkpoints_redo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::options()
+            .checkpoint_on_close(true)
+            .open(path, store.clone())
+            .unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        assert_eq!(store.get_flushed(&20), None);
+        redo_log.close().unwrap();
+
+        assert_eq!(store.get_flushed(&20), Some("Hello".to_string()));
+        let last = WalIterator::new(&mut file, ReadDirection::Backward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .next_back()
+            .unwrap()
+            .unwrap();
+        assert_eq!(last, SingleLogEntry::Checkpoint(Checkpoint::End));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_drop_flushes_pending_entries() {
+    create_test_file("./files/drop_flushes_redo_log", |path, file| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        let ticket = redo_log.commit_async(tid).unwrap();
+        let size = file.metadata().unwrap().len();
+
+        drop(redo_log);
+        assert!(file.metadata().unwrap().len() > size);
+        ticket.wait().unwrap();
+
+        store.discard_changes();
+        RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&20), Some("Hello".to_string()));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_prepare() {
+    create_test_file("./files/prepare_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid1 = redo_log.start();
+        redo_log.write(tid1, 1, "one".to_string()).unwrap();
+        redo_log.prepare(tid1).unwrap();
+        let tid2 = redo_log.start();
+        redo_log.write(tid2, 2, "two".to_string()).unwrap();
+        redo_log.prepare(tid2).unwrap();
+        let tid3 = redo_log.start();
+        redo_log.write(tid3, 3, "three".to_string()).unwrap();
+        assert_eq!(redo_log.synced_lsn(), redo_log.last_flushed_lsn().unwrap());
+        assert_eq!(redo_log.active_transactions(), vec![tid3]);
+        match redo_log.write(tid1, 1, "changed".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) if tid == tid1 => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        // The checkpoint keeps the prepared transactions for recovery.
+        redo_log.checkpoint().unwrap();
+        assert_eq!(store.flush_change_calls(), 0);
+        drop(redo_log);
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(redo_log.in_doubt_transactions(), vec![tid1, tid2]);
+        assert!(redo_log.active_transactions().is_empty());
+        assert!(store.data.read().unwrap().is_empty());
+
+        redo_log.resolve_prepared(tid1, Outcome::Commit).unwrap();
+        redo_log.resolve_prepared(tid2, Outcome::Abort).unwrap();
+        assert!(redo_log.in_doubt_transactions().is_empty());
+        assert_eq!(store.get(&1), Some("one".to_string()));
+        assert_eq!(store.get(&2), None);
+        match redo_log.resolve_prepared(tid1, Outcome::Abort) {
+            Err(LogError::UnknownTransaction(tid)) if tid == tid1 => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        drop(redo_log);
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert!(redo_log.in_doubt_transactions().is_empty());
+        assert_eq!(store.get(&1), Some("one".to_string()));
+        assert_eq!(store.get(&2), None);
+        assert_eq!(store.get(&3), None);
+        assert_eq!(redo_log.start(), tid3 + 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_start_with_tid() {
+    create_test_file("./files/start_with_tid_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(redo_log.start(), 1);
+        redo_log.start_with_tid(10).unwrap();
+        for &tid in &[1, 10] {
+            match redo_log.start_with_tid(tid) {
+                Err(LogError::DuplicateTransaction(id)) if id == tid => {}
+                result => panic!("Expected a duplicate transaction, got {:?}", result),
+            }
+        }
+        assert_eq!(redo_log.last_tid(), 10);
+        assert_eq!(redo_log.start(), 11);
+
+        redo_log.write(10, 10, "ten".to_string()).unwrap();
+        redo_log.commit(10).unwrap();
+        redo_log.abort(1).unwrap();
+        // Ids below the last one can be used if they're free.
+        redo_log.start_with_tid(5).unwrap();
+        redo_log.write(5, 5, "five".to_string()).unwrap();
+        redo_log.commit(5).unwrap();
+        redo_log.start_with_tid(20).unwrap();
+        redo_log.write(20, 20, "twenty".to_string()).unwrap();
+        for &tid in &[1, 5, 10] {
+            assert!(redo_log.start_with_tid(tid).is_err());
+        }
+        drop(redo_log);
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&5), Some("five".to_string()));
+        assert_eq!(store.get(&10), Some("ten".to_string()));
+        assert_eq!(store.get(&20), None);
+        for &tid in &[1, 5, 10, 11, 20] {
+            match redo_log.start_with_tid(tid) {
+                Err(LogError::DuplicateTransaction(id)) if id == tid => {}
+                result => panic!("Expected a duplicate transaction, got {:?}", result),
+            }
+        }
+        assert_eq!(redo_log.start(), 21);
+        redo_log.start_with_tid(15).unwrap();
+        redo_log.write(15, 15, "fifteen".to_string()).unwrap();
+        redo_log.commit(15).unwrap();
+        redo_log.commit(21).unwrap();
+        drop(redo_log);
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&15), Some("fifteen".to_string()));
+        assert_eq!(redo_log.start(), 22);
+    })
+    .unwrap();
+}
+
+/// Value that fails to serialize when it's empty.
+#[derive(Clone, PartialEq, Debug)]
+struct Unserializable(String);
+
+impl Serializable for Unserializable {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        if self.0.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty value"));
+        }
+        self.0.serialize(bytes)
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<Unserializable> {
+        Ok(Unserializable(String::deserialize(bytes)?))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct UnserializableLogData;
+
+impl LogData for UnserializableLogData {
+    type Key = i32;
+    type Value = Unserializable;
+}
+
+#[test]
+fn test_write_unserializable_value() {
+    create_test_file("./files/unserializable_value_redo_log", |path, _| {
+        let store: MyStore<UnserializableLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        let pending_bytes = redo_log.pending_bytes();
+
+        // The value is rejected before it's logged or applied.
+        match redo_log.write(tid, 1, Unserializable(String::new())) {
+            Err(LogError::IoError(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidInput),
+            result => panic!("Expected an io error, got {:?}", result),
+        }
+        assert_eq!(redo_log.pending_entries(), 1);
+        assert_eq!(redo_log.pending_bytes(), pending_bytes);
+        assert_eq!(store.get(&1), None);
+
+        redo_log
+            .write(tid, 2, Unserializable("value".to_string()))
+            .unwrap();
+        redo_log.commit(tid).unwrap();
+        drop(redo_log);
+
+        let store: MyStore<UnserializableLogData> = MyStore::new();
+        RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&1), None);
+        assert_eq!(store.get(&2), Some(Unserializable("value".to_string())));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_failed_flush_commits_are_aborted() {
+    let backend = InMemoryBackend::new();
+    let store: MyStore<MyLogData> = MyStore::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 1, "one".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    drop(redo_log);
+
+    // Writes fail partway through the flush of the queued commits.
+    let len = backend.contents().len() as u64;
+    let file = FaultyFile::new(backend.clone(), FaultPolicy::FailAfter(len + 100)).unwrap();
+    let mut redo_log = RedoLog::with_backend(file, store.clone(), LogOptions::default()).unwrap();
+    let tids = [redo_log.start(), redo_log.start()];
+    for &tid in &tids {
+        redo_log.write(tid, tid as i32, "value".repeat(10)).unwrap();
+    }
+    let tickets: Vec<_> = tids
+        .iter()
+        .map(|&tid| redo_log.commit_async(tid).unwrap())
+        .collect();
+    assert!(redo_log.flush_commits().is_err());
+    for ticket in tickets {
+        assert!(ticket.wait().is_err());
+    }
+    assert!(redo_log.active_transactions().is_empty());
+    // The entries written before the write that failed were cut off.
+    assert_eq!(backend.contents().len() as u64, len);
+    // Crash without flushing the aborts.
+    mem::forget(redo_log);
+
+    let store: MyStore<MyLogData> = MyStore::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend, store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(store.get(&1), Some("one".to_string()));
+    for &tid in &tids {
+        assert_eq!(store.get(&(tid as i32)), None);
+    }
+    assert_eq!(redo_log.start(), 2);
+}
+
+#[test]
+fn test_recover_commit_followed_by_abort() {
+    let backend = InMemoryBackend::new();
+    let store: MyStore<MyLogData> = MyStore::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend.clone(), store, LogOptions::default()).unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 1, "one".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    drop(redo_log);
+
+    // An abort logged after a commit whose sync failed cancels the commit.
+    let mut writer = Writer::with_header(backend.clone(), BlockFormat::default()).unwrap();
+    let entry: SingleLogEntry<MyLogData> = SingleLogEntry::Transaction(Transaction::Abort(tid));
+    writer.append_serializable(&entry).unwrap();
+
+    let store: MyStore<MyLogData> = MyStore::new();
+    let mut redo_log =
+        RedoLog::with_backend(backend, store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(store.get(&1), None);
+    assert!(!redo_log.replay_transaction(tid).unwrap());
+}
+
+#[test]
+fn test_checkpoint_forgets_finished_tids() {
+    create_test_file("./files/forget_finished_tids_redo_log", |path, _| {
+        let mut store: MyStore<MyLogData> = MyStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let active = redo_log.start();
+        redo_log.start_with_tid(10).unwrap();
+        redo_log.write(10, 10, "ten".to_string()).unwrap();
+        redo_log.commit(10).unwrap();
+
+        // Recovery would still read transaction 10 along with the
+        // transaction active when the checkpoint began.
+        redo_log.checkpoint().unwrap();
+        assert!(redo_log.start_with_tid(10).is_err());
+
+        redo_log.commit(active).unwrap();
+        redo_log.checkpoint().unwrap();
+        redo_log.start_with_tid(10).unwrap();
+        redo_log.write(10, 10, "reused".to_string()).unwrap();
+        redo_log.commit(10).unwrap();
+        drop(redo_log);
+
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&10), Some("reused".to_string()));
+        assert!(redo_log.start_with_tid(10).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_failed_commit_is_taken_back() {
+    let backend = InMemoryBackend::new();
+    let store: MyStore<MyLogData> = MyStore::new();
+    drop(RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap());
+
+    // The first write after the header fails, and the writes after it succeed.
+    let len = backend.contents().len() as u64;
+    let file = FaultyFile::new(backend.clone(), FaultPolicy::FailOnceAfter(len)).unwrap();
+    let mut redo_log = RedoLog::with_backend(file, store.clone(), LogOptions::default()).unwrap();
+    let failed = redo_log.start();
+    redo_log.write(failed, 1, "failed".to_string()).unwrap();
+    assert!(redo_log.commit(failed).is_err());
+    assert_eq!(redo_log.active_transactions(), vec![failed]);
+
+    // The next flush writes the failed transaction's change but not its commit.
+    let tid = redo_log.start();
+    redo_log.write(tid, 2, "two".to_string()).unwrap();
+    redo_log.commit(tid).unwrap();
+    // Crash with the failed transaction still active.
+    mem::forget(redo_log);
+
+    let store: MyStore<MyLogData> = MyStore::new();
+    RedoLog::with_backend(backend, store.clone(), LogOptions::default()).unwrap();
+    assert_eq!(store.get(&1), None);
+    assert_eq!(store.get(&2), Some("two".to_string()));
+}
+
+#[test]
+fn test_failed_flush_is_cut_off() {
+    let backend = InMemoryBackend::new();
+    let store: MyStore<MyLogData> = MyStore::new();
+    drop(RedoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap());
+
+    // Writing the change fails after the start before it was written.
+    let len = backend.contents().len() as u64;
+    let file = FaultyFile::new(backend.clone(), FaultPolicy::FailOnceAfter(len + 40)).unwrap();
+    let mut redo_log = RedoLog::with_backend(file, store.clone(), LogOptions::default()).unwrap();
+    let tid = redo_log.start();
+    redo_log.write(tid, 1, "value".repeat(30)).unwrap();
+    assert!(redo_log.commit(tid).is_err());
+    assert_eq!(backend.contents().len() as u64, len);
+
+    // The next flush logs each entry once.
+    redo_log.commit(tid).unwrap();
+    let mut file = io::Cursor::new(backend.contents());
+    let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            SingleLogEntry::Transaction(Transaction::Start(tid)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid,
+                key: 1,
+                value: "value".repeat(30),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(tid)),
+        ]
+    );
+}
@@ -0,0 +1,223 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::replication::{apply_stream, ApplyState};
+use disk_utils::wal::{LogError, LogOptions, Outcome, SerializeError};
+use disk_utils::Serializable;
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: 256,
+        // The leader's store only holds committed changes to compare with.
+        defer_store_updates: true,
+        ..LogOptions::default()
+    }
+}
+
+/// Commits transactions writing, deleting and batching changes to keys,
+/// with entries spanning several blocks, and aborts some of them.
+fn workload(redo_log: &mut RedoLog<TestData, TestStore>, values: std::ops::Range<i32>) {
+    for i in values {
+        let tid = redo_log.start();
+        redo_log.write(tid, i % 7, format!("value {}", i)).unwrap();
+        if i % 5 == 0 {
+            redo_log.delete(tid, (i + 3) % 7).unwrap();
+        }
+        if i % 6 == 0 {
+            let batch = (10..14).map(|key| (key, format!("{} {}", i, "x".repeat(300))));
+            redo_log.write_batch(tid, batch).unwrap();
+        }
+        if i % 4 == 3 {
+            redo_log.abort(tid).unwrap();
+        } else {
+            redo_log.commit(tid).unwrap();
+        }
+        if i % 20 == 10 {
+            redo_log.checkpoint().unwrap();
+        }
+    }
+}
+
+/// Writes a log whose last transaction is still running, returning the
+/// leader's committed state and the id of the running transaction.
+fn write_leader_log(path: &str, options: LogOptions) -> (HashMap<i32, String>, u64) {
+    let store = TestStore::new();
+    let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+    workload(&mut redo_log, 0..30);
+
+    // A prepared transaction commits after other transactions.
+    let prepared = redo_log.start();
+    redo_log
+        .write(prepared, 20, "prepared".to_string())
+        .unwrap();
+    redo_log.prepare(prepared).unwrap();
+    workload(&mut redo_log, 30..40);
+    redo_log
+        .resolve_prepared(prepared, Outcome::Commit)
+        .unwrap();
+
+    let committed = store.map();
+    let running = redo_log.start();
+    redo_log.write(running, 1, "running".to_string()).unwrap();
+    redo_log.close().unwrap();
+    (committed, running)
+}
+
+#[test]
+fn test_apply_stream() {
+    create_test_file("./files/replication_stream", |path, _| {
+        let (committed, running) = write_leader_log(path, options());
+        let bytes = fs::read(path).unwrap();
+
+        let mut store = TestStore::new();
+        let report = apply_stream(&bytes[..], &mut store, None).unwrap();
+        assert!(store.map() == committed);
+        assert_eq!(report.bytes_read, bytes.len() as u64);
+        assert_eq!(report.committed, 31);
+        assert_eq!(report.aborted, 10);
+        assert_eq!(report.in_flight, vec![running]);
+        assert_eq!(store.recorded_lsn(), report.applied_lsn);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_apply_chunks() {
+    create_test_file("./files/replication_chunks", |path, _| {
+        let (committed, running) = write_leader_log(path, options());
+        let bytes = fs::read(path).unwrap();
+        let full = apply_stream(&bytes[..], &mut TestStore::new(), None).unwrap();
+
+        for &chunk_size in &[1, 10, 256, 32768] {
+            let mut store = TestStore::new();
+            let mut state = ApplyState::new(None);
+            let mut committed_count = 0;
+            for chunk in bytes.chunks(chunk_size) {
+                let report = state.apply(chunk, &mut store).unwrap();
+                assert_eq!(report.bytes_read, chunk.len() as u64);
+                committed_count += report.committed;
+            }
+            assert!(store.map() == committed, "chunk size {}", chunk_size);
+            assert_eq!(committed_count, full.committed);
+            assert_eq!(state.position(), bytes.len() as u64);
+            assert_eq!(state.in_flight(), vec![running]);
+            assert_eq!(state.applied_lsn(), full.applied_lsn);
+            assert_eq!(state.last_lsn(), full.last_lsn);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_apply_block_checksums() {
+    create_test_file("./files/replication_checksums", |path, _| {
+        let options = LogOptions {
+            block_checksums: true,
+            ..options()
+        };
+        let (committed, running) = write_leader_log(path, options);
+        let bytes = fs::read(path).unwrap();
+
+        let mut store = TestStore::new();
+        let mut state = ApplyState::new(None);
+        for chunk in bytes.chunks(10) {
+            state.apply(chunk, &mut store).unwrap();
+        }
+        assert!(store.map() == committed);
+        assert_eq!(state.in_flight(), vec![running]);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_apply_while_leader_writes() {
+    create_test_file("./files/replication_follow", |path, _| {
+        let leader = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, leader.clone(), options()).unwrap();
+        let mut follower = TestStore::new();
+        let mut saved = Vec::new();
+        ApplyState::<TestData>::new(None)
+            .serialize(&mut saved)
+            .unwrap();
+
+        for round in 0..10 {
+            // A transaction is still running when the follower catches up,
+            // and commits in the next round.
+            let running = redo_log.start();
+            redo_log
+                .write(running, 100 + round, "running".to_string())
+                .unwrap();
+            workload(&mut redo_log, round * 5..round * 5 + 5);
+            let committed = leader.map();
+
+            // The follower restarts between rounds, resuming from its
+            // saved state with the log bytes it hasn't applied yet.
+            let mut state = ApplyState::deserialize(&mut &saved[..]).unwrap();
+            let bytes = fs::read(path).unwrap();
+            let new_bytes = &bytes[state.position() as usize..];
+            for chunk in new_bytes.chunks(37) {
+                state.apply(chunk, &mut follower).unwrap();
+            }
+            redo_log.commit(running).unwrap();
+
+            assert!(follower.map() == committed, "round {}", round);
+            assert_eq!(state.in_flight(), vec![running]);
+            saved.clear();
+            state.serialize(&mut saved).unwrap();
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_apply_from_lsn() {
+    create_test_file("./files/replication_from_lsn", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        workload(&mut redo_log, 0..20);
+        let first = fs::read(path).unwrap();
+        let mut follower = TestStore::new();
+        let report = apply_stream(&first[..], &mut follower, None).unwrap();
+        assert!(report.applied_lsn.is_some());
+
+        workload(&mut redo_log, 20..40);
+        redo_log.close().unwrap();
+
+        // Applying the whole log again only applies the new transactions.
+        let bytes = fs::read(path).unwrap();
+        let rest = apply_stream(&bytes[..], &mut follower, report.applied_lsn).unwrap();
+        assert_eq!(rest.skipped, report.committed);
+        assert_eq!(rest.committed, 15);
+        assert!(follower.map() == store.map());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_apply_corrupted_stream() {
+    create_test_file("./files/replication_corrupted", |path, _| {
+        write_leader_log(path, options());
+        let mut bytes = fs::read(path).unwrap();
+        // Damage the checksum of the first record, after the file header.
+        bytes[256 + 2] ^= 0xff;
+
+        let mut state = ApplyState::new(None);
+        let mut store = TestStore::new();
+        let result = bytes
+            .chunks(100)
+            .map(|chunk| state.apply(chunk, &mut store))
+            .find(Result::is_err);
+        match result {
+            Some(Err(LogError::SerializeError(SerializeError::Corrupted(_)))) => {}
+            result => panic!("Expected a corrupted record, got {:?}", result),
+        }
+    })
+    .unwrap();
+}
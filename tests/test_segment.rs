@@ -0,0 +1,154 @@
+extern crate disk_utils;
+
+use std::fs;
+use std::fs::File;
+use std::path::PathBuf;
+
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, RecordType};
+use disk_utils::wal::segment::{open_segments, SegmentedWriter};
+use disk_utils::wal::LogData;
+
+#[derive(Clone, PartialEq, Debug)]
+struct MyLogData;
+
+impl LogData for MyLogData {
+    type Key = i32;
+    type Value = String;
+}
+
+const BLOCK_SIZE: i64 = 256;
+const MAX_SEGMENT_SIZE: u64 = 4 * BLOCK_SIZE as u64;
+
+fn format(checksums: bool) -> BlockFormat {
+    BlockFormat {
+        block_size: BLOCK_SIZE,
+        checksums,
+    }
+}
+
+fn transaction_entries(tid: u64) -> Vec<SingleLogEntry<MyLogData>> {
+    vec![
+        SingleLogEntry::Transaction(Transaction::Start(tid)),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid,
+            key: tid as i32,
+            value: "a".repeat(tid as usize * 20),
+        }),
+        SingleLogEntry::Transaction(Transaction::Commit(tid)),
+    ]
+}
+
+fn remove_segments(segments: Vec<PathBuf>) {
+    for path in segments {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+/// Asserts that every segment starts and ends on an entry boundary.
+fn assert_entries_not_split(segments: &[PathBuf], format: BlockFormat) {
+    for path in segments {
+        let mut file = File::open(path).unwrap();
+        let types: Vec<_> = WalIterator::with_format(&mut file, ReadDirection::Forward, format)
+            .unwrap()
+            .map(|record| record.record_type)
+            .collect();
+        assert!(!types.is_empty());
+        assert!(matches!(types[0], RecordType::Full | RecordType::First));
+        assert!(matches!(
+            types[types.len() - 1],
+            RecordType::Full | RecordType::Last
+        ));
+    }
+}
+
+fn assert_segmented_log(base: &str, checksums: bool) {
+    let format = format(checksums);
+    let mut writer = SegmentedWriter::with_format(base, format, MAX_SEGMENT_SIZE).unwrap();
+    let mut expected = Vec::new();
+    for tid in 1..=10 {
+        for entry in transaction_entries(tid) {
+            writer.append_serializable(&entry).unwrap();
+            expected.push(entry);
+        }
+    }
+    let segments = writer.segments();
+    assert!(writer.sealed_segments().len() >= 2);
+    for path in writer.sealed_segments() {
+        let len = fs::metadata(path).unwrap().len();
+        assert!(len <= MAX_SEGMENT_SIZE);
+        assert_eq!(len % BLOCK_SIZE as u64, 0);
+    }
+    assert_entries_not_split(&segments, format);
+    let last_lsn = writer.last_lsn();
+
+    // Reopening appends to the last segment and continues its LSNs.
+    drop(writer);
+    let mut writer = SegmentedWriter::with_format(base, format, MAX_SEGMENT_SIZE).unwrap();
+    assert_eq!(writer.segments(), segments);
+    assert_eq!(writer.last_lsn(), last_lsn);
+    for entry in transaction_entries(11) {
+        writer.append_serializable(&entry).unwrap();
+        expected.push(entry);
+    }
+    let segments = writer.segments();
+    assert_eq!(
+        open_segments(base, format).unwrap().into_inner().len(),
+        segments.len()
+    );
+
+    let mut log = writer.reader().unwrap();
+    let mut iter = WalIterator::with_format(&mut log, ReadDirection::Forward, format).unwrap();
+    let lsns: Vec<_> = iter.by_ref().map(|record| record.lsn).collect();
+    assert_eq!(lsns, (1..=writer.last_lsn()).collect::<Vec<_>>());
+    assert!(iter.corruption().is_none());
+
+    let forward: Vec<_> = WalIterator::with_format(&mut log, ReadDirection::Forward, format)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(forward, expected);
+
+    let mut backward: Vec<_> = WalIterator::with_format(&mut log, ReadDirection::Backward, format)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .rev()
+        .map(Result::unwrap)
+        .collect();
+    backward.reverse();
+    assert_eq!(backward, expected);
+
+    remove_segments(segments);
+}
+
+#[test]
+fn test_segmented_writer() {
+    assert_segmented_log("./files/segmented_writer", false);
+}
+
+#[test]
+fn test_segmented_writer_block_checksums() {
+    assert_segmented_log("./files/segmented_writer_checksums", true);
+}
+
+#[test]
+fn test_large_entry_gets_own_segment() {
+    let base = "./files/segmented_writer_large_entry";
+    let format = format(false);
+    let mut writer = SegmentedWriter::with_format(base, format, MAX_SEGMENT_SIZE).unwrap();
+    assert_eq!(
+        writer.append_serializable(&"a".to_string()).unwrap(),
+        (0, 0)
+    );
+    let large = "b".repeat(2 * MAX_SEGMENT_SIZE as usize);
+    assert_eq!(writer.append_serializable(&large).unwrap(), (1, 0));
+    assert_eq!(writer.append_serializable(&"c".to_string()).unwrap().0, 2);
+    assert_eq!(writer.sealed_segments().len(), 2);
+    assert!(fs::metadata(&writer.sealed_segments()[1]).unwrap().len() > MAX_SEGMENT_SIZE);
+
+    let segments = writer.segments();
+    assert_entries_not_split(&segments, format);
+    remove_segments(segments);
+}
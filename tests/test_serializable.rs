@@ -0,0 +1,97 @@
+extern crate disk_utils;
+
+use std::collections::{BTreeSet, HashSet};
+
+use disk_utils::Serializable;
+
+#[test]
+fn test_hash_set() {
+    let set: HashSet<i32> = vec![1, 2, 3, 500].into_iter().collect();
+
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), 4 + 4 * 4);
+    assert_eq!(&bytes[..4], &[0, 0, 0, 4]);
+
+    let test_set = HashSet::<i32>::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(set, test_set);
+}
+
+#[test]
+fn test_btree_set_sorted() {
+    let set: BTreeSet<u64> = vec![30, 10, 20].into_iter().collect();
+
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+    let expected = vec![
+        0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0, 0, 0, 0, 0, 30,
+    ];
+    assert_eq!(bytes, expected);
+
+    let test_set = BTreeSet::<u64>::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(set, test_set);
+}
+
+#[test]
+fn test_set_of_strings() {
+    let set: BTreeSet<String> = vec!["Hello".to_string(), "World".to_string()]
+        .into_iter()
+        .collect();
+
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+
+    let test_set = BTreeSet::<String>::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(set, test_set);
+}
+
+#[test]
+fn test_empty_set() {
+    let set: HashSet<String> = HashSet::new();
+
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+    assert_eq!(bytes, vec![0, 0, 0, 0]);
+
+    let test_set = HashSet::<String>::deserialize(&mut &bytes[..]).unwrap();
+    assert!(test_set.is_empty());
+}
+
+#[test]
+fn test_truncated_set() {
+    let set: BTreeSet<u64> = vec![1, 2].into_iter().collect();
+
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+    bytes.truncate(bytes.len() - 1);
+
+    assert!(BTreeSet::<u64>::deserialize(&mut &bytes[..]).is_err());
+}
+
+#[test]
+fn test_serialized_size() {
+    let set: BTreeSet<String> = vec!["a".to_string(), "bc".to_string()]
+        .into_iter()
+        .collect();
+    let mut bytes = Vec::new();
+    set.serialize(&mut bytes).unwrap();
+    assert_eq!(set.serialized_size().unwrap(), bytes.len() as u64);
+    assert_eq!(7u64.serialized_size().unwrap(), 8);
+}
+
+#[test]
+fn test_serialized_size_matches_bytes() {
+    fn check<S: Serializable>(value: S) {
+        let mut bytes = Vec::new();
+        value.serialize(&mut bytes).unwrap();
+        assert_eq!(value.serialized_size().unwrap(), bytes.len() as u64);
+    }
+
+    check("Hello world!".to_string());
+    check(String::new());
+    check(-5i32);
+    check(5u32);
+    check(5u64);
+    check(vec![1u32, 2, 3].into_iter().collect::<HashSet<_>>());
+    check(BTreeSet::<String>::new());
+}
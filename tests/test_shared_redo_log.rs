@@ -0,0 +1,196 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::thread;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{create_test_file, FaultPolicy, FaultyFile, InMemoryBackend};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::shared_redo_log::SharedRedoLog;
+use disk_utils::wal::{CheckpointPolicy, LogError, LogOptions, LogStore};
+
+const THREADS: i32 = 8;
+const TRANSACTIONS: i32 = 100;
+
+fn options() -> LogOptions {
+    LogOptions {
+        checkpoint: CheckpointPolicy::EveryNCommits(50),
+        ..LogOptions::default()
+    }
+}
+
+/// Runs the thread's transactions, returning the values of the keys
+/// written by the transactions it committed.
+fn run_transactions(log: SharedRedoLog<TestData, TestStore>, thread: i32) -> HashMap<i32, String> {
+    let mut committed = HashMap::new();
+    for i in 0..TRANSACTIONS {
+        let tid = log.start();
+        let key = thread * 10 + i % 10;
+        let other_key = thread * 10 + (i + 3) % 10;
+        let value = format!("{} {}", thread, i);
+        log.write(tid, key, "overwritten".to_string()).unwrap();
+        log.write(tid, other_key, value.clone()).unwrap();
+        log.write(tid, key, value.clone()).unwrap();
+        assert_eq!(log.get(tid, &key), Some(value.clone()));
+
+        if i % 17 == 0 {
+            // Left unfinished when the log crashes.
+            continue;
+        }
+        if i % 5 == 0 {
+            log.abort(tid).unwrap();
+            continue;
+        }
+        log.commit(tid).unwrap();
+        committed.insert(key, value.clone());
+        committed.insert(other_key, value);
+    }
+    committed
+}
+
+#[test]
+fn test_concurrent_transactions_recover() {
+    create_test_file("./files/shared_redo_log_recover", |path, _| {
+        let store = TestStore::default();
+        let log = SharedRedoLog::new_with_options(path, store.clone(), options()).unwrap();
+        let threads: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let log = log.clone();
+                thread::spawn(move || run_transactions(log, thread))
+            })
+            .collect();
+        let mut expected = HashMap::new();
+        for thread in threads {
+            expected.extend(thread.join().unwrap());
+        }
+        assert_eq!(log.last_tid(), (THREADS * TRANSACTIONS) as u64);
+        assert_eq!(log.active_transactions().len(), THREADS as usize * 6);
+        assert_eq!(store.map(), expected);
+
+        // Crash without checkpointing, losing the changes the store didn't flush.
+        drop(log);
+        let recovered = store.after_crash();
+        RedoLog::new_with_options(path, recovered.clone(), options()).unwrap();
+        assert_eq!(recovered.map(), expected);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_shared_transactions() {
+    create_test_file("./files/shared_redo_log_transactions", |path, _| {
+        let store = TestStore::default();
+        let log = SharedRedoLog::new(path, store.clone()).unwrap();
+        let tid1 = log.start();
+        let tid2 = log.start();
+        assert_eq!(log.active_transactions(), vec![tid1, tid2]);
+
+        log.write(tid1, 1, "one".to_string()).unwrap();
+        log.write(tid2, 1, "two".to_string()).unwrap();
+        // Changes stay buffered until their transaction commits.
+        assert_eq!(store.get(&1), None);
+        assert_eq!(log.get(tid1, &1), Some("one".to_string()));
+        assert_eq!(log.get(tid2, &1), Some("two".to_string()));
+
+        log.commit(tid2).unwrap();
+        log.abort(tid1).unwrap();
+        assert_eq!(store.get(&1), Some("two".to_string()));
+        assert!(log.active_transactions().is_empty());
+
+        match log.write(tid1, 2, "aborted".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) => assert_eq!(tid, tid1),
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        assert!(matches!(
+            log.commit(tid2),
+            Err(LogError::UnknownTransaction(_))
+        ));
+        assert!(matches!(
+            log.abort(tid2),
+            Err(LogError::UnknownTransaction(_))
+        ));
+
+        // Nothing is logged for the aborted transaction.
+        log.checkpoint().unwrap();
+        drop(log);
+        let recovered = TestStore::default();
+        let redo_log = RedoLog::new(path, recovered.clone()).unwrap();
+        assert!(redo_log.active_transactions().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_shared_delete() {
+    create_test_file("./files/shared_redo_log_delete", |path, _| {
+        let store = TestStore::default();
+        let log = SharedRedoLog::new(path, store.clone()).unwrap();
+        let tid = log.start();
+        log.write_batch(tid, vec![(1, "one".to_string()), (2, "two".to_string())])
+            .unwrap();
+        log.commit(tid).unwrap();
+
+        // A deletion is buffered like a write, and a later write of the key
+        // brings it back.
+        let tid = log.start();
+        log.delete(tid, 1).unwrap();
+        log.delete(tid, 2).unwrap();
+        log.write(tid, 2, "again".to_string()).unwrap();
+        assert_eq!(log.get(tid, &1), None);
+        assert_eq!(log.get(tid, &2), Some("again".to_string()));
+        assert_eq!(store.get(&1), Some("one".to_string()));
+
+        log.commit(tid).unwrap();
+        assert_eq!(store.get(&1), None);
+        assert_eq!(store.get(&2), Some("again".to_string()));
+        assert!(matches!(
+            log.delete(tid, 2),
+            Err(LogError::UnknownTransaction(_))
+        ));
+
+        // Crash without checkpointing, losing the changes the store didn't flush.
+        drop(log);
+        let recovered = store.after_crash();
+        RedoLog::new(path, recovered.clone()).unwrap();
+        assert_eq!(recovered.map(), store.map());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_failed_commit_stays_active() {
+    let backend = InMemoryBackend::new();
+    drop(RedoLog::with_backend(backend.clone(), TestStore::new(), options()).unwrap());
+
+    // The first write after the header fails, and the writes after it succeed.
+    let len = backend.contents().len() as u64;
+    let file = FaultyFile::new(backend.clone(), FaultPolicy::FailOnceAfter(len)).unwrap();
+    let store = TestStore::new();
+    let log =
+        SharedRedoLog::from_log(RedoLog::with_backend(file, store.clone(), options()).unwrap());
+    let failed = log.start();
+    log.write(failed, 1, "failed".to_string()).unwrap();
+    assert!(log.commit(failed).is_err());
+    assert_eq!(log.active_transactions(), vec![failed]);
+    assert_eq!(store.get(&1), None);
+
+    // Flushing another transaction doesn't log the failed one.
+    let tid = log.start();
+    log.write(tid, 2, "two".to_string()).unwrap();
+    log.commit(tid).unwrap();
+    let recovered = TestStore::new();
+    let copy = InMemoryBackend::with_contents(backend.contents());
+    RedoLog::with_backend(copy, recovered.clone(), options()).unwrap();
+    assert_eq!(recovered.get(&1), None);
+    assert_eq!(recovered.get(&2), Some("two".to_string()));
+
+    // The failed transaction keeps its changes and can commit again.
+    log.commit(failed).unwrap();
+    assert_eq!(store.get(&1), Some("failed".to_string()));
+    drop(log);
+    let recovered = TestStore::new();
+    RedoLog::with_backend(backend, recovered.clone(), options()).unwrap();
+    assert_eq!(recovered.map(), store.map());
+}
@@ -0,0 +1,224 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::io;
+use std::io::{Cursor, Read, Write};
+
+use common::TestStore;
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::sink::{write_serializable_streaming, RecordSink, STREAMING_THRESHOLD};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{read_serializable_at, LogOptions, LogStore};
+use disk_utils::Serializable;
+
+/// Entry that serializes `len` generated bytes in small writes
+/// without holding them in memory.
+struct Generated {
+    len: usize,
+}
+
+/// Returns the byte at the position of the bytes a `Generated` serializes.
+fn generated_byte(pos: usize) -> u8 {
+    (pos % 4096 % 251) as u8
+}
+
+impl Serializable for Generated {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        let chunk: Vec<u8> = (0..4096).map(generated_byte).collect();
+        let mut written = 0;
+        while written < self.len {
+            let len = (self.len - written).min(chunk.len());
+            bytes.write_all(&chunk[..len])?;
+            written += len;
+        }
+        Ok(())
+    }
+
+    fn deserialize<R: Read>(bytes: &mut R) -> io::Result<Generated> {
+        let mut buf = Vec::new();
+        bytes.read_to_end(&mut buf)?;
+        if buf
+            .iter()
+            .enumerate()
+            .any(|(pos, &byte)| byte != generated_byte(pos))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Generated bytes don't match",
+            ));
+        }
+        Ok(Generated { len: buf.len() })
+    }
+}
+
+/// Entry that fails to serialize after writing `len` generated bytes.
+struct Failing {
+    len: usize,
+}
+
+impl Serializable for Failing {
+    fn serialize<W: Write>(&self, bytes: &mut W) -> io::Result<()> {
+        Generated { len: self.len }.serialize(bytes)?;
+        Err(io::Error::other("Serializing failed"))
+    }
+
+    fn deserialize<R: Read>(_: &mut R) -> io::Result<Failing> {
+        Err(io::Error::other("Failing entries are never written"))
+    }
+}
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    }
+}
+
+#[test]
+fn test_streaming_matches_buffered() {
+    let filler = Record::new(RecordType::Full, vec![9; 50]).unwrap();
+    let sizes = [0, 1, 100, 241, 242, 500, 1000, 5000];
+    for fillers in 0..6 {
+        for &len in &sizes {
+            let mut buffered = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+            let mut streamed = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+            for _ in 0..fillers {
+                buffered.append(&filler).unwrap();
+                streamed.append(&filler).unwrap();
+            }
+
+            let entry = Generated { len };
+            let offset = buffered.append_serializable(&entry).unwrap();
+            assert_eq!(
+                write_serializable_streaming(&mut streamed, &entry).unwrap(),
+                offset
+            );
+            assert_eq!(streamed.last_lsn(), buffered.last_lsn());
+            assert_eq!(streamed.position(), buffered.position());
+            assert_eq!(
+                streamed.into_inner().into_inner(),
+                buffered.into_inner().into_inner()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_streaming_failure_removes_records() {
+    let filler = Record::new(RecordType::Full, vec![9; 50]).unwrap();
+    for &len in &[100, 5000, STREAMING_THRESHOLD + 5000] {
+        let mut writer = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+        writer.append(&filler).unwrap();
+        let bytes = writer.file().get_ref().clone();
+        let position = writer.position();
+
+        // The records appended before serializing failed are cut off.
+        assert!(write_serializable_streaming(&mut writer, &Failing { len }).is_err());
+        assert_eq!(writer.position(), position);
+        assert_eq!(writer.last_lsn(), 1);
+        assert!(*writer.file().get_ref() == bytes);
+
+        let offset = write_serializable_streaming(&mut writer, &Generated { len }).unwrap();
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, format()).unwrap();
+        iter.next().unwrap();
+        let (entry, range) = read_serializable_at::<Generated>(&mut iter).unwrap();
+        assert_eq!(entry.len, len);
+        assert_eq!(range.start, offset);
+    }
+}
+
+#[test]
+fn test_sink_odd_writes() {
+    let bytes: Vec<u8> = (0..3000).map(|i| (i % 256) as u8).collect();
+    let mut buffered = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    buffered.append_bytes(&bytes).unwrap();
+
+    // Writes that don't line up with records give the same records.
+    let mut streamed = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    let mut sink = RecordSink::new(&mut streamed).unwrap();
+    for chunk in bytes.chunks(7) {
+        sink.write_all(chunk).unwrap();
+    }
+    assert_eq!(sink.max_buffered(), 256 - HEADER_SIZE);
+    assert_eq!(sink.finish().unwrap(), 0);
+    assert_eq!(
+        streamed.into_inner().into_inner(),
+        buffered.into_inner().into_inner()
+    );
+}
+
+#[test]
+fn test_streaming_large_value_bounded_memory() {
+    create_test_file("./files/sink_large_value", |_, file| {
+        let len = 64 * 1024 * 1024;
+        let mut writer = Writer::new(file).unwrap();
+        let max_record_size = writer.format().max_payload_size();
+
+        let mut sink = RecordSink::new(&mut writer).unwrap();
+        Generated { len }.serialize(&mut sink).unwrap();
+        // Only one record's payload is buffered at a time.
+        assert!(sink.max_buffered() <= max_record_size);
+        sink.finish().unwrap();
+
+        let records = writer.last_lsn() as usize;
+        assert_eq!(records, len.div_ceil(max_record_size));
+        let iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+        let mut read = 0;
+        let mut count = 0;
+        for record in iter {
+            let expected = match count {
+                0 => RecordType::First,
+                i if i == records - 1 => RecordType::Last,
+                _ => RecordType::Middle,
+            };
+            assert_eq!(record.record_type, expected);
+            read += record.payload.len();
+            count += 1;
+        }
+        assert_eq!(count, records);
+        assert_eq!(read, len);
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_sink_rejects_compression() {
+    use disk_utils::wal::Compression;
+
+    let mut writer = Writer::new(Cursor::new(Vec::new()))
+        .unwrap()
+        .compression(Compression::Lz4);
+    let err = RecordSink::new(&mut writer).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_redo_log_streams_large_values() {
+    create_test_file("./files/sink_redo_log", |path, _| {
+        let options = LogOptions {
+            block_size: 256,
+            ..LogOptions::default()
+        };
+        let value = "large value ".repeat(10_000);
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store, options.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, value.clone()).unwrap();
+        redo_log.write(tid, 2, "small".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        drop(redo_log);
+
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new_with_options(path, store.clone(), options).unwrap();
+        assert_eq!(redo_log.start(), 2);
+        assert_eq!(store.get(&1), Some(value));
+        assert_eq!(store.get(&2), Some("small".to_string()));
+    })
+    .unwrap();
+}
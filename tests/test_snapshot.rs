@@ -0,0 +1,259 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{crash_log, create_test_dir};
+use disk_utils::wal::record::{BLOCK_SIZE, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::snapshot::Snapshot;
+use disk_utils::wal::{LogError, SerializeError};
+
+/// Commits a transaction for each of the values, rewriting a few keys.
+fn commit_values(redo_log: &mut RedoLog<TestData, TestStore>, values: std::ops::Range<i32>) {
+    for i in values {
+        let tid = redo_log.start();
+        redo_log.write(tid, i % 10, format!("value {}", i)).unwrap();
+        if i % 9 == 0 {
+            redo_log.delete(tid, (i + 1) % 10).unwrap();
+        }
+        redo_log.commit(tid).unwrap();
+    }
+}
+
+#[test]
+fn test_snapshot_recovery() {
+    create_test_dir("./files/snapshot_recovery", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let log_path = dir.join("log");
+        let snapshot_path = dir.join("snapshot");
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::options()
+            .defer_store_updates(true)
+            .open(&log_path, store.clone())
+            .unwrap();
+        commit_values(&mut redo_log, 0..100);
+
+        // A transaction is still active when the snapshot is taken, and
+        // commits after it.
+        let active = redo_log.start();
+        redo_log.write(active, 100, "active".to_string()).unwrap();
+        let snapshot = redo_log.snapshot(&snapshot_path).unwrap();
+        assert_eq!(snapshot.active, vec![active]);
+        assert_eq!(snapshot.last_tid, active);
+
+        commit_values(&mut redo_log, 100..120);
+        redo_log.commit(active).unwrap();
+        commit_values(&mut redo_log, 120..130);
+        let committed = store.map();
+        let uncommitted = redo_log.start();
+        redo_log
+            .write(uncommitted, 1, "uncommitted".to_string())
+            .unwrap();
+        redo_log.flush_commits().unwrap();
+        crash_log(redo_log, &log_path).unwrap();
+
+        let full_path = dir.join("full");
+        fs::copy(&log_path, &full_path).unwrap();
+        let full_store = TestStore::new();
+        let full_log = RedoLog::new(&full_path, full_store.clone()).unwrap();
+
+        let snapshot_store = TestStore::new();
+        let snapshot_log = RedoLog::options()
+            .defer_store_updates(true)
+            .open_with_snapshot(&snapshot_path, &log_path, snapshot_store.clone())
+            .unwrap();
+
+        assert_eq!(full_store.map(), committed);
+        assert_eq!(snapshot_store.map(), committed);
+        assert_eq!(snapshot_log.last_tid(), full_log.last_tid());
+        // Recovery stops reading at the start of the transaction that
+        // was active when the snapshot was taken.
+        assert!(
+            snapshot_log.recovery_stats().records_read * 3 < full_log.recovery_stats().records_read
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_snapshot_after_last_entry() {
+    create_test_dir("./files/snapshot_last_entry", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let log_path = dir.join("log");
+        let snapshot_path = dir.join("snapshot");
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new(&log_path, store.clone()).unwrap();
+        commit_values(&mut redo_log, 0..20);
+        let last_tid = redo_log.last_tid();
+        redo_log.snapshot(&snapshot_path).unwrap();
+        crash_log(redo_log, &log_path).unwrap();
+
+        // Nothing after the snapshot is replayed.
+        let snapshot_store = TestStore::new();
+        let redo_log =
+            RedoLog::open_with_snapshot(&snapshot_path, &log_path, snapshot_store.clone()).unwrap();
+        assert_eq!(snapshot_store.map(), store.map());
+        assert_eq!(redo_log.last_tid(), last_tid);
+        // Only the last entry, at the snapshot's LSN, is read back.
+        assert!(redo_log.recovery_stats().records_read <= 2);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_snapshot_with_uncommitted_writes() {
+    create_test_dir("./files/snapshot_uncommitted", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let log_path = dir.join("log");
+        let snapshot_path = dir.join("snapshot");
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new(&log_path, store.clone()).unwrap();
+        commit_values(&mut redo_log, 0..20);
+
+        // The store already holds the active transaction's write, so the
+        // snapshot is refused until it finishes.
+        let active = redo_log.start();
+        redo_log
+            .write(active, 1, "uncommitted".to_string())
+            .unwrap();
+        match redo_log.snapshot(&snapshot_path) {
+            Err(LogError::ActiveTransactions(tids)) => assert_eq!(tids, vec![active]),
+            other => panic!("Expected ActiveTransactions, got {:?}", other),
+        }
+        assert!(!snapshot_path.exists());
+        redo_log.abort(active).unwrap();
+        redo_log.snapshot(&snapshot_path).unwrap();
+        redo_log.close().unwrap();
+        fs::remove_file(&snapshot_path).unwrap();
+
+        // With deferred updates the write stays out of the store, and the
+        // transaction never commits.
+        let mut redo_log = RedoLog::options()
+            .defer_store_updates(true)
+            .open(&log_path, store.clone())
+            .unwrap();
+        let committed = store.map();
+        let active = redo_log.start();
+        redo_log
+            .write(active, 1, "uncommitted".to_string())
+            .unwrap();
+        let snapshot = redo_log.snapshot(&snapshot_path).unwrap();
+        assert_eq!(snapshot.active, vec![active]);
+        crash_log(redo_log, &log_path).unwrap();
+
+        let snapshot_store = TestStore::new();
+        RedoLog::open_with_snapshot(&snapshot_path, &log_path, snapshot_store.clone()).unwrap();
+        assert_eq!(snapshot_store.map(), committed);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_open_without_snapshot() {
+    create_test_dir("./files/snapshot_missing", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let log_path = dir.join("log");
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new(&log_path, store.clone()).unwrap();
+        commit_values(&mut redo_log, 0..20);
+        redo_log.close().unwrap();
+
+        let recovered = TestStore::new();
+        RedoLog::open_with_snapshot(&dir.join("snapshot"), &log_path, recovered.clone()).unwrap();
+        assert_eq!(recovered.map(), store.map());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_truncated_snapshot() {
+    create_test_dir("./files/snapshot_truncated", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let log_path = dir.join("log");
+        let snapshot_path = dir.join("snapshot");
+        let snapshot = Snapshot {
+            lsn: 0,
+            last_tid: 0,
+            active: vec![],
+        };
+        let values: Vec<_> = (0..100).map(|i| (i, "v".repeat(1000))).collect();
+        snapshot
+            .write::<TestData, _, _>(&snapshot_path, values.clone())
+            .unwrap();
+
+        let (loaded, entries) = Snapshot::load::<TestData, _>(&snapshot_path).unwrap();
+        assert_eq!(loaded, snapshot);
+        let loaded: Vec<_> = entries.map(Result::unwrap).collect();
+        assert!(loaded == values);
+
+        // A record cut in half fails its checksum.
+        let bytes = fs::read(&snapshot_path).unwrap();
+        fs::write(&snapshot_path, &bytes[..bytes.len() - 500]).unwrap();
+        let (_, entries) = Snapshot::load::<TestData, _>(&snapshot_path).unwrap();
+        let results: Vec<_> = entries.collect();
+        assert!(results.last().unwrap().is_err());
+        assert!(results.iter().filter(|result| result.is_ok()).count() < values.len());
+
+        // The snapshot loses its last block.
+        let end = (bytes.len() as u64 / BLOCK_SIZE as u64 - 1) * BLOCK_SIZE as u64;
+        fs::write(&snapshot_path, &bytes[..end as usize]).unwrap();
+        let (_, entries) = Snapshot::load::<TestData, _>(&snapshot_path).unwrap();
+        let results: Vec<_> = entries.collect();
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(results.len() < values.len());
+
+        match RedoLog::open_with_snapshot(&snapshot_path, &log_path, TestStore::new()) {
+            Err(LogError::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            _ => panic!("Expected the truncated snapshot to fail to load"),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_corrupted_snapshot() {
+    create_test_dir("./files/snapshot_corrupted", |dir| {
+        let dir = Path::new(dir);
+        fs::create_dir_all(dir).unwrap();
+        let snapshot_path = dir.join("snapshot");
+        let snapshot = Snapshot {
+            lsn: 0,
+            last_tid: 0,
+            active: vec![],
+        };
+        let values: Vec<_> = (0..10).map(|i| (i, "v".repeat(100))).collect();
+        snapshot
+            .write::<TestData, _, _>(&snapshot_path, values)
+            .unwrap();
+
+        // The snapshot's position fails its checksum, and the error says why.
+        let mut bytes = fs::read(&snapshot_path).unwrap();
+        bytes[BLOCK_SIZE as usize + HEADER_SIZE + 5] ^= 1;
+        fs::write(&snapshot_path, &bytes).unwrap();
+        let err = match Snapshot::load::<TestData, _>(&snapshot_path) {
+            Err(err) => err,
+            Ok(_) => panic!("Expected the corrupted snapshot to fail to load"),
+        };
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let source = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<SerializeError>());
+        assert!(
+            matches!(source, Some(SerializeError::Corrupted(_))),
+            "{:?}",
+            err
+        );
+    })
+    .unwrap();
+}
@@ -0,0 +1,186 @@
+extern crate disk_utils;
+
+mod common;
+
+use common::TestStore;
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::record::{Record, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{LogOptions, SyncPolicy};
+
+fn options(sync: SyncPolicy) -> LogOptions {
+    LogOptions {
+        sync,
+        ..LogOptions::default()
+    }
+}
+
+#[test]
+fn test_writer_sync() {
+    create_test_file("./files/writer_sync", |_, file| {
+        let record = Record::new(RecordType::Full, vec![1; 10]).unwrap();
+        let mut writer = Writer::new(file.try_clone().unwrap()).unwrap();
+        for _ in 0..3 {
+            writer.append(&record).unwrap();
+        }
+        assert_eq!(writer.synced_lsn(), 0);
+        assert_eq!(writer.unsynced_bytes(), 3 * (HEADER_SIZE + 10) as u64);
+
+        writer.sync().unwrap();
+        assert_eq!(writer.synced_lsn(), 3);
+        assert_eq!(writer.unsynced_bytes(), 0);
+
+        // Records already in the file count as synced.
+        writer.append(&record).unwrap();
+        let writer = Writer::new(file).unwrap();
+        assert_eq!(writer.synced_lsn(), 4);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_sync_policies() {
+    create_test_file("./files/redo_log_sync_never", |path, _| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(redo_log.synced_lsn(), 0);
+    })
+    .unwrap();
+
+    create_test_file("./files/redo_log_sync_on_commit", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, store, options(SyncPolicy::OnCommit)).unwrap();
+        for i in 0..5 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "a".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+            // The commit record is the last record flushed.
+            assert_eq!(Some(redo_log.synced_lsn()), redo_log.last_flushed_lsn());
+        }
+        let synced = redo_log.synced_lsn();
+        redo_log.checkpoint().unwrap();
+        assert_eq!(redo_log.synced_lsn(), synced);
+    })
+    .unwrap();
+
+    create_test_file("./files/redo_log_sync_on_checkpoint", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, store, options(SyncPolicy::OnCheckpoint)).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+        assert_eq!(redo_log.synced_lsn(), 0);
+        redo_log.checkpoint().unwrap();
+        assert_eq!(Some(redo_log.synced_lsn()), redo_log.last_flushed_lsn());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_sync_every_n_bytes() {
+    create_test_file("./files/redo_log_sync_every_n_bytes", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log =
+            RedoLog::new_with_options(path, store, options(SyncPolicy::EveryNBytes(500))).unwrap();
+        let mut syncs = 0;
+        for i in 0..100 {
+            let synced = redo_log.synced_lsn();
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "a".repeat(20)).unwrap();
+            redo_log.commit(tid).unwrap();
+            if redo_log.synced_lsn() != synced {
+                syncs += 1;
+            }
+        }
+        // Each transaction writes 3 records of at least 15 bytes each.
+        assert!(syncs > 5);
+        assert!(syncs < 50);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_sync_on_commit() {
+    create_test_file("./files/undo_log_sync_on_commit", |path, _| {
+        let store = TestStore::new();
+        let mut undo_log =
+            UndoLog::new_with_options(path, store, options(SyncPolicy::OnCommit)).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        assert_eq!(undo_log.synced_lsn(), 0);
+        undo_log.commit(tid).unwrap();
+        assert_eq!(Some(undo_log.synced_lsn()), undo_log.last_flushed_lsn());
+    })
+    .unwrap();
+
+    create_test_file("./files/undo_log_sync_on_checkpoint", |path, _| {
+        let store = TestStore::new();
+        let mut undo_log =
+            UndoLog::new_with_options(path, store, options(SyncPolicy::OnCheckpoint)).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        undo_log.checkpoint().unwrap();
+        assert_eq!(Some(undo_log.synced_lsn()), undo_log.last_flushed_lsn());
+
+        // Committing the last transaction in the checkpoint ends the checkpoint.
+        let tid2 = undo_log.start();
+        undo_log.commit(tid).unwrap();
+        assert_eq!(Some(undo_log.synced_lsn()), undo_log.last_flushed_lsn());
+        let synced = undo_log.synced_lsn();
+        undo_log.commit(tid2).unwrap();
+        assert_eq!(undo_log.synced_lsn(), synced);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recovery_syncs_aborts() {
+    create_test_file("./files/redo_log_recovery_sync", |path, _| {
+        let options = || options(SyncPolicy::OnCheckpoint);
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, 1, "a".to_string()).unwrap();
+        // Committing another transaction flushes the uncommitted write.
+        let other = redo_log.start();
+        redo_log.commit(other).unwrap();
+        drop(redo_log);
+
+        let redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        let last = redo_log.last_flushed_lsn();
+        assert!(last.is_some());
+        assert_eq!(Some(redo_log.synced_lsn()), last);
+    })
+    .unwrap();
+
+    create_test_file("./files/undo_log_recovery_sync", |path, _| {
+        let options = || options(SyncPolicy::OnCommit);
+        let mut undo_log = UndoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        drop(undo_log);
+
+        let undo_log = UndoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        let last = undo_log.last_flushed_lsn();
+        assert!(last.is_some());
+        assert_eq!(Some(undo_log.synced_lsn()), last);
+    })
+    .unwrap();
+
+    create_test_file("./files/undo_log_recovery_sync_never", |path, _| {
+        let mut undo_log = UndoLog::new(path, TestStore::new()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        drop(undo_log);
+
+        let undo_log = UndoLog::new(path, TestStore::new()).unwrap();
+        assert!(Some(undo_log.synced_lsn()) < undo_log.last_flushed_lsn());
+    })
+    .unwrap();
+}
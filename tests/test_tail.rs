@@ -0,0 +1,189 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+use common::TestStore;
+use disk_utils::testing::{create_test_file, create_two_test_files};
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::SharedFile;
+use disk_utils::wal::record::BlockFormat;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::tail::TailIterator;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::LogOptions;
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    }
+}
+
+#[test]
+fn test_tail_iterator_polls() {
+    create_test_file("./files/tail_polls", |_, file| {
+        let mut writer = Writer::with_format(file, format()).unwrap();
+        let reader = SharedFile::new(writer.file()).unwrap();
+        let mut tail = TailIterator::<_, String>::new(reader, format());
+        assert_eq!(tail.try_next().unwrap(), None);
+        assert_eq!(tail.position(), None);
+
+        writer.append_serializable(&"a".to_string()).unwrap();
+        writer.append_serializable(&"b".to_string()).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some("a".to_string()));
+        assert_eq!(tail.try_next().unwrap(), Some("b".to_string()));
+        assert_eq!(tail.try_next().unwrap(), None);
+        let position = tail.position().unwrap();
+
+        // An entry spanning several blocks is read once it's complete.
+        let long = "c".repeat(1000);
+        writer.append_serializable(&long).unwrap();
+        writer.append_serializable(&"d".to_string()).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some(long.clone()));
+        assert_eq!(tail.try_next().unwrap(), Some("d".to_string()));
+        assert_eq!(tail.try_next().unwrap(), None);
+
+        // Resuming from a saved position reads the entries after it again.
+        let reader = SharedFile::new(writer.file()).unwrap();
+        let mut resumed = TailIterator::<_, String>::from_cursor(reader, format(), position);
+        assert_eq!(resumed.try_next().unwrap(), Some(long));
+        assert_eq!(resumed.try_next().unwrap(), Some("d".to_string()));
+        assert_eq!(resumed.try_next().unwrap(), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_tail_iterator_continues_after_padding() {
+    create_test_file("./files/tail_padding", |_, file| {
+        let mut writer = Writer::with_format(file, format()).unwrap();
+        let reader = SharedFile::new(writer.file()).unwrap();
+        let mut tail = TailIterator::<_, String>::new(reader, format());
+
+        writer.append_serializable(&"a".to_string()).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some("a".to_string()));
+        assert_eq!(tail.try_next().unwrap(), None);
+
+        // The block the tail stopped in is padded after it was read.
+        writer.pad_to_block_boundary().unwrap();
+        assert_eq!(tail.try_next().unwrap(), None);
+        writer.append_serializable(&"b".to_string()).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some("b".to_string()));
+
+        // Entries that don't fit in the rest of a block start in the next one.
+        let fill = "x".repeat(200);
+        writer.append_serializable(&fill).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some(fill));
+        writer.append_serializable(&"c".repeat(40)).unwrap();
+        assert_eq!(tail.try_next().unwrap(), Some("c".repeat(40)));
+        assert_eq!(tail.try_next().unwrap(), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_tail_iterator_waits_for_incomplete_entries() {
+    create_two_test_files(
+        "./files/tail_incomplete_source",
+        "./files/tail_incomplete",
+        |_, path, file, mut target| {
+            let mut writer = Writer::with_format(file, format()).unwrap();
+            writer.append_serializable(&"a".to_string()).unwrap();
+            let start = writer.position();
+            writer.append_serializable(&"b".repeat(600)).unwrap();
+            let end = writer.position();
+            writer.sync().unwrap();
+            let bytes = fs::read("./files/tail_incomplete_source").unwrap();
+
+            // The tailed file is written the way a writer appends the bytes,
+            // stopping partway through the records of the second entry.
+            let reader = SharedFile::new(&target).unwrap();
+            let mut tail = TailIterator::<_, String>::new(reader, format());
+            target.write_all(&bytes[..start as usize]).unwrap();
+            assert_eq!(tail.try_next().unwrap(), Some("a".to_string()));
+
+            for cut in [start + 5, start + 300, end - 3] {
+                let len = fs::metadata(path).unwrap().len();
+                target
+                    .write_all(&bytes[len as usize..cut as usize])
+                    .unwrap();
+                assert_eq!(tail.try_next().unwrap(), None);
+            }
+            target.write_all(&bytes[(end - 3) as usize..]).unwrap();
+            assert_eq!(tail.try_next().unwrap(), Some("b".repeat(600)));
+            assert_eq!(tail.try_next().unwrap(), None);
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_wait_next_times_out_and_ends_with_writer() {
+    create_test_file("./files/tail_wait_next", |_, file| {
+        let mut writer = Writer::with_format(file, format()).unwrap();
+        let reader = SharedFile::new(writer.file()).unwrap();
+        let mut tail = TailIterator::<_, String>::new(reader, format()).notifier(writer.notifier());
+
+        let timeout = Some(Duration::from_millis(10));
+        assert_eq!(tail.wait_next(timeout).unwrap(), None);
+        writer.append_serializable(&"a".to_string()).unwrap();
+        assert_eq!(tail.wait_next(timeout).unwrap(), Some("a".to_string()));
+
+        drop(writer);
+        assert_eq!(tail.wait_next(None).unwrap(), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_subscribe() {
+    const TRANSACTIONS: u64 = 100;
+
+    create_test_file("./files/tail_subscribe", |path, _| {
+        let options = LogOptions {
+            block_size: 256,
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        let mut tail = redo_log.subscribe().unwrap();
+
+        let tailer = thread::spawn(move || {
+            let mut entries = Vec::new();
+            loop {
+                let entry = tail
+                    .wait_next(Some(Duration::from_secs(10)))
+                    .unwrap()
+                    .expect("Timed out waiting for an entry");
+                let done = entry == SingleLogEntry::Transaction(Transaction::Commit(TRANSACTIONS));
+                entries.push(entry);
+                if done {
+                    return entries;
+                }
+            }
+        });
+
+        let mut expected = Vec::new();
+        for i in 0..TRANSACTIONS {
+            let tid = redo_log.start();
+            expected.push(SingleLogEntry::Transaction(Transaction::Start(tid)));
+            // Values of different sizes leave different amounts of padding.
+            for key in 0..(i % 3) as i32 {
+                let value = "v".repeat((i as usize * 7) % 300);
+                redo_log.write(tid, key, value.clone()).unwrap();
+                expected.push(SingleLogEntry::ChangeEntry(ChangeEntry { tid, key, value }));
+            }
+            redo_log.commit(tid).unwrap();
+            expected.push(SingleLogEntry::Transaction(Transaction::Commit(tid)));
+        }
+
+        let entries = tailer.join().unwrap();
+        assert_eq!(entries.len(), expected.len());
+        assert!(entries == expected);
+    })
+    .unwrap();
+}
@@ -0,0 +1,362 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Cursor;
+use std::ops::Range;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, RecordType};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::{
+    read_serializable, read_serializable_backwards, split_bytes_for_append, LogOptions, LogStore,
+    SerializeError,
+};
+use disk_utils::Serializable;
+
+const BLOCK_SIZE: i64 = 256;
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: BLOCK_SIZE,
+        ..BlockFormat::default()
+    }
+}
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        ..LogOptions::default()
+    }
+}
+
+/// Returns the offsets in the log where the records of its last chain of
+/// records end, except for the chain's last record. Cutting the log at any
+/// of them leaves the last entry torn.
+fn torn_offsets(path: &str) -> (u64, Vec<u64>) {
+    let mut file = File::open(path).unwrap();
+    let mut iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format()).unwrap();
+    let mut chain: Vec<(RecordType, Range<u64>)> = Vec::new();
+    while let Some(record) = iter.try_next().unwrap() {
+        if record.record_type == RecordType::First {
+            chain.clear();
+        }
+        chain.push((record.record_type, iter.last_record_range().unwrap()));
+    }
+    assert_eq!(chain[0].0, RecordType::First);
+    let start = chain[0].1.start;
+    let ends = chain
+        .iter()
+        .take_while(|(record_type, _)| *record_type != RecordType::Last)
+        .map(|(_, range)| range.end)
+        .collect();
+    (start, ends)
+}
+
+#[test]
+fn test_torn_entry_at_tail() {
+    let entries: Vec<String> = vec!["a".repeat(10), "b".repeat(600), "c".repeat(30)];
+    let torn = "d".repeat(1000);
+    let mut bytes = Vec::new();
+    torn.serialize(&mut bytes).unwrap();
+
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    for entry in &entries {
+        writer.append_serializable(entry).unwrap();
+    }
+    let records = split_bytes_for_append(&bytes, writer.remaining_in_block(), format()).unwrap();
+    assert!(records.len() > 3);
+
+    // Stop the writer after each of the records before the entry's last.
+    for written in 1..records.len() {
+        let mut writer = Writer::with_format(writer.file().clone(), format()).unwrap();
+        let start = writer.append(&records[0]).unwrap();
+        for record in &records[1..written] {
+            writer.append(record).unwrap();
+        }
+        let end = writer.position();
+        let mut cursor = writer.into_inner();
+
+        let mut iter =
+            WalIterator::with_format(&mut cursor, ReadDirection::Forward, format()).unwrap();
+        for entry in &entries {
+            assert_eq!(&read_serializable::<String>(&mut iter).unwrap(), entry);
+        }
+        match read_serializable::<String>(&mut iter) {
+            Err(SerializeError::TornEntry {
+                start: torn_start,
+                end: torn_end,
+            }) => assert_eq!(torn_start..torn_end, start..end),
+            result => panic!("Expected a torn entry, got {:?}", result),
+        }
+
+        // Going backwards, the torn entry is skipped over to the entries before it.
+        let mut iter =
+            WalIterator::with_format(&mut cursor, ReadDirection::Backward, format()).unwrap();
+        match read_serializable_backwards::<String>(&mut iter) {
+            Err(SerializeError::TornEntry {
+                start: torn_start,
+                end: torn_end,
+            }) => assert_eq!(torn_start..torn_end, start..end),
+            result => panic!("Expected a torn entry, got {:?}", result),
+        }
+        for entry in entries.iter().rev() {
+            assert_eq!(
+                &read_serializable_backwards::<String>(&mut iter).unwrap(),
+                entry
+            );
+        }
+    }
+}
+
+#[test]
+fn test_incomplete_chain_in_middle_is_corruption() {
+    let torn = "d".repeat(1000);
+    let mut bytes = Vec::new();
+    torn.serialize(&mut bytes).unwrap();
+
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format()).unwrap();
+    writer.append_serializable(&"a".to_string()).unwrap();
+    let records = split_bytes_for_append(&bytes, writer.remaining_in_block(), format()).unwrap();
+    let mut starts = Vec::new();
+    for record in &records[..records.len() - 1] {
+        starts.push(writer.append(record).unwrap());
+    }
+    let full = writer.append_serializable(&"b".to_string()).unwrap();
+    let mut cursor = writer.into_inner();
+
+    let mut iter = WalIterator::with_format(&mut cursor, ReadDirection::Forward, format()).unwrap();
+    assert_eq!(read_serializable::<String>(&mut iter).unwrap(), "a");
+    match read_serializable::<String>(&mut iter) {
+        Err(SerializeError::InvalidTransfer {
+            found: RecordType::Full,
+            previous: Some(RecordType::Middle),
+            offset,
+            ..
+        }) => assert_eq!(offset, full),
+        result => panic!("Expected an invalid transfer, got {:?}", result),
+    }
+
+    let mut iter =
+        WalIterator::with_format(&mut cursor, ReadDirection::Backward, format()).unwrap();
+    assert_eq!(
+        read_serializable_backwards::<String>(&mut iter).unwrap(),
+        "b"
+    );
+    match read_serializable_backwards::<String>(&mut iter) {
+        Err(SerializeError::InvalidTransfer {
+            found: RecordType::Middle,
+            previous: None,
+            offset,
+            record: 0,
+        }) => assert_eq!(offset, *starts.last().unwrap()),
+        result => panic!("Expected an invalid transfer, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_redo_log_truncates_torn_entry() {
+    create_test_file("./files/torn_entry_redo_log", |path, _| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        for i in 1..4 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        // The last transaction's change is split across several blocks.
+        let tid = redo_log.start();
+        redo_log.write(tid, 4, "torn".repeat(200)).unwrap();
+        redo_log.commit(tid).unwrap();
+        drop(redo_log);
+
+        let log = fs::read(path).unwrap();
+        let (start, offsets) = torn_offsets(path);
+        assert!(offsets.len() > 2);
+        for offset in offsets {
+            // Simulate a crash by cutting the log partway through the change.
+            fs::write(path, &log[..offset as usize]).unwrap();
+            let store = TestStore::new();
+            let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+            let expected: HashMap<_, _> = (1..4).map(|i| (i, format!("value {}", i))).collect();
+            assert_eq!(store.map(), expected);
+            // The torn records are removed so new entries follow the intact ones.
+            assert_eq!(fs::metadata(path).unwrap().len(), start);
+
+            let tid = redo_log.start();
+            redo_log.write(tid, 5, "after".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+            drop(redo_log);
+
+            let store = TestStore::new();
+            RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+            assert_eq!(store.get(&5), Some("after".to_string()));
+            assert_eq!(store.map().len(), 4);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_truncates_torn_entry() {
+    create_test_file("./files/torn_entry_undo_log", |path, _| {
+        let store = TestStore::new();
+        let mut undo_log = UndoLog::new_with_options(path, store.clone(), options()).unwrap();
+        for i in 1..4 {
+            let tid = undo_log.start();
+            undo_log
+                .write(tid, i, format!("{} {}", "old".repeat(300), i))
+                .unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+        // The last transaction logs the old value split across several blocks.
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "new".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+
+        let log = fs::read(path).unwrap();
+        let (_, offsets) = torn_offsets(path);
+        assert!(offsets.len() > 2);
+        let expected: HashMap<_, _> = (1..4)
+            .map(|i| (i, format!("{} {}", "old".repeat(300), i)))
+            .collect();
+        for offset in offsets {
+            // The store wasn't flushed before the log was, so it has the old values.
+            fs::write(path, &log[..offset as usize]).unwrap();
+            let store = TestStore::with_contents(expected.clone());
+            let undo_log = UndoLog::new_with_options(path, store.clone(), options()).unwrap();
+            assert_eq!(store.map(), expected);
+            drop(undo_log);
+
+            // Every entry left in the log can be read.
+            let mut file = File::open(path).unwrap();
+            let entries = WalIterator::with_format(&mut file, ReadDirection::Forward, format())
+                .unwrap()
+                .entries::<SingleLogEntry<TestData>>();
+            for entry in entries {
+                entry.unwrap();
+            }
+        }
+    })
+    .unwrap();
+}
+
+/// Appends an uncommitted change split across several blocks to the log,
+/// returning the offsets a dozen cuts spread over its bytes end at and the
+/// offset it starts at.
+fn append_last_entry(path: &str, tid: u64) -> (u64, Vec<u64>) {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    let mut writer = Writer::with_format(file, format()).unwrap();
+    let entry = SingleLogEntry::ChangeEntry(ChangeEntry {
+        tid,
+        key: 10,
+        value: "torn".repeat(200),
+    });
+    let start = writer
+        .append_serializable::<SingleLogEntry<TestData>>(&entry)
+        .unwrap();
+    let len = writer.position();
+    assert!(len - start > 2 * BLOCK_SIZE as u64);
+    let offsets = (0..12)
+        .map(|i| start + 1 + i * (len - start - 2) / 11)
+        .collect();
+    (start, offsets)
+}
+
+#[test]
+fn test_redo_log_torn_at_any_offset() {
+    create_test_file("./files/torn_offset_redo_log", |path, _| {
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        for i in 1..4 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, format!("value {}", i)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        let uncommitted = redo_log.start();
+        let tid = redo_log.start();
+        redo_log.commit(tid).unwrap();
+        drop(redo_log);
+        let (start, offsets) = append_last_entry(path, uncommitted);
+        let log = fs::read(path).unwrap();
+
+        // Recover the log without its last entry to compare against.
+        fs::write(path, &log[..start as usize]).unwrap();
+        let expected = TestStore::new();
+        let redo_log = RedoLog::new_with_options(path, expected.clone(), options()).unwrap();
+        let expected_tid = redo_log.last_tid();
+        drop(redo_log);
+        let expected_log = fs::read(path).unwrap();
+
+        for offset in offsets {
+            fs::write(path, &log[..offset as usize]).unwrap();
+            let store = TestStore::new();
+            let redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+            assert_eq!(store.map(), expected.map(), "torn at {}", offset);
+            assert_eq!(redo_log.last_tid(), expected_tid, "torn at {}", offset);
+            drop(redo_log);
+            assert!(
+                fs::read(path).unwrap() == expected_log,
+                "torn at {}",
+                offset
+            );
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_torn_at_any_offset() {
+    create_test_file("./files/torn_offset_undo_log", |path, _| {
+        let mut undo_log = UndoLog::new_with_options(path, TestStore::new(), options()).unwrap();
+        for i in 1..4 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, format!("value {}", i)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+        let uncommitted = undo_log.start();
+        undo_log
+            .write(uncommitted, 1, "uncommitted".to_string())
+            .unwrap();
+        drop(undo_log);
+        let (start, offsets) = append_last_entry(path, uncommitted);
+        let log = fs::read(path).unwrap();
+        let store = || {
+            let map = (1..4).map(|i| (i, format!("value {}", i))).collect();
+            TestStore::with_contents(map)
+        };
+
+        fs::write(path, &log[..start as usize]).unwrap();
+        let expected = store();
+        let undo_log = UndoLog::new_with_options(path, expected.clone(), options()).unwrap();
+        let expected_tid = undo_log.last_tid();
+        drop(undo_log);
+        let expected_log = fs::read(path).unwrap();
+
+        for offset in offsets {
+            fs::write(path, &log[..offset as usize]).unwrap();
+            let store = store();
+            let undo_log = UndoLog::new_with_options(path, store.clone(), options()).unwrap();
+            assert_eq!(store.map(), expected.map(), "torn at {}", offset);
+            assert_eq!(undo_log.last_tid(), expected_tid, "torn at {}", offset);
+            drop(undo_log);
+            assert!(
+                fs::read(path).unwrap() == expected_log,
+                "torn at {}",
+                offset
+            );
+        }
+    })
+    .unwrap();
+}
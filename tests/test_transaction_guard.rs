@@ -0,0 +1,138 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::panic;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::entries::{SingleLogEntry, Transaction};
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::LogStore;
+
+fn log_entries(file: &mut File) -> Vec<SingleLogEntry<TestData>> {
+    WalIterator::new(file, ReadDirection::Forward)
+        .unwrap()
+        .entries()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn test_redo_log_drop_aborts() {
+    create_test_file("./files/guard_drop_redo_log", |path, mut file| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        let tid = {
+            let mut txn = redo_log.transaction();
+            txn.write(10, "A".to_string()).unwrap();
+            txn.tid()
+        };
+        let mut txn = redo_log.transaction();
+        txn.write(20, "B".to_string()).unwrap();
+        txn.commit().unwrap();
+
+        let entries = log_entries(&mut file);
+        assert!(entries.contains(&SingleLogEntry::Transaction(Transaction::Abort(tid))));
+        assert_eq!(
+            entries.last(),
+            Some(&SingleLogEntry::Transaction(Transaction::Commit(tid + 1)))
+        );
+
+        crash_log(redo_log, path).unwrap();
+        let store = TestStore::new();
+        RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&10), None);
+        assert_eq!(store.get(&20), Some("B".to_string()));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_drop_aborts() {
+    create_test_file("./files/guard_drop_undo_log", |path, mut file| {
+        let store = TestStore::with_contents(vec![(10, "old".to_string())].into_iter().collect());
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = {
+            let mut txn = undo_log.transaction();
+            txn.write(10, "A".to_string()).unwrap();
+            txn.write(20, "B".to_string()).unwrap();
+            txn.tid()
+        };
+        assert_eq!(store.get(&10), Some("old".to_string()));
+        assert_eq!(store.get(&20), None);
+
+        let txn = undo_log.transaction();
+        txn.abort().unwrap();
+        let entries = log_entries(&mut file);
+        assert_eq!(
+            &entries[entries.len() - 2..],
+            &[
+                SingleLogEntry::Transaction(Transaction::Start(tid + 1)),
+                SingleLogEntry::Transaction(Transaction::Abort(tid + 1)),
+            ]
+        );
+        assert!(entries.contains(&SingleLogEntry::Transaction(Transaction::Abort(tid))));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_panic_inside_transaction() {
+    create_test_file("./files/guard_panic_undo_log", |path, mut file| {
+        let store = TestStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let mut txn = undo_log.transaction();
+        txn.write(10, "A".to_string()).unwrap();
+        txn.commit().unwrap();
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let mut txn = undo_log.transaction();
+            txn.write(10, "B".to_string()).unwrap();
+            txn.write(20, "C".to_string()).unwrap();
+            panic!("failed partway through the transaction");
+        }));
+        assert!(result.is_err());
+        assert_eq!(store.get(&10), Some("A".to_string()));
+        assert_eq!(store.get(&20), None);
+
+        // The log keeps working, and recovery has nothing left to roll back.
+        let mut txn = undo_log.transaction();
+        txn.write(30, "D".to_string()).unwrap();
+        txn.commit().unwrap();
+        drop(undo_log);
+        let entries = log_entries(&mut file);
+        assert!(entries.contains(&SingleLogEntry::Transaction(Transaction::Abort(2))));
+
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let expected: HashMap<_, _> = vec![(10, "A".to_string()), (30, "D".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(store.map(), expected);
+        assert_eq!(undo_log.start(), 4);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_write_batch() {
+    create_test_file("./files/guard_write_batch", |path, _| {
+        let store = TestStore::new();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let mut txn = redo_log.transaction();
+        txn.write_batch((0..3).map(|i| (i, format!("value {}", i))))
+            .unwrap();
+        txn.commit().unwrap();
+        assert_eq!(store.get(&2), Some("value 2".to_string()));
+
+        {
+            let mut txn = redo_log.transaction();
+            txn.write_batch(vec![(5, "dropped".to_string())]).unwrap();
+        }
+        assert_eq!(redo_log.active_transactions(), Vec::<u64>::new());
+    })
+    .unwrap();
+}
@@ -0,0 +1,194 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use common::TestStore;
+use disk_utils::testing::create_two_test_files;
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{LogOptions, LogStore};
+
+const BLOCK_SIZE: i64 = 256;
+
+fn options() -> LogOptions {
+    LogOptions {
+        block_size: BLOCK_SIZE,
+        ..LogOptions::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    /// Starts the transaction with the index.
+    Start(usize),
+    Write(usize, i32),
+    Commit(usize),
+    Checkpoint,
+}
+
+/// Returns cycles of transactions committed around checkpoints, with a
+/// transaction in each cycle that is active across the checkpoint.
+fn workload() -> Vec<Op> {
+    let mut ops = Vec::new();
+    let mut txn = 0;
+    for cycle in 0..4 {
+        let long = txn;
+        ops.push(Op::Start(long));
+        ops.push(Op::Write(long, 100 + cycle));
+        for i in 0..5 {
+            txn += 1;
+            ops.push(Op::Start(txn));
+            ops.push(Op::Write(txn, i));
+            ops.push(Op::Write(txn, cycle));
+            ops.push(Op::Commit(txn));
+        }
+        ops.push(Op::Checkpoint);
+        ops.push(Op::Write(long, 200 + cycle));
+        ops.push(Op::Commit(long));
+        txn += 1;
+    }
+    ops
+}
+
+/// Runs the operations on a log, truncating it after each checkpoint if
+/// `truncate` is set, and returns the store.
+macro_rules! run_workload {
+    ($log:ident, $path:expr, $ops:expr, $truncate:expr, $store:expr) => {{
+        let mut log = $log::new_with_options($path, $store.clone(), options()).unwrap();
+        let mut tids = HashMap::new();
+        for (i, op) in $ops.iter().enumerate() {
+            match *op {
+                Op::Start(txn) => {
+                    tids.insert(txn, log.start());
+                }
+                Op::Write(txn, key) => log
+                    .write(tids[&txn], key, format!("op {} txn {}", i, txn))
+                    .unwrap(),
+                Op::Commit(txn) => log.commit(tids[&txn]).unwrap(),
+                Op::Checkpoint => {
+                    log.checkpoint().unwrap();
+                    if $truncate {
+                        log.truncate_before_checkpoint().unwrap();
+                    }
+                }
+            }
+        }
+    }};
+}
+
+#[test]
+fn test_redo_log_truncation_matches_baseline() {
+    create_two_test_files(
+        "./files/truncate_redo_log",
+        "./files/truncate_redo_log_baseline",
+        |path, baseline, _, _| {
+            let ops = workload();
+            let mut sizes = Vec::new();
+            for crash in 1..=ops.len() {
+                fs::write(path, []).unwrap();
+                fs::write(baseline, []).unwrap();
+                let store = TestStore::new();
+                let baseline_store = TestStore::new();
+                run_workload!(RedoLog, path, ops[..crash], true, store);
+                run_workload!(RedoLog, baseline, ops[..crash], false, baseline_store);
+
+                // Recover both logs after a crash.
+                let store = store.after_crash();
+                let baseline_store = baseline_store.after_crash();
+                let redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+                RedoLog::new_with_options(baseline, baseline_store.clone(), options()).unwrap();
+                assert_eq!(store.map(), baseline_store.map(), "crash after {}", crash);
+                drop(redo_log);
+                sizes.push((
+                    fs::metadata(path).unwrap().len(),
+                    fs::metadata(baseline).unwrap().len(),
+                ));
+            }
+            let (truncated, untruncated) = *sizes.last().unwrap();
+            assert!(truncated * 2 < untruncated);
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_undo_log_truncation_matches_baseline() {
+    create_two_test_files(
+        "./files/truncate_undo_log",
+        "./files/truncate_undo_log_baseline",
+        |path, baseline, _, _| {
+            let ops = workload();
+            let mut sizes = Vec::new();
+            for crash in 1..=ops.len() {
+                fs::write(path, []).unwrap();
+                fs::write(baseline, []).unwrap();
+                let store = TestStore::new();
+                let baseline_store = TestStore::new();
+                run_workload!(UndoLog, path, ops[..crash], true, store);
+                run_workload!(UndoLog, baseline, ops[..crash], false, baseline_store);
+
+                let store = store.after_crash();
+                let baseline_store = baseline_store.after_crash();
+                let undo_log = UndoLog::new_with_options(path, store.clone(), options()).unwrap();
+                UndoLog::new_with_options(baseline, baseline_store.clone(), options()).unwrap();
+                assert_eq!(store.map(), baseline_store.map(), "crash after {}", crash);
+                drop(undo_log);
+                sizes.push((
+                    fs::metadata(path).unwrap().len(),
+                    fs::metadata(baseline).unwrap().len(),
+                ));
+            }
+            let (truncated, untruncated) = *sizes.last().unwrap();
+            assert!(truncated * 2 < untruncated);
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_crash_before_rewritten_log_replaces_log() {
+    create_two_test_files(
+        "./files/truncate_crash_redo_log",
+        "./files/truncate_crash_redo_log_baseline",
+        |path, baseline, _, _| {
+            let ops = workload();
+            let store = TestStore::new();
+            let baseline_store = TestStore::new();
+            run_workload!(RedoLog, path, ops[..ops.len() - 3], true, store);
+            run_workload!(
+                RedoLog,
+                baseline,
+                ops[..ops.len() - 3],
+                false,
+                baseline_store
+            );
+
+            // A crash partway through copying leaves part of the new log next to the log.
+            let rewrite = format!("{}.rewrite", path);
+            fs::write(&rewrite, &fs::read(path).unwrap()[..300]).unwrap();
+            let store = store.after_crash();
+            let baseline_store = baseline_store.after_crash();
+            let mut redo_log = RedoLog::new_with_options(path, store.clone(), options()).unwrap();
+            RedoLog::new_with_options(baseline, baseline_store.clone(), options()).unwrap();
+            assert_eq!(store.map(), baseline_store.map());
+
+            // The next truncation replaces the leftover copy.
+            let tid = redo_log.start();
+            redo_log.write(tid, 1, "after".to_string()).unwrap();
+            redo_log.commit(tid).unwrap();
+            redo_log.checkpoint().unwrap();
+            assert!(redo_log.truncate_before_checkpoint().unwrap());
+            assert!(!redo_log.truncate_before_checkpoint().unwrap());
+            assert!(fs::metadata(&rewrite).is_err());
+            drop(redo_log);
+
+            let recovered = store.after_crash();
+            RedoLog::new_with_options(path, recovered.clone(), options()).unwrap();
+            assert_eq!(recovered.get(&1), Some("after".to_string()));
+        },
+    )
+    .unwrap();
+}
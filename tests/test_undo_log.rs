@@ -1,14 +1,24 @@
 extern crate disk_utils;
 
 use std::collections::HashMap;
+use std::fs;
 use std::io;
+use std::io::Cursor;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use disk_utils::testing::create_test_file;
+use disk_utils::testing::{
+    crash_log, create_test_file, create_two_test_files, FaultPolicy, FaultyFile, InMemoryBackend,
+};
 use disk_utils::wal::entries::{ChangeEntry, Checkpoint, InsertEntry, SingleLogEntry, Transaction};
 use disk_utils::wal::iterator::{ReadDirection, WalIterator};
-use disk_utils::wal::undo_log::UndoLog;
-use disk_utils::wal::{read_serializable, LogData, LogStore};
+use disk_utils::wal::record::{Record, RecordType};
+use disk_utils::wal::undo_log::{CheckpointStatus, UndoLog};
+use disk_utils::wal::{
+    append_to_file, write_serializable, CheckpointPolicy, LogData, LogError, LogOptions, LogStore,
+    Outcome, RecoveryError, SerializeError, WriteAheadPolicy,
+};
+use disk_utils::Serializable;
 
 #[derive(Clone, PartialEq, Debug)]
 struct MyLogData;
@@ -89,7 +99,7 @@ fn test_start() {
         assert_eq!(tid, 1);
         assert_eq!(undo_log.entries().len(), 1);
         assert_eq!(
-            undo_log.entries()[0],
+            *undo_log.entries().next().unwrap(),
             SingleLogEntry::Transaction(Transaction::Start(1))
         );
     })
@@ -100,24 +110,28 @@ fn test_start() {
 fn test_write() {
     create_test_file("./files/write_undo_log", |path, _| {
         let store: MyStore<MyLogData> = MyStore::new();
-        let mut undo_log = UndoLog::new(path, store).unwrap();
+        // Keep the entries in memory so they can be inspected.
+        let mut undo_log = UndoLog::options()
+            .write_ahead(WriteAheadPolicy::OnCommit)
+            .open(path, store)
+            .unwrap();
 
         let tid = undo_log.start();
         assert_eq!(tid, 1);
 
-        undo_log.write(tid, 20, "Hello".to_string());
+        undo_log.write(tid, 20, "Hello".to_string()).unwrap();
 
         assert_eq!(undo_log.entries().len(), 2);
         assert_eq!(
-            undo_log.entries()[1],
+            *undo_log.entries().nth(1).unwrap(),
             SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 20 })
         );
 
-        undo_log.write(tid, 20, "World".to_string());
+        undo_log.write(tid, 20, "World".to_string()).unwrap();
 
         assert_eq!(undo_log.entries().len(), 3);
         assert_eq!(
-            undo_log.entries()[2],
+            *undo_log.entries().nth(2).unwrap(),
             SingleLogEntry::ChangeEntry(ChangeEntry {
                 tid: 1,
                 key: 20,
@@ -135,8 +149,8 @@ fn test_commit() {
         let mut undo_log = UndoLog::new(path, store).unwrap();
         let tid = undo_log.start();
         assert_eq!(tid, 1);
-        undo_log.write(tid, 20, "Hello".to_string());
-        undo_log.write(tid, 20, "World".to_string());
+        undo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        undo_log.write(tid, 20, "World".to_string()).unwrap();
         undo_log.commit(tid).unwrap();
 
         let mut expected_entries = vec![
@@ -150,9 +164,11 @@ fn test_commit() {
             SingleLogEntry::Transaction(Transaction::Commit(1)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
     })
     .unwrap();
@@ -165,22 +181,29 @@ fn test_recover() {
 
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         let tid = undo_log.start();
-        undo_log.write(tid, 20, "Hello".to_string());
+        undo_log.write(tid, 20, "Hello".to_string()).unwrap();
         undo_log.commit(tid).unwrap();
 
         store.set_flush_err(true);
 
         let tid = undo_log.start();
-        undo_log.write(tid, 20, "World".to_string());
-        undo_log.write(tid, 30, "Hello".to_string());
+        undo_log.write(tid, 20, "World".to_string()).unwrap();
+        undo_log.write(tid, 30, "Hello".to_string()).unwrap();
         assert!(undo_log.commit(tid).is_err());
 
         store.set_flush_err(false);
 
+        crash_log(undo_log, path).unwrap();
         // Create a new undo log which should automatically recover data.
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         assert_eq!(undo_log.start(), 3);
 
+        // Recovery reads every entry in the log, each in its own record.
+        let stats = undo_log.recovery_stats();
+        assert_eq!(stats.records_read, 6);
+        assert_eq!(stats.blocks_loaded, 1);
+        assert_eq!(stats.corrupt_records_skipped, 0);
+
         let mut expected_entries = vec![
             SingleLogEntry::Transaction(Transaction::Start(1)),
             SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 20 }),
@@ -195,9 +218,11 @@ fn test_recover() {
             SingleLogEntry::Transaction(Transaction::Abort(2)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
 
         assert_eq!(store.get(&20), Some("Hello".to_string()));
@@ -214,25 +239,26 @@ fn test_multiple_recover() {
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         let tid1 = undo_log.start();
         let tid2 = undo_log.start();
-        undo_log.write(tid1, 20, "Hello".to_string());
-        undo_log.write(tid2, 30, "World".to_string());
-        undo_log.write(tid1, 30, "Blah".to_string());
+        undo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        undo_log.write(tid2, 30, "World".to_string()).unwrap();
+        undo_log.write(tid1, 30, "Blah".to_string()).unwrap();
         undo_log.commit(tid1).unwrap();
-        undo_log.write(tid2, 20, "World".to_string());
+        undo_log.write(tid2, 20, "World".to_string()).unwrap();
         undo_log.commit(tid2).unwrap();
 
         let tid3 = undo_log.start();
         let tid4 = undo_log.start();
 
-        undo_log.write(tid3, 40, "Foo".to_string());
-        undo_log.write(tid4, 30, "Bar".to_string());
+        undo_log.write(tid3, 40, "Foo".to_string()).unwrap();
+        undo_log.write(tid4, 30, "Bar".to_string()).unwrap();
         undo_log.commit(tid3).unwrap();
 
-        undo_log.write(tid4, 50, "Hello".to_string());
+        undo_log.write(tid4, 50, "Hello".to_string()).unwrap();
         store.set_flush_err(true);
         assert!(undo_log.commit(tid4).is_err());
         store.set_flush_err(false);
 
+        crash_log(undo_log, path).unwrap();
         // Create a new undo log which should automatically recover data.
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         assert_eq!(undo_log.start(), 5);
@@ -267,9 +293,11 @@ fn test_multiple_recover() {
             SingleLogEntry::Transaction(Transaction::Abort(4)),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
-            assert_eq!(data, expected_entries.next().unwrap());
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            assert_eq!(data.unwrap(), expected_entries.next().unwrap());
         }
 
         // Test expected state after recovery:
@@ -297,6 +325,7 @@ fn test_add_end_checkpoint() {
         undo_log.commit(tid4).unwrap();
         undo_log.commit(tid2).unwrap();
 
+        crash_log(undo_log, path).unwrap();
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         assert_eq!(undo_log.start(), 5);
 
@@ -313,8 +342,11 @@ fn test_add_end_checkpoint() {
             SingleLogEntry::Checkpoint(Checkpoint::End),
         ]
         .into_iter();
-        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
-        while let Ok(data) = read_serializable::<SingleLogEntry<MyLogData>>(&mut iter) {
+        let entries = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>();
+        for data in entries {
+            let data = data.unwrap();
             if let SingleLogEntry::Checkpoint(Checkpoint::Begin(mut data)) = data {
                 data.sort();
                 assert_eq!(
@@ -338,10 +370,10 @@ fn test_checkpoint_recover_before_end() {
         let tid1 = undo_log.start();
         let tid2 = undo_log.start();
 
-        undo_log.write(tid1, 20, "Hello".to_string());
-        undo_log.write(tid2, 20, "World".to_string());
-        undo_log.write(tid2, 30, "Blah".to_string());
-        undo_log.write(tid1, 30, "Foo".to_string());
+        undo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        undo_log.write(tid2, 20, "World".to_string()).unwrap();
+        undo_log.write(tid2, 30, "Blah".to_string()).unwrap();
+        undo_log.write(tid1, 30, "Foo".to_string()).unwrap();
 
         undo_log.commit(tid1).unwrap();
         undo_log.commit(tid2).unwrap();
@@ -350,22 +382,23 @@ fn test_checkpoint_recover_before_end() {
         let tid4 = undo_log.start();
         let tid5 = undo_log.start();
 
-        undo_log.write(tid3, 20, "A".to_string());
-        undo_log.write(tid5, 30, "B".to_string());
-        undo_log.write(tid4, 30, "C".to_string());
-        undo_log.write(tid4, 50, "D".to_string());
+        undo_log.write(tid3, 20, "A".to_string()).unwrap();
+        undo_log.write(tid5, 30, "B".to_string()).unwrap();
+        undo_log.write(tid4, 30, "C".to_string()).unwrap();
+        undo_log.write(tid4, 50, "D".to_string()).unwrap();
 
         undo_log.commit(tid4).unwrap();
         undo_log.checkpoint().unwrap();
 
         let tid6 = undo_log.start();
-        undo_log.write(tid6, 60, "E".to_string());
+        undo_log.write(tid6, 60, "E".to_string()).unwrap();
         undo_log.commit(tid6).unwrap();
 
         store.set_flush_err(true);
         assert!(undo_log.commit(tid3).is_err());
         store.set_flush_err(false);
 
+        crash_log(undo_log, path).unwrap();
         // Create a new undo log which should automatically recover data.
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         assert_eq!(undo_log.start(), 7);
@@ -387,10 +420,10 @@ fn test_checkpoint_recover_after_end() {
         let tid1 = undo_log.start();
         let tid2 = undo_log.start();
 
-        undo_log.write(tid1, 20, "Hello".to_string());
-        undo_log.write(tid2, 20, "World".to_string());
-        undo_log.write(tid2, 30, "Blah".to_string());
-        undo_log.write(tid1, 30, "Foo".to_string());
+        undo_log.write(tid1, 20, "Hello".to_string()).unwrap();
+        undo_log.write(tid2, 20, "World".to_string()).unwrap();
+        undo_log.write(tid2, 30, "Blah".to_string()).unwrap();
+        undo_log.write(tid1, 30, "Foo".to_string()).unwrap();
 
         undo_log.commit(tid1).unwrap();
         undo_log.commit(tid2).unwrap();
@@ -399,10 +432,10 @@ fn test_checkpoint_recover_after_end() {
         let tid4 = undo_log.start();
         let tid5 = undo_log.start();
 
-        undo_log.write(tid3, 20, "A".to_string());
-        undo_log.write(tid5, 30, "B".to_string());
-        undo_log.write(tid4, 30, "C".to_string());
-        undo_log.write(tid4, 50, "D".to_string());
+        undo_log.write(tid3, 20, "A".to_string()).unwrap();
+        undo_log.write(tid5, 30, "B".to_string()).unwrap();
+        undo_log.write(tid4, 30, "C".to_string()).unwrap();
+        undo_log.write(tid4, 50, "D".to_string()).unwrap();
 
         undo_log.checkpoint().unwrap();
         undo_log.commit(tid4).unwrap();
@@ -410,13 +443,14 @@ fn test_checkpoint_recover_after_end() {
         undo_log.commit(tid5).unwrap();
 
         let tid6 = undo_log.start();
-        undo_log.write(tid6, 60, "E".to_string());
-        undo_log.write(tid6, 30, "F".to_string());
+        undo_log.write(tid6, 60, "E".to_string()).unwrap();
+        undo_log.write(tid6, 30, "F".to_string()).unwrap();
 
         store.set_flush_err(true);
         assert!(undo_log.commit(tid6).is_err());
         store.set_flush_err(false);
 
+        crash_log(undo_log, path).unwrap();
         // Create a new undo log which should automatically recover data.
         let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
         assert_eq!(undo_log.start(), 7);
@@ -428,3 +462,924 @@ fn test_checkpoint_recover_after_end() {
     })
     .unwrap();
 }
+
+#[test]
+fn test_recover_reports_undecodable_entry() {
+    create_test_file("./files/undo_undecodable_entry", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "a".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+
+        // A record that frames correctly but doesn't hold an entry.
+        let record = Record::new(RecordType::Full, vec![0xFF; 10]).unwrap();
+        let end = append_to_file(&mut file, &record).unwrap();
+
+        match UndoLog::new(path, store) {
+            Err(LogError::RecoveryError(RecoveryError {
+                error: SerializeError::IoError(_),
+                entries_recovered: 0,
+                offset: Some(offset),
+            })) => assert_eq!(offset, end),
+            Err(err) => panic!("Expected a serialize error, got {:?}", err),
+            Ok(_) => panic!("Expected recovery to fail"),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_abort() {
+    create_test_file("./files/abort_undo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 10, "A".to_string()).unwrap();
+        undo_log.write(tid, 20, "B".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        let before = store.map.read().unwrap().clone();
+
+        let tid1 = undo_log.start();
+        let tid2 = undo_log.start();
+        undo_log.write(tid1, 10, "C".to_string()).unwrap();
+        undo_log.write(tid1, 30, "D".to_string()).unwrap();
+        undo_log.write(tid2, 40, "E".to_string()).unwrap();
+        // Committing the other transaction flushes the first writes to the log.
+        undo_log.commit(tid2).unwrap();
+        undo_log.write(tid1, 10, "F".to_string()).unwrap();
+        undo_log.write(tid1, 20, "G".to_string()).unwrap();
+        undo_log.write(tid1, 30, "H".to_string()).unwrap();
+        undo_log.abort(tid1).unwrap();
+
+        let mut expected = before.clone();
+        expected.insert(40, "E".to_string());
+        assert_eq!(*store.map.read().unwrap(), expected);
+
+        // Writes to the aborted transaction are rejected.
+        assert!(undo_log.write(tid1, 50, "I".to_string()).is_err());
+        assert_eq!(store.get(&50), None);
+
+        let last = WalIterator::new(&mut file, ReadDirection::Backward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .next_back()
+            .unwrap()
+            .unwrap();
+        assert_eq!(last, SingleLogEntry::Transaction(Transaction::Abort(tid1)));
+
+        // Recovery doesn't roll back the aborted transaction again.
+        drop(undo_log);
+        store.map.write().unwrap().insert(10, "J".to_string());
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&10), Some("J".to_string()));
+        assert_eq!(undo_log.start(), 4);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_abort_ends_checkpoint() {
+    create_test_file("./files/abort_checkpoint_undo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid1 = undo_log.start();
+        let tid2 = undo_log.start();
+        undo_log.write(tid1, 10, "A".to_string()).unwrap();
+        undo_log.write(tid2, 20, "B".to_string()).unwrap();
+        undo_log.checkpoint().unwrap();
+
+        undo_log.commit(tid2).unwrap();
+        undo_log.abort(tid1).unwrap();
+        assert_eq!(store.get(&10), None);
+        assert_eq!(store.get(&20), Some("B".to_string()));
+
+        let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Backward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .rev()
+            .take(2)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                SingleLogEntry::Checkpoint(Checkpoint::End),
+                SingleLogEntry::Transaction(Transaction::Abort(tid1)),
+            ]
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_inactive_transaction() {
+    create_test_file("./files/inactive_transaction_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid1 = undo_log.start();
+        let tid2 = undo_log.start();
+        undo_log.write(tid1, 10, "A".to_string()).unwrap();
+        undo_log.commit(tid1).unwrap();
+        undo_log.abort(tid2).unwrap();
+
+        // Write after commit.
+        match undo_log.write(tid1, 20, "B".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) => assert_eq!(tid, tid1),
+            result => panic!("Expected unknown transaction, got {:?}", result),
+        }
+        // Write after abort.
+        match undo_log.write(tid2, 20, "B".to_string()) {
+            Err(LogError::UnknownTransaction(tid)) => assert_eq!(tid, tid2),
+            result => panic!("Expected unknown transaction, got {:?}", result),
+        }
+        // Commit twice, and a tid that was never started.
+        assert!(matches!(
+            undo_log.commit(tid1),
+            Err(LogError::UnknownTransaction(_))
+        ));
+        assert!(matches!(
+            undo_log.commit(100),
+            Err(LogError::UnknownTransaction(100))
+        ));
+        assert!(matches!(
+            undo_log.abort(tid2),
+            Err(LogError::UnknownTransaction(_))
+        ));
+        assert_eq!(store.get(&20), None);
+        assert_eq!(undo_log.pending_entries(), 0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_policy() {
+    create_test_file("./files/checkpoint_policy_undo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let options = LogOptions {
+            checkpoint: CheckpointPolicy::EveryNCommits(2),
+            ..LogOptions::default()
+        };
+        let mut undo_log = UndoLog::new_with_options(path, store.clone(), options.clone()).unwrap();
+        let long = undo_log.start();
+        undo_log.write(long, 100, "long".to_string()).unwrap();
+        for i in 1..=4 {
+            let tid = undo_log.start();
+            undo_log.write(tid, i, format!("value {}", i)).unwrap();
+            undo_log.commit(tid).unwrap();
+        }
+        // The checkpoint started after the second commit is still waiting
+        // for the long transaction, so no other checkpoint starts.
+        undo_log.commit(long).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 5, "value 5".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+
+        let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(Result::unwrap)
+            .filter(|entry| {
+                matches!(
+                    entry,
+                    SingleLogEntry::Transaction(Transaction::Commit(_))
+                        | SingleLogEntry::Checkpoint(_)
+                )
+            })
+            .collect();
+        let commit = |tid| SingleLogEntry::Transaction(Transaction::Commit(tid));
+        let begin = |tids| SingleLogEntry::Checkpoint(Checkpoint::Begin(tids));
+        let end = SingleLogEntry::Checkpoint(Checkpoint::End);
+        assert_eq!(
+            entries,
+            vec![
+                commit(2),
+                commit(3),
+                begin(vec![long]),
+                commit(4),
+                commit(5),
+                commit(long),
+                end.clone(),
+                begin(vec![]),
+                commit(6),
+                end,
+            ]
+        );
+
+        // Recovery stops at the last checkpoint.
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "uncommitted".to_string()).unwrap();
+        undo_log.write(tid, 6, "uncommitted".to_string()).unwrap();
+        // Committing another transaction flushes the uncommitted changes to the log.
+        let other = undo_log.start();
+        undo_log.commit(other).unwrap();
+        drop(undo_log);
+        let undo_log = UndoLog::new_with_options(path, store.clone(), options).unwrap();
+        assert_eq!(store.get(&1), Some("value 1".to_string()));
+        assert_eq!(store.get(&6), None);
+        assert!(undo_log.recovery_stats().records_read < 10);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_accessors() {
+    create_test_file("./files/accessors_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::options()
+            .write_ahead(WriteAheadPolicy::OnCommit)
+            .open(path, store)
+            .unwrap();
+        assert_eq!(undo_log.last_tid(), 0);
+
+        let tid1 = undo_log.start();
+        let tid2 = undo_log.start();
+        let tid3 = undo_log.start();
+        assert_eq!(undo_log.last_tid(), tid3);
+        assert_eq!(undo_log.active_transactions(), vec![tid1, tid2, tid3]);
+
+        undo_log.write(tid1, 10, "value".to_string()).unwrap();
+        assert_eq!(undo_log.pending_entries(), 4);
+        let bytes: u64 = undo_log
+            .entries()
+            .map(|entry| entry.serialized_size().unwrap())
+            .sum();
+        assert_eq!(undo_log.pending_bytes(), bytes);
+
+        undo_log.checkpoint().unwrap();
+        assert_eq!(undo_log.pending_entries(), 0);
+        assert_eq!(undo_log.pending_bytes(), 0);
+
+        undo_log.commit(tid2).unwrap();
+        undo_log.abort(tid1).unwrap();
+        assert_eq!(undo_log.active_transactions(), vec![tid3]);
+        assert_eq!(undo_log.last_tid(), tid3);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_write_batch() {
+    create_two_test_files(
+        "./files/write_batch_single_undo_log",
+        "./files/write_batch_undo_log",
+        |single_path, batch_path, _, _| {
+            // Half of the keys exist before the batch, and key 0 is written twice.
+            let original: HashMap<_, _> = (0..500).map(|i| (i * 2, format!("old {}", i))).collect();
+            let mut changes: Vec<_> = (0..1000).map(|i| (i, format!("new {}", i))).collect();
+            changes.push((0, "newer".to_string()));
+            let mut recovered = Vec::new();
+            for (path, batched) in [(single_path, false), (batch_path, true)] {
+                let store: MyStore<MyLogData> = MyStore::new();
+                *store.map.write().unwrap() = original.clone();
+                let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+                let tid = undo_log.start();
+                if batched {
+                    undo_log.write_batch(tid, changes.clone()).unwrap();
+                } else {
+                    for (key, val) in changes.clone() {
+                        undo_log.write(tid, key, val).unwrap();
+                    }
+                }
+                assert_eq!(store.get(&0), Some("newer".to_string()));
+                assert_eq!(store.get(&1), Some("new 1".to_string()));
+
+                // The uncommitted batch is flushed to the log by the commit after it.
+                let other = undo_log.start();
+                undo_log.write(other, 5000, "other".to_string()).unwrap();
+                undo_log.commit(other).unwrap();
+                drop(undo_log);
+
+                UndoLog::new(path, store.clone()).unwrap();
+                recovered.push(store.map.read().unwrap().clone());
+            }
+            let mut expected = original.clone();
+            expected.insert(5000, "other".to_string());
+            assert_eq!(recovered[0], expected);
+            assert_eq!(recovered[1], expected);
+
+            let single_size = fs::metadata(single_path).unwrap().len();
+            let batch_size = fs::metadata(batch_path).unwrap().len();
+            assert!(
+                batch_size < single_size,
+                "{} >= {}",
+                batch_size,
+                single_size
+            );
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_abort_write_batch() {
+    create_test_file("./files/abort_write_batch_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        store.map.write().unwrap().insert(1, "old".to_string());
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log
+            .write_batch(
+                tid,
+                vec![(1, "new".to_string()), (2, "inserted".to_string())],
+            )
+            .unwrap();
+        undo_log
+            .write_batch(tid, vec![(1, "newer".to_string())])
+            .unwrap();
+        undo_log.abort(tid).unwrap();
+
+        assert_eq!(store.get(&1), Some("old".to_string()));
+        assert_eq!(store.get(&2), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_delete() {
+    create_test_file("./files/delete_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        store.map.write().unwrap().insert(1, "one".to_string());
+        store.map.write().unwrap().insert(2, "two".to_string());
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+
+        let tid = undo_log.start();
+        undo_log.delete(tid, 1).unwrap();
+        undo_log.delete(tid, 3).unwrap();
+        assert_eq!(store.get(&1), None);
+        undo_log.commit(tid).unwrap();
+
+        // Crash before the deleting transaction commits, after its
+        // entries were flushed by another commit.
+        let uncommitted = undo_log.start();
+        undo_log.delete(uncommitted, 2).unwrap();
+        undo_log.write(uncommitted, 3, "three".to_string()).unwrap();
+        undo_log.delete(uncommitted, 3).unwrap();
+        let tid = undo_log.start();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+        assert_eq!(store.get(&2), None);
+
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.get(&1), None);
+        assert_eq!(store.get(&2), Some("two".to_string()));
+        assert_eq!(store.get(&3), None);
+
+        let tid = undo_log.start();
+        undo_log.delete(tid, 2).unwrap();
+        undo_log.abort(tid).unwrap();
+        assert_eq!(store.get(&2), Some("two".to_string()));
+    })
+    .unwrap();
+}
+
+/// Store recording how many entries were in the log each time it changed.
+#[derive(Clone)]
+struct LoggedEntriesStore {
+    path: String,
+    map: Arc<RwLock<HashMap<i32, String>>>,
+    logged_entries: Arc<RwLock<Vec<usize>>>,
+}
+
+impl LoggedEntriesStore {
+    fn new(path: &str) -> LoggedEntriesStore {
+        LoggedEntriesStore {
+            path: path.to_string(),
+            map: Arc::new(RwLock::new(HashMap::new())),
+            logged_entries: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    fn record_logged_entries(&self) {
+        let mut file = fs::File::open(&self.path).unwrap();
+        let count = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .count();
+        self.logged_entries.write().unwrap().push(count);
+    }
+}
+
+impl LogStore<MyLogData> for LoggedEntriesStore {
+    fn get(&self, key: &i32) -> Option<String> {
+        self.map.read().unwrap().get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &i32) {
+        self.record_logged_entries();
+        self.map.write().unwrap().remove(key);
+    }
+
+    fn update(&mut self, key: i32, val: String) {
+        self.record_logged_entries();
+        self.map.write().unwrap().insert(key, val);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush_change(&mut self, _: i32, _: String) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_ahead_of_store_update() {
+    create_test_file("./files/write_ahead_undo_log", |path, _| {
+        let store = LoggedEntriesStore::new(path);
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "one".to_string()).unwrap();
+        undo_log
+            .write_batch(tid, vec![(2, "two".to_string()), (2, "three".to_string())])
+            .unwrap();
+        undo_log.delete(tid, 1).unwrap();
+        // The entry holding the old value is in the log before each change.
+        assert_eq!(*store.logged_entries.read().unwrap(), vec![2, 3, 3, 4]);
+
+        // Crashing before the commit rolls back every change.
+        drop(undo_log);
+        UndoLog::new(path, store.clone()).unwrap();
+        assert!(store.map.read().unwrap().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_write_ahead_on_commit() {
+    create_test_file("./files/write_ahead_on_commit_undo_log", |path, _| {
+        let store = LoggedEntriesStore::new(path);
+        let mut undo_log = UndoLog::options()
+            .write_ahead(WriteAheadPolicy::OnCommit)
+            .open(path, store.clone())
+            .unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "one".to_string()).unwrap();
+        undo_log.write(tid, 2, "two".to_string()).unwrap();
+        assert_eq!(*store.logged_entries.read().unwrap(), vec![0, 0]);
+        assert_eq!(undo_log.pending_entries(), 3);
+
+        undo_log.commit(tid).unwrap();
+        assert_eq!(undo_log.pending_entries(), 0);
+        assert_eq!(store.get(&2), Some("two".to_string()));
+    })
+    .unwrap();
+}
+
+/// Returns the number of abort entries in the log.
+fn logged_aborts(path: &str) -> usize {
+    let mut file = fs::File::open(path).unwrap();
+    WalIterator::new(&mut file, ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .filter(|entry| {
+            matches!(
+                entry,
+                Ok(SingleLogEntry::Transaction(Transaction::Abort(_)))
+            )
+        })
+        .count()
+}
+
+#[test]
+fn test_recover_twice() {
+    create_test_file("./files/recover_twice_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let committed = undo_log.start();
+        let uncommitted = undo_log.start();
+        undo_log
+            .write(committed, 1, "committed".to_string())
+            .unwrap();
+        undo_log
+            .write(uncommitted, 1, "uncommitted".to_string())
+            .unwrap();
+        undo_log
+            .write(uncommitted, 2, "uncommitted".to_string())
+            .unwrap();
+        undo_log.commit(committed).unwrap();
+        drop(undo_log);
+
+        let undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(undo_log.last_tid(), uncommitted);
+        drop(undo_log);
+        let len = fs::metadata(path).unwrap().len();
+        let recovered = store.map.read().unwrap().clone();
+        assert_eq!(recovered.get(&1), Some(&"committed".to_string()));
+        assert_eq!(recovered.get(&2), None);
+        assert_eq!(logged_aborts(path), 1);
+
+        // Recovering again finds the transaction already aborted.
+        let undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(undo_log.last_tid(), uncommitted);
+        drop(undo_log);
+        assert_eq!(fs::metadata(path).unwrap().len(), len);
+        assert_eq!(*store.map.read().unwrap(), recovered);
+        assert_eq!(logged_aborts(path), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_start_only_transaction() {
+    create_test_file("./files/recover_start_only_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let committed = undo_log.start();
+        undo_log
+            .write(committed, 1, "committed".to_string())
+            .unwrap();
+        undo_log.commit(committed).unwrap();
+        // The checkpoint flushes the start of the transaction that writes nothing.
+        let started = undo_log.start();
+        undo_log.checkpoint().unwrap();
+        drop(undo_log);
+
+        let mut undo_log = UndoLog::new(path, store).unwrap();
+        assert_eq!(undo_log.last_tid(), started);
+        assert!(undo_log.start() > started);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_close() {
+    create_test_file("./files/close_undo_log", |path, mut file| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::options()
+            .write_ahead(WriteAheadPolicy::OnCommit)
+            .open(path, store.clone())
+            .unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        assert_eq!(undo_log.pending_entries(), 2);
+        undo_log.close().unwrap();
+
+        let expected_entries = vec![
+            SingleLogEntry::Transaction(Transaction::Start(1)),
+            SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 20 }),
+        ];
+        let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+            .unwrap()
+            .entries::<SingleLogEntry<MyLogData>>()
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert_eq!(entries, expected_entries);
+
+        // Recovery rolls back the unfinished transaction.
+        UndoLog::new(path, store.clone()).unwrap();
+        assert!(store.map.read().unwrap().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_drop_flushes_pending_entries() {
+    create_test_file("./files/drop_flushes_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::options()
+            .write_ahead(WriteAheadPolicy::OnCommit)
+            .open(path, store.clone())
+            .unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 20, "Hello".to_string()).unwrap();
+        assert_eq!(
+            store.map.read().unwrap().get(&20),
+            Some(&"Hello".to_string())
+        );
+
+        // The undo entry is only in the log if dropping the log flushed it.
+        drop(undo_log);
+        UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.map.read().unwrap().get(&20), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_prepare() {
+    create_test_file("./files/prepare_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid1 = undo_log.start();
+        undo_log.write(tid1, 1, "one".to_string()).unwrap();
+        undo_log.prepare(tid1).unwrap();
+        let tid2 = undo_log.start();
+        undo_log.write(tid2, 2, "two".to_string()).unwrap();
+        undo_log.prepare(tid2).unwrap();
+        let tid3 = undo_log.start();
+        undo_log.write(tid3, 3, "three".to_string()).unwrap();
+        assert_eq!(undo_log.active_transactions(), vec![tid3]);
+        match undo_log.commit(tid1) {
+            Err(LogError::UnknownTransaction(tid)) if tid == tid1 => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        undo_log.checkpoint().unwrap();
+        drop(undo_log);
+
+        // Recovery rolls back the unprepared transaction only.
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(undo_log.in_doubt_transactions(), vec![tid1, tid2]);
+        assert!(undo_log.active_transactions().is_empty());
+        assert_eq!(store.map.read().unwrap().get(&1), Some(&"one".to_string()));
+        assert_eq!(store.map.read().unwrap().get(&2), Some(&"two".to_string()));
+        assert_eq!(store.map.read().unwrap().get(&3), None);
+
+        undo_log.resolve_prepared(tid1, Outcome::Commit).unwrap();
+        undo_log.resolve_prepared(tid2, Outcome::Abort).unwrap();
+        assert!(undo_log.in_doubt_transactions().is_empty());
+        assert_eq!(store.map.read().unwrap().get(&2), None);
+        match undo_log.resolve_prepared(tid2, Outcome::Commit) {
+            Err(LogError::UnknownTransaction(tid)) if tid == tid2 => {}
+            result => panic!("Expected an unknown transaction, got {:?}", result),
+        }
+        drop(undo_log);
+
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert!(undo_log.in_doubt_transactions().is_empty());
+        assert_eq!(store.map.read().unwrap().get(&1), Some(&"one".to_string()));
+        assert_eq!(store.map.read().unwrap().get(&2), None);
+        assert_eq!(undo_log.start(), tid3 + 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_start_with_tid() {
+    create_test_file("./files/start_with_tid_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        undo_log.start_with_tid(10).unwrap();
+        assert_eq!(undo_log.start(), 11);
+        match undo_log.start_with_tid(11) {
+            Err(LogError::DuplicateTransaction(11)) => {}
+            result => panic!("Expected a duplicate transaction, got {:?}", result),
+        }
+        undo_log.write(10, 10, "ten".to_string()).unwrap();
+        undo_log.commit(10).unwrap();
+        undo_log.start_with_tid(3).unwrap();
+        undo_log.write(3, 3, "three".to_string()).unwrap();
+        drop(undo_log);
+
+        // Recovery rolls back transaction 3 and remembers every id it read.
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.map.read().unwrap().get(&10), Some(&"ten".to_string()));
+        assert_eq!(store.map.read().unwrap().get(&3), None);
+        for &tid in &[3, 10, 11] {
+            match undo_log.start_with_tid(tid) {
+                Err(LogError::DuplicateTransaction(id)) if id == tid => {}
+                result => panic!("Expected a duplicate transaction, got {:?}", result),
+            }
+        }
+        undo_log.start_with_tid(7).unwrap();
+        assert_eq!(undo_log.start(), 12);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_force() {
+    create_two_test_files(
+        "./files/checkpoint_force_undo_log",
+        "./files/checkpoint_pending_undo_log",
+        |path, pending_path, mut file, _| {
+            let store: MyStore<MyLogData> = MyStore::new();
+            let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+            let stale = undo_log.start();
+            undo_log.write(stale, 100, "stale".to_string()).unwrap();
+            for i in 0..20 {
+                let tid = undo_log.start();
+                undo_log.write(tid, i, i.to_string()).unwrap();
+                undo_log.commit(tid).unwrap();
+            }
+            assert_eq!(undo_log.checkpoint().unwrap(), CheckpointStatus::Started);
+            assert_eq!(undo_log.checkpoint_pending(), Some(&[stale][..]));
+            let tid = undo_log.start();
+            undo_log.write(tid, 200, "committed".to_string()).unwrap();
+            undo_log.commit(tid).unwrap();
+            assert_eq!(
+                undo_log.checkpoint().unwrap(),
+                CheckpointStatus::AlreadyPending
+            );
+            fs::copy(path, pending_path).unwrap();
+
+            undo_log.checkpoint_force().unwrap();
+            assert_eq!(undo_log.checkpoint_pending(), None);
+            assert!(undo_log.active_transactions().is_empty());
+            assert_eq!(store.get(&100), None);
+            let mut entries = WalIterator::new(&mut file, ReadDirection::Backward)
+                .unwrap()
+                .entries::<SingleLogEntry<MyLogData>>();
+            assert_eq!(
+                entries.next_back().unwrap().unwrap(),
+                SingleLogEntry::Checkpoint(Checkpoint::End)
+            );
+            assert_eq!(
+                entries.next_back().unwrap().unwrap(),
+                SingleLogEntry::Transaction(Transaction::Abort(stale))
+            );
+            drop(undo_log);
+
+            // Recovery stops at the ended checkpoint instead of reading back
+            // to the start of the stale transaction.
+            let pending_store: MyStore<MyLogData> = MyStore::new();
+            let pending_log = UndoLog::new(pending_path, pending_store).unwrap();
+            let undo_log = UndoLog::new(path, store.clone()).unwrap();
+            assert!(
+                undo_log.recovery_stats().records_read < pending_log.recovery_stats().records_read
+            );
+            assert_eq!(store.get(&200), Some("committed".to_string()));
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_timeout() {
+    create_test_file("./files/checkpoint_timeout_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::options()
+            .checkpoint_timeout(Duration::from_millis(0))
+            .open(path, store.clone())
+            .unwrap();
+        let stale = undo_log.start();
+        undo_log.write(stale, 1, "stale".to_string()).unwrap();
+        let prepared = undo_log.start();
+        undo_log.write(prepared, 2, "prepared".to_string()).unwrap();
+        undo_log.prepare(prepared).unwrap();
+        assert_eq!(undo_log.checkpoint().unwrap(), CheckpointStatus::Started);
+
+        // The timed out checkpoint can't end until the prepared transaction is resolved.
+        assert_eq!(
+            undo_log.checkpoint().unwrap(),
+            CheckpointStatus::AlreadyPending
+        );
+        assert!(undo_log.active_transactions().is_empty());
+        assert_eq!(store.get(&1), None);
+        let mut pending = undo_log.checkpoint_pending().unwrap().to_vec();
+        pending.sort_unstable();
+        assert_eq!(pending, vec![stale, prepared]);
+
+        undo_log
+            .resolve_prepared(prepared, Outcome::Commit)
+            .unwrap();
+        assert_eq!(undo_log.checkpoint_pending(), None);
+        assert_eq!(undo_log.checkpoint().unwrap(), CheckpointStatus::Started);
+        assert_eq!(undo_log.checkpoint_pending(), Some(&[][..]));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_transaction_spanning_checkpoints() {
+    create_test_file("./files/spanning_checkpoints_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 1, "old".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+
+        // The first checkpoint ends after the long running transaction starts.
+        let short = undo_log.start();
+        undo_log.checkpoint().unwrap();
+        let long = undo_log.start();
+        undo_log.write(long, 1, "first".to_string()).unwrap();
+        undo_log.write(long, 2, "first".to_string()).unwrap();
+        undo_log.commit(short).unwrap();
+        assert_eq!(undo_log.checkpoint_pending(), None);
+
+        // The second checkpoint waits for it.
+        undo_log.checkpoint().unwrap();
+        undo_log.write(long, 1, "second".to_string()).unwrap();
+        undo_log.write(long, 3, "second".to_string()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, 4, "committed".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+        drop(undo_log);
+
+        UndoLog::new(path, store.clone()).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert(1, "old".to_string());
+        expected.insert(4, "committed".to_string());
+        assert_eq!(*store.map.read().unwrap(), expected);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_recover_transaction_missing_from_checkpoint() {
+    create_test_file(
+        "./files/missing_from_checkpoint_undo_log",
+        |path, mut file| {
+            let store: MyStore<MyLogData> = MyStore::new();
+            drop(UndoLog::new(path, store.clone()).unwrap());
+
+            // The checkpoint doesn't list the transaction that was active when
+            // it began, so only reading back to its start finds its first change.
+            let entries: Vec<SingleLogEntry<MyLogData>> = vec![
+                SingleLogEntry::Transaction(Transaction::Start(1)),
+                SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: 10 }),
+                SingleLogEntry::Checkpoint(Checkpoint::Begin(vec![])),
+                SingleLogEntry::Checkpoint(Checkpoint::End),
+                SingleLogEntry::ChangeEntry(ChangeEntry {
+                    tid: 1,
+                    key: 20,
+                    value: "old".to_string(),
+                }),
+            ];
+            for entry in entries.iter() {
+                write_serializable(&mut file, entry, 1000).unwrap();
+            }
+            store.map.write().unwrap().insert(10, "new".to_string());
+            store.map.write().unwrap().insert(20, "new".to_string());
+
+            UndoLog::new(path, store.clone()).unwrap();
+            let mut expected = HashMap::new();
+            expected.insert(20, "old".to_string());
+            assert_eq!(*store.map.read().unwrap(), expected);
+        },
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_checkpoint_forgets_finished_tids() {
+    create_test_file("./files/forget_finished_tids_undo_log", |path, _| {
+        let store: MyStore<MyLogData> = MyStore::new();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let active = undo_log.start();
+        undo_log.start_with_tid(10).unwrap();
+        undo_log.write(10, 10, "ten".to_string()).unwrap();
+        undo_log.commit(10).unwrap();
+
+        // The checkpoint ends once the active transaction commits, but
+        // recovery would still read transaction 10 along with it.
+        undo_log.checkpoint().unwrap();
+        undo_log.commit(active).unwrap();
+        assert!(undo_log.start_with_tid(10).is_err());
+
+        // A checkpoint without active transactions ends with the next commit.
+        undo_log.checkpoint().unwrap();
+        let tid = undo_log.start();
+        undo_log.commit(tid).unwrap();
+        undo_log.start_with_tid(10).unwrap();
+        undo_log.write(10, 10, "reused".to_string()).unwrap();
+        drop(undo_log);
+
+        // Recovery rolls back only the reused transaction.
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(store.map.read().unwrap().get(&10), Some(&"ten".to_string()));
+        assert!(undo_log.start_with_tid(10).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_failed_flush_is_cut_off() {
+    let backend = InMemoryBackend::new();
+    let store: MyStore<MyLogData> = MyStore::new();
+    let mut undo_log =
+        UndoLog::with_backend(backend.clone(), store.clone(), LogOptions::default()).unwrap();
+    let tid = undo_log.start();
+    undo_log.write(tid, 1, "old".repeat(50)).unwrap();
+    undo_log.commit(tid).unwrap();
+    drop(undo_log);
+
+    // Writing the old value fails after the start before it was written.
+    let len = backend.contents().len() as u64;
+    let file = FaultyFile::new(backend.clone(), FaultPolicy::FailOnceAfter(len + 40)).unwrap();
+    let mut undo_log = UndoLog::with_backend(file, store.clone(), LogOptions::default()).unwrap();
+    let tid = undo_log.start();
+    assert!(undo_log.write(tid, 1, "new".to_string()).is_err());
+    assert_eq!(backend.contents().len() as u64, len);
+
+    // The next flush logs each entry once.
+    undo_log.commit(tid).unwrap();
+    let mut file = Cursor::new(backend.contents());
+    let entries: Vec<_> = WalIterator::new(&mut file, ReadDirection::Forward)
+        .unwrap()
+        .entries::<SingleLogEntry<MyLogData>>()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        entries[entries.len() - 3..],
+        [
+            SingleLogEntry::Transaction(Transaction::Start(tid)),
+            SingleLogEntry::ChangeEntry(ChangeEntry {
+                tid,
+                key: 1,
+                value: "old".repeat(50),
+            }),
+            SingleLogEntry::Transaction(Transaction::Commit(tid)),
+        ]
+    );
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|entry| **entry == SingleLogEntry::Transaction(Transaction::Start(tid)))
+            .count(),
+        1
+    );
+}
@@ -0,0 +1,173 @@
+#![cfg(feature = "uuid")]
+
+extern crate disk_utils;
+extern crate uuid;
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use uuid::Uuid;
+
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::entries::{ChangeEntry, InsertEntry, SingleLogEntry};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::undo_log::UndoLog;
+use disk_utils::wal::{LogData, LogStore};
+use disk_utils::Serializable;
+
+#[derive(Clone, PartialEq, Debug)]
+struct UuidLogData;
+
+impl LogData for UuidLogData {
+    type Key = Uuid;
+    type Value = String;
+}
+
+#[derive(Clone)]
+struct MyStore<Data: LogData> {
+    data: Arc<RwLock<HashMap<Data::Key, Data::Value>>>,
+    flushed_data: Arc<RwLock<HashMap<Data::Key, Data::Value>>>,
+    flush_err: Arc<RwLock<bool>>,
+}
+
+impl<Data> MyStore<Data>
+where
+    Data: LogData,
+{
+    pub fn new() -> MyStore<Data> {
+        MyStore {
+            data: Arc::new(RwLock::new(HashMap::new())),
+            flushed_data: Arc::new(RwLock::new(HashMap::new())),
+            flush_err: Arc::new(RwLock::new(false)),
+        }
+    }
+
+    pub fn set_flush_err(&mut self, flush_err: bool) {
+        *self.flush_err.write().unwrap() = flush_err;
+    }
+
+    pub fn get_flushed(&self, key: &Data::Key) -> Option<Data::Value> {
+        self.flushed_data.read().unwrap().get(key).cloned()
+    }
+
+    pub fn discard_changes(&mut self) {
+        *self.data.write().unwrap() = self.flushed_data.read().unwrap().clone();
+    }
+}
+
+impl<Data> LogStore<Data> for MyStore<Data>
+where
+    Data: LogData,
+{
+    fn get(&self, key: &Data::Key) -> Option<Data::Value> {
+        self.data.read().unwrap().get(key).cloned()
+    }
+
+    fn remove(&mut self, key: &Data::Key) {
+        self.data.write().unwrap().remove(key);
+    }
+
+    fn update(&mut self, key: Data::Key, val: Data::Value) {
+        self.data.write().unwrap().insert(key, val);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if *self.flush_err.read().unwrap() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Flush error occurred",
+            ));
+        }
+        *self.flushed_data.write().unwrap() = self.data.read().unwrap().clone();
+        Ok(())
+    }
+
+    fn flush_change(&mut self, key: Data::Key, val: Data::Value) -> io::Result<()> {
+        self.flushed_data.write().unwrap().insert(key, val);
+        Ok(())
+    }
+}
+
+const KEY1: Uuid = Uuid::from_u128(0x936d_a01f_9abd_4d9d_80c7_02af_85c8_22a8);
+const KEY2: Uuid = Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8);
+
+#[test]
+fn test_uuid_serialize() {
+    let mut bytes = Vec::new();
+    KEY1.serialize(&mut bytes).unwrap();
+    assert_eq!(&bytes[..], KEY1.as_bytes());
+
+    let test_key = Uuid::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(test_key, KEY1);
+}
+
+#[test]
+fn test_uuid_entries() {
+    let entries: Vec<SingleLogEntry<UuidLogData>> = vec![
+        SingleLogEntry::InsertEntry(InsertEntry { tid: 1, key: KEY1 }),
+        SingleLogEntry::ChangeEntry(ChangeEntry {
+            tid: 2,
+            key: KEY2,
+            value: "Hello".to_string(),
+        }),
+    ];
+
+    for entry in entries {
+        let mut bytes = Vec::new();
+        entry.serialize(&mut bytes).unwrap();
+
+        let test_entry = SingleLogEntry::deserialize(&mut &bytes[..]).unwrap();
+        assert_eq!(entry, test_entry);
+    }
+}
+
+#[test]
+fn test_uuid_undo_log_recover() {
+    create_test_file("./files/uuid_undo_log", |path, _| {
+        let mut store: MyStore<UuidLogData> = MyStore::new();
+
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        let tid = undo_log.start();
+        undo_log.write(tid, KEY1, "Hello".to_string()).unwrap();
+        undo_log.commit(tid).unwrap();
+
+        store.set_flush_err(true);
+        let tid = undo_log.start();
+        undo_log.write(tid, KEY1, "World".to_string()).unwrap();
+        undo_log.write(tid, KEY2, "Foo".to_string()).unwrap();
+        assert!(undo_log.commit(tid).is_err());
+        store.set_flush_err(false);
+
+        crash_log(undo_log, path).unwrap();
+        let mut undo_log = UndoLog::new(path, store.clone()).unwrap();
+        assert_eq!(undo_log.start(), 3);
+        assert_eq!(store.get(&KEY1), Some("Hello".to_string()));
+        assert_eq!(store.get(&KEY2), None);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_uuid_redo_log_recover() {
+    create_test_file("./files/uuid_redo_log", |path, _| {
+        let mut store: MyStore<UuidLogData> = MyStore::new();
+
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        let tid = redo_log.start();
+        redo_log.write(tid, KEY1, "Hello".to_string()).unwrap();
+        redo_log.commit(tid).unwrap();
+
+        let tid = redo_log.start();
+        redo_log.write(tid, KEY2, "World".to_string()).unwrap();
+
+        // Uncommitted redo entries are never flushed to the log.
+        crash_log(redo_log, path).unwrap();
+        store.discard_changes();
+        let mut redo_log = RedoLog::new(path, store.clone()).unwrap();
+        assert_eq!(redo_log.start(), 2);
+        assert_eq!(store.get_flushed(&KEY1), Some("Hello".to_string()));
+        assert_eq!(store.get_flushed(&KEY2), None);
+    })
+    .unwrap();
+}
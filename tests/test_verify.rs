@@ -0,0 +1,313 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+use common::{TestData, TestStore};
+use disk_utils::testing::create_test_file;
+use disk_utils::wal::entries::{ChangeEntry, SingleLogEntry, Transaction};
+use disk_utils::wal::header::HeaderError;
+use disk_utils::wal::iterator::{ReadDirection, WalIterator};
+use disk_utils::wal::record::{BlockFormat, Record, RecordError, RecordType, HEADER_SIZE};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::verify::{verify, verify_path, Finding, VerifyOptions};
+use disk_utils::wal::writer::Writer;
+use disk_utils::wal::LogOptions;
+
+type Entry = SingleLogEntry<TestData>;
+
+fn format() -> BlockFormat {
+    BlockFormat {
+        block_size: 256,
+        ..BlockFormat::default()
+    }
+}
+
+fn start(tid: u64) -> Entry {
+    SingleLogEntry::Transaction(Transaction::Start(tid))
+}
+
+fn commit(tid: u64) -> Entry {
+    SingleLogEntry::Transaction(Transaction::Commit(tid))
+}
+
+fn change(tid: u64, key: i32, value: String) -> Entry {
+    SingleLogEntry::ChangeEntry(ChangeEntry { tid, key, value })
+}
+
+fn check(file: &mut File, options: &VerifyOptions) -> Vec<Finding> {
+    verify::<TestData, _>(file, options).unwrap().findings
+}
+
+/// Overwrites the byte at the offset with its complement.
+fn flip_byte(path: &str, offset: u64) {
+    let mut bytes = fs::read(path).unwrap();
+    bytes[offset as usize] ^= 0xff;
+    fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn test_verify_clean_log() {
+    create_test_file("./files/verify_clean", |path, mut file| {
+        let options = LogOptions {
+            block_size: 256,
+            ..LogOptions::default()
+        };
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "v".repeat(i as usize * 50)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+        redo_log.close().unwrap();
+        let bytes = fs::read(path).unwrap();
+
+        let report = verify_path::<TestData, _>(path, &VerifyOptions::default()).unwrap();
+        assert!(report.is_clean(), "{:?}", report.findings);
+        assert_eq!(report.format, format());
+        assert!(report.header.is_some());
+        assert_eq!(report.blocks, (bytes.len() as u64).div_ceil(256) - 1);
+        assert!(!report.padding.is_empty());
+        for range in &report.padding {
+            assert!(bytes[range.start as usize..range.end as usize]
+                .iter()
+                .all(|&b| b == 0));
+        }
+
+        let iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format()).unwrap();
+        assert_eq!(report.entries, iter.entries::<Entry>().count() as u64);
+        let iter = WalIterator::with_format(&mut file, ReadDirection::Forward, format()).unwrap();
+        assert_eq!(report.records, iter.count() as u64);
+
+        // Verifying leaves the log as it was.
+        assert!(fs::read(path).unwrap() == bytes);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_bad_checksums() {
+    create_test_file("./files/verify_checksums", |path, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        let offset = writer
+            .append_serializable(&change(1, 1, "a".to_string()))
+            .unwrap();
+        writer.append_serializable(&commit(1)).unwrap();
+        flip_byte(path, offset + HEADER_SIZE as u64 + 2);
+
+        match &check(&mut file, &VerifyOptions::default())[..] {
+            [Finding::CorruptRecord {
+                offset: found,
+                error: RecordError::BadChecksum { .. },
+            }] if *found == offset => {}
+            findings => panic!("Unexpected findings {:?}", findings),
+        }
+    })
+    .unwrap();
+
+    create_test_file("./files/verify_block_checksums", |path, mut file| {
+        let format = BlockFormat {
+            block_size: 256,
+            checksums: true,
+        };
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        writer.pad_to_block_boundary().unwrap();
+        let offset = writer.append_serializable(&start(2)).unwrap();
+        writer.pad_to_block_boundary().unwrap();
+        // Damage the block's padding, which only the block checksum covers.
+        flip_byte(path, offset + 200);
+
+        match &check(&mut file, &VerifyOptions::default())[..] {
+            [Finding::CorruptRecord {
+                offset: found,
+                error: RecordError::BadBlockChecksum { .. },
+            }] if *found == offset => {}
+            findings => panic!("Unexpected findings {:?}", findings),
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_invalid_chains() {
+    create_test_file("./files/verify_chains", |_, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        writer
+            .append(&Record::new(RecordType::First, vec![1, 2]).unwrap())
+            .unwrap();
+        let full = writer
+            .append(&Record::new(RecordType::Full, vec![3]).unwrap())
+            .unwrap();
+        let middle = writer
+            .append(&Record::new(RecordType::Middle, vec![4]).unwrap())
+            .unwrap();
+
+        // The payloads aren't entries, so only the framing is checked.
+        let options = VerifyOptions {
+            entries: false,
+            ..VerifyOptions::default()
+        };
+        let report = verify::<TestData, _>(&mut file, &options).unwrap();
+        assert_eq!(report.records, 3);
+        assert_eq!(report.entries, 1);
+        assert_eq!(
+            report.findings,
+            vec![
+                Finding::InvalidChain {
+                    offset: full,
+                    found: RecordType::Full,
+                    previous: Some(RecordType::First),
+                },
+                Finding::InvalidChain {
+                    offset: middle,
+                    found: RecordType::Middle,
+                    previous: None,
+                },
+            ]
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_bad_entries() {
+    create_test_file("./files/verify_entries", |_, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        let bad = writer
+            .append(&Record::new(RecordType::Full, vec![9, 9, 9]).unwrap())
+            .unwrap();
+        let end = writer.position();
+        let orphan = writer
+            .append_serializable(&change(5, 1, "a".to_string()))
+            .unwrap();
+        let unstarted = writer.append_serializable(&commit(6)).unwrap();
+        writer.append_serializable(&start(7)).unwrap();
+        writer
+            .append_serializable(&change(7, 1, "b".repeat(600)))
+            .unwrap();
+        writer.append_serializable(&commit(7)).unwrap();
+
+        let findings = check(&mut file, &VerifyOptions::default());
+        assert_eq!(findings.len(), 3);
+        match findings[0] {
+            Finding::BadEntry { start, end: e, .. } if start == bad && e == end => {}
+            ref finding => panic!("Unexpected finding {:?}", finding),
+        }
+        assert_eq!(
+            findings[1..],
+            [
+                Finding::ChangeWithoutStart {
+                    offset: orphan,
+                    tid: 5
+                },
+                Finding::CommitWithoutStart {
+                    offset: unstarted,
+                    tid: 6
+                },
+            ]
+        );
+
+        let options = VerifyOptions {
+            entries: false,
+            ..VerifyOptions::default()
+        };
+        assert_eq!(check(&mut file, &options), vec![]);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_torn_tail() {
+    create_test_file("./files/verify_torn_tail", |path, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        let entry = writer
+            .append_serializable(&change(1, 1, "a".repeat(600)))
+            .unwrap();
+        let end = writer.position();
+        let bytes = fs::read(path).unwrap();
+
+        // The log ends between the records of the entry, then partway
+        // through one of them.
+        let between = entry - entry % 256 + 2 * 256;
+        for cut in [between, end - 10] {
+            fs::write(path, &bytes[..cut as usize]).unwrap();
+            assert_eq!(
+                check(&mut file, &VerifyOptions::default()),
+                vec![Finding::TornTail {
+                    start: entry,
+                    end: cut
+                }]
+            );
+        }
+
+        // The log ends partway through the header of the entry's first record.
+        fs::write(path, &bytes[..(entry + 5) as usize]).unwrap();
+        assert_eq!(
+            check(&mut file, &VerifyOptions::default()),
+            vec![Finding::TornTail {
+                start: entry,
+                end: entry + 5
+            }]
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_empty_block() {
+    create_test_file("./files/verify_empty_block", |path, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        let padding = writer.position();
+        writer.pad_to_block_boundary().unwrap();
+        let empty = writer.position();
+        drop(writer);
+
+        // A block of zeros is followed by more entries.
+        file.write_all(&[0; 256]).unwrap();
+        let file2 = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        let mut writer = Writer::with_header(file2, format()).unwrap();
+        writer.append_serializable(&commit(1)).unwrap();
+
+        let report = verify::<TestData, _>(&mut file, &VerifyOptions::default()).unwrap();
+        assert_eq!(report.findings, vec![Finding::EmptyBlock { offset: empty }]);
+        assert_eq!(report.entries, 2);
+        // The padding of the block before it and the empty block are joined.
+        assert_eq!(report.padding[0], padding..empty + 256);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_verify_bad_header() {
+    create_test_file("./files/verify_bad_header", |path, mut file| {
+        let mut writer = Writer::with_header(file.try_clone().unwrap(), format()).unwrap();
+        writer.append_serializable(&start(1)).unwrap();
+        writer.append_serializable(&commit(1)).unwrap();
+        flip_byte(path, 20);
+
+        // The rest of the log is still checked with the given format.
+        let options = VerifyOptions {
+            format: format(),
+            ..VerifyOptions::default()
+        };
+        let report = verify::<TestData, _>(&mut file, &options).unwrap();
+        assert_eq!(report.header, None);
+        assert_eq!(report.entries, 2);
+        match report.findings[..] {
+            [Finding::BadHeader(HeaderError::BadChecksum { .. })] => {}
+            ref findings => panic!("Unexpected findings {:?}", findings),
+        }
+    })
+    .unwrap();
+}
@@ -0,0 +1,706 @@
+extern crate disk_utils;
+
+mod common;
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common::TestStore;
+use disk_utils::testing::{crash_log, create_test_file};
+use disk_utils::wal::iterator::{BlockError, ReadDirection, WalIterator};
+use disk_utils::wal::record::{
+    BlockFormat, Record, RecordError, RecordType, BLOCK_SIZE, HEADER_SIZE,
+};
+use disk_utils::wal::redo_log::RedoLog;
+use disk_utils::wal::writer::{GroupCommitOptions, GroupWriter, SharedWriter, SyncData, Writer};
+use disk_utils::wal::{append_to_file, read_serializable, LogOptions};
+
+#[test]
+fn test_lsns_across_blocks() {
+    create_test_file("./files/writer_lsns_across_blocks", |_, file| {
+        let payload_size = (BLOCK_SIZE / 3) as usize - HEADER_SIZE;
+        let record = Record::new(RecordType::Full, vec![1; payload_size]).unwrap();
+
+        let mut writer = Writer::new(file).unwrap();
+        assert_eq!(writer.last_lsn(), 0);
+        for i in 0..10 {
+            writer.append(&record).unwrap();
+            assert_eq!(writer.last_lsn(), i + 1);
+        }
+
+        let iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+        let lsns: Vec<_> = iter.map(|record| record.lsn).collect();
+        assert_eq!(lsns, (1..=10).collect::<Vec<_>>());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_position_matches_append_to_file() {
+    create_test_file("./files/writer_position", |_, file| {
+        create_test_file(
+            "./files/writer_position_append_to_file",
+            |_, mut expected| {
+                let mut writer = Writer::new(file).unwrap();
+                assert_eq!(writer.position(), 0);
+                for i in 0..10_000u64 {
+                    let mut record = Record::new(RecordType::Full, vec![i as u8]).unwrap();
+                    writer.append(&record).unwrap();
+                    record.lsn = i + 1;
+                    append_to_file(&mut expected, &record).unwrap();
+                    assert_eq!(writer.position(), expected.metadata().unwrap().len());
+                }
+
+                let mut written = Vec::new();
+                let mut file = writer.into_inner();
+                file.seek(SeekFrom::Start(0)).unwrap();
+                file.read_to_end(&mut written).unwrap();
+                let mut bytes = Vec::new();
+                expected.seek(SeekFrom::Start(0)).unwrap();
+                expected.read_to_end(&mut bytes).unwrap();
+                assert!(bytes.len() > 2 * BLOCK_SIZE as usize);
+                assert_eq!(written, bytes);
+            },
+        )
+        .unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_position_continues_after_reopen() {
+    create_test_file("./files/writer_position_reopen", |_, file| {
+        let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+        let mut writer = Writer::new(file.try_clone().unwrap()).unwrap();
+        for _ in 0..5 {
+            writer.append(&record).unwrap();
+        }
+        let len = file.metadata().unwrap().len();
+        assert_eq!(writer.position(), len);
+
+        let format = BlockFormat::default();
+        let mut writer = Writer::with_position(file, format, len).unwrap();
+        writer.append(&record).unwrap();
+        assert_eq!(writer.position(), len + (HEADER_SIZE + 100) as u64);
+        assert_eq!(writer.last_lsn(), 6);
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_append_serializable() {
+    create_test_file("./files/writer_append_serializable", |_, file| {
+        // Entries smaller than a block, larger than a block, and empty.
+        let entries: Vec<String> = [10, 300, 1000, 0, 40, 250]
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| i.to_string().repeat(len))
+            .collect();
+        let mut writer = Writer::with_block_size(file, 256).unwrap();
+        let mut offsets = Vec::new();
+        for entry in entries.iter() {
+            let position = writer.position();
+            let offset = writer.append_serializable(entry).unwrap();
+            // Entries continue in the current block instead of padding it.
+            assert_eq!(offset, position);
+            offsets.push(offset);
+        }
+
+        let file = writer.file_mut();
+        let mut iter = WalIterator::with_block_size(file, ReadDirection::Forward, 256).unwrap();
+        let types: Vec<_> = iter.by_ref().map(|record| record.record_type).collect();
+        assert!(iter.error().is_none());
+        let count = |record_type| types.iter().filter(|&&t| t == record_type).count();
+        assert_eq!(
+            count(RecordType::Full) + count(RecordType::First),
+            entries.len()
+        );
+        assert_eq!(count(RecordType::First), count(RecordType::Last));
+        assert!(count(RecordType::Middle) >= 3);
+
+        let mut iter = WalIterator::with_block_size(file, ReadDirection::Forward, 256).unwrap();
+        for entry in entries.iter() {
+            assert_eq!(read_serializable::<String>(&mut iter).unwrap(), *entry);
+        }
+
+        // Seeking to an entry's offset reads the entry and the ones after it.
+        for (i, &offset) in offsets.iter().enumerate().rev() {
+            iter.seek(offset).unwrap();
+            for entry in entries[i..].iter() {
+                assert_eq!(read_serializable::<String>(&mut iter).unwrap(), *entry);
+            }
+            assert!(iter.next().is_none());
+        }
+        assert!(matches!(
+            iter.seek(offsets[1] + 1),
+            Err(BlockError::OutOfBounds)
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_lsns_continue_after_reopen() {
+    create_test_file("./files/writer_lsns_reopen", |_, file| {
+        let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+
+        let mut writer = Writer::new(file.try_clone().unwrap()).unwrap();
+        for _ in 0..5 {
+            writer.append(&record).unwrap();
+        }
+
+        let mut writer = Writer::new(file).unwrap();
+        assert_eq!(writer.last_lsn(), 5);
+        writer.append(&record).unwrap();
+        assert_eq!(writer.last_lsn(), 6);
+
+        let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Backward).unwrap();
+        assert_eq!(iter.next_back().map(|record| record.lsn), Some(6));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_lsns_survive_recovery() {
+    create_test_file("./files/redo_log_lsns", |path, _| {
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        assert_eq!(redo_log.last_flushed_lsn(), None);
+
+        let tid = redo_log.start();
+        redo_log
+            .write(tid, 1, "a".repeat(BLOCK_SIZE as usize))
+            .unwrap();
+        redo_log.commit(tid).unwrap();
+        let first_lsn = redo_log.last_flushed_lsn().unwrap();
+
+        let tid = redo_log.start();
+        redo_log.write(tid, 2, "Hello".to_string()).unwrap();
+
+        let mut file = crash_log(redo_log, path).unwrap();
+        // Uncommitted redo entries are never flushed, so recovery writes nothing.
+        let mut redo_log = RedoLog::new(path, TestStore::new()).unwrap();
+        assert_eq!(redo_log.last_flushed_lsn(), None);
+
+        let tid = redo_log.start();
+        redo_log.commit(tid).unwrap();
+        assert!(redo_log.last_flushed_lsn().unwrap() > first_lsn);
+
+        let iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        let lsns: Vec<_> = iter.map(|record| record.lsn).collect();
+        assert!(lsns.len() > 4);
+        for pair in lsns.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+    })
+    .unwrap();
+}
+
+const CHECKSUM_FORMAT: BlockFormat = BlockFormat {
+    block_size: 4096,
+    checksums: true,
+};
+
+/// Appends records with distinct payloads, four to each block.
+fn write_checksummed_records(writer: &mut Writer, count: u8) -> Vec<Record> {
+    let mut records = Vec::new();
+    for i in 0..count {
+        let record = Record::new(RecordType::Full, vec![i; 1000]).unwrap();
+        writer.append(&record).unwrap();
+        records.push(record);
+    }
+    records
+}
+
+fn read_lsns(iter: &mut WalIterator) -> Vec<u64> {
+    iter.map(|record| record.lsn).collect()
+}
+
+#[test]
+fn test_block_checksums() {
+    create_test_file("./files/writer_block_checksums", |_, file| {
+        let mut writer = Writer::with_format(file.try_clone().unwrap(), CHECKSUM_FORMAT).unwrap();
+        write_checksummed_records(&mut writer, 10);
+        assert_eq!(writer.file().metadata().unwrap().len(), 2 * 4096 + 2 * 1015);
+
+        // Sealing reads back the unsealed tail block written by another writer.
+        let mut writer = Writer::with_format(file, CHECKSUM_FORMAT).unwrap();
+        write_checksummed_records(&mut writer, 3);
+
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, CHECKSUM_FORMAT)
+                .unwrap();
+        assert_eq!(read_lsns(iter.by_ref()), (1..=13).collect::<Vec<_>>());
+        assert!(iter.corruption().is_none());
+
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), ReadDirection::Backward, CHECKSUM_FORMAT)
+                .unwrap();
+        assert_eq!(iter.by_ref().rev().count(), 13);
+        assert!(iter.corruption().is_none());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_block_checksum_detects_duplicated_record() {
+    create_test_file("./files/writer_duplicated_record", |path, mut file| {
+        let mut writer = Writer::with_format(file.try_clone().unwrap(), CHECKSUM_FORMAT).unwrap();
+        let records = write_checksummed_records(&mut writer, 6);
+
+        // Overwrite the second record in the sealed first block with the first.
+        let record_len = HEADER_SIZE + 1000;
+        let mut bytes = vec![0; record_len];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut bytes).unwrap();
+        let mut overwriter = OpenOptions::new().write(true).open(path).unwrap();
+        overwriter.seek(SeekFrom::Start(record_len as u64)).unwrap();
+        overwriter.write_all(&bytes).unwrap();
+
+        // Every record in the block still passes its own CRC.
+        let mut block = vec![0; 4 * record_len];
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_exact(&mut block).unwrap();
+        let mut block = &block[..];
+        for _ in 0..4 {
+            assert!(Record::read(&mut block).is_ok());
+        }
+
+        let mut iter =
+            WalIterator::with_format(&mut file, ReadDirection::Backward, CHECKSUM_FORMAT).unwrap();
+        let read_records: Vec<_> = iter.by_ref().rev().collect();
+        assert_eq!(read_records.len(), 2);
+        assert_eq!(read_records[0].payload, records[5].payload);
+        match iter.corruption() {
+            Some(BlockError::Corrupted {
+                offset,
+                block,
+                record,
+                ..
+            }) => {
+                assert_eq!((*offset, *block, *record), (0, 0, 0));
+            }
+            e => panic!("Expected corruption error, got {:?}", e),
+        }
+        assert!(matches!(
+            iter.corruption().and_then(|e| e.record_error()),
+            Some(&RecordError::BadBlockChecksum { expected, actual }) if expected != actual
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_redo_log_block_checksums() {
+    create_test_file("./files/redo_log_block_checksums", |path, mut file| {
+        let options = LogOptions {
+            block_size: 4096,
+            block_checksums: true,
+            ..LogOptions::default()
+        };
+        let mut redo_log =
+            RedoLog::new_with_options(path, TestStore::new(), options.clone()).unwrap();
+        for i in 0..10 {
+            let tid = redo_log.start();
+            redo_log.write(tid, i, "a".repeat(1000)).unwrap();
+            redo_log.commit(tid).unwrap();
+        }
+
+        drop(redo_log);
+        let mut redo_log = RedoLog::new_with_options(path, TestStore::new(), options).unwrap();
+        assert_eq!(redo_log.start(), 11);
+
+        let mut iter =
+            WalIterator::with_format(&mut file, ReadDirection::Forward, CHECKSUM_FORMAT).unwrap();
+        let lsns = read_lsns(iter.by_ref());
+        assert_eq!(lsns, (1..=lsns.len() as u64).collect::<Vec<_>>());
+        assert!(iter.corruption().is_none());
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_group_commit() {
+    create_test_file("./files/writer_group_commit", |_, file| {
+        let options = GroupCommitOptions {
+            max_delay: Duration::from_millis(2),
+            ..GroupCommitOptions::default()
+        };
+        let group = Arc::new(GroupWriter::new(Writer::new(file).unwrap(), options));
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let group = group.clone();
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        group.commit(&format!("{} {}", thread, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let commits = 8 * 50;
+        let group = Arc::try_unwrap(group).ok().unwrap();
+        assert!(group.syncs() > 0);
+        assert!(group.syncs() < commits / 2);
+
+        let mut writer = group.into_inner();
+        let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+        let mut entries = Vec::new();
+        while let Ok(entry) = read_serializable::<String>(&mut iter) {
+            entries.push(entry);
+        }
+        let unique: HashSet<_> = entries.iter().cloned().collect();
+        assert_eq!(entries.len(), commits);
+        assert_eq!(unique.len(), commits);
+        // Each thread's entries are in the order it committed them.
+        for thread in 0..8 {
+            let prefix = format!("{} ", thread);
+            let order: Vec<usize> = entries
+                .iter()
+                .filter_map(|entry| entry.strip_prefix(&prefix))
+                .map(|i| i.parse().unwrap())
+                .collect();
+            assert_eq!(order, (0..50).collect::<Vec<_>>());
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn test_cursor_lsns_across_blocks() {
+    let payload_size = (BLOCK_SIZE / 3) as usize - HEADER_SIZE;
+    let record = Record::new(RecordType::Full, vec![1; payload_size]).unwrap();
+
+    let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(writer.last_lsn(), 0);
+    for i in 0..10 {
+        writer.append(&record).unwrap();
+        assert_eq!(writer.last_lsn(), i + 1);
+    }
+
+    let iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+    let lsns: Vec<_> = iter.map(|record| record.lsn).collect();
+    assert_eq!(lsns, (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_cursor_position_matches_append_to_file() {
+    let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    let mut expected = Cursor::new(Vec::new());
+    for i in 0..10_000u64 {
+        let mut record = Record::new(RecordType::Full, vec![i as u8]).unwrap();
+        writer.append(&record).unwrap();
+        record.lsn = i + 1;
+        append_to_file(&mut expected, &record).unwrap();
+        assert_eq!(writer.position(), expected.get_ref().len() as u64);
+    }
+
+    let written = writer.into_inner().into_inner();
+    assert!(written.len() > 2 * BLOCK_SIZE as usize);
+    assert_eq!(written, expected.into_inner());
+}
+
+#[test]
+fn test_cursor_continues_after_reopen() {
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    for _ in 0..5 {
+        writer.append(&record).unwrap();
+    }
+    let len = writer.position();
+    let bytes = writer.into_inner().into_inner();
+    assert_eq!(bytes.len() as u64, len);
+
+    // Reopening starts at the end of the bytes, not the cursor's position.
+    let mut writer = Writer::new(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(writer.last_lsn(), 5);
+    writer.append(&record).unwrap();
+    assert_eq!(writer.position(), len + (HEADER_SIZE + 100) as u64);
+    assert_eq!(writer.last_lsn(), 6);
+
+    let format = BlockFormat::default();
+    let mut writer = Writer::with_position(Cursor::new(bytes), format, len).unwrap();
+    writer.append(&record).unwrap();
+    assert_eq!(writer.last_lsn(), 6);
+
+    let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Backward).unwrap();
+    assert_eq!(iter.next_back().map(|record| record.lsn), Some(6));
+}
+
+#[test]
+fn test_cursor_append_serializable() {
+    let entries: Vec<String> = [10, 300, 1000, 0, 40, 250]
+        .iter()
+        .enumerate()
+        .map(|(i, &len)| i.to_string().repeat(len))
+        .collect();
+    let mut writer = Writer::with_block_size(Cursor::new(Vec::new()), 256).unwrap();
+    let mut offsets = Vec::new();
+    for entry in entries.iter() {
+        let position = writer.position();
+        let offset = writer.append_serializable(entry).unwrap();
+        assert_eq!(offset, position);
+        offsets.push(offset);
+    }
+    writer.sync().unwrap();
+
+    let file = writer.file_mut();
+    let mut iter = WalIterator::with_block_size(file, ReadDirection::Forward, 256).unwrap();
+    for entry in entries.iter() {
+        assert_eq!(read_serializable::<String>(&mut iter).unwrap(), *entry);
+    }
+    for (i, &offset) in offsets.iter().enumerate().rev() {
+        iter.seek(offset).unwrap();
+        for entry in entries[i..].iter() {
+            assert_eq!(read_serializable::<String>(&mut iter).unwrap(), *entry);
+        }
+        assert!(iter.next().is_none());
+    }
+}
+
+#[test]
+fn test_cursor_block_checksums() {
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), CHECKSUM_FORMAT).unwrap();
+    for i in 0..10 {
+        let record = Record::new(RecordType::Full, vec![i; 1000]).unwrap();
+        writer.append(&record).unwrap();
+    }
+    let bytes = writer.into_inner().into_inner();
+    assert_eq!(bytes.len(), 2 * 4096 + 2 * 1015);
+
+    // Sealing reads back the unsealed tail block.
+    let mut writer = Writer::with_format(Cursor::new(bytes), CHECKSUM_FORMAT).unwrap();
+    for i in 0..3 {
+        let record = Record::new(RecordType::Full, vec![i; 1000]).unwrap();
+        writer.append(&record).unwrap();
+    }
+
+    for &direction in &[ReadDirection::Forward, ReadDirection::Backward] {
+        let mut iter =
+            WalIterator::with_format(writer.file_mut(), direction, CHECKSUM_FORMAT).unwrap();
+        let mut lsns: Vec<_> = iter.by_ref().rev().map(|record| record.lsn).collect();
+        lsns.sort_unstable();
+        assert_eq!(lsns, (1..=13).collect::<Vec<_>>());
+        assert!(iter.corruption().is_none());
+    }
+}
+
+#[test]
+fn test_cursor_group_commit() {
+    let writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    let group = Arc::new(GroupWriter::new(writer, GroupCommitOptions::default()));
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let group = group.clone();
+            thread::spawn(move || {
+                for i in 0..25 {
+                    group.commit(&format!("{} {}", t, i)).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let mut writer = Arc::try_unwrap(group).ok().unwrap().into_inner();
+    let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+    let mut committed = HashSet::new();
+    while let Ok(entry) = read_serializable::<String>(&mut iter) {
+        assert!(committed.insert(entry));
+    }
+    assert_eq!(committed.len(), 100);
+}
+
+#[test]
+fn test_shared_writer_producers() {
+    let writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    let shared = SharedWriter::new(writer, 4);
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let handles: Vec<_> = (0..50)
+                    .map(|i| shared.append_serializable(&format!("{} {}", t, i)).unwrap())
+                    .collect();
+                for handle in handles {
+                    handle.wait().unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    shared.flush().unwrap();
+
+    let mut writer = shared.close().unwrap();
+    assert_eq!(writer.last_lsn(), 400);
+    let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+    let mut next = vec![0; 8];
+    while let Ok(entry) = read_serializable::<String>(&mut iter) {
+        let parts: Vec<usize> = entry.split(' ').map(|s| s.parse().unwrap()).collect();
+        // Each producer's entries are appended in the order they were sent.
+        assert_eq!(parts[1], next[parts[0]]);
+        next[parts[0]] += 1;
+    }
+    assert_eq!(next, vec![50; 8]);
+}
+
+#[test]
+fn test_shared_writer_shutdown() {
+    create_test_file("./files/shared_writer_shutdown", |_, mut file| {
+        let writer = Writer::new(file.try_clone().unwrap()).unwrap();
+        let shared = SharedWriter::new(writer, 1000);
+        let clone = shared.clone();
+        let handles: Vec<_> = (0..500)
+            .map(|i| shared.append_serializable(&i.to_string()).unwrap())
+            .collect();
+        assert!(matches!(
+            shared.close(),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+        ));
+
+        // Dropping the last clone appends and syncs the queued entries.
+        drop(clone);
+        for handle in handles {
+            handle.wait().unwrap();
+        }
+        let mut iter = WalIterator::new(&mut file, ReadDirection::Forward).unwrap();
+        for i in 0..500 {
+            assert_eq!(
+                read_serializable::<String>(&mut iter).unwrap(),
+                i.to_string()
+            );
+        }
+        assert!(iter.next().is_none());
+    })
+    .unwrap();
+}
+
+/// In-memory log whose writes fail once they contain a marker.
+struct FailingCursor(Cursor<Vec<u8>>);
+
+const FAIL_MARKER: &[u8] = b"fail here";
+
+impl Read for FailingCursor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for FailingCursor {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.windows(FAIL_MARKER.len()).any(|w| w == FAIL_MARKER) {
+            return Err(io::Error::other("Injected failure"));
+        }
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for FailingCursor {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl SyncData for FailingCursor {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_shared_writer_write_failure() {
+    let writer = Writer::new(FailingCursor(Cursor::new(Vec::new()))).unwrap();
+    let shared = SharedWriter::new(writer, 16);
+    for i in 0..10 {
+        shared
+            .append_serializable(&i.to_string())
+            .unwrap()
+            .wait()
+            .unwrap();
+    }
+
+    let producer = {
+        let shared = shared.clone();
+        thread::spawn(move || shared.append_serializable(&"fail here".to_string()))
+    };
+    let failed = producer.join().unwrap().unwrap();
+    let later = shared.append_serializable(&"later".to_string()).unwrap();
+    match failed.wait() {
+        Err(e) => assert_eq!(e.to_string(), "Injected failure"),
+        Ok(()) => panic!("Expected the injected failure"),
+    }
+    assert!(later.wait().is_err());
+    assert!(shared.flush().is_err());
+
+    let mut writer = shared.close().unwrap();
+    assert_eq!(writer.synced_lsn(), 10);
+    let mut iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+    for i in 0..10 {
+        assert_eq!(
+            read_serializable::<String>(&mut iter).unwrap(),
+            i.to_string()
+        );
+    }
+}
+
+#[test]
+fn test_pad_to_block_boundary() {
+    let block_size = BLOCK_SIZE as u64;
+    let record = Record::new(RecordType::Full, vec![1; 100]).unwrap();
+    let mut writer = Writer::new(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(writer.remaining_in_block(), block_size);
+
+    writer.append(&record).unwrap();
+    let used = (HEADER_SIZE + 100) as u64;
+    assert_eq!(writer.remaining_in_block(), block_size - used);
+
+    writer.pad_to_block_boundary().unwrap();
+    assert_eq!(writer.position(), block_size);
+    assert_eq!(writer.remaining_in_block(), block_size);
+    assert_eq!(writer.file().get_ref().len() as u64, block_size);
+
+    // Padding on a block boundary writes nothing.
+    writer.pad_to_block_boundary().unwrap();
+    assert_eq!(writer.position(), block_size);
+
+    // The next record starts the next block.
+    assert_eq!(writer.append(&record).unwrap(), block_size);
+
+    let iter = WalIterator::new(writer.file_mut(), ReadDirection::Forward).unwrap();
+    let lsns: Vec<_> = iter.map(|record| record.lsn).collect();
+    assert_eq!(lsns, vec![1, 2]);
+}
+
+#[test]
+fn test_pad_to_block_boundary_with_checksums() {
+    let format = BlockFormat {
+        block_size: 256,
+        checksums: true,
+    };
+    let record = Record::new(RecordType::Full, vec![1; 10]).unwrap();
+    let mut writer = Writer::with_format(Cursor::new(Vec::new()), format).unwrap();
+    assert_eq!(writer.remaining_in_block(), format.capacity() as u64);
+    writer.append(&record).unwrap();
+    writer.pad_to_block_boundary().unwrap();
+    assert_eq!(writer.append(&record).unwrap(), 256);
+
+    // The padding seals the first block, so it verifies when read.
+    let mut iter =
+        WalIterator::with_format(writer.file_mut(), ReadDirection::Forward, format).unwrap();
+    assert_eq!(iter.by_ref().count(), 2);
+    assert!(iter.corruption().is_none());
+}